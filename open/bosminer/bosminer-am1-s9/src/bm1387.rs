@@ -379,6 +379,13 @@ pub struct GetAddressReg {
     pub addr: u8,
 }
 
+impl GetAddressReg {
+    /// Linear address of the responding chip (see `ChipAddress`)
+    pub fn chip_address(&self) -> usize {
+        self.addr as usize / 4
+    }
+}
+
 impl Register for GetAddressReg {
     const REG_NUM: u8 = 0x00;
 }
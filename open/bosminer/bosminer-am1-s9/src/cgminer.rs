@@ -20,7 +20,7 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
-use ii_cgminer_api::command::{DEVDETAILS, FANS, TEMPCTRL, TEMPS};
+use ii_cgminer_api::command::{CHIPSHARES, DEVDETAILS, FANS, TEMPCTRL, TEMPS};
 use ii_cgminer_api::{command, commands, response};
 
 use serde::Serialize;
@@ -95,6 +95,17 @@ impl Handler {
         }
     }
 
+    /// Measured current of given hashboard in amperes (`None` when the hashboard has no current
+    /// sensor)
+    pub async fn read_current(&self, hashboard: usize) -> Option<f32> {
+        for manager in self.managers.iter() {
+            if manager.hashboard_idx == hashboard {
+                return manager.read_current().await;
+            }
+        }
+        None
+    }
+
     fn get_monitor_status(&self) -> command::Result<monitor::Status> {
         match self.monitor.status_receiver.borrow().clone() {
             Some(status) => Ok(status),
@@ -202,6 +213,27 @@ impl Handler {
                 .collect(),
         })
     }
+
+    async fn handle_chip_shares(&self) -> command::Result<response::ext::ChipShares> {
+        let mut list = vec![];
+        for manager in self.managers.iter() {
+            let inner = manager.inner.lock().await;
+            if let Some(hash_chain) = inner.hash_chain.as_ref() {
+                let chip_shares = hash_chain.snapshot_counter().await.chip_shares();
+                for (chip, chip_share) in chip_shares.into_iter().enumerate() {
+                    list.push(response::ext::ChipShare {
+                        idx: list.len() as i32,
+                        id: manager.hashboard_idx as i32,
+                        chip: chip as i32,
+                        valid: chip_share.valid as u64,
+                        share: chip_share.share,
+                        ratio: chip_share.ratio,
+                    });
+                }
+            }
+        }
+        Ok(response::ext::ChipShares { list })
+    }
 }
 
 pub fn create_custom_commands(
@@ -215,7 +247,8 @@ pub fn create_custom_commands(
         (DEVDETAILS: ParameterLess -> handler.handle_dev_details),
         (TEMPCTRL: ParameterLess -> handler.handle_temp_ctrl),
         (TEMPS: ParameterLess -> handler.handle_temps),
-        (FANS: ParameterLess -> handler.handle_fans)
+        (FANS: ParameterLess -> handler.handle_fans),
+        (CHIPSHARES: ParameterLess -> handler.handle_chip_shares)
     ];
 
     Some(custom_commands)
@@ -38,12 +38,18 @@ use crate::fan;
 use crate::hooks;
 use crate::monitor;
 use crate::power;
+use crate::reject_throttle;
+use crate::restart;
+use crate::tuning;
 use crate::FrequencySettings;
 
 use support::OptionDefault;
 
+use bosminer::affinity;
 use bosminer::client;
 use bosminer::hal::{self, BackendConfig as _};
+use bosminer::stats;
+use bosminer::work;
 
 use bosminer_config::{ClientDescriptor, ClientUserInfo};
 
@@ -52,6 +58,7 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::fs;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -136,17 +143,37 @@ pub const FANS_MAX: usize = 4;
 /// Default ASIC difficulty
 pub const DEFAULT_ASIC_DIFFICULTY: usize = 64;
 
+/// Number of works generated at once for a hash chain and number of blocking threads computing
+/// their midstates (the work is then sent to the FIFO one by one)
+pub const WORK_GENERATION_BATCH: usize = 4;
+pub const WORK_GENERATION_THREADS: usize = 2;
+
 /// Default hashrate interval used for statistics in seconds
 pub const DEFAULT_HASHRATE_INTERVAL: Duration = Duration::from_secs(60);
 
 /// Maximum time it takes to compute one job under normal circumstances
 pub const JOB_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// Default interval between two rows of statistics CSV file
+pub const DEFAULT_STATS_CSV_INTERVAL: Duration = Duration::from_secs(10);
+
 pub struct ResolvedChainConfig {
     pub midstate_count: MidstateCount,
     pub frequency: FrequencySettings,
     pub voltage: power::Voltage,
     pub enabled: bool,
+    /// Location of tuning profile which overrides default frequency and voltage (it is not used
+    /// when any of them is set explicitly in the configuration)
+    pub tuning_profile_path: Option<PathBuf>,
+    /// Backoff between restarts of the failing hashchain
+    pub restart: restart::Config,
+    /// Period of checking that all chips detected during initialization still respond
+    pub chip_reenumeration_interval: Option<Duration>,
+    /// I2C address of the hashboard current sensor (it is not probed when `None`)
+    pub current_sensor_address: Option<u8>,
+    /// Period after change of frequency during which the solutions of the chain are not
+    /// reflected in its displayed hashrate
+    pub hashrate_warmup: Option<Duration>,
 }
 
 #[derive(Serialize, Deserialize, Copy, Clone, Debug)]
@@ -245,6 +272,59 @@ pub struct Backend {
     pub hooks: Option<Arc<dyn hooks::Hooks>>,
     #[serde(skip)]
     pub fans_on_while_warming_up: Option<bool>,
+    #[serde(skip)]
+    pub stats_csv: Option<stats::csv::CsvConfig>,
+    #[serde(skip)]
+    pub stats_log_interval: Option<Duration>,
+    #[serde(skip)]
+    pub best_share_log_threshold: Option<u64>,
+    /// Unix domain socket for subscribers of the solution stream
+    #[serde(skip)]
+    pub solution_socket: Option<PathBuf>,
+    /// Directory for work in flight saved on panic
+    #[serde(skip)]
+    pub inflight_dump_dir: Option<PathBuf>,
+    #[serde(skip)]
+    pub cross_check_solutions: bool,
+    #[serde(skip)]
+    pub fastest_block_submission: bool,
+    #[serde(skip)]
+    pub midstate_verification: bool,
+    #[serde(skip)]
+    pub hashrate_warmup: Option<Duration>,
+    #[serde(skip)]
+    pub max_ntime_roll: Option<u32>,
+    #[serde(skip)]
+    pub work_seed: Option<u64>,
+    #[serde(skip)]
+    pub work_distribution: work::Distribution,
+    /// Directory with tuning profiles of all hashboards
+    #[serde(skip)]
+    pub tuning_profile_dir: Option<PathBuf>,
+    /// Backoff between restarts of failing hashchains shared by all of them
+    #[serde(skip)]
+    pub chain_restart: restart::Config,
+    /// CPU cores dedicated to work generation and solution handling
+    #[serde(skip)]
+    pub core_affinity: affinity::Config,
+    /// Lowering of frequency when pools reject too many shares as invalid (disabled when `None`)
+    #[serde(skip)]
+    pub reject_throttle: Option<reject_throttle::Config>,
+    /// Period of chip re-enumeration which detects chips dropping out (disabled when `None`)
+    #[serde(skip)]
+    pub chip_reenumeration_interval: Option<Duration>,
+    /// I2C address of current sensor of hashboards which are equipped with it (power consumption
+    /// is estimated when `None`)
+    #[serde(skip)]
+    pub current_sensor_address: Option<u8>,
+    /// Temperature below which fans are stopped while hashing is paused (disabled when `None`)
+    #[serde(skip)]
+    pub idle_fan_stop_temp: Option<f32>,
+    /// How long can be hashchain broken before the miner is shut down
+    #[serde(skip)]
+    pub broken_grace_period: Option<Duration>,
+    #[serde(skip)]
+    pub error_registry: Option<Arc<bosminer::error::ErrorRegistry>>,
 }
 
 pub trait ConfigBody
@@ -395,6 +475,15 @@ impl Backend {
             voltage: power::Voltage::from_volts(*voltage as f32)
                 .expect("TODO: bad voltage requested"),
             enabled,
+            tuning_profile_path: self
+                .tuning_profile_dir
+                .as_ref()
+                .filter(|_| !frequency.is_some() && !voltage.is_some())
+                .map(|dir| tuning::Profile::path(dir, hash_chain_idx)),
+            restart: self.chain_restart.clone(),
+            chip_reenumeration_interval: self.chip_reenumeration_interval,
+            current_sensor_address: self.current_sensor_address,
+            hashrate_warmup: self.hashrate_warmup,
         }
     }
 
@@ -491,10 +580,29 @@ impl Backend {
             }
         };
 
+        // Stopping fans during idle is safe only with working temperature and fan control
+        let idle_fan_stop_temp = match (self.idle_fan_stop_temp, &temp_config, &fan_config) {
+            (None, _, _) => None,
+            (Some(idle_temp), Some(temp_config), Some(_)) if idle_temp < temp_config.hot_temp => {
+                Some(idle_temp)
+            }
+            (Some(idle_temp), _, _) => {
+                warn!(
+                    "Unused idle fan stop temperature ({}) because it requires temperature and fan control with higher 'hot_temp'",
+                    idle_temp
+                );
+                None
+            }
+        };
+
         monitor::Config {
             temp_config,
             fan_config,
             fans_on_while_warming_up: self.fans_on_while_warming_up.unwrap_or(true),
+            idle_fan_stop_temp,
+            broken_grace_period: self
+                .broken_grace_period
+                .unwrap_or(monitor::DEFAULT_BROKEN_GRACE_PERIOD),
         }
     }
 
@@ -593,11 +701,126 @@ impl hal::BackendConfig for Backend {
         }
     }
 
+    /// The IP core is reconfigured whenever work with a different midstate count arrives. Work
+    /// registry is sized for the configured count, so only counts up to it fit.
+    fn supported_midstate_counts(&self) -> Vec<usize> {
+        let midstate_count = self.midstate_count();
+        [1, 2, 4]
+            .iter()
+            .cloned()
+            .filter(|count| *count <= midstate_count)
+            .collect()
+    }
+
     fn set_client_manager(&mut self, client_manager: client::Manager) {
         self.client_manager.replace(client_manager);
     }
 
+    fn set_error_registry(&mut self, error_registry: Arc<bosminer::error::ErrorRegistry>) {
+        self.error_registry.replace(error_registry);
+    }
+
     fn info(&self) -> Option<hal::BackendInfo> {
         Some(self.info.clone())
     }
+
+    fn stats_csv(&self) -> Option<stats::csv::CsvConfig> {
+        self.stats_csv.clone()
+    }
+
+    fn stats_log_interval(&self) -> Option<Duration> {
+        self.stats_log_interval
+    }
+
+    fn best_share_log_threshold(&self) -> Option<u64> {
+        self.best_share_log_threshold
+    }
+
+    fn solution_socket(&self) -> Option<PathBuf> {
+        self.solution_socket.clone()
+    }
+
+    fn inflight_dump_dir(&self) -> Option<PathBuf> {
+        self.inflight_dump_dir.clone()
+    }
+
+    fn cross_check_solutions(&self) -> bool {
+        self.cross_check_solutions
+    }
+
+    fn fastest_block_submission(&self) -> bool {
+        self.fastest_block_submission
+    }
+
+    fn midstate_verification(&self) -> bool {
+        self.midstate_verification
+    }
+
+    fn hashrate_warmup(&self) -> Option<Duration> {
+        self.hashrate_warmup
+    }
+
+    fn max_ntime_roll(&self) -> Option<u32> {
+        self.max_ntime_roll
+    }
+
+    fn work_seed(&self) -> Option<u64> {
+        self.work_seed
+    }
+
+    fn work_distribution(&self) -> work::Distribution {
+        self.work_distribution
+    }
+
+    fn core_affinity(&self) -> affinity::Config {
+        self.core_affinity.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Test that explicitly configured frequency or voltage takes precedence over tuning profile
+    #[test]
+    fn test_tuning_profile_precedence() {
+        let dir = PathBuf::from("/tmp/profiles");
+        let mut backend_config = Backend::default();
+        backend_config.tuning_profile_dir = Some(dir.clone());
+        assert_eq!(
+            backend_config.resolve_chain_config(6).tuning_profile_path,
+            Some(tuning::Profile::path(&dir, 6))
+        );
+
+        let mut hash_chains = BTreeMap::new();
+        hash_chains.insert(
+            "6".to_string(),
+            HashChain {
+                voltage: Some(8.8),
+                ..Default::default()
+            },
+        );
+        backend_config.hash_chains = Some(hash_chains);
+        assert_eq!(
+            backend_config.resolve_chain_config(6).tuning_profile_path,
+            None
+        );
+        // boards without explicit settings still use their profiles
+        assert!(backend_config
+            .resolve_chain_config(7)
+            .tuning_profile_path
+            .is_some());
+
+        backend_config.hash_chain_global = Some(HashChainGlobal {
+            overridable: Some(HashChain {
+                frequency: Some(600.0),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert_eq!(
+            backend_config.resolve_chain_config(7).tuning_profile_path,
+            None
+        );
+    }
 }
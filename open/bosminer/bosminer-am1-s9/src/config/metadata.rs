@@ -124,6 +124,15 @@ pub fn for_backend() -> serde_json::Value {
                                 "span": 3
                             }
                         ],
+                        [
+                            "donation",
+                            {
+                                "type": "bool",
+                                "label": "Donation",
+                                "default": false,
+                                "span": 12
+                            }
+                        ],
                         [
                             "pool",
                             {
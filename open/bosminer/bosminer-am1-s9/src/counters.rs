@@ -28,24 +28,56 @@ use crate::bm1387;
 
 use std::time::{Duration, Instant};
 
+/// Number of consecutive solutions of different work with identical nonce after which the core
+/// is considered to be stuck
+pub const STUCK_CORE_NONCE_REPEATS: usize = 5;
+
 /// Per-core counters for valid nonces/errors
 #[derive(Clone, Copy)]
 pub struct Core {
     pub valid: usize,
     pub errors: usize,
+    /// Last nonce returned by the core
+    last_nonce: Option<u32>,
+    /// Number of consecutive repetitions of the last nonce
+    nonce_repeats: usize,
+    /// The core returns only the same nonce and its solutions cannot be trusted
+    pub stuck: bool,
 }
 
 impl Core {
     pub fn reset(&mut self) {
         self.valid = 0;
         self.errors = 0;
+        self.last_nonce = None;
+        self.nonce_repeats = 0;
+        self.stuck = false;
     }
 
     pub fn new() -> Self {
         Self {
             valid: 0,
             errors: 0,
+            last_nonce: None,
+            nonce_repeats: 0,
+            stuck: false,
+        }
+    }
+
+    /// Account nonce of a solution and return `true` when the core has just been detected as
+    /// stuck
+    fn account_nonce(&mut self, nonce: u32) -> bool {
+        if self.last_nonce == Some(nonce) {
+            self.nonce_repeats += 1;
+        } else {
+            self.last_nonce = Some(nonce);
+            self.nonce_repeats = 0;
         }
+        if !self.stuck && self.nonce_repeats >= STUCK_CORE_NONCE_REPEATS {
+            self.stuck = true;
+            return true;
+        }
+        false
     }
 }
 
@@ -54,6 +86,8 @@ pub struct Chip {
     pub core: [Core; super::CORE_ADR_SPACE_SIZE],
     pub valid: usize,
     pub errors: usize,
+    /// The chip has not responded to the last re-enumeration
+    pub missing: bool,
 }
 
 impl Chip {
@@ -62,6 +96,7 @@ impl Chip {
             valid: 0,
             errors: 0,
             core: [Core::new(); super::CORE_ADR_SPACE_SIZE],
+            missing: false,
         }
     }
 
@@ -74,11 +109,44 @@ impl Chip {
     }
 }
 
+/// Contribution of a single chip to valid solutions of its hash chain
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChipShare {
+    /// Valid solutions (in shares) found by the chip
+    pub valid: usize,
+    /// Fraction of all valid solutions found by the hash chain
+    pub share: f64,
+    /// Share relative to the expectation when all chips contribute equally (i.e. `1.0` means
+    /// the chip performs as expected, values close to zero indicate dead chip)
+    pub ratio: f64,
+}
+
+/// Difference between chips responding to re-enumeration and the previously known state
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChipPresence {
+    /// Addresses of chips which have stopped responding
+    pub dropped: Vec<usize>,
+    /// Addresses of previously dropped chips which respond again
+    pub recovered: Vec<usize>,
+}
+
+impl ChipPresence {
+    pub fn is_empty(&self) -> bool {
+        self.dropped.is_empty() && self.recovered.is_empty()
+    }
+}
+
 #[derive(Clone)]
 pub struct HashChain {
     pub chip: Vec<Chip>,
     pub valid: usize,
     pub errors: usize,
+    /// Implausible solutions read from the FPGA (e.g. corrupted by bus noise) which cannot be
+    /// attributed to any chip
+    pub hw_framing_errors: usize,
+    /// Solutions dropped because they come from a stuck core. They are not hardware errors of
+    /// the working cores so they are not accounted as errors.
+    pub stuck_core_drops: usize,
     pub started: Instant,
     pub stopped: Option<Instant>,
     pub asic_difficulty: usize,
@@ -89,6 +157,8 @@ impl HashChain {
         Self {
             valid: 0,
             errors: 0,
+            hw_framing_errors: 0,
+            stuck_core_drops: 0,
             started: Instant::now(),
             stopped: None,
             chip: vec![Chip::new(); chip_count],
@@ -99,6 +169,8 @@ impl HashChain {
     pub fn reset(&mut self) {
         self.valid = 0;
         self.errors = 0;
+        self.hw_framing_errors = 0;
+        self.stuck_core_drops = 0;
         for chip in self.chip.iter_mut() {
             chip.reset();
         }
@@ -142,6 +214,40 @@ impl HashChain {
         self.chip[addr.chip].core[addr.core].errors += 1;
     }
 
+    pub fn add_framing_error(&mut self) {
+        self.hw_framing_errors += 1;
+    }
+
+    pub fn add_stuck_core_drop(&mut self) {
+        self.stuck_core_drops += 1;
+    }
+
+    /// Track nonces returned by each core to detect a stuck core which keeps returning the same
+    /// nonce for different work. It is not the same as duplicate solution of a single work so
+    /// duplicates should not be accounted. Returns `true` when the core has just been detected
+    /// as stuck.
+    pub fn account_nonce(&mut self, addr: bm1387::CoreAddress, nonce: u32) -> bool {
+        match self.chip.get_mut(addr.chip) {
+            Some(chip) => chip.core[addr.core].account_nonce(nonce),
+            None => false,
+        }
+    }
+
+    pub fn is_stuck(&self, addr: bm1387::CoreAddress) -> bool {
+        self.chip
+            .get(addr.chip)
+            .map_or(false, |chip| chip.core[addr.core].stuck)
+    }
+
+    /// Total number of cores detected as stuck
+    pub fn stuck_core_count(&self) -> usize {
+        self.chip
+            .iter()
+            .flat_map(|chip| chip.core.iter())
+            .filter(|core| core.stuck)
+            .count()
+    }
+
     pub fn set_chip_count(&mut self, chip_count: usize) {
         self.chip.resize(chip_count, Chip::new());
     }
@@ -149,4 +255,183 @@ impl HashChain {
     pub fn chip_count(&self) -> usize {
         self.chip.len()
     }
+
+    /// Number of chips which have not dropped out of the chain since enumeration
+    pub fn present_chip_count(&self) -> usize {
+        self.chip.iter().filter(|chip| !chip.missing).count()
+    }
+
+    /// Compare `addresses` of chips responding to re-enumeration with the chips detected during
+    /// initialization. The counters stay indexed by chip address so a dropped chip keeps its
+    /// statistics and solutions of the other chips are still attributed correctly. Only the
+    /// present chips are expected to contribute to the solutions of the chain.
+    pub fn update_present_chips(&mut self, addresses: &[usize]) -> ChipPresence {
+        let mut presence = ChipPresence::default();
+        for (address, chip) in self.chip.iter_mut().enumerate() {
+            let missing = !addresses.contains(&address);
+            if missing != chip.missing {
+                if missing {
+                    presence.dropped.push(address);
+                } else {
+                    presence.recovered.push(address);
+                }
+                chip.missing = missing;
+            }
+        }
+        presence
+    }
+
+    /// Distribution of valid solutions among chips which reveals outliers in the chain
+    /// Shares and ratios are zero when no solution has been found yet.
+    pub fn chip_shares(&self) -> Vec<ChipShare> {
+        let total = self.chip.iter().map(|chip| chip.valid).sum::<usize>();
+        let chip_count = self.present_chip_count();
+        self.chip
+            .iter()
+            .map(|chip| {
+                let share = if total > 0 {
+                    chip.valid as f64 / total as f64
+                } else {
+                    0.0
+                };
+                ChipShare {
+                    valid: chip.valid,
+                    share,
+                    ratio: share * chip_count as f64,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chip_shares() {
+        let mut counter = HashChain::new(4, 2);
+        assert!(counter
+            .chip_shares()
+            .iter()
+            .all(|chip_share| chip_share.share == 0.0 && chip_share.ratio == 0.0));
+
+        // skewed distribution with one superstar chip and one dead chip
+        for (chip, solutions) in [10, 5, 5, 0].iter().enumerate() {
+            for _ in 0..*solutions {
+                counter.add_valid(bm1387::CoreAddress { chip, core: 0 });
+            }
+        }
+        // solution from non-existent chip is not accounted
+        counter.add_valid(bm1387::CoreAddress { chip: 4, core: 0 });
+
+        let chip_shares = counter.chip_shares();
+        let valid: Vec<_> = chip_shares.iter().map(|chip| chip.valid).collect();
+        assert_eq!(valid, vec![20, 10, 10, 0]);
+        let shares: Vec<_> = chip_shares.iter().map(|chip| chip.share).collect();
+        assert_eq!(shares, vec![0.5, 0.25, 0.25, 0.0]);
+        let ratios: Vec<_> = chip_shares.iter().map(|chip| chip.ratio).collect();
+        assert_eq!(ratios, vec![2.0, 1.0, 1.0, 0.0]);
+        assert_eq!(shares.iter().sum::<f64>(), 1.0);
+    }
+
+    #[test]
+    fn test_stuck_core() {
+        let mut counter = HashChain::new(4, 1);
+        // nonce 0x0700000c belongs to chip 3 and core 7
+        const STUCK_NONCE: u32 = 0x0700_000c;
+        let addr = bm1387::CoreAddress::new(STUCK_NONCE);
+        assert_eq!(addr, bm1387::CoreAddress { chip: 3, core: 7 });
+
+        // different nonces from the same core are fine
+        for i in 0..2 * STUCK_CORE_NONCE_REPEATS as u32 {
+            assert!(!counter.account_nonce(addr, STUCK_NONCE + (i << 8)));
+        }
+        assert!(!counter.is_stuck(addr));
+
+        // the first occurrence of the nonce is not a repetition
+        for _ in 0..STUCK_CORE_NONCE_REPEATS {
+            assert!(!counter.account_nonce(addr, STUCK_NONCE));
+        }
+        assert!(!counter.is_stuck(addr));
+        // the condition is reported only once
+        assert!(counter.account_nonce(addr, STUCK_NONCE));
+        assert!(!counter.account_nonce(addr, STUCK_NONCE));
+        assert!(counter.is_stuck(addr));
+        assert_eq!(counter.stuck_core_count(), 1);
+
+        // other cores of the chip are not affected
+        assert!(!counter.is_stuck(bm1387::CoreAddress { chip: 3, core: 6 }));
+        // the core remains stuck even when it returns other nonce
+        assert!(!counter.account_nonce(addr, STUCK_NONCE + 0x100));
+        assert!(counter.is_stuck(addr));
+        // nonces from non-existent chip are ignored
+        let addr_out_of_range = bm1387::CoreAddress { chip: 4, core: 0 };
+        for _ in 0..=STUCK_CORE_NONCE_REPEATS {
+            assert!(!counter.account_nonce(addr_out_of_range, 0x10));
+        }
+        assert!(!counter.is_stuck(addr_out_of_range));
+
+        counter.reset();
+        assert!(!counter.is_stuck(addr));
+        assert_eq!(counter.stuck_core_count(), 0);
+    }
+
+    #[test]
+    fn test_stuck_core_drops() {
+        let mut counter = HashChain::new(4, 1);
+        let addr = bm1387::CoreAddress { chip: 3, core: 7 };
+
+        counter.add_error(addr);
+        counter.add_stuck_core_drop();
+        counter.add_stuck_core_drop();
+        // drops of a stuck core do not inflate hardware errors
+        assert_eq!(counter.errors, 1);
+        assert_eq!(counter.chip[addr.chip].errors, 1);
+        assert_eq!(counter.stuck_core_drops, 2);
+
+        counter.reset();
+        assert_eq!(counter.stuck_core_drops, 0);
+    }
+
+    #[test]
+    fn test_chip_dropout() {
+        let mut counter = HashChain::new(4, 1);
+        assert_eq!(
+            counter.update_present_chips(&[0, 1, 2, 3]),
+            Default::default()
+        );
+        for chip in 0..4 {
+            counter.add_valid(bm1387::CoreAddress { chip, core: 0 });
+        }
+
+        // chip 2 drops out between enumerations
+        let presence = counter.update_present_chips(&[0, 1, 3]);
+        assert_eq!(presence.dropped, vec![2]);
+        assert!(presence.recovered.is_empty());
+        assert_eq!(counter.chip_count(), 4);
+        assert_eq!(counter.present_chip_count(), 3);
+        // the same state is not reported again
+        assert!(counter.update_present_chips(&[0, 1, 3]).is_empty());
+
+        // solutions of chips behind the dropped one are attributed to their own counters
+        for _ in 0..2 {
+            counter.add_valid(bm1387::CoreAddress { chip: 3, core: 0 });
+        }
+        let valid: Vec<_> = counter.chip.iter().map(|chip| chip.valid).collect();
+        assert_eq!(valid, vec![1, 1, 1, 3]);
+        // only present chips are expected to contribute
+        let ratios: Vec<_> = counter
+            .chip_shares()
+            .iter()
+            .map(|chip_share| chip_share.ratio)
+            .collect();
+        assert_eq!(ratios, vec![0.5, 0.5, 0.5, 1.5]);
+
+        // the chip is back in the next enumeration
+        let presence = counter.update_present_chips(&[0, 1, 2, 3]);
+        assert!(presence.dropped.is_empty());
+        assert_eq!(presence.recovered, vec![2]);
+        assert_eq!(counter.present_chip_count(), 4);
+    }
 }
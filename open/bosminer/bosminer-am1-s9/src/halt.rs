@@ -36,6 +36,8 @@ use std::time::Duration;
 use crate::error;
 use error::ErrorKind;
 
+use bosminer::affinity;
+
 use futures::channel::mpsc;
 use futures::future::{select, Either};
 use futures::lock::Mutex;
@@ -101,22 +103,37 @@ impl NotifyReceiver {
     where
         F: Future<Output = ()> + 'static + Send,
     {
-        tokio::spawn(async move {
-            match select(f.boxed(), self.wait_for_halt().boxed()).await {
-                // in case we received halt notification, reply and exit
-                Either::Right((halt_result, _)) => {
-                    match halt_result {
-                        // confirm we are done (there's no cleanup)
-                        Some(done_sender) => done_sender.confirm(),
-                        // halt sender was dropped
-                        None => (),
-                    }
-                }
-                Either::Left(_) => {
-                    // task exited normally, do nothing
+        tokio::spawn(self.run_until_halt(f));
+    }
+
+    /// Same as `spawn` but the task runs on a dedicated thread pinned to cores of `pinning`
+    pub fn spawn_pinned<F>(self, pinning: &affinity::Pinning, name: &str, f: F)
+    where
+        F: Future<Output = ()> + 'static + Send,
+    {
+        pinning
+            .spawn(name, self.run_until_halt(f))
+            .expect("BUG: cannot spawn pinned thread");
+    }
+
+    async fn run_until_halt<F>(self, f: F)
+    where
+        F: Future<Output = ()> + 'static + Send,
+    {
+        match select(f.boxed(), self.wait_for_halt().boxed()).await {
+            // in case we received halt notification, reply and exit
+            Either::Right((halt_result, _)) => {
+                match halt_result {
+                    // confirm we are done (there's no cleanup)
+                    Some(done_sender) => done_sender.confirm(),
+                    // halt sender was dropped
+                    None => (),
                 }
             }
-        });
+            Either::Left(_) => {
+                // task exited normally, do nothing
+            }
+        }
     }
 }
 
@@ -240,6 +257,12 @@ impl Sender {
         Ok(())
     }
 
+    /// Issue halt for all registered clients and report failure to the caller instead of
+    /// panicking. The caller is responsible for not being halted by one of the clients.
+    pub async fn try_send_halt(self: Arc<Self>) -> error::Result<()> {
+        self.send_halt_internal().await
+    }
+
     /// This is a hack around `halt_sender` having to be run from tokio context, because it spawns
     /// additional threads.
     pub fn hook_termination_signals(self: Arc<Self>) {
@@ -368,6 +391,30 @@ mod test {
         assert!(chan_tx.unbounded_send(()).is_err());
     }
 
+    // Test that task spawned on a pinned thread is halted as well
+    #[tokio::test]
+    async fn test_halt_spawn_pinned() {
+        let (sender, receiver) = make_pair(Duration::from_millis(100));
+        let notify_receiver = receiver.register_client("test".into()).await;
+        let (chan_tx, mut chan_rx) = mpsc::unbounded();
+
+        let pinning = affinity::Pinning::system(vec![0]);
+        notify_receiver.spawn_pinned(&pinning, "test", async move {
+            // This should never return
+            assert!(chan_rx.next().await.is_some())
+        });
+        sender.send_halt().await;
+
+        // the task is dropped on its own thread after the halt has been confirmed
+        for _ in 0..100 {
+            if chan_tx.unbounded_send(()).is_err() {
+                return;
+            }
+            delay_for(Duration::from_millis(10)).await;
+        }
+        panic!("pinned task has not been halted");
+    }
+
     // Test that if task in termination context issues halt request, the halt request will finish
     // and terminate all registered tasks, not just itself.
     #[tokio::test]
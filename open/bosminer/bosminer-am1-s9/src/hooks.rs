@@ -22,6 +22,7 @@
 
 use crate::halt;
 use crate::monitor;
+use crate::shutdown;
 use crate::Manager;
 
 use bosminer::client;
@@ -45,6 +46,10 @@ pub trait Hooks: Send + Sync + Debug {
     ) {
     }
 
+    /// Called when ordered shutdown has been created. Subsystems which have to be stopped in
+    /// a particular phase (e.g. submission draining) should register to the phase receiver.
+    async fn shutdown_created(&self, _coordinator: Arc<shutdown::Coordinator>) {}
+
     /// Called when `Monitor` has been started
     async fn monitor_started(&self, _monitor: Arc<monitor::Monitor>) {}
 
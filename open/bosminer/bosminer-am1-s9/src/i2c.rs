@@ -25,15 +25,20 @@
 #[cfg(test)]
 pub mod test_utils;
 
+use ii_logging::macros::*;
+
 use async_trait::async_trait;
 use std::fmt;
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures::lock::Mutex;
 use ii_async_compat::futures;
 
 use crate::error::{self, ErrorKind};
 
+use bosminer::retry::RetryPolicy;
+
 /// Struct representing I2C address
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Address(u8);
@@ -104,6 +109,88 @@ where
     }
 }
 
+/// Default number of attempts for each transaction on `RetryBus`
+pub const DEFAULT_RETRY_ATTEMPTS: usize = 3;
+/// Default delay before the first retry on `RetryBus`
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(10);
+/// Upper bound of the delay between retries on `RetryBus`
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Bus wrapper that retries failed transactions to cope with transient errors (electrical noise
+/// etc.). By default the delay between successive attempts is doubled after each failure and
+/// the last error is returned once all attempts are exhausted.
+#[derive(Clone)]
+pub struct RetryBus<T> {
+    inner: T,
+    policy: RetryPolicy,
+}
+
+impl<T> RetryBus<T>
+where
+    T: AsyncBus,
+{
+    pub fn new(bus: T, attempts: usize, initial_delay: Duration) -> Self {
+        Self::with_policy(
+            bus,
+            RetryPolicy::new(attempts, initial_delay).with_backoff(2.0, MAX_RETRY_DELAY),
+        )
+    }
+
+    pub fn with_policy(bus: T, policy: RetryPolicy) -> Self {
+        Self { inner: bus, policy }
+    }
+
+    /// Sleep before next attempt and return `false` when there are no attempts left
+    async fn backoff(&self, attempt: usize, e: &error::Error) -> bool {
+        if !self.policy.has_next_attempt(attempt) {
+            return false;
+        }
+        warn!(
+            "I2C transaction failed (attempt {}/{}): {}, retrying...",
+            attempt + 1,
+            self.policy.max_attempts,
+            e
+        );
+        self.policy.backoff(attempt).await
+    }
+}
+
+#[async_trait]
+impl<T> AsyncBus for RetryBus<T>
+where
+    T: AsyncBus,
+{
+    async fn read(&mut self, addr: Address, reg: u8) -> error::Result<u8> {
+        let mut attempt = 0;
+        loop {
+            match self.inner.read(addr, reg).await {
+                Ok(val) => return Ok(val),
+                Err(e) => {
+                    if !self.backoff(attempt, &e).await {
+                        return Err(e);
+                    }
+                }
+            }
+            attempt += 1;
+        }
+    }
+
+    async fn write(&mut self, addr: Address, reg: u8, val: u8) -> error::Result<()> {
+        let mut attempt = 0;
+        loop {
+            match self.inner.write(addr, reg, val).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if !self.backoff(attempt, &e).await {
+                        return Err(e);
+                    }
+                }
+            }
+            attempt += 1;
+        }
+    }
+}
+
 /// `AsyncDevice` represents (async) ops on a device on I2C bus
 #[async_trait]
 pub trait AsyncDevice
@@ -254,4 +341,36 @@ mod test {
         assert_eq!(dev1.read(5).await.unwrap(), 0x22);
         assert_eq!(dev1.read(4).await.unwrap(), 0x00);
     }
+
+    #[tokio::test]
+    async fn test_retry_i2c_bus() {
+        let bus = test_utils::FakeI2cBus::new(Address::new(0x16), &[], Some(0), None);
+        let delay = Duration::from_millis(1);
+
+        // transient failures are recovered within the retry budget
+        let faulty_bus = test_utils::FaultyI2cBus::new(bus.clone(), 2);
+        let mut dev = Device::new(
+            RetryBus::new(faulty_bus.clone(), 3, delay),
+            Address::new(0x16),
+        );
+        dev.write(3, 0x11).await.unwrap();
+        assert_eq!(faulty_bus.transactions(), 3);
+        assert_eq!(dev.read(3).await.unwrap(), 0x11);
+        assert_eq!(faulty_bus.transactions(), 4);
+
+        // persistent failure is propagated after all attempts are exhausted
+        let faulty_bus = test_utils::FaultyI2cBus::new(bus.clone(), 3);
+        let mut dev = Device::new(
+            RetryBus::new(faulty_bus.clone(), 3, delay),
+            Address::new(0x16),
+        );
+        assert!(dev.read(3).await.is_err());
+        assert_eq!(faulty_bus.transactions(), 3);
+        // the bus works again once the fault is gone
+        assert_eq!(dev.read(3).await.unwrap(), 0);
+
+        // errors of the underlying bus are not masked
+        let mut dev = Device::new(RetryBus::new(bus, 2, delay), Address::new(0x14));
+        assert!(dev.read(3).await.is_err());
+    }
 }
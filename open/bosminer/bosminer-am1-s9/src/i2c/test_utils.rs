@@ -25,6 +25,9 @@ use crate::i2c::{self, Address};
 
 use async_trait::async_trait;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+
 /// Register, Value
 pub struct InitReg(pub u8, pub u8);
 
@@ -105,3 +108,52 @@ impl i2c::AsyncBus for FakeI2cBus {
         Ok(())
     }
 }
+
+/// Fault-injecting bus that fails a given number of transactions before passing them to the
+/// wrapped bus. Clones share the fault and transaction counters.
+#[derive(Clone)]
+pub struct FaultyI2cBus {
+    inner: FakeI2cBus,
+    /// Number of transactions that are yet to fail
+    faults_left: Arc<StdMutex<usize>>,
+    /// Number of all attempted transactions
+    transactions: Arc<AtomicUsize>,
+}
+
+impl FaultyI2cBus {
+    pub fn new(inner: FakeI2cBus, faults: usize) -> Self {
+        Self {
+            inner,
+            faults_left: Arc::new(StdMutex::new(faults)),
+            transactions: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Number of all transactions issued on this bus (including failed ones)
+    pub fn transactions(&self) -> usize {
+        self.transactions.load(Ordering::Relaxed)
+    }
+
+    fn inject_fault(&self) -> error::Result<()> {
+        self.transactions.fetch_add(1, Ordering::Relaxed);
+        let mut faults_left = self.faults_left.lock().expect("BUG: cannot lock faults");
+        if *faults_left > 0 {
+            *faults_left -= 1;
+            Err(ErrorKind::I2c("injected transient fault".to_string()))?
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl i2c::AsyncBus for FaultyI2cBus {
+    async fn read(&mut self, addr: Address, reg: u8) -> error::Result<u8> {
+        self.inject_fault()?;
+        self.inner.read(addr, reg).await
+    }
+
+    async fn write(&mut self, addr: Address, reg: u8, val: u8) -> error::Result<()> {
+        self.inject_fault()?;
+        self.inner.write(addr, reg, val).await
+    }
+}
@@ -38,6 +38,8 @@ use ext_work_id::ExtWorkId;
 use bosminer::work;
 use std::convert::TryInto;
 use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use chrono::prelude::DateTime;
 use chrono::Utc;
@@ -71,6 +73,28 @@ enum MinerType {
     Unknown(usize),
 }
 
+/// Midstate count the IP core is configured for. It determines the format of both outgoing work
+/// and incoming solutions so it is shared by all IO blocks of one IP core and changing it (see
+/// `Common::change_midstate_count`) takes effect for all of them at once.
+#[derive(Debug, Clone)]
+pub struct SharedMidstateCount(Arc<AtomicUsize>);
+
+impl SharedMidstateCount {
+    pub fn new(midstate_count: MidstateCount) -> Self {
+        Self(Arc::new(AtomicUsize::new(midstate_count.to_count())))
+    }
+
+    #[inline]
+    pub fn get(&self) -> MidstateCount {
+        MidstateCount::new(self.0.load(Ordering::Relaxed))
+    }
+
+    #[inline]
+    fn set(&self, midstate_count: MidstateCount) {
+        self.0.store(midstate_count.to_count(), Ordering::Relaxed);
+    }
+}
+
 /// Structure representing the build time from register `BUILD_ID`
 struct BuildId(u32);
 
@@ -201,6 +225,11 @@ impl WorkTxFifo {
         self.regs.work_tx_stat_reg.read().tx_full().bit()
     }
 
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.regs.work_tx_stat_reg.read().tx_empty().bit()
+    }
+
     #[inline]
     pub fn has_space_for_one_job(&self) -> bool {
         self.regs.work_tx_stat_reg.read().irq_pend().bit()
@@ -232,6 +261,14 @@ impl WorkTxFifo {
         Ok(())
     }
 
+    /// Wait for output FIFO to become empty
+    /// Uses timed polling
+    pub async fn wait_empty(&self) {
+        while !self.is_empty() {
+            delay_for(Duration::from_millis(1)).await;
+        }
+    }
+
     pub fn init(&mut self) -> error::Result<()> {
         // Set threshold for work TX so that there's space for
         // at least one job.
@@ -385,14 +422,14 @@ impl WorkRxResponse {
 
 pub struct WorkRx {
     fifo: WorkRxFifo,
-    midstate_count: MidstateCount,
+    midstate_count: SharedMidstateCount,
 }
 
 impl WorkRx {
     pub async fn recv_solution(mut self) -> Result<(Self, Solution), failure::Error> {
         let word1 = self.fifo.async_read().await?;
         let word2 = self.fifo.async_read().await?;
-        let resp = WorkRxResponse::from_hw(self.midstate_count, word1, word2);
+        let resp = WorkRxResponse::from_hw(self.midstate_count.get(), word1, word2);
 
         let solution = Solution {
             nonce: resp.nonce,
@@ -408,7 +445,7 @@ impl WorkRx {
         self.fifo.init()
     }
 
-    fn new(hashboard_idx: usize, midstate_count: MidstateCount) -> error::Result<Self> {
+    fn new(hashboard_idx: usize, midstate_count: SharedMidstateCount) -> error::Result<Self> {
         Ok(Self {
             fifo: WorkRxFifo::new(hashboard_idx)?,
             midstate_count,
@@ -418,7 +455,7 @@ impl WorkRx {
 
 pub struct WorkTx {
     fifo: WorkTxFifo,
-    midstate_count: MidstateCount,
+    midstate_count: SharedMidstateCount,
 }
 
 impl WorkTx {
@@ -426,16 +463,37 @@ impl WorkTx {
         self.fifo.async_wait_for_room().await
     }
 
+    /// Wait until IP core takes all work from the FIFO (it has to be done before the midstate
+    /// count is changed, because queued work is parsed according to the current one)
+    pub async fn wait_for_empty(&self) {
+        self.fifo.wait_empty().await
+    }
+
+    #[inline]
+    pub fn midstate_count(&self) -> MidstateCount {
+        self.midstate_count.get()
+    }
+
     pub fn assert_midstate_count(&self, expected_midstate_count: usize) {
+        let midstate_count = self.midstate_count.get().to_count();
         assert_eq!(
-            expected_midstate_count,
-            self.midstate_count.to_count(),
+            expected_midstate_count, midstate_count,
             "Outgoing work has {} midstates, but miner is configured for {} midstates!",
-            expected_midstate_count,
-            self.midstate_count.to_count(),
+            expected_midstate_count, midstate_count,
         );
     }
 
+    /// Build the first words of work sent to the FIFO. The IP core expects the merkle root tail
+    /// as a little endian word (see `work::Assignment::merkle_root_tail_le`).
+    fn work_header(work: &work::Assignment, hw_work_id: u32) -> [u32; 4] {
+        [
+            hw_work_id,
+            work.bits(),
+            work.ntime,
+            work.merkle_root_tail_le(),
+        ]
+    }
+
     pub fn send_work(
         &mut self,
         work: &work::Assignment,
@@ -444,11 +502,10 @@ impl WorkTx {
         self.assert_midstate_count(work.midstates.len());
         let ext_work_id = ExtWorkId::new(work_id, 0);
 
-        self.fifo
-            .write(ext_work_id.to_hw(self.midstate_count).to_le())?;
-        self.fifo.write(work.bits().to_le())?;
-        self.fifo.write(work.ntime.to_le())?;
-        self.fifo.write(work.merkle_root_tail().to_le())?;
+        let hw_work_id = ext_work_id.to_hw(self.midstate_count.get());
+        for word in Self::work_header(work, hw_work_id).iter() {
+            self.fifo.write(word.to_le())?;
+        }
 
         for mid in work.midstates.iter() {
             for midstate_word in mid.state.words::<u32>().rev() {
@@ -461,14 +518,14 @@ impl WorkTx {
     /// Return upper bound for `work_id`
     /// Determines how big the work registry has to be
     pub fn work_id_count(&self) -> usize {
-        ExtWorkId::get_work_id_count(self.midstate_count)
+        ExtWorkId::get_work_id_count(self.midstate_count.get())
     }
 
     fn init(&mut self) -> error::Result<()> {
         self.fifo.init()
     }
 
-    fn new(hashboard_idx: usize, midstate_count: MidstateCount) -> error::Result<Self> {
+    fn new(hashboard_idx: usize, midstate_count: SharedMidstateCount) -> error::Result<Self> {
         Ok(Self {
             fifo: WorkTxFifo::new(hashboard_idx)?,
             midstate_count,
@@ -554,7 +611,7 @@ pub struct Common {
     /// The `common` register block itself
     regs: uio_async::UioTypedMapping<ii_fpga_io_am1_s9::common::RegisterBlock>,
     /// Current midstate configuration
-    midstate_count: MidstateCount,
+    midstate_count: SharedMidstateCount,
     /// With which hashboard is this register block associated?
     /// This is required to print meaningful error messages.
     hashboard_idx: usize,
@@ -649,7 +706,19 @@ impl Common {
     }
 
     pub fn set_midstate_count(&self) {
-        self.set_ip_core_midstate_count(self.midstate_count.to_reg());
+        self.set_ip_core_midstate_count(self.midstate_count.get().to_reg());
+    }
+
+    #[inline]
+    pub fn midstate_count(&self) -> MidstateCount {
+        self.midstate_count.get()
+    }
+
+    /// Reconfigure IP core for work with different number of midstates
+    /// Work TX FIFO has to be empty when calling this (see `WorkTx::wait_for_empty`).
+    pub fn change_midstate_count(&self, midstate_count: MidstateCount) {
+        self.midstate_count.set(midstate_count);
+        self.set_midstate_count();
     }
 
     fn init(&mut self) -> error::Result<()> {
@@ -661,7 +730,7 @@ impl Common {
         Ok(())
     }
 
-    fn new(hashboard_idx: usize, midstate_count: MidstateCount) -> error::Result<Self> {
+    fn new(hashboard_idx: usize, midstate_count: SharedMidstateCount) -> error::Result<Self> {
         let uio = uio::Device::open(hashboard_idx, uio::Type::Common)?;
         Ok(Self {
             regs: uio.map()?,
@@ -682,10 +751,11 @@ pub struct Core {
 impl Core {
     /// Build a new IP core
     pub fn new(hashboard_idx: usize, midstate_count: MidstateCount) -> error::Result<Self> {
+        let midstate_count = SharedMidstateCount::new(midstate_count);
         Ok(Self {
-            common_io: Common::new(hashboard_idx, midstate_count)?,
+            common_io: Common::new(hashboard_idx, midstate_count.clone())?,
             command_io: CommandRxTx::new(hashboard_idx)?,
-            work_rx_io: WorkRx::new(hashboard_idx, midstate_count)?,
+            work_rx_io: WorkRx::new(hashboard_idx, midstate_count.clone())?,
             work_tx_io: WorkTx::new(hashboard_idx, midstate_count)?,
         })
     }
@@ -723,6 +793,18 @@ mod test {
             Core::new(TEST_CHAIN_INDEX, MidstateCount::new(1)).expect("fifo construction failed");
         core.init_and_split().expect("fifo initialization failed");
     }
+    /// Midstate count changed through one IO block has to be seen by all other blocks
+    #[test]
+    fn test_shared_midstate_count() {
+        let midstate_count = SharedMidstateCount::new(MidstateCount::new(4));
+        let work_rx_midstate_count = midstate_count.clone();
+        assert_eq!(work_rx_midstate_count.get().to_count(), 4);
+
+        midstate_count.set(MidstateCount::new(2));
+        assert_eq!(work_rx_midstate_count.get().to_count(), 2);
+        assert_eq!(midstate_count.get().to_count(), 2);
+    }
+
     /// This test verifies correct parsing of mining work solution for all multi-midstate
     /// configurations.
     /// The solution_word represents the second word of data provided that follows the nonce as
@@ -813,4 +895,23 @@ mod test {
         let build_id = BuildId(0x5D8255F0);
         assert_eq!(build_id.to_string(), "2019-09-18 16:06:08 UTC");
     }
+
+    /// Verify that the work header carries merkle root tail in the byte order of the IP core
+    #[test]
+    fn test_work_header() {
+        for block in bosminer::test_utils::TEST_BLOCKS.iter() {
+            let work: work::Assignment = block.into();
+            let header = WorkTx::work_header(&work, 0x1234);
+
+            assert_eq!(header[0], 0x1234);
+            assert_eq!(header[1], block.bits);
+            assert_eq!(header[2], block.time);
+            // merkle root tail is read from bytes 64..68 of the block header as a little endian
+            let tail: [u8; 4] = block.header_bytes[64..68]
+                .try_into()
+                .expect("BUG: slice with incorrect length");
+            assert_eq!(header[3], u32::from_le_bytes(tail));
+            assert_eq!(header[3], work.merkle_root_tail_le());
+        }
+    }
 }
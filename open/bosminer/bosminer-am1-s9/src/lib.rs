@@ -34,11 +34,16 @@ pub mod halt;
 pub mod hooks;
 pub mod i2c;
 pub mod io;
+pub mod log_throttle;
 pub mod monitor;
 pub mod null_work;
 pub mod power;
 pub mod registry;
+pub mod reject_throttle;
+pub mod restart;
 pub mod sensor;
+pub mod shutdown;
+pub mod tuning;
 pub mod utils;
 
 #[cfg(test)]
@@ -47,6 +52,8 @@ pub mod test;
 use ii_logging::macros::*;
 
 use bosminer::async_trait;
+use bosminer::client;
+use bosminer::error::{ConditionKind, ErrorRegistry, Severity};
 use bosminer::hal::{self, BackendConfig as _};
 use bosminer::node;
 use bosminer::stats;
@@ -55,7 +62,8 @@ use bosminer::work;
 use bosminer_macros::WorkSolverNode;
 
 use std::fmt;
-use std::sync::{Arc, Mutex as StdMutex};
+use std::path::Path;
+use std::sync::{Arc, Mutex as StdMutex, MutexGuard as StdMutexGuard};
 use std::time::{Duration, Instant};
 
 use error::ErrorKind;
@@ -64,7 +72,9 @@ use failure::ResultExt;
 use futures::channel::mpsc;
 use futures::lock::{Mutex, MutexGuard};
 use futures::stream::StreamExt;
+use futures::FutureExt as _;
 use ii_async_compat::futures;
+use ii_async_compat::select;
 
 use bm1387::{ChipAddress, MidstateCount};
 use command::Interface;
@@ -84,10 +94,8 @@ use tokio::time::delay_for;
 const INACTIVATE_FROM_CHAIN_DELAY: Duration = Duration::from_millis(100);
 /// Base delay quantum during hashboard initialization
 const INIT_DELAY: Duration = Duration::from_secs(1);
-/// Time to wait between successive hashboard initialization attempts
-const ENUM_RETRY_DELAY: Duration = Duration::from_secs(10);
-/// How many times to retry the enumeration
-const ENUM_RETRY_COUNT: usize = 10;
+/// After this many failed starts stop worrying about having less chips than expected
+const ENUM_STRICT_ATTEMPTS: usize = 5;
 
 /// Maximum number of chips is limitted by the fact that there is only 8-bit address field and
 /// addresses to the chips need to be assigned with step of 4 (e.g. 0, 4, 8, etc.)
@@ -107,7 +115,21 @@ const TARGET_CHIP_BAUD_RATE: usize = 1562500;
 const TEMP_CHIP: ChipAddress = ChipAddress::One(61);
 
 /// Timeout for completion of haschain halt
-const HALT_TIMEOUT: Duration = Duration::from_secs(30);
+pub(crate) const HALT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Period of sampling the solution rate for detection of degrading chips
+const SOLUTION_RATE_SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Maximal change of chip frequency in one step when converging to capped hash rate
+const FREQUENCY_RAMP_STEP: usize = 25_000_000;
+/// Delay between successive steps of the frequency ramp
+const FREQUENCY_RAMP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Period of checking whether work generation of all hashchains has been paused
+const HASHING_PAUSE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a chain waiting for restart checks whether other owner has released it
+const OWNERSHIP_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 /// Core address space size (it should be 114, but the addresses are non-consecutive)
 const CORE_ADR_SPACE_SIZE: usize = 128;
@@ -179,8 +201,6 @@ impl ResetPin {
 pub struct HashChain {
     /// Number of chips that have been detected
     chip_count: usize,
-    /// Eliminates the need to query the IP core about the current number of configured midstates
-    midstate_count: MidstateCount,
     /// ASIC difficulty
     asic_difficulty: usize,
     /// ASIC target (matches difficulty)
@@ -210,6 +230,20 @@ pub struct HashChain {
     halt_receiver: halt::Receiver,
     /// Current hashchain settings
     frequency: Mutex<FrequencySettings>,
+    /// Frequency set when the chain has been started or retuned (see `set_frequency`) which is
+    /// scaled down by hash rate cap
+    nominal_frequency: Mutex<FrequencySettings>,
+    /// Fraction of nominal hash rate requested by the frontend
+    hashrate_cap: Arc<work::HashrateCap>,
+    /// Registry for reporting operational problems of this hashchain
+    error_registry: Option<Arc<ErrorRegistry>>,
+    /// Last current (in amperes) measured by the board current sensor (`None` when the board has
+    /// no such sensor or the reading failed)
+    current: Mutex<Option<f32>>,
+    /// Period of checking that all enumerated chips still respond (disabled when `None`)
+    chip_reenumeration_interval: Option<Duration>,
+    /// I2C address of the current sensor when the hashboard is equipped with it
+    current_sensor_address: Option<u8>,
 }
 
 impl HashChain {
@@ -218,7 +252,8 @@ impl HashChain {
     /// * `gpio_mgr` - gpio manager used for producing pins required for hashboard control
     /// * `voltage_ctrl_backend` - communication backend for the voltage controller
     /// * `hashboard_idx` - index of this hashboard determines which FPGA IP core is to be mapped
-    /// * `midstate_count` - see Self
+    /// * `midstate_count` - number of midstates the IP core is initially configured for (work with
+    /// fewer midstates reconfigures it at runtime)
     /// * `asic_difficulty` - to what difficulty set the hardware target filter
     pub fn new(
         reset_pin: ResetPin,
@@ -249,7 +284,6 @@ impl HashChain {
 
         Ok(Self {
             chip_count: 0,
-            midstate_count,
             asic_difficulty,
             asic_target: ii_bitcoin::Target::from_pool_difficulty(asic_difficulty),
             voltage_ctrl: Arc::new(power::Control::new(voltage_ctrl_backend, hashboard_idx)),
@@ -270,6 +304,12 @@ impl HashChain {
             halt_sender,
             halt_receiver,
             frequency: Mutex::new(FrequencySettings::from_frequency(0)),
+            nominal_frequency: Mutex::new(FrequencySettings::from_frequency(0)),
+            hashrate_cap: Arc::new(work::HashrateCap::new()),
+            error_registry: None,
+            current: Mutex::new(None),
+            chip_reenumeration_interval: None,
+            current_sensor_address: None,
         })
     }
 
@@ -277,6 +317,11 @@ impl HashChain {
         self.temperature_receiver.borrow().clone()
     }
 
+    /// Measured current drawn by the hashboard (available only on boards with current sensor)
+    pub async fn current(&self) -> Option<f32> {
+        *self.current.lock().await
+    }
+
     async fn take_work_rx_io(&self) -> io::WorkRx {
         self.work_rx_io
             .lock()
@@ -299,7 +344,7 @@ impl HashChain {
     #[inline]
     fn calculate_work_time(&self, max_pll_frequency: usize) -> u32 {
         secs_to_fpga_ticks(calculate_work_delay_for_pll(
-            self.midstate_count.to_count(),
+            self.common_io.midstate_count().to_count(),
             max_pll_frequency,
         ))
     }
@@ -313,6 +358,19 @@ impl HashChain {
         self.common_io.set_ip_core_work_time(new_work_time);
     }
 
+    /// Reconfigure the IP core for work with different number of midstates and adjust work time
+    /// to it. Solutions of work with the previous midstate count which chips are still solving
+    /// cannot be paired with their work anymore and end up as hardware errors.
+    async fn change_midstate_count(&self, midstate_count: MidstateCount) {
+        info!(
+            "Chain {}: switching to {} midstates",
+            self.hashboard_idx,
+            midstate_count.to_count()
+        );
+        self.common_io.change_midstate_count(midstate_count);
+        self.set_work_time(self.frequency.lock().await.max()).await;
+    }
+
     /// Helper method that initializes the FPGA IP core
     async fn ip_core_init(&mut self) -> error::Result<()> {
         // Configure IP core
@@ -551,8 +609,8 @@ impl HashChain {
         } else {
             // Update chips one-by-one
             for i in 0..self.chip_count {
-                let new_freq = self.frequency.lock().await.chip[i];
-                if new_freq != frequency.chip[i] {
+                let new_freq = frequency.chip[i];
+                if new_freq != self.frequency.lock().await.chip[i] {
                     self.set_chip_pll(ChipAddress::One(i), new_freq).await?;
                 }
             }
@@ -636,7 +694,7 @@ impl HashChain {
             "Sending out {} pieces of dummy work to initialize chips",
             NUM_WORK
         );
-        let midstate_count = self.midstate_count.to_count();
+        let midstate_count = self.common_io.midstate_count().to_count();
         let mut work_tx_io = self.work_tx_io.lock().await;
         let tx_fifo = work_tx_io.as_mut().expect("tx fifo missing");
         for _ in 0..NUM_WORK {
@@ -652,24 +710,39 @@ impl HashChain {
     /// This task picks up work from frontend (via generator), saves it to
     /// registry (to pair with `Assignment` later) and sends it out to hw.
     /// It makes sure that TX fifo is empty before requesting work from
-    /// generator.
-    /// It exits when generator returns `None`.
+    /// generator. Midstates of a small batch of work are computed in parallel
+    /// (see `work::Generator::generate_parallel`) to not stall the executor.
+    /// It exits when generator returns no work.
+    /// The IP core is reconfigured whenever generated work has different number of midstates.
     async fn work_tx_task(
+        self: Arc<Self>,
         work_registry: Arc<Mutex<registry::WorkRegistry>>,
         mut tx_fifo: io::WorkTx,
         mut work_generator: work::Generator,
     ) {
         loop {
             tx_fifo.wait_for_room().await.expect("wait for tx room");
-            let work = work_generator.generate().await;
-            match work {
-                None => return,
-                Some(work) => {
-                    // assign `work_id` to `work`
-                    let work_id = work_registry.lock().await.store_work(work.clone(), false);
-                    // send work is synchronous
-                    tx_fifo.send_work(&work, work_id).expect("send work");
+            let works = work_generator
+                .generate_parallel(
+                    config::WORK_GENERATION_BATCH,
+                    config::WORK_GENERATION_THREADS,
+                )
+                .await;
+            if works.is_empty() {
+                return;
+            }
+            for work in works {
+                if work.midstates.len() != tx_fifo.midstate_count().to_count() {
+                    tx_fifo.wait_for_empty().await;
+                    self.change_midstate_count(MidstateCount::new(work.midstates.len()))
+                        .await;
                 }
+                tx_fifo.wait_for_room().await.expect("wait for tx room");
+                // assign `work_id` to `work`
+                let work_id = work_registry.lock().await.store_work(work.clone(), false);
+                // send work is synchronous
+                tx_fifo.send_work(&work, work_id).expect("send work");
+                work_generator.confirm_delivery(&work);
             }
         }
     }
@@ -678,6 +751,8 @@ impl HashChain {
     /// registry (under `work_id` got from FPGA), pairs them together and
     /// sends them back to frontend (via `solution_sender`).
     /// If solution is duplicated, it gets dropped (and errors stats incremented).
+    /// Solutions from cores stuck on a single nonce are dropped as well (but they are counted
+    /// separately from errors).
     /// It prints warnings when solution doesn't hit ASIC target.
     /// TODO: this task is not very platform dependent, maybe move it somewhere else?
     /// TODO: figure out when and how to stop this task
@@ -688,11 +763,18 @@ impl HashChain {
         solution_sender: work::SolutionSender,
         counter: Arc<Mutex<counters::HashChain>>,
     ) {
+        // messages of errors which tend to come in storms when hardware fails
+        let mut framing_error_log = log_throttle::LogThrottle::new("framing errors");
+        let mut hw_error_log = log_throttle::LogThrottle::new("hardware errors");
+
         // solution receiving/filtering part
         loop {
             let (rx_fifo_out, hw_solution) =
                 rx_fifo.recv_solution().await.expect("recv solution failed");
             rx_fifo = rx_fifo_out;
+            // regular solutions let summaries of ended storms out without waiting for next error
+            framing_error_log.tick();
+            hw_error_log.tick();
             let work_id = hw_solution.hardware_id;
             let solution = Solution::from_hw_solution(&hw_solution, self.asic_target);
             let mut work_registry = work_registry.lock().await;
@@ -705,14 +787,53 @@ impl HashChain {
                         continue;
                     }
                     let core_addr = bm1387::CoreAddress::new(solution.nonce);
+                    if core_addr.chip >= counter.lock().await.chip_count() {
+                        if framing_error_log.pass() {
+                            warn!(
+                                "Discarding solution from non-existent chip {}: {:#010x?}",
+                                core_addr.chip, solution
+                            );
+                        }
+                        counter.lock().await.add_framing_error();
+                        continue;
+                    }
+                    let nonce = solution.nonce;
                     let status = work_item.insert_solution(solution);
+                    if let Some(fault) = status.framing_error {
+                        if framing_error_log.pass() {
+                            warn!("Discarding implausible solution from hashchain: {}", fault);
+                        }
+                        counter.lock().await.add_framing_error();
+                        continue;
+                    }
+                    // duplicate is the same nonce for the same work so it does not indicate
+                    // a stuck core
+                    if !status.duplicate && counter.lock().await.account_nonce(core_addr, nonce) {
+                        self.report_stuck_core(core_addr, nonce);
+                    }
+                    if counter.lock().await.is_stuck(core_addr) {
+                        counter.lock().await.add_stuck_core_drop();
+                        continue;
+                    }
 
                     // work item detected a new unique solution, we will push it for further processing
                     if let Some(unique_solution) = status.unique_solution {
+                        if let Err(fault) = unique_solution.validate() {
+                            if hw_error_log.pass() {
+                                warn!("Dropping malformed solution from hashchain: {}", fault);
+                            }
+                            counter.lock().await.add_error(core_addr);
+                            continue;
+                        }
                         if !status.duplicate {
                             let hash = unique_solution.hash();
                             if !hash.meets(unique_solution.backend_target()) {
-                                info!("Solution from hashchain not hitting ASIC target; {}", hash);
+                                if hw_error_log.pass() {
+                                    info!(
+                                        "Solution from hashchain not hitting ASIC target; {}",
+                                        hash
+                                    );
+                                }
                                 counter.lock().await.add_error(core_addr);
                             } else {
                                 counter.lock().await.add_valid(core_addr);
@@ -737,6 +858,78 @@ impl HashChain {
         }
     }
 
+    /// Report core which keeps returning the same nonce. The solutions from such core are not
+    /// processed anymore.
+    fn report_stuck_core(&self, core_addr: bm1387::CoreAddress, nonce: u32) {
+        let message = format!(
+            "core {} of chip {} repeatedly returns nonce {:#010x} and has been disabled",
+            core_addr.core, core_addr.chip, nonce
+        );
+        warn!("Hashchain {}: {}", self.hashboard_idx, message);
+        if let Some(error_registry) = self.error_registry.as_ref() {
+            error_registry.push(
+                ConditionKind::StuckCore,
+                Severity::Error,
+                format!("chain {} chip {}", self.hashboard_idx, core_addr.chip),
+                message,
+            );
+        }
+    }
+
+    /// Periodically re-enumerate chips and compare the responding ones with chips detected
+    /// during initialization to reveal a chip which intermittently drops out of the chain
+    async fn chip_reenumeration_task(self: Arc<Self>, interval: Duration) {
+        loop {
+            delay_for(interval).await;
+
+            let addresses: Vec<_> = match self
+                .command_context
+                .read_register::<bm1387::GetAddressReg>(ChipAddress::All)
+                .await
+            {
+                Ok(responses) => responses
+                    .iter()
+                    .filter(|addr_reg| addr_reg.chip_rev == bm1387::CHIP_REV_BM1387)
+                    .map(|addr_reg| addr_reg.chip_address())
+                    .collect(),
+                Err(e) => {
+                    warn!(
+                        "Hashchain {}: chip re-enumeration failed: {}",
+                        self.hashboard_idx, e
+                    );
+                    continue;
+                }
+            };
+
+            let (presence, present_chip_count) = {
+                let mut counter = self.counter.lock().await;
+                let presence = counter.update_present_chips(&addresses);
+                (presence, counter.present_chip_count())
+            };
+            for chip_address in presence.dropped.iter() {
+                let message = format!(
+                    "chip {} does not respond ({} of {} chips present)",
+                    chip_address, present_chip_count, self.chip_count
+                );
+                warn!("Hashchain {}: {}", self.hashboard_idx, message);
+                if let Some(error_registry) = self.error_registry.as_ref() {
+                    error_registry.push(
+                        ConditionKind::ChipDropout,
+                        Severity::Warning,
+                        format!("chain {} chip {}", self.hashboard_idx, chip_address),
+                        message,
+                    );
+                }
+            }
+            for chip_address in presence.recovered.iter() {
+                info!(
+                    "Hashchain {}: chip {} responds again",
+                    self.hashboard_idx, chip_address
+                );
+            }
+        }
+    }
+
     async fn try_to_initialize_sensor(
         command_context: command::Context,
     ) -> error::Result<Box<dyn sensor::Sensor>> {
@@ -744,6 +937,12 @@ impl HashChain {
         let i2c_bus = bm1387::i2c::Bus::new_and_init(command_context, TEMP_CHIP)
             .await
             .with_context(|_| ErrorKind::Sensors("bus construction failed".into()))?;
+        // retry transient failures of sensor transactions before giving up
+        let i2c_bus = i2c::RetryBus::new(
+            i2c_bus,
+            i2c::DEFAULT_RETRY_ATTEMPTS,
+            i2c::DEFAULT_RETRY_DELAY,
+        );
 
         // try to probe sensor
         let sensor = sensor::probe_i2c_sensors(i2c_bus)
@@ -766,6 +965,34 @@ impl HashChain {
         Ok(sensor)
     }
 
+    async fn try_to_initialize_current_sensor(
+        command_context: command::Context,
+        address: u8,
+    ) -> error::Result<Option<Box<dyn sensor::CurrentSensor>>> {
+        let i2c_bus = bm1387::i2c::Bus::new_and_init(command_context, TEMP_CHIP)
+            .await
+            .with_context(|_| ErrorKind::Sensors("bus construction failed".into()))?;
+        let i2c_bus = i2c::RetryBus::new(
+            i2c_bus,
+            i2c::DEFAULT_RETRY_ATTEMPTS,
+            i2c::DEFAULT_RETRY_DELAY,
+        );
+
+        let mut sensor = match sensor::probe_i2c_current_sensor(i2c_bus, i2c::Address::new(address))
+            .await
+            .with_context(|_| ErrorKind::Sensors("error when probing current sensor".into()))?
+        {
+            Some(sensor) => sensor,
+            None => return Ok(None),
+        };
+        sensor
+            .init()
+            .await
+            .with_context(|_| ErrorKind::Sensors("failed to initialize current sensor".into()))?;
+
+        Ok(Some(sensor))
+    }
+
     /// Monitor watchdog task.
     /// This task sends periodically ping to monitor task. It also tries to read temperature.
     async fn monitor_watchdog_temp_task(self: Arc<Self>) {
@@ -804,6 +1031,31 @@ impl HashChain {
             error::Result::Ok(sensor) => Some(sensor),
         };
 
+        // Power consumption is estimated when current cannot be measured. Most of the hashboards
+        // are not equipped with current sensor at all so it is probed only when configured.
+        let mut current_sensor = match self.current_sensor_address {
+            Some(address) => {
+                match Self::try_to_initialize_current_sensor(self.command_context.clone(), address)
+                    .await
+                {
+                    Ok(current_sensor) => {
+                        if current_sensor.is_none() {
+                            warn!(
+                                "Hashboard {} has no current sensor at {:#x}",
+                                self.hashboard_idx, address
+                            );
+                        }
+                        current_sensor
+                    }
+                    Err(e) => {
+                        error!("Current sensor probing failed: {}", e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
         // "Watchdog" loop that pings monitor every some seconds
         loop {
             // If we have temperature sensor, try to read it
@@ -830,6 +1082,17 @@ impl HashChain {
                 sensor::INVALID_TEMPERATURE_READING
             };
 
+            if let Some(current_sensor) = current_sensor.as_mut() {
+                let current = match current_sensor.read_current().await {
+                    Ok(current) => Some(current),
+                    Err(e) => {
+                        error!("Sensor current read failed: {}", e);
+                        None
+                    }
+                };
+                *self.current.lock().await = current;
+            }
+
             // Broadcast
             temperature_sender
                 .broadcast(Some(temp.clone()))
@@ -862,13 +1125,106 @@ impl HashChain {
             let mut sum = 0;
             for (chip_address, hashrate_reg) in responses.iter().enumerate() {
                 trace!(
-                    "chip {} hashrate {} GHash/s",
+                    "chip {} hashrate {}",
                     chip_address,
-                    hashrate_reg.hashrate() as f64 / 1e9
+                    ii_bitcoin::format_hashrate(
+                        hashrate_reg.hashrate() as f64,
+                        ii_bitcoin::HashrateUnit::GigaHashes
+                    )
                 );
                 sum += hashrate_reg.hashrate() as u128;
             }
-            info!("Total chip hashrate {} GH/s", sum as f64 / 1e9);
+            info!(
+                "Total chip hashrate {}",
+                ii_bitcoin::format_hashrate_auto(sum as f64)
+            );
+        }
+    }
+
+    /// Periodically sample rate of valid solutions and report its sustained decline which is an
+    /// early sign of degrading chips. Change of frequency (retuning, hash rate cap) changes the
+    /// expected rate so the baseline is learned again.
+    async fn solution_rate_monitor_task(self: Arc<Self>) {
+        let error_registry = match self.error_registry.as_ref() {
+            Some(error_registry) => error_registry.clone(),
+            None => return,
+        };
+        let mut detector = stats::anomaly::RateDecline::new(Default::default());
+        let mut last_valid = self.counter.lock().await.valid;
+        let mut last_frequency = self.get_frequency().await.total();
+        loop {
+            delay_for(SOLUTION_RATE_SAMPLE_INTERVAL).await;
+
+            let valid = self.counter.lock().await.valid;
+            // the counter may have been reset in the meantime
+            let count = if valid >= last_valid {
+                valid - last_valid
+            } else {
+                valid
+            };
+            last_valid = valid;
+
+            let frequency = self.get_frequency().await.total();
+            if frequency != last_frequency {
+                // the sample is taken partially at the previous frequency
+                last_frequency = frequency;
+                detector.reset();
+                continue;
+            }
+
+            if let Some(anomaly) = detector.account_count(count, SOLUTION_RATE_SAMPLE_INTERVAL) {
+                let message = format!(
+                    "solution rate declined to {:.1}% of baseline",
+                    anomaly.ratio()
+                );
+                warn!("Hashchain {}: {}", self.hashboard_idx, message);
+                error_registry.push(
+                    ConditionKind::SolutionRateDecline,
+                    Severity::Warning,
+                    format!("chain {}", self.hashboard_idx),
+                    message,
+                );
+            }
+        }
+    }
+
+    /// Keep hash rate of the chain at the fraction of nominal hash rate requested by `hashrate_cap`
+    /// Frequency of the chips is ramped step by step to prevent sudden changes of power
+    /// consumption. Releasing the cap ramps the chips back to the nominal frequency which is read
+    /// in every step because the chain may be retuned in the meantime. Voltage is left intact
+    /// because lowering it in the middle of the ramp could make the chips unstable.
+    async fn hashrate_cap_task(self: Arc<Self>) {
+        let mut cap_receiver = self.hashrate_cap.subscribe();
+        loop {
+            let fraction = *cap_receiver.borrow();
+            loop {
+                // converge to the latest cap when it has changed in the meantime
+                if *cap_receiver.borrow() != fraction {
+                    break;
+                }
+                let frequency = self.get_frequency().await;
+                let nominal_frequency = self.get_nominal_frequency().await;
+                let next_frequency = match frequency.ramp_toward_cap(
+                    &nominal_frequency,
+                    fraction,
+                    FREQUENCY_RAMP_STEP,
+                ) {
+                    Some(next_frequency) => next_frequency,
+                    None => break,
+                };
+                if let Err(e) = self.set_pll(&next_frequency).await {
+                    error!(
+                        "Chain {}: cannot ramp frequency to {}: {}",
+                        self.hashboard_idx, next_frequency, e
+                    );
+                    break;
+                }
+                delay_for(FREQUENCY_RAMP_INTERVAL).await;
+            }
+            if *cap_receiver.borrow() == fraction && cap_receiver.recv().await.is_none() {
+                // hash rate cap has been dropped together with work generator
+                return;
+            }
         }
     }
 
@@ -878,16 +1234,27 @@ impl HashChain {
         solution_sender: work::SolutionSender,
         work_registry: Arc<Mutex<registry::WorkRegistry>>,
     ) {
-        // spawn tx task
-        let tx_fifo = self.take_work_tx_io().await;
+        // frequency set during initialization (or by tuning profile) is nominal
+        *self.nominal_frequency.lock().await = self.get_frequency().await;
         self.halt_receiver
-            .register_client("work-tx".into())
+            .register_client("hashrate cap".into())
             .await
-            .spawn(Self::work_tx_task(
-                work_registry.clone(),
-                tx_fifo,
-                work_generator,
-            ));
+            .spawn(Self::hashrate_cap_task(self.clone()));
+
+        // spawn tx task which drives the work generation (on dedicated cores when configured)
+        let tx_fifo = self.take_work_tx_io().await;
+        let work_pinning = work_generator.pinning().cloned();
+        let work_tx_task =
+            Self::work_tx_task(self.clone(), work_registry.clone(), tx_fifo, work_generator);
+        let work_tx_halt = self.halt_receiver.register_client("work-tx".into()).await;
+        match work_pinning {
+            Some(pinning) => work_tx_halt.spawn_pinned(
+                &pinning,
+                &format!("work-tx-chain{}", self.hashboard_idx),
+                work_tx_task,
+            ),
+            None => work_tx_halt.spawn(work_tx_task),
+        }
 
         // spawn rx task
         let rx_fifo = self.take_work_rx_io().await;
@@ -911,6 +1278,20 @@ impl HashChain {
             .spawn(Self::hashrate_monitor_task(self.clone()));
         */
 
+        // spawn solution rate monitor
+        self.halt_receiver
+            .register_client("solution rate monitor".into())
+            .await
+            .spawn(Self::solution_rate_monitor_task(self.clone()));
+
+        // spawn chip re-enumeration
+        if let Some(interval) = self.chip_reenumeration_interval {
+            self.halt_receiver
+                .register_client("chip re-enumeration".into())
+                .await
+                .spawn(Self::chip_reenumeration_task(self.clone(), interval));
+        }
+
         // spawn temperature monitor
         self.halt_receiver
             .register_client("temperature monitor".into())
@@ -930,12 +1311,53 @@ impl HashChain {
         self.frequency.lock().await.clone()
     }
 
+    /// Return frequency of the chain when its hash rate is not capped
+    pub async fn get_nominal_frequency(&self) -> FrequencySettings {
+        self.nominal_frequency.lock().await.clone()
+    }
+
+    /// Change nominal frequency of the chain (e.g. by tuning) and apply it scaled by the current
+    /// hash rate cap
+    pub async fn set_frequency(&self, frequency: &FrequencySettings) -> error::Result<()> {
+        *self.nominal_frequency.lock().await = frequency.clone();
+        self.set_pll(&frequency.scaled(self.hashrate_cap.get()))
+            .await
+    }
+
     pub async fn get_voltage(&self) -> power::Voltage {
         self.voltage_ctrl
             .get_current_voltage()
             .await
             .expect("BUG: no voltage on hashchain")
     }
+
+    /// Capture nominal per-chip frequency and voltage for reapplying them on the next start
+    pub async fn tuning_profile(&self) -> tuning::Profile {
+        tuning::Profile::new(
+            self.hashboard_idx,
+            &self.get_nominal_frequency().await,
+            self.get_voltage().await,
+        )
+    }
+
+    /// Load tuning profile from `path` and apply it when it matches this hashboard
+    /// Returns `false` when there is no saved profile.
+    async fn apply_tuning_profile(&self, path: &Path) -> error::Result<bool> {
+        let profile = match tuning::Profile::load(path)? {
+            Some(profile) => profile,
+            None => return Ok(false),
+        };
+        profile.validate(self.hashboard_idx, self.chip_count)?;
+
+        info!(
+            "chain {}: applying tuning profile '{}'",
+            self.hashboard_idx,
+            path.display()
+        );
+        self.set_pll(&profile.frequency()).await?;
+        self.voltage_ctrl.set_voltage(profile.voltage()?).await?;
+        Ok(true)
+    }
 }
 
 impl fmt::Debug for HashChain {
@@ -952,7 +1374,7 @@ impl fmt::Display for HashChain {
 
 type Frequency = usize;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct FrequencySettings {
     pub chip: Vec<Frequency>,
 }
@@ -990,6 +1412,57 @@ impl FrequencySettings {
         (sum / self.chip.len() as u64) as usize
     }
 
+    /// Scale frequency of all chips by `fraction` without going below the minimal supported
+    /// frequency. Nominal hash rate of the chain is proportional to the total frequency.
+    pub fn scaled(&self, fraction: f64) -> Self {
+        let min_frequency = (config::FREQUENCY_MHZ_MIN * 1_000_000.0) as Frequency;
+        Self {
+            chip: self
+                .chip
+                .iter()
+                .map(|&frequency| {
+                    let scaled = (frequency as f64 * fraction).round() as Frequency;
+                    scaled.max(min_frequency.min(frequency))
+                })
+                .collect(),
+        }
+    }
+
+    /// Return next step of frequency ramp toward `nominal` settings scaled by hash rate cap
+    /// `fraction` or `None` when the capped frequency has been already reached
+    pub fn ramp_toward_cap(&self, nominal: &Self, fraction: f64, step: Frequency) -> Option<Self> {
+        let target = nominal.scaled(fraction);
+        if *self == target {
+            None
+        } else {
+            Some(self.ramp_toward(&target, step))
+        }
+    }
+
+    /// Return next step of frequency ramp from current settings to `target` where frequency of
+    /// each chip is changed at most by `step`
+    pub fn ramp_toward(&self, target: &Self, step: Frequency) -> Self {
+        assert_eq!(
+            self.chip.len(),
+            target.chip.len(),
+            "BUG: ramp between different chip counts"
+        );
+        Self {
+            chip: self
+                .chip
+                .iter()
+                .zip(target.chip.iter())
+                .map(|(&current, &target)| {
+                    if current < target {
+                        target.min(current + step)
+                    } else {
+                        target.max(current.saturating_sub(step))
+                    }
+                })
+                .collect(),
+        }
+    }
+
     fn pretty_frequency(freq: usize) -> String {
         format!("{:.01} MHz", (freq as f32) / 1_000_000.0)
     }
@@ -1040,22 +1513,38 @@ impl StoppedChain {
         initial_voltage: power::Voltage,
         asic_difficulty: usize,
     ) -> Result<RunningChain, (Self, error::Error)> {
-        // if miner initialization fails, retry
-        let mut tries_left = ENUM_RETRY_COUNT;
+        // if miner initialization fails, retry with backoff until the chain gets disabled
+        let mut attempt = 0;
 
         loop {
+            if self.manager.is_halted() {
+                let hashboard_idx = self.manager.hashboard_idx;
+                return Err((
+                    self,
+                    ErrorKind::Hashboard(hashboard_idx, "miner is being halted".into()).into(),
+                ));
+            }
+            if self.manager.restart_backoff().is_disabled() {
+                let hashboard_idx = self.manager.hashboard_idx;
+                return Err((
+                    self,
+                    ErrorKind::Hashboard(hashboard_idx, "disabled after repeated failures".into())
+                        .into(),
+                ));
+            }
+
             info!(
                 "Registering hashboard {} with monitor",
                 self.manager.hashboard_idx
             );
 
             // Start this hashchain
-            // If we've already exhausted half of our tries, then stop worrying about having
-            // less chips than expected (63).
+            // If we've already failed several times, then stop worrying about having less chips
+            // than expected (63).
             match self
                 .manager
                 .attempt_start_chain(
-                    tries_left <= ENUM_RETRY_COUNT / 2,
+                    attempt >= ENUM_STRICT_ATTEMPTS,
                     initial_frequency,
                     initial_voltage,
                     asic_difficulty,
@@ -1064,6 +1553,8 @@ impl StoppedChain {
             {
                 // start successful
                 Ok(_) => {
+                    // the hashboard is fine so only consecutive failures may disable it
+                    self.manager.restart_backoff().reset();
                     // we've started the hashchain
                     // create a `Running` tape and be gone
                     return Ok(RunningChain::from_manager(
@@ -1076,15 +1567,31 @@ impl StoppedChain {
                     error!("Chain {} start failed: {}", self.manager.hashboard_idx, e);
 
                     // retry if possible
-                    if tries_left == 0 {
-                        error!("No tries left");
-                        return Err((self, e.into()));
-                    } else {
-                        tries_left -= 1;
-                        // TODO: wait with locks unlocked()! Otherwise no-one can halt the miner
-                        // This is not possible with current lock design, but fix this ASAP!
-                        delay_for(ENUM_RETRY_DELAY).await;
-                        info!("Retrying chain {} start...", self.manager.hashboard_idx);
+                    let decision = self.manager.restart_backoff().failure(Instant::now());
+                    match decision {
+                        restart::Decision::Disable => {
+                            error!("No tries left");
+                            self.manager.report_disabled_chain();
+                            return Err((self, e.into()));
+                        }
+                        restart::Decision::Retry(delay) => {
+                            attempt += 1;
+                            info!(
+                                "Retrying chain {} start in {:?}...",
+                                self.manager.hashboard_idx, delay
+                            );
+                            // release the chain while waiting so that other owners are not blocked
+                            // for the whole delay
+                            let owner = self.manager.release_ownership();
+                            // the wait is interrupted when the miner is halted (checked above)
+                            self.manager.wait_for_restart(delay).await;
+                            self.manager.reclaim_ownership(owner).await;
+                            // the chain may have been started by other owner in the meantime
+                            let inner = self.manager.inner.lock().await;
+                            if inner.hash_chain.is_some() {
+                                return Ok(RunningChain::from_manager(self.manager.clone(), inner));
+                            }
+                        }
                     }
                 }
             }
@@ -1142,6 +1649,16 @@ impl RunningChain {
             .await
     }
 
+    pub async fn get_nominal_frequency(&self) -> FrequencySettings {
+        let inner = self.manager.inner.lock().await;
+        inner
+            .hash_chain
+            .as_ref()
+            .expect("BUG: hashchain is not running")
+            .get_nominal_frequency()
+            .await
+    }
+
     /// TODO: for the love of god use macros or something
     pub async fn get_voltage(&self) -> power::Voltage {
         let inner = self.manager.inner.lock().await;
@@ -1153,14 +1670,26 @@ impl RunningChain {
             .await
     }
 
+    /// Change frequency of the running chain and start hashrate warm-up of the chain (when it is
+    /// configured) because its solution rate is erratic right after the change
     pub async fn set_frequency(&self, frequency: &FrequencySettings) -> error::Result<()> {
         let inner = self.manager.inner.lock().await;
         inner
             .hash_chain
             .as_ref()
             .expect("BUG: hashchain is not running")
-            .set_pll(frequency)
-            .await
+            .set_frequency(frequency)
+            .await?;
+        if let Some(hashrate_warmup) = self.manager.chain_config.hashrate_warmup {
+            stats::Mining::start_warmup(&self.manager.work_solver_stats, hashrate_warmup);
+        }
+        Ok(())
+    }
+
+    /// Persist current frequency and voltage of the chain so that it is reapplied on next start
+    /// (see `config::Backend::tuning_profile_dir`)
+    pub async fn save_tuning_profile(&self) -> error::Result<()> {
+        self.manager.save_tuning_profile().await
     }
 
     pub async fn set_voltage(&self, voltage: power::Voltage) -> error::Result<()> {
@@ -1305,14 +1834,95 @@ pub struct Manager {
     midstate_count: MidstateCount,
     /// channel to report to the monitor
     monitor_tx: mpsc::UnboundedSender<monitor::Message>,
+    /// Registry passed to every started hashchain
+    error_registry: Option<Arc<ErrorRegistry>>,
     /// TODO: wrap this type in a structure (in Monitor)
     pub status_receiver: watch::Receiver<Option<monitor::Status>>,
     owned_by: StdMutex<Option<&'static str>>,
+    /// Failures of recent starts which determine delay before the next one
+    restart_backoff: StdMutex<restart::Backoff>,
+    /// Set when the miner is being halted so that pending restarts are abandoned
+    halted_sender: watch::Sender<bool>,
+    halted_receiver: watch::Receiver<bool>,
     pub inner: Mutex<ManagerInner>,
     pub chain_config: config::ResolvedChainConfig,
 }
 
 impl Manager {
+    fn restart_backoff(&self) -> StdMutexGuard<restart::Backoff> {
+        self.restart_backoff
+            .lock()
+            .expect("BUG: cannot lock restart backoff")
+    }
+
+    fn is_halted(&self) -> bool {
+        *self.halted_receiver.borrow()
+    }
+
+    /// Give up ownership of the chain and return the name of its owner
+    fn release_ownership(&self) -> &'static str {
+        self.owned_by
+            .lock()
+            .expect("BUG: failed to lock mutex")
+            .take()
+            .expect("BUG: chain is not owned")
+    }
+
+    /// Take ownership of the chain back once it is released by its current owner
+    async fn reclaim_ownership(&self, owner_name: &'static str) {
+        loop {
+            {
+                let mut owned_by = self.owned_by.lock().expect("BUG: failed to lock mutex");
+                if owned_by.is_none() {
+                    owned_by.replace(owner_name);
+                    return;
+                }
+            }
+            delay_for(OWNERSHIP_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Wait for the next start attempt without holding any lock or ownership of the chain. The
+    /// wait ends early when the miner is being halted.
+    async fn wait_for_restart(&self, delay: Duration) {
+        let mut halted_receiver = self.halted_receiver.clone();
+        let wait_for_halt = async move {
+            while let Some(halted) = halted_receiver.recv().await {
+                if halted {
+                    break;
+                }
+            }
+        };
+        select! {
+            _ = delay_for(delay).fuse() => {}
+            _ = wait_for_halt.fuse() => {}
+        }
+    }
+
+    /// Alert that the hashchain keeps failing and it will not be restarted anymore
+    fn report_disabled_chain(&self) {
+        if let Some(error_registry) = self.error_registry.as_ref() {
+            error_registry.push(
+                ConditionKind::ChainDisabled,
+                Severity::Critical,
+                format!("chain {}", self.hashboard_idx),
+                format!(
+                    "chain has been disabled after {} failed starts",
+                    self.restart_backoff().failures()
+                ),
+            );
+        }
+    }
+
+    /// Current drawn by the hashboard measured by its current sensor (`None` when the chain is not
+    /// running or the board has no current sensor)
+    pub async fn read_current(&self) -> Option<f32> {
+        match self.inner.lock().await.hash_chain.as_ref() {
+            Some(hash_chain) => hash_chain.current().await,
+            None => None,
+        }
+    }
+
     /// Acquire stopped or running chain
     pub async fn acquire(
         self: Arc<Self>,
@@ -1370,6 +1980,10 @@ impl Manager {
             self.monitor_tx.clone(),
         )
         .expect("BUG: hashchain instantiation failed");
+        hash_chain.error_registry = self.error_registry.clone();
+        hash_chain.hashrate_cap = self.work_generator.hashrate_cap().clone();
+        hash_chain.chip_reenumeration_interval = self.chain_config.chip_reenumeration_interval;
+        hash_chain.current_sensor_address = self.chain_config.current_sensor_address;
 
         // initialize it
         let work_registry = match hash_chain
@@ -1389,6 +2003,16 @@ impl Manager {
             Ok(a) => a,
         };
 
+        // reapply settings found by previous tuning instead of the default ones
+        if let Some(path) = self.chain_config.tuning_profile_path.as_ref() {
+            if let Err(e) = hash_chain.apply_tuning_profile(path).await {
+                warn!(
+                    "Chain {}: tuning profile has not been applied: {}",
+                    self.hashboard_idx, e
+                );
+            }
+        }
+
         // spawn worker tasks for hash chain and start mining
         let hash_chain = Arc::new(hash_chain);
         hash_chain
@@ -1427,7 +2051,23 @@ impl Manager {
             .expect("BUG: send failed");
     }
 
+    /// Persist current frequency and voltage of running hashchain to its tuning profile
+    async fn save_tuning_profile(&self) -> error::Result<()> {
+        let path = self
+            .chain_config
+            .tuning_profile_path
+            .clone()
+            .ok_or_else(|| ErrorKind::General("tuning profiles are not enabled".to_string()))?;
+        let inner = self.inner.lock().await;
+        match inner.hash_chain.as_ref() {
+            Some(hash_chain) => hash_chain.tuning_profile().await.save(&path),
+            None => Err(ErrorKind::General("hashchain is not running".to_string()))?,
+        }
+    }
+
     async fn termination_handler(self: Arc<Self>) {
+        // abandon pending restart of failed hashchain
+        let _ = self.halted_sender.broadcast(true);
         self.stop_chain(true).await;
     }
 }
@@ -1448,6 +2088,22 @@ impl node::WorkSolver for Manager {
             None => None,
         }
     }
+
+    async fn get_estimated_power(&self) -> Option<f64> {
+        let inner = self.inner.lock().await;
+        match inner.hash_chain.as_ref() {
+            Some(hash_chain) => {
+                let freq_sum = hash_chain.frequency.lock().await.total();
+                let current = hash_chain.current().await;
+                hash_chain
+                    .voltage_ctrl
+                    .get_current_voltage()
+                    .await
+                    .map(|voltage| voltage.power(current, freq_sum))
+            }
+            None => None,
+        }
+    }
 }
 
 impl fmt::Debug for Manager {
@@ -1535,9 +2191,89 @@ impl Backend {
     }
 
     /// Miner termination handler called when app is shutdown.
-    /// Just propagate the shutdown to all hashchain managers
-    async fn termination_handler(halt_sender: Arc<halt::Sender>) {
-        halt_sender.send_halt().await;
+    /// Propagate the shutdown to all subsystems in the order given by shutdown phases
+    async fn termination_handler(coordinator: Arc<shutdown::Coordinator>) {
+        coordinator.shutdown().await;
+    }
+
+    /// Let monitor know when hashing is paused (work generation of all hashchains is disabled)
+    /// so that it can stop the fans of an idle miner
+    async fn hashing_pause_task(managers: Vec<Arc<Manager>>, monitor: Arc<monitor::Monitor>) {
+        let mut was_paused = false;
+        loop {
+            let paused = managers
+                .iter()
+                .all(|manager| !manager.work_generator.switch().is_enabled());
+            if paused != was_paused {
+                info!(
+                    "Hashing of all chains has been {}",
+                    if paused { "paused" } else { "resumed" }
+                );
+                monitor.set_hashing_paused(paused).await;
+                was_paused = paused;
+            }
+            delay_for(HASHING_PAUSE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Periodically check shares resolved by all pools and lower frequency of all running chains
+    /// when too many of them are rejected as invalid. The reduced frequency is not persisted and
+    /// releasing of hash rate cap ramps the chips back to the frequency set on chain start.
+    async fn reject_throttle_task(
+        managers: Vec<Arc<Manager>>,
+        client_manager: client::Manager,
+        config: reject_throttle::Config,
+    ) {
+        let mut throttle = reject_throttle::Throttle::new(config);
+        loop {
+            delay_for(throttle.config().sample_interval).await;
+            let (accepted, invalid) = client_manager.accepted_and_invalid_shares().await;
+            if !throttle.account(accepted, invalid) {
+                continue;
+            }
+            for manager in managers.iter() {
+                let hashboard_idx = manager.hashboard_idx;
+                let chain = match manager.clone().acquire("reject throttle").await {
+                    Ok(ChainStatus::Running(chain)) => chain,
+                    Ok(ChainStatus::Stopped(_)) => continue,
+                    Err(owner) => {
+                        warn!(
+                            "Chain {}: frequency not reduced, chain is owned by {}",
+                            hashboard_idx, owner
+                        );
+                        continue;
+                    }
+                };
+                // the chain may be slowed down by hash rate cap which is not a retuning
+                let frequency = chain.get_nominal_frequency().await;
+                match throttle.reduce(&frequency) {
+                    Some(reduced) => {
+                        warn!(
+                            "Chain {}: too many invalid shares, reducing frequency {} -> {}",
+                            hashboard_idx, frequency, reduced
+                        );
+                        if let Err(e) = chain.set_frequency(&reduced).await {
+                            error!("Chain {}: cannot reduce frequency: {}", hashboard_idx, e);
+                            continue;
+                        }
+                        // the reduced frequency is what the board has been measured to sustain
+                        if manager.chain_config.tuning_profile_path.is_some() {
+                            match chain.save_tuning_profile().await {
+                                Ok(_) => info!("Chain {}: tuning profile saved", hashboard_idx),
+                                Err(e) => warn!(
+                                    "Chain {}: tuning profile has not been saved: {}",
+                                    hashboard_idx, e
+                                ),
+                            }
+                        }
+                    }
+                    None => warn!(
+                        "Chain {}: too many invalid shares at minimal frequency {}",
+                        hashboard_idx, frequency
+                    ),
+                }
+            }
+        }
     }
 
     /// Start miner
@@ -1547,6 +2283,7 @@ impl Backend {
         enabled_chains: Vec<usize>,
         work_hub: work::SolverBuilder<Backend>,
         backend_config: config::Backend,
+        client_manager: client::Manager,
         app_halt_receiver: halt::Receiver,
         app_halt_sender: Arc<halt::Sender>,
     ) -> (Vec<Arc<Manager>>, Arc<monitor::Monitor>) {
@@ -1556,12 +2293,15 @@ impl Backend {
             None => Arc::new(hooks::NoHooks),
         };
 
-        // Create new termination context and link it to the main (app) termination context
-        let (halt_sender, halt_receiver) = halt::make_pair(HALT_TIMEOUT);
+        // Create new ordered termination context and link it to the main (app) termination
+        // context. Hash chains are stopped in the hardware phase of the shutdown.
+        let coordinator = shutdown::Coordinator::new(shutdown::Phase::default_timeout);
+        let halt_sender = coordinator.sender(shutdown::Phase::StopHardware);
+        let halt_receiver = coordinator.receiver(shutdown::Phase::StopHardware);
         app_halt_receiver
             .register_client("miner termination".into())
             .await
-            .spawn_halt_handler(Self::termination_handler(halt_sender.clone()));
+            .spawn_halt_handler(Self::termination_handler(coordinator.clone()));
         hooks
             .halt_created(
                 halt_sender.clone(),
@@ -1569,15 +2309,17 @@ impl Backend {
                 app_halt_sender.clone(),
             )
             .await;
+        hooks.shutdown_created(coordinator.clone()).await;
 
-        // Start monitor in main (app) termination context
-        // Let it shutdown the main context as well
+        // Start monitor in the last phase of the shutdown to keep cooling until hash chains are
+        // stopped. Let it shutdown the main context as well
         let monitor_config = backend_config.resolve_monitor_config();
         info!("Resolved monitor backend_config: {:?}", monitor_config);
         let monitor = monitor::Monitor::new_and_start(
             monitor_config,
             app_halt_sender.clone(),
-            app_halt_receiver.clone(),
+            coordinator.receiver(shutdown::Phase::StopFans),
+            backend_config.error_registry.clone(),
         )
         .await;
         hooks.monitor_started(monitor.clone()).await;
@@ -1597,6 +2339,8 @@ impl Backend {
             let chain_config = backend_config.resolve_chain_config(hashboard_idx);
 
             let status_receiver = monitor.status_receiver.clone();
+            let error_registry = backend_config.error_registry.clone();
+            let (halted_sender, halted_receiver) = watch::channel(false);
 
             // build hashchain_node for statistics and static parameters
             let manager = work_hub
@@ -1617,8 +2361,14 @@ impl Backend {
                         solution_sender,
                         work_generator,
                         monitor_tx,
+                        error_registry,
                         status_receiver,
                         owned_by: StdMutex::new(None),
+                        restart_backoff: StdMutex::new(restart::Backoff::new(
+                            chain_config.restart.clone(),
+                        )),
+                        halted_sender,
+                        halted_receiver,
                         inner: Mutex::new(ManagerInner {
                             hash_chain: None,
                             start_count: 0,
@@ -1630,6 +2380,18 @@ impl Backend {
             managers.push(manager);
         }
 
+        // Stop jobs of all hash chains and drain submissions of the clients before the hash
+        // chains are stopped
+        shutdown::register_frontend(
+            &coordinator,
+            managers
+                .iter()
+                .map(|manager| manager.work_generator.switch().clone())
+                .collect(),
+            client_manager,
+        )
+        .await;
+
         // start everything
         for manager in managers.iter() {
             let halt_receiver = halt_receiver.clone();
@@ -1649,7 +2411,8 @@ impl Backend {
             // want us to start it (default `NoHooks` has all chains enabled).
             if hooks.can_start_chain(manager.clone()).await {
                 tokio::spawn(async move {
-                    manager
+                    let result = manager
+                        .clone()
                         .acquire("main")
                         .await
                         .expect("BUG: failed to acquire hashchain")
@@ -1659,8 +2422,13 @@ impl Backend {
                             initial_voltage,
                             config::DEFAULT_ASIC_DIFFICULTY,
                         )
-                        .await
-                        .expect("BUG: failed to start hashchain");
+                        .await;
+                    if let Err((_, e)) = result {
+                        error!(
+                            "Chain {} has not been started: {}",
+                            manager.hashboard_idx, e
+                        );
+                    }
                 });
             }
         }
@@ -1693,15 +2461,19 @@ impl hal::Backend for Backend {
             .expect("BUG: missing client manager");
         let group_configs = backend_config.groups.take();
         let backend_info = backend_config.info();
+        let reject_throttle = backend_config.reject_throttle.take();
 
         let backend = work_hub.to_node().clone();
         let gpio_mgr = gpio::ControlPinManager::new();
-        let (app_halt_sender, app_halt_receiver) = halt::make_pair(HALT_TIMEOUT);
+        // The miner termination client runs the whole ordered shutdown
+        let (app_halt_sender, app_halt_receiver) =
+            halt::make_pair(shutdown::Phase::default_total_timeout());
         let (managers, monitor) = Self::start_miner(
             &gpio_mgr,
             Self::detect_hashboards(&gpio_mgr).expect("failed detecting hashboards"),
             work_hub,
             backend_config,
+            client_manager.clone(),
             app_halt_receiver,
             app_halt_sender.clone(),
         )
@@ -1717,6 +2489,22 @@ impl hal::Backend for Backend {
         // Hook `Ctrl-C`, `SIGTERM` and other termination methods
         app_halt_sender.hook_termination_signals();
 
+        // Suggest pools difficulty which the hardware is able to handle (unless the pool URL sets
+        // its own minimum)
+        let nominal_hashrate: u128 = managers
+            .iter()
+            .map(|manager| {
+                (manager.chain_config.frequency.total() as u128)
+                    * (bm1387::NUM_CORES_ON_CHIP as u128)
+            })
+            .sum();
+        client_manager
+            .set_hardware_min_difficulty(client::DifficultyFloor::hardware_minimum(
+                nominal_hashrate.into(),
+                &ii_bitcoin::Target::from_pool_difficulty(config::DEFAULT_ASIC_DIFFICULTY),
+            ))
+            .await;
+
         // Load initial pool configuration
         client_manager
             .load_config(
@@ -1727,11 +2515,33 @@ impl hal::Backend for Backend {
             .await?;
         if let Some(hooks) = hooks {
             // Pass the client manager to hook for further processing
-            hooks.clients_loaded(client_manager).await;
+            hooks.clients_loaded(client_manager.clone()).await;
+        }
+        if let Some(reject_throttle) = reject_throttle {
+            tokio::spawn(Self::reject_throttle_task(
+                managers.clone(),
+                client_manager,
+                reject_throttle,
+            ));
+        }
+        if monitor
+            .with_configuration(|config| config.idle_fan_stop_temp.is_some())
+            .await
+        {
+            tokio::spawn(Self::hashing_pause_task(managers.clone(), monitor.clone()));
         }
 
+        // Aggregated temperature of all hash chains is taken from the last monitor status
+        let status_receiver = monitor.status_receiver.clone();
+        let temperature_source: stats::csv::TemperatureSource =
+            Box::new(move || match status_receiver.borrow().as_ref()?.input_temperature {
+                monitor::ChainTemperature::Ok(temperature) => Some(temperature),
+                _ => None,
+            });
+
         Ok(hal::FrontendConfig {
             cgminer_custom_commands: cgminer::create_custom_commands(backend, managers, monitor),
+            temperature_source: Some(temperature_source),
         })
     }
 
@@ -1748,6 +2558,10 @@ impl node::WorkSolver for Backend {
     async fn get_nominal_hashrate(&self) -> Option<ii_bitcoin::HashesUnit> {
         None
     }
+
+    async fn get_estimated_power(&self) -> Option<f64> {
+        None
+    }
 }
 
 impl fmt::Display for Backend {
@@ -0,0 +1,234 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! This module keeps the log readable during failure storms. Messages from high-volume error
+//! paths are logged individually only up to a limit within each period and the rest of them is
+//! collapsed into a summary with the number of errors.
+
+use ii_logging::macros::*;
+
+use std::time::{Duration, Instant};
+
+/// Length of the period in which repeated messages are counted
+pub const DEFAULT_PERIOD: Duration = Duration::from_secs(10);
+/// Number of messages logged individually in each period
+pub const DEFAULT_BURST: usize = 5;
+
+/// Errors counted within a period of which some have not been logged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub suppressed: usize,
+}
+
+/// Decision about single occurrence of the throttled message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Verdict {
+    /// The message may be logged
+    pub log: bool,
+    /// Summary of the previous period which has to be logged first
+    pub summary: Option<Summary>,
+}
+
+/// Owned by the task running the error path so no locking is needed. The summary of a period is
+/// logged with the first error of the following period, by `tick` once the period is over or
+/// when the throttle is dropped.
+#[derive(Debug)]
+pub struct LogThrottle {
+    /// Description of counted errors used in summaries (e.g. "hardware errors")
+    name: &'static str,
+    period: Duration,
+    burst: usize,
+    period_start: Option<Instant>,
+    count: usize,
+}
+
+impl LogThrottle {
+    pub fn new(name: &'static str) -> Self {
+        Self::with_limits(name, DEFAULT_PERIOD, DEFAULT_BURST)
+    }
+
+    pub fn with_limits(name: &'static str, period: Duration, burst: usize) -> Self {
+        Self {
+            name,
+            period,
+            burst,
+            period_start: None,
+            count: 0,
+        }
+    }
+
+    fn is_period_over(&self, now: Instant) -> bool {
+        self.period_start
+            .map_or(true, |start| now.duration_since(start) >= self.period)
+    }
+
+    /// Reset counting and return summary of the period when some messages have been suppressed
+    fn finish_period(&mut self) -> Option<Summary> {
+        let summary = if self.count > self.burst {
+            Some(Summary {
+                count: self.count,
+                suppressed: self.count - self.burst,
+            })
+        } else {
+            None
+        };
+        self.period_start = None;
+        self.count = 0;
+        summary
+    }
+
+    fn account(&mut self, now: Instant) -> Verdict {
+        let mut summary = None;
+        if self.is_period_over(now) {
+            summary = self.finish_period();
+            self.period_start = Some(now);
+        }
+        self.count += 1;
+        Verdict {
+            log: self.count <= self.burst,
+            summary,
+        }
+    }
+
+    /// Account an error and return whether its message may be logged. The summary of the previous
+    /// period is logged here when some messages have been suppressed.
+    pub fn pass(&mut self) -> bool {
+        let verdict = self.account(Instant::now());
+        if let Some(summary) = verdict.summary {
+            self.log_summary(summary);
+        }
+        verdict.log
+    }
+
+    /// Return summary of the period which is over without waiting for the next error
+    fn poll(&mut self, now: Instant) -> Option<Summary> {
+        if self.period_start.is_some() && self.is_period_over(now) {
+            self.finish_period()
+        } else {
+            None
+        }
+    }
+
+    /// Log the summary as soon as the period is over even when the storm has ended. It is
+    /// intended to be called regularly from the task running the error path.
+    pub fn tick(&mut self) {
+        if let Some(summary) = self.poll(Instant::now()) {
+            self.log_summary(summary);
+        }
+    }
+
+    fn log_summary(&self, summary: Summary) {
+        warn!(
+            "{} {} within {} s (only first {} logged)",
+            summary.count,
+            self.name,
+            self.period.as_secs(),
+            self.burst
+        );
+    }
+}
+
+impl Drop for LogThrottle {
+    /// Do not lose the summary of the unfinished period
+    fn drop(&mut self) {
+        if let Some(summary) = self.finish_period() {
+            self.log_summary(summary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_burst() {
+        const BURST: usize = 3;
+        const STORM: usize = 100;
+
+        let mut throttle = LogThrottle::with_limits("hardware errors", DEFAULT_PERIOD, BURST);
+        let start = Instant::now();
+
+        // only the beginning of the storm is logged
+        let logged = (0..STORM)
+            .map(|i| throttle.account(start + Duration::from_millis(i as u64)))
+            .inspect(|verdict| assert_eq!(verdict.summary, None))
+            .filter(|verdict| verdict.log)
+            .count();
+        assert_eq!(logged, BURST);
+
+        // the rest is collapsed into the summary reported with the next error
+        assert_eq!(
+            throttle.account(start + DEFAULT_PERIOD),
+            Verdict {
+                log: true,
+                summary: Some(Summary {
+                    count: STORM,
+                    suppressed: STORM - BURST,
+                }),
+            }
+        );
+
+        // no summary when all errors of the period have been logged
+        assert_eq!(
+            throttle.account(start + DEFAULT_PERIOD * 2),
+            Verdict {
+                log: true,
+                summary: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_tick() {
+        const BURST: usize = 2;
+
+        let mut throttle = LogThrottle::with_limits("framing errors", DEFAULT_PERIOD, BURST);
+        let start = Instant::now();
+        assert_eq!(throttle.poll(start), None);
+
+        for i in 0..10 {
+            throttle.account(start + Duration::from_millis(i));
+        }
+        // the summary is not reported before the period is over
+        assert_eq!(throttle.poll(start + DEFAULT_PERIOD / 2), None);
+        // the summary of the ended storm is reported without waiting for the next error
+        assert_eq!(
+            throttle.poll(start + DEFAULT_PERIOD),
+            Some(Summary {
+                count: 10,
+                suppressed: 10 - BURST,
+            })
+        );
+        assert_eq!(throttle.poll(start + DEFAULT_PERIOD * 2), None);
+
+        // the next error starts a new period without repeating the summary
+        assert_eq!(
+            throttle.account(start + DEFAULT_PERIOD * 3),
+            Verdict {
+                log: true,
+                summary: None,
+            }
+        );
+    }
+}
@@ -22,13 +22,20 @@
 
 use ii_logging::macros::*;
 
+use bosminer::affinity;
+use bosminer::stats;
+
 use bosminer_am1_s9::config;
+use bosminer_am1_s9::reject_throttle;
+use bosminer_am1_s9::restart;
 
 use bosminer_config::clap;
 use bosminer_config::{ClientDescriptor, ClientUserInfo, GroupConfig, PoolConfig};
 
 use ii_async_compat::tokio;
 
+use std::time::Duration;
+
 #[tokio::main]
 async fn main() {
     let app = clap::App::new(bosminer::SIGNATURE)
@@ -80,6 +87,177 @@ async fn main() {
                 .required(false)
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("stats-csv")
+                .long("stats-csv")
+                .value_name("PATH")
+                .help("Periodically append mining statistics to CSV file")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("stats-csv-interval")
+                .long("stats-csv-interval")
+                .value_name("SECONDS")
+                .help("Set interval between two rows in statistics CSV file")
+                .required(false)
+                .requires("stats-csv")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("stats-log-interval")
+                .long("stats-log-interval")
+                .value_name("SECONDS")
+                .help("Periodically log status line with statistics of each hash chain")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("best-share-log-threshold")
+                .long("best-share-log-threshold")
+                .value_name("DIFFICULTY")
+                .help("Log every new best share with at least given difficulty")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("solution-socket")
+                .long("solution-socket")
+                .value_name("PATH")
+                .help("Stream valid solutions as JSON lines to subscribers of Unix socket")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("inflight-dump-dir")
+                .long("inflight-dump-dir")
+                .value_name("DIR")
+                .help("Save work in flight of each chain into given directory when the miner panics")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("hashrate-warmup")
+                .long("hashrate-warmup")
+                .value_name("SECONDS")
+                .help("Exclude shares found after start or frequency change from displayed hashrate for given period")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("max-ntime-roll")
+                .long("max-ntime-roll")
+                .value_name("SECONDS")
+                .help("Limit rolling of nTime in work and shares to given number of seconds")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("work-distribution")
+                .long("work-distribution")
+                .value_name("STRATEGY")
+                .help("Distribute jobs among 'midstates' (AsicBoost) or 'work-items'")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("work-seed")
+                .long("work-seed")
+                .value_name("SEED")
+                .help("Generate reproducible sequence of extranonce values from given seed")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("cross-check")
+                .long("cross-check")
+                .help("Verify all hardware solutions on CPU and report nonce/ntime offsets")
+                .required(false),
+        )
+        .arg(
+            clap::Arg::with_name("fastest-block-submission")
+                .long("fastest-block-submission")
+                .help("Submit found blocks also to the endpoint with the lowest latency")
+                .required(false),
+        )
+        .arg(
+            clap::Arg::with_name("midstate-verification")
+                .long("midstate-verification")
+                .help("Verify solutions from precomputed midstate instead of the full hash")
+                .required(false),
+        )
+        .arg(
+            clap::Arg::with_name("tuning-profiles")
+                .long("tuning-profiles")
+                .value_name("DIR")
+                .help("Reapply per-chip tuning profiles of hashboards saved in given directory")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("max-chain-failures")
+                .long("max-chain-failures")
+                .value_name("COUNT")
+                .help("Disable hashchain which fails to start this many times within an hour")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("reject-throttle")
+                .long("reject-throttle")
+                .value_name("RATIO")
+                .help("Lower frequency when pools keep rejecting more than this ratio of shares as invalid (e.g. 0.02)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("chip-reenumeration")
+                .long("chip-reenumeration")
+                .value_name("SECONDS")
+                .help("Periodically re-enumerate chips to detect chips dropping out of hash chains")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("current-sensor")
+                .long("current-sensor")
+                .value_name("ADDRESS")
+                .help("Measure power consumption by current sensor at given I2C address (hex) on hashboard revisions equipped with it")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("idle-fan-stop")
+                .long("idle-fan-stop")
+                .value_name("TEMP")
+                .help("Stop fans while hashing is paused and all chains are cooler than this temperature (Celsius)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("broken-grace-period")
+                .long("broken-grace-period")
+                .value_name("SECONDS")
+                .help("How long can be a hash chain broken before the miner is shut down")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("work-cores")
+                .long("work-cores")
+                .value_name("CORES")
+                .help("Pin work generation to CPU cores (comma separated list, e.g. 0,1)")
+                .required(false)
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("solution-cores")
+                .long("solution-cores")
+                .value_name("CORES")
+                .help("Pin handling of solutions to CPU cores (comma separated list, e.g. 2)")
+                .required(false)
+                .takes_value(true),
+        )
         .subcommand(
             clap::SubCommand::with_name("config")
                 .about("Configuration backend API")
@@ -242,6 +420,256 @@ async fn main() {
             .replace(voltage);
     }
 
+    if let Some(path) = matches.value_of("stats-csv") {
+        let interval = match matches.value_of("stats-csv-interval") {
+            None => config::DEFAULT_STATS_CSV_INTERVAL,
+            Some(value) => match value.parse::<u64>() {
+                Ok(0) => {
+                    error!("Cannot use zero statistics interval from command line");
+                    return;
+                }
+                Ok(value) => Duration::from_secs(value),
+                Err(e) => {
+                    error!(
+                        "Cannot use statistics interval '{}' from command line: {}",
+                        value,
+                        e.to_string()
+                    );
+                    return;
+                }
+            },
+        };
+        backend_config.stats_csv.replace(stats::csv::CsvConfig {
+            path: path.into(),
+            interval,
+        });
+    }
+
+    if let Some(value) = matches.value_of("stats-log-interval") {
+        let interval = match value.parse::<u64>() {
+            Ok(value) => Duration::from_secs(value),
+            Err(e) => {
+                error!(
+                    "Cannot use statistics log interval '{}' from command line: {}",
+                    value,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+        backend_config.stats_log_interval.replace(interval);
+    }
+
+    if let Some(value) = matches.value_of("best-share-log-threshold") {
+        let threshold = match value.parse::<u64>() {
+            Ok(value) => value,
+            Err(e) => {
+                error!(
+                    "Cannot use best share log threshold '{}' from command line: {}",
+                    value,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+        backend_config.best_share_log_threshold.replace(threshold);
+    }
+
+    if let Some(path) = matches.value_of("solution-socket") {
+        backend_config.solution_socket.replace(path.into());
+    }
+
+    if let Some(path) = matches.value_of("inflight-dump-dir") {
+        backend_config.inflight_dump_dir.replace(path.into());
+    }
+
+    if let Some(value) = matches.value_of("hashrate-warmup") {
+        let hashrate_warmup = match value.parse::<u64>() {
+            Ok(value) => Duration::from_secs(value),
+            Err(e) => {
+                error!(
+                    "Cannot use hashrate warm-up '{}' from command line: {}",
+                    value,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+        backend_config.hashrate_warmup.replace(hashrate_warmup);
+    }
+
+    if let Some(value) = matches.value_of("max-ntime-roll") {
+        let max_ntime_roll = match value.parse::<u32>() {
+            Ok(value) => value,
+            Err(e) => {
+                error!(
+                    "Cannot use maximal nTime roll '{}' from command line: {}",
+                    value,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+        backend_config.max_ntime_roll.replace(max_ntime_roll);
+    }
+
+    if let Some(value) = matches.value_of("work-distribution") {
+        backend_config.work_distribution = match value.parse() {
+            Ok(value) => value,
+            Err(e) => {
+                error!(
+                    "Cannot use work distribution '{}' from command line: {}",
+                    value,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+    }
+
+    if let Some(value) = matches.value_of("work-seed") {
+        let work_seed = match value.parse::<u64>() {
+            Ok(value) => value,
+            Err(e) => {
+                error!(
+                    "Cannot use work seed '{}' from command line: {}",
+                    value,
+                    e.to_string()
+                );
+                return;
+            }
+        };
+        backend_config.work_seed.replace(work_seed);
+    }
+
+    if matches.is_present("cross-check") {
+        backend_config.cross_check_solutions = true;
+    }
+
+    if matches.is_present("fastest-block-submission") {
+        backend_config.fastest_block_submission = true;
+    }
+
+    if matches.is_present("midstate-verification") {
+        backend_config.midstate_verification = true;
+    }
+
+    if let Some(dir) = matches.value_of("tuning-profiles") {
+        backend_config.tuning_profile_dir.replace(dir.into());
+    }
+
+    if let Some(value) = matches.value_of("max-chain-failures") {
+        match value.parse::<usize>() {
+            Ok(max_failures) if max_failures > 0 => {
+                backend_config.chain_restart = restart::Config::new(max_failures);
+            }
+            _ => {
+                error!(
+                    "Cannot use maximal number of hashchain failures '{}' from command line",
+                    value
+                );
+                return;
+            }
+        }
+    }
+
+    if let Some(value) = matches.value_of("reject-throttle") {
+        match value.parse::<f64>() {
+            Ok(ratio) if ratio > 0.0 && ratio < 1.0 => {
+                backend_config.reject_throttle = Some(reject_throttle::Config::new(ratio));
+            }
+            _ => {
+                error!(
+                    "Cannot use ratio of invalid shares '{}' from command line",
+                    value
+                );
+                return;
+            }
+        }
+    }
+
+    if let Some(value) = matches.value_of("chip-reenumeration") {
+        let interval = match value.parse::<u64>() {
+            Ok(value) if value > 0 => Duration::from_secs(value),
+            _ => {
+                error!(
+                    "Cannot use chip re-enumeration interval '{}' from command line",
+                    value
+                );
+                return;
+            }
+        };
+        backend_config.chip_reenumeration_interval.replace(interval);
+    }
+
+    if let Some(value) = matches.value_of("current-sensor") {
+        let address = match u8::from_str_radix(value.trim_start_matches("0x"), 16) {
+            Ok(address) => address,
+            Err(_) => {
+                error!(
+                    "Cannot use current sensor address '{}' from command line",
+                    value
+                );
+                return;
+            }
+        };
+        backend_config.current_sensor_address.replace(address);
+    }
+
+    if let Some(value) = matches.value_of("idle-fan-stop") {
+        match value.parse::<f32>() {
+            Ok(temp) if temp > 0.0 => {
+                backend_config.idle_fan_stop_temp.replace(temp);
+            }
+            _ => {
+                error!(
+                    "Cannot use idle fan stop temperature '{}' from command line",
+                    value
+                );
+                return;
+            }
+        }
+    }
+
+    if let Some(value) = matches.value_of("broken-grace-period") {
+        let grace_period = match value.parse::<u64>() {
+            Ok(value) => Duration::from_secs(value),
+            _ => {
+                error!(
+                    "Cannot use hash chain broken grace period '{}' from command line",
+                    value
+                );
+                return;
+            }
+        };
+        backend_config.broken_grace_period.replace(grace_period);
+    }
+
+    for (arg, pinning) in [
+        (
+            "work-cores",
+            &mut backend_config.core_affinity.work_generation,
+        ),
+        (
+            "solution-cores",
+            &mut backend_config.core_affinity.solution_handling,
+        ),
+    ]
+    .iter_mut()
+    {
+        if let Some(value) = matches.value_of(*arg) {
+            match affinity::Config::parse_cores(value) {
+                Ok(cores) => {
+                    pinning.replace(affinity::Pinning::system(cores));
+                }
+                Err(e) => {
+                    error!("Cannot use '{}' from command line: {}", value, e);
+                    return;
+                }
+            }
+        }
+    }
+
     if let Err(e) = backend_config.fill_info::<config::Backend>() {
         error!("Cannot get backend information: {}", e.to_string());
         return;
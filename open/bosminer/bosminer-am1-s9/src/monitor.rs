@@ -29,6 +29,8 @@ use crate::fan;
 use crate::halt;
 use crate::sensor::{self, Measurement};
 
+use bosminer::error::{ConditionKind, ErrorRegistry, Severity};
+
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -50,6 +52,18 @@ const RUN_UPDATE_TIMEOUT: Duration = Duration::from_secs(10);
 const TICK_LENGTH: Duration = Duration::from_secs(5);
 /// How long does it take until miner warm up? We won't let it tu turn fans off until then...
 const WARM_UP_PERIOD: Duration = Duration::from_secs(90);
+/// Default of how long can be hashchain broken before it is declared dead and the miner is
+/// shut down. Chain which is being restarted can recover from a transient failure within this
+/// period.
+pub const DEFAULT_BROKEN_GRACE_PERIOD: Duration = Duration::from_secs(15);
+/// Fans stopped during idle do not report they are running until they spin up again
+const FAN_SPIN_UP_PERIOD: Duration = Duration::from_secs(20);
+/// Readings outside of this range (exclusive) are considered to be garbage returned by faulty
+/// temperature sensor
+const MIN_SANE_TEMP: f32 = 0.0;
+const MAX_SANE_TEMP: f32 = 150.0;
+/// Conservative fan speed used when temperature sensors return nonsensical readings
+const SENSOR_FAULT_FAN_SPEED: fan::Speed = fan::Speed::FULL_SPEED;
 
 /// A message from hashchain
 ///
@@ -73,31 +87,49 @@ pub enum ChainTemperature {
     Unknown,
     /// Temperature unknown... in a bad way (miner caught fire, etc.)
     Failed,
+    /// Sensor returned a reading outside of sane bounds which cannot be trusted
+    Faulted(f32),
     /// Temperature was measured
     Ok(f32),
 }
 
 impl ChainTemperature {
+    pub fn is_faulted(&self) -> bool {
+        match self {
+            Self::Faulted(_) => true,
+            _ => false,
+        }
+    }
+
     /// Convert temperature to monitor interpretation.
     /// Specific to S9, because it fakes chip temperature.
     ///
-    /// TODO: Maybe figure out a strage for disabling remote sensors that are failing. Sometimes
-    /// remote sensors fail while mining and instead of signalizing error they return non-sensical
-    /// numbers.
+    /// Sometimes remote sensors fail while mining and instead of signalizing error they return
+    /// non-sensical numbers. Such readings are ignored in favour of the local sensor and when
+    /// none of the sensors is sane, the temperature is reported as `Faulted`.
     /// TODO: Is returning "Unknown" when sensor fails OK?
     fn from_s9_sensor(temp: sensor::Temperature) -> Self {
         match temp.remote {
             // remote is chip temperature
-            Measurement::Ok(t) => Self::Ok(t),
-            _ => {
+            Measurement::Ok(t) if Self::is_sane(t) => Self::Ok(t),
+            remote => {
                 // fake chip temperature from local (PCB) temperature
                 match temp.local {
-                    Measurement::Ok(t) => Self::Ok(t + 15.0),
-                    _ => Self::Unknown,
+                    Measurement::Ok(t) if Self::is_sane(t) => Self::Ok(t + 15.0),
+                    Measurement::Ok(t) => Self::Faulted(t),
+                    _ => match remote {
+                        Measurement::Ok(t) => Self::Faulted(t),
+                        _ => Self::Unknown,
+                    },
                 }
             }
         }
     }
+
+    #[inline]
+    fn is_sane(temp: f32) -> bool {
+        temp > MIN_SANE_TEMP && temp < MAX_SANE_TEMP
+    }
 }
 
 /// State of hashchain as seen from Monitor point of view
@@ -116,7 +148,7 @@ enum ChainState {
 }
 
 impl ChainState {
-    /// Go into invalid state (no way out)
+    /// Go into invalid state (the only way out is restart of hashchain)
     fn bad_transition(&mut self) {
         *self = ChainState::Broken("bad state transition");
     }
@@ -129,7 +161,8 @@ impl ChainState {
     fn transition(&mut self, now: Instant, message: Message) {
         match message {
             Message::On => match *self {
-                ChainState::Off => *self = ChainState::On(now),
+                // broken hashchain can recover by restarting
+                ChainState::Off | ChainState::Broken(_) => *self = ChainState::On(now),
                 _ => self.bad_transition(),
             },
             Message::Running(temperature) => match *self {
@@ -143,7 +176,9 @@ impl ChainState {
                 _ => self.bad_transition(),
             },
             Message::Off => match *self {
-                ChainState::On(_) | ChainState::Running { .. } => *self = ChainState::Off,
+                ChainState::On(_) | ChainState::Running { .. } | ChainState::Broken(_) => {
+                    *self = ChainState::Off
+                }
                 _ => self.bad_transition(),
             },
         }
@@ -197,6 +232,8 @@ impl ChainState {
 struct Chain {
     state: ChainState,
     hashboard_idx: usize,
+    /// Time when the chain has been first seen broken (reset after it recovers)
+    broken_since: Option<Instant>,
 }
 
 impl Chain {
@@ -204,6 +241,26 @@ impl Chain {
         Self {
             state: ChainState::Off,
             hashboard_idx,
+            broken_since: None,
+        }
+    }
+
+    /// Check whether the chain has been broken for longer than `grace_period`.
+    /// Return the reason of the failure when the chain is considered dead.
+    fn check_dead(&mut self, now: Instant, grace_period: Duration) -> Option<&'static str> {
+        match self.state {
+            ChainState::Broken(reason) => {
+                let broken_since = *self.broken_since.get_or_insert(now);
+                if now.duration_since(broken_since) >= grace_period {
+                    Some(reason)
+                } else {
+                    None
+                }
+            }
+            _ => {
+                self.broken_since = None;
+                None
+            }
         }
     }
 }
@@ -240,9 +297,14 @@ pub struct Config {
     /// If true, then do not let fans bellow predefined limit while miner is warming up.
     /// TODO: this is not particularly nice, it should be done per-chain and run-time.
     pub fans_on_while_warming_up: bool,
+    /// Stop fans while hashing is paused and all chains are cooler than this temperature.
+    /// It requires both temperature and fan control to be enabled.
+    pub idle_fan_stop_temp: Option<f32>,
+    /// How long can be hashchain broken before it is declared dead and the miner is shut down
+    pub broken_grace_period: Duration,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ControlDecisionExplained {
     pub decision: ControlDecision,
     pub reason: &'static str,
@@ -267,7 +329,15 @@ impl ControlDecision {
         fan_config: &FanControlConfig,
         temp_config: &TempControlConfig,
         temp: ChainTemperature,
+        sensor_faulted: bool,
     ) -> ControlDecisionExplained {
+        // temperature of the remaining chains may be fine while the faulted one overheats
+        if sensor_faulted || temp.is_faulted() {
+            return ControlDecisionExplained {
+                decision: Self::UseFixedSpeed(SENSOR_FAULT_FAN_SPEED),
+                reason: "temperature sensor faulted",
+            };
+        }
         if temp == ChainTemperature::Unknown {
             return ControlDecisionExplained {
                 decision: Self::UseFixedSpeed(fan::Speed::FULL_SPEED),
@@ -282,7 +352,9 @@ impl ControlDecision {
                 };
             }
             FanControlMode::TargetTemperature(target_temp) => match temp {
-                ChainTemperature::Failed | ChainTemperature::Unknown => {
+                ChainTemperature::Failed
+                | ChainTemperature::Unknown
+                | ChainTemperature::Faulted(_) => {
                     panic!("BUG: should've been caught earlier at the top of `decide()` function")
                 }
                 ChainTemperature::Ok(input_temp) => {
@@ -324,12 +396,48 @@ impl ControlDecision {
         }
     }
 
+    /// Decide whether fans can be stopped because the miner is idle. It is allowed only when
+    /// hashing is paused and every one of `chain_count` chains reports trustworthy temperature
+    /// below the idle threshold. Any unknown or faulted reading keeps the fans running.
+    fn decide_idle(
+        config: &Config,
+        hashing_paused: bool,
+        chain_count: usize,
+        temperature_accumulator: &TemperatureAccumulator,
+    ) -> Option<ControlDecisionExplained> {
+        let idle_temp = config.idle_fan_stop_temp?;
+        if !hashing_paused || config.temp_config.is_none() || config.fan_config.is_none() {
+            return None;
+        }
+        let chain_temperatures = &temperature_accumulator.chain_temperatures;
+        // broken chains are not accounted in the accumulator
+        if chain_count == 0 || chain_temperatures.len() != chain_count {
+            return None;
+        }
+        let all_cool = chain_temperatures.iter().all(|temp| match temp {
+            ChainTemperature::Ok(t) => *t < idle_temp,
+            _ => false,
+        });
+        if all_cool {
+            Some(ControlDecisionExplained {
+                decision: Self::UseFixedSpeed(fan::Speed::STOPPED),
+                reason: "hashing paused and chains cooled down",
+            })
+        } else {
+            None
+        }
+    }
+
     /// Decide what to do depending on temperature/fan feedback.
     /// This function has been factored out of the main control code to facilitate testing.
+    ///
+    /// * `temp` - aggregated temperature of chains with trustworthy readings
+    /// * `sensor_faulted` - sensor of some other chain returns readings which cannot be trusted
     fn decide(
         config: &Config,
         num_fans_running: usize,
         temp: ChainTemperature,
+        sensor_faulted: bool,
     ) -> ControlDecisionExplained {
         // This section is labeled `TEMP_DANGER` in the diagram
         // Check for dangerous temperature or dead sensors
@@ -349,13 +457,14 @@ impl ControlDecision {
                         };
                     }
                 }
-                ChainTemperature::Unknown => {}
+                // do not shut down the miner because of a spurious reading
+                ChainTemperature::Unknown | ChainTemperature::Faulted(_) => {}
             }
         }
         // Check the health of fans and decide their speed
         if let Some(fan_config) = config.fan_config.as_ref() {
             let decision_explained = if let Some(temp_config) = config.temp_config.as_ref() {
-                Self::decide_fan_control(fan_config, temp_config, temp)
+                Self::decide_fan_control(fan_config, temp_config, temp, sensor_faulted)
             } else {
                 Self::decide_fan_control_notemp(fan_config)
             };
@@ -407,13 +516,17 @@ impl TemperatureAccumulator {
 
     /// Function to calculate aggregated temperature.
     /// This one calculates maximum temperatures over all temperatures measured while
-    /// prefering failures to measurement.
+    /// prefering failures to measurement. Faulted sensor is reported only when there is no
+    /// trustworthy measurement (see `is_faulted`) so it cannot hide overheating of other chains.
     fn calc_result(&self) -> ChainTemperature {
         let mut temps = vec![];
+        let mut faulted = None;
         for &temp in self.chain_temperatures.iter() {
             match temp {
                 // Failure thrumps everything
                 ChainTemperature::Failed => return temp,
+                // Faulted sensor may hide overheating of its chain
+                ChainTemperature::Faulted(_) => faulted = Some(temp),
                 // Unknown temperature doesn't add any information
                 ChainTemperature::Unknown => (),
                 // Collect measurements
@@ -423,10 +536,19 @@ impl TemperatureAccumulator {
         // If we collected any temperatures, take maximum of them, otherwise return unknown
         if temps.len() > 0 {
             ChainTemperature::Ok(temps.drain(..).fold(0.0, |a, b| a.max(b)))
+        } else if let Some(faulted) = faulted {
+            faulted
         } else {
             ChainTemperature::Unknown
         }
     }
+
+    /// Some chain reports temperature which cannot be trusted
+    fn is_faulted(&self) -> bool {
+        self.chain_temperatures
+            .iter()
+            .any(ChainTemperature::is_faulted)
+    }
 }
 
 /// Status of `Monitor` for others to observe
@@ -455,6 +577,12 @@ pub struct MonitorInner {
     /// Flag whether miner is in failure state - temperature critical, hashboards not responding,
     /// fans gone missing...
     failure_state: bool,
+    /// Work generation of all hashchains has been disabled
+    hashing_paused: bool,
+    /// Fans have been stopped because the miner is idle
+    fans_idle_stopped: bool,
+    /// Time when the fans stopped during idle have been started again
+    fans_resumed: Option<Instant>,
 }
 
 /// Wrapper around `MonitorInner` with immutable fields
@@ -466,6 +594,9 @@ pub struct Monitor {
     /// Context to shutdown when miner enters critical state
     miner_shutdown: Arc<halt::Sender>,
 
+    /// Registry for reporting operational problems
+    error_registry: Option<Arc<ErrorRegistry>>,
+
     /// Inner context
     inner: Mutex<MonitorInner>,
 }
@@ -475,10 +606,12 @@ impl Monitor {
     ///
     /// * `miner_shutdown` - halt sender to shutdown the whole miner in case of a failure
     /// * `halt_receiver` - termination context in which to start the monitor
+    /// * `error_registry` - optional registry where all detected problems are reported
     pub async fn new_and_start(
         config: Config,
         miner_shutdown: Arc<halt::Sender>,
         halt_receiver: halt::Receiver,
+        error_registry: Option<Arc<ErrorRegistry>>,
     ) -> Arc<Self> {
         let (status_sender, status_receiver) = watch::channel(None);
 
@@ -489,10 +622,14 @@ impl Monitor {
             pid: fan::pid::TempControl::new(),
             failure_state: false,
             current_fan_speed: None,
+            hashing_paused: false,
+            fans_idle_stopped: false,
+            fans_resumed: None,
         };
 
         let monitor = Arc::new(Monitor {
             miner_shutdown,
+            error_registry,
             status_sender,
             status_receiver,
             inner: Mutex::new(inner),
@@ -523,6 +660,34 @@ impl Monitor {
         }
     }
 
+    /// Report error condition to the registry (if there's any)
+    fn report(&self, kind: ConditionKind, severity: Severity, source: String, message: &str) {
+        if let Some(error_registry) = self.error_registry.as_ref() {
+            error_registry.push(kind, severity, source, message);
+        }
+    }
+
+    /// Report the reason of control decision which signals a problem with cooling
+    fn report_decision(&self, decision_explained: &ControlDecisionExplained) {
+        let severity = match decision_explained.decision {
+            ControlDecision::Shutdown => Severity::Critical,
+            ControlDecision::UseFixedSpeed(_)
+                if decision_explained.reason == "temperature above HOT"
+                    || decision_explained.reason == "temperature sensor faulted" =>
+            {
+                Severity::Warning
+            }
+            _ => return,
+        };
+        let kind = match decision_explained.reason {
+            "not enough fans" => ConditionKind::FanFailure,
+            "temperature above HOT" | "temperature above DANGEROUS" => ConditionKind::Overheat,
+            "temperature sensor faulted" => ConditionKind::SensorFault,
+            reason => ConditionKind::Other(reason.into()),
+        };
+        self.report(kind, severity, "monitor".into(), decision_explained.reason);
+    }
+
     /// Shutdown miner
     async fn shutdown(&self, inner: &mut MonitorInner, reason: String) {
         error!("Monitor task declared miner shutdown: {}", reason);
@@ -537,6 +702,16 @@ impl Monitor {
         inner.current_fan_speed = Some(fan_speed);
     }
 
+    /// Spin up fans which have been stopped during idle
+    fn resume_idle_fans(&self, inner: &mut MonitorInner) {
+        if inner.fans_idle_stopped {
+            info!("Monitor: resuming fans stopped during idle");
+            inner.fans_idle_stopped = false;
+            inner.fans_resumed = Some(Instant::now());
+            self.set_fan_speed(inner, fan::Speed::FULL_SPEED);
+        }
+    }
+
     /// One tick of temperature/fan controller
     ///
     /// TODO: Run this tick every time new temperature is submitted to lower temp controller
@@ -546,12 +721,19 @@ impl Monitor {
         let mut inner = self.inner.lock().await;
         let mut temperature_accumulator = TemperatureAccumulator::new();
         let mut miner_warming_up = false;
+        let broken_grace_period = inner.config.broken_grace_period;
         for chain in inner.chains.iter() {
             let mut chain = chain.lock().await;
-            chain.state.tick(Instant::now());
-
-            if let ChainState::Broken(reason) = chain.state {
-                // TODO: here comes "Shutdown"
+            let now = Instant::now();
+            chain.state.tick(now);
+
+            if let Some(reason) = chain.check_dead(now, broken_grace_period) {
+                self.report(
+                    ConditionKind::ChainBroken,
+                    Severity::Critical,
+                    format!("chain {}", chain.hashboard_idx),
+                    reason,
+                );
                 let reason = format!("Chain {} is broken: {}", chain.hashboard_idx, reason);
                 // drop `chain` here to drop iterator which holds immutable reference
                 // to `monitor`
@@ -559,24 +741,68 @@ impl Monitor {
                 self.shutdown(&mut inner, reason).await;
                 return;
             }
+            if let ChainState::Broken(reason) = chain.state {
+                // give the chain a chance to recover and do not account its temperature
+                warn!(
+                    "chain {}: broken ({}), waiting for recovery",
+                    chain.hashboard_idx, reason
+                );
+                self.report(
+                    ConditionKind::ChainBroken,
+                    Severity::Error,
+                    format!("chain {}", chain.hashboard_idx),
+                    reason,
+                );
+                continue;
+            }
             info!("chain {}: {:?}", chain.hashboard_idx, chain.state);
             temperature_accumulator.add_chain_temp(chain.state.get_temperature());
             miner_warming_up |= chain.state.is_warming_up(Instant::now());
         }
         let input_temperature = temperature_accumulator.calc_result();
+        let sensor_faulted = temperature_accumulator.is_faulted();
 
         // Read fans
         let fan_feedback = inner.fan_control.read_feedback();
-        let num_fans_running = fan_feedback.num_fans_running();
+        let mut num_fans_running = fan_feedback.num_fans_running();
         info!(
             "Monitor: fan={:?} num_fans={} acc.temp.={:?}",
             fan_feedback, num_fans_running, input_temperature,
         );
+        if let (Some(fans_resumed), Some(fan_config)) =
+            (inner.fans_resumed, inner.config.fan_config.as_ref())
+        {
+            if fans_resumed.elapsed() < FAN_SPIN_UP_PERIOD {
+                // do not shut down the miner because of fans which have not spun up yet
+                num_fans_running = num_fans_running.max(fan_config.min_fans);
+            } else {
+                inner.fans_resumed = None;
+            }
+        }
 
         // all right, temperature has been aggregated, decide what to do
-        let decision_explained =
-            ControlDecision::decide(&inner.config, num_fans_running, input_temperature);
+        let decision_explained = match ControlDecision::decide_idle(
+            &inner.config,
+            inner.hashing_paused,
+            inner.chains.len(),
+            &temperature_accumulator,
+        ) {
+            Some(decision_explained) => {
+                inner.fans_idle_stopped = true;
+                decision_explained
+            }
+            None => {
+                self.resume_idle_fans(&mut inner);
+                ControlDecision::decide(
+                    &inner.config,
+                    num_fans_running,
+                    input_temperature,
+                    sensor_faulted,
+                )
+            }
+        };
         info!("Monitor: {:?}", decision_explained);
+        self.report_decision(&decision_explained);
         match decision_explained.decision {
             ControlDecision::Shutdown => {
                 self.shutdown(&mut inner, decision_explained.reason.into())
@@ -649,6 +875,16 @@ impl Monitor {
         tx
     }
 
+    /// Inform monitor whether work generation of all hashchains has been paused. The fans
+    /// stopped during idle are started immediately when hashing resumes.
+    pub async fn set_hashing_paused(&self, paused: bool) {
+        let mut inner = self.inner.lock().await;
+        inner.hashing_paused = paused;
+        if !paused {
+            self.resume_idle_fans(&mut inner);
+        }
+    }
+
     pub async fn with_configuration<F, R>(&self, f: F) -> R
     where
         F: FnOnce(&mut Config) -> R,
@@ -770,6 +1006,56 @@ mod test {
         );
     }
 
+    /// Test that brief disruption of hashchain within grace period is not fatal
+    #[test]
+    fn test_monitor_broken_grace_period() {
+        let temp = sensor::Temperature {
+            local: sensor::Measurement::Ok(10.0),
+            remote: sensor::Measurement::Ok(22.0),
+        };
+        let now = Instant::now();
+        let grace_period = Duration::from_secs(30);
+        let broken_time = now + RUN_UPDATE_TIMEOUT;
+        let within_grace = broken_time + grace_period / 2;
+        let after_grace = broken_time + grace_period;
+
+        let mut chain = Chain::new(0);
+        chain.state = ChainState::Running {
+            started: now,
+            last_heartbeat: now,
+            temperature: temp.clone(),
+        };
+        assert_eq!(chain.check_dead(now, grace_period), None);
+
+        // chain missed heartbeat and is broken but not dead yet
+        chain.state.tick(broken_time);
+        assert_variant!(chain.state, ChainState::Broken(_));
+        assert_eq!(chain.check_dead(broken_time, grace_period), None);
+        assert_eq!(chain.check_dead(within_grace, grace_period), None);
+
+        // chain has been restarted within grace period
+        chain.state.transition(within_grace, Message::Off);
+        chain.state.transition(within_grace, Message::On);
+        chain
+            .state
+            .transition(within_grace, Message::Running(temp.clone()));
+        assert_eq!(chain.check_dead(after_grace, grace_period), None);
+        assert_eq!(chain.broken_since, None);
+
+        // chain which does not recover is declared dead after grace period
+        chain.state.tick(after_grace + RUN_UPDATE_TIMEOUT);
+        assert_eq!(
+            chain.check_dead(after_grace + RUN_UPDATE_TIMEOUT, grace_period),
+            None
+        );
+        assert!(chain
+            .check_dead(
+                after_grace + RUN_UPDATE_TIMEOUT + grace_period,
+                grace_period
+            )
+            .is_some());
+    }
+
     /// Test "warm up" period
     #[test]
     fn test_monitor_warm_up() {
@@ -914,26 +1200,36 @@ mod test {
                 min_fans: 2,
             }),
             temp_config: None,
+            idle_fan_stop_temp: None,
+            broken_grace_period: DEFAULT_BROKEN_GRACE_PERIOD,
         };
         let all_off_config = Config {
             fans_on_while_warming_up: true,
             fan_config: None,
             temp_config: None,
+            idle_fan_stop_temp: None,
+            broken_grace_period: DEFAULT_BROKEN_GRACE_PERIOD,
         };
         let fans_on_config = Config {
             fans_on_while_warming_up: true,
             fan_config: Some(fan_config.clone()),
             temp_config: None,
+            idle_fan_stop_temp: None,
+            broken_grace_period: DEFAULT_BROKEN_GRACE_PERIOD,
         };
         let temp_on_config = Config {
             fans_on_while_warming_up: true,
             fan_config: None,
             temp_config: Some(temp_config.clone()),
+            idle_fan_stop_temp: None,
+            broken_grace_period: DEFAULT_BROKEN_GRACE_PERIOD,
         };
         let both_on_config = Config {
             fans_on_while_warming_up: true,
             fan_config: Some(fan_config.clone()),
             temp_config: Some(temp_config.clone()),
+            idle_fan_stop_temp: None,
+            broken_grace_period: DEFAULT_BROKEN_GRACE_PERIOD,
         };
         let both_on_pid_config = Config {
             fans_on_while_warming_up: true,
@@ -942,108 +1238,277 @@ mod test {
                 min_fans: 2,
             }),
             temp_config: Some(temp_config.clone()),
+            idle_fan_stop_temp: None,
+            broken_grace_period: DEFAULT_BROKEN_GRACE_PERIOD,
         };
 
         assert_variant!(
-            ControlDecision::decide(&all_off_config, 0, dang_temp.clone()).decision,
+            ControlDecision::decide(&all_off_config, 0, dang_temp.clone(), false).decision,
             ControlDecision::Nothing
         );
         assert_variant!(
-            ControlDecision::decide(&all_off_config, 0, ChainTemperature::Failed).decision,
+            ControlDecision::decide(&all_off_config, 0, ChainTemperature::Failed, false).decision,
             ControlDecision::Nothing
         );
 
         assert_eq!(
-            ControlDecision::decide(&fans_on_config, 2, dang_temp.clone()).decision,
+            ControlDecision::decide(&fans_on_config, 2, dang_temp.clone(), false).decision,
             ControlDecision::UseFixedSpeed(fan_speed)
         );
         assert_eq!(
-            ControlDecision::decide(&fans_on_config, 0, dang_temp.clone()).decision,
+            ControlDecision::decide(&fans_on_config, 0, dang_temp.clone(), false).decision,
             ControlDecision::Shutdown
         );
         assert_eq!(
-            ControlDecision::decide(&fans_on_config, 1, dang_temp.clone()).decision,
+            ControlDecision::decide(&fans_on_config, 1, dang_temp.clone(), false).decision,
             ControlDecision::Shutdown
         );
         assert_eq!(
-            ControlDecision::decide(&fans_on_config, 2, ChainTemperature::Failed).decision,
+            ControlDecision::decide(&fans_on_config, 2, ChainTemperature::Failed, false).decision,
             ControlDecision::UseFixedSpeed(fan_speed)
         );
 
         // fans set to 0 -> do not check if fans are running
         assert_eq!(
-            ControlDecision::decide(&fans_off_config, 0, dang_temp.clone()).decision,
+            ControlDecision::decide(&fans_off_config, 0, dang_temp.clone(), false).decision,
             ControlDecision::UseFixedSpeed(fans_off)
         );
 
         assert_eq!(
-            ControlDecision::decide(&temp_on_config, 0, ChainTemperature::Failed).decision,
+            ControlDecision::decide(&temp_on_config, 0, ChainTemperature::Failed, false).decision,
             ControlDecision::Shutdown
         );
         assert_variant!(
-            ControlDecision::decide(&temp_on_config, 0, ChainTemperature::Unknown).decision,
+            ControlDecision::decide(&temp_on_config, 0, ChainTemperature::Unknown, false).decision,
             ControlDecision::Nothing
         );
         assert_eq!(
-            ControlDecision::decide(&temp_on_config, 0, dang_temp).decision,
+            ControlDecision::decide(&temp_on_config, 0, dang_temp, false).decision,
             ControlDecision::Shutdown
         );
         assert_variant!(
-            ControlDecision::decide(&temp_on_config, 0, hot_temp).decision,
+            ControlDecision::decide(&temp_on_config, 0, hot_temp, false).decision,
             ControlDecision::Nothing
         );
 
         assert_eq!(
-            ControlDecision::decide(&both_on_config, 0, low_temp).decision,
+            ControlDecision::decide(&both_on_config, 0, low_temp, false).decision,
             ControlDecision::Shutdown
         );
         assert_eq!(
-            ControlDecision::decide(&both_on_config, 2, dang_temp).decision,
+            ControlDecision::decide(&both_on_config, 2, dang_temp, false).decision,
             ControlDecision::Shutdown
         );
         assert_eq!(
-            ControlDecision::decide(&both_on_config, 2, ChainTemperature::Failed).decision,
+            ControlDecision::decide(&both_on_config, 2, ChainTemperature::Failed, false).decision,
             ControlDecision::Shutdown
         );
         assert_eq!(
-            ControlDecision::decide(&both_on_config, 2, ChainTemperature::Unknown).decision,
+            ControlDecision::decide(&both_on_config, 2, ChainTemperature::Unknown, false).decision,
             ControlDecision::UseFixedSpeed(fan::Speed::FULL_SPEED)
         );
         assert_eq!(
-            ControlDecision::decide(&both_on_config, 2, hot_temp).decision,
+            ControlDecision::decide(&both_on_config, 2, hot_temp, false).decision,
             ControlDecision::UseFixedSpeed(fan_speed)
         );
         assert_eq!(
-            ControlDecision::decide(&both_on_config, 2, low_temp).decision,
+            ControlDecision::decide(&both_on_config, 2, low_temp, false).decision,
             ControlDecision::UseFixedSpeed(fan_speed)
         );
 
         assert_eq!(
-            ControlDecision::decide(&both_on_pid_config, 0, low_temp).decision,
+            ControlDecision::decide(&both_on_pid_config, 0, low_temp, false).decision,
             ControlDecision::Shutdown
         );
         assert_eq!(
-            ControlDecision::decide(&both_on_pid_config, 2, dang_temp).decision,
+            ControlDecision::decide(&both_on_pid_config, 2, dang_temp, false).decision,
             ControlDecision::Shutdown
         );
         assert_eq!(
-            ControlDecision::decide(&both_on_pid_config, 2, ChainTemperature::Failed).decision,
+            ControlDecision::decide(&both_on_pid_config, 2, ChainTemperature::Failed, false)
+                .decision,
             ControlDecision::Shutdown
         );
         assert_eq!(
-            ControlDecision::decide(&both_on_pid_config, 2, ChainTemperature::Unknown).decision,
+            ControlDecision::decide(&both_on_pid_config, 2, ChainTemperature::Unknown, false)
+                .decision,
             ControlDecision::UseFixedSpeed(fan::Speed::FULL_SPEED)
         );
         assert_eq!(
-            ControlDecision::decide(&both_on_pid_config, 2, hot_temp).decision,
+            ControlDecision::decide(&both_on_pid_config, 2, hot_temp, false).decision,
             ControlDecision::UseFixedSpeed(fan::Speed::FULL_SPEED)
         );
         assert_eq!(
-            ControlDecision::decide(&both_on_pid_config, 2, low_temp).decision,
+            ControlDecision::decide(&both_on_pid_config, 2, low_temp, false).decision,
             ControlDecision::UsePid {
                 target_temp: 75.0,
                 input_temp: 50.0
             }
         );
     }
+
+    /// Test that out-of-range sensor readings are not trusted
+    #[test]
+    fn test_monitor_faulted_sensor() {
+        // garbage from remote sensor falls back to local sensor
+        let temp = sensor::Temperature {
+            local: sensor::Measurement::Ok(10.0),
+            remote: sensor::Measurement::Ok(200.0),
+        };
+        assert_eq!(
+            ChainTemperature::from_s9_sensor(temp),
+            ChainTemperature::Ok(25.0)
+        );
+        // no sane reading at all
+        let temp = sensor::Temperature {
+            local: sensor::Measurement::Ok(0.0),
+            remote: sensor::Measurement::Ok(200.0),
+        };
+        assert_eq!(
+            ChainTemperature::from_s9_sensor(temp),
+            ChainTemperature::Faulted(0.0)
+        );
+        let temp = sensor::Temperature {
+            local: sensor::Measurement::InvalidReading,
+            remote: sensor::Measurement::Ok(0.0),
+        };
+        assert_eq!(
+            ChainTemperature::from_s9_sensor(temp),
+            ChainTemperature::Faulted(0.0)
+        );
+
+        // faulted sensor is reported only without trustworthy measurement and never instead
+        // of failure
+        assert_eq!(
+            test_acc(ChainTemperature::Ok(60.0), ChainTemperature::Faulted(200.0)),
+            ChainTemperature::Ok(60.0)
+        );
+        assert_eq!(
+            test_acc(ChainTemperature::Unknown, ChainTemperature::Faulted(200.0)),
+            ChainTemperature::Faulted(200.0)
+        );
+        assert_eq!(
+            test_acc(ChainTemperature::Faulted(200.0), ChainTemperature::Failed),
+            ChainTemperature::Failed
+        );
+
+        // faulted sensor neither shuts down the miner nor drives PID
+        let config = Config {
+            fans_on_while_warming_up: true,
+            fan_config: Some(FanControlConfig {
+                mode: FanControlMode::TargetTemperature(75.0),
+                min_fans: 2,
+            }),
+            temp_config: Some(TempControlConfig {
+                dangerous_temp: 100.0,
+                hot_temp: 80.0,
+            }),
+            idle_fan_stop_temp: None,
+            broken_grace_period: DEFAULT_BROKEN_GRACE_PERIOD,
+        };
+        for faulted in [0.0, 200.0].iter() {
+            let decision_explained =
+                ControlDecision::decide(&config, 2, ChainTemperature::Faulted(*faulted), false);
+            assert_eq!(
+                decision_explained.decision,
+                ControlDecision::UseFixedSpeed(SENSOR_FAULT_FAN_SPEED)
+            );
+            assert_eq!(decision_explained.reason, "temperature sensor faulted");
+        }
+        // but missing fans are still fatal
+        assert_eq!(
+            ControlDecision::decide(&config, 0, ChainTemperature::Faulted(200.0), false).decision,
+            ControlDecision::Shutdown
+        );
+
+        // faulted sensor of one chain does not hide overheating of another one
+        let mut accumulator = TemperatureAccumulator::new();
+        accumulator.add_chain_temp(ChainTemperature::Faulted(0.0));
+        accumulator.add_chain_temp(ChainTemperature::Ok(105.0));
+        assert!(accumulator.is_faulted());
+        let temp = accumulator.calc_result();
+        assert_eq!(temp, ChainTemperature::Ok(105.0));
+        assert_eq!(
+            ControlDecision::decide(&config, 2, temp, accumulator.is_faulted()).decision,
+            ControlDecision::Shutdown
+        );
+        // otherwise the fans are kept at safe speed instead of being driven by PID
+        let decision_explained =
+            ControlDecision::decide(&config, 2, ChainTemperature::Ok(60.0), true);
+        assert_eq!(
+            decision_explained.decision,
+            ControlDecision::UseFixedSpeed(SENSOR_FAULT_FAN_SPEED)
+        );
+        assert_eq!(decision_explained.reason, "temperature sensor faulted");
+    }
+
+    #[test]
+    fn test_decide_idle() {
+        let config = Config {
+            fans_on_while_warming_up: true,
+            fan_config: Some(FanControlConfig {
+                mode: FanControlMode::TargetTemperature(75.0),
+                min_fans: 2,
+            }),
+            temp_config: Some(TempControlConfig {
+                dangerous_temp: 100.0,
+                hot_temp: 80.0,
+            }),
+            idle_fan_stop_temp: Some(40.0),
+            broken_grace_period: DEFAULT_BROKEN_GRACE_PERIOD,
+        };
+        let accumulator = |temps: &[ChainTemperature]| TemperatureAccumulator {
+            chain_temperatures: temps.to_vec(),
+        };
+        let cool = accumulator(&[ChainTemperature::Ok(30.0), ChainTemperature::Ok(35.0)]);
+
+        // fans stop only when hashing is paused and all chains are cool
+        let decision_explained = ControlDecision::decide_idle(&config, true, 2, &cool)
+            .expect("BUG: fans not stopped during idle");
+        assert_eq!(
+            decision_explained.decision,
+            ControlDecision::UseFixedSpeed(fan::Speed::STOPPED)
+        );
+        // hashing resumes
+        assert_eq!(ControlDecision::decide_idle(&config, false, 2, &cool), None);
+        // temperature of a chain rises
+        let warm = accumulator(&[ChainTemperature::Ok(30.0), ChainTemperature::Ok(40.0)]);
+        assert_eq!(ControlDecision::decide_idle(&config, true, 2, &warm), None);
+        // temperature of some chain is not known or cannot be trusted
+        for temp in [
+            ChainTemperature::Unknown,
+            ChainTemperature::Failed,
+            ChainTemperature::Faulted(0.0),
+        ]
+        .iter()
+        {
+            let temps = accumulator(&[ChainTemperature::Ok(30.0), *temp]);
+            assert_eq!(ControlDecision::decide_idle(&config, true, 2, &temps), None);
+        }
+        // broken chain is missing in the accumulator
+        assert_eq!(ControlDecision::decide_idle(&config, true, 3, &cool), None);
+        assert_eq!(
+            ControlDecision::decide_idle(&config, true, 0, &accumulator(&[])),
+            None
+        );
+
+        // the option is disabled or temperature control is turned off
+        let disabled_config = Config {
+            idle_fan_stop_temp: None,
+            broken_grace_period: DEFAULT_BROKEN_GRACE_PERIOD,
+            ..config.clone()
+        };
+        assert_eq!(
+            ControlDecision::decide_idle(&disabled_config, true, 2, &cool),
+            None
+        );
+        let no_temp_config = Config {
+            temp_config: None,
+            ..config.clone()
+        };
+        assert_eq!(
+            ControlDecision::decide_idle(&no_temp_config, true, 2, &cool),
+            None
+        );
+    }
 }
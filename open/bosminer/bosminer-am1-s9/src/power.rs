@@ -33,6 +33,8 @@ use crate::async_i2c::AsyncI2cDev;
 use crate::error::{self, ErrorKind};
 use crate::halt;
 
+use bosminer::retry::RetryPolicy;
+
 use futures::lock::Mutex;
 use ii_async_compat::futures;
 use ii_async_compat::tokio;
@@ -146,6 +148,33 @@ impl Voltage {
     pub fn as_pic_value(&self) -> u8 {
         self.0
     }
+
+    /// Dynamic power of the hashboard is roughly proportional to the square of its voltage and
+    /// to the sum of chip frequencies. The coefficient is derived from consumption of a stock
+    /// hashboard drawing approx. 450 W with 63 chips at 650 MHz and 8.8 V.
+    const POWER_COEF: f64 = 450.0 / (8.8 * 8.8 * 63.0 * 650_000_000.0);
+
+    /// Rough estimate of power consumption (in watts) of a hashboard running at this voltage with
+    /// chips whose frequencies (in Hz) sum up to `frequency_sum`
+    pub fn estimate_power(&self, frequency_sum: u64) -> f64 {
+        let volts = self.as_volts() as f64;
+        Self::POWER_COEF * volts * volts * frequency_sum as f64
+    }
+
+    /// Power consumption (in watts) of a hashboard running at this voltage and drawing measured
+    /// `current` (in amperes)
+    pub fn measured_power(&self, current: f32) -> f64 {
+        self.as_volts() as f64 * current as f64
+    }
+
+    /// Power consumption (in watts) of a hashboard running at this voltage which is measured
+    /// from its `current` when it is known or estimated from `frequency_sum` of its chips
+    pub fn power(&self, current: Option<f32>, frequency_sum: u64) -> f64 {
+        match current {
+            Some(current) => self.measured_power(current),
+            None => self.estimate_power(frequency_sum),
+        }
+    }
 }
 
 impl std::fmt::Display for Voltage {
@@ -174,6 +203,10 @@ impl I2cBackend {
     /// Duration between successive tries
     const I2C_RETRY_DELAY: Duration = Duration::from_millis(100);
 
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy::new(Self::I2C_NUM_RETRIES, Self::I2C_RETRY_DELAY)
+    }
+
     /// Calculates I2C address of the controller based on hashboard index.
     fn get_i2c_address(hashboard_idx: usize) -> u8 {
         PIC_BASE_ADDRESS + hashboard_idx as u8 - 1
@@ -191,25 +224,15 @@ impl I2cBackend {
     /// Attempt to write a byte to power controller on I2C.
     /// If write fails then retry (at most `I2C_NUM_RETRIES`).
     async fn write_retry(&self, hashboard_idx: usize, data: u8) -> error::Result<()> {
-        let mut tries_left: usize = Self::I2C_NUM_RETRIES;
-        loop {
-            let ret = self
-                .inner
-                .write(Self::get_i2c_address(hashboard_idx), vec![data])
-                .await;
-            if ret.is_ok() {
-                return ret;
-            }
-            tries_left -= 1;
-            if tries_left == 0 {
-                return ret;
-            }
-            warn!(
-                "I2C transaction on hashboard {} failed, retrying...",
-                hashboard_idx
-            );
-            delay_for(Self::I2C_RETRY_DELAY).await;
-        }
+        Self::retry_policy()
+            .retry(
+                &format!("I2C transaction on hashboard {}", hashboard_idx),
+                || {
+                    self.inner
+                        .write(Self::get_i2c_address(hashboard_idx), vec![data])
+                },
+            )
+            .await
     }
 
     /// Perform a write command to power controller on I2C
@@ -789,6 +812,44 @@ mod test {
         assert!(difference.abs() <= epsilon);
     }
 
+    #[test]
+    fn test_estimate_power() {
+        let frequency_sum = 63 * 650_000_000;
+        let voltage = Voltage::from_volts(8.8).unwrap();
+        let power = voltage.estimate_power(frequency_sum);
+        // PIC value rounding slightly shifts the voltage
+        assert!((power - 450.0).abs() < 5.0, "unexpected power {}", power);
+        // power scales linearly with frequency
+        let half_power = voltage.estimate_power(frequency_sum / 2);
+        assert!((power - 2.0 * half_power).abs() < 1e-6);
+        assert_eq!(voltage.estimate_power(0), 0.0);
+    }
+
+    #[test]
+    fn test_measured_power() {
+        let voltage = Voltage::from_volts(8.8).unwrap();
+        let power = voltage.measured_power(50.0);
+        assert!((power - 440.0).abs() < 5.0, "unexpected power {}", power);
+        assert_eq!(voltage.measured_power(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_power_fallback() {
+        let voltage = Voltage::from_volts(8.8).unwrap();
+        let frequency_sum = 63 * 650_000_000;
+        // measured current takes precedence over the estimate
+        assert_eq!(
+            voltage.power(Some(50.0), frequency_sum),
+            voltage.measured_power(50.0)
+        );
+        // power is estimated on hashboards without current sensor
+        assert_eq!(
+            voltage.power(None, frequency_sum),
+            voltage.estimate_power(frequency_sum)
+        );
+        assert_eq!(voltage.power(None, 0), 0.0);
+    }
+
     #[test]
     fn test_pic_boundary() {
         // pic=255
@@ -23,6 +23,7 @@
 use crate::Solution;
 
 use bosminer::work;
+use std::collections::HashSet;
 use std::iter::Iterator;
 
 /// Mining registry item contains work and solutions
@@ -46,8 +47,15 @@ impl WorkRegistryItem {
         let mut status = InsertSolutionStatus {
             duplicate: false,
             mismatched_nonce: false,
+            framing_error: None,
             unique_solution: None,
         };
+        // implausible solution is neither stored nor passed further because it would refer
+        // to non-existent data of the work
+        if let Err(fault) = self.check_solution(&new_solution) {
+            status.framing_error = Some(fault);
+            return status;
+        }
         // scan the current solutions and detect a duplicate
         let matching_solution = self
             .solutions
@@ -68,6 +76,37 @@ impl WorkRegistryItem {
         status.unique_solution = Some(work::Solution::new(self.work.clone(), new_solution, None));
         status
     }
+
+    /// Check that raw solution words read from hardware are consistent with this work
+    pub fn check_solution(&self, solution: &Solution) -> Result<(), work::SolutionFault> {
+        let midstate_count = self.work.midstates.len();
+        if solution.midstate_idx >= midstate_count {
+            return Err(work::SolutionFault::MidstateIdx {
+                midstate_idx: solution.midstate_idx,
+                midstate_count,
+            });
+        }
+        Ok(())
+    }
+
+    /// Number of distinct midstates of this work for which at least one solution has been found
+    pub fn solved_midstates(&self) -> usize {
+        self.solutions
+            .iter()
+            .map(|solution| solution.midstate_idx)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Account number of solved midstates to all nodes the work has been passed through
+    fn account_midstate_yield(&self) {
+        let solved_midstates = self.solved_midstates();
+        for node in self.work.path.iter() {
+            node.mining_stats()
+                .midstate_yield()
+                .account_work(solved_midstates);
+        }
+    }
 }
 
 /// Helper container for the status after inserting the solution
@@ -77,6 +116,8 @@ pub struct InsertSolutionStatus {
     pub mismatched_nonce: bool,
     /// Solution is duplicate (given WorkRegistryItem) already has it
     pub duplicate: bool,
+    /// Solution is implausible for its work (e.g. garbage read from the FIFO)
+    pub framing_error: Option<work::SolutionFault>,
     /// actual solution (defined if the above 2 are false)
     /// TODO: rename `unique_solution` to solution
     pub unique_solution: Option<work::Solution>,
@@ -133,7 +174,11 @@ impl WorkRegistry {
 
         // retire stale work
         let retire_id = (work_id + self.registry_size / 2) % self.registry_size;
-        self.pending_work_list[retire_id] = None;
+        if let Some(retired_work) = self.pending_work_list[retire_id].take() {
+            if !retired_work.initial_work {
+                retired_work.account_midstate_yield();
+            }
+        }
 
         // put new work into registry
         self.pending_work_list[work_id] = Some(WorkRegistryItem {
@@ -147,9 +192,9 @@ impl WorkRegistry {
     }
 
     /// Look-up work id
-    pub fn find_work(&mut self, work_id: usize) -> &mut Option<WorkRegistryItem> {
-        assert!(work_id < self.registry_size);
-        &mut self.pending_work_list[work_id]
+    /// Work id out of the registry range (e.g. corrupted by hardware) is treated as unknown work.
+    pub fn find_work(&mut self, work_id: usize) -> Option<&mut WorkRegistryItem> {
+        self.pending_work_list.get_mut(work_id)?.as_mut()
     }
 }
 
@@ -158,6 +203,11 @@ mod test {
     use super::*;
     use crate::null_work;
 
+    use bosminer::node::{self, Stats as _};
+    use bosminer::test_utils;
+
+    use std::sync::Arc;
+
     /// Test that it's possible to store work
     #[test]
     fn test_store_work() {
@@ -238,4 +288,91 @@ mod test {
             false
         );
     }
+
+    /// Test that work id out of range doesn't match any work
+    #[test]
+    fn test_find_work_out_of_range() {
+        let mut registry = WorkRegistry::new(4);
+        registry.store_work(null_work::prepare(0), false);
+
+        assert!(registry.find_work(0).is_some());
+        assert!(registry.find_work(4).is_none());
+        assert!(registry.find_work(std::usize::MAX).is_none());
+    }
+
+    fn solution(nonce: u32, midstate_idx: usize) -> Solution {
+        Solution {
+            nonce,
+            midstate_idx,
+            solution_idx: 0,
+            target: Default::default(),
+        }
+    }
+
+    /// Test that distinct solved midstates are accounted to work path when the work retires
+    #[test]
+    fn test_midstate_yield() {
+        const REGISTRY_SIZE: usize = 4;
+        const MIDSTATE_COUNT: usize = 4;
+        let work_solver = Arc::new(test_utils::TestWorkSolver::new());
+        let mut registry = WorkRegistry::new(REGISTRY_SIZE);
+
+        // midstate indexes of solutions found for each work
+        let patterns: [&[usize]; 4] = [&[0, 1, 2, 3], &[1, 1, 1], &[], &[2, 0, 2]];
+        for midstate_indexes in patterns.iter() {
+            let mut work = null_work::prepare_opencore(true, MIDSTATE_COUNT);
+            work.path = vec![work_solver.clone() as node::DynInfo];
+            let work_id = registry.store_work(work, false);
+            let item = registry.find_work(work_id).expect("work not found");
+            for (nonce, midstate_idx) in midstate_indexes.iter().enumerate() {
+                item.insert_solution(solution(nonce as u32, *midstate_idx));
+            }
+        }
+        assert_eq!(
+            registry
+                .find_work(3)
+                .expect("work not found")
+                .solved_midstates(),
+            2
+        );
+
+        // retire all work by initial work which is not accounted
+        for _ in 0..REGISTRY_SIZE {
+            registry.store_work(null_work::prepare(0), true);
+        }
+        let midstate_yield = work_solver.mining_stats().midstate_yield();
+        assert_eq!(*midstate_yield.work.take_snapshot(), 4);
+        assert_eq!(
+            *midstate_yield.solved_midstates.take_snapshot(),
+            4 + 1 + 0 + 2
+        );
+        assert_eq!(midstate_yield.average(), Some(7.0 / 4.0));
+    }
+
+    /// Test that solution with out of range midstate index is discarded
+    #[test]
+    fn test_insert_solution_framing_error() {
+        const MIDSTATE_COUNT: usize = 2;
+        let mut registry = WorkRegistry::new(4);
+        let work_id = registry.store_work(null_work::prepare_opencore(true, MIDSTATE_COUNT), false);
+        let item = registry.find_work(work_id).expect("work not found");
+
+        let status = item.insert_solution(solution(0, MIDSTATE_COUNT));
+        assert_eq!(
+            status.framing_error,
+            Some(work::SolutionFault::MidstateIdx {
+                midstate_idx: MIDSTATE_COUNT,
+                midstate_count: MIDSTATE_COUNT,
+            })
+        );
+        assert!(status.unique_solution.is_none());
+        assert_eq!(item.solved_midstates(), 0);
+
+        // valid solution is not affected
+        let status = item.insert_solution(solution(0, MIDSTATE_COUNT - 1));
+        assert!(status.framing_error.is_none());
+        assert!(status.unique_solution.is_some());
+        assert!(!status.duplicate);
+        assert_eq!(item.solved_midstates(), 1);
+    }
 }
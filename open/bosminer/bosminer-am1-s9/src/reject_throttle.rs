@@ -0,0 +1,232 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Purpose of this module: lowering frequency of hashchains when the pool keeps rejecting shares
+//! which have passed all local checks. It usually happens with marginally stable chips which
+//! compute wrong hashes that are not caught by the verification of hardware errors.
+
+use crate::config;
+use crate::{Frequency, FrequencySettings};
+
+use std::time::Duration;
+
+/// Period of sampling the counts of accepted and rejected shares
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Number of consecutive samples with high ratio of invalid shares which triggers the reduction
+pub const DEFAULT_SUSTAINED_SAMPLES: usize = 3;
+/// Samples with less shares than this are not conclusive
+pub const DEFAULT_MIN_SHARES: u64 = 50;
+/// Decrease of chip frequency in single reduction
+pub const DEFAULT_FREQUENCY_STEP: Frequency = 6_250_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub sample_interval: Duration,
+    /// Highest tolerated fraction of invalid shares from all shares resolved within a sample
+    pub max_invalid_ratio: f64,
+    pub sustained_samples: usize,
+    pub min_shares: u64,
+    pub frequency_step: Frequency,
+}
+
+impl Config {
+    pub fn new(max_invalid_ratio: f64) -> Self {
+        Self {
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+            max_invalid_ratio,
+            sustained_samples: DEFAULT_SUSTAINED_SAMPLES,
+            min_shares: DEFAULT_MIN_SHARES,
+            frequency_step: DEFAULT_FREQUENCY_STEP,
+        }
+    }
+}
+
+/// Decides about frequency reduction from total counts of accepted shares and shares rejected
+/// due to invalid hash (shares rejected as stale, duplicate or unauthorized are not a sign of
+/// unstable chips)
+#[derive(Debug)]
+pub struct Throttle {
+    config: Config,
+    /// Totals from the previous sample
+    last_counts: (u64, u64),
+    /// Number of consecutive samples exceeding the tolerated ratio
+    high_samples: usize,
+}
+
+impl Throttle {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            last_counts: (0, 0),
+            high_samples: 0,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Account sample with total `accepted` and `invalid` shares and return true when the
+    /// frequency should be reduced
+    pub fn account(&mut self, accepted: u64, invalid: u64) -> bool {
+        let (last_accepted, last_invalid) = self.last_counts;
+        // the totals may decrease when the pools are replaced
+        let accepted_delta = accepted.saturating_sub(last_accepted);
+        let invalid_delta = invalid.saturating_sub(last_invalid);
+        self.last_counts = (accepted, invalid);
+
+        let total = accepted_delta + invalid_delta;
+        if total < self.config.min_shares {
+            return false;
+        }
+        if invalid_delta as f64 / total as f64 > self.config.max_invalid_ratio {
+            self.high_samples += 1;
+        } else {
+            self.high_samples = 0;
+        }
+        if self.high_samples < self.config.sustained_samples {
+            return false;
+        }
+        // give the chips some time to show the effect of the reduction
+        self.high_samples = 0;
+        true
+    }
+
+    /// Lower frequency of all chips by one step without going below the minimal supported
+    /// frequency. Nothing is returned when the frequency cannot be lowered anymore.
+    pub fn reduce(&self, frequency: &FrequencySettings) -> Option<FrequencySettings> {
+        let min_frequency = (config::FREQUENCY_MHZ_MIN * 1_000_000.0) as Frequency;
+        let reduced = FrequencySettings {
+            chip: frequency
+                .chip
+                .iter()
+                .map(|&frequency| {
+                    frequency
+                        .saturating_sub(self.config.frequency_step)
+                        .max(min_frequency.min(frequency))
+                })
+                .collect(),
+        };
+        if reduced == *frequency {
+            None
+        } else {
+            Some(reduced)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bosminer::client::rejection::RejectReason;
+    use bosminer::stats;
+
+    #[test]
+    fn test_throttle() {
+        let mut throttle = Throttle::new(Config::new(0.05));
+        let reject_reasons = stats::RejectReasons::default();
+        let mut accepted = 0;
+
+        // stale shares do not indicate unstable chips
+        for _ in 0..DEFAULT_SUSTAINED_SAMPLES {
+            accepted += 100;
+            for _ in 0..20 {
+                reject_reasons.account(&RejectReason::Stale);
+                reject_reasons.account(&RejectReason::JobNotFound);
+            }
+            assert!(!throttle.account(accepted, reject_reasons.invalid_hash()));
+        }
+
+        // sustained high rate of invalid shares
+        for sample in 1..=DEFAULT_SUSTAINED_SAMPLES {
+            accepted += 90;
+            for _ in 0..10 {
+                reject_reasons.account(&RejectReason::LowDifficulty);
+            }
+            let reduce = throttle.account(accepted, reject_reasons.invalid_hash());
+            assert_eq!(reduce, sample == DEFAULT_SUSTAINED_SAMPLES);
+        }
+
+        let frequency = FrequencySettings::from_frequency(650_000_000);
+        let reduced = throttle
+            .reduce(&frequency)
+            .expect("BUG: frequency not reduced");
+        assert!(reduced
+            .chip
+            .iter()
+            .all(|&chip_frequency| chip_frequency == 650_000_000 - DEFAULT_FREQUENCY_STEP));
+
+        // the reduction resets the observation
+        accepted += 90;
+        for _ in 0..10 {
+            reject_reasons.account(&RejectReason::LowDifficulty);
+        }
+        assert!(!throttle.account(accepted, reject_reasons.invalid_hash()));
+    }
+
+    #[test]
+    fn test_throttle_pool_rejects() {
+        let mut throttle = Throttle::new(Config::new(0.05));
+        let reject_reasons = stats::RejectReasons::default();
+        let mut accepted = 0;
+
+        // pool refusing shares of unauthorized user or duplicates doesn't mean wrong hashes
+        for _ in 0..2 * DEFAULT_SUSTAINED_SAMPLES {
+            accepted += 50;
+            for _ in 0..50 {
+                reject_reasons.account(&RejectReason::Other("Unauthorized worker".to_string()));
+                reject_reasons.account(&RejectReason::Duplicate);
+            }
+            assert!(!throttle.account(accepted, reject_reasons.invalid_hash()));
+        }
+        assert_eq!(reject_reasons.invalid_hash(), 0);
+    }
+
+    #[test]
+    fn test_throttle_inconclusive() {
+        let mut throttle = Throttle::new(Config::new(0.05));
+
+        // too few shares within each sample
+        for sample in 1..10 {
+            assert!(!throttle.account(sample * 10, sample * 10));
+        }
+        // isolated sample with high ratio doesn't matter
+        let (accepted, invalid) = (1000, 100);
+        assert!(!throttle.account(accepted, invalid));
+        assert!(!throttle.account(accepted + 100, invalid));
+        assert!(!throttle.account(accepted + 200, invalid + 10));
+    }
+
+    #[test]
+    fn test_reduce_floor() {
+        let throttle = Throttle::new(Config::new(0.05));
+        let min_frequency = (config::FREQUENCY_MHZ_MIN * 1_000_000.0) as Frequency;
+
+        let frequency = FrequencySettings::from_frequency(min_frequency + 1_000_000);
+        let reduced = throttle
+            .reduce(&frequency)
+            .expect("BUG: frequency not reduced");
+        assert_eq!(reduced.max(), min_frequency);
+        assert!(throttle.reduce(&reduced).is_none());
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Purpose of this module: backoff between restart attempts of a failing hashchain which
+//! eventually disables the hashchain permanently instead of restarting it in an endless loop
+
+use bosminer::retry::RetryPolicy;
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Delay before the first restart of a failed hashchain
+pub const DEFAULT_RESTART_DELAY: Duration = Duration::from_secs(10);
+/// Upper bound of the delay between restarts
+pub const MAX_RESTART_DELAY: Duration = Duration::from_secs(10 * 60);
+/// Number of failures within `DEFAULT_FAILURE_WINDOW` after which the hashchain is disabled
+pub const DEFAULT_MAX_FAILURES: usize = 11;
+/// Failures older than this are forgotten
+pub const DEFAULT_FAILURE_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Delays between restarts. The number of attempts is the number of failures within
+    /// `failure_window` which disables the hashchain.
+    pub policy: RetryPolicy,
+    pub failure_window: Duration,
+}
+
+impl Config {
+    pub fn new(max_failures: usize) -> Self {
+        Self {
+            policy: RetryPolicy::new(max_failures, DEFAULT_RESTART_DELAY)
+                .with_backoff(2.0, MAX_RESTART_DELAY),
+            failure_window: DEFAULT_FAILURE_WINDOW,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FAILURES)
+    }
+}
+
+/// What to do after a failed start of hashchain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    /// Wait before the next restart
+    Retry(Duration),
+    /// Give up and keep the hashchain disabled
+    Disable,
+}
+
+/// Keeps track of recent failures of single hashchain
+#[derive(Debug)]
+pub struct Backoff {
+    config: Config,
+    failures: VecDeque<Instant>,
+    disabled: bool,
+}
+
+impl Backoff {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            failures: VecDeque::new(),
+            disabled: false,
+        }
+    }
+
+    /// Account failed start of hashchain at `now` and decide whether it should be restarted
+    pub fn failure(&mut self, now: Instant) -> Decision {
+        while let Some(&oldest) = self.failures.front() {
+            if now.duration_since(oldest) < self.config.failure_window {
+                break;
+            }
+            self.failures.pop_front();
+        }
+        self.failures.push_back(now);
+
+        let attempt = self.failures.len() - 1;
+        if self.config.policy.has_next_attempt(attempt) {
+            Decision::Retry(self.config.policy.delay(attempt))
+        } else {
+            self.disabled = true;
+            Decision::Disable
+        }
+    }
+
+    /// Number of failures within the window
+    pub fn failures(&self) -> usize {
+        self.failures.len()
+    }
+
+    /// The hashchain has failed too many times and it should not be restarted anymore
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    /// Give the hashchain another chance (e.g. on explicit request of the user)
+    pub fn reset(&mut self) {
+        self.failures.clear();
+        self.disabled = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new(Config::new(5));
+        let mut now = Instant::now();
+
+        let mut delays = vec![];
+        for _ in 0..4 {
+            match backoff.failure(now) {
+                Decision::Retry(delay) => {
+                    delays.push(delay);
+                    now += delay;
+                }
+                Decision::Disable => panic!("hashchain disabled too early"),
+            }
+        }
+        // the delay grows with each failure
+        assert_eq!(
+            delays,
+            vec![
+                DEFAULT_RESTART_DELAY,
+                DEFAULT_RESTART_DELAY * 2,
+                DEFAULT_RESTART_DELAY * 4,
+                DEFAULT_RESTART_DELAY * 8,
+            ]
+        );
+        assert!(!backoff.is_disabled());
+
+        // too many failures within the window
+        assert_eq!(backoff.failure(now), Decision::Disable);
+        assert!(backoff.is_disabled());
+
+        backoff.reset();
+        assert_eq!(backoff.failures(), 0);
+        assert_eq!(backoff.failure(now), Decision::Retry(DEFAULT_RESTART_DELAY));
+    }
+
+    #[test]
+    fn test_backoff_window() {
+        let mut backoff = Backoff::new(Config::new(3));
+        let mut now = Instant::now();
+
+        // sparse failures never disable the hashchain
+        for _ in 0..10 {
+            assert_eq!(backoff.failure(now), Decision::Retry(DEFAULT_RESTART_DELAY));
+            now += DEFAULT_FAILURE_WINDOW;
+        }
+        assert_eq!(backoff.failures(), 1);
+        assert!(!backoff.is_disabled());
+    }
+}
@@ -21,7 +21,8 @@
 // contact us at opensource@braiins.com.
 
 //! This module contains interface for reading from sensor (`Sensor`) and what
-//! constitutes a sensor reading (`Temperature`, `Measurement`).
+//! constitutes a sensor reading (`Temperature`, `Measurement`). Hashboards equipped with current
+//! monitor are read through `CurrentSensor`.
 //!
 //! HOW TO EXTEND THIS IN THE FUTURE
 //!
@@ -36,6 +37,7 @@
 //! * Maybe provide a generic temperature readout structure that has just the `local` and `remote`
 //!   portions (and make a conversion function when needed).
 
+mod current;
 mod tmp42x;
 mod tmp451;
 
@@ -57,6 +59,16 @@ pub trait Sensor: Sync + Send {
     async fn read_temperature(&mut self) -> error::Result<Temperature>;
 }
 
+/// Sensor of the current drawn by the hashboard
+#[async_trait]
+pub trait CurrentSensor: Sync + Send {
+    /// Initialize the sensor (should be called at least once before first call to `read_current`
+    async fn init(&mut self) -> error::Result<()>;
+
+    /// Read current in amperes
+    async fn read_current(&mut self) -> error::Result<f32>;
+}
+
 /// Result of measuring temperature with remote sensor
 #[derive(Debug, PartialEq, Clone)]
 pub enum Measurement {
@@ -164,6 +176,37 @@ pub async fn probe_i2c_sensors<T: 'static + i2c::AsyncBus + Clone>(
     Ok(None)
 }
 
+/// Probe I2C `address` for known current sensor. Only some hashboard revisions are equipped
+/// with the sensor and it cannot be detected otherwise so the address has to be configured.
+pub async fn probe_i2c_current_sensor<T: 'static + i2c::AsyncBus + Clone>(
+    i2c_bus: T,
+    address: i2c::Address,
+) -> error::Result<Option<Box<dyn CurrentSensor>>> {
+    // Interesting SMBus registers
+    const REG_PRODUCT_ID: u8 = 0xfd;
+    const REG_MANUFACTURER_ID: u8 = 0xfe;
+
+    let mut i2c_device: Box<dyn i2c::AsyncDevice> = Box::new(i2c::Device::new(i2c_bus, address));
+
+    // Read manufacturer and product ID
+    let manufacturer_id = i2c_device.read(REG_MANUFACTURER_ID).await?;
+    let product_id = i2c_device.read(REG_PRODUCT_ID).await?;
+
+    info!(
+        "{:?} manufacturer_id={:#x} product_id={:#x}",
+        i2c_device.get_address(),
+        manufacturer_id,
+        product_id
+    );
+
+    let sensor = match (manufacturer_id, product_id) {
+        (0x5d, 0x57) | (0x5d, 0x58) => Some(current::PAC1710::new(i2c_device)),
+        _ => None,
+    };
+
+    Ok(sensor)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -194,4 +237,30 @@ mod test {
         assert_eq!(test_probe_address(0x9c, 0x37, 0x21).await, false);
         assert_eq!(test_probe_address(0x84, 0x55, 0x21).await, false);
     }
+
+    async fn test_probe_current_sensor(addr: u8, man_id: u8, product_id: u8) -> bool {
+        let bus = test_utils::FakeI2cBus::new(
+            i2c::Address::new(addr),
+            &[
+                test_utils::InitReg(0xfd, product_id),
+                test_utils::InitReg(0xfe, man_id),
+            ],
+            Some(0),
+            Some(0xff),
+        );
+        let bus = i2c::SharedBus::new(bus);
+        let result = probe_i2c_current_sensor(bus, i2c::Address::new(0x30))
+            .await
+            .unwrap();
+        result.is_some()
+    }
+
+    #[tokio::test]
+    async fn test_probe_i2c_current_sensor() {
+        assert_eq!(test_probe_current_sensor(0x30, 0x5d, 0x57).await, true);
+        assert_eq!(test_probe_current_sensor(0x30, 0x5d, 0x58).await, true);
+        assert_eq!(test_probe_current_sensor(0x30, 0x55, 0x21).await, false);
+        // Temperature sensor is not mistaken for current sensor
+        assert_eq!(test_probe_current_sensor(0x98, 0x55, 0x21).await, false);
+    }
 }
@@ -0,0 +1,133 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Common Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Common Public License for more details.
+//
+// You should have received a copy of the GNU Common Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Driver implementation of current sensor for PAC1710 and similar current monitors measuring
+//! voltage across a shunt resistor in the hashboard power path
+
+use crate::error;
+use crate::i2c;
+use crate::sensor;
+
+use async_trait::async_trait;
+use std::boxed::Box;
+
+const REG_CONFIG: u8 = 0x00;
+/// Sensing (not the bus voltage) measurement is enabled when this bit is cleared
+const CONFIG_SENSE_DISABLE: u8 = 0x02;
+const REG_SENSE_HIGH: u8 = 0x0d;
+const REG_SENSE_LOW: u8 = 0x0e;
+
+/// Full scale range of the sense voltage in default configuration (in volts)
+const SENSE_FULL_SCALE: f32 = 0.08;
+/// Sense voltage reading is 11-bit value with sign
+const SENSE_DENOMINATOR: f32 = 2047.0;
+/// Resistance of the shunt resistor (in ohms)
+const SHUNT_RESISTANCE: f32 = 0.0005;
+
+/// Build a current (in amperes) from internal representation of the sense voltage. The reading
+/// is left aligned in both registers with the lowest four bits of `low` being unused.
+fn decode_current(high: u8, low: u8) -> f32 {
+    let raw = ((high as i16) << 8 | low as i16) >> 4;
+    raw as f32 * SENSE_FULL_SCALE / (SENSE_DENOMINATOR * SHUNT_RESISTANCE)
+}
+
+/// PAC1710 driver (single channel, only the first channel is read on dual channel variants)
+pub struct PAC1710 {
+    i2c_dev: Box<dyn i2c::AsyncDevice>,
+}
+
+impl PAC1710 {
+    pub fn new(i2c_dev: Box<dyn i2c::AsyncDevice>) -> Box<dyn sensor::CurrentSensor> {
+        Box::new(Self { i2c_dev }) as Box<dyn sensor::CurrentSensor>
+    }
+}
+
+#[async_trait]
+impl sensor::CurrentSensor for PAC1710 {
+    async fn init(&mut self) -> error::Result<()> {
+        let config = self.i2c_dev.read(REG_CONFIG).await?;
+        self.i2c_dev
+            .write(REG_CONFIG, config & !CONFIG_SENSE_DISABLE)
+            .await
+    }
+
+    async fn read_current(&mut self) -> error::Result<f32> {
+        let high = self.i2c_dev.read(REG_SENSE_HIGH).await?;
+        let low = self.i2c_dev.read(REG_SENSE_LOW).await?;
+        Ok(decode_current(high, low))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use i2c::test_utils::InitReg;
+    use i2c::AsyncDevice as _;
+    use ii_async_compat::tokio;
+    use sensor::CurrentSensor as _;
+
+    fn assert_current(current: f32, expected: f32) {
+        assert!(
+            (current - expected).abs() < 0.1,
+            "current {} differs from {}",
+            current,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_decode_current() {
+        assert_current(decode_current(0x00, 0x00), 0.0);
+        // Full scale
+        assert_current(decode_current(0x7f, 0xf0), 160.0);
+        // Unused bits are ignored
+        assert_current(decode_current(0x7f, 0xff), 160.0);
+        // Reading of negative current
+        assert_current(decode_current(0x80, 0x10), -160.0);
+        assert_current(decode_current(0x19, 0x90), 32.0);
+    }
+
+    #[tokio::test]
+    async fn test_read_current() {
+        let addr = i2c::Address::new(0x30);
+        let bus = i2c::test_utils::FakeI2cBus::new(
+            addr,
+            &[
+                InitReg(REG_CONFIG, CONFIG_SENSE_DISABLE),
+                InitReg(REG_SENSE_HIGH, 0x19),
+                InitReg(REG_SENSE_LOW, 0x90),
+            ],
+            None,
+            None,
+        );
+        let bus = i2c::SharedBus::new(bus);
+        let mut dev = i2c::Device::new(bus, addr);
+
+        let mut sensor = PAC1710::new(Box::new(dev.clone()));
+        sensor.init().await.expect("BUG: init failed");
+        assert_eq!(
+            dev.read(REG_CONFIG).await.unwrap() & CONFIG_SENSE_DISABLE,
+            0
+        );
+        assert_current(sensor.read_current().await.expect("BUG: read failed"), 32.0);
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Ordered shutdown of the miner built on top of `halt` contexts. Every `Phase` has its own halt
+//! context and the phases are halted one after another, so e.g. fans keep cooling the hashboards
+//! while solutions are being drained and the hardware is stopped.
+
+use ii_logging::macros::*;
+
+use crate::halt;
+
+use bosminer::client;
+use bosminer::work;
+
+use ii_async_compat::prelude::*;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Shutdown phases in the order they are executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Stop accepting new jobs from pools
+    StopJobs,
+    /// Let already found solutions to be submitted
+    DrainSubmissions,
+    /// Stop all hash chains
+    StopHardware,
+    /// Stop the fans (or leave them at full speed in case of failure)
+    StopFans,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [
+        Phase::StopJobs,
+        Phase::DrainSubmissions,
+        Phase::StopHardware,
+        Phase::StopFans,
+    ];
+
+    /// Default time limit for completion of the phase
+    pub fn default_timeout(self) -> Duration {
+        match self {
+            Phase::StopJobs => Duration::from_secs(5),
+            Phase::DrainSubmissions => Duration::from_secs(10),
+            Phase::StopHardware => crate::HALT_TIMEOUT,
+            Phase::StopFans => Duration::from_secs(5),
+        }
+    }
+
+    /// Time limit of the whole shutdown with default phase timeouts
+    pub fn default_total_timeout() -> Duration {
+        Self::ALL.iter().map(|phase| phase.default_timeout()).sum()
+    }
+}
+
+/// Halt context of one shutdown phase
+struct Stage {
+    phase: Phase,
+    timeout: Duration,
+    sender: Arc<halt::Sender>,
+    receiver: halt::Receiver,
+}
+
+/// Coordinator halting all registered subsystems phase by phase
+pub struct Coordinator {
+    stages: Vec<Stage>,
+}
+
+impl Coordinator {
+    /// Create halt context for each phase with time limit provided by `timeout`
+    pub fn new<F>(timeout: F) -> Arc<Self>
+    where
+        F: Fn(Phase) -> Duration,
+    {
+        let stages = Phase::ALL
+            .iter()
+            .map(|&phase| {
+                let timeout = timeout(phase);
+                let (sender, receiver) = halt::make_pair(timeout);
+                Stage {
+                    phase,
+                    timeout,
+                    sender,
+                    receiver,
+                }
+            })
+            .collect();
+
+        Arc::new(Self { stages })
+    }
+
+    fn stage(&self, phase: Phase) -> &Stage {
+        self.stages
+            .iter()
+            .find(|stage| stage.phase == phase)
+            .expect("BUG: missing shutdown phase")
+    }
+
+    /// Halt context in which subsystems of given phase should be registered
+    pub fn receiver(&self, phase: Phase) -> halt::Receiver {
+        self.stage(phase).receiver.clone()
+    }
+
+    pub fn sender(&self, phase: Phase) -> Arc<halt::Sender> {
+        self.stage(phase).sender.clone()
+    }
+
+    /// Halt all phases in order. A phase which doesn't finish within its time limit is reported
+    /// and the shutdown proceeds with the next phase.
+    async fn shutdown_internal(self: Arc<Self>) {
+        for stage in self.stages.iter() {
+            info!("Shutdown: entering phase {:?}", stage.phase);
+            match stage
+                .sender
+                .clone()
+                .try_send_halt()
+                .timeout(stage.timeout)
+                .await
+            {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => warn!("Shutdown: phase {:?} failed: {}", stage.phase, e),
+                Err(_) => warn!(
+                    "Shutdown: phase {:?} timed out after {:?}",
+                    stage.phase, stage.timeout
+                ),
+            }
+        }
+    }
+
+    /// Run the shutdown in a separate task so that it is completed even when the caller is
+    /// halted by one of the phases
+    pub async fn shutdown(self: Arc<Self>) {
+        tokio::spawn(self.shutdown_internal())
+            .await
+            .expect("BUG: shutdown task has panicked");
+    }
+}
+
+/// Register the mining frontend in the first phases of the shutdown: generation of work for all
+/// `solver_switches` is stopped first and then all clients of `client_manager` are disabled as
+/// soon as their pending submissions are done
+pub async fn register_frontend(
+    coordinator: &Coordinator,
+    solver_switches: Vec<Arc<work::SolverSwitch>>,
+    client_manager: client::Manager,
+) {
+    coordinator
+        .receiver(Phase::StopJobs)
+        .register_client("work generation".into())
+        .await
+        .spawn_halt_handler(async move {
+            for solver_switch in solver_switches {
+                solver_switch.set_enabled(false);
+            }
+        });
+    coordinator
+        .receiver(Phase::DrainSubmissions)
+        .register_client("clients".into())
+        .await
+        .spawn_halt_handler(async move { client_manager.drain().await });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bosminer_config::PoolConfig;
+
+    use futures::lock::Mutex;
+    use tokio::time::delay_for;
+
+    /// Register mock subsystem which records the phase in which it was halted
+    async fn register_mock(
+        coordinator: &Coordinator,
+        phase: Phase,
+        log: Arc<Mutex<Vec<Phase>>>,
+        cleanup: Duration,
+    ) {
+        coordinator
+            .receiver(phase)
+            .register_client(format!("{:?}", phase))
+            .await
+            .spawn_halt_handler(async move {
+                delay_for(cleanup).await;
+                log.lock().await.push(phase);
+            });
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_order() {
+        let coordinator = Coordinator::new(|_| Duration::from_secs(1));
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        // register subsystems in reverse order to make sure registration order doesn't matter
+        for &phase in Phase::ALL.iter().rev() {
+            register_mock(&coordinator, phase, log.clone(), Duration::from_millis(5)).await;
+        }
+        coordinator.shutdown().await;
+
+        assert_eq!(*log.lock().await, Phase::ALL.to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_timeout() {
+        let coordinator = Coordinator::new(|phase| match phase {
+            Phase::DrainSubmissions => Duration::from_millis(10),
+            _ => Duration::from_secs(1),
+        });
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        for &phase in Phase::ALL.iter() {
+            let cleanup = match phase {
+                // drain never finishes in time
+                Phase::DrainSubmissions => Duration::from_secs(100),
+                _ => Duration::from_millis(5),
+            };
+            register_mock(&coordinator, phase, log.clone(), cleanup).await;
+        }
+        coordinator
+            .shutdown()
+            .timeout(Duration::from_secs(10))
+            .await
+            .expect("shutdown hangs on phase timeout");
+
+        assert_eq!(
+            *log.lock().await,
+            vec![Phase::StopJobs, Phase::StopHardware, Phase::StopFans]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_frontend_order() {
+        let coordinator = Coordinator::new(|_| Duration::from_secs(1));
+        let solver_switch = Arc::new(work::SolverSwitch::new());
+        let client_manager = client::Manager::new(1);
+        client_manager
+            .replace_pools(
+                vec![PoolConfig {
+                    enabled: None,
+                    url: "drain://pool".to_string(),
+                    user: "user".to_string(),
+                    password: None,
+                }],
+                None,
+                true,
+            )
+            .await
+            .expect("BUG: cannot replace pools");
+        let client = client_manager
+            .get_default_group()
+            .await
+            .expect("BUG: missing default group")
+            .get_clients()
+            .await
+            .pop()
+            .expect("BUG: missing client");
+        register_frontend(
+            &coordinator,
+            vec![solver_switch.clone()],
+            client_manager.clone(),
+        )
+        .await;
+
+        // Record state of the frontend as seen by the subsequent phases
+        let log = Arc::new(Mutex::new(Vec::new()));
+        coordinator
+            .receiver(Phase::DrainSubmissions)
+            .register_client("drain".into())
+            .await
+            .spawn_halt_handler({
+                let log = log.clone();
+                let solver_switch = solver_switch.clone();
+                async move {
+                    log.lock()
+                        .await
+                        .push(("work generation", solver_switch.is_enabled()));
+                }
+            });
+        coordinator
+            .receiver(Phase::StopHardware)
+            .register_client("hardware".into())
+            .await
+            .spawn_halt_handler({
+                let log = log.clone();
+                async move { log.lock().await.push(("client", client.is_enabled())) }
+            });
+        coordinator.shutdown().await;
+
+        // Work generation is stopped before the submissions are drained and the clients are
+        // disabled before the hardware is stopped
+        assert_eq!(
+            *log.lock().await,
+            vec![("work generation", false), ("client", false)]
+        );
+    }
+}
@@ -98,6 +98,50 @@ fn test_calc_baud_div_over_baud_rate_bm1387() {
     );
 }
 
+/// Test that midstate count of running chains can be changed to any count supported by the IP core
+#[tokio::test]
+async fn test_supported_midstate_counts() {
+    use bosminer::backend::{self, HierarchyBuilder};
+    use bosminer::hal::BackendConfig as _;
+
+    let mut backend_config = config::Backend::default();
+    assert_eq!(backend_config.supported_midstate_counts(), vec![1, 2, 4]);
+
+    let backend_registry = Arc::new(backend::Registry::new());
+    let core = bosminer::hub::Core::new(backend_config.midstate_count(), &backend_registry, None);
+    let midstate_count = Arc::new(work::MidstateCount::new());
+    backend_registry
+        .add_midstate_count(midstate_count.clone())
+        .await;
+    core.set_supported_midstate_counts(backend_config.supported_midstate_counts());
+    core.set_midstate_count(0, 2)
+        .await
+        .expect("BUG: cannot set midstate count");
+    assert_eq!(midstate_count.get(), Some(2));
+    core.set_midstate_count(0, 1)
+        .await
+        .expect("BUG: cannot set midstate count");
+    assert_eq!(midstate_count.get(), Some(1));
+
+    // distribution of jobs among work items is accepted as single midstate work is supported
+    core.set_work_distribution(work::Distribution::WorkItems)
+        .await
+        .expect("BUG: cannot distribute work by work items");
+    assert_eq!(core.work_distribution(), work::Distribution::WorkItems);
+    assert_eq!(midstate_count.get(), Some(1));
+    core.set_work_distribution(work::Distribution::Midstates)
+        .await
+        .expect("BUG: cannot distribute work by midstates");
+    assert_eq!(midstate_count.get(), Some(backend_config.midstate_count()));
+
+    // without AsicBoost only single midstate work fits the work registry
+    backend_config.hash_chain_global = Some(config::HashChainGlobal {
+        asic_boost: Some(false),
+        ..Default::default()
+    });
+    assert_eq!(backend_config.supported_midstate_counts(), vec![1]);
+}
+
 /// Test work_time computation
 #[test]
 fn test_work_time_computation() {
@@ -107,3 +151,64 @@ fn test_work_time_computation() {
         36296
     );
 }
+
+/// Test scaling of frequency for hash rate cap and ramping to it
+#[test]
+fn test_frequency_hashrate_cap() {
+    let nominal = FrequencySettings::from_frequency(650_000_000);
+    let nominal_hashrate =
+        |frequency: &FrequencySettings| frequency.total() as f64 * bm1387::NUM_CORES_ON_CHIP as f64;
+
+    // cap to half of nominal hash rate
+    let capped = nominal.scaled(0.5);
+    assert_eq!(capped.avg(), 325_000_000);
+    let ratio = nominal_hashrate(&capped) / nominal_hashrate(&nominal);
+    assert!((ratio - 0.5).abs() < 1e-3);
+
+    // converge smoothly without exceeding the ramp step
+    let mut frequency = nominal.clone();
+    let mut steps = 0;
+    while frequency != capped {
+        let next = frequency.ramp_toward(&capped, FREQUENCY_RAMP_STEP);
+        assert!(frequency.max() - next.max() <= FREQUENCY_RAMP_STEP);
+        frequency = next;
+        steps += 1;
+    }
+    assert_eq!(steps, 13);
+
+    // releasing the cap restores nominal frequency
+    let released = nominal.scaled(work::HashrateCap::RELEASED);
+    while frequency != released {
+        frequency = frequency.ramp_toward(&released, FREQUENCY_RAMP_STEP);
+    }
+    assert!(frequency == nominal);
+
+    // frequency never drops below the minimal supported one
+    assert_eq!(nominal.scaled(0.01).min(), 200_000_000);
+}
+
+/// Test that ramping of capped frequency follows retuning of the nominal frequency
+#[test]
+fn test_hashrate_cap_retune() {
+    let ramp = |mut frequency: FrequencySettings, nominal: &FrequencySettings, fraction| {
+        while let Some(next) = frequency.ramp_toward_cap(nominal, fraction, FREQUENCY_RAMP_STEP) {
+            frequency = next;
+        }
+        frequency
+    };
+    let nominal = FrequencySettings::from_frequency(650_000_000);
+    let frequency = ramp(nominal.clone(), &nominal, 0.5);
+    assert_eq!(frequency.avg(), 325_000_000);
+
+    // the chain is retuned (e.g. by reject throttle) while the hash rate is capped
+    let retuned = FrequencySettings::from_frequency(600_000_000);
+    let frequency = ramp(frequency, &retuned, 0.5);
+    assert_eq!(frequency.avg(), 300_000_000);
+
+    // releasing the cap restores the retuned frequency instead of the original one
+    let frequency = ramp(frequency, &retuned, work::HashrateCap::RELEASED);
+    assert!(frequency == retuned);
+    assert!(frequency
+        .ramp_toward_cap(&retuned, work::HashrateCap::RELEASED, FREQUENCY_RAMP_STEP)
+        .is_none());
+}
@@ -0,0 +1,223 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Common Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Common Public License for more details.
+//
+// You should have received a copy of the GNU Common Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Tuning profiles store frequency of each chip and voltage of a particular hashboard so that
+//! the result of tuning can be reapplied on the next start without tuning the board again.
+
+use crate::bm1387;
+use crate::error::{self, ErrorKind};
+use crate::power;
+use crate::FrequencySettings;
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Index of the hashboard the profile has been tuned for
+    pub hashboard_idx: usize,
+    /// Voltage of the whole hashboard (it cannot be set per chip)
+    pub voltage: f32,
+    /// Frequency of each chip in Hz (in the order of chip addresses)
+    pub chip_frequency: Vec<usize>,
+}
+
+impl Profile {
+    pub fn new(
+        hashboard_idx: usize,
+        frequency: &FrequencySettings,
+        voltage: power::Voltage,
+    ) -> Self {
+        Self {
+            hashboard_idx,
+            voltage: voltage.as_volts(),
+            chip_frequency: frequency.chip.clone(),
+        }
+    }
+
+    /// Location of the profile for `hashboard_idx` in directory `dir`
+    pub fn path(dir: &Path, hashboard_idx: usize) -> PathBuf {
+        dir.join(format!("hashboard_{}.toml", hashboard_idx))
+    }
+
+    /// Load profile from `path` and return `None` when no profile has been saved yet
+    pub fn load(path: &Path) -> error::Result<Option<Self>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => Err(e)?,
+        };
+        let profile = toml::from_str(&content).map_err(|e| {
+            ErrorKind::General(format!(
+                "cannot parse tuning profile '{}': {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Ok(Some(profile))
+    }
+
+    /// Save profile to `path` (the parent directory is created when it does not exist)
+    pub fn save(&self, path: &Path) -> error::Result<()> {
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| ErrorKind::General(format!("cannot serialize tuning profile: {}", e)))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Check that the profile has been tuned for hashboard `hashboard_idx` with `chip_count`
+    /// chips and that all its settings are supported by the hardware
+    pub fn validate(&self, hashboard_idx: usize, chip_count: usize) -> error::Result<()> {
+        if self.hashboard_idx != hashboard_idx {
+            Err(ErrorKind::Hashboard(
+                hashboard_idx,
+                format!("tuning profile belongs to hashboard {}", self.hashboard_idx),
+            ))?
+        }
+        if self.chip_frequency.len() != chip_count {
+            Err(ErrorKind::Hashboard(
+                hashboard_idx,
+                format!(
+                    "tuning profile has {} chips but {} chips have been detected",
+                    self.chip_frequency.len(),
+                    chip_count
+                ),
+            ))?
+        }
+        for &frequency in self.chip_frequency.iter() {
+            bm1387::PllFrequency::lookup_freq(frequency)?;
+        }
+        self.voltage()?;
+        Ok(())
+    }
+
+    pub fn frequency(&self) -> FrequencySettings {
+        FrequencySettings {
+            chip: self.chip_frequency.clone(),
+        }
+    }
+
+    pub fn voltage(&self) -> error::Result<power::Voltage> {
+        power::Voltage::from_volts(self.voltage)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CHIP_COUNT: usize = 63;
+
+    fn build_profile(hashboard_idx: usize) -> Profile {
+        let mut frequency = FrequencySettings::from_frequency(650_000_000);
+        frequency.set_chip_count(CHIP_COUNT);
+        // slower silicon at the end of the chain
+        for chip_frequency in frequency.chip[CHIP_COUNT - 3..].iter_mut() {
+            *chip_frequency = 600_000_000;
+        }
+        let voltage = power::Voltage::from_volts(8.8).expect("BUG: invalid voltage");
+        Profile::new(hashboard_idx, &frequency, voltage)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("bosminer-tuning-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_profile_save_and_load() {
+        let dir = temp_dir("save");
+        let path = Profile::path(&dir, 6);
+        assert_eq!(
+            Profile::load(&path).expect("BUG: cannot load profile"),
+            None
+        );
+
+        let profile = build_profile(6);
+        profile.save(&path).expect("BUG: cannot save profile");
+        let loaded = Profile::load(&path)
+            .expect("BUG: cannot load profile")
+            .expect("BUG: missing profile");
+        assert_eq!(loaded, profile);
+        loaded
+            .validate(6, CHIP_COUNT)
+            .expect("BUG: invalid profile");
+
+        // per-chip settings are reapplied exactly as they were tuned
+        let frequency = loaded.frequency();
+        assert_eq!(frequency.chip.len(), CHIP_COUNT);
+        assert_eq!(frequency.chip[0], 650_000_000);
+        assert_eq!(frequency.chip[CHIP_COUNT - 1], 600_000_000);
+        assert_eq!(frequency.min(), 600_000_000);
+        assert_eq!(frequency.max(), 650_000_000);
+        assert_eq!(
+            loaded
+                .voltage()
+                .expect("BUG: invalid voltage")
+                .as_pic_value(),
+            power::Voltage::from_volts(8.8)
+                .expect("BUG: invalid voltage")
+                .as_pic_value()
+        );
+
+        fs::remove_dir_all(&dir).expect("BUG: cannot remove profile directory");
+    }
+
+    #[test]
+    fn test_profile_validation() {
+        let profile = build_profile(6);
+        assert!(profile.validate(6, CHIP_COUNT).is_ok());
+        // chips missing on the board cannot be tuned by the profile
+        assert!(profile.validate(6, CHIP_COUNT - 1).is_err());
+        assert!(profile.validate(6, CHIP_COUNT + 1).is_err());
+        // profile tuned for another board
+        assert!(profile.validate(7, CHIP_COUNT).is_err());
+
+        let mut profile = build_profile(6);
+        profile.chip_frequency[0] = 1;
+        assert!(profile.validate(6, CHIP_COUNT).is_err());
+
+        let mut profile = build_profile(6);
+        profile.voltage = 100.0;
+        assert!(profile.validate(6, CHIP_COUNT).is_err());
+    }
+
+    #[test]
+    fn test_profile_malformed() {
+        let dir = temp_dir("malformed");
+        let path = Profile::path(&dir, 6);
+        fs::create_dir_all(&dir).expect("BUG: cannot create profile directory");
+        fs::write(&path, "voltage = \"high\"").expect("BUG: cannot write profile");
+        assert!(Profile::load(&path).is_err());
+
+        fs::remove_dir_all(&dir).expect("BUG: cannot remove profile directory");
+    }
+}
@@ -32,7 +32,7 @@ use std::fmt;
 use failure::ResultExt;
 
 pub const URL_JAVA_SCRIPT_REGEX: &'static str =
-    "(?:drain|(?:stratum2?\\+tcp(?:\\+insecure)?)):\\/\\/[\\w\\.-]+(?::\\d+)?(?:\\/[\\dA-HJ-NP-Za-km-z]+)?";
+    "(?:drain|solo\\+http|(?:stratum2?\\+tcp(?:\\+insecure)?)):\\/\\/[\\w\\.-]+(?::\\d+)?(?:\\/[\\dA-HJ-NP-Za-km-z]+)?";
 
 #[derive(Clone, Debug)]
 pub enum Protocol {
@@ -40,6 +40,8 @@ pub enum Protocol {
     StratumV1,
     StratumV2(v2::noise::auth::EncodedEd25519PublicKey),
     StratumV2Insecure,
+    /// Full node JSON-RPC interface (`getblocktemplate`) used for solo mining
+    Solo,
 }
 
 impl Protocol {
@@ -47,11 +49,13 @@ impl Protocol {
     pub const SCHEME_STRATUM_V1: &'static str = "stratum+tcp";
     pub const SCHEME_STRATUM_V2: &'static str = "stratum2+tcp";
     pub const SCHEME_STRATUM_V2_INSECURE: &'static str = "stratum2+tcp+insecure";
+    pub const SCHEME_SOLO: &'static str = "solo+http";
 
     pub const DEFAULT_PORT_DRAIN: u16 = 0;
     pub const DEFAULT_PORT_STRATUM_V1: u16 = 3333;
     pub const DEFAULT_PORT_STRATUM_V2: u16 = 3336;
     pub const DEFAULT_PORT_STRATUM_V2_INSECURE: u16 = 3336;
+    pub const DEFAULT_PORT_SOLO: u16 = 8332;
 
     pub fn default_port(&self) -> u16 {
         match self {
@@ -59,6 +63,7 @@ impl Protocol {
             Self::StratumV1 => Self::DEFAULT_PORT_STRATUM_V1,
             Self::StratumV2(_) => Self::DEFAULT_PORT_STRATUM_V2,
             Self::StratumV2Insecure => Self::DEFAULT_PORT_STRATUM_V2_INSECURE,
+            Self::Solo => Self::DEFAULT_PORT_SOLO,
         }
     }
 
@@ -89,6 +94,7 @@ impl Protocol {
                 Self::StratumV2(upstream_authority_public_key)
             }
             Self::SCHEME_STRATUM_V2_INSECURE => Self::StratumV2Insecure,
+            Self::SCHEME_SOLO => Self::Solo,
             _ => Err(error::ErrorKind::Client(format!(
                 "unknown protocol '{}'",
                 scheme
@@ -102,6 +108,7 @@ impl Protocol {
             Self::StratumV1 => Self::SCHEME_STRATUM_V1,
             Self::StratumV2(_) => Self::SCHEME_STRATUM_V2,
             Self::StratumV2Insecure => Self::SCHEME_STRATUM_V2_INSECURE,
+            Self::Solo => Self::SCHEME_SOLO,
         }
     }
 }
@@ -115,6 +122,7 @@ impl fmt::Display for Protocol {
                 write!(f, "Stratum V2 (authority key: {})", public_key)
             }
             Protocol::StratumV2Insecure => write!(f, "Stratum V2 Insecure"),
+            Protocol::Solo => write!(f, "Solo (full node)"),
         }
     }
 }
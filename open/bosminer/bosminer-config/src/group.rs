@@ -56,6 +56,11 @@ pub struct Descriptor {
     pub name: String,
     #[serde(skip)]
     pub private: bool,
+    /// The group mines on behalf of developers (dev fee) and its mining time and shares are
+    /// accounted separately from the user's pools
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub donation: bool,
     #[serde(flatten)]
     #[serde(skip_serializing_if = "Option::is_none")]
     strategy: Option<LoadBalanceStrategy>,
@@ -73,6 +78,7 @@ impl Descriptor {
         Self {
             name,
             private,
+            donation: false,
             strategy: strategy.into(),
         }
     }
@@ -102,6 +108,7 @@ impl Default for Descriptor {
         Self {
             name: Self::DEFAULT_NAME.to_string(),
             private: false,
+            donation: false,
             strategy: None,
         }
     }
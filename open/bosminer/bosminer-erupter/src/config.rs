@@ -20,6 +20,8 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+use crate::icarus;
+
 use bosminer::client;
 use bosminer::hal;
 
@@ -43,6 +45,8 @@ pub const JOB_TIMEOUT: Duration = Duration::from_secs(30);
 pub struct Backend {
     client_manager: Option<client::Manager>,
     client_descriptor: Option<ClientDescriptor>,
+    /// Maximal time of searching one work (the whole nonce space is searched when not set)
+    pub max_search_time: Option<Duration>,
 }
 
 impl Backend {
@@ -50,9 +54,18 @@ impl Backend {
         Self {
             client_manager: None,
             client_descriptor: Some(client_descriptor),
+            max_search_time: None,
         }
     }
 
+    /// Limit search of one work to `ratio` multiple of expected share interval of the chip. Work
+    /// is then abandoned in favour of fresher one even if its nonce space is not exhausted.
+    pub fn with_max_search_ratio(mut self, ratio: f64) -> Self {
+        assert!(ratio > 0.0, "BUG: non-positive maximal search ratio");
+        self.max_search_time = Some(icarus::expected_share_interval().mul_f64(ratio));
+        self
+    }
+
     pub async fn init_client(self) {
         if let Some(client_descriptor) = self.client_descriptor {
             let group = self
@@ -74,7 +87,34 @@ impl hal::BackendConfig for Backend {
         DEFAULT_MIDSTATE_COUNT
     }
 
+    /// Icarus protocol carries exactly one midstate so work cannot be split any further
+    fn supported_midstate_counts(&self) -> Vec<usize> {
+        vec![DEFAULT_MIDSTATE_COUNT]
+    }
+
     fn set_client_manager(&mut self, client_manager: client::Manager) {
         self.client_manager.replace(client_manager);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bosminer::hal::BackendConfig as _;
+
+    #[test]
+    fn test_max_search_ratio() {
+        assert_eq!(Backend::default().max_search_time, None);
+
+        let backend = Backend::default().with_max_search_ratio(0.5);
+        assert_eq!(
+            backend.max_search_time,
+            Some(icarus::expected_share_interval() / 2)
+        );
+    }
+
+    #[test]
+    fn test_supported_midstate_counts() {
+        assert_eq!(Backend::default().supported_midstate_counts(), vec![1]);
+    }
+}
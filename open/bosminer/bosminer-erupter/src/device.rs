@@ -70,6 +70,35 @@ const READ_REDUCE_MS: f64 = WAIT_TIMEOUT_MS as f64 * 1.5;
 const MAX_READ_TIME: Duration =
     Duration::from_millis((icarus::FULL_NONCE_TIME_MS - READ_REDUCE_MS) as u64);
 
+/// Measures time spent by searching one work to allow abandoning it before the whole nonce space
+/// is exhausted
+#[derive(Debug, Clone, Copy)]
+struct SearchTimer {
+    start: time::Instant,
+    max_search_time: Duration,
+}
+
+impl SearchTimer {
+    /// The limit cannot exceed the time needed for searching the whole nonce space
+    fn new(max_search_time: Duration) -> Self {
+        Self {
+            start: time::Instant::now(),
+            max_search_time: max_search_time.min(MAX_READ_TIME),
+        }
+    }
+
+    fn restart(&mut self, now: time::Instant) {
+        self.start = now;
+    }
+
+    /// Remaining time for searching current work or `None` when the work should be abandoned
+    fn remaining(&self, now: time::Instant) -> Option<Duration> {
+        self.max_search_time
+            .checked_sub(now.duration_since(self.start))
+            .filter(|remaining| *remaining > Duration::from_secs(0))
+    }
+}
+
 pub struct BlockErupter<'a> {
     context: &'a libusb::Context,
     device: libusb::DeviceHandle<'a>,
@@ -195,7 +224,7 @@ impl<'a> BlockErupter<'a> {
 pub struct BlockErupterSolver<'a> {
     device: BlockErupter<'a>,
     work_generator: work::Generator,
-    work_start: time::Instant,
+    search_timer: SearchTimer,
     curr_work: Option<work::Assignment>,
     next_solution: Option<work::Solution>,
     solution_idx: usize,
@@ -207,7 +236,7 @@ impl<'a> BlockErupterSolver<'a> {
         Self {
             device,
             work_generator,
-            work_start: time::Instant::now(),
+            search_timer: SearchTimer::new(MAX_READ_TIME),
             curr_work: None,
             next_solution: None,
             solution_idx: 0,
@@ -215,6 +244,12 @@ impl<'a> BlockErupterSolver<'a> {
         }
     }
 
+    /// Abandon each work after `max_search_time` even if its nonce space is not exhausted yet
+    pub fn with_max_search_time(mut self, max_search_time: Duration) -> Self {
+        self.search_timer = SearchTimer::new(max_search_time);
+        self
+    }
+
     /// Consume the iterator and return the reason of stream termination
     pub fn get_stop_reason(self) -> error::Result<()> {
         // the object is consumed so replacing with `Ok` is fine
@@ -228,15 +263,17 @@ impl<'a> BlockErupterSolver<'a> {
             work.ntime,
             work.bits(),
         );
-        self.work_start = time::Instant::now();
+        self.search_timer.restart(time::Instant::now());
         self.device.send_work(work_payload).unwrap_or_else(|e| {
             *self.stop_reason.get_mut() = Err(e);
         });
     }
 
     fn wait_for_nonce(&self) -> Option<(u32, time::Instant)> {
-        let duration = time::Instant::now().duration_since(self.work_start);
-        let timeout_rem = MAX_READ_TIME.checked_sub(duration).unwrap_or(WAIT_TIMEOUT);
+        let timeout_rem = self
+            .search_timer
+            .remaining(time::Instant::now())
+            .unwrap_or(WAIT_TIMEOUT);
 
         self.device
             .wait_for_nonce(timeout_rem)
@@ -310,6 +347,7 @@ impl<'a> Iterator for BlockErupterSolver<'a> {
                     break;
                 }
             }
+            // the current work is either exhausted or it has been searched for too long
 
             prev_work = self.curr_work.take().map(|work| (work, self.solution_idx));
             match block_on(self.work_generator.generate()) {
@@ -318,6 +356,7 @@ impl<'a> Iterator for BlockErupterSolver<'a> {
                 // send new work and wait for result in the next iteration when no error occurs
                 Some(work) => {
                     self.send_work(&work);
+                    self.work_generator.confirm_delivery(&work);
                     self.curr_work = Some(work);
                     self.solution_idx = 0;
                 }
@@ -399,6 +438,28 @@ pub mod test {
         BlockErupterGuard::new(device, context_guard.unwrap())
     }
 
+    #[test]
+    fn test_search_timer() {
+        let max_search_time = Duration::from_millis(500);
+        let mut timer = SearchTimer::new(max_search_time);
+        let start = time::Instant::now();
+        timer.restart(start);
+
+        assert_eq!(timer.remaining(start), Some(max_search_time));
+        assert_eq!(
+            timer.remaining(start + Duration::from_millis(200)),
+            Some(Duration::from_millis(300))
+        );
+        // the work is abandoned after configured duration
+        assert_eq!(timer.remaining(start + max_search_time), None);
+        assert_eq!(timer.remaining(start + Duration::from_secs(1)), None);
+
+        // the limit is bounded by the time of searching the whole nonce space
+        let mut timer = SearchTimer::new(Duration::from_secs(3600));
+        timer.restart(start);
+        assert_eq!(timer.remaining(start), Some(MAX_READ_TIME));
+    }
+
     #[test]
     fn test_block_erupter_init() {
         let _device = get_block_erupter();
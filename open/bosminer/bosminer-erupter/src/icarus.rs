@@ -26,6 +26,7 @@ use packed_struct::prelude::*;
 use packed_struct_codegen::PackedStruct;
 
 use std::mem::size_of;
+use std::time::Duration;
 
 use lazy_static::lazy_static;
 
@@ -39,6 +40,15 @@ pub const HASH_TIME_S: f64 = 0.0000000029761;
 
 /// Time needed for iteration of the whole search space in milliseconds
 pub const FULL_NONCE_TIME_MS: f64 = (HASH_TIME_S * (0xffffffffu64 + 1u64) as f64) * 1000f64;
+/// Mean time between two solutions reported by the chip which finds all nonces at difficulty 1
+pub fn expected_share_interval() -> Duration {
+    bosminer::stats::expected_share_interval(
+        ii_bitcoin::HashesUnit::Hashes((1.0 / HASH_TIME_S) as u128),
+        &ASIC_TARGET,
+    )
+    .expect("BUG: undefined share interval of Icarus chip")
+}
+
 /// Size of work structure required by the chip
 pub const WORK_PAYLOAD_SIZE: usize = 64;
 
@@ -108,4 +118,11 @@ pub mod test {
             assert_eq!(block.icarus_bytes[..], work.into_bytes()[..]);
         }
     }
+
+    #[test]
+    fn test_expected_share_interval() {
+        // one share is expected per search of the whole nonce space
+        let interval_ms = expected_share_interval().as_secs_f64() * 1000.0;
+        assert!((interval_ms - FULL_NONCE_TIME_MS).abs() < 1.0);
+    }
 }
@@ -91,6 +91,7 @@ pub struct Backend {
     work_solver_stats: stats::BasicWorkSolver,
     work_generator: Mutex<Option<work::Generator>>,
     solution_sender: work::SolutionSender,
+    max_search_time: Option<Duration>,
 }
 
 impl Backend {
@@ -99,9 +100,16 @@ impl Backend {
             work_solver_stats: Default::default(),
             work_generator: Mutex::new(Some(work_generator)),
             solution_sender,
+            max_search_time: None,
         }
     }
 
+    /// Abandon work which has been searched for longer than `max_search_time`
+    pub fn with_max_search_time(mut self, max_search_time: Option<Duration>) -> Self {
+        self.max_search_time = max_search_time;
+        self
+    }
+
     fn run(&self) -> bosminer::error::Result<()> {
         info!("Block Erupter: finding device in USB...");
         let usb_context =
@@ -120,6 +128,10 @@ impl Backend {
                 .take()
                 .expect("missing work generator"),
         );
+        if let Some(max_search_time) = self.max_search_time {
+            info!("Block Erupter: maximal search time {:?}", max_search_time);
+            solver = solver.with_max_search_time(max_search_time);
+        }
 
         // iterate until there exists any work or the error occurs
         for solution in &mut solver {
@@ -149,6 +161,10 @@ impl node::WorkSolver for Backend {
             (1.0 / icarus::HASH_TIME_S) / 1000.0,
         ))
     }
+
+    async fn get_estimated_power(&self) -> Option<f64> {
+        None
+    }
 }
 
 impl fmt::Display for Backend {
@@ -165,9 +181,10 @@ impl hal::Backend for Backend {
     const DEFAULT_HASHRATE_INTERVAL: Duration = config::DEFAULT_HASHRATE_INTERVAL;
     const JOB_TIMEOUT: Duration = config::JOB_TIMEOUT;
 
-    fn create(_backend_config: &mut config::Backend) -> hal::WorkNode<Self> {
-        node::WorkSolverType::WorkSolver(Box::new(|work_generator, solution_sender| {
-            Self::new(work_generator, solution_sender)
+    fn create(backend_config: &mut config::Backend) -> hal::WorkNode<Self> {
+        let max_search_time = backend_config.max_search_time;
+        node::WorkSolverType::WorkSolver(Box::new(move |work_generator, solution_sender| {
+            Self::new(work_generator, solution_sender).with_max_search_time(max_search_time)
         }))
     }
 
@@ -190,6 +207,7 @@ impl hal::Backend for Backend {
 
         Ok(hal::FrontendConfig {
             cgminer_custom_commands: None,
+            temperature_source: None,
         })
     }
 }
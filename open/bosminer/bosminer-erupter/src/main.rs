@@ -50,6 +50,14 @@ async fn main() {
                 .help("Specify user and worker name")
                 .required(true)
                 .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("max-search-ratio")
+                .long("max-search-ratio")
+                .value_name("RATIO")
+                .help("Abandon work after RATIO multiple of expected share interval")
+                .required(false)
+                .takes_value(true),
         );
 
     let matches = app.get_matches();
@@ -64,7 +72,7 @@ async fn main() {
         .expect("BUG: missing 'user' attribute");
     let user_info = ClientUserInfo::parse(user_info);
 
-    let backend_config =
+    let mut backend_config =
         config::Backend::new(match ClientDescriptor::create(url, &user_info, true) {
             Err(e) => {
                 error!("Cannot set pool from command line: {}", e.to_string());
@@ -72,6 +80,15 @@ async fn main() {
             }
             Ok(v) => v,
         });
+    if let Some(ratio) = matches.value_of("max-search-ratio") {
+        backend_config = match ratio.parse::<f64>() {
+            Ok(ratio) if ratio > 0.0 => backend_config.with_max_search_ratio(ratio),
+            _ => {
+                error!("Invalid maximal search ratio '{}'", ratio);
+                return;
+            }
+        };
+    }
 
     ii_async_compat::setup_panic_handling();
     bosminer::main::<bosminer_erupter::Backend>(backend_config, bosminer::SIGNATURE.to_string())
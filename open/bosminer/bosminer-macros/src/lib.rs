@@ -141,7 +141,13 @@ fn impl_derive_work_solver_node(
         member_valid_network_diff,
         member_valid_job_diff,
         member_valid_backend_diff,
-        member_error_backend_diff
+        member_error_backend_diff,
+        member_cross_check,
+        member_dropped_solutions,
+        member_stale_solutions,
+        member_generation_bumps,
+        member_midstate_yield,
+        member_pending_submissions
     )
 )]
 pub fn derive_mining_stats(input: TokenStream) -> TokenStream {
@@ -161,6 +167,12 @@ fn impl_derive_mining_stats(ast: &DeriveInput, derive_name: &str) -> proc_macro2
     let valid_job_diff = find_member(&fields, "member_valid_job_diff");
     let valid_backend_diff = find_member(&fields, "member_valid_backend_diff");
     let error_backend_diff = find_member(&fields, "member_error_backend_diff");
+    let cross_check = find_member(&fields, "member_cross_check");
+    let dropped_solutions = find_member(&fields, "member_dropped_solutions");
+    let stale_solutions = find_member(&fields, "member_stale_solutions");
+    let generation_bumps = find_member(&fields, "member_generation_bumps");
+    let midstate_yield = find_member(&fields, "member_midstate_yield");
+    let pending_submissions = find_member(&fields, "member_pending_submissions");
 
     quote! {
         impl#generics stats::Mining for #name#generics {
@@ -198,6 +210,36 @@ fn impl_derive_mining_stats(ast: &DeriveInput, derive_name: &str) -> proc_macro2
             fn error_backend_diff(&self) -> &stats::Meter {
                 &self.#error_backend_diff
             }
+
+            #[inline]
+            fn cross_check(&self) -> &stats::CrossCheck {
+                &self.#cross_check
+            }
+
+            #[inline]
+            fn dropped_solutions(&self) -> &stats::CounterU64 {
+                &self.#dropped_solutions
+            }
+
+            #[inline]
+            fn stale_solutions(&self) -> &stats::CounterU64 {
+                &self.#stale_solutions
+            }
+
+            #[inline]
+            fn generation_bumps(&self) -> &stats::GenerationBumps {
+                &self.#generation_bumps
+            }
+
+            #[inline]
+            fn midstate_yield(&self) -> &stats::MidstateYield {
+                &self.#midstate_yield
+            }
+
+            #[inline]
+            fn pending_submissions(&self) -> &stats::PendingSubmissions {
+                &self.#pending_submissions
+            }
         }
     }
 }
@@ -209,16 +251,26 @@ fn impl_derive_mining_stats(ast: &DeriveInput, derive_name: &str) -> proc_macro2
         member_start_time,
         member_valid_jobs,
         member_invalid_jobs,
+        member_last_job,
         member_generated_work,
         member_last_share,
         member_best_share,
         member_accepted,
         member_rejected,
         member_stale,
+        member_reject_reasons,
+        member_floor_dropped,
+        member_version_rolling,
         member_valid_network_diff,
         member_valid_job_diff,
         member_valid_backend_diff,
-        member_error_backend_diff
+        member_error_backend_diff,
+        member_cross_check,
+        member_dropped_solutions,
+        member_stale_solutions,
+        member_generation_bumps,
+        member_midstate_yield,
+        member_pending_submissions
     )
 )]
 pub fn derive_client_stats(input: TokenStream) -> TokenStream {
@@ -239,10 +291,14 @@ fn impl_derive_client_stats(
     let fields = get_fields(&ast, derive_name);
     let valid_jobs = find_member(&fields, "member_valid_jobs");
     let invalid_jobs = find_member(&fields, "member_invalid_jobs");
+    let last_job = find_member(&fields, "member_last_job");
     let generated_work = find_member(&fields, "member_generated_work");
     let accepted = find_member(&fields, "member_accepted");
     let rejected = find_member(&fields, "member_rejected");
     let stale = find_member(&fields, "member_stale");
+    let reject_reasons = find_member(&fields, "member_reject_reasons");
+    let floor_dropped = find_member(&fields, "member_floor_dropped");
+    let version_rolling = find_member(&fields, "member_version_rolling");
 
     stream.extend(quote! {
         impl#generics stats::Client for #name#generics {
@@ -256,6 +312,11 @@ fn impl_derive_client_stats(
                 &self.#invalid_jobs
             }
 
+            #[inline]
+            fn last_job(&self) -> &stats::LastJob {
+                &self.#last_job
+            }
+
             #[inline]
             fn generated_work(&self) -> &stats::CounterU64 {
                 &self.#generated_work
@@ -275,6 +336,21 @@ fn impl_derive_client_stats(
             fn stale(&self) -> &stats::Meter {
                 &self.#stale
             }
+
+            #[inline]
+            fn reject_reasons(&self) -> &stats::RejectReasons {
+                &self.#reject_reasons
+            }
+
+            #[inline]
+            fn floor_dropped(&self) -> &stats::CounterU64 {
+                &self.#floor_dropped
+            }
+
+            #[inline]
+            fn version_rolling(&self) -> &stats::VersionRollingShares {
+                &self.#version_rolling
+            }
         }
     });
     stream
@@ -287,12 +363,19 @@ fn impl_derive_client_stats(
         member_start_time,
         member_last_work_time,
         member_generated_work,
+        member_invalid_work,
         member_last_share,
         member_best_share,
         member_valid_network_diff,
         member_valid_job_diff,
         member_valid_backend_diff,
-        member_error_backend_diff
+        member_error_backend_diff,
+        member_cross_check,
+        member_dropped_solutions,
+        member_stale_solutions,
+        member_generation_bumps,
+        member_midstate_yield,
+        member_pending_submissions
     )
 )]
 pub fn derive_work_solver_stats(input: TokenStream) -> TokenStream {
@@ -313,6 +396,7 @@ fn impl_derive_work_solver_stats(
     let fields = get_fields(&ast, derive_name);
     let last_work_time = find_member(&fields, "member_last_work_time");
     let generated_work = find_member(&fields, "member_generated_work");
+    let invalid_work = find_member(&fields, "member_invalid_work");
 
     stream.extend(quote! {
         impl#generics stats::WorkSolver for #name#generics {
@@ -325,6 +409,11 @@ fn impl_derive_work_solver_stats(
             fn generated_work(&self) -> &stats::CounterU64 {
                 &self.#generated_work
             }
+
+            #[inline]
+            fn invalid_work(&self) -> &stats::CounterU64 {
+                &self.#invalid_work
+            }
         }
     });
     stream
@@ -0,0 +1,276 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Optional pinning of latency sensitive tasks (work generation and solution handling) to
+//! dedicated CPU cores so that scheduler jitter caused by other load cannot starve the hardware
+
+use ii_logging::macros::*;
+
+use ii_async_compat::tokio;
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::thread;
+
+/// Low level access to CPU affinity of the current thread
+pub trait CoreAffinity: Debug + Send + Sync {
+    /// Return indexes of cores the current thread is allowed to run on
+    fn get(&self) -> io::Result<Vec<usize>>;
+    /// Restrict the current thread to cores with `cores` indexes
+    fn set(&self, cores: &[usize]) -> io::Result<()>;
+}
+
+/// CPU affinity provided by the operating system
+#[derive(Debug, Default)]
+pub struct SystemAffinity;
+
+#[cfg(target_os = "linux")]
+impl CoreAffinity for SystemAffinity {
+    fn get(&self) -> io::Result<Vec<usize>> {
+        let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+        let result =
+            unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((0..libc::CPU_SETSIZE as usize)
+            .filter(|&core| unsafe { libc::CPU_ISSET(core, &set) })
+            .collect())
+    }
+
+    fn set(&self, cores: &[usize]) -> io::Result<()> {
+        let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+        for &core in cores {
+            if core >= libc::CPU_SETSIZE as usize {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("core index {} out of range", core),
+                ));
+            }
+            unsafe { libc::CPU_SET(core, &mut set) };
+        }
+        let result =
+            unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl CoreAffinity for SystemAffinity {
+    fn get(&self) -> io::Result<Vec<usize>> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "CPU affinity is not supported on this platform",
+        ))
+    }
+
+    fn set(&self, _cores: &[usize]) -> io::Result<()> {
+        self.get().map(|_| ())
+    }
+}
+
+/// Set of cores dedicated to a particular kind of tasks
+#[derive(Debug, Clone)]
+pub struct Pinning {
+    affinity: Arc<dyn CoreAffinity>,
+    cores: Vec<usize>,
+}
+
+impl Pinning {
+    pub fn new(affinity: Arc<dyn CoreAffinity>, cores: Vec<usize>) -> Self {
+        assert!(!cores.is_empty(), "BUG: pinning to empty set of cores");
+        Self { affinity, cores }
+    }
+
+    /// Pinning using affinity of the operating system
+    pub fn system(cores: Vec<usize>) -> Self {
+        Self::new(Arc::new(SystemAffinity), cores)
+    }
+
+    #[inline]
+    pub fn cores(&self) -> &[usize] {
+        &self.cores
+    }
+
+    /// Pin the current thread until the returned guard is dropped. Failure is not fatal, the
+    /// thread just keeps running on any core.
+    pub fn pin(&self) -> Option<PinGuard> {
+        let previous = match self.affinity.get() {
+            Ok(previous) => previous,
+            Err(e) => {
+                warn!("Cannot get CPU affinity of current thread: {}", e);
+                return None;
+            }
+        };
+        if let Err(e) = self.affinity.set(&self.cores) {
+            warn!("Cannot pin current thread to cores {:?}: {}", self.cores, e);
+            return None;
+        }
+        Some(PinGuard {
+            affinity: self.affinity.clone(),
+            previous,
+        })
+    }
+
+    /// Run `future` to completion on a new thread pinned to the cores. The thread has its own
+    /// single threaded runtime so the future is never moved to another core by the scheduler.
+    pub fn spawn<F>(&self, name: &str, future: F) -> io::Result<thread::JoinHandle<()>>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let pinning = self.clone();
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                let _pinned = pinning.pin();
+                tokio::runtime::Builder::new()
+                    .basic_scheduler()
+                    .enable_all()
+                    .build()
+                    .expect("BUG: cannot build runtime for pinned thread")
+                    .block_on(future)
+            })
+    }
+}
+
+/// Restores the original affinity of the pinned thread when dropped
+#[derive(Debug)]
+pub struct PinGuard {
+    affinity: Arc<dyn CoreAffinity>,
+    previous: Vec<usize>,
+}
+
+impl Drop for PinGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.affinity.set(&self.previous) {
+            warn!("Cannot restore CPU affinity {:?}: {}", self.previous, e);
+        }
+    }
+}
+
+/// Cores dedicated to tasks spawned by the work hub. Tasks without pinning run on any core.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Cores for computation of work (see `work::Generator::pinning`)
+    pub work_generation: Option<Pinning>,
+    /// Cores for routing solutions from the backend to clients
+    pub solution_handling: Option<Pinning>,
+}
+
+impl Config {
+    /// Parse list of core indexes separated by comma (e.g. `0,2,3`)
+    pub fn parse_cores(value: &str) -> Result<Vec<usize>, String> {
+        let cores = value
+            .split(',')
+            .map(|core| {
+                core.trim()
+                    .parse::<usize>()
+                    .map_err(|e| format!("invalid core '{}': {}", core, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(cores)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    use std::sync::Mutex as StdMutex;
+
+    /// Affinity which only records requested core sets. All threads report the same initial
+    /// affinity because the requests are not applied.
+    #[derive(Debug)]
+    pub struct TestAffinity {
+        initial: Vec<usize>,
+        calls: StdMutex<Vec<Vec<usize>>>,
+    }
+
+    impl TestAffinity {
+        pub fn new(initial: Vec<usize>) -> Self {
+            Self {
+                initial,
+                calls: StdMutex::new(vec![]),
+            }
+        }
+
+        /// Return all core sets passed to `set`
+        pub fn calls(&self) -> Vec<Vec<usize>> {
+            self.calls.lock().expect("BUG: cannot lock calls").clone()
+        }
+    }
+
+    impl CoreAffinity for TestAffinity {
+        fn get(&self) -> io::Result<Vec<usize>> {
+            Ok(self.initial.clone())
+        }
+
+        fn set(&self, cores: &[usize]) -> io::Result<()> {
+            self.calls
+                .lock()
+                .expect("BUG: cannot lock calls")
+                .push(cores.to_vec());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_pin_restores_affinity() {
+        let affinity = Arc::new(TestAffinity::new(vec![0, 1, 2, 3]));
+        let pinning = Pinning::new(affinity.clone(), vec![2, 3]);
+
+        let guard = pinning.pin();
+        assert!(guard.is_some());
+        assert_eq!(affinity.calls(), vec![vec![2, 3]]);
+        drop(guard);
+        assert_eq!(affinity.calls(), vec![vec![2, 3], vec![0, 1, 2, 3]]);
+    }
+
+    #[test]
+    fn test_spawn_pinned() {
+        let affinity = Arc::new(TestAffinity::new(vec![0, 1]));
+        let pinning = Pinning::new(affinity.clone(), vec![1]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        pinning
+            .spawn("test", async move {
+                sender.send(()).expect("BUG: cannot send");
+            })
+            .expect("BUG: cannot spawn pinned thread")
+            .join()
+            .expect("BUG: pinned thread panicked");
+        assert!(receiver.recv().is_ok());
+        assert_eq!(affinity.calls(), vec![vec![1], vec![0, 1]]);
+    }
+
+    #[test]
+    fn test_parse_cores() {
+        assert_eq!(Config::parse_cores("0, 2,3"), Ok(vec![0, 2, 3]));
+        assert!(Config::parse_cores("").is_err());
+        assert!(Config::parse_cores("1,x").is_err());
+    }
+}
@@ -30,11 +30,17 @@ use crate::node::{self, Stats as _, WorkSolver, WorkSolverStats as _};
 use crate::stats::{self, UnixTime as _};
 use crate::sync;
 use crate::version;
+use crate::work;
 
+#[cfg(feature = "share-simulation")]
+use ii_cgminer_api::command::SIMULATE_SHARE;
+use ii_cgminer_api::command::{
+    BUILDINFO, CLEAR_ERRORS, DONATION, ERRORS, REPLACE_POOLS, SOLUTIONS,
+};
 use ii_cgminer_api::support::ValueExt as _;
-use ii_cgminer_api::{command, json, response};
+use ii_cgminer_api::{command, commands, json, response};
 
-use bosminer_config::{ClientDescriptor, ClientUserInfo};
+use bosminer_config::{ClientDescriptor, ClientUserInfo, PoolConfig};
 
 use std::future::Future;
 use std::net::SocketAddr;
@@ -91,7 +97,7 @@ impl Handler {
         let last_share_time = last_share
             .as_ref()
             .map_or(0, |share| share.time.get_unix_time().unwrap_or_default());
-        let last_share_difficulty = last_share.map_or(0.0, |share| share.difficulty as f64);
+        let last_share_difficulty = last_share.map_or(0.0, |share| share.difficulty);
 
         let pool_accepted_shares = accepted.shares.as_f64();
         let pool_rejected_shares = rejected.shares.as_f64();
@@ -110,7 +116,7 @@ impl Handler {
 
         let last_diff = last_job
             .as_ref()
-            .map(|job| job.target().get_difficulty() as f64)
+            .map(|job| job.network_params().difficulty(&job.target()))
             .unwrap_or(0.0);
         let current_block_version = last_job.map(|job| job.version()).unwrap_or_default();
 
@@ -169,7 +175,7 @@ impl Handler {
             // TODO: get actual value from client (Asic Boost)
             has_vmask: true,
             has_gbt: false,
-            best_share: best_share.map(|inner| *inner).unwrap_or_default() as u64,
+            best_share: best_share.map(|inner| *inner).unwrap_or_default(),
             pool_rejected_ratio,
             pool_stale_ratio,
             bad_work: *invalid_jobs as u64,
@@ -205,7 +211,7 @@ impl Handler {
         let last_share_time = last_share
             .as_ref()
             .map_or(0, |share| share.time.get_unix_time().unwrap_or_default());
-        let last_share_difficulty = last_share.map_or(0.0, |share| share.difficulty as f64);
+        let last_share_difficulty = last_share.map_or(0.0, |share| share.difficulty);
 
         let total_mega_hashes = valid_job_diff.shares.into_mega_hashes().into_f64();
         let backend_valid_solutions = valid_backend_diff.solutions;
@@ -535,7 +541,7 @@ impl command::Handler for Handler {
             difficulty_accepted: pools_accepted_shares,
             difficulty_rejected: pools_rejected_shares,
             difficulty_stale: pools_stale_shares,
-            best_share: best_share.map(|inner| *inner).unwrap_or_default() as u64,
+            best_share: best_share.map(|inner| *inner).unwrap_or_default(),
             device_hardware_ratio: backend_error_ratio,
             device_rejected_ratio: backend_rejected_ratio,
             pool_rejected_ratio: pools_rejected_ratio,
@@ -556,6 +562,7 @@ impl command::Handler for Handler {
             // TODO: detect underlying operation system
             os: "Braiins OS".to_string(),
             hotplug: "None".to_string(),
+            work_distribution: Some(self.core.work_distribution().to_string()),
         })
     }
 
@@ -778,18 +785,227 @@ impl command::Handler for Handler {
     }
 }
 
+/// Handler of extended commands which are implemented by BOSminer frontend
+struct ExtHandler {
+    core: Arc<hub::Core>,
+    error_registry: Arc<error::ErrorRegistry>,
+    donation: Arc<stats::Donation>,
+}
+
+impl ExtHandler {
+    pub fn new(core: Arc<hub::Core>) -> Self {
+        Self {
+            error_registry: core.error_registry.clone(),
+            donation: core.get_client_manager().donation(),
+            core,
+        }
+    }
+
+    async fn handle_errors(&self) -> command::Result<response::ext::Errors> {
+        let list = self
+            .error_registry
+            .list()
+            .into_iter()
+            .enumerate()
+            .map(|(idx, condition)| response::ext::ErrorCondition {
+                idx: idx as i32,
+                kind: condition.kind.to_string(),
+                severity: condition.severity.to_string(),
+                source: condition.source,
+                message: condition.message,
+                first_seen: condition.first_seen.get_unix_time().unwrap_or_default(),
+                last_seen: condition.last_seen.get_unix_time().unwrap_or_default(),
+                count: condition.count,
+            })
+            .collect();
+
+        Ok(response::ext::Errors { list })
+    }
+
+    async fn handle_clear_errors(&self) -> command::Result<response::ext::ClearErrors> {
+        Ok(response::ext::ClearErrors {
+            count: self.error_registry.clear(),
+        })
+    }
+
+    async fn handle_build_info(&self) -> command::Result<response::ext::BuildInfo> {
+        let build_info = version::build_info();
+        Ok(response::ext::BuildInfo {
+            version: build_info.version.to_string(),
+            git_hash: build_info.git_hash.to_string(),
+            profile: build_info.profile.to_string(),
+            target: build_info.target,
+            features: build_info.features.join(","),
+        })
+    }
+
+    async fn handle_donation(&self) -> command::Result<response::ext::Donation> {
+        let now = time::Instant::now();
+        let valid_job_diff = self.donation.valid_job_diff.take_snapshot().await;
+        let valid_backend_diff = self.donation.valid_backend_diff.take_snapshot().await;
+
+        Ok(response::ext::Donation {
+            active: self.donation.is_active(),
+            elapsed: self.donation.time(now).as_secs(),
+            mhs_1m: valid_backend_diff
+                .to_mega_hashes(*INTERVAL_1M, now)
+                .into_f64(),
+            mhs_15m: valid_backend_diff
+                .to_mega_hashes(*INTERVAL_15M, now)
+                .into_f64(),
+            valid: valid_job_diff.solutions,
+            difficulty_valid: valid_job_diff.shares.as_f64(),
+        })
+    }
+
+    async fn handle_solutions(&self) -> command::Result<response::ext::Solutions> {
+        let list = self
+            .core
+            .recent_solutions()
+            .into_iter()
+            .enumerate()
+            .map(|(idx, record)| {
+                let (status, reason) = match record.outcome {
+                    None => ("Pending", String::new()),
+                    Some(work::SubmitOutcome::Accepted) => ("Accepted", String::new()),
+                    Some(work::SubmitOutcome::Rejected(reason)) => ("Rejected", reason.to_string()),
+                };
+                response::ext::Solution {
+                    idx: idx as i32,
+                    id: record.solver_id.map_or(-1, |id| id as i32),
+                    nonce: format!("{:08x}", record.nonce),
+                    hash: record.hash.to_string(),
+                    share_difficulty: record.share_difficulty,
+                    job_difficulty: record.job_difficulty,
+                    age: record.age.as_secs_f64(),
+                    status: status.to_string(),
+                    reason,
+                }
+            })
+            .collect();
+
+        Ok(response::ext::Solutions { list })
+    }
+
+    /// Parse pool details in the `url,user[,password]` format
+    fn parse_pool_config(details: &str) -> Option<PoolConfig> {
+        let mut parameters = details.splitn(3, ii_cgminer_api::PARAMETER_DELIMITER);
+
+        // URL and user name is required
+        let url = parameters.next().filter(|url| !url.is_empty())?;
+        let user = parameters.next().filter(|user| !user.is_empty())?;
+        let password = parameters.next().filter(|password| !password.is_empty());
+
+        Some(PoolConfig {
+            enabled: None,
+            url: url.to_string(),
+            user: user.to_string(),
+            password: password.map(str::to_string),
+        })
+    }
+
+    async fn handle_replace_pools(
+        &self,
+        parameter: Option<&json::Value>,
+    ) -> command::Result<response::ext::ReplacePools> {
+        let parameter = parameter
+            .expect("BUG: missing REPLACEPOOLS parameter")
+            .as_str()
+            .expect("BUG: invalid REPLACEPOOLS parameter type");
+
+        let pools = parameter
+            .split(ii_cgminer_api::POOL_DELIMITER)
+            .map(|details| {
+                Self::parse_pool_config(details)
+                    .ok_or_else(|| response::ErrorCode::InvalidAddPoolDetails(details.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let count = pools.len();
+
+        // Pools are validated as a whole so the pool set is left intact on any error
+        self.core
+            .replace_pools(pools)
+            .await
+            .map_err(|_| response::ErrorCode::InvalidAddPoolDetails(parameter.to_string()))?;
+
+        Ok(response::ext::ReplacePools { count })
+    }
+
+    /// Accept difficulty given either as a number or as a string
+    #[cfg(feature = "share-simulation")]
+    fn parse_difficulty(parameter: &json::Value) -> Option<f64> {
+        match parameter {
+            json::Value::Number(difficulty) => difficulty.as_f64(),
+            json::Value::String(difficulty) => difficulty.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "share-simulation")]
+    async fn handle_simulate_share(
+        &self,
+        parameter: Option<&json::Value>,
+    ) -> command::Result<response::ext::SimulateShare> {
+        let parameter = parameter.expect("BUG: missing SIMULATESHARE parameter");
+        let difficulty = Self::parse_difficulty(parameter).ok_or_else(|| {
+            response::ErrorCode::InvalidSimulatedShare(format!("invalid difficulty {}", parameter))
+        })?;
+
+        self.core
+            .simulate_accepted_share(difficulty)
+            .await
+            .map_err(|e| response::ErrorCode::InvalidSimulatedShare(e.to_string()))?;
+
+        Ok(response::ext::SimulateShare { difficulty })
+    }
+}
+
 pub async fn run(
     core: Arc<hub::Core>,
     listen_addr: SocketAddr,
     custom_commands: Option<command::Map>,
     signature: String,
 ) {
+    let ext_handler = Arc::new(ExtHandler::new(core.clone()));
+    let check_replace_pools: command::ParameterCheckHandler =
+        Box::new(|_command, parameter| match parameter {
+            Some(json::Value::String(_)) => Ok(()),
+            _ => Err(response::ErrorCode::MissingAddPoolDetails.into()),
+        });
+    let mut commands = commands![
+        (ERRORS: ParameterLess -> ext_handler.handle_errors),
+        (CLEAR_ERRORS: ParameterLess -> ext_handler.handle_clear_errors),
+        (BUILDINFO: ParameterLess -> ext_handler.handle_build_info),
+        (DONATION: ParameterLess -> ext_handler.handle_donation),
+        (SOLUTIONS: ParameterLess -> ext_handler.handle_solutions),
+        (REPLACE_POOLS: Parameter(check_replace_pools) -> ext_handler.handle_replace_pools)
+    ];
+    // simulated shares distort statistics so the command is available only in testing builds
+    #[cfg(feature = "share-simulation")]
+    {
+        let check_simulate_share: command::ParameterCheckHandler =
+            Box::new(|_command, parameter| match parameter {
+                Some(_) => Ok(()),
+                None => Err(response::ErrorCode::InvalidSimulatedShare(
+                    "missing difficulty".to_string(),
+                )
+                .into()),
+            });
+        commands.extend(commands![
+            (SIMULATE_SHARE: Parameter(check_simulate_share) -> ext_handler.handle_simulate_share)
+        ]);
+    }
+    // backend specific commands take precedence
+    if let Some(custom_commands) = custom_commands {
+        commands.extend(custom_commands);
+    }
+
     let handler = Handler::new(core);
     let command_receiver = command::Receiver::new(
         handler,
         signature,
         version::STRING.to_string(),
-        custom_commands,
+        commands,
     );
 
     ii_cgminer_api::run(command_receiver, listen_addr)
@@ -23,6 +23,7 @@
 //! This module contains dynamically built backend hierarchy
 
 use crate::node::{self, WorkSolverType};
+use crate::work;
 
 use async_trait::async_trait;
 use futures::lock::{Mutex, MutexGuard};
@@ -36,6 +37,21 @@ pub trait HierarchyBuilder: Send + Sync {
 
     async fn add_work_solver(&self, _work_solver: Arc<dyn node::WorkSolver>) {}
 
+    /// Register midstate count handle of the most recently added work solver
+    async fn add_midstate_count(&self, _midstate_count: Arc<work::MidstateCount>) {}
+
+    /// Register switch for enabling work generation of the most recently added work solver
+    async fn add_solver_switch(&self, _switch: Arc<work::SolverSwitch>) {}
+
+    /// Register hash rate cap of the most recently added work solver
+    async fn add_hashrate_cap(&self, _hashrate_cap: Arc<work::HashrateCap>) {}
+
+    /// Register generation of work engines used by the most recently added work solver
+    async fn add_work_generation(&self, _generation: Arc<work::WorkGeneration>) {}
+
+    /// Register the most recent work of the most recently added work solver
+    async fn add_inflight_work(&self, _inflight: Arc<work::InflightWork>) {}
+
     async fn add_node(&self, node: WorkSolverType<Arc<dyn node::WorkSolver>>) {
         match node {
             WorkSolverType::WorkHub(work_hub) => {
@@ -76,6 +92,19 @@ pub struct Registry {
     work_hubs: Mutex<Vec<Arc<dyn node::WorkSolver>>>,
     /// List of work solvers which do real work and usually represents physical HW
     work_solvers: Mutex<Vec<Arc<dyn node::WorkSolver>>>,
+    /// Midstate count handles of all work solvers (in the same order)
+    midstate_counts: Mutex<Vec<Arc<work::MidstateCount>>>,
+    /// Switches for enabling work generation paired with their work solvers (work solvers created
+    /// without `work::SolverBuilder` have no switch)
+    solver_switches: Mutex<Vec<(Arc<dyn node::WorkSolver>, Arc<work::SolverSwitch>)>>,
+    /// Hash rate caps of all work solvers (in the same order)
+    hashrate_caps: Mutex<Vec<Arc<work::HashrateCap>>>,
+    /// Generations of work engines used by all work solvers (in the same order)
+    work_generations: Mutex<Vec<Arc<work::WorkGeneration>>>,
+    /// Barrier passed when all work solvers have started the same job
+    job_barrier: Arc<work::JobBarrier>,
+    /// The most recent work of all work solvers (in the same order)
+    inflight_works: Mutex<Vec<Arc<work::InflightWork>>>,
 }
 
 impl Registry {
@@ -84,6 +113,12 @@ impl Registry {
             root_hub: Mutex::new(None),
             work_hubs: Mutex::new(vec![]),
             work_solvers: Mutex::new(vec![]),
+            midstate_counts: Mutex::new(vec![]),
+            solver_switches: Mutex::new(vec![]),
+            hashrate_caps: Mutex::new(vec![]),
+            work_generations: Mutex::new(vec![]),
+            job_barrier: Arc::new(work::JobBarrier::new()),
+            inflight_works: Mutex::new(vec![]),
         }
     }
 
@@ -131,6 +166,48 @@ impl Registry {
     pub async fn lock_work_solvers<'a>(&'a self) -> MutexGuard<'a, Vec<Arc<dyn node::WorkSolver>>> {
         self.work_solvers.lock().await
     }
+
+    #[inline]
+    pub async fn lock_midstate_counts<'a>(
+        &'a self,
+    ) -> MutexGuard<'a, Vec<Arc<work::MidstateCount>>> {
+        self.midstate_counts.lock().await
+    }
+
+    /// Return switch for enabling work generation of `work_solver` when it has any
+    pub async fn get_solver_switch(
+        &self,
+        work_solver: &Arc<dyn node::WorkSolver>,
+    ) -> Option<Arc<work::SolverSwitch>> {
+        self.solver_switches
+            .lock()
+            .await
+            .iter()
+            .find(|(switched_work_solver, _)| Arc::ptr_eq(switched_work_solver, work_solver))
+            .map(|(_, switch)| switch.clone())
+    }
+
+    #[inline]
+    pub async fn lock_hashrate_caps<'a>(&'a self) -> MutexGuard<'a, Vec<Arc<work::HashrateCap>>> {
+        self.hashrate_caps.lock().await
+    }
+
+    #[inline]
+    pub async fn lock_work_generations<'a>(
+        &'a self,
+    ) -> MutexGuard<'a, Vec<Arc<work::WorkGeneration>>> {
+        self.work_generations.lock().await
+    }
+
+    #[inline]
+    pub fn job_barrier(&self) -> &Arc<work::JobBarrier> {
+        &self.job_barrier
+    }
+
+    #[inline]
+    pub async fn lock_inflight_works<'a>(&'a self) -> MutexGuard<'a, Vec<Arc<work::InflightWork>>> {
+        self.inflight_works.lock().await
+    }
 }
 
 #[async_trait]
@@ -143,6 +220,39 @@ impl HierarchyBuilder for Registry {
         self.register_work_solver(work_solver).await;
     }
 
+    async fn add_midstate_count(&self, midstate_count: Arc<work::MidstateCount>) {
+        self.midstate_counts.lock().await.push(midstate_count);
+    }
+
+    async fn add_solver_switch(&self, switch: Arc<work::SolverSwitch>) {
+        let work_solver = self
+            .work_solvers
+            .lock()
+            .await
+            .last()
+            .expect("BUG: solver switch registered before its work solver")
+            .clone();
+        self.solver_switches
+            .lock()
+            .await
+            .push((work_solver, switch));
+    }
+
+    async fn add_hashrate_cap(&self, hashrate_cap: Arc<work::HashrateCap>) {
+        self.hashrate_caps.lock().await.push(hashrate_cap);
+    }
+
+    async fn add_work_generation(&self, generation: Arc<work::WorkGeneration>) {
+        let mut work_generations = self.work_generations.lock().await;
+        // the index is the same as the index of the work solver
+        generation.join_barrier(self.job_barrier.clone(), work_generations.len());
+        work_generations.push(generation);
+    }
+
+    async fn add_inflight_work(&self, inflight: Arc<work::InflightWork>) {
+        self.inflight_works.lock().await.push(inflight);
+    }
+
     async fn add_root(&self, node: WorkSolverType<Arc<dyn node::WorkSolver>>) {
         // register node as a root hub
         self.register_root_hub(node.as_ref().clone()).await;
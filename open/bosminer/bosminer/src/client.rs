@@ -23,13 +23,25 @@
 //! This module contains common functionality related to mining protocol client and allows
 //! executing a specific type of mining protocol client instance.
 
+mod clock_drift;
+mod difficulty_floor;
+mod health;
+mod job_expiry;
+mod keepalive;
+pub mod rejection;
 mod scheduler;
+mod solution_queue;
+mod submission_filter;
+mod submission_limit;
 
 // Sub-modules with client implementation
 pub mod drain;
+pub mod solo;
 pub mod stratum_v2;
 pub mod stratum_v2_channels;
 
+use ii_logging::macros::*;
+
 use crate::error;
 use crate::hal;
 use crate::job;
@@ -39,20 +51,76 @@ use crate::sync::event;
 use crate::work;
 
 // Scheduler re-exports
-pub use scheduler::JobExecutor;
+pub use scheduler::{JobExecutor, SwitchSignal};
+
+pub use clock_drift::ClockDrift;
+pub use difficulty_floor::DifficultyFloor;
+pub use health::{ConnectionState, PoolHealth};
+pub use job_expiry::JobExpiry;
+pub use keepalive::Keepalive;
+pub use solution_queue::SolutionQueue;
+pub use submission_filter::SubmissionFilter;
+pub use submission_limit::SubmissionLimit;
 
 use bosminer_config::{
     ClientDescriptor, ClientProtocol, ClientUserInfo, GroupConfig, GroupDescriptor,
-    LoadBalanceStrategy,
+    LoadBalanceStrategy, PoolConfig,
 };
 
 use futures::channel::mpsc;
 use futures::lock::Mutex;
-use ii_async_compat::futures;
+use ii_async_compat::{futures, tokio};
+use tokio::time::delay_for;
 
+use std::mem;
 use std::slice;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Period during which a client removed by `Group::replace_pools` keeps its connection open so
+/// that solutions of its recent jobs can still be submitted
+pub const RETIRED_CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Period of checking whether all pending submissions have been done during `Manager::drain`
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Check if both descriptors refer to the same pool connection (enable flag is not considered)
+fn is_same_pool(a: &ClientDescriptor, b: &ClientDescriptor) -> bool {
+    a.get_full_url() == b.get_full_url() && a.password == b.password && a.fragment == b.fragment
+}
+
+/// Parse local vardiff from fragment in format `vardiff=<shares per minute>` (see
+/// `job::SolutionReceiver::enable_vardiff`). It is intended for pools without their own vardiff.
+fn get_vardiff_config(descriptor: &ClientDescriptor) -> Option<job::vardiff::Config> {
+    const VARDIFF: &str = "vardiff=";
+
+    let fragment = descriptor.fragment.as_ref()?;
+    let value = &fragment[fragment.find(VARDIFF)? + VARDIFF.len()..];
+    let value = value
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or(value, |end| &value[..end]);
+    value
+        .parse::<u32>()
+        .ok()
+        .filter(|&shares_per_minute| shares_per_minute > 0)
+        .map(|shares_per_minute| job::vardiff::Config {
+            shares_per_minute: shares_per_minute as f64,
+            ..Default::default()
+        })
+}
+
+fn create_descriptor(
+    pool_config: &PoolConfig,
+    default_pool_enabled: bool,
+) -> error::Result<ClientDescriptor> {
+    Ok(ClientDescriptor::create(
+        pool_config.url.as_str(),
+        &ClientUserInfo::new(pool_config.user.as_str(), pool_config.password.as_deref()),
+        pool_config.enabled.unwrap_or(default_pool_enabled),
+    )
+    .map_err(|e| e.to_string())?)
+}
 
 #[derive(Debug)]
 pub struct Handle {
@@ -62,6 +130,8 @@ pub struct Handle {
     enabled: AtomicBool,
     engine_sender: Arc<work::EngineSender>,
     solution_sender: mpsc::UnboundedSender<work::Solution>,
+    /// The client belongs to a donation group (see `GroupDescriptor::donation`)
+    donation: bool,
 }
 
 impl Handle {
@@ -79,7 +149,13 @@ impl Handle {
         // Initially register new client without ability to send work
         let engine_sender = Arc::new(work::EngineSender::new(None));
 
-        let job_solver = job::Solver::new(engine_sender.clone(), solution_receiver);
+        let mut job_solver = job::Solver::new(engine_sender.clone(), solution_receiver);
+        if let Some(config) = get_vardiff_config(&descriptor) {
+            let initial_difficulty = config.min_difficulty;
+            job_solver
+                .solution_receiver
+                .enable_vardiff(config, initial_difficulty);
+        }
         let node: Arc<dyn node::Client> = match &descriptor.protocol {
             ClientProtocol::Drain => {
                 assert!(
@@ -110,6 +186,16 @@ impl Handle {
                 job_solver,
                 channel,
             )),
+            ClientProtocol::Solo => {
+                assert!(
+                    channel.is_none(),
+                    "BUG: protocol 'Solo' does not support channel"
+                );
+                Arc::new(solo::Client::new(
+                    solo::ConnectionDetails::from_descriptor(&descriptor),
+                    job_solver,
+                ))
+            }
         };
 
         Self {
@@ -118,6 +204,7 @@ impl Handle {
             enabled: AtomicBool::new(false),
             engine_sender,
             solution_sender,
+            donation: false,
         }
     }
 
@@ -195,6 +282,12 @@ impl Handle {
         }
     }
 
+    /// Check if the client mines on behalf of developers
+    #[inline]
+    pub fn is_donation(&self) -> bool {
+        self.donation
+    }
+
     /// Check if current state of the client is enabled
     #[inline]
     pub fn is_enabled(&self) -> bool {
@@ -227,8 +320,12 @@ impl Handle {
         }
     }
 
-    #[inline]
     pub fn try_restart(&self, enabled: bool) -> Result<(), ()> {
+        debug!("Work generation of '{}' bumped by restart", self.node);
+        self.node
+            .client_stats()
+            .generation_bumps()
+            .account(work::GenerationCause::ManualRestart);
         match self.try_disable() {
             Ok(_) => {}
             Err(_) => {
@@ -246,10 +343,30 @@ impl Handle {
         self.node.client_stats()
     }
 
+    /// Node of the client which is the first one in the accounting path of its solutions
+    #[cfg(any(test, feature = "share-simulation"))]
+    pub(crate) fn node(&self) -> node::DynInfo {
+        Arc::new(self.node.clone())
+    }
+
     #[inline]
     pub(crate) async fn get_last_job(&self) -> Option<Arc<dyn job::Bitcoin>> {
         self.node.get_last_job().await
     }
+
+    /// Return health of the connection to the pool as observed at `now`
+    pub async fn health(&self, now: Instant) -> PoolHealth {
+        let client_stats = self.stats();
+        let accepted = client_stats.accepted().take_snapshot().await.solutions;
+        let rejected = client_stats.rejected().take_snapshot().await.solutions;
+        PoolHealth {
+            url: self.descriptor().await.get_full_url(),
+            state: ConnectionState::new(self.status(), self.is_enabled()),
+            last_job_age: client_stats.last_job().age(now),
+            submit_success_rate: health::submit_success_rate(accepted, rejected),
+            ping_latency: self.node.submission_latency(),
+        }
+    }
 }
 
 impl Drop for Handle {
@@ -271,22 +388,34 @@ impl PartialEq for Handle {
 pub struct Group {
     pub descriptor: GroupDescriptor,
     scheduler_client_handles: Mutex<Vec<scheduler::ClientHandle>>,
+    /// Clients removed from scheduling which are still able to submit solutions
+    retired_client_handles: Arc<Mutex<Vec<Arc<Handle>>>>,
     event_sender: event::Sender,
-    /// All clients in the group must support the same amount of midstates
-    midstate_count: usize,
+    /// Creates work engines for jobs of all clients in the group (all of them must support the
+    /// same amount of midstates)
+    prefetcher: work::engine::Prefetcher,
+    /// Registry passed to all clients for reporting their operational problems
+    error_registry: Option<Arc<error::ErrorRegistry>>,
+    /// Minimal pool difficulty suggested by all clients when it is known for the hardware
+    hardware_min_difficulty: Option<u64>,
 }
 
 impl Group {
     fn new(
         descriptor: GroupDescriptor,
         event_sender: event::Sender,
-        midstate_count: usize,
+        prefetcher: work::engine::Prefetcher,
+        error_registry: Option<Arc<error::ErrorRegistry>>,
+        hardware_min_difficulty: Option<u64>,
     ) -> Self {
         Self {
             descriptor,
             scheduler_client_handles: Mutex::new(vec![]),
+            retired_client_handles: Arc::new(Mutex::new(vec![])),
             event_sender,
-            midstate_count,
+            prefetcher,
+            error_registry,
+            hardware_min_difficulty,
         }
     }
 
@@ -310,15 +439,31 @@ impl Group {
             .collect()
     }
 
-    pub async fn push_client(&self, client_handle: Handle) -> Arc<Handle> {
-        let midstate_count = self.midstate_count;
-        let _ = client_handle.replace_engine_generator(Box::new(move |job| {
-            Arc::new(work::engine::VersionRolling::new(job, midstate_count))
-        }));
+    /// Make client compatible with the group before its insertion
+    async fn prepare_client(&self, mut client_handle: Handle) -> Handle {
+        client_handle.donation = self.descriptor.donation;
+        let prefetcher = self.prefetcher.clone();
+        let _ = client_handle
+            .replace_engine_generator(Box::new(move |job| prefetcher.build_engine(job)));
         let _ = client_handle.try_disable();
         client_handle.set_event_sender(self.event_sender.clone());
+        client_handle
+            .node
+            .set_min_extranonce_size(self.prefetcher.min_extranonce_size());
+        if let Some(difficulty) = self.hardware_min_difficulty {
+            client_handle.node.set_hardware_min_difficulty(difficulty);
+        }
+        if let Some(error_registry) = self.error_registry.as_ref() {
+            client_handle
+                .node
+                .set_error_registry(error_registry.clone())
+                .await;
+        }
+        client_handle
+    }
 
-        let client_handle = Arc::new(client_handle);
+    pub async fn push_client(&self, client_handle: Handle) -> Arc<Handle> {
+        let client_handle = Arc::new(self.prepare_client(client_handle).await);
         let scheduler_client_handle = scheduler::ClientHandle::new(client_handle.clone());
         self.scheduler_client_handles
             .lock()
@@ -394,8 +539,115 @@ impl Group {
         Ok(client_handle)
     }
 
+    /// Replace all clients in the group with clients described by `descriptors`.
+    /// Clients connected to the same pool as one of the new descriptors are kept running (only
+    /// their position and enabled state are updated), new ones are created and the rest is
+    /// retired. Retired clients are excluded from scheduling immediately but they are stopped
+    /// after `RETIRED_CLIENT_TIMEOUT` to be able to submit solutions of their last jobs.
+    /// Returns retired clients.
+    pub async fn replace_pools(
+        &self,
+        descriptors: Vec<ClientDescriptor>,
+        backend_info: Option<&hal::BackendInfo>,
+    ) -> Vec<Arc<Handle>> {
+        let mut scheduler_client_handles = self.scheduler_client_handles.lock().await;
+        let mut old_client_handles = mem::take(&mut *scheduler_client_handles);
+        let mut added_client_handles = vec![];
+
+        for descriptor in descriptors {
+            let mut kept_index = None;
+            for (i, scheduler_client_handle) in old_client_handles.iter().enumerate() {
+                if is_same_pool(
+                    &*scheduler_client_handle
+                        .client_handle
+                        .descriptor
+                        .lock()
+                        .await,
+                    &descriptor,
+                ) {
+                    kept_index = Some(i);
+                    break;
+                }
+            }
+            match kept_index {
+                Some(i) => {
+                    let scheduler_client_handle = old_client_handles.remove(i);
+                    let client_handle = &scheduler_client_handle.client_handle;
+                    {
+                        // NOTE: Keep descriptor locked to synchronize descriptor changes
+                        let mut client_descriptor = client_handle.descriptor.lock().await;
+                        if descriptor.enabled {
+                            let _ = client_handle.try_enable();
+                        } else {
+                            let _ = client_handle.try_disable();
+                        }
+                        client_descriptor.enabled = descriptor.enabled;
+                    }
+                    scheduler_client_handles.push(scheduler_client_handle);
+                }
+                None => {
+                    let client_handle = Arc::new(
+                        self.prepare_client(Handle::new(descriptor, backend_info.cloned(), None))
+                            .await,
+                    );
+                    scheduler_client_handles
+                        .push(scheduler::ClientHandle::new(client_handle.clone()));
+                    added_client_handles.push(client_handle);
+                }
+            }
+        }
+        drop(scheduler_client_handles);
+
+        let retired_client_handles: Vec<_> = old_client_handles
+            .into_iter()
+            .map(|scheduler_client_handle| {
+                let client_handle = scheduler_client_handle.client_handle;
+                // Remove event sender not to notify about retired client status changes
+                client_handle.take_event_sender();
+                client_handle
+            })
+            .collect();
+        self.retire_clients(retired_client_handles.clone()).await;
+        // Immediately notify about changes to force scheduler to select one of actual clients
+        self.event_sender.notify();
+
+        for client_handle in added_client_handles {
+            // NOTE: Keep descriptor locked to synchronize descriptor changes
+            let client_descriptor = client_handle.descriptor.lock().await;
+
+            if client_descriptor.enabled {
+                let _ = client_handle.try_enable();
+            }
+        }
+
+        retired_client_handles
+    }
+
+    async fn retire_clients(&self, client_handles: Vec<Arc<Handle>>) {
+        if client_handles.is_empty() {
+            return;
+        }
+        self.retired_client_handles
+            .lock()
+            .await
+            .extend(client_handles.iter().cloned());
+
+        let retired_client_handles = self.retired_client_handles.clone();
+        tokio::spawn(async move {
+            delay_for(RETIRED_CLIENT_TIMEOUT).await;
+            retired_client_handles
+                .lock()
+                .await
+                .retain(|retired| !client_handles.contains(retired));
+            for client_handle in client_handles {
+                let _ = client_handle.try_disable();
+            }
+        });
+    }
+
     async fn find_client(&self, solution: &work::Solution) -> Option<Arc<Handle>> {
-        self.scheduler_client_handles
+        let client_handle = self
+            .scheduler_client_handles
             .lock()
             .await
             .iter()
@@ -404,7 +656,18 @@ impl Group {
                     .client_handle
                     .matching_solution(solution)
             })
-            .map(|scheduler_client_handle| scheduler_client_handle.client_handle.clone())
+            .map(|scheduler_client_handle| scheduler_client_handle.client_handle.clone());
+        match client_handle {
+            Some(_) => client_handle,
+            // Solutions of recently retired clients are still delivered to their pools
+            None => self
+                .retired_client_handles
+                .lock()
+                .await
+                .iter()
+                .find(|client_handle| client_handle.matching_solution(solution))
+                .cloned(),
+        }
     }
 }
 
@@ -415,16 +678,33 @@ pub struct GroupRegistry {
     total_quota: usize,
     fixed_share_ratio_count: usize,
     total_fixed_share_ratio: f64,
+    error_registry: Option<Arc<error::ErrorRegistry>>,
+    /// Number of extranonce values the hardware rolls on its own within single work
+    extranonce_rolling: u32,
+    /// Seed of the extranonce sequence of each job
+    work_seed: Option<u64>,
+    /// Maximal number of seconds the nTime of each job can be rolled
+    max_ntime_roll: Option<u32>,
+    /// Practical minimum of pool difficulty derived from the hardware
+    hardware_min_difficulty: Option<u64>,
 }
 
 impl GroupRegistry {
-    pub fn new(event_monitor: event::Monitor) -> Self {
+    pub fn new(
+        event_monitor: event::Monitor,
+        error_registry: Option<Arc<error::ErrorRegistry>>,
+    ) -> Self {
         Self {
             list: vec![],
             event_monitor,
             total_quota: 0,
             fixed_share_ratio_count: 0,
             total_fixed_share_ratio: 0.0,
+            error_registry,
+            extranonce_rolling: 0,
+            work_seed: None,
+            max_ntime_roll: None,
+            hardware_min_difficulty: None,
         }
     }
 
@@ -472,10 +752,22 @@ impl GroupRegistry {
             }
         }
 
+        // Reuse midstates in each nTime round of the job and for reissued jobs. Groups mining
+        // the same job (e.g. solo mining to the same node) roll distinct extranonce ranges.
+        let prefetcher = work::engine::Prefetcher::new(
+            midstate_count,
+            work::engine::DEFAULT_PREFETCH_MIDSTATE_COUNT,
+        )
+        .with_extranonce_rolling(self.extranonce_rolling)
+        .with_work_seed(self.work_seed)
+        .with_seed_index(self.list.len())
+        .with_max_ntime_roll(self.max_ntime_roll);
         let group_handle = Arc::new(Group::new(
             descriptor,
             self.event_monitor.publish(),
-            midstate_count,
+            prefetcher,
+            self.error_registry.clone(),
+            self.hardware_min_difficulty,
         ));
         let scheduler_group_handle = scheduler::GroupHandle::new(group_handle.clone());
         self.list.push(scheduler_group_handle);
@@ -498,6 +790,15 @@ impl GroupRegistry {
             .map(|scheduler_group_handle| scheduler_group_handle.group_handle.clone())
     }
 
+    /// Return clients of all groups (in the order of groups)
+    async fn get_clients(&self) -> Vec<Arc<Handle>> {
+        let mut clients = vec![];
+        for scheduler_group_handle in &self.list {
+            clients.extend(scheduler_group_handle.group_handle.get_clients().await);
+        }
+        clients
+    }
+
     /// Find client which given solution is associated with
     async fn find_client(&self, solution: &work::Solution) -> Option<Arc<Handle>> {
         for scheduler_group_handle in &self.list {
@@ -548,18 +849,66 @@ pub struct Manager {
     group_registry: Arc<Mutex<GroupRegistry>>,
     event_monitor: event::Monitor,
     midstate_count: usize,
+    /// Mining of all donation groups accounted apart from the user's pools
+    donation: Arc<stats::Donation>,
 }
 
 impl Manager {
     pub fn new(midstate_count: usize) -> Self {
+        Self::build(midstate_count, None)
+    }
+
+    /// Create manager with clients reporting their problems (e.g. solution backlog) to
+    /// `error_registry`
+    pub fn with_error_registry(
+        midstate_count: usize,
+        error_registry: Arc<error::ErrorRegistry>,
+    ) -> Self {
+        Self::build(midstate_count, Some(error_registry))
+    }
+
+    fn build(midstate_count: usize, error_registry: Option<Arc<error::ErrorRegistry>>) -> Self {
         let event_monitor = event::Monitor::new();
         Self {
-            group_registry: Arc::new(Mutex::new(GroupRegistry::new(event_monitor.clone()))),
+            group_registry: Arc::new(Mutex::new(GroupRegistry::new(
+                event_monitor.clone(),
+                error_registry,
+            ))),
             event_monitor,
             midstate_count,
+            donation: Default::default(),
         }
     }
 
+    /// Let the hardware roll `range_size` extranonce values on its own within single work (see
+    /// `hal::BackendConfig::extranonce_rolling`). It affects only groups created afterwards.
+    pub async fn set_extranonce_rolling(&self, range_size: u32) {
+        self.group_registry.lock().await.extranonce_rolling = range_size;
+    }
+
+    /// Start the extranonce sequence of each job at a position given by `seed` (see
+    /// `hal::BackendConfig::work_seed`). It affects only groups created afterwards.
+    pub async fn set_work_seed(&self, seed: Option<u64>) {
+        self.group_registry.lock().await.work_seed = seed;
+    }
+
+    /// Limit rolling of nTime to `max_ntime_roll` seconds after the job time (see
+    /// `hal::BackendConfig::max_ntime_roll`). It affects only groups created afterwards.
+    pub async fn set_max_ntime_roll(&self, max_ntime_roll: Option<u32>) {
+        self.group_registry.lock().await.max_ntime_roll = max_ntime_roll;
+    }
+
+    /// Suggest `difficulty` to pools which are not configured otherwise (see
+    /// `DifficultyFloor::hardware_minimum`). It affects only groups created afterwards.
+    pub async fn set_hardware_min_difficulty(&self, difficulty: u64) {
+        self.group_registry.lock().await.hardware_min_difficulty = Some(difficulty);
+    }
+
+    /// Return statistics of mining on behalf of developers
+    pub fn donation(&self) -> Arc<stats::Donation> {
+        self.donation.clone()
+    }
+
     pub async fn load_config<T>(
         &self,
         group_configs: T,
@@ -574,15 +923,7 @@ impl Manager {
                 let group = self.create_group(group_config.descriptor).await?;
                 if let Some(pool_configs) = group_config.pools {
                     for pool_config in pool_configs {
-                        let descriptor = ClientDescriptor::create(
-                            pool_config.url.as_str(),
-                            &ClientUserInfo::new(
-                                pool_config.user.as_str(),
-                                pool_config.password.as_deref(),
-                            ),
-                            pool_config.enabled.unwrap_or(default_pool_enabled),
-                        )
-                        .map_err(|e| e.to_string())?;
+                        let descriptor = create_descriptor(&pool_config, default_pool_enabled)?;
                         let client_handle = Handle::new(descriptor, backend_info.cloned(), None);
                         group.push_client(client_handle).await;
                     }
@@ -592,6 +933,25 @@ impl Manager {
         Ok(())
     }
 
+    /// Swap the pool set of the default group (see `Group::replace_pools`). All pools are
+    /// validated before any change is made.
+    pub async fn replace_pools(
+        &self,
+        pools: Vec<PoolConfig>,
+        backend_info: Option<&hal::BackendInfo>,
+        default_pool_enabled: bool,
+    ) -> error::Result<Vec<Arc<Handle>>> {
+        let descriptors = pools
+            .iter()
+            .map(|pool_config| create_descriptor(pool_config, default_pool_enabled))
+            .collect::<error::Result<Vec<_>>>()?;
+        Ok(self
+            .create_or_get_default_group()
+            .await
+            .replace_pools(descriptors, backend_info)
+            .await)
+    }
+
     #[inline]
     pub fn subscribe_to_clients_status_changes(&self) -> event::Receiver {
         self.event_monitor.subscribe()
@@ -630,4 +990,248 @@ impl Manager {
     pub async fn get_groups(&self) -> Vec<Arc<Group>> {
         self.group_registry.lock().await.get_groups()
     }
+
+    /// Total number of shares accepted by all pools and of shares rejected due to invalid hash
+    /// (see `stats::RejectReasons::invalid_hash`)
+    pub async fn accepted_and_invalid_shares(&self) -> (u64, u64) {
+        let (mut accepted, mut invalid) = (0, 0);
+        for group in self.get_groups().await {
+            for client in group.get_clients().await {
+                let client_stats = client.stats();
+                accepted += client_stats.accepted().take_snapshot().await.solutions;
+                invalid += client_stats.reject_reasons().invalid_hash();
+            }
+        }
+        (accepted, invalid)
+    }
+
+    /// Number of solutions of all clients which have not been acknowledged by the remote server
+    /// yet or which are still held back (see `stats::PendingSubmissions`)
+    pub async fn pending_submissions(&self) -> usize {
+        let mut pending = 0;
+        for group in self.get_groups().await {
+            for client in group.get_clients().await {
+                let submissions = client.stats().pending_submissions();
+                pending += submissions.outstanding() + submissions.queued();
+            }
+        }
+        pending
+    }
+
+    /// Wait until all pending submissions are done and then disable all clients. It is intended
+    /// for shutdown of the miner after the work generation has been stopped. The caller should
+    /// limit the time of the drain because a disconnected pool never acknowledges submissions.
+    pub async fn drain(&self) {
+        while self.pending_submissions().await > 0 {
+            delay_for(DRAIN_POLL_INTERVAL).await;
+        }
+        for group in self.get_groups().await {
+            for client in group.get_clients().await {
+                let _ = client.try_disable();
+            }
+        }
+    }
+
+    /// Health of connections to all pools in the order of groups and their clients
+    pub async fn pool_health(&self) -> Vec<PoolHealth> {
+        let now = Instant::now();
+        let mut pool_health = vec![];
+        for group in self.get_groups().await {
+            for client in group.get_clients().await {
+                pool_health.push(client.health(now).await);
+            }
+        }
+        pool_health
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ii_async_compat::prelude::*;
+
+    fn pool(url: &str) -> PoolConfig {
+        PoolConfig {
+            enabled: None,
+            url: url.to_string(),
+            user: "user".to_string(),
+            password: None,
+        }
+    }
+
+    async fn client_urls(group: &Group) -> Vec<String> {
+        let mut urls = vec![];
+        for client_handle in group.get_clients().await {
+            urls.push(client_handle.descriptor().await.get_full_url());
+        }
+        urls
+    }
+
+    #[tokio::test]
+    async fn test_replace_pools() {
+        let manager = Manager::new(1);
+        let retired = manager
+            .replace_pools(vec![pool("drain://a"), pool("drain://b")], None, true)
+            .await
+            .expect("BUG: cannot replace pools");
+        assert!(retired.is_empty());
+
+        let group = manager
+            .get_default_group()
+            .await
+            .expect("BUG: missing default group");
+        let old_clients = group.get_clients().await;
+        assert_eq!(old_clients.len(), 2);
+
+        let retired = manager
+            .replace_pools(vec![pool("drain://c"), pool("drain://b")], None, true)
+            .await
+            .expect("BUG: cannot replace pools");
+        assert_eq!(
+            client_urls(&group).await,
+            vec!["drain://user@c".to_string(), "drain://user@b".to_string()]
+        );
+
+        // Connection to unchanged pool is kept
+        let new_clients = group.get_clients().await;
+        assert!(Arc::ptr_eq(&old_clients[1], &new_clients[1]));
+        assert!(new_clients[0].is_enabled());
+        assert!(new_clients[1].is_enabled());
+
+        // Removed pool is retired but still connected
+        assert_eq!(retired.len(), 1);
+        assert!(Arc::ptr_eq(&retired[0], &old_clients[0]));
+        assert!(retired[0].is_enabled());
+        assert_eq!(group.retired_client_handles.lock().await.len(), 1);
+
+        // Disabling the pool does not recreate the client
+        let mut disabled = pool("drain://c");
+        disabled.enabled = Some(false);
+        let retired = manager
+            .replace_pools(vec![disabled, pool("drain://b")], None, true)
+            .await
+            .expect("BUG: cannot replace pools");
+        assert!(retired.is_empty());
+        let clients = group.get_clients().await;
+        assert!(Arc::ptr_eq(&new_clients[0], &clients[0]));
+        assert!(!clients[0].is_enabled());
+        assert!(!clients[0].descriptor().await.enabled);
+    }
+
+    #[tokio::test]
+    async fn test_pool_health() {
+        let manager = Manager::new(1);
+        let mut disabled = pool("drain://b");
+        disabled.enabled = Some(false);
+        manager
+            .replace_pools(vec![pool("drain://a"), disabled], None, true)
+            .await
+            .expect("BUG: cannot replace pools");
+
+        let pool_health = manager.pool_health().await;
+        assert_eq!(pool_health.len(), 2);
+        assert_eq!(pool_health[0].url, "drain://user@a");
+        assert_ne!(pool_health[0].state, ConnectionState::Down);
+        assert_eq!(pool_health[1].state, ConnectionState::Down);
+        for health in pool_health.iter() {
+            assert_eq!(health.last_job_age, None);
+            assert_eq!(health.submit_success_rate, None);
+            assert_eq!(health.ping_latency, None);
+        }
+
+        // simulate job and share submissions of the enabled pool
+        let group = manager
+            .get_default_group()
+            .await
+            .expect("BUG: missing default group");
+        let clients = group.get_clients().await;
+        let client_stats = clients[0].stats();
+        let now = Instant::now();
+        client_stats.last_job().touch(now);
+        let target = Default::default();
+        for _ in 0..3 {
+            client_stats.accepted().account_solution(&target, now).await;
+        }
+        client_stats.rejected().account_solution(&target, now).await;
+
+        let pool_health = manager.pool_health().await;
+        assert!(pool_health[0].last_job_age.is_some());
+        assert_eq!(pool_health[0].submit_success_rate, Some(0.75));
+        assert_eq!(pool_health[1].submit_success_rate, None);
+    }
+
+    #[tokio::test]
+    async fn test_replace_pools_invalid() {
+        let manager = Manager::new(1);
+        manager
+            .replace_pools(vec![pool("drain://a")], None, true)
+            .await
+            .expect("BUG: cannot replace pools");
+        // Whole set is rejected when any pool is invalid
+        assert!(manager
+            .replace_pools(vec![pool("drain://b"), pool("invalid")], None, true)
+            .await
+            .is_err());
+        let group = manager
+            .get_default_group()
+            .await
+            .expect("BUG: missing default group");
+        assert_eq!(
+            client_urls(&group).await,
+            vec!["drain://user@a".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_vardiff_config() {
+        let get_vardiff_config = |url: &str| {
+            let descriptor =
+                ClientDescriptor::create(url, &ClientUserInfo::new("user", None), true)
+                    .expect("BUG: cannot parse URL");
+            get_vardiff_config(&descriptor).map(|config| config.shares_per_minute)
+        };
+        assert_eq!(get_vardiff_config("drain://a"), None);
+        assert_eq!(get_vardiff_config("drain://a#vardiff=30"), Some(30.0));
+        assert_eq!(
+            get_vardiff_config("drain://a#log-rejected,vardiff=5,x"),
+            Some(5.0)
+        );
+        assert_eq!(get_vardiff_config("drain://a#vardiff=0"), None);
+        assert_eq!(get_vardiff_config("drain://a#vardiff=fast"), None);
+    }
+
+    #[tokio::test]
+    async fn test_drain() {
+        let manager = Manager::new(1);
+        manager
+            .replace_pools(vec![pool("drain://a")], None, true)
+            .await
+            .expect("BUG: cannot replace pools");
+        let clients = manager
+            .get_default_group()
+            .await
+            .expect("BUG: missing default group")
+            .get_clients()
+            .await;
+        let submissions = clients[0].stats().pending_submissions();
+        submissions.set(1, 1);
+        assert_eq!(manager.pending_submissions().await, 2);
+
+        let drain = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.drain().await }
+        });
+        // The client is kept enabled until its submissions are done
+        delay_for(DRAIN_POLL_INTERVAL * 2).await;
+        assert!(clients[0].is_enabled());
+
+        submissions.set(0, 0);
+        drain
+            .timeout(DRAIN_POLL_INTERVAL * 10)
+            .await
+            .expect("BUG: drain hangs")
+            .expect("BUG: drain has panicked");
+        assert!(!clients[0].is_enabled());
+    }
 }
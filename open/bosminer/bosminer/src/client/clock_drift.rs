@@ -0,0 +1,187 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Detection of drift between the time of jobs from the remote server and the system clock
+
+use ii_logging::macros::*;
+
+use crate::error;
+
+use std::sync::Arc;
+use std::time;
+
+/// Compares time of each received job with the system time at its arrival. Jobs carry the time
+/// of the pool so a large difference means that the clock of one side is wrong. The ntime of
+/// submitted solutions is always derived from the job so it is kept within the valid window
+/// regardless of the system clock, but anything relying on local time (logs, statistics, time
+/// based expiry) is skewed and it is worth to report it to the operator.
+#[derive(Debug)]
+pub struct ClockDrift {
+    /// Smallest difference which is reported (`None` disables the detection)
+    max_drift: Option<time::Duration>,
+    /// Offset of job time from system time in seconds measured at the last job (positive when
+    /// the jobs are ahead of the system clock)
+    offset: Option<i64>,
+    alert_raised: bool,
+    error_registry: Option<(Arc<error::ErrorRegistry>, String)>,
+}
+
+impl ClockDrift {
+    /// Default tolerated difference which covers usual ntime rolling done by pools
+    pub const DEFAULT_MAX_DRIFT: time::Duration = time::Duration::from_secs(10 * 60);
+
+    pub fn new(max_drift: time::Duration) -> Self {
+        Self {
+            max_drift: Some(max_drift),
+            offset: None,
+            alert_raised: false,
+            error_registry: None,
+        }
+    }
+
+    /// Drift is never reported
+    pub fn disabled() -> Self {
+        Self {
+            max_drift: None,
+            ..Self::new(Default::default())
+        }
+    }
+
+    /// Report detected drift to the `error_registry` on behalf of `source`
+    pub fn set_error_registry<S: Into<String>>(
+        &mut self,
+        error_registry: Arc<error::ErrorRegistry>,
+        source: S,
+    ) {
+        self.error_registry = Some((error_registry, source.into()));
+    }
+
+    #[inline]
+    pub fn offset(&self) -> Option<i64> {
+        self.offset
+    }
+
+    #[inline]
+    pub fn is_drifting(&self) -> bool {
+        self.alert_raised
+    }
+
+    /// Account job with `job_time` (seconds since Unix epoch) which has arrived at `now` and
+    /// return `true` when the difference exceeds the tolerated drift
+    pub fn job_received(&mut self, job_time: u32, now: time::SystemTime) -> bool {
+        let system_time = match now.duration_since(time::UNIX_EPOCH) {
+            Ok(system_time) => system_time.as_secs() as i64,
+            // system clock before the epoch is surely wrong
+            Err(_) => 0,
+        };
+        let offset = job_time as i64 - system_time;
+        self.offset = Some(offset);
+
+        let max_drift = match self.max_drift {
+            Some(max_drift) => max_drift.as_secs(),
+            None => return false,
+        };
+        let drifting = offset.abs() as u64 > max_drift;
+        if drifting && !self.alert_raised {
+            self.raise_alert(offset);
+        } else if !drifting && self.alert_raised {
+            info!("Clock drift: job time is in sync with system time again");
+            self.alert_raised = false;
+        }
+        drifting
+    }
+
+    fn raise_alert(&mut self, offset: i64) {
+        self.alert_raised = true;
+        let message = format!(
+            "job time is {} s {} system time",
+            offset.abs(),
+            if offset > 0 { "ahead of" } else { "behind" }
+        );
+        warn!("Clock drift: {}", message);
+        if let Some((error_registry, source)) = self.error_registry.as_ref() {
+            error_registry.push(
+                error::ConditionKind::ClockDrift,
+                error::Severity::Warning,
+                source.as_str(),
+                message,
+            );
+        }
+    }
+}
+
+impl Default for ClockDrift {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAX_DRIFT)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const JOB_TIME: u32 = 1_600_000_000;
+
+    fn system_time(offset: i64) -> time::SystemTime {
+        time::UNIX_EPOCH + time::Duration::from_secs((JOB_TIME as i64 + offset) as u64)
+    }
+
+    #[test]
+    fn test_drift_detected() {
+        let error_registry = Arc::new(error::ErrorRegistry::new());
+        let mut clock_drift = ClockDrift::new(time::Duration::from_secs(600));
+        clock_drift.set_error_registry(error_registry.clone(), "pool");
+
+        // usual difference caused by latency and ntime rolling
+        assert!(!clock_drift.job_received(JOB_TIME, system_time(-30)));
+        assert_eq!(clock_drift.offset(), Some(30));
+        assert!(error_registry.list().is_empty());
+
+        // system clock is a day behind
+        assert!(clock_drift.job_received(JOB_TIME, system_time(-86400)));
+        assert_eq!(clock_drift.offset(), Some(86400));
+        assert!(clock_drift.is_drifting());
+        let conditions = error_registry.list();
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(conditions[0].kind, error::ConditionKind::ClockDrift);
+        assert_eq!(conditions[0].source, "pool");
+
+        // persisting drift is reported just once
+        assert!(clock_drift.job_received(JOB_TIME + 30, system_time(-86370)));
+        assert_eq!(error_registry.list()[0].count, 1);
+
+        // clock fixed and broken again in the other direction
+        assert!(!clock_drift.job_received(JOB_TIME, system_time(0)));
+        assert!(!clock_drift.is_drifting());
+        assert!(clock_drift.job_received(JOB_TIME, system_time(3600)));
+        assert_eq!(clock_drift.offset(), Some(-3600));
+        assert_eq!(error_registry.list()[0].count, 2);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut clock_drift = ClockDrift::disabled();
+        assert!(!clock_drift.job_received(JOB_TIME, system_time(-86400)));
+        assert_eq!(clock_drift.offset(), Some(86400));
+        assert!(!clock_drift.is_drifting());
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Protection against pools which set difficulty below the practical minimum of the hardware
+
+/// Minimal difficulty of shares passed to the pool. Hardware which is able to find solutions at
+/// much lower difficulty than the pool sets would flood the submission pipeline, so solutions are
+/// filtered internally by a harder target instead. The pool credits every share at its own
+/// difficulty so the floor drops credited work and it is used only when configured explicitly.
+#[derive(Debug, Clone)]
+pub struct DifficultyFloor {
+    /// Pool difficulty below which internal filter is applied (`None` disables the floor)
+    min_difficulty: Option<u64>,
+    /// Target corresponding to `min_difficulty`
+    min_target: Option<ii_bitcoin::Target>,
+}
+
+impl DifficultyFloor {
+    /// Highest rate of shares per second which the submission pipeline is expected to sustain
+    pub const MAX_SHARE_RATE: f64 = 1.0;
+
+    /// Derive practical minimum of pool difficulty for hardware with `nominal_hashrate` which
+    /// reports only solutions meeting `backend_target`. Easier pool target cannot be met exactly
+    /// by the hardware and lower difficulty than the one given by `MAX_SHARE_RATE` would flood
+    /// the submission pipeline.
+    pub fn hardware_minimum(
+        nominal_hashrate: ii_bitcoin::HashesUnit,
+        backend_target: &ii_bitcoin::Target,
+    ) -> u64 {
+        // every share at difficulty 1 requires 2^32 hashes on average
+        let rate_difficulty = nominal_hashrate.into_hashes().into_f64()
+            / (Self::MAX_SHARE_RATE * (1u64 << 32) as f64);
+        (rate_difficulty.ceil() as u64)
+            .max(backend_target.get_difficulty_u64())
+            .max(1)
+    }
+
+    pub fn new(min_difficulty: u64) -> Self {
+        Self {
+            min_difficulty: Some(min_difficulty),
+            min_target: Some(ii_bitcoin::Target::from_pool_difficulty(
+                min_difficulty as usize,
+            )),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            min_difficulty: None,
+            min_target: None,
+        }
+    }
+
+    #[inline]
+    pub fn min_difficulty(&self) -> Option<u64> {
+        self.min_difficulty
+    }
+
+    /// Check if the pool `target` is easier than the floor and has to be filtered internally
+    pub fn is_engaged(&self, target: &ii_bitcoin::Target) -> bool {
+        self.min_target
+            .map_or(false, |min_target| *target > min_target)
+    }
+
+    /// Return target used for filtering of solutions sent to the pool which requires `target`
+    pub fn filter(&self, target: ii_bitcoin::Target) -> ii_bitcoin::Target {
+        match self.min_target {
+            Some(min_target) if target > min_target => min_target,
+            _ => target,
+        }
+    }
+}
+
+impl Default for DifficultyFloor {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_difficulty_floor() {
+        let floor = DifficultyFloor::new(1024);
+        let min_target = ii_bitcoin::Target::from_pool_difficulty(1024);
+
+        // extremely low difficulty is replaced by the floor
+        let low_target = ii_bitcoin::Target::from_pool_difficulty_f64(0.001);
+        assert!(floor.is_engaged(&low_target));
+        assert!(floor.filter(low_target) == min_target);
+
+        // regular pool difficulty is kept intact
+        for &difficulty in [1024, 65536].iter() {
+            let target = ii_bitcoin::Target::from_pool_difficulty(difficulty);
+            assert!(!floor.is_engaged(&target));
+            assert!(floor.filter(target) == target);
+        }
+    }
+
+    #[test]
+    fn test_hardware_minimum() {
+        let backend_target = ii_bitcoin::Target::from_pool_difficulty(64);
+
+        // slow hardware is limited by its own target filter
+        let hashrate = ii_bitcoin::HashesUnit::GigaHashes(1.0);
+        assert_eq!(
+            DifficultyFloor::hardware_minimum(hashrate, &backend_target),
+            64
+        );
+
+        // fast hardware is limited by the rate of submitted shares
+        let hashrate = ii_bitcoin::HashesUnit::TeraHashes(14.0);
+        assert_eq!(
+            DifficultyFloor::hardware_minimum(hashrate, &backend_target),
+            3260
+        );
+    }
+
+    #[test]
+    fn test_difficulty_floor_disabled() {
+        let floor = DifficultyFloor::disabled();
+        let target = ii_bitcoin::Target::from_pool_difficulty_f64(0.001);
+        assert_eq!(floor.min_difficulty(), None);
+        assert!(!floor.is_engaged(&target));
+        assert!(floor.filter(target) == target);
+    }
+}
@@ -267,10 +267,8 @@ impl Client {
 
     async fn account_solution(&self, solution: work::Solution) {
         let now = std::time::Instant::now();
-        self.stats
-            .accepted
-            .account_solution(&solution.job_target(), now)
-            .await;
+        solution.resolve(work::SubmitOutcome::Accepted);
+        self.stats.account_accepted(&solution, now).await;
     }
 
     async fn main_loop(self: Arc<Self>) -> error::Result<()> {
@@ -0,0 +1,131 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Aggregated health of pool connections intended for dashboards
+
+use crate::sync;
+
+use std::time;
+
+/// Simplified state of the connection to a pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Client is connected and the pool is able to send jobs
+    Connected,
+    /// Connection is being established or restored after a failure
+    Reconnecting,
+    /// Client is disabled, stopped or its connection has failed
+    Down,
+}
+
+impl ConnectionState {
+    /// Derive the connection state from the `status` of the client. Disabled clients are always
+    /// reported as down even when they are still being stopped.
+    pub fn new(status: sync::Status, enabled: bool) -> Self {
+        if !enabled {
+            return ConnectionState::Down;
+        }
+        match status {
+            sync::Status::Running => ConnectionState::Connected,
+            sync::Status::Starting
+            | sync::Status::Retrying
+            | sync::Status::Restarting
+            | sync::Status::Recovering => ConnectionState::Reconnecting,
+            sync::Status::Created
+            | sync::Status::Stopping
+            | sync::Status::Failing
+            | sync::Status::Declining
+            | sync::Status::Stopped
+            | sync::Status::Failed => ConnectionState::Down,
+        }
+    }
+}
+
+/// Fraction of submitted shares accepted by the pool (`None` until the pool acknowledges any)
+pub fn submit_success_rate(accepted: u64, rejected: u64) -> Option<f64> {
+    match accepted + rejected {
+        0 => None,
+        total => Some(accepted as f64 / total as f64),
+    }
+}
+
+/// Consolidated view of reconnection and submission metrics of a single pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolHealth {
+    /// Full URL of the pool including the user
+    pub url: String,
+    pub state: ConnectionState,
+    /// Time elapsed since the pool sent the most recent valid job
+    pub last_job_age: Option<time::Duration>,
+    /// Fraction of shares accepted by the pool (see `submit_success_rate`)
+    pub submit_success_rate: Option<f64>,
+    /// Smoothed time between submission of a share and its acknowledgement
+    pub ping_latency: Option<time::Duration>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_connection_state() {
+        assert_eq!(
+            ConnectionState::new(sync::Status::Running, true),
+            ConnectionState::Connected
+        );
+        for &status in [
+            sync::Status::Starting,
+            sync::Status::Retrying,
+            sync::Status::Restarting,
+            sync::Status::Recovering,
+        ]
+        .iter()
+        {
+            assert_eq!(
+                ConnectionState::new(status, true),
+                ConnectionState::Reconnecting
+            );
+        }
+        for &status in [
+            sync::Status::Created,
+            sync::Status::Failing,
+            sync::Status::Failed,
+            sync::Status::Stopped,
+        ]
+        .iter()
+        {
+            assert_eq!(ConnectionState::new(status, true), ConnectionState::Down);
+        }
+        // disabled client is down regardless of its status
+        assert_eq!(
+            ConnectionState::new(sync::Status::Running, false),
+            ConnectionState::Down
+        );
+    }
+
+    #[test]
+    fn test_submit_success_rate() {
+        assert_eq!(submit_success_rate(0, 0), None);
+        assert_eq!(submit_success_rate(3, 1), Some(0.75));
+        assert_eq!(submit_success_rate(0, 2), Some(0.0));
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Expiry of the current job when the remote server stops sending new ones
+
+use std::time;
+
+/// Tracks arrival of the most recent job. When no new job arrives within configured interval the
+/// current job is considered expired and its solutions should not be submitted because they would
+/// be most likely rejected as stale anyway.
+#[derive(Debug)]
+pub struct JobExpiry {
+    /// Maximal age of a job (`None` disables the expiry)
+    interval: Option<time::Duration>,
+    /// Time of the most recent job
+    last_job: Option<time::Instant>,
+}
+
+impl JobExpiry {
+    pub fn new(interval: time::Duration) -> Self {
+        Self {
+            interval: Some(interval),
+            last_job: None,
+        }
+    }
+
+    /// Jobs never expire
+    pub fn disabled() -> Self {
+        Self {
+            interval: None,
+            last_job: None,
+        }
+    }
+
+    #[inline]
+    pub fn interval(&self) -> Option<time::Duration> {
+        self.interval
+    }
+
+    /// Restart the expiry timer because a new job has arrived at `now`
+    pub fn job_received(&mut self, now: time::Instant) {
+        self.last_job = Some(now);
+    }
+
+    /// Check if the most recent job has been already expired at `now`. There is nothing to expire
+    /// until the first job arrives.
+    pub fn is_expired(&self, now: time::Instant) -> bool {
+        match (self.interval, self.last_job) {
+            (Some(interval), Some(last_job)) => now.saturating_duration_since(last_job) >= interval,
+            _ => false,
+        }
+    }
+}
+
+impl Default for JobExpiry {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_job_expiry() {
+        let interval = time::Duration::from_secs(30);
+        let mut expiry = JobExpiry::new(interval);
+        let now = time::Instant::now();
+
+        assert!(!expiry.is_expired(now + interval * 2));
+
+        expiry.job_received(now);
+        assert!(!expiry.is_expired(now));
+        assert!(!expiry.is_expired(now + interval - time::Duration::from_millis(1)));
+        // the job ages past the expiry
+        assert!(expiry.is_expired(now + interval));
+        assert!(expiry.is_expired(now + interval * 2));
+
+        // new job restarts the timer
+        expiry.job_received(now + interval * 2);
+        assert!(!expiry.is_expired(now + interval * 2));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut expiry = JobExpiry::disabled();
+        let now = time::Instant::now();
+
+        expiry.job_received(now);
+        assert_eq!(expiry.interval(), None);
+        assert!(!expiry.is_expired(now + time::Duration::from_secs(3600)));
+    }
+}
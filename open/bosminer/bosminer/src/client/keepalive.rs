@@ -0,0 +1,134 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Keepalive of idle connections to the remote server
+
+use std::time;
+
+/// Tracks outgoing traffic of a connection. When nothing has been sent for the configured
+/// interval a keepalive message should be sent to prevent the server from dropping the
+/// connection as idle.
+#[derive(Debug)]
+pub struct Keepalive {
+    /// Maximal idle period of the connection (`None` disables the keepalive)
+    interval: Option<time::Duration>,
+    /// Time of the most recent outgoing message
+    last_traffic: time::Instant,
+}
+
+impl Keepalive {
+    pub fn new(interval: time::Duration, now: time::Instant) -> Self {
+        Self {
+            interval: Some(interval),
+            last_traffic: now,
+        }
+    }
+
+    /// Keepalive messages are never sent
+    pub fn disabled() -> Self {
+        Self {
+            interval: None,
+            last_traffic: time::Instant::now(),
+        }
+    }
+
+    #[inline]
+    pub fn interval(&self) -> Option<time::Duration> {
+        self.interval
+    }
+
+    /// Restart the idle timer because a message has been sent at `now`
+    pub fn traffic_sent(&mut self, now: time::Instant) {
+        self.last_traffic = now;
+    }
+
+    /// Remaining time at `now` until the keepalive is due
+    pub fn time_to_keepalive(&self, now: time::Instant) -> Option<time::Duration> {
+        self.interval
+            .map(|interval| (self.last_traffic + interval).saturating_duration_since(now))
+    }
+
+    /// Check if the connection has been idle for the whole interval at `now`. The idle timer is
+    /// restarted when the keepalive is due because the caller is expected to send it.
+    pub fn poll(&mut self, now: time::Instant) -> bool {
+        match self.time_to_keepalive(now) {
+            Some(remaining) if remaining == time::Duration::from_secs(0) => {
+                self.traffic_sent(now);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_keepalive_idle() {
+        let interval = time::Duration::from_secs(30);
+        let now = time::Instant::now();
+        let mut keepalive = Keepalive::new(interval, now);
+
+        assert_eq!(keepalive.time_to_keepalive(now), Some(interval));
+        assert!(!keepalive.poll(now + interval - time::Duration::from_millis(1)));
+        // idle connection receives keepalive once per interval
+        assert!(keepalive.poll(now + interval));
+        assert!(!keepalive.poll(now + interval));
+        assert_eq!(keepalive.time_to_keepalive(now + interval), Some(interval));
+        assert!(keepalive.poll(now + interval * 2));
+    }
+
+    #[test]
+    fn test_keepalive_traffic() {
+        let interval = time::Duration::from_secs(30);
+        let now = time::Instant::now();
+        let mut keepalive = Keepalive::new(interval, now);
+
+        // regular messages sent more often than the interval suppress the keepalive
+        for i in 1..10 {
+            let sent = now + interval / 2 * i;
+            assert!(!keepalive.poll(sent));
+            keepalive.traffic_sent(sent);
+        }
+        let last_sent = now + interval / 2 * 9;
+        assert!(!keepalive.poll(last_sent + interval / 2));
+        assert!(keepalive.poll(last_sent + interval));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut keepalive = Keepalive::disabled();
+        let now = time::Instant::now();
+
+        assert_eq!(keepalive.interval(), None);
+        assert_eq!(keepalive.time_to_keepalive(now), None);
+        assert!(!keepalive.poll(now + time::Duration::from_secs(3600)));
+    }
+}
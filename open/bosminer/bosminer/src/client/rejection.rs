@@ -0,0 +1,259 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Detailed logging of shares rejected by remote server which provides enough context for
+//! resolving disputes with a pool
+
+use ii_logging::macros::*;
+
+use crate::work;
+
+use ii_bitcoin::HashTrait as _;
+
+use std::fmt;
+use std::time;
+
+/// Reason of share rejection as stated by the remote server
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectReason {
+    /// Share for a job which is not valid anymore
+    Stale,
+    /// Share for a job which the server doesn't know (anymore). It usually means that the job
+    /// has been invalidated earlier than we noticed.
+    JobNotFound,
+    /// Share has been already submitted
+    Duplicate,
+    /// Share does not meet the pool target
+    LowDifficulty,
+    /// Unrecognized reason
+    Other(String),
+}
+
+impl RejectReason {
+    /// Parse error code received in `SubmitSharesError`
+    /// The numeric codes are used by stratum V1 servers and they are passed through the V2->V1
+    /// translation in the form "ShareRjct:StratumError(<code>, ..."
+    pub fn from_code(code: &str) -> Self {
+        let lowercase_code = code.to_lowercase();
+        if lowercase_code.contains("job not found") || code.contains("(21,") {
+            RejectReason::JobNotFound
+        } else if lowercase_code.contains("stale") {
+            RejectReason::Stale
+        } else if lowercase_code.contains("duplicate") || code.contains("(22,") {
+            RejectReason::Duplicate
+        } else if lowercase_code.contains("low difficulty") || code.contains("(23,") {
+            RejectReason::LowDifficulty
+        } else {
+            RejectReason::Other(code.to_string())
+        }
+    }
+
+    /// The share has been rejected only because its job has been already invalidated
+    pub fn is_stale(&self) -> bool {
+        match self {
+            RejectReason::Stale | RejectReason::JobNotFound => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::Stale => write!(f, "stale"),
+            RejectReason::JobNotFound => write!(f, "job not found"),
+            RejectReason::Duplicate => write!(f, "duplicate"),
+            RejectReason::LowDifficulty => write!(f, "low difficulty"),
+            RejectReason::Other(code) => write!(f, "{}", code),
+        }
+    }
+}
+
+/// Structured record describing the rejected share
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub reason: RejectReason,
+    pub version: u32,
+    pub previous_hash: ii_bitcoin::DHash,
+    pub merkle_root: ii_bitcoin::DHash,
+    pub time: u32,
+    pub bits: u32,
+    pub nonce: u32,
+    pub hash: ii_bitcoin::DHash,
+    /// Difficulty computed from the hash of the share
+    pub share_difficulty: f64,
+    /// Difficulty required by the job
+    pub job_difficulty: f64,
+    /// Our own view of staleness (the job has been already invalidated)
+    pub stale: bool,
+    /// Time elapsed from reception of the solution from the backend
+    pub age: time::Duration,
+}
+
+impl Record {
+    pub fn new(solution: &work::Solution, reason: RejectReason) -> Self {
+        let header = solution.get_block_header();
+        let hash = *solution.hash();
+        let network_params = solution.network_params();
+        Self {
+            reason,
+            version: header.version,
+            previous_hash: ii_bitcoin::DHash::from_inner(header.previous_hash),
+            merkle_root: ii_bitcoin::DHash::from_inner(header.merkle_root),
+            time: header.time,
+            bits: header.bits,
+            nonce: header.nonce,
+            hash,
+            share_difficulty: network_params.difficulty(&hash.into()),
+            job_difficulty: network_params.difficulty(solution.job_target()),
+            stale: !solution.has_valid_job(),
+            age: solution.timestamp().elapsed(),
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reason=\"{}\" version={:08x} prev_hash={} merkle_root={} ntime={:08x} nbits={:08x} \
+             nonce={:08x} hash={} share_diff={} job_diff={} stale={} age_ms={}",
+            self.reason,
+            self.version,
+            self.previous_hash,
+            self.merkle_root,
+            self.time,
+            self.bits,
+            self.nonce,
+            self.hash,
+            self.share_difficulty,
+            self.job_difficulty,
+            self.stale,
+            self.age.as_millis()
+        )
+    }
+}
+
+/// Logger of rejected shares which can be enabled per client
+#[derive(Debug)]
+pub struct Logger {
+    enabled: bool,
+}
+
+impl Logger {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    /// Log full record of rejected `solution` when the logger is enabled
+    pub fn log(&self, solution: &work::Solution, reason: RejectReason) -> Option<Record> {
+        if !self.enabled {
+            return None;
+        }
+        let record = Record::new(solution, reason);
+        info!("Stratum: rejected share: {}", record);
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+    use crate::test_utils::TestBlockBuilder as _;
+
+    #[test]
+    fn test_reject_reason() {
+        assert_eq!(
+            RejectReason::from_code("ShareRjct:StratumError(21, \"Job"),
+            RejectReason::JobNotFound
+        );
+        assert_eq!(
+            RejectReason::from_code("Job not found"),
+            RejectReason::JobNotFound
+        );
+        assert_eq!(RejectReason::from_code("stale-share"), RejectReason::Stale);
+        assert!(RejectReason::JobNotFound.is_stale());
+        assert!(RejectReason::Stale.is_stale());
+        assert!(!RejectReason::LowDifficulty.is_stale());
+        assert_eq!(RejectReason::from_code("Stale share"), RejectReason::Stale);
+        assert_eq!(
+            RejectReason::from_code("ShareRjct:StratumError(22, \"Dup"),
+            RejectReason::Duplicate
+        );
+        assert_eq!(
+            RejectReason::from_code("ShareRjct:StratumError(23, \"Low"),
+            RejectReason::LowDifficulty
+        );
+        assert_eq!(
+            RejectReason::from_code("unknown"),
+            RejectReason::Other("unknown".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rejection_record() {
+        let block = &test_utils::TEST_BLOCKS[0];
+        let solution: work::Solution = block.into();
+
+        assert!(Logger::new(false)
+            .log(&solution, RejectReason::Duplicate)
+            .is_none());
+        let record = Logger::new(true)
+            .log(&solution, RejectReason::Duplicate)
+            .expect("BUG: missing record");
+
+        assert_eq!(record.reason, RejectReason::Duplicate);
+        assert_eq!(record.version, block.version);
+        assert_eq!(record.previous_hash, block.previous_hash);
+        assert_eq!(record.merkle_root, block.merkle_root);
+        assert_eq!(record.time, block.time);
+        assert_eq!(record.bits, block.bits);
+        assert_eq!(record.nonce, block.nonce);
+        assert_eq!(record.hash, block.hash);
+        assert!(record.share_difficulty >= record.job_difficulty);
+
+        let line = record.to_string();
+        assert!(line.contains("reason=\"duplicate\""));
+        assert!(line.contains(&format!("nonce={:08x}", block.nonce)));
+        assert!(line.contains(&format!("merkle_root={}", block.merkle_root)));
+    }
+
+    #[test]
+    fn test_rejection_record_fractional_difficulty() {
+        let block = &test_utils::TEST_BLOCKS[0];
+        let hash_difficulty = ii_bitcoin::Target::from(block.hash).get_difficulty_f64();
+
+        for difficulty in [0.5, 3.0, 16384.5].iter() {
+            let target = ii_bitcoin::Target::from_pool_difficulty_f64(*difficulty);
+            let solution: work::Solution = (&block.change_target(target)).into();
+            let record = Logger::new(true)
+                .log(&solution, RejectReason::LowDifficulty)
+                .expect("BUG: missing record");
+            assert!((record.job_difficulty - difficulty).abs() < 1e-6);
+            assert_eq!(record.share_difficulty, hash_difficulty);
+            assert!(record
+                .to_string()
+                .contains(&format!("job_diff={}", record.job_difficulty)));
+        }
+    }
+}
@@ -20,17 +20,26 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+use ii_logging::macros::*;
+
 use crate::client;
+use crate::stats;
 use crate::sync::event;
 use crate::work;
 
 use futures::channel::mpsc;
 use futures::lock::{Mutex, MutexGuard};
-use ii_async_compat::{futures, FutureExt};
+use futures::stream::StreamExt;
+use ii_async_compat::{futures, tokio, FutureExt};
 
 use std::sync::Arc;
 use std::time;
 
+/// Amount of generated work after which the older history starts to be forgotten. Quotas are
+/// then enforced within this window instead of the whole uptime so that a group which has been
+/// disconnected for a long time does not get all work after reconnection.
+const GENERATED_WORK_WINDOW: u64 = 1 << 24;
+
 /// This struct cannot be shared and it is possible to use mutable references. However, the
 /// client handle is shared object with interior mutability scheduler::ClientHandle. It solves
 /// many synchronization problems.
@@ -171,6 +180,12 @@ impl GroupHandle {
     pub fn reset_generated_work(&mut self) {
         self.generated_work = 0;
     }
+
+    /// Reduce weight of the old generated work when the scheduling window is full
+    #[inline]
+    fn decay_generated_work(&mut self) {
+        self.generated_work /= 2;
+    }
 }
 
 enum ActiveClient {
@@ -220,30 +235,65 @@ impl PartialEq<Arc<client::Handle>> for ActiveClient {
     }
 }
 
+/// Instruction of an external profit-switching controller which decides what pool (coin) should
+/// be mined. The pool is identified by name of its group which is kept when the pools are
+/// reconfigured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwitchSignal {
+    /// Mine exclusively for the group as long as it has some running client
+    Group(String),
+    /// Return to scheduling by configured quotas and share ratios
+    Release,
+}
+
 /// Responsible for selecting and switching jobs
 struct JobDispatcher {
     active_client: ActiveClient,
     group_registry: Arc<Mutex<client::GroupRegistry>>,
+    /// Group selected by the profit-switching controller
+    directed_group: Option<String>,
+    /// Donation period lasts as long as a donation client is active
+    donation: Arc<stats::Donation>,
 }
 
 impl JobDispatcher {
     fn new(
         engine_sender: work::EngineSender,
         group_registry: Arc<Mutex<client::GroupRegistry>>,
+        donation: Arc<stats::Donation>,
     ) -> Self {
         Self {
             active_client: ActiveClient::None(Arc::new(engine_sender)),
             group_registry,
+            directed_group: None,
+            donation,
         }
     }
 
-    fn switch_client<T>(&mut self, next_client: T)
+    fn switch_client<T>(&mut self, next_client: T, cause: work::GenerationCause)
     where
         T: Into<Option<Arc<client::Handle>>>,
     {
-        match next_client.into() {
+        let next_client = next_client.into();
+        let now = time::Instant::now();
+        match next_client.as_ref() {
+            Some(next_client) if next_client.is_donation() => self.donation.start(now),
+            _ => self.donation.stop(now),
+        }
+        match next_client {
             Some(next_client) => {
                 if self.active_client != next_client {
+                    if let ActiveClient::Some(prev_client) = &self.active_client {
+                        debug!(
+                            "Work generation bumped by {:?} from '{}' to '{}'",
+                            cause, prev_client.node, next_client.node
+                        );
+                        next_client
+                            .node
+                            .client_stats()
+                            .generation_bumps()
+                            .account(cause);
+                    }
                     next_client
                         .engine_sender
                         .swap_sender(self.active_client.get_engine_sender());
@@ -259,6 +309,31 @@ impl JobDispatcher {
         }
     }
 
+    /// Select the candidate whose share ratio gets closest to its configured share ratio when it
+    /// is given the next `generated_work_delta` of work. Each candidate is described by a pair of
+    /// configured share ratio and work generated within the current window.
+    fn select_candidate(
+        candidates: &[(f64, u64)],
+        total_generated_work: u64,
+        generated_work_delta: u64,
+    ) -> Option<usize> {
+        let mut next_candidate = None;
+        for (idx, &(share_ratio, generated_work)) in candidates.iter().enumerate() {
+            let next_share_ratio = (generated_work + generated_work_delta) as f64
+                / (total_generated_work + generated_work_delta) as f64;
+            let next_error = (share_ratio - next_share_ratio).abs();
+            match next_candidate {
+                None => next_candidate = Some((idx, next_error)),
+                Some((_, min_error)) => {
+                    if min_error >= next_error {
+                        next_candidate = Some((idx, next_error));
+                    }
+                }
+            }
+        }
+        next_candidate.map(|(idx, _)| idx)
+    }
+
     async fn select_client(&self, generated_work_delta: u64) -> Option<Arc<client::Handle>> {
         let mut group_registry = self.group_registry.lock().await;
         if group_registry.is_empty() {
@@ -270,25 +345,47 @@ impl JobDispatcher {
             scheduler_group_handle.update_status().await;
             total_generated_work += scheduler_group_handle.generated_work;
         }
+        if total_generated_work > GENERATED_WORK_WINDOW {
+            // forget the history gradually so the ratio reflects only the recent window
+            total_generated_work = 0;
+            for scheduler_group_handle in group_registry.iter_mut() {
+                scheduler_group_handle.decay_generated_work();
+                total_generated_work += scheduler_group_handle.generated_work;
+            }
+        }
 
-        let mut next_client = None;
-        for scheduler_group_handle in group_registry.iter() {
-            let group_generated_work = scheduler_group_handle.generated_work;
-            let next_group_share_ratio = (group_generated_work + generated_work_delta) as f64
-                / (total_generated_work + generated_work_delta) as f64;
-            let next_error = (scheduler_group_handle.share_ratio - next_group_share_ratio).abs();
-            if let Some(active_client) = scheduler_group_handle.active_client.as_ref().cloned() {
-                match next_client {
-                    None => next_client = Some((active_client, next_error)),
-                    Some((_, min_error)) => {
-                        if min_error >= next_error {
-                            next_client = Some((active_client, next_error));
-                        }
-                    }
-                }
+        if let Some(directed_group) = self.directed_group.as_ref() {
+            // fall back to quotas while the directed group has no running client
+            let directed_client = group_registry
+                .iter()
+                .find(|scheduler_group_handle| {
+                    scheduler_group_handle.group_handle.descriptor.name == *directed_group
+                })
+                .and_then(|scheduler_group_handle| scheduler_group_handle.active_client.clone());
+            if directed_client.is_some() {
+                return directed_client;
             }
         }
-        next_client.map(|(next_client, _)| next_client)
+
+        let (clients, candidates): (Vec<_>, Vec<_>) = group_registry
+            .iter()
+            .filter_map(|scheduler_group_handle| {
+                scheduler_group_handle
+                    .active_client
+                    .as_ref()
+                    .map(|active_client| {
+                        (
+                            active_client.clone(),
+                            (
+                                scheduler_group_handle.share_ratio,
+                                scheduler_group_handle.generated_work,
+                            ),
+                        )
+                    })
+            })
+            .unzip();
+        Self::select_candidate(&candidates, total_generated_work, generated_work_delta)
+            .map(|idx| clients[idx].clone())
     }
 
     async fn schedule(&mut self, generated_work_delta: u64) {
@@ -302,7 +399,24 @@ impl JobDispatcher {
             _ => {}
         }
         if let Some(next_client) = self.select_client(generated_work_delta).await {
-            self.switch_client(next_client);
+            self.switch_client(next_client, work::GenerationCause::PoolFailover);
+        }
+    }
+
+    /// Follow instruction of the profit-switching controller immediately
+    async fn direct(&mut self, signal: SwitchSignal) {
+        self.directed_group = match signal {
+            SwitchSignal::Group(name) => Some(name),
+            SwitchSignal::Release => {
+                // work generated while switching does not count against the quotas
+                for scheduler_group_handle in self.group_registry.lock().await.iter_mut() {
+                    scheduler_group_handle.reset_generated_work();
+                }
+                None
+            }
+        };
+        if let Some(next_client) = self.select_client(0).await {
+            self.switch_client(next_client, work::GenerationCause::ProfitSwitch);
         }
     }
 }
@@ -313,6 +427,9 @@ pub struct JobExecutor {
     group_registry: Arc<Mutex<client::GroupRegistry>>,
     event_monitor: Mutex<Option<event::Monitor>>,
     dispatcher: Mutex<JobDispatcher>,
+    donation: Arc<stats::Donation>,
+    switch_sender: mpsc::UnboundedSender<SwitchSignal>,
+    switch_receiver: Mutex<Option<mpsc::UnboundedReceiver<SwitchSignal>>>,
 }
 
 impl JobExecutor {
@@ -323,6 +440,7 @@ impl JobExecutor {
         engine_sender: work::EngineSender,
         client_manager: client::Manager,
     ) -> Self {
+        let (switch_sender, switch_receiver) = mpsc::unbounded();
         Self {
             frontend,
             group_registry: client_manager.group_registry.clone(),
@@ -330,7 +448,27 @@ impl JobExecutor {
             dispatcher: Mutex::new(JobDispatcher::new(
                 engine_sender,
                 client_manager.group_registry,
+                client_manager.donation.clone(),
             )),
+            donation: client_manager.donation,
+            switch_sender,
+            switch_receiver: Mutex::new(Some(switch_receiver)),
+        }
+    }
+
+    /// Return channel through which an external profit-switching controller directs what group
+    /// is mined. The switch takes effect immediately with a new work generation.
+    pub fn switch_sender(&self) -> mpsc::UnboundedSender<SwitchSignal> {
+        self.switch_sender.clone()
+    }
+
+    async fn switch_task(
+        self: Arc<Self>,
+        mut switch_receiver: mpsc::UnboundedReceiver<SwitchSignal>,
+    ) {
+        while let Some(signal) = switch_receiver.next().await {
+            info!("Hub: profit switching signal {:?}", signal);
+            self.lock_dispatcher().await.direct(signal).await;
         }
     }
 
@@ -347,10 +485,27 @@ impl JobExecutor {
         self.group_registry.lock().await.find_client(solution).await
     }
 
+    /// Prepare `solution` for submission by its origin `client`. Solutions of donation pools are
+    /// accounted apart from the user's ones.
+    async fn origin_solution(
+        &self,
+        client: &client::Handle,
+        solution: &work::Solution,
+    ) -> work::Solution {
+        if client.is_donation() {
+            self.donation.account_solution(solution).await;
+            solution.donated()
+        } else {
+            solution.clone()
+        }
+    }
+
+    /// Return solution sender of the client which issued the job together with the solution
+    /// which should be sent to it
     pub async fn get_solution_sender(
         &self,
         solution: &work::Solution,
-    ) -> Option<mpsc::UnboundedSender<work::Solution>> {
+    ) -> Option<(mpsc::UnboundedSender<work::Solution>, work::Solution)> {
         let active_client = self.active_client().await;
 
         // solution receiver is probably active client which is work generated from
@@ -359,11 +514,93 @@ impl JobExecutor {
         if client.is_none() {
             client = self.find_client(&solution).await
         }
+        let client = client?;
         // return associated solution sender when matching client is found
-        client.map(|client| client.solution_sender.clone())
+        Some((
+            client.solution_sender.clone(),
+            self.origin_solution(&client, solution).await,
+        ))
+    }
+
+    /// Select index of endpoint for submission of block candidate from `(solo, latency)` pairs.
+    /// Endpoints connected directly to a full node (solo) are preferred to pools and then the one
+    /// with the lowest known latency is used. Ties are resolved by the order of endpoints.
+    fn select_block_endpoint(endpoints: &[(bool, Option<time::Duration>)]) -> Option<usize> {
+        endpoints
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (solo, latency))| (!solo, latency.is_none(), *latency))
+            .map(|(idx, _)| idx)
+    }
+
+    /// Return solution senders of clients which should receive block candidate `solution`. The
+    /// client which issued the job always receives the solution and it is also sent to the
+    /// fastest endpoint which is able to submit it to minimize risk of the block being orphaned.
+    /// Other clients can submit only the whole block so the solution is relayed just when its job
+    /// knows all transactions of the block. Each sender is paired with the solution which should
+    /// be sent to it (other clients than the origin receive a relayed copy).
+    pub async fn get_block_solution_senders(
+        &self,
+        solution: &work::Solution,
+    ) -> Vec<(mpsc::UnboundedSender<work::Solution>, work::Solution)> {
+        let origin = self.find_client(solution).await;
+        // the origin goes first so that it wins when latencies are equal
+        let mut endpoints: Vec<_> = origin.iter().cloned().collect();
+        let relayable = solution.serialize_block().is_some();
+        for client in self.group_registry.lock().await.get_clients().await {
+            if relayable
+                && client.node.accepts_foreign_blocks()
+                && !origin
+                    .as_ref()
+                    .map(|origin| Arc::ptr_eq(origin, &client))
+                    .unwrap_or(false)
+            {
+                endpoints.push(client);
+            }
+        }
+        let candidates: Vec<_> = endpoints
+            .iter()
+            .map(|client| {
+                (
+                    client.node.accepts_foreign_blocks(),
+                    client.node.submission_latency(),
+                )
+            })
+            .collect();
+
+        let mut solution_senders = vec![];
+        if let Some(origin) = origin.as_ref() {
+            solution_senders.push((
+                origin.solution_sender.clone(),
+                self.origin_solution(origin, solution).await,
+            ));
+        }
+        if let Some(idx) = Self::select_block_endpoint(&candidates) {
+            let fastest = &endpoints[idx];
+            if origin
+                .as_ref()
+                .map(|origin| !Arc::ptr_eq(origin, fastest))
+                .unwrap_or(true)
+            {
+                info!(
+                    "Hub: block candidate is also submitted to '{}'",
+                    fastest.node
+                );
+                solution_senders.push((fastest.solution_sender.clone(), solution.relayed()));
+            }
+        }
+        solution_senders
     }
 
     pub async fn run(self: Arc<Self>) {
+        let switch_receiver = self
+            .switch_receiver
+            .lock()
+            .await
+            .take()
+            .expect("BUG: missing switch receiver");
+        tokio::spawn(self.clone().switch_task(switch_receiver));
+
         let mut event_receiver = self
             .event_monitor
             .lock()
@@ -396,3 +633,330 @@ impl JobExecutor {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bosminer_config::{GroupDescriptor, LoadBalanceStrategy, PoolConfig};
+    use tokio::time::delay_for;
+
+    fn drain_client(name: &str) -> client::Handle {
+        let pool_config = PoolConfig {
+            enabled: None,
+            url: format!("drain://{}", name),
+            user: "user".to_string(),
+            password: None,
+        };
+        client::Handle::new(
+            client::create_descriptor(&pool_config, true).expect("BUG: invalid pool"),
+            None,
+            None,
+        )
+    }
+
+    fn switch_to(group_name: &str) -> SwitchSignal {
+        SwitchSignal::Group(group_name.to_string())
+    }
+
+    /// Check that the engine generates work from job of the client
+    fn is_work_of(engine: &work::DynEngine, client: &client::Handle) -> bool {
+        match engine.next_work() {
+            work::LoopState::Continue(work) | work::LoopState::Break(work) => work
+                .origin()
+                .upgrade()
+                .map(|origin| {
+                    Arc::ptr_eq(
+                        &origin.get_unique_ptr(),
+                        &client.node.clone().get_unique_ptr(),
+                    )
+                })
+                .unwrap_or(false),
+            work::LoopState::Exhausted => false,
+        }
+    }
+
+    /// Simulate scheduling of work between groups with given quotas. The dispatcher selects
+    /// a client for each `delta` of work which is then accounted as generated by that client.
+    /// Returns the amount of work generated by each group.
+    async fn simulate(quotas: &[usize], iterations: usize, delta: u64) -> Vec<u64> {
+        let manager = client::Manager::new(1);
+        let mut clients = vec![];
+        for (idx, &quota) in quotas.iter().enumerate() {
+            let name = format!("group{}", idx);
+            let group = manager
+                .create_group(GroupDescriptor::new(
+                    name.clone(),
+                    false,
+                    LoadBalanceStrategy::Quota(quota),
+                ))
+                .await
+                .expect("BUG: cannot create group");
+            clients.push(group.push_client(drain_client(&name)).await);
+        }
+        for client in clients.iter() {
+            while !client.is_running() {
+                delay_for(time::Duration::from_millis(10)).await;
+            }
+        }
+
+        let (engine_sender, _engine_receiver) = work::engine_channel(work::IgnoreEvents);
+        let dispatcher = JobDispatcher::new(
+            engine_sender,
+            manager.group_registry.clone(),
+            manager.donation(),
+        );
+        let mut generated_work = vec![0; quotas.len()];
+        for _ in 0..iterations {
+            let next_client = dispatcher
+                .select_client(delta)
+                .await
+                .expect("BUG: no client selected");
+            let idx = clients
+                .iter()
+                .position(|client| Arc::ptr_eq(client, &next_client))
+                .expect("BUG: unknown client selected");
+            next_client.node.client_stats().generated_work().add(delta);
+            generated_work[idx] += delta;
+        }
+        generated_work
+    }
+
+    #[tokio::test]
+    async fn test_quota_convergence() {
+        let generated_work = simulate(&[7, 3], 10_000, 100).await;
+        let total_generated_work = generated_work.iter().sum::<u64>() as f64;
+        let ratio = generated_work[0] as f64 / total_generated_work;
+        assert!((ratio - 0.7).abs() < 0.01, "unexpected ratio {}", ratio);
+
+        // the ratio is kept even when the history is decayed by the scheduling window
+        let delta = GENERATED_WORK_WINDOW / 100;
+        let generated_work = simulate(&[7, 3], 10_000, delta).await;
+        let total_generated_work = generated_work.iter().sum::<u64>() as f64;
+        let ratio = generated_work[0] as f64 / total_generated_work;
+        assert!((ratio - 0.7).abs() < 0.02, "unexpected ratio {}", ratio);
+    }
+
+    #[test]
+    fn test_select_candidate() {
+        assert_eq!(JobDispatcher::select_candidate(&[], 0, 100), None);
+        // the first work goes to the group with the highest share ratio
+        assert_eq!(
+            JobDispatcher::select_candidate(&[(0.7, 0), (0.3, 0)], 0, 100),
+            Some(0)
+        );
+        // and then to the group which is below its share ratio
+        assert_eq!(
+            JobDispatcher::select_candidate(&[(0.7, 100), (0.3, 0)], 100, 100),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_profit_switch() {
+        let manager = client::Manager::new(1);
+        let mut clients = vec![];
+        for name in ["a", "b"].iter() {
+            let group = manager
+                .create_group(GroupDescriptor::new(
+                    name.to_string(),
+                    false,
+                    LoadBalanceStrategy::Quota(1),
+                ))
+                .await
+                .expect("BUG: cannot create group");
+            clients.push(group.push_client(drain_client(name)).await);
+        }
+        // wait until both clients provide their first job
+        for client in clients.iter() {
+            while !client.is_running() || client.get_last_job().await.is_none() {
+                delay_for(time::Duration::from_millis(10)).await;
+            }
+        }
+
+        let (engine_sender, mut engine_receiver) = work::engine_channel(work::IgnoreEvents);
+        let mut dispatcher = JobDispatcher::new(
+            engine_sender,
+            manager.group_registry.clone(),
+            manager.donation(),
+        );
+        dispatcher.direct(switch_to("b")).await;
+        assert!(dispatcher.active_client == clients[1]);
+        let old_engine = engine_receiver.get_engine().await.expect("BUG: no engine");
+        assert!(is_work_of(&old_engine, &clients[1]));
+
+        // regular scheduling keeps the directed group
+        dispatcher.schedule(1000).await;
+        assert!(dispatcher.active_client == clients[1]);
+
+        dispatcher.direct(switch_to("a")).await;
+        assert!(dispatcher.active_client == clients[0]);
+        let engine = engine_receiver.get_engine().await.expect("BUG: no engine");
+        assert!(!Arc::ptr_eq(&engine, &old_engine));
+        assert!(is_work_of(&engine, &clients[0]));
+        assert_eq!(
+            clients[0]
+                .stats()
+                .generation_bumps()
+                .get(work::GenerationCause::ProfitSwitch),
+            1
+        );
+
+        // unknown group falls back to quotas
+        dispatcher.direct(switch_to("c")).await;
+        assert!(dispatcher.active_client.is_some());
+        dispatcher.direct(SwitchSignal::Release).await;
+        assert_eq!(dispatcher.directed_group, None);
+    }
+
+    #[tokio::test]
+    async fn test_donation_window() {
+        let manager = client::Manager::new(1);
+        let user_group = manager
+            .create_group(GroupDescriptor::new(
+                "user".to_string(),
+                false,
+                LoadBalanceStrategy::Quota(1),
+            ))
+            .await
+            .expect("BUG: cannot create group");
+        let mut donation_descriptor = GroupDescriptor::new(
+            "donation".to_string(),
+            true,
+            LoadBalanceStrategy::FixedShareRatio(0.02),
+        );
+        donation_descriptor.donation = true;
+        let donation_group = manager
+            .create_group(donation_descriptor)
+            .await
+            .expect("BUG: cannot create group");
+        let clients = vec![
+            user_group.push_client(drain_client("user")).await,
+            donation_group.push_client(drain_client("donation")).await,
+        ];
+        assert!(!clients[0].is_donation());
+        assert!(clients[1].is_donation());
+        for client in clients.iter() {
+            while !client.is_running() || client.get_last_job().await.is_none() {
+                delay_for(time::Duration::from_millis(10)).await;
+            }
+        }
+
+        let (engine_sender, _engine_receiver) = work::engine_channel(work::IgnoreEvents);
+        let donation = manager.donation();
+        let mut dispatcher = JobDispatcher::new(
+            engine_sender,
+            manager.group_registry.clone(),
+            donation.clone(),
+        );
+        dispatcher.direct(switch_to("user")).await;
+        assert!(!donation.is_active());
+
+        // the donation period lasts until the user's pool is mined again
+        dispatcher.direct(switch_to("donation")).await;
+        assert!(dispatcher.active_client == clients[1]);
+        assert!(donation.is_active());
+        delay_for(time::Duration::from_millis(20)).await;
+        dispatcher.direct(switch_to("user")).await;
+        assert!(!donation.is_active());
+        let donation_time = donation.time(time::Instant::now());
+        assert!(donation_time >= time::Duration::from_millis(20));
+
+        // mining for the user doesn't extend the donation time
+        delay_for(time::Duration::from_millis(20)).await;
+        assert_eq!(donation.time(time::Instant::now()), donation_time);
+        assert_eq!(donation.valid_job_diff.take_snapshot().await.solutions, 0);
+    }
+
+    #[tokio::test]
+    async fn test_profit_switch_reconfiguration() {
+        let manager = client::Manager::new(1);
+        let mut groups = vec![];
+        // private group takes the first position in the registry
+        for (name, private) in [("private", true), ("a", false)].iter() {
+            let group = manager
+                .create_group(GroupDescriptor::new(
+                    name.to_string(),
+                    *private,
+                    LoadBalanceStrategy::Quota(1),
+                ))
+                .await
+                .expect("BUG: cannot create group");
+            group.push_client(drain_client(name)).await;
+            groups.push(group);
+        }
+        let wait_for_clients = |group: Arc<client::Group>| async move {
+            let clients = group.get_clients().await;
+            for client in clients.iter() {
+                while !client.is_running() || client.get_last_job().await.is_none() {
+                    delay_for(time::Duration::from_millis(10)).await;
+                }
+            }
+            clients
+        };
+        wait_for_clients(groups[0].clone()).await;
+        let old_clients = wait_for_clients(groups[1].clone()).await;
+
+        let (engine_sender, _engine_receiver) = work::engine_channel(work::IgnoreEvents);
+        let mut dispatcher = JobDispatcher::new(
+            engine_sender,
+            manager.group_registry.clone(),
+            manager.donation(),
+        );
+        dispatcher.direct(switch_to("a")).await;
+        assert!(dispatcher.active_client == old_clients[0]);
+
+        // the group is still mined after its pool has been replaced
+        let pool_config = PoolConfig {
+            enabled: None,
+            url: "drain://b".to_string(),
+            user: "user".to_string(),
+            password: None,
+        };
+        groups[1]
+            .replace_pools(
+                vec![client::create_descriptor(&pool_config, true).expect("BUG: invalid pool")],
+                None,
+            )
+            .await;
+        let new_clients = wait_for_clients(groups[1].clone()).await;
+        dispatcher.schedule(1000).await;
+        assert!(dispatcher.active_client == new_clients[0]);
+        assert_eq!(dispatcher.directed_group, Some("a".to_string()));
+    }
+
+    #[test]
+    fn test_select_block_endpoint() {
+        let ms = time::Duration::from_millis;
+
+        assert_eq!(JobExecutor::select_block_endpoint(&[]), None);
+        // the issuing pool is used when no other endpoint is faster
+        assert_eq!(
+            JobExecutor::select_block_endpoint(&[(false, Some(ms(50)))]),
+            Some(0)
+        );
+        assert_eq!(
+            JobExecutor::select_block_endpoint(&[(false, None), (false, None)]),
+            Some(0)
+        );
+        // the lowest known latency wins
+        assert_eq!(
+            JobExecutor::select_block_endpoint(&[
+                (false, Some(ms(80))),
+                (false, None),
+                (false, Some(ms(20))),
+                (false, Some(ms(40)))
+            ]),
+            Some(2)
+        );
+        // configured solo endpoint is preferred to any pool
+        assert_eq!(
+            JobExecutor::select_block_endpoint(&[
+                (false, Some(ms(5))),
+                (true, Some(ms(30))),
+                (true, Some(ms(10)))
+            ]),
+            Some(2)
+        );
+    }
+}
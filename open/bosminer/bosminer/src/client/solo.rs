@@ -0,0 +1,1137 @@
+// Copyright (C) 2020  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Solo mining client connected directly to a full node (e.g. `bitcoind`) over its JSON-RPC
+//! interface. The block template is fetched by `getblocktemplate` every 30 seconds and the
+//! coinbase paying the whole block reward to the payout address is built locally. Block
+//! candidates are submitted in full by `submitblock`. The client also accepts block candidates
+//! of other clients when their jobs know all transactions of the block (see
+//! `job::Bitcoin::serialize_block`).
+//!
+//! The pool user and password are used as RPC credentials and the client is configured by URL
+//! fragment options, e.g. `solo+http://127.0.0.1:8332#payout=<address>&network=regtest`:
+//! * `payout=<address>` - address receiving the block reward (required)
+//! * `network=<name>` - Bitcoin network of the full node (mainnet by default)
+//! * `tip-poll=<seconds>` - period of polling the chain tip for faster template updates
+
+use ii_logging::macros::*;
+
+use crate::client;
+use crate::error;
+use crate::job;
+use crate::node;
+use crate::stats;
+use crate::sync;
+use crate::work;
+
+use bosminer_config::ClientDescriptor;
+use bosminer_macros::ClientNode;
+
+use ii_bitcoin::coinbase;
+use ii_bitcoin::{FromHex, HashTrait as _};
+
+use async_trait::async_trait;
+use futures::channel::mpsc;
+use futures::lock::Mutex;
+use futures::{FutureExt as _, StreamExt as _};
+use ii_async_compat::{futures, select, tokio};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::delay_for;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, Weak};
+use std::time;
+
+#[derive(Debug, Clone)]
+pub struct ConnectionDetails {
+    pub user: String,
+    pub password: Option<String>,
+    pub host: String,
+    pub port: u16,
+    pub fragment: Option<String>,
+}
+
+impl ConnectionDetails {
+    pub fn from_descriptor(descriptor: &ClientDescriptor) -> Self {
+        Self {
+            user: descriptor.user.clone(),
+            password: descriptor.password.clone(),
+            host: descriptor.host.clone(),
+            port: descriptor.port(),
+            fragment: descriptor.fragment.clone(),
+        }
+    }
+
+    fn get_host_and_port(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// Parse alphanumeric value from fragment in format `<key><value>`
+    fn get_value(&self, key: &str) -> Option<&str> {
+        let fragment = self.fragment.as_ref()?;
+        let value = &fragment[fragment.find(key)? + key.len()..];
+        let value = value
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .map_or(value, |end| &value[..end]);
+        Some(value).filter(|value| !value.is_empty())
+    }
+
+    /// Parse address receiving the block reward from fragment in format `payout=<address>`
+    fn get_payout_address(&self) -> Option<&str> {
+        self.get_value("payout=")
+    }
+
+    /// Parse Bitcoin network of the full node from fragment in format `network=<name>`. Unknown
+    /// networks fall back to mainnet.
+    fn get_network(&self) -> ii_bitcoin::Network {
+        self.get_value("network=")
+            .and_then(|name| name.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Parse period of polling the chain tip from fragment in format `tip-poll=<seconds>`. A new
+    /// template is then fetched as soon as a new block arrives instead of waiting for the next
+    /// regular template update.
+    fn get_tip_watch(&self) -> job::tip_watch::TipWatch {
+        self.get_value("tip-poll=")
+            .and_then(|seconds| seconds.parse().ok())
+            .filter(|&seconds| seconds > 0)
+            .map_or_else(job::tip_watch::TipWatch::disabled, |seconds| {
+                job::tip_watch::TipWatch::new(time::Duration::from_secs(seconds))
+            })
+    }
+}
+
+fn decode_hex(hex: &str) -> error::Result<Vec<u8>> {
+    Ok(hex::decode(hex).map_err(|e| format!("invalid hex string '{}': {}", hex, e))?)
+}
+
+/// Minimal JSON-RPC client of the full node. Each call uses its own HTTP/1.1 connection which
+/// is closed by the node after the response is sent.
+#[derive(Debug)]
+struct Rpc {
+    address: String,
+    authorization: String,
+    /// Duration of the last successful call
+    latency: StdMutex<Option<time::Duration>>,
+}
+
+impl Rpc {
+    const TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+    fn new(details: &ConnectionDetails) -> Self {
+        let credentials = format!(
+            "{}:{}",
+            details.user,
+            details.password.as_deref().unwrap_or_default()
+        );
+        Self {
+            address: details.get_host_and_port(),
+            authorization: base64::encode(&credentials),
+            latency: StdMutex::new(None),
+        }
+    }
+
+    fn request(&self, method: &str, params: serde_json::Value) -> Vec<u8> {
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": "bosminer",
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+        format!(
+            "POST / HTTP/1.1\r\nHost: {}\r\nAuthorization: Basic {}\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.address,
+            self.authorization,
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+
+    /// Extract result of `method` from the whole HTTP response
+    fn parse_response(method: &str, response: &[u8]) -> error::Result<serde_json::Value> {
+        let response = String::from_utf8_lossy(response);
+        let (head, body) = match response.find("\r\n\r\n") {
+            Some(end) => (&response[..end], &response[end + 4..]),
+            None => Err(format!("'{}': malformed HTTP response", method))?,
+        };
+        // the node reports RPC errors with HTTP error status and JSON body with the reason
+        let reply: serde_json::Value = match serde_json::from_str(body) {
+            Ok(reply) => reply,
+            Err(_) => Err(format!(
+                "'{}': unexpected response '{}'",
+                method,
+                head.lines().next().unwrap_or_default()
+            ))?,
+        };
+        if !reply["error"].is_null() {
+            Err(format!("'{}' failed: {}", method, reply["error"]))?;
+        }
+        Ok(reply["result"].clone())
+    }
+
+    async fn call(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> error::Result<serde_json::Value> {
+        let request = self.request(method, params);
+        let start = time::Instant::now();
+        let exchange = async {
+            let mut stream = TcpStream::connect(self.address.as_str()).await?;
+            stream.write_all(&request).await?;
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await?;
+            Ok::<_, std::io::Error>(response)
+        };
+        let response = match tokio::time::timeout(Self::TIMEOUT, exchange).await {
+            Ok(response) => response?,
+            Err(_) => Err(format!("'{}': full node has not responded in time", method))?,
+        };
+        let result = Self::parse_response(method, &response)?;
+        self.latency
+            .lock()
+            .expect("BUG: cannot lock RPC latency")
+            .replace(start.elapsed());
+        Ok(result)
+    }
+
+    fn latency(&self) -> Option<time::Duration> {
+        *self.latency.lock().expect("BUG: cannot lock RPC latency")
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct TemplateTransaction {
+    data: String,
+    txid: String,
+}
+
+/// Subset of `getblocktemplate` result (BIP22) required for building the block
+#[derive(Deserialize, Debug, Clone)]
+struct BlockTemplate {
+    version: u32,
+    previousblockhash: String,
+    transactions: Vec<TemplateTransaction>,
+    coinbasevalue: coinbase::Amount,
+    bits: String,
+    height: u32,
+    curtime: u32,
+    #[serde(default)]
+    default_witness_commitment: Option<String>,
+}
+
+/// Hash pair of nodes of merkle tree
+fn merkle_node(left: &ii_bitcoin::DHash, right: &ii_bitcoin::DHash) -> ii_bitcoin::DHash {
+    let mut bytes = [0u8; 2 * ii_bitcoin::SHA256_DIGEST_SIZE];
+    bytes[..ii_bitcoin::SHA256_DIGEST_SIZE].copy_from_slice(&left.into_inner());
+    bytes[ii_bitcoin::SHA256_DIGEST_SIZE..].copy_from_slice(&right.into_inner());
+    ii_bitcoin::DHash::hash(&bytes)
+}
+
+/// Compute merkle branch of the coinbase from hashes of all other transactions of the block. The
+/// branch contains siblings of the coinbase path on each level of the merkle tree.
+fn merkle_branch(mut hashes: Vec<ii_bitcoin::DHash>) -> Vec<ii_bitcoin::DHash> {
+    let mut branch = Vec::new();
+    while !hashes.is_empty() {
+        branch.push(hashes[0]);
+        // the first pair contains the coinbase path which is not known yet
+        let mut level = hashes.split_off(1);
+        if level.len() % 2 == 1 {
+            // the last hash is paired with itself
+            level.push(*level.last().expect("BUG: missing hash"));
+        }
+        hashes = level
+            .chunks(2)
+            .map(|pair| merkle_node(&pair[0], &pair[1]))
+            .collect();
+    }
+    branch
+}
+
+/// Compute merkle root from hash of the coinbase and its merkle branch
+fn merkle_root(
+    coinbase_hash: ii_bitcoin::DHash,
+    branch: &[ii_bitcoin::DHash],
+) -> ii_bitcoin::DHash {
+    branch
+        .iter()
+        .fold(coinbase_hash, |node, sibling| merkle_node(&node, sibling))
+}
+
+#[derive(Debug)]
+pub struct Job {
+    client: Weak<Client>,
+    version: u32,
+    prev_hash: ii_bitcoin::DHash,
+    merkle_root: ii_bitcoin::DHash,
+    time: u32,
+    bits: u32,
+    target: ii_bitcoin::Target,
+    network: ii_bitcoin::Network,
+    height: u32,
+    /// Coinbase without the rolled part of extranonce
+    coinbase: coinbase::CoinbaseBuilder,
+    /// The block commits to witnesses so the coinbase has to be serialized with witness
+    witness: bool,
+    /// Merkle branch of the coinbase
+    merkle_branch: Vec<ii_bitcoin::DHash>,
+    /// All other serialized transactions of the block template
+    transactions: Vec<Vec<u8>>,
+}
+
+impl Job {
+    /// Size of the part of coinbase extranonce rolled by the miner which follows the extranonce
+    /// unique for each job
+    pub const EXTRANONCE_SIZE: usize = mem::size_of::<u64>();
+
+    fn new(
+        client: &Arc<Client>,
+        template: &BlockTemplate,
+        payout_script: &[u8],
+        extranonce: u64,
+        network: ii_bitcoin::Network,
+    ) -> error::Result<Self> {
+        let bits = u32::from_str_radix(&template.bits, 16)
+            .map_err(|_| format!("invalid nBits '{}'", template.bits))?;
+        let target = network.target_from_compact(bits)?;
+        let prev_hash = ii_bitcoin::DHash::from_hex(&template.previousblockhash)
+            .map_err(|e| format!("invalid previous block hash: {}", e))?;
+
+        // each job has unique coinbase thanks to extranonce following the block height
+        let mut builder = coinbase::CoinbaseBuilder::new(template.height)
+            .script_sig_data(&extranonce.to_le_bytes())
+            .output(template.coinbasevalue, payout_script);
+        if let Some(commitment) = template.default_witness_commitment.as_ref() {
+            builder = builder.output(0, &decode_hex(commitment)?);
+        }
+        coinbase::verify_payout(&builder.build(), payout_script)?;
+
+        let mut hashes = Vec::with_capacity(template.transactions.len());
+        let mut transactions = Vec::with_capacity(template.transactions.len());
+        for tx in template.transactions.iter() {
+            hashes.push(
+                ii_bitcoin::DHash::from_hex(&tx.txid)
+                    .map_err(|e| format!("invalid transaction id: {}", e))?,
+            );
+            transactions.push(decode_hex(&tx.data)?);
+        }
+
+        // merkle root of the job is the one for the first value of the rolled extranonce
+        let merkle_branch = merkle_branch(hashes);
+        let stripped_coinbase = builder.clone().script_sig_data(&0u64.to_le_bytes()).build();
+        Ok(Self {
+            client: Arc::downgrade(client),
+            version: template.version,
+            prev_hash,
+            merkle_root: merkle_root(ii_bitcoin::DHash::hash(&stripped_coinbase), &merkle_branch),
+            time: template.curtime,
+            bits,
+            target,
+            network,
+            height: template.height,
+            coinbase: builder,
+            witness: template.default_witness_commitment.is_some(),
+            merkle_branch,
+            transactions,
+        })
+    }
+
+    /// Return coinbase with given value of the rolled part of extranonce
+    fn coinbase(&self, extranonce: u64) -> coinbase::CoinbaseBuilder {
+        self.coinbase
+            .clone()
+            .script_sig_data(&extranonce.to_le_bytes())
+    }
+}
+
+impl job::Bitcoin for Job {
+    fn origin(&self) -> Weak<dyn node::Client> {
+        self.client.clone()
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn version_mask(&self) -> u32 {
+        ii_bitcoin::BIP320_VERSION_MASK
+    }
+
+    fn previous_hash(&self) -> &ii_bitcoin::DHash {
+        &self.prev_hash
+    }
+
+    fn merkle_root(&self) -> &ii_bitcoin::DHash {
+        &self.merkle_root
+    }
+
+    fn time(&self) -> u32 {
+        self.time
+    }
+
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    fn target(&self) -> ii_bitcoin::Target {
+        self.target
+    }
+
+    fn is_valid(&self) -> bool {
+        true
+    }
+
+    fn block_height(&self) -> Option<u64> {
+        Some(self.height as u64)
+    }
+
+    fn network(&self) -> ii_bitcoin::Network {
+        self.network
+    }
+
+    fn extranonce_size(&self) -> usize {
+        Self::EXTRANONCE_SIZE
+    }
+
+    fn extranonce_merkle_root(&self, extranonce: u64) -> ii_bitcoin::DHash {
+        let stripped_coinbase = self.coinbase(extranonce).build();
+        merkle_root(
+            ii_bitcoin::DHash::hash(&stripped_coinbase),
+            &self.merkle_branch,
+        )
+    }
+
+    fn serialize_block(&self, header: ii_bitcoin::BlockHeader, extranonce: u64) -> Option<Vec<u8>> {
+        let mut block = header.into_bytes().to_vec();
+        coinbase::write_var_int(&mut block, 1 + self.transactions.len() as u64);
+        let coinbase = self.coinbase(extranonce);
+        block.extend_from_slice(&if self.witness {
+            coinbase.build_with_witness()
+        } else {
+            coinbase.build()
+        });
+        for tx in self.transactions.iter() {
+            block.extend_from_slice(tx);
+        }
+        Some(block)
+    }
+}
+
+#[derive(Debug, ClientNode)]
+pub struct Client {
+    details: ConnectionDetails,
+    rpc: Rpc,
+    #[member_status]
+    status: sync::StatusMonitor,
+    #[member_client_stats]
+    stats: stats::BasicClient,
+    stop_sender: mpsc::Sender<()>,
+    stop_receiver: Mutex<mpsc::Receiver<()>>,
+    last_job: Mutex<Option<Arc<Job>>>,
+    /// Shared with the chain tip watch which invalidates work while the client waits for a new
+    /// template
+    job_sender: job::Sender,
+    solution_receiver: Mutex<job::SolutionReceiver>,
+    /// Extranonce of the next job which makes the coinbase of each job unique
+    extranonce: AtomicU64,
+}
+
+impl Client {
+    /// Period of fetching new template with recent transactions
+    const TEMPLATE_INTERVAL: time::Duration = time::Duration::from_secs(30);
+    /// Delay before the next attempt when the full node is not available
+    const RETRY_DELAY: time::Duration = time::Duration::from_secs(5);
+
+    pub fn new(details: ConnectionDetails, solver: job::Solver) -> Self {
+        let (stop_sender, stop_receiver) = mpsc::channel(1);
+        Self {
+            rpc: Rpc::new(&details),
+            details,
+            status: Default::default(),
+            stats: Default::default(),
+            stop_sender,
+            stop_receiver: Mutex::new(stop_receiver),
+            last_job: Mutex::new(None),
+            job_sender: solver.job_sender,
+            solution_receiver: Mutex::new(solver.solution_receiver),
+            extranonce: AtomicU64::new(0),
+        }
+    }
+
+    /// Let the full node resolve the payout address to its output script
+    async fn payout_script(&self) -> error::Result<Vec<u8>> {
+        let address = self
+            .details
+            .get_payout_address()
+            .ok_or("missing payout address (URL fragment 'payout=<address>')")?;
+        let result = self.rpc.call("validateaddress", json!([address])).await?;
+        if result["isvalid"] != json!(true) {
+            Err(format!("invalid payout address '{}'", address))?;
+        }
+        let script = result["scriptPubKey"]
+            .as_str()
+            .ok_or("missing script of payout address")?;
+        decode_hex(script)
+    }
+
+    async fn get_template(&self) -> error::Result<BlockTemplate> {
+        let result = self
+            .rpc
+            .call("getblocktemplate", json!([{ "rules": ["segwit"] }]))
+            .await?;
+        Ok(serde_json::from_value(result)
+            .map_err(|e| format!("malformed block template: {}", e))?)
+    }
+
+    async fn update_job(self: &Arc<Self>, payout_script: &[u8]) -> error::Result<()> {
+        let template = self.get_template().await?;
+        let extranonce = self.extranonce.fetch_add(1, Ordering::Relaxed);
+        let job = Arc::new(Job::new(
+            self,
+            &template,
+            payout_script,
+            extranonce,
+            self.details.get_network(),
+        )?);
+
+        self.last_job.lock().await.replace(job.clone());
+        self.job_sender.send(job);
+        Ok(())
+    }
+
+    async fn last_job(&self) -> Option<Arc<Job>> {
+        self.last_job.lock().await.as_ref().map(|job| job.clone())
+    }
+
+    /// Submit the whole block of block candidate to the full node. Block candidates of other
+    /// clients (see `node::Client::accepts_foreign_blocks`) are relayed only when their job
+    /// knows all transactions of the block.
+    async fn submit(&self, solution: work::Solution) {
+        // outcome of relayed solution is reported by its origin
+        let relayed = solution.is_relayed();
+        let block = match solution.serialize_block() {
+            Some(block) => block,
+            None => {
+                warn!(
+                    "Solo client '{}': block candidate {} cannot be submitted without \
+                     transactions of the block",
+                    self,
+                    solution.hash()
+                );
+                return;
+            }
+        };
+        info!(
+            "Solo client '{}': submitting block candidate {}",
+            self,
+            solution.hash()
+        );
+
+        match self
+            .rpc
+            .call("submitblock", json!([hex::encode(block)]))
+            .await
+        {
+            // the node returns `null` when the block has been accepted
+            Ok(serde_json::Value::Null) => {
+                info!("Solo client '{}': block candidate accepted", self);
+                if !relayed {
+                    solution.resolve(work::SubmitOutcome::Accepted);
+                    self.stats
+                        .account_accepted(&solution, time::Instant::now())
+                        .await;
+                }
+            }
+            Ok(reason) => {
+                warn!(
+                    "Solo client '{}': block candidate rejected: {}",
+                    self, reason
+                );
+                if !relayed {
+                    let reason = reason.as_str().unwrap_or_default().to_string();
+                    solution.resolve(work::SubmitOutcome::Rejected(
+                        client::rejection::RejectReason::Other(reason),
+                    ));
+                    self.stats
+                        .rejected
+                        .account_solution(&solution.job_target(), time::Instant::now())
+                        .await;
+                }
+            }
+            Err(e) => error!(
+                "Solo client '{}': cannot submit block candidate: {}",
+                self, e
+            ),
+        }
+    }
+
+    async fn main_loop(self: Arc<Self>) -> error::Result<()> {
+        let payout_script = self.payout_script().await?;
+        self.update_job(&payout_script).await?;
+        if !self.status.initiate_running() {
+            return Ok(());
+        }
+
+        let tip_watch = self.details.get_tip_watch();
+        if let Some(interval) = tip_watch.interval() {
+            info!(
+                "Solo client '{}': polling chain tip every {}s",
+                self,
+                interval.as_secs()
+            );
+        }
+        let mut solution_receiver = self.solution_receiver.lock().await;
+        while !self.status.is_shutting_down() {
+            select! {
+                _ = delay_for(Self::TEMPLATE_INTERVAL).fuse() => {
+                    self.update_job(&payout_script).await?;
+                }
+                _ = tip_watch.run(&*self, &self.job_sender).fuse() => {
+                    self.update_job(&payout_script).await?;
+                }
+                solution = solution_receiver.receive().fuse() => {
+                    match solution {
+                        Some(solution) => self.submit(solution).await,
+                        None => {
+                            // TODO: initiate Destroying and remove error
+                            Err("Standard application shutdown")?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(self: Arc<Self>) {
+        if let Err(e) = self.clone().main_loop().await {
+            error!("Solo client '{}': {}", self, e);
+            self.status.initiate_failing();
+            // do not flood the full node with requests while it is not available
+            delay_for(Self::RETRY_DELAY).await;
+        }
+    }
+
+    async fn main_task(self: Arc<Self>) {
+        loop {
+            let mut stop_receiver = self.stop_receiver.lock().await;
+            select! {
+                _ = self.clone().run().fuse() => {}
+                _ = stop_receiver.next() => {}
+            }
+
+            // Invalidate current job to stop working on it
+            self.job_sender.invalidate();
+
+            if self.status.can_stop() {
+                // NOTE: it is not safe to add here any code!
+                break;
+            }
+            // Restarting
+        }
+    }
+}
+
+/// The tip is the best block known to the full node
+#[async_trait]
+impl job::tip_watch::Tip for Client {
+    async fn tip(&self) -> Option<ii_bitcoin::DHash> {
+        match self.rpc.call("getbestblockhash", json!([])).await {
+            Ok(result) => result
+                .as_str()
+                .and_then(|hash| ii_bitcoin::DHash::from_hex(hash).ok()),
+            Err(e) => {
+                debug!("Solo client '{}': cannot get chain tip: {}", self, e);
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl node::Client for Client {
+    fn start(self: Arc<Self>) {
+        tokio::spawn(self.clone().main_task());
+    }
+
+    fn stop(&self) {
+        if let Err(e) = self.stop_sender.clone().try_send(()) {
+            assert!(
+                e.is_full(),
+                "BUG: Unexpected error in stop sender: {}",
+                e.to_string()
+            );
+        }
+    }
+
+    async fn get_last_job(&self) -> Option<Arc<dyn job::Bitcoin>> {
+        self.last_job()
+            .await
+            .map(|job| job as Arc<dyn job::Bitcoin>)
+    }
+
+    fn submission_latency(&self) -> Option<time::Duration> {
+        self.rpc.latency()
+    }
+
+    fn accepts_foreign_blocks(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.details.get_host_and_port())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::hal;
+    use crate::job::Bitcoin as _;
+
+    use tokio::net::TcpListener;
+
+    use std::net::SocketAddr;
+
+    pub const PAYOUT_ADDRESS: &str = "bcrt1qpayout";
+    /// P2WPKH script of the payout address
+    const PAYOUT_SCRIPT: &str = "00140102030405060708090a0b0c0d0e0f1011121314";
+    pub const TIP: &str = "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206";
+    const TXID: &str = "a3e2ae3ec5bd811ef8fb5a0bf1ad6a7e2e7a0ed5e8b3a8542ee0e55d4aafc3a8";
+    const TX_DATA: &str = "0200000000";
+    const WITNESS_COMMITMENT: &str =
+        "6a24aa21a9ede2f61c3f71d1defd3fa999dfa36953755c690689799962b48bebd836974e8cf9";
+    const HEIGHT: u32 = 1000;
+
+    /// Full node answering JSON-RPC requests of the solo client
+    #[derive(Debug, Clone)]
+    pub struct FakeNode {
+        pub address: SocketAddr,
+        /// Result of `getblocktemplate`
+        pub template: Arc<StdMutex<serde_json::Value>>,
+        /// Method and parameters of all received calls
+        calls: Arc<StdMutex<Vec<(String, serde_json::Value)>>>,
+    }
+
+    impl FakeNode {
+        pub async fn start() -> Self {
+            let mut listener = TcpListener::bind("127.0.0.1:0")
+                .await
+                .expect("BUG: cannot bind fake node");
+            let node = Self {
+                address: listener.local_addr().expect("BUG: missing local address"),
+                template: Arc::new(StdMutex::new(Self::template(TIP))),
+                calls: Default::default(),
+            };
+            let server = node.clone();
+            tokio::spawn(async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(server.clone().serve(stream));
+                }
+            });
+            node
+        }
+
+        /// Regtest template building on block `tip` with one transaction
+        pub fn template(tip: &str) -> serde_json::Value {
+            json!({
+                "version": 0x2000_0000,
+                "previousblockhash": tip,
+                "transactions": [{ "data": TX_DATA, "txid": TXID }],
+                "coinbasevalue": 50 * 100_000_000u64,
+                "bits": "207fffff",
+                "height": HEIGHT,
+                "curtime": 1_600_000_000,
+                "default_witness_commitment": WITNESS_COMMITMENT,
+            })
+        }
+
+        pub fn url(&self) -> String {
+            format!(
+                "solo+http://{}#payout={}&network=regtest",
+                self.address, PAYOUT_ADDRESS
+            )
+        }
+
+        pub fn descriptor(&self) -> ClientDescriptor {
+            ClientDescriptor::create(
+                self.url().as_str(),
+                &bosminer_config::ClientUserInfo::new("rpc", Some("secret")),
+                true,
+            )
+            .expect("BUG: invalid solo URL")
+        }
+
+        /// Parameters of all calls of `method`
+        pub fn calls(&self, method: &str) -> Vec<serde_json::Value> {
+            self.calls
+                .lock()
+                .expect("BUG: cannot lock calls")
+                .iter()
+                .filter(|(name, _)| name == method)
+                .map(|(_, params)| params.clone())
+                .collect()
+        }
+
+        /// Return body of `request` when it has been received completely
+        fn body(request: &[u8]) -> Option<&[u8]> {
+            let text = std::str::from_utf8(request).ok()?;
+            let end = text.find("\r\n\r\n")? + 4;
+            let length: usize = text[..end]
+                .lines()
+                .filter_map(|line| {
+                    let mut parts = line.splitn(2, ':');
+                    match parts.next() {
+                        Some(name) if name.eq_ignore_ascii_case("content-length") => {
+                            parts.next()?.trim().parse().ok()
+                        }
+                        _ => None,
+                    }
+                })
+                .next()?;
+            request.get(end..end + length)
+        }
+
+        async fn serve(self, mut stream: TcpStream) {
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            while Self::body(&request).is_none() {
+                match stream.read(&mut buffer).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => request.extend_from_slice(&buffer[..n]),
+                }
+            }
+            let request: serde_json::Value =
+                serde_json::from_slice(Self::body(&request).expect("BUG: missing body"))
+                    .expect("BUG: malformed request");
+            let method = request["method"]
+                .as_str()
+                .expect("BUG: missing method")
+                .to_string();
+            let result = match method.as_str() {
+                "validateaddress" => json!({
+                    "isvalid": request["params"][0] == PAYOUT_ADDRESS,
+                    "scriptPubKey": PAYOUT_SCRIPT,
+                }),
+                "getblocktemplate" => self
+                    .template
+                    .lock()
+                    .expect("BUG: cannot lock template")
+                    .clone(),
+                "getbestblockhash" => self.template.lock().expect("BUG: cannot lock template")
+                    ["previousblockhash"]
+                    .clone(),
+                _ => serde_json::Value::Null,
+            };
+            self.calls
+                .lock()
+                .expect("BUG: cannot lock calls")
+                .push((method, request["params"].clone()));
+
+            let reply = json!({ "result": result, "error": null, "id": request["id"] }).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                reply.len(),
+                reply
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    }
+
+    /// Create solo client connected to the `node` and start it. The client runs as long as the
+    /// returned solution sender exists.
+    pub fn start_client(node: &FakeNode) -> (Arc<Client>, mpsc::UnboundedSender<work::Solution>) {
+        let (solution_sender, solution_receiver) = mpsc::unbounded();
+        let client = Arc::new(Client::new(
+            ConnectionDetails::from_descriptor(&node.descriptor()),
+            job::Solver::new(Arc::new(work::EngineSender::new(None)), solution_receiver),
+        ));
+        assert!(client.status.initiate_starting());
+        client.clone().start();
+        (client, solution_sender)
+    }
+
+    /// Backend solution with arbitrary nonce
+    #[derive(Debug)]
+    struct NonceSolution {
+        nonce: u32,
+        target: ii_bitcoin::Target,
+    }
+
+    impl hal::BackendSolution for NonceSolution {
+        fn nonce(&self) -> u32 {
+            self.nonce
+        }
+
+        fn midstate_idx(&self) -> usize {
+            0
+        }
+
+        fn solution_idx(&self) -> usize {
+            0
+        }
+
+        fn target(&self) -> &ii_bitcoin::Target {
+            &self.target
+        }
+    }
+
+    /// Wait for the first job of `client` and return a block candidate solving it (it is easy to
+    /// find with the maximal target of regtest)
+    pub async fn block_solution(client: &Client) -> work::Solution {
+        let mut job = None;
+        for _ in 0..100 {
+            job = client.last_job().await;
+            if job.is_some() {
+                break;
+            }
+            delay_for(time::Duration::from_millis(10)).await;
+        }
+        let job = job.expect("BUG: solo client has not received template");
+        let midstate = work::Midstate {
+            version: job.version,
+            state: hal::compute_midstate(job.as_ref(), job.version),
+        };
+        (0..)
+            .map(|nonce| {
+                let work = work::Assignment::new(job.clone(), vec![midstate.clone()], job.time);
+                let solution = NonceSolution {
+                    nonce,
+                    target: Default::default(),
+                };
+                work::Solution::new(work, solution, None)
+            })
+            .find(|solution| solution.is_block())
+            .expect("BUG: no block candidate")
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 37\r\n\r\n\
+            {\"result\":10,\"error\":null,\"id\":\"1\"}";
+        assert_eq!(
+            Rpc::parse_response("getblockcount", response).expect("BUG: valid response"),
+            json!(10)
+        );
+
+        // RPC errors are reported with HTTP error status
+        let response = b"HTTP/1.1 500 Internal Server Error\r\n\r\n\
+            {\"result\":null,\"error\":{\"code\":-8,\"message\":\"bad\"},\"id\":\"1\"}";
+        let error = Rpc::parse_response("submitblock", response).expect_err("BUG: RPC error");
+        assert!(error.to_string().contains("bad"));
+
+        // wrong credentials have no JSON body
+        let response = b"HTTP/1.1 401 Unauthorized\r\n\r\n";
+        let error = Rpc::parse_response("submitblock", response).expect_err("BUG: HTTP error");
+        assert!(error.to_string().contains("401 Unauthorized"));
+        assert!(Rpc::parse_response("submitblock", b"HTTP/1.1 200 OK").is_err());
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        let hash = |byte: u8| ii_bitcoin::DHash::hash(&[byte]);
+        let pair = |a: ii_bitcoin::DHash, b: ii_bitcoin::DHash| {
+            let mut bytes = a.into_inner().to_vec();
+            bytes.extend_from_slice(&b.into_inner());
+            ii_bitcoin::DHash::hash(&bytes)
+        };
+
+        let root = |hashes: Vec<ii_bitcoin::DHash>| {
+            merkle_root(hashes[0], &merkle_branch(hashes[1..].to_vec()))
+        };
+
+        // block with the coinbase only
+        assert!(merkle_branch(vec![]).is_empty());
+        assert_eq!(root(vec![hash(0)]), hash(0));
+        assert_eq!(root(vec![hash(0), hash(1)]), pair(hash(0), hash(1)));
+        // the last hash of odd level is duplicated
+        assert_eq!(
+            root(vec![hash(0), hash(1), hash(2)]),
+            pair(pair(hash(0), hash(1)), pair(hash(2), hash(2)))
+        );
+        assert_eq!(
+            merkle_branch(vec![hash(1), hash(2)]),
+            vec![hash(1), pair(hash(2), hash(2))]
+        );
+        assert_eq!(
+            root((0..5).map(hash).collect()),
+            pair(
+                pair(pair(hash(0), hash(1)), pair(hash(2), hash(3))),
+                pair(pair(hash(4), hash(4)), pair(hash(4), hash(4)))
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_solo_job() {
+        let node = FakeNode::start().await;
+        let (client, _solution_sender) = start_client(&node);
+        let mut job = None;
+        for _ in 0..100 {
+            job = client.last_job().await;
+            if job.is_some() {
+                break;
+            }
+            delay_for(time::Duration::from_millis(10)).await;
+        }
+        let job = job.expect("BUG: solo client has not received template");
+        assert_eq!(node.calls("validateaddress"), vec![json!([PAYOUT_ADDRESS])]);
+        assert_eq!(client.status.status(), sync::Status::Running);
+
+        assert_eq!(job.previous_hash_hex(), TIP);
+        assert_eq!(
+            job::Bitcoin::block_height(job.as_ref()),
+            Some(HEIGHT as u64)
+        );
+        assert_eq!(
+            job::Bitcoin::network(job.as_ref()),
+            ii_bitcoin::Network::Regtest
+        );
+        assert_eq!(
+            job.target,
+            ii_bitcoin::Network::Regtest.max_target(),
+            "BUG: target does not match nBits"
+        );
+
+        // the whole reward is paid to the payout address
+        let payout_script = decode_hex(PAYOUT_SCRIPT).expect("BUG: invalid script");
+        let coinbase = job.coinbase(0).build_with_witness();
+        assert_eq!(
+            coinbase::verify_payout(&coinbase, &payout_script),
+            Ok(50 * 100_000_000)
+        );
+        assert_eq!(coinbase::parse_height(&coinbase), Ok(HEIGHT as u64));
+
+        // merkle root is computed from coinbase without witness
+        let stripped_coinbase = |extranonce: u64| {
+            coinbase::CoinbaseBuilder::new(HEIGHT)
+                .script_sig_data(&0u64.to_le_bytes())
+                .script_sig_data(&extranonce.to_le_bytes())
+                .output(50 * 100_000_000, &payout_script)
+                .output(
+                    0,
+                    &decode_hex(WITNESS_COMMITMENT).expect("BUG: invalid commitment"),
+                )
+                .build()
+        };
+        let expected_merkle_root = |extranonce| {
+            merkle_node(
+                &ii_bitcoin::DHash::hash(&stripped_coinbase(extranonce)),
+                &ii_bitcoin::DHash::from_hex(TXID).expect("BUG: invalid txid"),
+            )
+        };
+        assert_eq!(job.merkle_root, expected_merkle_root(0));
+
+        // the miner is allowed to roll the extranonce following the one unique for the job
+        assert_eq!(job::Bitcoin::extranonce_size(job.as_ref()), 8);
+        assert_eq!(
+            job::Bitcoin::extranonce_merkle_root(job.as_ref(), 0),
+            job.merkle_root
+        );
+        assert_eq!(
+            job::Bitcoin::extranonce_merkle_root(job.as_ref(), 0x1234),
+            expected_merkle_root(0x1234)
+        );
+        let engine = work::engine::Prefetcher::new(1, 0)
+            .with_extranonce_rolling(16)
+            .build_engine(job.clone());
+        let range = engine
+            .next_work()
+            .unwrap()
+            .extranonce
+            .expect("BUG: solo job is not mined with extranonce rolling");
+        assert_eq!(range.count, 16);
+
+        // the block contains header, coinbase with witness and the template transaction
+        let header = ii_bitcoin::BlockHeader {
+            version: job.version,
+            previous_hash: job.prev_hash.into_inner(),
+            merkle_root: job.merkle_root.into_inner(),
+            time: job.time,
+            bits: job.bits,
+            nonce: 0x1234_5678,
+        };
+        let block = job
+            .serialize_block(header, 0)
+            .expect("BUG: solo job without block");
+        let header_bytes = header.into_bytes();
+        assert_eq!(&block[..header_bytes.len()], &header_bytes[..]);
+        assert_eq!(block[header_bytes.len()], 2);
+        assert_eq!(
+            &block[header_bytes.len() + 1..block.len() - TX_DATA.len() / 2],
+            &coinbase[..]
+        );
+        assert!(hex::encode(&block).ends_with(TX_DATA));
+        // the coinbase of the block contains extranonce used for the solution
+        let block = job
+            .serialize_block(header, 0x1234)
+            .expect("BUG: solo job without block");
+        assert_eq!(
+            &block[header_bytes.len() + 1..block.len() - TX_DATA.len() / 2],
+            &job.coinbase(0x1234).build_with_witness()[..]
+        );
+        client.stop();
+    }
+
+    #[tokio::test]
+    async fn test_chain_tip() {
+        use job::tip_watch::Tip as _;
+
+        let node = FakeNode::start().await;
+        let details = ConnectionDetails::from_descriptor(&node.descriptor());
+        assert_eq!(details.get_tip_watch().interval(), None);
+        let details = ConnectionDetails {
+            fragment: Some(format!("payout={}&tip-poll=5", PAYOUT_ADDRESS)),
+            ..details
+        };
+        assert_eq!(
+            details.get_tip_watch().interval(),
+            Some(time::Duration::from_secs(5))
+        );
+
+        // the tip is the best block of the full node
+        let (client, _solution_sender) = start_client(&node);
+        assert_eq!(
+            client.tip().await.map(|tip| tip.to_string()),
+            Some(TIP.to_string())
+        );
+        let new_tip = "00000000000000000000000000000000000000000000000000000000000000ff";
+        *node.template.lock().expect("BUG: cannot lock template") = FakeNode::template(new_tip);
+        assert_eq!(
+            client.tip().await.map(|tip| tip.to_string()),
+            Some(new_tip.to_string())
+        );
+        client.stop();
+    }
+}
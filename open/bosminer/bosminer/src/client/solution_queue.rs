@@ -0,0 +1,379 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Queue of solutions submitted to remote server which are waiting for acknowledgement
+
+use ii_logging::macros::*;
+
+use crate::error;
+use crate::work;
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time;
+
+/// Solution with unique identifier assigned by the queue
+#[derive(Debug)]
+pub struct Entry {
+    /// Identifier is never reused because its upper half counts wraparounds of sequence number
+    id: u64,
+    pub solution: work::Solution,
+    /// Time when the solution has been stored for submission
+    pub submitted: time::Instant,
+}
+
+impl Entry {
+    /// Sequence number used in the protocol
+    #[inline]
+    pub fn seq_num(&self) -> u32 {
+        self.id as u32
+    }
+}
+
+/// The queue assigns monotonic sequence numbers to solutions so that a client can easily process
+/// bulk acknowledgements. The sequence number type u32 matches the protocol and it wraps around at
+/// high hash rates. Therefore each acknowledged sequence number is resolved to the most recent
+/// solution with such number which prevents a wrapped number to match any stale solution.
+/// The queue is bounded and warns when the high-water mark is crossed long before solutions start
+/// to be dropped.
+#[derive(Debug)]
+pub struct SolutionQueue {
+    /// Identifier of the next solution (lower half is the sequence number)
+    next_id: u64,
+    queue: VecDeque<Entry>,
+    capacity: usize,
+    high_water_mark: usize,
+    /// The alert is raised only once until the queue falls below the high-water mark
+    alert_raised: bool,
+    /// Number of solutions dropped due to full queue
+    dropped: u64,
+    /// Solutions dropped since the queue has become full which are logged in one summary once
+    /// the backlog is processed to not flood the log during a stall
+    backlog_dropped: u64,
+    /// Registry for reporting the alert together with a name of the queue owner
+    error_registry: Option<(Arc<error::ErrorRegistry>, String)>,
+}
+
+impl SolutionQueue {
+    /// Maximal number of solutions waiting for acknowledgement
+    /// When exceeded then the oldest solution is dropped.
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    pub fn new() -> Self {
+        Self::with_seq_num(0)
+    }
+
+    /// Create a queue where the first solution is assigned `seq_num`
+    pub fn with_seq_num(seq_num: u32) -> Self {
+        Self {
+            next_id: seq_num as u64,
+            queue: VecDeque::new(),
+            capacity: Self::DEFAULT_CAPACITY,
+            high_water_mark: Self::default_high_water_mark(Self::DEFAULT_CAPACITY),
+            alert_raised: false,
+            dropped: 0,
+            backlog_dropped: 0,
+            error_registry: None,
+        }
+    }
+
+    /// Number of pending solutions which raises an alert about stalled submission
+    pub fn default_high_water_mark(capacity: usize) -> usize {
+        capacity * 3 / 4
+    }
+
+    /// Change limits of the queue. The high-water mark has to be lower than the capacity.
+    pub fn with_limits(mut self, capacity: usize, high_water_mark: usize) -> Self {
+        assert!(
+            high_water_mark > 0 && high_water_mark < capacity,
+            "BUG: high-water mark {} out of queue capacity {}",
+            high_water_mark,
+            capacity
+        );
+        self.capacity = capacity;
+        self.high_water_mark = high_water_mark;
+        self
+    }
+
+    /// Report crossing of the high-water mark to the `error_registry` on behalf of `source`
+    pub fn set_error_registry<S: Into<String>>(
+        &mut self,
+        error_registry: Arc<error::ErrorRegistry>,
+        source: S,
+    ) {
+        self.error_registry = Some((error_registry, source.into()));
+    }
+
+    /// Store solution and return its sequence number
+    pub fn push(&mut self, solution: work::Solution) -> u32 {
+        self.push_evicting(solution).0
+    }
+
+    /// Store solution and return its sequence number together with the oldest solution which
+    /// has been dropped because the queue is full. The dropped solution is never acknowledged so
+    /// the caller has to release its submission.
+    pub fn push_evicting(&mut self, solution: work::Solution) -> (u32, Option<Entry>) {
+        let mut evicted = None;
+        if self.queue.len() >= self.capacity {
+            evicted = self.queue.pop_front();
+            self.dropped += 1;
+            if self.backlog_dropped == 0 {
+                warn!(
+                    "Solution queue is full ({} solutions), dropping the oldest ones",
+                    self.capacity
+                );
+            }
+            self.backlog_dropped += 1;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.push_back(Entry {
+            id,
+            solution,
+            submitted: time::Instant::now(),
+        });
+
+        if self.queue.len() >= self.high_water_mark && !self.alert_raised {
+            self.raise_alert();
+        }
+        (id as u32, evicted)
+    }
+
+    fn raise_alert(&mut self) {
+        self.alert_raised = true;
+        let message = format!(
+            "{} solutions waiting for acknowledgement (high-water mark {}, capacity {})",
+            self.queue.len(),
+            self.high_water_mark,
+            self.capacity
+        );
+        warn!("Solution queue backlog: {}", message);
+        if let Some((error_registry, source)) = self.error_registry.as_ref() {
+            error_registry.push(
+                error::ConditionKind::SolutionQueueBacklog,
+                error::Severity::Warning,
+                source.as_str(),
+                message,
+            );
+        }
+    }
+
+    /// Re-arm the alert once the backlog has been processed
+    #[inline]
+    fn update_alert(&mut self) {
+        if self.queue.len() < self.high_water_mark {
+            self.alert_raised = false;
+            if self.backlog_dropped > 0 {
+                warn!(
+                    "Solution queue backlog processed, {} solutions have been dropped",
+                    self.backlog_dropped
+                );
+                self.backlog_dropped = 0;
+            }
+        }
+    }
+
+    /// Convert acknowledged `seq_num` to identifier of the most recent solution with this number
+    fn resolve_id(&self, seq_num: u32) -> Option<u64> {
+        let last_id = self.next_id.checked_sub(1)?;
+        let id = (last_id & !(u32::max_value() as u64)) | seq_num as u64;
+        if id > last_id {
+            // the sequence number belongs to the previous wraparound
+            id.checked_sub(1 << 32)
+        } else {
+            Some(id)
+        }
+    }
+
+    /// Remove the oldest solution when it has been submitted before or with acknowledged `seq_num`
+    pub fn pop_acknowledged(&mut self, seq_num: u32) -> Option<Entry> {
+        let id = self.resolve_id(seq_num)?;
+        if self.queue.front()?.id <= id {
+            let entry = self.queue.pop_front();
+            self.update_alert();
+            entry
+        } else {
+            None
+        }
+    }
+
+    /// Drop all solutions and start sequence numbers from the beginning
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.update_alert();
+        // keep the generation to never match solutions from previous session
+        self.next_id = (self.next_id & !(u32::max_value() as u64)) + (1 << 32);
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Check if the queue is above the high-water mark
+    #[inline]
+    pub fn is_alert_raised(&self) -> bool {
+        self.alert_raised
+    }
+
+    #[inline]
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn test_seq_num_wraparound() {
+        let mut queue = SolutionQueue::with_seq_num(u32::max_value() - 1);
+
+        let blocks: Vec<_> = test_utils::TEST_BLOCKS.iter().cycle().take(4).collect();
+        let seq_nums: Vec<_> = blocks
+            .iter()
+            .map(|&block| queue.push(block.into()))
+            .collect();
+        assert_eq!(seq_nums, vec![u32::max_value() - 1, u32::max_value(), 0, 1]);
+
+        // acknowledgement of wrapped sequence number covers all preceding solutions
+        for (i, &seq_num) in seq_nums[..3].iter().enumerate() {
+            let entry = queue.pop_acknowledged(0).expect("BUG: missing solution");
+            assert_eq!(entry.seq_num(), seq_num);
+            assert_eq!(entry.solution.nonce(), blocks[i].nonce);
+        }
+        assert!(queue.pop_acknowledged(0).is_none());
+
+        // stale sequence number from before the wraparound must not match new solution
+        assert!(queue.pop_acknowledged(u32::max_value()).is_none());
+        assert_eq!(queue.len(), 1);
+
+        let entry = queue.pop_acknowledged(1).expect("BUG: missing solution");
+        assert_eq!(entry.seq_num(), 1);
+        assert_eq!(entry.solution.nonce(), blocks[3].nonce);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut queue = SolutionQueue::new();
+        let block = &test_utils::TEST_BLOCKS[0];
+
+        assert_eq!(queue.push(block.into()), 0);
+        assert_eq!(queue.push(block.into()), 1);
+        queue.clear();
+
+        // sequence numbers start from the beginning and stale acknowledgement is ignored
+        assert!(queue.pop_acknowledged(1).is_none());
+        assert_eq!(queue.push(block.into()), 0);
+        assert!(queue.pop_acknowledged(1).is_none());
+        assert_eq!(queue.pop_acknowledged(0).map(|entry| entry.seq_num()), Some(0));
+    }
+
+    #[test]
+    fn test_high_water_mark() {
+        let error_registry = Arc::new(error::ErrorRegistry::new());
+        let mut queue = SolutionQueue::new().with_limits(4, 2);
+        queue.set_error_registry(error_registry.clone(), "pool");
+        let block = &test_utils::TEST_BLOCKS[0];
+
+        queue.push(block.into());
+        assert!(!queue.is_alert_raised());
+        assert!(error_registry.list().is_empty());
+
+        // crossing the high-water mark raises the alert before any solution is dropped
+        queue.push(block.into());
+        assert!(queue.is_alert_raised());
+        assert_eq!(queue.dropped(), 0);
+        let conditions = error_registry.list();
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(
+            conditions[0].kind,
+            error::ConditionKind::SolutionQueueBacklog
+        );
+        assert_eq!(conditions[0].source, "pool");
+
+        // the alert is not repeated while the queue stays above the mark
+        queue.push(block.into());
+        queue.push(block.into());
+        assert_eq!(queue.dropped(), 0);
+        assert_eq!(error_registry.list()[0].count, 1);
+
+        // full queue drops the oldest solution and hands it over to the caller
+        let (seq_num, evicted) = queue.push_evicting(block.into());
+        assert_eq!(seq_num, 4);
+        assert_eq!(evicted.map(|entry| entry.seq_num()), Some(0));
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.dropped(), 1);
+        assert!(queue.pop_acknowledged(0).is_none());
+        assert_eq!(
+            queue.pop_acknowledged(1).map(|entry| entry.seq_num()),
+            Some(1)
+        );
+
+        // the alert is re-armed after the backlog is processed
+        for seq_num in 2..4 {
+            assert!(queue.pop_acknowledged(seq_num).is_some());
+        }
+        assert!(!queue.is_alert_raised());
+        queue.push(block.into());
+        assert!(queue.is_alert_raised());
+        assert_eq!(error_registry.list()[0].count, 2);
+    }
+
+    #[test]
+    fn test_dropped_summary() {
+        let mut queue = SolutionQueue::new().with_limits(4, 2);
+        let block = &test_utils::TEST_BLOCKS[0];
+
+        // single stall drops many solutions which are counted only
+        for _ in 0..10 {
+            queue.push(block.into());
+        }
+        assert_eq!(queue.dropped(), 6);
+        assert_eq!(queue.backlog_dropped, 6);
+
+        // the drops are summarized once the backlog is processed
+        let first_seq_num = queue.pop_acknowledged(9).map(|entry| entry.seq_num());
+        assert_eq!(first_seq_num, Some(6));
+        assert_eq!(queue.backlog_dropped, 6);
+        assert!(queue.pop_acknowledged(9).is_some());
+        assert!(queue.pop_acknowledged(9).is_some());
+        assert_eq!(queue.backlog_dropped, 0);
+
+        // next stall is counted on its own
+        queue.clear();
+        assert_eq!(queue.backlog_dropped, 0);
+        for _ in 0..5 {
+            queue.push(block.into());
+        }
+        assert_eq!(queue.dropped(), 7);
+        assert_eq!(queue.backlog_dropped, 1);
+    }
+}
@@ -25,6 +25,7 @@ pub mod telemetry;
 
 use ii_logging::macros::*;
 
+use crate::client;
 use crate::error;
 use crate::hal;
 use crate::job;
@@ -46,7 +47,6 @@ use futures::lock::Mutex;
 use ii_async_compat::prelude::*;
 use ii_async_compat::select;
 
-use std::collections::VecDeque;
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Mutex as StdMutex;
@@ -79,6 +79,7 @@ pub struct ConnectionDetails {
     pub user: String,
     pub host: String,
     pub port: u16,
+    pub fragment: Option<String>,
 }
 
 impl ConnectionDetails {
@@ -88,12 +89,20 @@ impl ConnectionDetails {
             user: descriptor.user.clone(),
             host: descriptor.host.clone(),
             port: descriptor.port(),
+            fragment: descriptor.fragment.clone(),
         }
     }
 
     fn get_host_and_port(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    fn try_enable_submission_filter(&self) -> bool {
+        self.fragment
+            .as_ref()
+            .and_then(|fragment| fragment.find("allow-duplicates"))
+            .is_none()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -174,16 +183,39 @@ impl job::Bitcoin for StratumJob {
     }
 }
 
-/// Queue that contains pairs of solution and its assigned sequence number. It is our responsibility
-/// to keep the sequence number monotonic so that we as a stratum V2 client can easily process bulk
-/// acknowledgements. The sequence number type has been selected as u32 to match
-/// up with the protocol.
-type SolutionQueue = Mutex<VecDeque<(work::Solution, u32)>>;
+/// Standard mining channel opened with the remote server
+/// The server manages block templates of the channel so only block header fields are received
+#[derive(Debug, Clone, Copy)]
+struct ChannelSession {
+    channel_id: u32,
+    /// Group channel which the channel belongs to (the jobs can be addressed to the whole group)
+    group_channel_id: u32,
+    /// Initial target negotiated for the channel
+    target: ii_bitcoin::Target,
+}
+
+impl ChannelSession {
+    /// Check that a message with `channel_id` is intended for this channel
+    fn is_addressed(&self, channel_id: u32) -> bool {
+        channel_id == self.channel_id || channel_id == self.group_channel_id
+    }
+}
+
+impl From<&OpenStandardMiningChannelSuccess> for ChannelSession {
+    fn from(success_msg: &OpenStandardMiningChannelSuccess) -> Self {
+        Self {
+            channel_id: success_msg.channel_id,
+            group_channel_id: success_msg.group_channel_id,
+            target: success_msg.target.into(),
+        }
+    }
+}
 
 /// Helper task for `StratumClient` that implements Stratum V2 visitor which processes incoming
 /// messages from remote server.
 struct StratumEventHandler {
     client: Arc<StratumClient>,
+    session: ChannelSession,
     all_jobs: HashMap<u32, NewMiningJob>,
     current_prevhash_msg: Option<SetNewPrevHash>,
     /// Mining target for the next job that is to be solved
@@ -191,13 +223,26 @@ struct StratumEventHandler {
 }
 
 impl StratumEventHandler {
-    pub fn new(client: Arc<StratumClient>, current_target: ii_bitcoin::Target) -> Self {
+    fn new(client: Arc<StratumClient>, session: ChannelSession) -> Self {
         Self {
             client,
+            session,
             all_jobs: Default::default(),
             current_prevhash_msg: None,
-            current_target,
+            current_target: session.target,
+        }
+    }
+
+    /// Check that message belongs to the opened channel, messages for other channels are dropped
+    fn is_own_channel(&self, channel_id: u32, message: &str) -> bool {
+        if self.session.is_addressed(channel_id) {
+            return true;
         }
+        warn!(
+            "Stratum: ignoring {} for unknown channel {} (opened channel {})",
+            message, channel_id, self.session.channel_id
+        );
+        false
     }
 
     /// Convert new mining job message into StratumJob and send it down the line for solving.
@@ -228,16 +273,23 @@ impl StratumEventHandler {
 
     async fn process_accepted_shares(&self, success_msg: &SubmitSharesSuccess) {
         let now = std::time::Instant::now();
-        while let Some((solution, seq_num)) = self.client.solutions.lock().await.pop_front() {
+        while let Some(entry) = self
+            .client
+            .solutions
+            .lock()
+            .await
+            .pop_acknowledged(success_msg.last_seq_num)
+        {
+            let (solution, seq_num) = (entry.solution, entry.seq_num());
             info!(
                 "Stratum: accepted solution #{} with nonce={:08x}",
                 seq_num,
                 solution.nonce()
             );
+            solution.resolve(work::SubmitOutcome::Accepted);
             self.client
                 .client_stats
-                .accepted
-                .account_solution(&solution.job_target(), now)
+                .account_accepted(&solution, now)
                 .await;
             if success_msg.last_seq_num == seq_num {
                 // all accepted solutions have been found
@@ -252,13 +304,23 @@ impl StratumEventHandler {
 
     async fn process_rejected_shares(&self, error_msg: &SubmitSharesError) {
         let now = std::time::Instant::now();
-        while let Some((solution, seq_num)) = self.client.solutions.lock().await.pop_front() {
+        while let Some(entry) = self
+            .client
+            .solutions
+            .lock()
+            .await
+            .pop_acknowledged(error_msg.seq_num)
+        {
+            let (solution, seq_num) = (entry.solution, entry.seq_num());
             if error_msg.seq_num == seq_num {
                 info!(
                     "Stratum: rejected solution #{} with nonce={:08x}!",
                     seq_num,
                     solution.nonce()
                 );
+                solution.resolve(work::SubmitOutcome::Rejected(
+                    client::rejection::RejectReason::from_code(&error_msg.code.to_string()),
+                ));
                 self.client
                     .client_stats
                     .rejected
@@ -274,10 +336,10 @@ impl StratumEventHandler {
                     seq_num,
                     solution.nonce()
                 );
+                solution.resolve(work::SubmitOutcome::Accepted);
                 self.client
                     .client_stats
-                    .accepted
-                    .account_solution(&solution.job_target(), now)
+                    .account_accepted(&solution, now)
                     .await;
                 warn!(
                     "Stratum: the solution #{} precedes rejected solution #{}!",
@@ -308,6 +370,9 @@ impl Handler for StratumEventHandler {
     //      - flush all other jobs
 
     async fn visit_new_mining_job(&mut self, _header: &Header, job_msg: &NewMiningJob) {
+        if !self.is_own_channel(job_msg.channel_id, "new mining job") {
+            return;
+        }
         // all jobs since last `prevmsg` have to be stored in job table
         self.all_jobs.insert(job_msg.job_id, job_msg.clone());
         // TODO: close connection when maximal capacity of `all_jobs` has been reached
@@ -325,6 +390,9 @@ impl Handler for StratumEventHandler {
     }
 
     async fn visit_set_new_prev_hash(&mut self, _header: &Header, prevhash_msg: &SetNewPrevHash) {
+        if !self.is_own_channel(prevhash_msg.channel_id, "new prevhash") {
+            return;
+        }
         self.current_prevhash_msg.replace(prevhash_msg.clone());
 
         // find the future job with ID referenced in prevhash_msg
@@ -346,7 +414,9 @@ impl Handler for StratumEventHandler {
     }
 
     async fn visit_set_target(&mut self, _header: &Header, target_msg: &SetTarget) {
-        self.update_target(target_msg.max_target);
+        if self.is_own_channel(target_msg.channel_id, "new target") {
+            self.update_target(target_msg.max_target);
+        }
     }
 
     async fn visit_submit_shares_success(
@@ -403,7 +473,6 @@ impl<T> FrameStream for T where
 struct StratumSolutionHandler<S> {
     client: Arc<StratumClient>,
     connection_tx: Arc<Mutex<S>>,
-    seq_num: u32,
 }
 
 impl<S, E> StratumSolutionHandler<S>
@@ -419,30 +488,32 @@ where
         Self {
             client,
             connection_tx,
-            seq_num: 0,
         }
     }
 
     async fn process_solution(&mut self, solution: work::Solution) -> error::Result<()> {
         let job: &StratumJob = solution.job();
+        let (channel_id, job_id) = (job.channel_id, job.id);
+        let (nonce, ntime, version) = (solution.nonce(), solution.time(), solution.version());
 
-        let seq_num = self.seq_num;
-        self.seq_num = self.seq_num.wrapping_add(1);
+        if !self.client.submitted.lock().await.try_submit(&solution) {
+            warn!(
+                "Stratum: suppressed duplicate solution with nonce={:08x} and ntime={:08x}",
+                nonce, ntime
+            );
+            return Ok(());
+        }
+        // store solution for future server acknowledge and get its sequence number
+        let seq_num = self.client.solutions.lock().await.push(solution);
 
         let share_msg = SubmitSharesStandard {
-            channel_id: job.channel_id,
+            channel_id,
             seq_num,
-            job_id: job.id,
-            nonce: solution.nonce(),
-            ntime: solution.time(),
-            version: solution.version(),
+            job_id,
+            nonce,
+            ntime,
+            version,
         };
-        // store solution with sequence number for future server acknowledge
-        self.client
-            .solutions
-            .lock()
-            .await
-            .push_back((solution, seq_num));
         // send solutions back to the stratum server
         StratumClient::send_msg(&self.connection_tx, share_msg)
             .await
@@ -454,7 +525,7 @@ where
 
 struct StratumConnectionHandler {
     client: Arc<StratumClient>,
-    init_target: ii_bitcoin::Target,
+    session: Option<ChannelSession>,
     status: Option<error::Result<()>>,
 }
 
@@ -462,7 +533,7 @@ impl StratumConnectionHandler {
     pub fn new(client: Arc<StratumClient>) -> Self {
         Self {
             client,
-            init_target: Default::default(),
+            session: None,
             status: None,
         }
     }
@@ -569,12 +640,13 @@ impl StratumConnectionHandler {
         Ok(client_framed_stream)
     }
 
-    /// Starts mining session and provides the initial target negotiated by the upstream endpoint
+    /// Starts mining session and provides the channel with initial target negotiated by the
+    /// upstream endpoint
     async fn init_mining_session<R, S>(
         mut self,
         connection_rx: &mut R,
         connection_tx: Arc<Mutex<S>>,
-    ) -> error::Result<ii_bitcoin::Target>
+    ) -> error::Result<ChannelSession>
     where
         R: FrameStream,
         S: FrameSink,
@@ -586,7 +658,9 @@ impl StratumConnectionHandler {
             .await
             .context("Cannot open stratum channel")?;
 
-        Ok(self.init_target)
+        Ok(self
+            .session
+            .expect("BUG: missing session of successfully opened channel"))
     }
 }
 
@@ -614,7 +688,14 @@ impl Handler for StratumConnectionHandler {
         _header: &Header,
         success_msg: &OpenStandardMiningChannelSuccess,
     ) {
-        self.init_target = success_msg.target.into();
+        let session = ChannelSession::from(success_msg);
+        info!(
+            "Stratum: opened standard channel {} with target {} diff={}",
+            session.channel_id,
+            session.target,
+            session.target.get_difficulty()
+        );
+        self.session = Some(session);
         self.status = Ok(()).into();
     }
 
@@ -662,7 +743,9 @@ pub struct StratumClient {
     // Last job has to be weak reference to prevent circular reference (the `StratumJob` keeps
     // reference to `StratumClient`)
     last_job: Mutex<Option<Arc<StratumJob>>>,
-    solutions: SolutionQueue,
+    solutions: Mutex<client::SolutionQueue>,
+    /// Hashes of submitted solutions which are kept across reconnects
+    submitted: Mutex<client::SubmissionFilter>,
     job_sender: Mutex<job::Sender>,
     solution_receiver: Mutex<job::SolutionReceiver>,
     /// Frames received from this channel will be forwarded to the network connection
@@ -723,6 +806,11 @@ impl StratumClient {
         )>,
     ) -> Self {
         let (stop_sender, stop_receiver) = mpsc::channel(1);
+        let submitted = if connection_details.try_enable_submission_filter() {
+            client::SubmissionFilter::default()
+        } else {
+            client::SubmissionFilter::disabled()
+        };
 
         // Extract the both channel endpoints that connect the client with the stratum extension
         // or populate it with dummy endpoints. That way we can handle the endpoints uniformly
@@ -744,7 +832,8 @@ impl StratumClient {
             stop_sender: stop_sender,
             stop_receiver: Mutex::new(stop_receiver),
             last_job: Mutex::new(None),
-            solutions: Mutex::new(VecDeque::new()),
+            solutions: Mutex::new(client::SolutionQueue::new()),
+            submitted: Mutex::new(submitted),
             job_sender: Mutex::new(solver.job_sender),
             solution_receiver: Mutex::new(solver.solution_receiver),
             extension_channel_receiver: Mutex::new(extension_channel_receiver),
@@ -886,12 +975,12 @@ impl StratumClient {
         self: Arc<Self>,
         connection_rx: R,
         connection_tx: Arc<Mutex<S>>,
-        init_target: ii_bitcoin::Target,
+        session: ChannelSession,
     ) where
         R: FrameStream,
         S: FrameSink,
     {
-        let event_handler = StratumEventHandler::new(self.clone(), init_target);
+        let event_handler = StratumEventHandler::new(self.clone(), session);
         // TODO consider changing main_loop to accept Arc<Self> and build the solution_handler
         //  along with solution handler communication channels inside of the main_loop.
         let client = self.clone();
@@ -925,10 +1014,10 @@ impl StratumClient {
                     .map_err(|_| {
                         error::ErrorKind::General("Init mining session timeout".to_string()).into()
                     }) {
-                    Ok(Ok(init_target)) => {
+                    Ok(Ok(session)) => {
                         if self.status.initiate_running() {
                             self.clone()
-                                .run_job_solver(framed_stream, framed_sink, init_target)
+                                .run_job_solver(framed_stream, framed_sink, session)
                                 .await;
                         }
                     }
@@ -1015,6 +1104,13 @@ impl node::Client for StratumClient {
         }
     }
 
+    async fn set_error_registry(&self, error_registry: Arc<error::ErrorRegistry>) {
+        self.solutions
+            .lock()
+            .await
+            .set_error_registry(error_registry, self.to_string());
+    }
+
     async fn get_last_job(&self) -> Option<Arc<dyn job::Bitcoin>> {
         self.last_job
             .lock()
@@ -1043,3 +1139,143 @@ impl fmt::Display for StratumClient {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::node::Client as _;
+    use crate::test_utils;
+
+    const CHANNEL_ID: u32 = 7;
+
+    fn build_connection_details(fragment: Option<&str>) -> ConnectionDetails {
+        ConnectionDetails {
+            protocol: ClientProtocol::StratumV2Insecure,
+            user: "user".to_string(),
+            host: "localhost".to_string(),
+            port: 3336,
+            fragment: fragment.map(str::to_string),
+        }
+    }
+
+    fn build_client() -> Arc<StratumClient> {
+        let (_, solution_receiver) = mpsc::unbounded();
+        let solver = job::Solver::new(Arc::new(work::EngineSender::new(None)), solution_receiver);
+        Arc::new(StratumClient::new(
+            build_connection_details(None),
+            None,
+            solver,
+            None,
+        ))
+    }
+
+    #[test]
+    fn test_submission_filter_config() {
+        let try_enable =
+            |fragment| build_connection_details(fragment).try_enable_submission_filter();
+
+        assert!(try_enable(None));
+        assert!(try_enable(Some("keepalive=90")));
+        assert!(!try_enable(Some("allow-duplicates")));
+    }
+
+    /// Pass message through its binary frame to the handler as it would be received from network
+    async fn receive<M>(handler: &mut dyn Handler, message: M)
+    where
+        M: TryInto<<Framing as ii_wire::Framing>::Tx, Error = <Framing as ii_wire::Framing>::Error>,
+    {
+        let frame = message.try_into().expect("BUG: cannot build frame");
+        build_message_from_frame(frame)
+            .expect("BUG: cannot decode frame")
+            .accept(handler)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_standard_channel_job() {
+        let client = build_client();
+        let block = test_utils::TEST_BLOCKS[0];
+        let init_target = ii_bitcoin::Target::from_pool_difficulty(4);
+
+        // session setup
+        let mut connection_handler = StratumConnectionHandler::new(client.clone());
+        receive(
+            &mut connection_handler,
+            SetupConnectionSuccess {
+                used_version: 2,
+                flags: 0,
+            },
+        )
+        .await;
+        assert!(connection_handler.status.take().expect("no status").is_ok());
+        receive(
+            &mut connection_handler,
+            OpenStandardMiningChannelSuccess {
+                req_id: 10,
+                channel_id: CHANNEL_ID,
+                target: init_target.into(),
+                extranonce_prefix: Bytes0_32::new(),
+                group_channel_id: CHANNEL_ID,
+            },
+        )
+        .await;
+        assert!(connection_handler.status.take().expect("no status").is_ok());
+        let session = connection_handler.session.expect("BUG: missing session");
+        assert_eq!(session.channel_id, CHANNEL_ID);
+
+        // the job is started after the prevhash
+        let mut event_handler = StratumEventHandler::new(client.clone(), session);
+        receive(
+            &mut event_handler,
+            NewMiningJob {
+                channel_id: CHANNEL_ID,
+                job_id: 1,
+                future_job: true,
+                version: block.version,
+                merkle_root: Uint256Bytes(block.merkle_root.into_inner()),
+            },
+        )
+        .await;
+        assert!(client.get_last_job().await.is_none());
+        receive(
+            &mut event_handler,
+            SetNewPrevHash {
+                channel_id: CHANNEL_ID,
+                job_id: 1,
+                prev_hash: Uint256Bytes(block.previous_hash.into_inner()),
+                min_ntime: block.time,
+                nbits: block.bits,
+            },
+        )
+        .await;
+
+        let job = client.get_last_job().await.expect("BUG: missing job");
+        assert_eq!(job.target(), init_target);
+        let header = ii_bitcoin::BlockHeader {
+            version: job.version(),
+            previous_hash: job.previous_hash().into_inner(),
+            merkle_root: job.merkle_root().into_inner(),
+            time: job.time(),
+            bits: job.bits(),
+            nonce: block.nonce,
+        };
+        assert_eq!(header.hash(), block.hash);
+
+        // the difficulty is changed only by target of the opened channel
+        let new_target = ii_bitcoin::Target::from_pool_difficulty(16);
+        for channel_id in &[CHANNEL_ID + 1, CHANNEL_ID] {
+            receive(
+                &mut event_handler,
+                SetTarget {
+                    channel_id: *channel_id,
+                    max_target: new_target.into(),
+                },
+            )
+            .await;
+            assert_eq!(
+                event_handler.current_target == new_target,
+                *channel_id == CHANNEL_ID
+            );
+        }
+    }
+}
@@ -24,9 +24,11 @@
 
 use ii_logging::macros::*;
 
+use crate::client::{self, rejection};
 use crate::error;
 use crate::job;
 use crate::node;
+use crate::retry::RetryPolicy;
 use crate::stats;
 use crate::sync;
 use crate::work;
@@ -43,11 +45,12 @@ use futures::channel::mpsc;
 use futures::lock::Mutex;
 use ii_async_compat::prelude::*;
 use ii_async_compat::select;
+use tokio::time::delay_for;
 
-use std::collections::VecDeque;
 use std::fmt;
 use std::net::ToSocketAddrs;
-use std::sync::{Arc, Weak};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, Weak};
 use std::time;
 
 use ii_stratum::v2::framing::{Framing, Header};
@@ -61,7 +64,9 @@ use ii_stratum::v2::types::DeviceInfo;
 use ii_stratum::v2::types::*;
 use ii_stratum::v2::{build_message_from_frame, Handler};
 use ii_stratum::{v1, v2};
-use ii_stratum_proxy::translation::{V2ToV1Translation, V2ToV1TranslationOptions};
+use ii_stratum_proxy::translation::{
+    BlockHeight, ExtranonceChange, SessionState, V2ToV1Translation, V2ToV1TranslationOptions,
+};
 use ii_wire::Connection;
 
 use std::collections::HashMap;
@@ -99,6 +104,164 @@ impl ConnectionDetails {
                 .and_then(|fragment| fragment.find("xnsub"))
                 .is_some()
     }
+
+    fn try_enable_rejection_logging(&self) -> bool {
+        self.fragment
+            .as_ref()
+            .and_then(|fragment| fragment.find("log-rejected"))
+            .is_some()
+    }
+
+    fn try_enable_stale_resubmission(&self) -> bool {
+        self.fragment
+            .as_ref()
+            .and_then(|fragment| fragment.find("resubmit-stale"))
+            .is_some()
+    }
+
+    /// Solutions of jobs abandoned by the pool are dropped unless the fragment contains
+    /// `clean-jobs=keep`
+    fn get_clean_jobs_policy(&self) -> CleanJobsPolicy {
+        match self
+            .fragment
+            .as_ref()
+            .and_then(|fragment| fragment.find("clean-jobs=keep"))
+        {
+            Some(_) => CleanJobsPolicy::Keep,
+            None => CleanJobsPolicy::Invalidate,
+        }
+    }
+
+    fn try_enable_submission_filter(&self) -> bool {
+        self.fragment
+            .as_ref()
+            .and_then(|fragment| fragment.find("allow-duplicates"))
+            .is_none()
+    }
+
+    /// Parse non-zero number from fragment in format `<key><number>`
+    fn get_number(&self, key: &str) -> Option<u64> {
+        let fragment = self.fragment.as_ref()?;
+        let value = &fragment[fragment.find(key)? + key.len()..];
+        let number = value
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(value, |end| &value[..end]);
+        match number.parse() {
+            Ok(0) | Err(_) => None,
+            Ok(number) => Some(number),
+        }
+    }
+
+    /// Parse non-zero interval from fragment in format `<key><seconds>`
+    fn get_seconds(&self, key: &str) -> Option<time::Duration> {
+        self.get_number(key).map(time::Duration::from_secs)
+    }
+
+    /// Parse limit of submissions waiting for acknowledgement from fragment in format
+    /// `max-pending=<count>`
+    fn get_max_pending(&self) -> Option<usize> {
+        self.get_number("max-pending=").map(|count| count as usize)
+    }
+
+    /// Parse limits of the solution queue from fragment in format `queue-size=<count>` and
+    /// `queue-alert=<count>`. The alert about stalled submission is raised when the number of
+    /// solutions waiting for acknowledgement reaches the latter and it has to be lower than the
+    /// queue size. Invalid limits are ignored.
+    fn get_queue_limits(&self) -> Option<(usize, usize)> {
+        let capacity = self.get_number("queue-size=").map(|count| count as usize);
+        let high_water_mark = self.get_number("queue-alert=").map(|count| count as usize);
+        if capacity.is_none() && high_water_mark.is_none() {
+            return None;
+        }
+        let capacity = capacity.unwrap_or(client::SolutionQueue::DEFAULT_CAPACITY);
+        let high_water_mark = high_water_mark
+            .unwrap_or_else(|| client::SolutionQueue::default_high_water_mark(capacity));
+        Some((capacity, high_water_mark))
+            .filter(|_| high_water_mark > 0 && high_water_mark < capacity)
+    }
+
+    /// Parse idle period of V1 connection after which a keepalive is sent from fragment in format
+    /// `keepalive=<seconds>`
+    fn get_keepalive(&self) -> Option<time::Duration> {
+        self.get_seconds("keepalive=")
+    }
+
+    /// Parse minimal difficulty of shares submitted to the pool from fragment in format
+    /// `min-diff=<difficulty>`. Lower pool difficulty is replaced by internal filter.
+    fn get_min_difficulty(&self) -> Option<u64> {
+        self.get_number("min-diff=")
+    }
+
+    /// Minimal difficulty is also suggested to the pool when the fragment contains `suggest-diff`
+    fn get_suggested_difficulty(&self) -> Option<u32> {
+        self.fragment
+            .as_ref()
+            .and_then(|fragment| fragment.find("suggest-diff"))
+            .and_then(|_| self.get_min_difficulty())
+            .map(|difficulty| difficulty.min(std::u32::MAX as u64) as u32)
+    }
+
+    /// Parse maximal job age from fragment in format `job-expiry=<seconds>`
+    fn get_job_expiry(&self) -> Option<time::Duration> {
+        self.get_seconds("job-expiry=")
+    }
+
+    /// Parse tolerated difference of job time from system time from fragment in format
+    /// `clock-drift=<seconds>`
+    fn get_clock_drift(&self) -> time::Duration {
+        self.get_seconds("clock-drift=")
+            .unwrap_or(client::ClockDrift::DEFAULT_MAX_DRIFT)
+    }
+
+    /// Parse horizon of submit-ahead from fragment in format `submit-ahead=<seconds>`
+    fn get_submit_ahead(&self) -> Option<time::Duration> {
+        self.get_seconds("submit-ahead=")
+    }
+
+    /// Parse prefix of per-chain workers from fragment in format `chain-worker=<prefix>`. Shares
+    /// found by chain `n` are then submitted as worker `<user>.<prefix><n>`.
+    fn get_chain_worker_prefix(&self) -> Option<String> {
+        const CHAIN_WORKER: &str = "chain-worker=";
+
+        let fragment = self.fragment.as_ref()?;
+        let value = &fragment[fragment.find(CHAIN_WORKER)? + CHAIN_WORKER.len()..];
+        let prefix = value
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .map_or(value, |end| &value[..end]);
+        Some(prefix.to_string()).filter(|prefix| !prefix.is_empty())
+    }
+
+    /// Parse Bitcoin network of the pool from fragment in format `network=<name>` (e.g. for
+    /// development against testnet or regtest). Unknown networks fall back to mainnet.
+    fn get_network(&self) -> ii_bitcoin::Network {
+        const NETWORK: &str = "network=";
+
+        let value = self.fragment.as_ref().and_then(|fragment| {
+            fragment
+                .find(NETWORK)
+                .map(|start| &fragment[start + NETWORK.len()..])
+        });
+        match value {
+            None => Default::default(),
+            Some(value) => {
+                let name = value
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .map_or(value, |end| &value[..end]);
+                name.parse().unwrap_or_default()
+            }
+        }
+    }
+}
+
+/// Treatment of jobs abandoned by the pool with V1 `clean_jobs` flag (or with a new block)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CleanJobsPolicy {
+    /// All solutions of the previous jobs are dropped including the ones found for rolled
+    /// versions (the validity is shared by all midstates of the job)
+    Invalidate,
+    /// Solutions of the previous jobs are still submitted because some pools accept them for
+    /// a short time
+    Keep,
 }
 
 #[derive(Debug, Clone)]
@@ -112,6 +275,14 @@ pub struct StratumJob {
     time: u32,
     bits: u32,
     target: ii_bitcoin::Target,
+    /// Target set by the pool before it has been raised by the difficulty floor
+    pool_target: ii_bitcoin::Target,
+    /// Targets of hash chains with difficulty independent of the channel
+    solver_targets: Arc<HashMap<usize, ii_bitcoin::Target>>,
+    /// Flag shared by all jobs received since the last clean jobs
+    valid: Arc<AtomicBool>,
+    block_height: Option<u64>,
+    network: ii_bitcoin::Network,
 }
 
 impl StratumJob {
@@ -120,7 +291,9 @@ impl StratumJob {
         job_msg: &NewMiningJob,
         prevhash_msg: &SetNewPrevHash,
         target: ii_bitcoin::Target,
+        block_height: Option<u64>,
     ) -> Self {
+        let network = client.connection_details.get_network();
         Self {
             client: Arc::downgrade(&client),
             id: job_msg.job_id,
@@ -133,8 +306,34 @@ impl StratumJob {
             time: prevhash_msg.min_ntime,
             bits: prevhash_msg.nbits,
             target,
+            pool_target: target,
+            solver_targets: Default::default(),
+            valid: Arc::new(AtomicBool::new(true)),
+            block_height,
+            network,
         }
     }
+
+    /// Share validity with other jobs so that all of them can be invalidated at once
+    pub fn with_validity(mut self, valid: Arc<AtomicBool>) -> Self {
+        self.valid = valid;
+        self
+    }
+
+    /// Keep target set by the pool when the job target has been raised by the difficulty floor
+    pub fn with_pool_target(mut self, pool_target: ii_bitcoin::Target) -> Self {
+        self.pool_target = pool_target;
+        self
+    }
+
+    /// Use independent targets for solutions of particular hash chains
+    pub fn with_solver_targets(
+        mut self,
+        solver_targets: Arc<HashMap<usize, ii_bitcoin::Target>>,
+    ) -> Self {
+        self.solver_targets = solver_targets;
+        self
+    }
 }
 
 impl job::Bitcoin for StratumJob {
@@ -170,20 +369,29 @@ impl job::Bitcoin for StratumJob {
         self.target
     }
 
+    fn solver_target(&self, solver_id: Option<usize>) -> ii_bitcoin::Target {
+        solver_id
+            .and_then(|solver_id| self.solver_targets.get(&solver_id))
+            .cloned()
+            .unwrap_or(self.target)
+    }
+
+    fn pool_target(&self) -> ii_bitcoin::Target {
+        self.pool_target
+    }
+
     fn is_valid(&self) -> bool {
-        // TODO: currently there is no easy way to detect the job is valid -> we have to check
-        //  its presence in the registry. The inequality below was possible in the previous
-        //  iteration of the protocol
-        // self.block_height >= self.current_block_height.load(Ordering::Relaxed)
-        true
+        self.valid.load(Ordering::Relaxed)
+    }
+
+    fn block_height(&self) -> Option<u64> {
+        self.block_height
     }
-}
 
-/// Queue that contains pairs of solution and its assigned sequence number. It is our responsibility
-/// to keep the sequence number monotonic so that we as a stratum V2 client can easily process bulk
-/// acknowledgements. The sequence number type has been selected as u32 to match
-/// up with the protocol.
-type SolutionQueue = Mutex<VecDeque<(work::Solution, u32)>>;
+    fn network(&self) -> ii_bitcoin::Network {
+        self.network
+    }
+}
 
 /// Helper task for `StratumClient` that implements Stratum V2 visitor which processes incoming
 /// messages from remote server.
@@ -193,58 +401,212 @@ struct StratumEventHandler {
     current_prevhash_msg: Option<SetNewPrevHash>,
     /// Mining target for the next job that is to be solved
     current_target: ii_bitcoin::Target,
+    /// Channel target set by the pool which may be easier than `current_target`
+    pool_target: ii_bitcoin::Target,
+    /// Targets set for channels of particular hash chains (see `StratumClient::chain_workers`)
+    /// which override `current_target` for their solutions
+    solver_targets: Arc<HashMap<usize, ii_bitcoin::Target>>,
+    clean_jobs_policy: CleanJobsPolicy,
+    /// Validity of all jobs sent since the last clean jobs
+    job_validity: Arc<AtomicBool>,
+    /// Try to resubmit solutions rejected as stale with adjusted nTime
+    resubmit_stale: bool,
+    /// Solutions prepared for resubmission
+    resubmissions: Vec<work::Solution>,
+    /// Logger with full context of rejected shares
+    rejection_logger: rejection::Logger,
+    /// Pool target is currently replaced by the difficulty floor of the client
+    floor_engaged: bool,
 }
 
 impl StratumEventHandler {
     pub fn new(client: Arc<StratumClient>, current_target: ii_bitcoin::Target) -> Self {
-        Self {
+        let resubmit_stale = client.connection_details.try_enable_stale_resubmission();
+        let clean_jobs_policy = client.connection_details.get_clean_jobs_policy();
+        let rejection_logger =
+            rejection::Logger::new(client.connection_details.try_enable_rejection_logging());
+        let mut event_handler = Self {
             client,
             all_jobs: Default::default(),
             current_prevhash_msg: None,
             current_target,
+            pool_target: current_target,
+            solver_targets: Default::default(),
+            clean_jobs_policy,
+            job_validity: Arc::new(AtomicBool::new(true)),
+            resubmit_stale,
+            resubmissions: vec![],
+            rejection_logger,
+            floor_engaged: false,
+        };
+        event_handler.current_target = event_handler.filter_target(current_target);
+        event_handler
+    }
+
+    /// Return all solutions which should be submitted again
+    fn take_resubmissions(&mut self) -> Vec<work::Solution> {
+        std::mem::replace(&mut self.resubmissions, vec![])
+    }
+
+    /// Prepare rejected stale `solution` for resubmission with adjusted nTime when it is possible
+    fn try_resubmit(
+        &mut self,
+        solution: &work::Solution,
+        reason: &rejection::RejectReason,
+    ) -> bool {
+        if !self.resubmit_stale || !reason.is_stale() {
+            return false;
+        }
+        match solution.adjust_time() {
+            Some(adjusted_solution) => {
+                info!(
+                    "Stratum: resubmitting stale solution with nonce={:08x} and ntime={:08x}",
+                    adjusted_solution.nonce(),
+                    adjusted_solution.time()
+                );
+                self.resubmissions.push(adjusted_solution);
+                true
+            }
+            None => false,
         }
     }
 
     /// Convert new mining job message into StratumJob and send it down the line for solving.
     ///
     /// * `job_msg` - job message used as a base for the StratumJob
-    async fn update_job(&mut self, job_msg: &NewMiningJob) {
-        let job = Arc::new(StratumJob::new(
+    async fn update_job(&mut self, job_msg: &NewMiningJob, cause: work::GenerationCause) {
+        // the first job after extranonce change is built with the new extranonce
+        let cause = if self.client.extranonce_change.lock().await.take() {
+            work::GenerationCause::ExtranonceChange
+        } else {
+            cause
+        };
+        if (cause == work::GenerationCause::CleanJobs
+            || cause == work::GenerationCause::ExtranonceChange)
+            && self.clean_jobs_policy == CleanJobsPolicy::Invalidate
+        {
+            self.invalidate_jobs();
+        }
+        let block_height = self.client.block_height.lock().await.get();
+        let job = Arc::new(self.build_job(job_msg, block_height));
+        if let Some(height) = block_height {
+            info!("Stratum: mining job {} at block height {}", job.id, height);
+        }
+        self.client.update_last_job(job.clone()).await;
+        self.client
+            .job_expiry
+            .lock()
+            .await
+            .job_received(time::Instant::now());
+        self.client
+            .clock_drift
+            .lock()
+            .await
+            .job_received(job.time, time::SystemTime::now());
+        self.client
+            .job_sender
+            .lock()
+            .await
+            .send_with_cause(job, cause);
+    }
+
+    /// Invalidate all jobs sent so far so that none of their solutions is submitted
+    fn invalidate_jobs(&mut self) {
+        self.job_validity.store(false, Ordering::Relaxed);
+        self.job_validity = Arc::new(AtomicBool::new(true));
+    }
+
+    fn build_job(&self, job_msg: &NewMiningJob, block_height: Option<u64>) -> StratumJob {
+        StratumJob::new(
             self.client.clone(),
             job_msg,
             self.current_prevhash_msg
                 .as_ref()
                 .expect("TODO: no prevhash"),
             self.current_target,
-        ));
-        self.client.update_last_job(job.clone()).await;
-        self.client.job_sender.lock().await.send(job);
+            block_height,
+        )
+        .with_pool_target(self.pool_target)
+        .with_solver_targets(self.solver_targets.clone())
+        .with_validity(self.job_validity.clone())
     }
 
-    fn update_target(&mut self, value: Uint256Bytes) {
-        let new_target: ii_bitcoin::Target = value.into();
+    /// Replace pool target which is easier than the difficulty floor of the client. Only changes
+    /// of the floor engagement are logged to not repeat the warning with every new target.
+    fn filter_target(&mut self, target: ii_bitcoin::Target) -> ii_bitcoin::Target {
+        let floor = &self.client.difficulty_floor;
+        let engaged = floor.is_engaged(&target);
+        if engaged != self.floor_engaged {
+            self.floor_engaged = engaged;
+            let network_params = self.client.connection_details.get_network().params();
+            let min_difficulty = floor
+                .min_difficulty()
+                .expect("BUG: missing minimal difficulty");
+            if engaged {
+                warn!(
+                    "Stratum: pool difficulty {} is below hardware minimum {}, filtering internally",
+                    network_params.difficulty(&target),
+                    min_difficulty
+                );
+            } else {
+                info!(
+                    "Stratum: pool difficulty {} is above hardware minimum {}, filter disengaged",
+                    network_params.difficulty(&target),
+                    min_difficulty
+                );
+            }
+        }
+        floor.filter(target)
+    }
+
+    fn update_target(&mut self, channel_id: u32, value: Uint256Bytes) {
+        let pool_target = value.into();
+        let new_target = self.filter_target(pool_target);
+        let network_params = self.client.connection_details.get_network().params();
+        if let Some(solver_id) = self.client.channel_solver_id(channel_id) {
+            info!(
+                "Stratum: changing target of chain {} to {} diff={}",
+                solver_id,
+                new_target,
+                network_params.get_difficulty(&new_target)
+            );
+            // jobs already sent keep their own copy of the targets
+            Arc::make_mut(&mut self.solver_targets).insert(solver_id, new_target);
+            return;
+        }
         info!(
             "Stratum: changing target to {} diff={}",
             new_target,
-            new_target.get_difficulty()
+            network_params.get_difficulty(&new_target)
         );
         self.current_target = new_target;
+        self.pool_target = pool_target;
     }
 
     async fn process_accepted_shares(&self, success_msg: &SubmitSharesSuccess) {
         let now = std::time::Instant::now();
-        while let Some((solution, seq_num)) = self.client.solutions.lock().await.pop_front() {
+        while let Some(entry) = self
+            .client
+            .solutions
+            .lock()
+            .await
+            .pop_acknowledged(success_msg.last_seq_num)
+        {
+            self.client.release_submission().await;
+            let (solution, seq_num) = (entry.solution, entry.seq_num());
             info!(
                 "Stratum: accepted solution #{} with nonce={:08x}",
                 seq_num,
                 solution.nonce()
             );
+            solution.resolve(work::SubmitOutcome::Accepted);
             self.client
                 .client_stats
-                .accepted
-                .account_solution(&solution.job_target(), now)
+                .account_accepted(&solution, now)
                 .await;
             if success_msg.last_seq_num == seq_num {
+                // only the last solution is acknowledged right after the server has received it
+                self.client.account_latency(entry.submitted, now);
                 // all accepted solutions have been found
                 return;
             }
@@ -255,15 +617,32 @@ impl StratumEventHandler {
         );
     }
 
-    async fn process_rejected_shares(&self, error_msg: &SubmitSharesError) {
+    async fn process_rejected_shares(&mut self, error_msg: &SubmitSharesError) {
         let now = std::time::Instant::now();
-        while let Some((solution, seq_num)) = self.client.solutions.lock().await.pop_front() {
+        while let Some(entry) = self
+            .client
+            .solutions
+            .lock()
+            .await
+            .pop_acknowledged(error_msg.seq_num)
+        {
+            self.client.release_submission().await;
+            let (solution, seq_num) = (entry.solution, entry.seq_num());
             if error_msg.seq_num == seq_num {
                 info!(
                     "Stratum: rejected solution #{} with nonce={:08x}!",
                     seq_num,
                     solution.nonce()
                 );
+                self.client.account_latency(entry.submitted, now);
+                let reason = rejection::RejectReason::from_code(&error_msg.code.to_string());
+                self.client.client_stats.reject_reasons.account(&reason);
+                self.rejection_logger.log(&solution, reason.clone());
+                if self.try_resubmit(&solution, &reason) {
+                    // the solution will be accounted after the resubmission is resolved
+                    return;
+                }
+                solution.resolve(work::SubmitOutcome::Rejected(reason));
                 self.client
                     .client_stats
                     .rejected
@@ -279,10 +658,10 @@ impl StratumEventHandler {
                     seq_num,
                     solution.nonce()
                 );
+                solution.resolve(work::SubmitOutcome::Accepted);
                 self.client
                     .client_stats
-                    .accepted
-                    .account_solution(&solution.job_target(), now)
+                    .account_accepted(&solution, now)
                     .await;
                 warn!(
                     "Stratum: the solution #{} precedes rejected solution #{}!",
@@ -325,7 +704,8 @@ impl Handler for StratumEventHandler {
         // should be dealt with in proxy, but let's put the `current_prevhash_msg` existence check
         // here anyway.
         if !job_msg.future_job && self.current_prevhash_msg.is_some() {
-            self.update_job(job_msg).await;
+            self.update_job(job_msg, work::GenerationCause::JobUpdate)
+                .await;
         }
     }
 
@@ -346,12 +726,14 @@ impl Handler for StratumEventHandler {
         self.all_jobs
             .insert(future_job_msg.job_id, future_job_msg.clone());
 
-        // and start immediately solving it
-        self.update_job(&future_job_msg).await;
+        // and start immediately solving it, the prevhash without change of the block originates
+        // from V1 `clean_jobs` flag
+        self.update_job(&future_job_msg, work::GenerationCause::CleanJobs)
+            .await;
     }
 
     async fn visit_set_target(&mut self, _header: &Header, target_msg: &SetTarget) {
-        self.update_target(target_msg.max_target);
+        self.update_target(target_msg.channel_id, target_msg.max_target);
     }
 
     async fn visit_submit_shares_success(
@@ -399,7 +781,6 @@ impl<T> FrameStream for T where
 struct StratumSolutionHandler<S> {
     client: Arc<StratumClient>,
     connection_tx: S,
-    seq_num: u32,
 }
 
 impl<S> StratumSolutionHandler<S>
@@ -413,30 +794,69 @@ where
         Self {
             client,
             connection_tx,
-            seq_num: 0,
         }
     }
 
+    /// Submit the solution or queue it when too many submissions are waiting for acknowledgement
     async fn process_solution(&mut self, solution: work::Solution) -> error::Result<()> {
+        match self.client.limit_submission(solution).await {
+            Some(solution) => self.submit_solution(solution).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Submit queued solutions for which the submission limit has been released
+    async fn process_ready_solutions(&mut self) -> error::Result<()> {
+        loop {
+            // dropped solutions release their slots for the following ones
+            let solutions = self.client.take_ready_submissions().await;
+            if solutions.is_empty() {
+                return Ok(());
+            }
+            for solution in solutions {
+                self.submit_solution(solution).await?;
+            }
+        }
+    }
+
+    async fn submit_solution(&mut self, solution: work::Solution) -> error::Result<()> {
         let job: &StratumJob = solution.job();
+        let (channel_id, job_id) = (self.client.submit_channel_id(&solution), job.id);
+        let (nonce, ntime, version) = (solution.nonce(), solution.time(), solution.version());
 
-        let seq_num = self.seq_num;
-        self.seq_num = self.seq_num.wrapping_add(1);
+        if self
+            .client
+            .job_expiry
+            .lock()
+            .await
+            .is_expired(time::Instant::now())
+        {
+            warn!(
+                "Stratum: dropped solution with nonce={:08x} because no new job has arrived",
+                nonce
+            );
+            self.client.release_submission().await;
+            return Ok(());
+        }
+        if !self.client.submitted.lock().await.try_submit(&solution) {
+            warn!(
+                "Stratum: suppressed duplicate solution with nonce={:08x} and ntime={:08x}",
+                nonce, ntime
+            );
+            self.client.release_submission().await;
+            return Ok(());
+        }
+        // store solution for future server acknowledge and get its sequence number
+        let seq_num = self.client.store_submission(solution).await;
 
         let share_msg = SubmitSharesStandard {
-            channel_id: job.channel_id,
+            channel_id,
             seq_num,
-            job_id: job.id,
-            nonce: solution.nonce(),
-            ntime: solution.time(),
-            version: solution.version(),
+            job_id,
+            nonce,
+            ntime,
+            version,
         };
-        // store solution with sequence number for future server acknowledge
-        self.client
-            .solutions
-            .lock()
-            .await
-            .push_back((solution, seq_num));
         // send solutions back to the stratum server
         StratumClient::send_msg(&mut self.connection_tx, share_msg)
             .await
@@ -639,9 +1059,33 @@ pub struct StratumClient {
     // Last job has to be week reference to prevent circular reference (the `StratumJob` keeps
     // reference to `StratumClient`)
     last_job: Mutex<Option<Weak<StratumJob>>>,
-    solutions: SolutionQueue,
+    solutions: Mutex<client::SolutionQueue>,
+    /// Hashes of submitted solutions which are kept across reconnects
+    submitted: Mutex<client::SubmissionFilter>,
+    /// Stop submitting solutions of a job which the server hasn't replaced for too long
+    job_expiry: Mutex<client::JobExpiry>,
+    /// Detection of difference between job time and system time
+    clock_drift: Mutex<client::ClockDrift>,
+    /// Height of the block being mined, it is updated by V2->V1 translation
+    block_height: Mutex<BlockHeight>,
+    /// Notification about extranonce change, it is raised by V2->V1 translation
+    extranonce_change: Mutex<ExtranonceChange>,
+    /// Session negotiated with V1 server which is requested again after reconnect
+    v1_session: Arc<StdMutex<Option<SessionState>>>,
     job_sender: Mutex<job::Sender>,
     solution_receiver: Mutex<job::SolutionReceiver>,
+    /// Smoothed round-trip time of submitted solutions
+    submission_latency: StdMutex<Option<time::Duration>>,
+    /// Submit solutions of each chain as a separate worker
+    chain_workers: bool,
+    /// Bound of submissions waiting for acknowledgement
+    submission_limit: Mutex<client::SubmissionLimit>,
+    /// Internal filter of solutions applied when the pool sets too low difficulty
+    difficulty_floor: client::DifficultyFloor,
+    /// Practical minimum of pool difficulty derived from the hardware (zero when it is unknown)
+    hardware_min_difficulty: AtomicU64,
+    /// Number of extranonce 2 bytes required by hardware which rolls extranonce on its own
+    min_extranonce2_size: AtomicUsize,
 }
 
 impl StratumClient {
@@ -649,8 +1093,44 @@ impl StratumClient {
     const EVENT_TIMEOUT: time::Duration = time::Duration::from_secs(60);
     const SEND_TIMEOUT: time::Duration = time::Duration::from_secs(2);
 
-    pub fn new(connection_details: ConnectionDetails, solver: job::Solver) -> Self {
+    /// Backoff of repeated reconnects to the server which is not reachable. The client keeps
+    /// reconnecting until it is stopped.
+    fn reconnect_policy() -> RetryPolicy {
+        RetryPolicy::new(std::usize::MAX, time::Duration::from_secs(1))
+            .with_backoff(2.0, time::Duration::from_secs(60))
+            .with_jitter(0.2)
+    }
+
+    pub fn new(connection_details: ConnectionDetails, mut solver: job::Solver) -> Self {
         let (stop_sender, stop_receiver) = mpsc::channel(1);
+        let submitted = if connection_details.try_enable_submission_filter() {
+            client::SubmissionFilter::default()
+        } else {
+            client::SubmissionFilter::disabled()
+        };
+        let job_expiry = match connection_details.get_job_expiry() {
+            Some(interval) => client::JobExpiry::new(interval),
+            None => client::JobExpiry::disabled(),
+        };
+        let clock_drift = client::ClockDrift::new(connection_details.get_clock_drift());
+        if let Some(horizon) = connection_details.get_submit_ahead() {
+            solver.solution_receiver.enable_submit_ahead(horizon);
+        }
+        let chain_workers = connection_details.get_chain_worker_prefix().is_some();
+        let submission_limit = match connection_details.get_max_pending() {
+            Some(limit) => client::SubmissionLimit::new(limit),
+            None => client::SubmissionLimit::disabled(),
+        };
+        let difficulty_floor = match connection_details.get_min_difficulty() {
+            Some(min_difficulty) => client::DifficultyFloor::new(min_difficulty),
+            None => client::DifficultyFloor::disabled(),
+        };
+        let solutions = match connection_details.get_queue_limits() {
+            Some((capacity, high_water_mark)) => {
+                client::SolutionQueue::new().with_limits(capacity, high_water_mark)
+            }
+            None => client::SolutionQueue::new(),
+        };
         Self {
             connection_details,
             status: Default::default(),
@@ -658,12 +1138,121 @@ impl StratumClient {
             stop_sender: stop_sender,
             stop_receiver: Mutex::new(stop_receiver),
             last_job: Mutex::new(None),
-            solutions: Mutex::new(VecDeque::new()),
+            solutions: Mutex::new(solutions),
+            submitted: Mutex::new(submitted),
+            job_expiry: Mutex::new(job_expiry),
+            clock_drift: Mutex::new(clock_drift),
+            block_height: Mutex::new(Default::default()),
+            extranonce_change: Mutex::new(Default::default()),
+            v1_session: Arc::new(StdMutex::new(None)),
             job_sender: Mutex::new(solver.job_sender),
             solution_receiver: Mutex::new(solver.solution_receiver),
+            submission_latency: StdMutex::new(None),
+            chain_workers,
+            submission_limit: Mutex::new(submission_limit),
+            difficulty_floor,
+            hardware_min_difficulty: AtomicU64::new(0),
+            min_extranonce2_size: AtomicUsize::new(0),
         }
     }
 
+    /// Publish depth of submissions in mining statistics
+    fn update_pending_submissions(&self, submission_limit: &client::SubmissionLimit) {
+        self.client_stats.pending_submissions.set(
+            submission_limit.outstanding(),
+            submission_limit.queued(),
+            submission_limit.dropped(),
+        );
+    }
+
+    /// Return the solution when it can be submitted right now, otherwise it is queued
+    async fn limit_submission(&self, solution: work::Solution) -> Option<work::Solution> {
+        let mut submission_limit = self.submission_limit.lock().await;
+        let solution = submission_limit.try_submit(solution);
+        self.update_pending_submissions(&submission_limit);
+        solution
+    }
+
+    async fn take_ready_submissions(&self) -> Vec<work::Solution> {
+        let mut submission_limit = self.submission_limit.lock().await;
+        let solutions = submission_limit.take_ready();
+        self.update_pending_submissions(&submission_limit);
+        solutions
+    }
+
+    /// Forget all submissions of the lost connection
+    async fn reset_submissions(&self) {
+        let mut submission_limit = self.submission_limit.lock().await;
+        submission_limit.reset();
+        self.update_pending_submissions(&submission_limit);
+    }
+
+    /// Release submission which has been acknowledged or which won't be sent at all
+    async fn release_submission(&self) {
+        let mut submission_limit = self.submission_limit.lock().await;
+        submission_limit.release();
+        self.update_pending_submissions(&submission_limit);
+    }
+
+    /// Store submitted solution for acknowledgement and return its sequence number. Submission of
+    /// a solution dropped from the full queue is released because it is never acknowledged.
+    async fn store_submission(&self, solution: work::Solution) -> u32 {
+        let (seq_num, evicted) = self.solutions.lock().await.push_evicting(solution);
+        if evicted.is_some() {
+            self.release_submission().await;
+        }
+        seq_num
+    }
+
+    /// Return channel used for submitting `solution`. Solutions of distinct chains are submitted
+    /// to separate sub-worker channels of the translation when per-chain workers are enabled.
+    fn submit_channel_id(&self, solution: &work::Solution) -> u32 {
+        match solution.solver_id() {
+            Some(solver_id) if self.chain_workers => {
+                V2ToV1Translation::sub_worker_channel_id(solver_id as u32)
+            }
+            _ => solution.job::<StratumJob>().channel_id,
+        }
+    }
+
+    /// Return hash chain of the sub-worker `channel_id` (inverse of `submit_channel_id`) or
+    /// `None` when the channel is not specific to any chain
+    fn channel_solver_id(&self, channel_id: u32) -> Option<usize> {
+        if !self.chain_workers {
+            return None;
+        }
+        channel_id
+            .checked_sub(V2ToV1Translation::sub_worker_channel_id(0))
+            .map(|index| index as usize)
+    }
+
+    /// Difficulty suggested to the server. The configuration from the pool URL takes precedence
+    /// over the minimum of the hardware which is only a hint for the server (shares of lower
+    /// difficulty are never filtered because of it).
+    fn suggested_difficulty(&self) -> Option<u32> {
+        if self.connection_details.get_min_difficulty().is_some() {
+            return self.connection_details.get_suggested_difficulty();
+        }
+        match self.hardware_min_difficulty.load(Ordering::Relaxed) {
+            0 => None,
+            difficulty => Some(difficulty.min(std::u32::MAX as u64) as u32),
+        }
+    }
+
+    /// Account round-trip time of a solution submitted at `submitted` and acknowledged at `now`
+    fn account_latency(&self, submitted: time::Instant, now: time::Instant) {
+        let sample = now.saturating_duration_since(submitted);
+        let mut latency = self
+            .submission_latency
+            .lock()
+            .expect("BUG: cannot lock submission latency");
+        // the same smoothing as is used for estimation of TCP round-trip time
+        *latency = Some(match *latency {
+            Some(latency) => (latency * 7 + sample) / 8,
+            None => sample,
+        });
+    }
+
     async fn update_last_job(&self, job: Arc<StratumJob>) {
         self.last_job.lock().await.replace(Arc::downgrade(&job));
     }
@@ -701,6 +1290,10 @@ impl StratumClient {
                         Ok(Some(frame)) => {
                             let event_msg = build_message_from_frame(frame)?;
                             event_msg.accept(event_handler).await;
+                            for solution in event_handler.take_resubmissions() {
+                                solution_handler.process_solution(solution).await?;
+                            }
+                            solution_handler.process_ready_solutions().await?;
                         }
                         Ok(None) | Err(_) => {
                             Err("The remote stratum server was disconnected prematurely")?;
@@ -746,7 +1339,8 @@ impl StratumClient {
         }
     }
 
-    async fn run(self: Arc<Self>) {
+    /// Return `true` when the connection with the server has been established
+    async fn run(self: Arc<Self>) -> bool {
         match StratumConnectionHandler::new(self.clone())
             .connect()
             .timeout(Self::CONNECTION_TIMEOUT)
@@ -756,20 +1350,54 @@ impl StratumClient {
                 if self.status.initiate_running() {
                     let options = V2ToV1TranslationOptions {
                         try_enable_xnsub: self.connection_details.try_enable_xnsub(),
+                        minimum_difficulty: self.suggested_difficulty(),
+                        min_extranonce2_size: self.min_extranonce2_size.load(Ordering::Relaxed),
+                        sub_worker_prefix: self.connection_details.get_chain_worker_prefix(),
+                        resume_session: self
+                            .v1_session
+                            .lock()
+                            .expect("BUG: cannot lock V1 session")
+                            .clone(),
+                    };
+                    let keepalive = match self.connection_details.get_keepalive() {
+                        Some(interval) => client::Keepalive::new(interval, time::Instant::now()),
+                        None => client::Keepalive::disabled(),
                     };
-                    let (translation_handler, v2_translation_rx, v2_translation_tx) =
-                        TranslationHandler::new(v1_framed_connection, options);
+                    let (mut translation_handler, v2_translation_rx, v2_translation_tx) =
+                        TranslationHandler::new(v1_framed_connection, options, keepalive);
+                    *self.block_height.lock().await =
+                        translation_handler.translation.block_height();
+                    *self.extranonce_change.lock().await =
+                        translation_handler.translation.extranonce_change();
+                    let v1_session = self.v1_session.clone();
                     tokio::spawn(async move {
                         let status = translation_handler.run().await;
                         info!("V2->V1 translation terminated: {:?}", status);
+                        // Keep the previous session when the new one hasn't been established
+                        if let Some(session) = translation_handler.translation.session_state() {
+                            *v1_session.lock().expect("BUG: cannot lock V1 session") =
+                                Some(session);
+                        }
                     });
                     self.clone()
                         .run_job_solver(v2_translation_rx, v2_translation_tx)
                         .await;
                 }
+                true
             }
-            Ok(Err(_)) | Err(_) => self.status.initiate_failing(),
+            Ok(Err(_)) | Err(_) => {
+                self.status.initiate_failing();
+                false
+            }
+        }
+    }
+
+    /// Wait for `delay` before the client is run again
+    async fn run_after(self: Arc<Self>, delay: Option<time::Duration>) -> bool {
+        if let Some(delay) = delay {
+            delay_for(delay).await;
         }
+        self.run().await
     }
 
     async fn main_task(self: Arc<Self>) {
@@ -777,10 +1405,14 @@ impl StratumClient {
         // Flush all obsolete solutions from previous run
         self.solution_receiver.lock().await.flush();
 
+        let reconnect_policy = Self::reconnect_policy();
+        let mut failed_attempts = 0;
+        let mut reconnect_delay = None;
         loop {
             let mut stop_receiver = self.stop_receiver.lock().await;
+            let mut connected = false;
             select! {
-                _ = self.clone().run().fuse() => {}
+                result = self.clone().run_after(reconnect_delay).fuse() => connected = result,
                 _ = stop_receiver.next() => {}
             }
 
@@ -790,13 +1422,24 @@ impl StratumClient {
             // TODO: Count as a discarded solution?
             self.solution_receiver.lock().await.flush();
             self.solutions.lock().await.clear();
+            self.reset_submissions().await;
 
             if self.status.can_stop() {
                 // NOTE: it is not safe to add here any code!
                 // The reason is that at this point the main task can be executed in parallel again
                 break;
             }
-            // Restarting
+            // Restarting: lost connection is established again immediately and only repeated
+            // failures to connect are delayed
+            if connected {
+                failed_attempts = 0;
+                reconnect_delay = None;
+            } else {
+                let delay = reconnect_policy.delay(failed_attempts);
+                info!("{}: cannot connect, reconnecting in {:?}", self, delay);
+                failed_attempts += 1;
+                reconnect_delay = Some(delay);
+            }
         }
     }
 }
@@ -814,6 +1457,8 @@ struct TranslationHandler {
     v1_translation_rx: mpsc::Receiver<v1::Frame>,
     /// V2 Frames from the client that we use for feeding the translator
     v2_client_rx: mpsc::Receiver<v2::Frame>,
+    /// Holds the V1 connection when there is no other outgoing traffic
+    keepalive: client::Keepalive,
 }
 
 impl TranslationHandler {
@@ -823,6 +1468,7 @@ impl TranslationHandler {
     fn new(
         v1_conn: v1::Framed,
         options: V2ToV1TranslationOptions,
+        keepalive: client::Keepalive,
     ) -> (Self, mpsc::Receiver<v2::Frame>, mpsc::Sender<v2::Frame>) {
         let (v1_translation_tx, v1_translation_rx) =
             mpsc::channel(Self::MAX_TRANSLATION_CHANNEL_SIZE);
@@ -838,6 +1484,7 @@ impl TranslationHandler {
                 v1_conn,
                 v1_translation_rx,
                 v2_client_rx,
+                keepalive,
             },
             v2_translation_rx,
             v2_client_tx,
@@ -851,11 +1498,17 @@ impl TranslationHandler {
     /// - v1_conn_rx -> build message + accept(translation)
     /// - v2_client_rx -> build message + accept(translation)
     /// - v1_translation_rx -> send
+    /// - keepalive timeout -> send keepalive(translation)
     /// terminate upon any error or timeout
-    async fn run(mut self) -> error::Result<()> {
+    async fn run(&mut self) -> error::Result<()> {
         //while !self.status.is_shutting_down() {
         info!("Starting V2->V1 translation handler");
         loop {
+            // Without keepalive the timer just wakes the loop up without any effect
+            let keepalive_timeout = self
+                .keepalive
+                .time_to_keepalive(time::Instant::now())
+                .unwrap_or(StratumClient::EVENT_TIMEOUT);
             select! {
                 // Receive V1 frame and translate it to V2 message
                 v1_frame = self.v1_conn.next().timeout(StratumClient::EVENT_TIMEOUT).fuse() => {
@@ -863,6 +1516,9 @@ impl TranslationHandler {
                         Ok(Some(v1_frame)) => {
                             let v1_msg = v1::build_message_from_frame(v1_frame?)?;
                             v1_msg.accept(&mut self.translation).await;
+                            self.translation.check_fatal_error().map_err(|e| {
+                                format!("Upstream V1 stratum connection unusable: {}", e)
+                            })?;
                         }
                         Ok(None) | Err(_) => {
                             Err("Upstream V1 stratum connection dropped terminating translation")?;
@@ -885,22 +1541,34 @@ impl TranslationHandler {
                 // Receive V1 frame from the translation and send it upstream
                 v1_frame = self.v1_translation_rx.next().fuse() => {
                     match v1_frame {
-                        Some(v1_frame) => self
-                            .v1_conn
-                            .send(v1_frame)
-                            // NOTE: this timeout is important otherwise the whole task could
-                            // block indefinitely and the above timeout for v1_conn_rx wouldn't
-                            // do anything. Besides this, we don't want to wait with system time
-                            // out in case the upstream connection just hangs
-                            .timeout(StratumClient::EVENT_TIMEOUT)
-                            .await
-                            // Unwrap timeout and actual sending error
-                            .map_err(|e| "V1 send timeout")??,
+                        Some(v1_frame) => {
+                            self.keepalive.traffic_sent(time::Instant::now());
+                            self
+                                .v1_conn
+                                .send(v1_frame)
+                                // NOTE: this timeout is important otherwise the whole task could
+                                // block indefinitely and the above timeout for v1_conn_rx
+                                // wouldn't do anything. Besides this, we don't want to wait with
+                                // system time out in case the upstream connection just hangs
+                                .timeout(StratumClient::EVENT_TIMEOUT)
+                                .await
+                                // Unwrap timeout and actual sending error
+                                .map_err(|e| "V1 send timeout")??
+                        }
                         None => {
                             Err("V1 translation component terminated, terminating translation")?;
                         }
                     }
                 },
+                // The V1 connection has been idle for too long
+                _ = delay_for(keepalive_timeout).fuse() => {
+                    if self.keepalive.poll(time::Instant::now()) {
+                        trace!("Sending V1 keepalive");
+                        if let Err(e) = self.translation.send_keepalive() {
+                            info!("Cannot send V1 keepalive: {:?}", e);
+                        }
+                    }
+                },
             }
         }
     }
@@ -922,6 +1590,33 @@ impl node::Client for StratumClient {
         }
     }
 
+    fn set_min_extranonce_size(&self, size: usize) {
+        self.min_extranonce2_size.store(size, Ordering::Relaxed);
+    }
+
+    fn set_hardware_min_difficulty(&self, difficulty: u64) {
+        self.hardware_min_difficulty
+            .store(difficulty, Ordering::Relaxed);
+    }
+
+    async fn set_error_registry(&self, error_registry: Arc<error::ErrorRegistry>) {
+        self.solutions
+            .lock()
+            .await
+            .set_error_registry(error_registry.clone(), self.to_string());
+        self.clock_drift
+            .lock()
+            .await
+            .set_error_registry(error_registry, self.to_string());
+    }
+
+    fn submission_latency(&self) -> Option<time::Duration> {
+        *self
+            .submission_latency
+            .lock()
+            .expect("BUG: cannot lock submission latency")
+    }
+
     async fn get_last_job(&self) -> Option<Arc<dyn job::Bitcoin>> {
         self.last_job
             .lock()
@@ -942,3 +1637,751 @@ impl fmt::Display for StratumClient {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hal;
+    use crate::test_utils;
+
+    use ii_bitcoin::MeetsTarget as _;
+
+    use std::convert::TryInto;
+
+    /// Solution reported by hardware with borderline nTime
+    #[derive(Debug)]
+    struct BorderlineSolution {
+        block: test_utils::TestBlock,
+        target: ii_bitcoin::Target,
+    }
+
+    impl hal::BackendSolution for BorderlineSolution {
+        fn nonce(&self) -> u32 {
+            self.block.nonce
+        }
+
+        fn midstate_idx(&self) -> usize {
+            0
+        }
+
+        fn solution_idx(&self) -> usize {
+            0
+        }
+
+        fn target(&self) -> &ii_bitcoin::Target {
+            &self.target
+        }
+
+        fn ntime(&self) -> Option<u32> {
+            Some(self.block.time + 1)
+        }
+    }
+
+    fn build_connection_details(fragment: &str) -> ConnectionDetails {
+        ConnectionDetails {
+            user: "user".to_string(),
+            host: "localhost".to_string(),
+            port: 3333,
+            fragment: Some(fragment.to_string()),
+        }
+    }
+
+    fn build_client_with_fragment(fragment: &str) -> Arc<StratumClient> {
+        let (_, solution_receiver) = mpsc::unbounded();
+        let solver = job::Solver::new(Arc::new(work::EngineSender::new(None)), solution_receiver);
+        Arc::new(StratumClient::new(
+            build_connection_details(fragment),
+            solver,
+        ))
+    }
+
+    fn build_client() -> Arc<StratumClient> {
+        build_client_with_fragment("resubmit-stale")
+    }
+
+    /// Messages announcing job of the first test block
+    fn build_job_messages() -> (SetNewPrevHash, NewMiningJob) {
+        let block = test_utils::TEST_BLOCKS[0];
+        let prevhash_msg = SetNewPrevHash {
+            channel_id: 0,
+            job_id: 0,
+            prev_hash: Uint256Bytes(block.previous_hash.into_inner()),
+            min_ntime: block.time,
+            nbits: block.bits,
+        };
+        let job_msg = NewMiningJob {
+            channel_id: 0,
+            job_id: 0,
+            future_job: false,
+            version: block.version,
+            merkle_root: Uint256Bytes(block.merkle_root.into_inner()),
+        };
+        (prevhash_msg, job_msg)
+    }
+
+    /// Event handler which has already received prevhash of the first test block together with
+    /// the job message which can be passed to it
+    fn event_handler_with_job(
+        client: Arc<StratumClient>,
+        target: ii_bitcoin::Target,
+    ) -> (StratumEventHandler, NewMiningJob) {
+        let (prevhash_msg, job_msg) = build_job_messages();
+        let mut event_handler = StratumEventHandler::new(client, target);
+        event_handler.current_prevhash_msg = Some(prevhash_msg);
+        (event_handler, job_msg)
+    }
+
+    fn build_stale_error(seq_num: u32) -> SubmitSharesError {
+        SubmitSharesError {
+            channel_id: 0,
+            seq_num,
+            code: "ShareRjct:StratumError(21, \"Job"
+                .try_into()
+                .expect("BUG: incorrect error message"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stale_resubmission() {
+        let client = build_client();
+        let mut event_handler = StratumEventHandler::new(client.clone(), Default::default());
+        let block = test_utils::TEST_BLOCKS[0];
+
+        let solution = work::Solution::new(
+            (&block).into(),
+            BorderlineSolution {
+                block,
+                target: Default::default(),
+            },
+            None,
+        );
+
+        let seq_num = client.solutions.lock().await.push(solution);
+        event_handler
+            .process_rejected_shares(&build_stale_error(seq_num))
+            .await;
+
+        let mut resubmissions = event_handler.take_resubmissions();
+        assert_eq!(resubmissions.len(), 1);
+        let solution = resubmissions.pop().expect("BUG: missing resubmission");
+        assert_eq!(solution.time(), block.time);
+        assert_eq!(&block.hash, solution.hash());
+
+        // rejection of resubmitted solution cannot trigger another one
+        let seq_num = client.solutions.lock().await.push(solution);
+        event_handler
+            .process_rejected_shares(&build_stale_error(seq_num))
+            .await;
+        assert!(event_handler.take_resubmissions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_submission() {
+        let client = build_client();
+        let mut event_handler = StratumEventHandler::new(client.clone(), Default::default());
+        let block = test_utils::TEST_BLOCKS[0];
+
+        let build_solution = || {
+            work::Solution::new(
+                (&block).into(),
+                BorderlineSolution {
+                    block,
+                    target: Default::default(),
+                },
+                None,
+            )
+        };
+
+        let solution = build_solution();
+        assert!(client.submitted.lock().await.try_submit(&solution));
+        let seq_num = client.solutions.lock().await.push(solution);
+        event_handler
+            .process_rejected_shares(&build_stale_error(seq_num))
+            .await;
+
+        // the same solution must not be submitted again even after reconnect
+        client.solutions.lock().await.clear();
+        assert!(!client.submitted.lock().await.try_submit(&build_solution()));
+
+        // resubmitted solution has adjusted nTime and therefore a different hash
+        let resubmission = event_handler
+            .take_resubmissions()
+            .pop()
+            .expect("BUG: missing resubmission");
+        assert!(client.submitted.lock().await.try_submit(&resubmission));
+    }
+
+    #[tokio::test]
+    async fn test_reject_reasons() {
+        let client = build_client_with_fragment("");
+        let mut event_handler = StratumEventHandler::new(client.clone(), Default::default());
+        let block = test_utils::TEST_BLOCKS[0];
+        let reject_reasons = &client.client_stats.reject_reasons;
+
+        for code in [
+            "ShareRjct:StratumError(21, \"Job not found (=stale)\", null)",
+            "ShareRjct:StratumError(23, \"Low difficulty share\", null)",
+            "ShareRjct:StratumError(21, \"Job not found (=stale)\", null)",
+        ]
+        .iter()
+        {
+            let solution: work::Solution = (&block).into();
+            let seq_num = client.solutions.lock().await.push(solution);
+            event_handler
+                .process_rejected_shares(&SubmitSharesError {
+                    channel_id: 0,
+                    seq_num,
+                    code: (*code).try_into().expect("BUG: incorrect error message"),
+                })
+                .await;
+        }
+
+        assert_eq!(*reject_reasons.job_not_found.take_snapshot(), 2);
+        assert_eq!(*reject_reasons.low_difficulty.take_snapshot(), 1);
+        assert_eq!(*reject_reasons.stale.take_snapshot(), 0);
+        assert_eq!(*reject_reasons.other.take_snapshot(), 0);
+        // stale resubmission is not enabled so all shares are accounted as rejected
+        let rejected = client.client_stats.rejected.take_snapshot().await;
+        assert_eq!(rejected.solutions, 3);
+    }
+
+    #[tokio::test]
+    async fn test_clean_jobs_invalidation() {
+        let block = test_utils::TEST_BLOCKS[0];
+        let (_, job_msg) = build_job_messages();
+        // solution of the job found for rolled version
+        let build_rolled_solution = |job: StratumJob| {
+            let midstate = work::Midstate {
+                version: block.version ^ (1 << ii_bitcoin::BIP320_VERSION_SHIFT),
+                state: block.midstate,
+            };
+            let work = work::Assignment::new(Arc::new(job), vec![midstate], block.time);
+            work::Solution::new(work, test_utils::TestSolution::new(&block), None)
+        };
+        let build_event_handler = |fragment: &str| {
+            event_handler_with_job(build_client_with_fragment(fragment), block.target).0
+        };
+
+        let mut event_handler = build_event_handler("");
+        let solution = build_rolled_solution(event_handler.build_job(&job_msg, None));
+        assert!(solution.version_rolled());
+        // regular job update keeps the previous jobs valid
+        event_handler
+            .update_job(&job_msg, work::GenerationCause::JobUpdate)
+            .await;
+        assert!(solution.has_valid_job());
+        // the solution cannot be submitted after clean jobs while the new job stays valid
+        event_handler
+            .update_job(&job_msg, work::GenerationCause::CleanJobs)
+            .await;
+        assert!(!solution.has_valid_job());
+        let new_solution = build_rolled_solution(event_handler.build_job(&job_msg, None));
+        assert!(new_solution.has_valid_job());
+
+        let mut event_handler = build_event_handler("clean-jobs=keep");
+        let solution = build_rolled_solution(event_handler.build_job(&job_msg, None));
+        event_handler
+            .update_job(&job_msg, work::GenerationCause::CleanJobs)
+            .await;
+        assert!(solution.has_valid_job());
+    }
+
+    #[tokio::test]
+    async fn test_extranonce_change() {
+        use crate::job::Bitcoin as _;
+        use crate::node::Client as _;
+
+        let client = build_client();
+        let (mut event_handler, job_msg) =
+            event_handler_with_job(client.clone(), Default::default());
+        let bumps = client.client_stats().generation_bumps();
+
+        event_handler
+            .update_job(&job_msg, work::GenerationCause::JobUpdate)
+            .await;
+        let job = event_handler.build_job(&job_msg, None);
+
+        // the job following `mining.set_extranonce` obsoletes all previous jobs
+        client.extranonce_change.lock().await.set();
+        event_handler
+            .update_job(&job_msg, work::GenerationCause::JobUpdate)
+            .await;
+        assert_eq!(bumps.get(work::GenerationCause::ExtranonceChange), 1);
+        assert!(!job.is_valid());
+
+        // the change is accounted only once
+        event_handler
+            .update_job(&job_msg, work::GenerationCause::JobUpdate)
+            .await;
+        assert_eq!(bumps.get(work::GenerationCause::ExtranonceChange), 1);
+        assert_eq!(bumps.get(work::GenerationCause::JobUpdate), 1);
+    }
+
+    #[tokio::test]
+    async fn test_clock_drift() {
+        let client = build_client_with_fragment("clock-drift=3600");
+        let error_registry = Arc::new(error::ErrorRegistry::new());
+        node::Client::set_error_registry(client.as_ref(), error_registry.clone()).await;
+        // time of the historical test block is years behind the system time
+        let (mut event_handler, job_msg) =
+            event_handler_with_job(client.clone(), Default::default());
+        event_handler
+            .update_job(&job_msg, work::GenerationCause::JobUpdate)
+            .await;
+
+        let clock_drift = client.clock_drift.lock().await;
+        assert!(clock_drift.is_drifting());
+        assert!(clock_drift.offset().expect("BUG: missing clock offset") < -3600);
+        let conditions = error_registry.list();
+        assert_eq!(conditions.len(), 1);
+        assert_eq!(conditions[0].kind, error::ConditionKind::ClockDrift);
+    }
+
+    #[test]
+    fn test_clock_drift_config() {
+        let get_clock_drift = |fragment: &str| build_connection_details(fragment).get_clock_drift();
+
+        assert_eq!(
+            get_clock_drift("resubmit-stale"),
+            client::ClockDrift::DEFAULT_MAX_DRIFT
+        );
+        assert_eq!(
+            get_clock_drift("job-expiry=45,clock-drift=120"),
+            time::Duration::from_secs(120)
+        );
+    }
+
+    #[test]
+    fn test_keepalive_config() {
+        let get_keepalive = |fragment: &str| build_connection_details(fragment).get_keepalive();
+
+        assert_eq!(get_keepalive("job-expiry=45"), None);
+        assert_eq!(
+            get_keepalive("job-expiry=45,keepalive=90"),
+            Some(time::Duration::from_secs(90))
+        );
+        assert_eq!(get_keepalive("keepalive=0"), None);
+    }
+
+    #[test]
+    fn test_min_difficulty_config() {
+        let connection_details = build_connection_details("resubmit-stale");
+        assert_eq!(connection_details.get_min_difficulty(), None);
+        assert_eq!(connection_details.get_suggested_difficulty(), None);
+
+        let connection_details = build_connection_details("min-diff=512");
+        assert_eq!(connection_details.get_min_difficulty(), Some(512));
+        assert_eq!(connection_details.get_suggested_difficulty(), None);
+
+        let connection_details = build_connection_details("min-diff=512,suggest-diff");
+        assert_eq!(connection_details.get_suggested_difficulty(), Some(512));
+
+        // there is nothing to suggest without minimal difficulty
+        let connection_details = build_connection_details("suggest-diff");
+        assert_eq!(connection_details.get_suggested_difficulty(), None);
+    }
+
+    #[test]
+    fn test_difficulty_floor() {
+        let min_target = ii_bitcoin::Target::from_pool_difficulty(512);
+        let low_target = ii_bitcoin::Target::from_pool_difficulty_f64(0.0001);
+
+        let mut event_handler =
+            StratumEventHandler::new(build_client_with_fragment("min-diff=512"), low_target);
+        assert!(event_handler.current_target == min_target);
+
+        // extremely low difficulty set by the pool engages the internal filter
+        event_handler.update_target(0, ii_bitcoin::Target::from_pool_difficulty(1024).into());
+        assert!(event_handler.current_target == ii_bitcoin::Target::from_pool_difficulty(1024));
+        event_handler.update_target(0, low_target.into());
+        assert!(event_handler.current_target == min_target);
+
+        // the warning is issued only when the floor engagement changes
+        assert!(event_handler.floor_engaged);
+        event_handler.update_target(0, ii_bitcoin::Target::from_pool_difficulty(1024).into());
+        assert!(!event_handler.floor_engaged);
+
+        // the pool target is used as is without the floor
+        let mut event_handler = StratumEventHandler::new(build_client(), low_target);
+        event_handler.update_target(0, low_target.into());
+        assert!(event_handler.current_target == low_target);
+        assert!(!event_handler.floor_engaged);
+
+        // minimum derived from the hardware is only suggested to the pool and never filters
+        // shares meeting the pool difficulty
+        let client = build_client();
+        assert_eq!(client.suggested_difficulty(), None);
+        node::Client::set_hardware_min_difficulty(client.as_ref(), 512);
+        assert_eq!(client.suggested_difficulty(), Some(512));
+        let mut event_handler = StratumEventHandler::new(client, low_target);
+        event_handler.update_target(0, low_target.into());
+        assert!(event_handler.current_target == low_target);
+        assert!(!event_handler.floor_engaged);
+
+        // explicit minimal difficulty from the pool URL overrides the hardware minimum
+        for &(fragment, suggested) in [
+            ("min-diff=2048", None),
+            ("min-diff=2048,suggest-diff", Some(2048)),
+        ]
+        .iter()
+        {
+            let client = build_client_with_fragment(fragment);
+            node::Client::set_hardware_min_difficulty(client.as_ref(), 512);
+            assert_eq!(client.suggested_difficulty(), suggested);
+        }
+    }
+
+    #[test]
+    fn test_job_expiry_config() {
+        let get_job_expiry = |fragment: &str| build_connection_details(fragment).get_job_expiry();
+
+        assert_eq!(get_job_expiry("resubmit-stale"), None);
+        assert_eq!(
+            get_job_expiry("job-expiry=45"),
+            Some(time::Duration::from_secs(45))
+        );
+        assert_eq!(
+            get_job_expiry("resubmit-stale,job-expiry=120,log-rejected"),
+            Some(time::Duration::from_secs(120))
+        );
+        assert_eq!(get_job_expiry("job-expiry=0"), None);
+        assert_eq!(get_job_expiry("job-expiry=never"), None);
+    }
+
+    #[test]
+    fn test_submit_ahead_config() {
+        let get_submit_ahead =
+            |fragment: &str| build_connection_details(fragment).get_submit_ahead();
+
+        assert_eq!(get_submit_ahead("job-expiry=45"), None);
+        assert_eq!(
+            get_submit_ahead("job-expiry=45,submit-ahead=3"),
+            Some(time::Duration::from_secs(3))
+        );
+        assert_eq!(get_submit_ahead("submit-ahead=0"), None);
+    }
+
+    #[test]
+    fn test_max_pending_config() {
+        let get_max_pending = |fragment: &str| build_connection_details(fragment).get_max_pending();
+
+        assert_eq!(get_max_pending("job-expiry=45"), None);
+        assert_eq!(get_max_pending("log-rejected,max-pending=8"), Some(8));
+        assert_eq!(get_max_pending("max-pending=0"), None);
+    }
+
+    #[test]
+    fn test_reconnect_policy() {
+        let policy = StratumClient::reconnect_policy();
+
+        // the client never gives up reconnecting
+        assert!(policy.has_next_attempt(1_000_000));
+        assert_eq!(policy.nominal_delay(0), time::Duration::from_secs(1));
+        assert_eq!(policy.nominal_delay(3), time::Duration::from_secs(8));
+        assert_eq!(policy.nominal_delay(100), time::Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_queue_limits_config() {
+        let get_queue_limits =
+            |fragment: &str| build_connection_details(fragment).get_queue_limits();
+
+        assert_eq!(get_queue_limits("max-pending=8"), None);
+        assert_eq!(get_queue_limits("queue-alert=100"), Some((1024, 100)));
+        assert_eq!(get_queue_limits("queue-size=64"), Some((64, 48)));
+        assert_eq!(
+            get_queue_limits("queue-size=64,queue-alert=16"),
+            Some((64, 16))
+        );
+        // the alert has to be raised before the queue is full
+        assert_eq!(get_queue_limits("queue-size=64,queue-alert=64"), None);
+        assert_eq!(get_queue_limits("queue-size=1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_submission_limit() {
+        use crate::node::Stats as _;
+
+        let client = build_client_with_fragment("max-pending=2");
+        let event_handler = StratumEventHandler::new(client.clone(), Default::default());
+        let pending_submissions = || {
+            let pending = client.mining_stats().pending_submissions();
+            (pending.outstanding(), pending.queued())
+        };
+
+        let mut seq_nums = vec![];
+        for block in test_utils::TEST_BLOCKS.iter() {
+            if let Some(solution) = client.limit_submission(block.into()).await {
+                seq_nums.push(client.solutions.lock().await.push(solution));
+            }
+        }
+        // only the configured number of submissions is outstanding at once
+        assert_eq!(seq_nums.len(), 2);
+        assert_eq!(
+            pending_submissions(),
+            (2, test_utils::TEST_BLOCKS.len() - 2)
+        );
+        assert!(client.take_ready_submissions().await.is_empty());
+
+        // acknowledgement of the first submission lets the next solution through
+        event_handler
+            .process_accepted_shares(&SubmitSharesSuccess {
+                channel_id: 0,
+                last_seq_num: seq_nums[0],
+                new_submits_accepted_count: 1,
+                new_shares_sum: 1,
+            })
+            .await;
+        assert_eq!(
+            pending_submissions(),
+            (1, test_utils::TEST_BLOCKS.len() - 2)
+        );
+        assert_eq!(client.take_ready_submissions().await.len(), 1);
+        assert_eq!(
+            pending_submissions(),
+            (2, test_utils::TEST_BLOCKS.len() - 3)
+        );
+
+        // lost connection releases all submissions
+        client.reset_submissions().await;
+        assert_eq!(pending_submissions(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_submission_eviction() {
+        use crate::node::Stats as _;
+
+        let client = build_client_with_fragment("max-pending=3");
+        *client.solutions.lock().await = client::SolutionQueue::new().with_limits(2, 1);
+        let pending_submissions = || {
+            let pending = client.mining_stats().pending_submissions();
+            (pending.outstanding(), pending.queued())
+        };
+
+        for block in test_utils::TEST_BLOCKS.iter().cycle().take(3) {
+            let solution = client
+                .limit_submission(block.into())
+                .await
+                .expect("BUG: submission over the limit");
+            client.store_submission(solution).await;
+        }
+        // the oldest solution has been dropped from the full queue and its slot is released
+        assert_eq!(client.solutions.lock().await.dropped(), 1);
+        assert_eq!(pending_submissions(), (2, 0));
+        assert!(client
+            .limit_submission((&test_utils::TEST_BLOCKS[0]).into())
+            .await
+            .is_some());
+    }
+
+    #[test]
+    fn test_network_config() {
+        let get_network = |fragment: &str| build_connection_details(fragment).get_network();
+
+        assert_eq!(get_network("resubmit-stale"), ii_bitcoin::Network::Mainnet);
+        assert_eq!(get_network("network=testnet"), ii_bitcoin::Network::Testnet);
+        assert_eq!(
+            get_network("log-rejected,network=regtest,job-expiry=30"),
+            ii_bitcoin::Network::Regtest
+        );
+        assert_eq!(
+            get_network("network=litecoin"),
+            ii_bitcoin::Network::Mainnet
+        );
+    }
+
+    #[test]
+    fn test_chain_worker_config() {
+        let get_prefix =
+            |fragment: &str| build_connection_details(fragment).get_chain_worker_prefix();
+
+        assert_eq!(get_prefix("resubmit-stale"), None);
+        assert_eq!(get_prefix("chain-worker=board"), Some("board".to_string()));
+        assert_eq!(
+            get_prefix("log-rejected,chain-worker=hb,job-expiry=30"),
+            Some("hb".to_string())
+        );
+        assert_eq!(get_prefix("chain-worker=,job-expiry=30"), None);
+    }
+
+    #[test]
+    fn test_chain_worker_submission() {
+        let block = test_utils::TEST_BLOCKS[0];
+        let (prevhash_msg, job_msg) = build_job_messages();
+        let build_solution = |client: &Arc<StratumClient>, solver_id| {
+            let job = StratumJob::new(client.clone(), &job_msg, &prevhash_msg, block.target, None);
+            let mut work = work::Assignment::new(Arc::new(job), vec![], block.time);
+            work.solver_id = solver_id;
+            work::Solution::new(
+                work,
+                BorderlineSolution {
+                    block,
+                    target: Default::default(),
+                },
+                None,
+            )
+        };
+
+        // each chain submits to the channel of its own worker
+        let client = build_client_with_fragment("chain-worker=board");
+        for solver_id in 0..3 {
+            assert_eq!(
+                client.submit_channel_id(&build_solution(&client, Some(solver_id))),
+                V2ToV1Translation::sub_worker_channel_id(solver_id as u32)
+            );
+        }
+        // work solver without id is submitted under the pool user
+        assert_eq!(client.submit_channel_id(&build_solution(&client, None)), 0);
+
+        let client = build_client();
+        assert_eq!(
+            client.submit_channel_id(&build_solution(&client, Some(1))),
+            0
+        );
+    }
+
+    #[test]
+    fn test_chain_target() {
+        let block = test_utils::TEST_BLOCKS[0];
+        let client = build_client_with_fragment("chain-worker=board");
+        let (mut event_handler, job_msg) = event_handler_with_job(client.clone(), block.target);
+        let build_solution = |event_handler: &StratumEventHandler, solver_id| {
+            let job = event_handler.build_job(&job_msg, None);
+            let midstate = work::Midstate {
+                version: block.version,
+                state: block.midstate,
+            };
+            let mut work = work::Assignment::new(Arc::new(job), vec![midstate], block.time);
+            work.solver_id = Some(solver_id);
+            work::Solution::new(work, test_utils::TestSolution::new(&block), None)
+        };
+
+        // the second chain gets difficulty which the block doesn't meet
+        let chain_target = ii_bitcoin::Target::from_pool_difficulty_f64(1e12);
+        event_handler.update_target(
+            V2ToV1Translation::sub_worker_channel_id(1),
+            chain_target.into(),
+        );
+        for solver_id in 0..3 {
+            let solution = build_solution(&event_handler, solver_id);
+            let expected_target = if solver_id == 1 {
+                chain_target
+            } else {
+                block.target
+            };
+            assert!(*solution.job_target() == expected_target);
+            assert_eq!(solution.hash().meets(solution.job_target()), solver_id != 1);
+        }
+
+        // target of the channel is still used by the remaining chains
+        let channel_target = ii_bitcoin::Target::from_pool_difficulty(2);
+        event_handler.update_target(0, channel_target.into());
+        assert!(*build_solution(&event_handler, 0).job_target() == channel_target);
+        assert!(*build_solution(&event_handler, 1).job_target() == chain_target);
+
+        // sub-worker channels are not recognized without per-chain workers
+        let mut event_handler = StratumEventHandler::new(build_client(), block.target);
+        event_handler.update_target(
+            V2ToV1Translation::sub_worker_channel_id(1),
+            chain_target.into(),
+        );
+        assert!(event_handler.solver_targets.is_empty());
+        assert!(event_handler.current_target == chain_target);
+    }
+
+    #[tokio::test]
+    async fn test_job_expiry() {
+        use crate::node::Stats as _;
+
+        let interval = time::Duration::from_secs(30);
+        let client = build_client_with_fragment("job-expiry=30");
+        let (mut event_handler, job_msg) =
+            event_handler_with_job(client.clone(), Default::default());
+        let block = test_utils::TEST_BLOCKS[0];
+        let (prevhash_msg, _) = build_job_messages();
+
+        // nothing expires before the first job arrives
+        assert!(!client
+            .job_expiry
+            .lock()
+            .await
+            .is_expired(time::Instant::now() + interval));
+
+        event_handler
+            .update_job(&job_msg, work::GenerationCause::JobUpdate)
+            .await;
+        let job_time = time::Instant::now();
+        assert!(!client.job_expiry.lock().await.is_expired(job_time));
+        // the job ages past the expiry so its solutions are not submitted anymore
+        let expired_time = job_time + interval;
+        assert!(client.job_expiry.lock().await.is_expired(expired_time));
+
+        let (connection_tx, mut connection_rx) = mpsc::channel(1);
+        let mut solution_handler = StratumSolutionHandler::new(client.clone(), connection_tx);
+        let build_solution = || {
+            let job = StratumJob::new(client.clone(), &job_msg, &prevhash_msg, block.target, None);
+            work::Solution::new(
+                work::Assignment::new(Arc::new(job), vec![], block.time),
+                BorderlineSolution {
+                    block,
+                    target: Default::default(),
+                },
+                None,
+            )
+        };
+
+        // solution found on the expired job is dropped instead of being submitted
+        client
+            .job_expiry
+            .lock()
+            .await
+            .job_received(time::Instant::now() - interval);
+        solution_handler
+            .process_solution(build_solution())
+            .await
+            .expect("BUG: cannot process solution");
+        assert!(connection_rx.try_next().is_err());
+        assert!(client.solutions.lock().await.is_empty());
+        assert_eq!(client.mining_stats().pending_submissions().outstanding(), 0);
+
+        // submitting is resumed when a new job arrives
+        event_handler
+            .update_job(&job_msg, work::GenerationCause::JobUpdate)
+            .await;
+        assert!(!client
+            .job_expiry
+            .lock()
+            .await
+            .is_expired(time::Instant::now()));
+        solution_handler
+            .process_solution(build_solution())
+            .await
+            .expect("BUG: cannot process solution");
+        assert!(connection_rx
+            .try_next()
+            .expect("BUG: solution has not been submitted")
+            .is_some());
+        assert_eq!(client.solutions.lock().await.len(), 1);
+    }
+
+    #[test]
+    fn test_submission_latency() {
+        use crate::node::Client as _;
+
+        let client = build_client();
+        let submitted = time::Instant::now();
+        assert_eq!(client.submission_latency(), None);
+
+        client.account_latency(submitted, submitted + time::Duration::from_millis(80));
+        assert_eq!(
+            client.submission_latency(),
+            Some(time::Duration::from_millis(80))
+        );
+        // latency is smoothed over subsequent acknowledgements
+        client.account_latency(submitted, submitted + time::Duration::from_millis(160));
+        assert_eq!(
+            client.submission_latency(),
+            Some(time::Duration::from_millis(90))
+        );
+    }
+}
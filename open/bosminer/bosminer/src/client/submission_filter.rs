@@ -0,0 +1,134 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Filter of solutions already submitted to remote server which prevents sending duplicate shares
+
+use crate::work;
+
+use std::collections::{HashSet, VecDeque};
+
+/// Remembers header hashes of the most recent submitted solutions. The filter is intentionally
+/// kept across reconnects so that a solution cannot be sent twice just because it was still in
+/// flight when the connection had been restarted. The filter can be turned off per pool with
+/// the `allow-duplicates` URL fragment option.
+#[derive(Debug)]
+pub struct SubmissionFilter {
+    /// Maximal number of remembered hashes (zero disables the filter)
+    capacity: usize,
+    hashes: HashSet<ii_bitcoin::DHash>,
+    /// Submission order used for evicting the oldest hashes
+    order: VecDeque<ii_bitcoin::DHash>,
+}
+
+impl SubmissionFilter {
+    /// Default number of remembered solutions
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            hashes: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Filter which lets all solutions pass
+    pub fn disabled() -> Self {
+        Self::new(0)
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Record `solution` as submitted and return `false` when it has been submitted before
+    pub fn try_submit(&mut self, solution: &work::Solution) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        let hash = *solution.hash();
+        if !self.hashes.insert(hash) {
+            return false;
+        }
+        if self.order.len() == self.capacity {
+            let oldest = self.order.pop_front().expect("BUG: empty submission order");
+            self.hashes.remove(&oldest);
+        }
+        self.order.push_back(hash);
+        true
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+}
+
+impl Default for SubmissionFilter {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn test_duplicate_suppressed() {
+        let mut filter = SubmissionFilter::default();
+        let block = &test_utils::TEST_BLOCKS[0];
+
+        assert!(filter.try_submit(&block.into()));
+        // the same solution cannot be submitted again
+        assert!(!filter.try_submit(&block.into()));
+        assert!(filter.try_submit(&(&test_utils::TEST_BLOCKS[1]).into()));
+        assert_eq!(filter.len(), 2);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut filter = SubmissionFilter::new(2);
+        let blocks = &test_utils::TEST_BLOCKS;
+
+        assert!(filter.try_submit(&(&blocks[0]).into()));
+        assert!(filter.try_submit(&(&blocks[1]).into()));
+        assert!(filter.try_submit(&(&blocks[2]).into()));
+        assert_eq!(filter.len(), 2);
+
+        // the oldest hash has been forgotten while the recent ones are still suppressed
+        assert!(filter.try_submit(&(&blocks[0]).into()));
+        assert!(!filter.try_submit(&(&blocks[2]).into()));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut filter = SubmissionFilter::disabled();
+        let block = &test_utils::TEST_BLOCKS[0];
+
+        assert!(filter.try_submit(&block.into()));
+        assert!(filter.try_submit(&block.into()));
+        assert_eq!(filter.len(), 0);
+    }
+}
@@ -0,0 +1,247 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Limit of concurrent submissions which have not been acknowledged by the remote server yet
+
+use crate::work;
+
+use std::collections::VecDeque;
+
+/// Bounds the number of outstanding (unacknowledged) submissions. Solutions
+/// above the limit are queued until the previous ones are acknowledged so that a burst of
+/// solutions (e.g. after reconnect) does not trip rate limiting of the server which would reject
+/// them. The limit never blocks the caller because acknowledgements are usually processed by the
+/// same task which submits solutions.
+#[derive(Debug)]
+pub struct SubmissionLimit {
+    /// Maximal number of outstanding submissions (`None` disables the limit)
+    limit: Option<usize>,
+    outstanding: usize,
+    /// Solutions waiting for a free submission slot (in order of arrival)
+    queue: VecDeque<work::Solution>,
+    /// Maximal number of queued solutions
+    queue_capacity: usize,
+    /// Number of queued solutions dropped because the queue was full
+    dropped: u64,
+}
+
+impl SubmissionLimit {
+    /// Default maximal number of solutions waiting for a free submission slot
+    pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+    pub fn new(limit: usize) -> Self {
+        assert!(limit > 0, "BUG: submission limit has to be positive");
+        Self {
+            limit: Some(limit),
+            outstanding: 0,
+            queue: VecDeque::new(),
+            queue_capacity: Self::DEFAULT_QUEUE_CAPACITY,
+            dropped: 0,
+        }
+    }
+
+    /// Number of outstanding submissions is not limited (they are only accounted)
+    pub fn disabled() -> Self {
+        Self {
+            limit: None,
+            outstanding: 0,
+            queue: VecDeque::new(),
+            queue_capacity: Self::DEFAULT_QUEUE_CAPACITY,
+            dropped: 0,
+        }
+    }
+
+    /// Hold back at most `queue_capacity` solutions. The oldest one is dropped when another
+    /// solution arrives to the full queue.
+    pub fn with_queue_capacity(mut self, queue_capacity: usize) -> Self {
+        assert!(queue_capacity > 0, "BUG: queue capacity has to be positive");
+        self.queue_capacity = queue_capacity;
+        self
+    }
+
+    #[inline]
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Number of submissions waiting for acknowledgement
+    #[inline]
+    pub fn outstanding(&self) -> usize {
+        self.outstanding
+    }
+
+    /// Number of solutions waiting for a free submission slot
+    #[inline]
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Total number of solutions dropped from the full queue
+    #[inline]
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        match self.limit {
+            Some(limit) if self.outstanding >= limit => false,
+            _ => {
+                self.outstanding += 1;
+                true
+            }
+        }
+    }
+
+    /// Return the solution back when it can be submitted right now. Otherwise it is queued
+    /// behind older solutions and returned later by `take_ready`. The oldest queued solution is
+    /// dropped when the queue is full because it is the most likely one to be stale.
+    pub fn try_submit(&mut self, solution: work::Solution) -> Option<work::Solution> {
+        if self.queue.is_empty() && self.try_acquire() {
+            Some(solution)
+        } else {
+            if self.queue.len() >= self.queue_capacity {
+                self.queue.pop_front();
+                self.dropped += 1;
+            }
+            self.queue.push_back(solution);
+            None
+        }
+    }
+
+    /// Release slot of one acknowledged (or discarded) submission
+    pub fn release(&mut self) {
+        if self.outstanding == 0 {
+            // the submission has been already released by `reset`
+            return;
+        }
+        self.outstanding -= 1;
+    }
+
+    /// Take queued solutions for which a submission slot has been released in the meantime
+    pub fn take_ready(&mut self) -> Vec<work::Solution> {
+        let mut ready = vec![];
+        while !self.queue.is_empty() && self.try_acquire() {
+            ready.extend(self.queue.pop_front());
+        }
+        ready
+    }
+
+    /// Release slots of all outstanding submissions and drop all queued solutions because they
+    /// belong to a connection which has been lost
+    pub fn reset(&mut self) {
+        self.outstanding = 0;
+        self.queue.clear();
+    }
+}
+
+impl Default for SubmissionLimit {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    fn build_solution(i: usize) -> work::Solution {
+        (&test_utils::TEST_BLOCKS[i % test_utils::TEST_BLOCKS.len()]).into()
+    }
+
+    #[test]
+    fn test_submission_limit() {
+        let mut limit = SubmissionLimit::new(2);
+        let mut submitted = 0;
+        for i in 0..5 {
+            if limit.try_submit(build_solution(i)).is_some() {
+                submitted += 1;
+            }
+            // no more than the limit is outstanding at once
+            assert!(limit.outstanding() <= 2);
+        }
+        assert_eq!(submitted, 2);
+        assert_eq!(limit.queued(), 3);
+        assert!(limit.take_ready().is_empty());
+
+        // each acknowledgement lets one queued solution through in order of arrival
+        limit.release();
+        let ready = limit.take_ready();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].nonce(), build_solution(2).nonce());
+        assert_eq!(limit.outstanding(), 2);
+        assert_eq!(limit.queued(), 2);
+
+        // new solution cannot overtake queued ones even when there is a free slot
+        limit.release();
+        assert!(limit.try_submit(build_solution(5)).is_none());
+        assert_eq!(limit.take_ready().len(), 1);
+        assert_eq!(limit.outstanding(), 2);
+        assert_eq!(limit.queued(), 2);
+
+        // reset returns all slots
+        limit.reset();
+        assert_eq!(limit.outstanding(), 0);
+        assert_eq!(limit.queued(), 0);
+        limit.release();
+        assert_eq!(limit.outstanding(), 0);
+        assert!(limit.try_submit(build_solution(0)).is_some());
+        assert!(limit.try_submit(build_solution(1)).is_some());
+        assert!(limit.try_submit(build_solution(2)).is_none());
+    }
+
+    #[test]
+    fn test_queue_capacity() {
+        let mut limit = SubmissionLimit::new(1).with_queue_capacity(2);
+        assert!(limit.try_submit(build_solution(0)).is_some());
+        for i in 1..5 {
+            assert!(limit.try_submit(build_solution(i)).is_none());
+            assert!(limit.queued() <= 2);
+        }
+        // the oldest held back solutions have been dropped
+        assert_eq!(limit.queued(), 2);
+        assert_eq!(limit.dropped(), 2);
+
+        limit.release();
+        let ready = limit.take_ready();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].nonce(), build_solution(3).nonce());
+
+        // the count of dropped solutions is not cleared with the lost connection
+        limit.reset();
+        assert_eq!(limit.dropped(), 2);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut limit = SubmissionLimit::disabled();
+        for i in 0..100 {
+            assert!(limit.try_submit(build_solution(i)).is_some());
+        }
+        assert_eq!(limit.limit(), None);
+        assert_eq!(limit.outstanding(), 100);
+        assert_eq!(limit.queued(), 0);
+
+        limit.release();
+        assert_eq!(limit.outstanding(), 99);
+    }
+}
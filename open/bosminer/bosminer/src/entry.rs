@@ -23,20 +23,41 @@
 //! This module provides top level functionality to build the BOSminer core and use it to connect
 //! the frontend and hardware specific backend.
 
+use ii_logging::macros::*;
+
 use crate::api;
 use crate::backend;
 use crate::hal::{self, BackendConfig as _};
 use crate::hub;
 use crate::stats;
+use crate::version;
 
 use ii_async_compat::tokio;
 
 use std::sync::Arc;
 
 pub async fn main<T: hal::Backend>(backend_config: T::Config, signature: String) {
+    info!("Starting BOSminer {}", version::build_info());
+
     let backend_registry = Arc::new(backend::Registry::new());
     // Get frontend specific settings from backend config
     let backend_info = backend_config.info();
+    let stats_csv = backend_config.stats_csv();
+    let stats_log_interval = backend_config.stats_log_interval();
+    let best_share_log_threshold = backend_config.best_share_log_threshold();
+    let solution_socket = backend_config.solution_socket();
+    let inflight_dump_dir = backend_config.inflight_dump_dir();
+    let cross_check_solutions = backend_config.cross_check_solutions();
+    let fastest_block_submission = backend_config.fastest_block_submission();
+    let extranonce_rolling = backend_config.extranonce_rolling();
+    let max_ntime_roll = backend_config.max_ntime_roll();
+    let work_seed = backend_config.work_seed();
+    let work_distribution = backend_config.work_distribution();
+    let hashrate_warmup = backend_config.hashrate_warmup();
+    let core_affinity = backend_config.core_affinity();
+    let supported_midstate_counts = backend_config.supported_midstate_counts();
+
+    stats::set_best_share_log_threshold(best_share_log_threshold);
 
     // Initialize hub core which manages all resources
     let core = Arc::new(hub::Core::new(
@@ -44,12 +65,45 @@ pub async fn main<T: hal::Backend>(backend_config: T::Config, signature: String)
         &backend_registry,
         backend_info.clone(),
     ));
+    // Groups are created by the backend so the capability has to be known before it is built
+    core.get_client_manager()
+        .set_extranonce_rolling(extranonce_rolling)
+        .await;
+    core.get_client_manager().set_work_seed(work_seed).await;
+    core.get_client_manager()
+        .set_max_ntime_roll(max_ntime_roll)
+        .await;
+    if cross_check_solutions {
+        core.enable_cross_check().await;
+    }
+    if fastest_block_submission {
+        core.enable_fastest_block_submission().await;
+    }
+    core.set_core_affinity(core_affinity).await;
+    core.set_supported_midstate_counts(supported_midstate_counts);
+    if let Some(path) = solution_socket {
+        match hub::sink::SolutionSink::bind(&path) {
+            Ok(solution_sink) => core.set_solution_sink(solution_sink).await,
+            Err(e) => error!("Cannot create solution socket '{}': {}", path.display(), e),
+        }
+    }
 
     // Create and initialize the backend
-    let frontend_config = core
+    let mut frontend_config = core
         .build_backend::<T>(backend_config)
         .await
         .expect("Backend initialization failed");
+    // All work solvers are registered by now so the dump covers every chain
+    if let Some(dir) = inflight_dump_dir {
+        core.inflight_dump().await.install_panic_hook(dir);
+    }
+    if let Err(e) = core.set_work_distribution(work_distribution).await {
+        error!("Cannot distribute work: {}", e);
+    }
+
+    if let Some(hashrate_warmup) = hashrate_warmup {
+        core.start_warmup(hashrate_warmup).await;
+    }
 
     tokio::spawn(core.clone().run());
     // start statistics processing
@@ -57,6 +111,24 @@ pub async fn main<T: hal::Backend>(backend_config: T::Config, signature: String)
         core.frontend.clone(),
         T::DEFAULT_HASHRATE_INTERVAL,
     ));
+    if let Some(interval) = stats_log_interval {
+        let logger = stats::summary::SummaryLogger::new(
+            core.get_work_solvers().await,
+            core.frontend.clone(),
+        )
+        .await;
+        tokio::spawn(logger.run(interval));
+    }
+    if let Some(stats_csv) = stats_csv {
+        tokio::spawn(stats::csv::csv_task(
+            core.frontend.clone(),
+            stats_csv,
+            T::DEFAULT_HASHRATE_INTERVAL,
+            frontend_config.temperature_source.take(),
+            Some(core.generation_source().await),
+            Some(core.clone().efficiency_source(T::DEFAULT_HASHRATE_INTERVAL)),
+        ));
+    }
 
     // the bosminer is controlled with API which also controls when the miner will end
     api::run(core, frontend_config, signature).await;
@@ -23,8 +23,10 @@
 //! The bosminer errors
 
 mod client;
+mod registry;
 
 pub use client::ErrorKind as Client;
+pub use registry::{Condition, ConditionKind, ErrorRegistry, Severity};
 
 use ii_async_compat::prelude::*;
 
@@ -0,0 +1,293 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Registry of active and recent operational error conditions (overheating, chip dropouts, pool
+//! reject storms, ...) which are pushed by various subsystems and presented by the API
+
+use std::fmt;
+use std::sync::Mutex as StdMutex;
+use std::time;
+
+/// Maximal number of distinct conditions kept in the registry
+/// When exceeded then the condition with the oldest occurrence is forgotten.
+const MAX_CONDITIONS: usize = 64;
+
+/// Severity of error condition ordered from the lowest to the highest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+    /// Conditions which lead to shutdown of the miner
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Severity::Info => "Info",
+            Severity::Warning => "Warning",
+            Severity::Error => "Error",
+            Severity::Critical => "Critical",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ConditionKind {
+    Overheat,
+    ChipDropout,
+    FanFailure,
+    /// Temperature sensor returns readings which cannot be trusted
+    SensorFault,
+    RejectStorm,
+    HardwareErrorSpike,
+    /// Sustained decline of solution rate (usually degrading chips)
+    SolutionRateDecline,
+    /// Solutions are piling up in a queue waiting for acknowledgement from a pool
+    SolutionQueueBacklog,
+    /// Hashing core keeps returning the same nonce regardless of the work
+    StuckCore,
+    /// Hashchain has been found broken (e.g. it stopped responding) by the monitor
+    ChainBroken,
+    /// Hashchain has failed to start too many times and it is not restarted anymore
+    ChainDisabled,
+    /// Time of jobs from a pool differs too much from the system time
+    ClockDrift,
+    Other(String),
+}
+
+impl fmt::Display for ConditionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConditionKind::Overheat => write!(f, "Overheat"),
+            ConditionKind::ChipDropout => write!(f, "ChipDropout"),
+            ConditionKind::FanFailure => write!(f, "FanFailure"),
+            ConditionKind::SensorFault => write!(f, "SensorFault"),
+            ConditionKind::RejectStorm => write!(f, "RejectStorm"),
+            ConditionKind::HardwareErrorSpike => write!(f, "HardwareErrorSpike"),
+            ConditionKind::SolutionRateDecline => write!(f, "SolutionRateDecline"),
+            ConditionKind::SolutionQueueBacklog => write!(f, "SolutionQueueBacklog"),
+            ConditionKind::StuckCore => write!(f, "StuckCore"),
+            ConditionKind::ChainBroken => write!(f, "ChainBroken"),
+            ConditionKind::ChainDisabled => write!(f, "ChainDisabled"),
+            ConditionKind::ClockDrift => write!(f, "ClockDrift"),
+            ConditionKind::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Error condition aggregated over all its occurrences from the same source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub kind: ConditionKind,
+    /// The highest severity of all occurrences
+    pub severity: Severity,
+    /// Subsystem or device which reported the condition (e.g. "chain 6")
+    pub source: String,
+    /// Message of the last occurrence
+    pub message: String,
+    pub first_seen: time::SystemTime,
+    pub last_seen: time::SystemTime,
+    /// Number of occurrences
+    pub count: u64,
+}
+
+/// Shared registry of error conditions
+#[derive(Debug)]
+pub struct ErrorRegistry {
+    conditions: StdMutex<Vec<Condition>>,
+}
+
+impl ErrorRegistry {
+    pub fn new() -> Self {
+        Self {
+            conditions: StdMutex::new(Vec::new()),
+        }
+    }
+
+    fn lock_conditions(&self) -> std::sync::MutexGuard<Vec<Condition>> {
+        self.conditions.lock().expect("cannot lock error registry")
+    }
+
+    /// Report a new occurrence of error condition
+    /// Repeated conditions of the same kind from the same source are merged into one entry.
+    pub fn push<S, M>(&self, kind: ConditionKind, severity: Severity, source: S, message: M)
+    where
+        S: Into<String>,
+        M: Into<String>,
+    {
+        self.push_at(kind, severity, source, message, time::SystemTime::now());
+    }
+
+    fn push_at<S, M>(
+        &self,
+        kind: ConditionKind,
+        severity: Severity,
+        source: S,
+        message: M,
+        now: time::SystemTime,
+    ) where
+        S: Into<String>,
+        M: Into<String>,
+    {
+        let source = source.into();
+        let message = message.into();
+        let mut conditions = self.lock_conditions();
+
+        if let Some(condition) = conditions
+            .iter_mut()
+            .find(|condition| condition.kind == kind && condition.source == source)
+        {
+            condition.severity = condition.severity.max(severity);
+            condition.message = message;
+            condition.last_seen = now;
+            condition.count += 1;
+            return;
+        }
+
+        if conditions.len() >= MAX_CONDITIONS {
+            // forget the condition which has not been seen for the longest time
+            if let Some((idx, _)) = conditions
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, condition)| condition.last_seen)
+            {
+                conditions.remove(idx);
+            }
+        }
+        conditions.push(Condition {
+            kind,
+            severity,
+            source,
+            message,
+            first_seen: now,
+            last_seen: now,
+            count: 1,
+        });
+    }
+
+    /// Return all registered conditions sorted from the most severe and the most recent ones
+    pub fn list(&self) -> Vec<Condition> {
+        let mut conditions = self.lock_conditions().clone();
+        conditions.sort_by(|a, b| {
+            b.severity
+                .cmp(&a.severity)
+                .then_with(|| b.last_seen.cmp(&a.last_seen))
+        });
+        conditions
+    }
+
+    /// Remove all conditions (e.g. after they have been acknowledged by the operator) and return
+    /// the number of removed conditions
+    pub fn clear(&self) -> usize {
+        let mut conditions = self.lock_conditions();
+        let count = conditions.len();
+        conditions.clear();
+        count
+    }
+}
+
+impl Default for ErrorRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_registry() {
+        let registry = ErrorRegistry::new();
+        let now = time::SystemTime::now();
+        let later = now + time::Duration::from_secs(10);
+
+        registry.push_at(
+            ConditionKind::RejectStorm,
+            Severity::Warning,
+            "pool 0",
+            "50% of shares rejected",
+            now,
+        );
+        registry.push_at(
+            ConditionKind::Overheat,
+            Severity::Warning,
+            "chain 6",
+            "temperature above HOT",
+            now,
+        );
+        registry.push_at(
+            ConditionKind::Overheat,
+            Severity::Critical,
+            "chain 6",
+            "temperature above DANGEROUS",
+            later,
+        );
+        registry.push_at(
+            ConditionKind::ChainBroken,
+            Severity::Error,
+            "chain 7",
+            "chain is broken",
+            later,
+        );
+
+        let conditions = registry.list();
+        assert_eq!(conditions.len(), 3);
+        // the most severe condition goes first
+        assert_eq!(conditions[0].kind, ConditionKind::Overheat);
+        assert_eq!(conditions[0].severity, Severity::Critical);
+        assert_eq!(conditions[0].message, "temperature above DANGEROUS");
+        assert_eq!(conditions[0].count, 2);
+        assert_eq!(conditions[0].first_seen, now);
+        assert_eq!(conditions[0].last_seen, later);
+        assert_eq!(conditions[1].kind, ConditionKind::ChainBroken);
+        assert_eq!(conditions[2].kind, ConditionKind::RejectStorm);
+
+        assert_eq!(registry.clear(), 3);
+        assert!(registry.list().is_empty());
+    }
+
+    #[test]
+    fn test_error_registry_capacity() {
+        let registry = ErrorRegistry::new();
+        let now = time::SystemTime::now();
+
+        for i in 0..=MAX_CONDITIONS as u64 {
+            registry.push_at(
+                ConditionKind::HardwareErrorSpike,
+                Severity::Warning,
+                format!("chain {}", i),
+                "",
+                now + time::Duration::from_secs(i),
+            );
+        }
+        let conditions = registry.list();
+        assert_eq!(conditions.len(), MAX_CONDITIONS);
+        // the oldest condition has been forgotten
+        assert!(conditions
+            .iter()
+            .all(|condition| condition.source != "chain 0"));
+    }
+}
@@ -20,16 +20,21 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+use crate::affinity;
 use crate::client;
 use crate::error;
+use crate::job;
 use crate::node;
+use crate::stats;
 use crate::work;
 
+use ii_bitcoin::HashTrait as _;
 use ii_cgminer_api::command;
 use ii_stratum::v2::types::DeviceInfo;
 
 use std::convert::TryInto;
 use std::fmt::Debug;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -46,6 +51,37 @@ pub trait BackendSolution: Debug + Send + Sync {
     /// Backend target used for finding this nonce
     /// This information is used mainly for detecting HW errors
     fn target(&self) -> &ii_bitcoin::Target;
+    /// nTime reported by the hardware (if it rolls nTime on its own and is able to report it)
+    /// When `None` is returned the nTime is inferred from the solution and mining work
+    fn ntime(&self) -> Option<u32> {
+        None
+    }
+    /// Extranonce used by hardware which rolls extranonce on its own within the range assigned
+    /// to the work
+    fn extranonce(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Compute midstate of the first chunk of block header for given job and (possibly rolled) version
+pub fn compute_midstate(job: &dyn job::Bitcoin, version: u32) -> ii_bitcoin::Midstate {
+    ii_bitcoin::BlockHeader {
+        version,
+        previous_hash: job.previous_hash().into_inner(),
+        merkle_root: job.merkle_root().into_inner(),
+        ..Default::default()
+    }
+    .midstate()
+}
+
+/// Check midstate reported by the hardware against software computation. It is intended for
+/// detection of defective SHA256 cores on hardware which is able to report its internal state.
+pub fn verify_midstate(
+    job: &dyn job::Bitcoin,
+    version: u32,
+    claimed: &ii_bitcoin::Midstate,
+) -> bool {
+    compute_midstate(job, version) == *claimed
 }
 
 /// Enum returned from `Backend::create` is intended for choosing type of backend root node (work
@@ -104,16 +140,86 @@ impl From<BackendInfo> for DeviceInfo {
 pub trait BackendConfig: Debug + Send + Sync {
     /// Number of midstates that backend is able to solve at once
     fn midstate_count(&self) -> usize;
+    /// Midstate counts of work which the backend accepts without restart (hardware configured
+    /// for fixed midstate count supports only `midstate_count`)
+    fn supported_midstate_counts(&self) -> Vec<usize> {
+        vec![self.midstate_count()]
+    }
     /// Pass client manager to backend to get access to its functionality
     fn set_client_manager(&mut self, _client_manager: client::Manager) {}
+    /// Pass registry of error conditions to backend to let it report operational problems
+    fn set_error_registry(&mut self, _error_registry: Arc<error::ErrorRegistry>) {}
     /// Optional information about backend
     fn info(&self) -> Option<BackendInfo> {
         None
     }
+    /// Optional periodic dump of mining statistics into CSV file
+    fn stats_csv(&self) -> Option<stats::csv::CsvConfig> {
+        None
+    }
+    /// Optional period of status line with mining statistics of each work solver in the log
+    fn stats_log_interval(&self) -> Option<Duration> {
+        None
+    }
+    /// Minimal difficulty of a new best share which is worth logging (no logging when `None`)
+    fn best_share_log_threshold(&self) -> Option<u64> {
+        None
+    }
+    /// Optional Unix domain socket streaming valid solutions as newline-delimited JSON
+    fn solution_socket(&self) -> Option<PathBuf> {
+        None
+    }
+    /// Optional directory where the work in flight of each chain is saved when the miner panics
+    fn inflight_dump_dir(&self) -> Option<PathBuf> {
+        None
+    }
+    /// Verify all hardware solutions on CPU and report offsets of nonce/nTime in statistics
+    fn cross_check_solutions(&self) -> bool {
+        false
+    }
+    /// Submit block candidates to the lowest-latency endpoint able to accept them in addition to
+    /// the pool which issued the job
+    fn fastest_block_submission(&self) -> bool {
+        false
+    }
+    /// Verify solutions from the solving midstate which saves one SHA256 compression per hash
+    fn midstate_verification(&self) -> bool {
+        false
+    }
+    /// Number of extranonce values the hardware is able to roll on its own within single work
+    /// (zero when it is not supported)
+    fn extranonce_rolling(&self) -> u32 {
+        0
+    }
+    /// Maximal number of seconds the nTime can be rolled forward from the job time (it never
+    /// exceeds the limit given by the job itself)
+    fn max_ntime_roll(&self) -> Option<u32> {
+        None
+    }
+    /// Seed of the generated extranonce sequence which makes the work reproducible (otherwise
+    /// the sequence starts at a position derived from current time)
+    fn work_seed(&self) -> Option<u64> {
+        None
+    }
+    /// Strategy of distributing jobs among work which suits the hardware work FIFO best
+    fn work_distribution(&self) -> work::Distribution {
+        Default::default()
+    }
+    /// Period after start of the miner (or after change of frequency of a hash chain) during which
+    /// the solutions are not reflected in the displayed hashrate
+    fn hashrate_warmup(&self) -> Option<Duration> {
+        None
+    }
+    /// Optional pinning of work generation and solution handling to dedicated CPU cores
+    fn core_affinity(&self) -> affinity::Config {
+        Default::default()
+    }
 }
 
 pub struct FrontendConfig {
     pub cgminer_custom_commands: Option<command::Map>,
+    /// Source of miner temperature used for statistics
+    pub temperature_source: Option<stats::csv::TemperatureSource>,
 }
 
 /// Minimal interface for running compatible backend with BOSminer crate
@@ -155,3 +261,26 @@ pub trait Backend: Send + Sync + 'static {
         work_solver: Arc<Self::Type>,
     ) -> error::Result<FrontendConfig>;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::job::Bitcoin as _;
+    use crate::test_utils;
+
+    #[test]
+    fn test_verify_midstate() {
+        for block in test_utils::TEST_BLOCKS.iter() {
+            assert!(verify_midstate(block, block.version(), &block.midstate));
+
+            // Flip single bit of the midstate as a defective core would do
+            let mut corrupted: [u8; 32] = block.midstate.into();
+            corrupted[7] ^= 0x10;
+            assert!(!verify_midstate(block, block.version(), &corrupted.into()));
+
+            // Midstate does not match other (rolled) version
+            let rolled_version = block.version() ^ (1 << ii_bitcoin::BIP320_VERSION_SHIFT);
+            assert!(!verify_midstate(block, rolled_version, &block.midstate));
+        }
+    }
+}
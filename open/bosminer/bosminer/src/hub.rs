@@ -23,21 +23,32 @@
 //! Top level builder for `job::Solver` and `work::Solver` intended to be used when instantiating
 //! the full miner
 
+pub mod sink;
+
 use ii_logging::macros::*;
 
+use crate::affinity;
 use crate::backend;
 use crate::client;
 use crate::error;
 use crate::hal::{self, BackendConfig};
-use crate::node;
+use crate::node::{self, Stats as _};
+use crate::stats;
 use crate::work;
 
+use bosminer_config::PoolConfig;
+
 use futures::channel::mpsc;
 use futures::lock::Mutex;
 use futures::stream::StreamExt;
 use ii_async_compat::{futures, tokio};
 
-use std::sync::{Arc, Weak};
+use std::collections::VecDeque;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex, MutexGuard as StdMutexGuard, Weak};
+use std::time;
 
 /// Handle external events. Currently it is used only wor handling exhausted work from work engine.
 /// It usually signals some serious problem in backend.
@@ -50,27 +61,194 @@ impl work::ExhaustedHandler for EventHandler {
     }
 }
 
+/// Neighbourhood of reported nonce and nTime scanned during cross-check of hardware solutions
+const CROSS_CHECK_RADIUS: u32 = 2;
+
+/// Number of the most recent solutions kept for live monitoring
+pub const SOLUTION_FEED_CAPACITY: usize = 100;
+
+/// Snapshot of a recent solution intended for live feed of mining progress
+#[derive(Debug, Clone)]
+pub struct SolutionRecord {
+    /// Id of the work solver (e.g. hash chain index) which has found the solution
+    pub solver_id: Option<usize>,
+    pub nonce: u32,
+    pub hash: ii_bitcoin::DHash,
+    /// Difficulty computed from the hash of the solution
+    pub share_difficulty: f64,
+    /// Difficulty required by the job
+    pub job_difficulty: f64,
+    /// Time elapsed since the solution has been received from the backend
+    pub age: time::Duration,
+    /// Response of the remote server to the submission when it is already known
+    pub outcome: Option<work::SubmitOutcome>,
+}
+
+impl From<&work::Solution> for SolutionRecord {
+    fn from(solution: &work::Solution) -> Self {
+        let hash = *solution.hash();
+        let network_params = solution.network_params();
+        Self {
+            solver_id: solution.solver_id(),
+            nonce: solution.nonce(),
+            hash,
+            share_difficulty: network_params.difficulty(&hash.into()),
+            job_difficulty: network_params.difficulty(solution.job_target()),
+            age: solution.timestamp().elapsed(),
+            outcome: solution.outcome().cloned(),
+        }
+    }
+}
+
+/// Bounded ring buffer with the most recent solutions meeting their job target (i.e. submitted to
+/// the clients) from all work solvers. The outcome of submission is shared with the solution
+/// passed to the client so it is updated in place.
+#[derive(Debug)]
+pub struct SolutionFeed {
+    capacity: usize,
+    solutions: StdMutex<VecDeque<work::Solution>>,
+}
+
+impl SolutionFeed {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            solutions: StdMutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    fn lock(&self) -> StdMutexGuard<VecDeque<work::Solution>> {
+        self.solutions
+            .lock()
+            .expect("BUG: cannot lock solution feed")
+    }
+
+    /// Append the solution and drop the oldest one when the feed is full
+    pub fn push(&self, solution: work::Solution) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut solutions = self.lock();
+        if solutions.len() == self.capacity {
+            solutions.pop_front();
+        }
+        solutions.push_back(solution);
+    }
+
+    /// Return records of retained solutions ordered from the oldest to the most recent one
+    pub fn records(&self) -> Vec<SolutionRecord> {
+        self.lock().iter().map(SolutionRecord::from).collect()
+    }
+}
+
 /// Responsible for delivering work solution to the client from which the work has been generated
 struct SolutionRouter {
     job_executor: Arc<client::JobExecutor>,
+    /// Recent solutions for live monitoring
+    solution_feed: Arc<SolutionFeed>,
+    /// Stream of solutions for external monitoring tools
+    solution_sink: Option<Arc<sink::SolutionSink>>,
     solution_receiver: mpsc::UnboundedReceiver<work::Solution>,
+    /// Verify all hardware solutions on CPU (intended for debugging suspect hardware)
+    cross_check: bool,
+    /// Submit block candidates also to the fastest endpoint regardless of the job origin
+    fastest_block_submission: bool,
+    /// Route solutions on a dedicated thread pinned to these cores
+    pinning: Option<affinity::Pinning>,
 }
 
 impl SolutionRouter {
     fn new(
         job_executor: Arc<client::JobExecutor>,
+        solution_feed: Arc<SolutionFeed>,
         solution_receiver: mpsc::UnboundedReceiver<work::Solution>,
     ) -> Self {
         Self {
             job_executor,
+            solution_feed,
+            solution_sink: None,
             solution_receiver,
+            cross_check: false,
+            fastest_block_submission: false,
+            pinning: None,
+        }
+    }
+
+    /// Verify the solution on CPU and account the result in all nodes in its path
+    fn cross_check(solution: &work::Solution) {
+        let result = solution.cross_check(CROSS_CHECK_RADIUS);
+        if let work::CrossCheck::Offset(offset) = result {
+            warn!(
+                "Hub: hardware solution {:?} is off by {} from the real solution",
+                solution, offset
+            );
+        }
+        for node in solution.path() {
+            node.mining_stats().cross_check().account(result);
+        }
+    }
+
+    /// Drop malformed solution which cannot be processed and account it in all nodes in its path
+    /// Returns `false` when the solution has been dropped.
+    fn check(solution: &work::Solution) -> bool {
+        match solution.validate() {
+            Ok(()) => true,
+            Err(fault) => {
+                let time_offset = solution.time_offset();
+                if time_offset < 0 {
+                    // solution cannot be found before its job has been created
+                    warn!(
+                        "Hub: dropping solution with nTime {} s before time of its job (clock skew of the pool?): {}",
+                        -time_offset, fault
+                    );
+                } else {
+                    warn!("Hub: dropping malformed solution from backend: {}", fault);
+                }
+                for node in solution.path() {
+                    node.mining_stats().dropped_solutions().inc();
+                }
+                false
+            }
         }
     }
 
     async fn run(mut self) {
         while let Some(solution) = self.solution_receiver.next().await {
+            if !Self::check(&solution) {
+                continue;
+            }
+            if self.cross_check {
+                Self::cross_check(&solution);
+            }
+            // solutions not meeting the job target are not submitted so they are never resolved
+            if solution.hash().meets(solution.job_target()) {
+                self.solution_feed.push(solution.clone());
+            }
+            if let Some(solution_sink) = &self.solution_sink {
+                // only solutions meeting the backend target are valid
+                if solution.hash().meets(solution.backend_target()) {
+                    solution_sink.emit(&solution);
+                }
+            }
+            if self.fastest_block_submission && solution.is_block() {
+                let solution_senders = self
+                    .job_executor
+                    .get_block_solution_senders(&solution)
+                    .await;
+                if solution_senders.is_empty() {
+                    warn!("Hub: block candidate has been discarded because no client exists");
+                }
+                for (solution_sender, solution) in solution_senders {
+                    solution_sender
+                        .unbounded_send(solution)
+                        .expect("solution queue send failed");
+                }
+                continue;
+            }
             // NOTE: all solutions targeting to removed clients are discarded
-            if let Some(solution_sender) = self.job_executor.get_solution_sender(&solution).await {
+            if let Some((solution_sender, solution)) =
+                self.job_executor.get_solution_sender(&solution).await
+            {
                 solution_sender
                     .unbounded_send(solution)
                     .expect("solution queue send failed");
@@ -81,6 +259,56 @@ impl SolutionRouter {
     }
 }
 
+/// Synchronous handle for capturing the work in flight on all chains. It does not depend on the
+/// executor so it can be used from a panic hook or a signal handler.
+#[derive(Debug, Clone)]
+pub struct InflightDump {
+    inflight_works: Vec<Arc<work::InflightWork>>,
+}
+
+impl InflightDump {
+    /// Name of the file with in-flight work of chain `chain_id` written by `save`
+    pub fn file_name(chain_id: usize) -> String {
+        format!("inflight-chain{}.bin", chain_id)
+    }
+
+    /// Return snapshots of the work in flight on all chains (ordered by chain index and from the
+    /// oldest work)
+    pub fn dump_inflight(&self) -> Vec<work::replay::WorkSnapshot> {
+        self.inflight_works
+            .iter()
+            .flat_map(|inflight| inflight.snapshot())
+            .collect()
+    }
+
+    /// Write the work in flight of each chain into separate file in `dir`. The files can be
+    /// reloaded with `work::replay::decode_snapshots`.
+    pub fn save(&self, dir: &Path) -> error::Result<()> {
+        fs::create_dir_all(dir)?;
+        for (chain_id, inflight) in self.inflight_works.iter().enumerate() {
+            let bytes = work::replay::encode_snapshots(&inflight.snapshot());
+            fs::write(dir.join(Self::file_name(chain_id)), bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Register a panic hook which saves the work in flight into `dir` (see `save`) before the
+    /// previously registered hook is called
+    pub fn install_panic_hook(self, dir: PathBuf) {
+        let default_hook = panic::take_hook();
+
+        let our_hook = move |pi: &panic::PanicInfo| {
+            match self.save(&dir) {
+                Ok(()) => error!("Work in flight has been saved into '{}'", dir.display()),
+                Err(e) => error!("Cannot save work in flight into '{}': {}", dir.display(), e),
+            }
+            default_hook(pi);
+        };
+
+        panic::set_hook(Box::new(our_hook));
+    }
+}
+
 pub struct Core {
     pub backend_info: Option<hal::BackendInfo>,
     // NOTE: Weak reference must be released first!
@@ -90,8 +318,23 @@ pub struct Core {
     engine_receiver: work::EngineReceiver,
     solution_sender: mpsc::UnboundedSender<work::Solution>,
     solution_router: Mutex<Option<SolutionRouter>>,
+    /// Recent solutions from all work solvers with their submission outcome
+    solution_feed: Arc<SolutionFeed>,
     /// Registry of clients that are able to supply new jobs for mining
     client_manager: client::Manager,
+    /// Active and recent error conditions reported by all subsystems
+    pub error_registry: Arc<error::ErrorRegistry>,
+    /// Maximal number of midstates supported by the backend
+    midstate_count: usize,
+    /// Midstate counts of work which the backend is able to accept at runtime
+    supported_midstate_counts: StdMutex<Vec<usize>>,
+    /// Active strategy of distributing jobs among work of all work solvers
+    work_distribution: StdMutex<work::Distribution>,
+    /// Cores dedicated to work generation of all work solvers built by the core
+    work_pinning: Mutex<Option<affinity::Pinning>>,
+    /// Sink of the solution router which also receives simulated shares
+    #[cfg(any(test, feature = "share-simulation"))]
+    solution_sink: Mutex<Option<Arc<sink::SolutionSink>>>,
 }
 
 /// Concentrates handles to all nodes associated with mining (backends, clients, work solvers)
@@ -106,12 +349,15 @@ impl Core {
         let (engine_sender, engine_receiver) = work::engine_channel(EventHandler);
         let (solution_sender, solution_receiver) = mpsc::unbounded();
 
-        let client_manager = client::Manager::new(midstate_count);
+        let error_registry = Arc::new(error::ErrorRegistry::new());
+        let client_manager =
+            client::Manager::with_error_registry(midstate_count, error_registry.clone());
         let job_executor = Arc::new(client::JobExecutor::new(
             frontend.clone(),
             engine_sender,
             client_manager.clone(),
         ));
+        let solution_feed = Arc::new(SolutionFeed::new(SOLUTION_FEED_CAPACITY));
 
         Self {
             backend_info,
@@ -120,11 +366,75 @@ impl Core {
             job_executor: job_executor.clone(),
             engine_receiver,
             solution_sender,
-            solution_router: Mutex::new(Some(SolutionRouter::new(job_executor, solution_receiver))),
+            solution_router: Mutex::new(Some(SolutionRouter::new(
+                job_executor,
+                solution_feed.clone(),
+                solution_receiver,
+            ))),
+            solution_feed,
             client_manager,
+            error_registry,
+            midstate_count,
+            supported_midstate_counts: StdMutex::new(vec![midstate_count]),
+            work_distribution: StdMutex::new(Default::default()),
+            work_pinning: Mutex::new(None),
+            #[cfg(any(test, feature = "share-simulation"))]
+            solution_sink: Mutex::new(None),
         }
     }
 
+    /// Pin work generation and solution handling tasks to dedicated cores
+    /// It has to be called before the backend is built and the core is started.
+    pub async fn set_core_affinity(&self, config: affinity::Config) {
+        self.solution_router
+            .lock()
+            .await
+            .as_mut()
+            .expect("BUG: solution router has been already started")
+            .pinning = config.solution_handling;
+        *self.work_pinning.lock().await = config.work_generation;
+    }
+
+    /// Enable CPU verification of all solutions found by the hardware
+    /// It has to be called before the core is started.
+    pub async fn enable_cross_check(&self) {
+        self.solution_router
+            .lock()
+            .await
+            .as_mut()
+            .expect("BUG: solution router has been already started")
+            .cross_check = true;
+    }
+
+    /// Submit block candidates to the fastest endpoint in addition to the client which issued
+    /// the job (see `client::JobExecutor::get_block_solution_senders`)
+    /// It has to be called before the core is started.
+    pub async fn enable_fastest_block_submission(&self) {
+        self.solution_router
+            .lock()
+            .await
+            .as_mut()
+            .expect("BUG: solution router has been already started")
+            .fastest_block_submission = true;
+    }
+
+    /// Emit all valid solutions to subscribers of the sink
+    /// It has to be called before the core is started.
+    pub async fn set_solution_sink(&self, solution_sink: Arc<sink::SolutionSink>) {
+        #[cfg(any(test, feature = "share-simulation"))]
+        self.solution_sink
+            .lock()
+            .await
+            .replace(solution_sink.clone());
+        self.solution_router
+            .lock()
+            .await
+            .as_mut()
+            .expect("BUG: solution router has been already started")
+            .solution_sink
+            .replace(solution_sink);
+    }
+
     /// Builds a new backend for a specified `backend_config`.
     /// The resulting `hal::FrontendConfig` is then available for starting additional BOSminer
     /// components
@@ -132,7 +442,7 @@ impl Core {
         &self,
         mut backend_config: T::Config,
     ) -> error::Result<hal::FrontendConfig> {
-        let work_solver_builder = work::SolverBuilder::new(
+        let mut work_solver_builder = work::SolverBuilder::new(
             self.frontend.clone(),
             self.backend_registry
                 .upgrade()
@@ -140,8 +450,11 @@ impl Core {
             self.engine_receiver.clone(),
             self.solution_sender.clone(),
         );
+        work_solver_builder.set_work_pinning(self.work_pinning.lock().await.clone());
+        work_solver_builder.set_midstate_verification(backend_config.midstate_verification());
 
         backend_config.set_client_manager(self.get_client_manager().clone());
+        backend_config.set_error_registry(self.error_registry.clone());
         // call backend create to determine the preferred hierarchy
         match T::create(&mut backend_config) {
             // the generic tree hierarchy where the backend consists of multiple devices
@@ -197,10 +510,384 @@ impl Core {
         }
     }
 
+    /// Declare midstate counts of work which the backend is able to accept without restart (see
+    /// `hal::BackendConfig::supported_midstate_counts`)
+    pub fn set_supported_midstate_counts(&self, counts: Vec<usize>) {
+        *self
+            .supported_midstate_counts
+            .lock()
+            .expect("BUG: cannot lock supported midstate counts") = counts;
+    }
+
+    /// Check that work with `count` midstates can be generated for the backend
+    fn check_midstate_count(&self, count: usize) -> error::Result<()> {
+        let supported = self
+            .supported_midstate_counts
+            .lock()
+            .expect("BUG: cannot lock supported midstate counts");
+        if count == 0
+            || count > self.midstate_count
+            || self.midstate_count % count != 0
+            || !supported.contains(&count)
+        {
+            Err(error::ErrorKind::General(format!(
+                "unsupported midstate count {} (backend supports {:?} midstates)",
+                count, *supported
+            )))?
+        }
+        Ok(())
+    }
+
+    /// Change midstate count of work generated for work solver `chain_id` (index in the list of
+    /// registered work solvers). The count has to be one of the counts supported by the backend at
+    /// runtime. Work already split with the previous count is thrown away.
+    pub async fn set_midstate_count(&self, chain_id: usize, count: usize) -> error::Result<()> {
+        self.check_midstate_count(count)?;
+        let backend_registry = self
+            .backend_registry
+            .upgrade()
+            .ok_or_else(|| error::ErrorKind::General("backend does not exist".to_string()))?;
+        let midstate_counts = backend_registry.lock_midstate_counts().await;
+        midstate_counts
+            .get(chain_id)
+            .ok_or_else(|| error::ErrorKind::General(format!("chain {} does not exist", chain_id)))?
+            .set(count);
+        Ok(())
+    }
+
+    /// Generate work for all registered work solvers with midstate count given by `distribution`
+    /// strategy. It overrides midstate counts set by `set_midstate_count`. The strategy is
+    /// rejected when the backend does not accept work with resulting midstate count.
+    pub async fn set_work_distribution(
+        &self,
+        distribution: work::Distribution,
+    ) -> error::Result<()> {
+        let count = distribution.midstate_count(self.midstate_count);
+        if let Err(e) = self.check_midstate_count(count) {
+            Err(error::ErrorKind::General(format!(
+                "work distribution by {} is not supported: {}",
+                distribution, e
+            )))?
+        }
+        let backend_registry = self
+            .backend_registry
+            .upgrade()
+            .ok_or_else(|| error::ErrorKind::General("backend does not exist".to_string()))?;
+        for midstate_count in backend_registry.lock_midstate_counts().await.iter() {
+            midstate_count.set(count);
+        }
+        *self
+            .work_distribution
+            .lock()
+            .expect("BUG: cannot lock work distribution") = distribution;
+        info!(
+            "Work is distributed by {} ({} midstates per work)",
+            distribution, count
+        );
+        Ok(())
+    }
+
+    /// Return currently active strategy of work distribution
+    pub fn work_distribution(&self) -> work::Distribution {
+        *self
+            .work_distribution
+            .lock()
+            .expect("BUG: cannot lock work distribution")
+    }
+
+    /// Stop or resume routing of work to work solver `chain_id` (index in the list of registered
+    /// work solvers). Disabled chain is kept alive so it can still be monitored but it does not
+    /// receive any new work and it is excluded from the nominal hash rate.
+    pub async fn set_chain_enabled(&self, chain_id: usize, enabled: bool) -> error::Result<()> {
+        let backend_registry = self
+            .backend_registry
+            .upgrade()
+            .ok_or_else(|| error::ErrorKind::General("backend does not exist".to_string()))?;
+        let work_solver = backend_registry
+            .lock_work_solvers()
+            .await
+            .get(chain_id)
+            .cloned()
+            .ok_or_else(|| {
+                error::ErrorKind::General(format!("chain {} does not exist", chain_id))
+            })?;
+        backend_registry
+            .get_solver_switch(&work_solver)
+            .await
+            .ok_or_else(|| {
+                error::ErrorKind::General(format!("chain {} cannot be disabled", chain_id))
+            })?
+            .set_enabled(enabled);
+        // disabled chain doesn't start new jobs so it cannot hold them back from being live
+        backend_registry
+            .job_barrier()
+            .set_participating(chain_id, enabled);
+        Ok(())
+    }
+
+    /// Gracefully reduce hash rate of all registered work solvers to `fraction` of their nominal
+    /// hash rate (e.g. during demand-response event). The backends converge to the capped hash
+    /// rate gradually and setting `fraction` to `work::HashrateCap::RELEASED` restores the
+    /// nominal hash rate.
+    pub async fn set_hashrate_cap(&self, fraction: f64) -> error::Result<()> {
+        if !work::HashrateCap::is_valid(fraction) {
+            Err(error::ErrorKind::General(format!(
+                "invalid hash rate cap {} (expected fraction in range (0, 1])",
+                fraction
+            )))?
+        }
+        let backend_registry = self
+            .backend_registry
+            .upgrade()
+            .ok_or_else(|| error::ErrorKind::General("backend does not exist".to_string()))?;
+        for hashrate_cap in backend_registry.lock_hashrate_caps().await.iter() {
+            hashrate_cap.set(fraction);
+        }
+        Ok(())
+    }
+
+    /// Restore nominal hash rate of all registered work solvers
+    pub async fn release_hashrate_cap(&self) -> error::Result<()> {
+        self.set_hashrate_cap(work::HashrateCap::RELEASED).await
+    }
+
+    /// Check if work is routed to `work_solver` (work solvers created without
+    /// `work::SolverBuilder` cannot be disabled)
+    async fn is_chain_enabled(&self, work_solver: &Arc<dyn node::WorkSolver>) -> bool {
+        match self.backend_registry.upgrade() {
+            Some(backend_registry) => backend_registry
+                .get_solver_switch(work_solver)
+                .await
+                .map_or(true, |switch| switch.is_enabled()),
+            None => true,
+        }
+    }
+
+    /// Return generation of work engines used by work solver `chain_id` (index in the list of
+    /// registered work solvers). The generation is bumped every time the chain starts working on
+    /// a new job so comparing generations of chains reveals those that lag behind.
+    pub async fn current_generation(&self, chain_id: usize) -> Option<u64> {
+        self.backend_registry
+            .upgrade()?
+            .lock_work_generations()
+            .await
+            .get(chain_id)
+            .map(|generation| generation.get())
+    }
+
+    /// Return the most recent job which has been started by all enabled chains
+    pub fn live_job(&self) -> Option<work::LiveJob> {
+        self.backend_registry.upgrade()?.job_barrier().live_job()
+    }
+
+    /// Number of jobs which have been started by all enabled chains
+    pub fn live_job_count(&self) -> u64 {
+        self.backend_registry
+            .upgrade()
+            .map_or(0, |backend_registry| {
+                backend_registry.job_barrier().live_count()
+            })
+    }
+
+    /// Return source of generations of all registered work solvers for statistics export
+    pub async fn generation_source(&self) -> stats::csv::GenerationSource {
+        let generations: Vec<_> = match self.backend_registry.upgrade() {
+            Some(backend_registry) => backend_registry
+                .lock_work_generations()
+                .await
+                .iter()
+                .cloned()
+                .collect(),
+            None => vec![],
+        };
+        Box::new(move || {
+            generations
+                .iter()
+                .map(|generation| generation.get())
+                .collect()
+        })
+    }
+
+    /// Return handle for dumping the work in flight of all registered work solvers. It should be
+    /// obtained after the backend has been initialized because later registered work solvers are
+    /// not included.
+    pub async fn inflight_dump(&self) -> InflightDump {
+        let inflight_works = match self.backend_registry.upgrade() {
+            Some(backend_registry) => backend_registry
+                .lock_inflight_works()
+                .await
+                .iter()
+                .cloned()
+                .collect(),
+            None => vec![],
+        };
+        InflightDump { inflight_works }
+    }
+
+    /// Most recent solutions (up to `SOLUTION_FEED_CAPACITY`) ordered from the oldest one
+    pub fn recent_solutions(&self) -> Vec<SolutionRecord> {
+        self.solution_feed.records()
+    }
+
     pub fn get_client_manager(&self) -> &client::Manager {
         &self.client_manager
     }
 
+    /// Hook for an external profit-switching controller (see `client::SwitchSignal`)
+    pub fn profit_switch_sender(&self) -> mpsc::UnboundedSender<client::SwitchSignal> {
+        self.job_executor.switch_sender()
+    }
+
+    /// Atomically swap the active pool set while keeping connections to unchanged pools
+    pub async fn replace_pools(&self, pools: Vec<PoolConfig>) -> error::Result<()> {
+        let retired = self
+            .client_manager
+            .replace_pools(pools, self.backend_info.as_ref(), true)
+            .await?;
+        if !retired.is_empty() {
+            info!("Retired {} client(s) after pool set change", retired.len());
+        }
+        Ok(())
+    }
+
+    /// Connection state, job and submission metrics of all configured pools
+    pub async fn pool_health(&self) -> Vec<client::PoolHealth> {
+        self.client_manager.pool_health().await
+    }
+
+    /// Estimate luck of the whole session from valid shares at job difficulty and nominal hash
+    /// rate of all enabled work solvers (see `stats::MeterSnapshot::luck`)
+    pub async fn luck(&self) -> Option<f64> {
+        let mut nominal_hashrate = 0.0;
+        for work_solver in self.get_work_solvers().await {
+            if !self.is_chain_enabled(&work_solver).await {
+                continue;
+            }
+            if let Some(hashrate) = work_solver.get_nominal_hashrate().await {
+                nominal_hashrate += hashrate.into_hashes().into_f64();
+            }
+        }
+        let mining_stats = self.frontend.mining_stats();
+        mining_stats.valid_job_diff().take_snapshot().await.luck(
+            ii_bitcoin::HashesUnit::Hashes(nominal_hashrate as u128),
+            mining_stats.start_time().elapsed(),
+        )
+    }
+
+    /// Efficiency in J/GH of a single work solver computed from its estimated power and hash rate
+    /// of valid backend solutions measured within `interval`
+    pub async fn chain_efficiency(&self, chain_id: usize, interval: time::Duration) -> Option<f64> {
+        let work_solver = self.get_work_solvers().await.into_iter().nth(chain_id)?;
+        let power = work_solver.get_estimated_power().await?;
+        work_solver
+            .mining_stats()
+            .valid_backend_diff()
+            .take_snapshot()
+            .await
+            .efficiency(power, interval, time::Instant::now())
+    }
+
+    /// Aggregate efficiency in J/GH of all enabled work solvers with known power consumption
+    /// (total power divided by total hash rate measured within `interval`)
+    pub async fn efficiency(&self, interval: time::Duration) -> Option<f64> {
+        let now = time::Instant::now();
+        let mut total_power = 0.0;
+        let mut total_giga_hashes = 0.0;
+        let mut power_known = false;
+        for work_solver in self.get_work_solvers().await {
+            if !self.is_chain_enabled(&work_solver).await {
+                continue;
+            }
+            // chains without power estimate would make the result look better than it is
+            if let Some(power) = work_solver.get_estimated_power().await {
+                power_known = true;
+                total_power += power;
+                total_giga_hashes += work_solver
+                    .mining_stats()
+                    .valid_backend_diff()
+                    .take_snapshot()
+                    .await
+                    .to_giga_hashes(interval, now)
+                    .into_f64();
+            }
+        }
+        if !power_known {
+            return None;
+        }
+        stats::efficiency(
+            total_power,
+            ii_bitcoin::HashesUnit::GigaHashes(total_giga_hashes),
+        )
+    }
+
+    /// Closure estimating aggregate efficiency for periodic export of statistics
+    pub fn efficiency_source(
+        self: Arc<Self>,
+        interval: time::Duration,
+    ) -> stats::csv::EfficiencySource {
+        Box::new(move || {
+            let core = self.clone();
+            Box::pin(async move { core.efficiency(interval).await })
+        })
+    }
+
+    /// Start hashrate warm-up in the frontend and in all registered work solvers
+    pub async fn start_warmup(&self, duration: time::Duration) {
+        self.frontend.mining_stats().start_warmup(duration);
+        for work_solver in self.get_work_solvers().await {
+            work_solver.mining_stats().start_warmup(duration);
+        }
+    }
+
+    /// Inject a share with `difficulty` accepted by the pool in use (or by the first one when
+    /// none is running) without involving any hardware or remote server. The share passes the
+    /// same accounting as a real one so that all exports of statistics reflect it and monitoring
+    /// with alerting can be verified end to end. Subscribers of the solution sink are notified
+    /// about the share too.
+    /// NOTE: The simulated shares distort statistics so it is not available in production builds.
+    #[cfg(any(test, feature = "share-simulation"))]
+    pub async fn simulate_accepted_share(&self, difficulty: f64) -> error::Result<()> {
+        if !difficulty.is_finite() || difficulty <= 0.0 {
+            Err(error::ErrorKind::General(format!(
+                "invalid share difficulty {}",
+                difficulty
+            )))?
+        }
+        let mut clients = vec![];
+        for group in self.client_manager.get_groups().await {
+            clients.extend(group.get_clients().await.into_iter());
+        }
+        let client = clients
+            .iter()
+            .find(|client| client.is_running())
+            .or_else(|| clients.first())
+            .ok_or_else(|| error::ErrorKind::General("no pool for simulated share".to_string()))?;
+
+        warn!("Simulating accepted share with difficulty {}", difficulty);
+        // the difficulty is related to the network of the last job of the pool
+        let network_params = client
+            .get_last_job()
+            .await
+            .map(|job| job.network_params())
+            .unwrap_or_default();
+        let target = network_params.target_from_difficulty_f64(difficulty);
+        let now = time::Instant::now();
+        // the share is accounted in the same nodes as a solution of the pool job except for the
+        // backend nodes which have not taken part in it
+        let frontend: node::DynInfo = self.frontend.clone();
+        let path = vec![client.node(), frontend];
+        stats::account_simulated_share(&path, &target, &network_params, now).await;
+        client
+            .stats()
+            .accepted()
+            .account_solution(&target, now)
+            .await;
+        if let Some(solution_sink) = self.solution_sink.lock().await.as_ref() {
+            solution_sink.emit_event(sink::SolutionEvent::simulated(&target, &network_params));
+        }
+        Ok(())
+    }
+
     pub async fn run(self: Arc<Self>) {
         let solution_router = self
             .solution_router
@@ -209,7 +896,17 @@ impl Core {
             .take()
             .expect("missing solution router");
 
-        tokio::spawn(solution_router.run());
+        match solution_router.pinning.clone() {
+            Some(pinning) => {
+                info!("Hub: routing solutions on cores {:?}", pinning.cores());
+                pinning
+                    .spawn("solution-router", solution_router.run())
+                    .expect("BUG: cannot spawn solution router thread");
+            }
+            None => {
+                tokio::spawn(solution_router.run());
+            }
+        }
         self.job_executor.clone().run().await;
     }
 }
@@ -217,15 +914,24 @@ impl Core {
 #[cfg(test)]
 pub mod test {
     use super::*;
-    use crate::job;
+    use crate::job::{self, Bitcoin as _};
     use crate::test_utils;
     use crate::Frontend;
 
-    use std::sync::Arc;
+    use async_trait::async_trait;
+
+    use std::sync::{Arc, Mutex as StdMutex};
 
     /// Create job solver for frontend (pool) and work solver builder for backend (as we expect a
     /// hierarchical structure in backends)
     fn build_solvers() -> (job::Solver, work::SolverBuilder<Frontend>) {
+        build_solvers_with_registry(Arc::new(backend::Registry::new()))
+    }
+
+    /// Same as `build_solvers` but all nodes are registered into `backend_registry`
+    fn build_solvers_with_registry(
+        backend_registry: Arc<backend::Registry>,
+    ) -> (job::Solver, work::SolverBuilder<Frontend>) {
         let (engine_sender, engine_receiver) = work::engine_channel(EventHandler);
         let (solution_sender, solution_receiver) = mpsc::unbounded();
         let frontend = Arc::new(crate::Frontend::new());
@@ -234,12 +940,7 @@ pub mod test {
         }));
         (
             job::Solver::new(Arc::new(engine_sender), solution_receiver),
-            work::SolverBuilder::new(
-                frontend,
-                Arc::new(backend::Registry::new()),
-                engine_receiver,
-                solution_sender,
-            ),
+            work::SolverBuilder::new(frontend, backend_registry, engine_receiver, solution_sender),
         )
     }
 
@@ -290,4 +991,801 @@ pub mod test {
         drop(job_solver);
         assert!(work_generator.generate().await.is_some());
     }
+
+    /// Trivial job source which provides all testing blocks
+    struct TestJobSource {
+        blocks: std::slice::Iter<'static, test_utils::TestBlock>,
+        started: bool,
+        stopped: Arc<StdMutex<bool>>,
+    }
+
+    #[async_trait]
+    impl job::Source for TestJobSource {
+        async fn start(&mut self) {
+            self.started = true;
+        }
+
+        async fn next_job(&mut self) -> Option<Arc<dyn job::Bitcoin>> {
+            assert!(self.started, "BUG: job source has not been started");
+            self.blocks
+                .next()
+                .map(|block| Arc::new(*block) as Arc<dyn job::Bitcoin>)
+        }
+
+        async fn stop(&mut self) {
+            *self.stopped.lock().expect("BUG: cannot lock stop flag") = true;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_job_source() {
+        let (job_solver, work_solver_builder) = build_solvers();
+
+        let mut work_generator = None;
+        work_solver_builder
+            .create_work_solver(|local_work_generator, _| {
+                work_generator = Some(local_work_generator);
+                Arc::new(test_utils::TestWorkSolver::new())
+            })
+            .await;
+        let mut work_generator = work_generator.unwrap();
+
+        let stopped = Arc::new(StdMutex::new(false));
+        let job_source = TestJobSource {
+            blocks: test_utils::TEST_BLOCKS.iter(),
+            started: false,
+            stopped: stopped.clone(),
+        };
+        job_solver.job_sender.consume(job_source).await;
+        assert!(*stopped.lock().expect("BUG: cannot lock stop flag"));
+
+        // work is generated from the last job provided by the source
+        let block = test_utils::TEST_BLOCKS.last().expect("BUG: missing test block");
+        let work = work_generator.generate().await.unwrap();
+        assert_eq!(block.midstate, work.midstates[0].state);
+    }
+
+    #[tokio::test]
+    async fn test_set_midstate_count() {
+        use crate::backend::HierarchyBuilder;
+
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(4, &backend_registry, None);
+        let midstate_count = Arc::new(work::MidstateCount::new());
+        backend_registry
+            .add_midstate_count(midstate_count.clone())
+            .await;
+
+        // by default the backend does not accept any other count than the one it is started with
+        assert!(core.set_midstate_count(0, 2).await.is_err());
+        assert_eq!(midstate_count.get(), None);
+
+        core.set_supported_midstate_counts(vec![1, 2, 3, 4]);
+        core.set_midstate_count(0, 2)
+            .await
+            .expect("BUG: cannot set midstate count");
+        assert_eq!(midstate_count.get(), Some(2));
+
+        // the count has to be supported by the hardware
+        assert!(core.set_midstate_count(0, 0).await.is_err());
+        assert!(core.set_midstate_count(0, 3).await.is_err());
+        assert!(core.set_midstate_count(0, 8).await.is_err());
+        // the chain has to exist
+        assert!(core.set_midstate_count(1, 1).await.is_err());
+        assert_eq!(midstate_count.get(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_set_work_distribution() {
+        use crate::backend::HierarchyBuilder;
+
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(4, &backend_registry, None);
+        let midstate_counts: Vec<_> = (0..2)
+            .map(|_| Arc::new(work::MidstateCount::new()))
+            .collect();
+        for midstate_count in midstate_counts.iter() {
+            backend_registry
+                .add_midstate_count(midstate_count.clone())
+                .await;
+        }
+        assert_eq!(core.work_distribution(), work::Distribution::Midstates);
+
+        // hardware with fixed midstate count cannot distribute work among more work items
+        assert!(core
+            .set_work_distribution(work::Distribution::WorkItems)
+            .await
+            .is_err());
+        assert_eq!(core.work_distribution(), work::Distribution::Midstates);
+        assert!(midstate_counts.iter().all(|count| count.get() == None));
+
+        core.set_supported_midstate_counts(vec![1, 4]);
+        core.set_work_distribution(work::Distribution::WorkItems)
+            .await
+            .expect("BUG: cannot set work distribution");
+        assert_eq!(core.work_distribution(), work::Distribution::WorkItems);
+        assert!(midstate_counts.iter().all(|count| count.get() == Some(1)));
+
+        core.set_work_distribution(work::Distribution::Midstates)
+            .await
+            .expect("BUG: cannot set work distribution");
+        assert_eq!(core.work_distribution(), work::Distribution::Midstates);
+        assert!(midstate_counts.iter().all(|count| count.get() == Some(4)));
+    }
+
+    #[tokio::test]
+    async fn test_set_chain_enabled() {
+        use crate::backend::HierarchyBuilder;
+
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(4, &backend_registry, None);
+        let mut work_solvers: Vec<Arc<dyn node::WorkSolver>> = vec![];
+        let mut switches = vec![];
+        for _ in 0..3 {
+            let work_solver = Arc::new(test_utils::TestWorkSolver::new());
+            backend_registry.add_work_solver(work_solver.clone()).await;
+            let switch = Arc::new(work::SolverSwitch::new());
+            backend_registry.add_solver_switch(switch.clone()).await;
+            work_solvers.push(work_solver);
+            switches.push(switch);
+        }
+
+        core.set_chain_enabled(1, false)
+            .await
+            .expect("BUG: cannot disable chain");
+        let mut chains_enabled = vec![];
+        for work_solver in work_solvers.iter() {
+            chains_enabled.push(core.is_chain_enabled(work_solver).await);
+        }
+        assert_eq!(chains_enabled, vec![true, false, true]);
+        assert!(!switches[1].is_enabled());
+
+        core.set_chain_enabled(1, true)
+            .await
+            .expect("BUG: cannot enable chain");
+        assert!(switches.iter().all(|switch| switch.is_enabled()));
+
+        // the chain has to exist
+        assert!(core.set_chain_enabled(3, false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_chain_without_switch() {
+        use crate::backend::HierarchyBuilder;
+
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(4, &backend_registry, None);
+        // the first work solver has been created without a switch
+        let work_solvers: Vec<Arc<dyn node::WorkSolver>> = (0..2)
+            .map(|_| Arc::new(test_utils::TestWorkSolver::new()) as Arc<dyn node::WorkSolver>)
+            .collect();
+        for work_solver in work_solvers.iter() {
+            backend_registry.add_work_solver(work_solver.clone()).await;
+        }
+        let switch = Arc::new(work::SolverSwitch::new());
+        backend_registry.add_solver_switch(switch.clone()).await;
+
+        assert!(core.set_chain_enabled(0, false).await.is_err());
+        assert!(core.is_chain_enabled(&work_solvers[0]).await);
+        core.set_chain_enabled(1, false)
+            .await
+            .expect("BUG: cannot disable chain");
+        assert!(!switch.is_enabled());
+        assert!(core.is_chain_enabled(&work_solvers[0]).await);
+        assert!(!core.is_chain_enabled(&work_solvers[1]).await);
+    }
+
+    #[tokio::test]
+    async fn test_set_hashrate_cap() {
+        use crate::backend::HierarchyBuilder;
+
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(4, &backend_registry, None);
+        let hashrate_caps: Vec<_> = (0..2).map(|_| Arc::new(work::HashrateCap::new())).collect();
+        for hashrate_cap in hashrate_caps.iter() {
+            backend_registry
+                .add_hashrate_cap(hashrate_cap.clone())
+                .await;
+        }
+        core.set_hashrate_cap(0.5)
+            .await
+            .expect("BUG: cannot cap hash rate");
+        for hashrate_cap in hashrate_caps.iter() {
+            assert!(hashrate_cap.is_capped());
+            assert_eq!(hashrate_cap.get(), 0.5);
+        }
+
+        // invalid cap does not change the current one
+        assert!(core.set_hashrate_cap(0.0).await.is_err());
+        assert!(core.set_hashrate_cap(1.5).await.is_err());
+        assert!(hashrate_caps.iter().all(|cap| cap.get() == 0.5));
+
+        core.release_hashrate_cap()
+            .await
+            .expect("BUG: cannot release hash rate cap");
+        for hashrate_cap in hashrate_caps.iter() {
+            assert!(!hashrate_cap.is_capped());
+            assert_eq!(hashrate_cap.get(), work::HashrateCap::RELEASED);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_current_generation() {
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(1, &backend_registry, None);
+        let (job_solver, work_solver_builder) = build_solvers_with_registry(backend_registry);
+
+        let mut work_generators = vec![];
+        for _ in 0..2 {
+            work_solver_builder
+                .create_work_solver(|work_generator, _| {
+                    work_generators.push(work_generator);
+                    Arc::new(test_utils::TestWorkSolver::new())
+                })
+                .await;
+        }
+        assert_eq!(core.current_generation(0).await, Some(0));
+        assert_eq!(core.current_generation(2).await, None);
+        let generation_source = core.generation_source().await;
+
+        for (i, block) in test_utils::TEST_BLOCKS.iter().enumerate() {
+            job_solver.job_sender.send(Arc::new(*block));
+            // only the first chain keeps up with job updates
+            assert!(work_generators[0].generate().await.is_some());
+            let generation = i as u64 + 1;
+            assert_eq!(core.current_generation(0).await, Some(generation));
+            assert_eq!(generation_source(), vec![generation, 0]);
+        }
+
+        // the lagging chain catches up with the latest job at once
+        assert!(work_generators[1].generate().await.is_some());
+        assert_eq!(core.current_generation(1).await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_efficiency() {
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(1, &backend_registry, None);
+        let (_job_solver, work_solver_builder) = build_solvers_with_registry(backend_registry);
+
+        let mut work_solvers = vec![];
+        for work_solver in vec![
+            test_utils::TestWorkSolver::with_power(100.0),
+            test_utils::TestWorkSolver::new(),
+        ] {
+            work_solvers.push(
+                work_solver_builder
+                    .create_work_solver(|_, _| Arc::new(work_solver))
+                    .await,
+            );
+        }
+        let interval = *stats::TIME_MEAN_INTERVAL_1M;
+        // nothing has been computed yet
+        assert_eq!(core.chain_efficiency(0, interval).await, None);
+        assert_eq!(core.efficiency(interval).await, None);
+
+        let target = ii_bitcoin::Target::from_pool_difficulty(1000);
+        for work_solver in work_solvers.iter() {
+            work_solver
+                .mining_stats()
+                .valid_backend_diff()
+                .account_solution(&target, time::Instant::now())
+                .await;
+        }
+        let chain_efficiency = core
+            .chain_efficiency(0, interval)
+            .await
+            .expect("BUG: undefined chain efficiency");
+        // work solver without power estimate is not included in the aggregate
+        assert_eq!(core.chain_efficiency(1, interval).await, None);
+        let efficiency = core
+            .efficiency(interval)
+            .await
+            .expect("BUG: undefined efficiency");
+        assert!(
+            (efficiency - chain_efficiency).abs() < chain_efficiency * 1e-3,
+            "unexpected efficiency {}",
+            efficiency
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dump_inflight() {
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(1, &backend_registry, None);
+        let (job_solver, work_solver_builder) = build_solvers_with_registry(backend_registry);
+
+        let mut work_generators = vec![];
+        for _ in 0..2 {
+            work_solver_builder
+                .create_work_solver(|work_generator, _| {
+                    work_generators.push(work_generator);
+                    Arc::new(test_utils::TestWorkSolver::new())
+                })
+                .await;
+        }
+        let inflight_dump = core.inflight_dump().await;
+        assert!(inflight_dump.dump_inflight().is_empty());
+
+        let block = &test_utils::TEST_BLOCKS[0];
+        job_solver.job_sender.send(Arc::new(*block));
+        let work = work_generators[1]
+            .generate()
+            .await
+            .expect("BUG: no work generated");
+
+        let snapshots = inflight_dump.dump_inflight();
+        assert_eq!(snapshots.len(), 1);
+        let snapshot = &snapshots[0];
+        assert_eq!(snapshot.ntime, work.ntime);
+        assert_eq!(snapshot.generation, 1);
+        assert_eq!(snapshot.midstates.len(), work.midstates.len());
+        let inflight = snapshot.clone().into_assignment();
+        assert_eq!(inflight.merkle_root_tail(), work.merkle_root_tail());
+
+        // only the chain which received the work has non-empty dump
+        let dir = std::env::temp_dir().join(format!("bosminer-inflight-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        inflight_dump
+            .save(&dir)
+            .expect("BUG: cannot save in-flight work");
+        for (chain_id, expected_len) in [0, 1].iter().enumerate() {
+            let bytes = fs::read(dir.join(InflightDump::file_name(chain_id)))
+                .expect("BUG: cannot read in-flight work");
+            let reloaded = work::replay::decode_snapshots(&bytes, work.origin())
+                .expect("BUG: cannot reload in-flight work");
+            assert_eq!(reloaded.len(), *expected_len);
+        }
+        fs::remove_dir_all(&dir).expect("BUG: cannot remove in-flight work directory");
+    }
+
+    #[tokio::test]
+    async fn test_simulate_accepted_share() {
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(1, &backend_registry, None);
+        // there is no pool which would accept the share
+        assert!(core.simulate_accepted_share(1.0).await.is_err());
+
+        let descriptor = bosminer_config::ClientDescriptor::create(
+            "drain://test",
+            &bosminer_config::ClientUserInfo::new("user", None),
+            false,
+        )
+        .expect("BUG: invalid pool");
+        let client = core
+            .get_client_manager()
+            .create_or_get_default_group()
+            .await
+            .push_client(client::Handle::new(descriptor, None, None))
+            .await;
+        let frontend: node::DynInfo = core.frontend.clone();
+        let mut sampler =
+            stats::csv::CsvSampler::new(frontend, Vec::new(), *stats::TIME_MEAN_INTERVAL_1M, None)
+                .expect("BUG: cannot create CSV sampler");
+        let sink_path = std::env::temp_dir().join(format!(
+            "bosminer-simulated-shares-{}.sock",
+            std::process::id()
+        ));
+        let solution_sink =
+            sink::SolutionSink::bind(&sink_path).expect("BUG: cannot bind solution socket");
+        core.set_solution_sink(solution_sink.clone()).await;
+        let stream = tokio::net::UnixStream::connect(&sink_path)
+            .await
+            .expect("BUG: cannot connect to solution socket");
+        while solution_sink.subscriber_count() == 0 {
+            tokio::time::delay_for(time::Duration::from_millis(10)).await;
+        }
+
+        assert!(core.simulate_accepted_share(0.0).await.is_err());
+        for difficulty in [1000.0, 0.5].iter() {
+            core.simulate_accepted_share(*difficulty)
+                .await
+                .expect("BUG: cannot simulate share");
+        }
+
+        // subscribers of the sink are notified about each share
+        let mut reader = tokio::io::BufReader::new(stream);
+        for difficulty in [1000.0, 0.5].iter() {
+            let mut line = String::new();
+            tokio::io::AsyncBufReadExt::read_line(&mut reader, &mut line)
+                .await
+                .expect("BUG: cannot read solution event");
+            let event: sink::SolutionEvent =
+                serde_json::from_str(&line).expect("BUG: malformed solution event");
+            assert!((event.job_difficulty - difficulty).abs() < 1e-6);
+            assert!(!event.block);
+        }
+        fs::remove_file(&sink_path).expect("BUG: cannot remove solution socket");
+
+        let client_stats = client.stats();
+        let accepted = client_stats.accepted().take_snapshot().await;
+        assert_eq!(accepted.solutions, 2);
+        assert_eq!(accepted.shares.as_f64(), 1000.5);
+        let last_share = client_stats
+            .last_share()
+            .take_snapshot()
+            .await
+            .expect("BUG: missing last share");
+        assert_eq!(last_share.difficulty, 0.5);
+        let mining_stats = core.frontend.mining_stats();
+        let valid_job_diff = mining_stats.valid_job_diff().take_snapshot().await;
+        assert_eq!(valid_job_diff.solutions, 2);
+        assert_eq!(
+            mining_stats
+                .best_share()
+                .take_snapshot()
+                .map(|share| *share),
+            Some(1000)
+        );
+
+        // the simulated shares are exported as any other shares
+        sampler.sample().await.expect("BUG: cannot sample stats");
+        let csv = String::from_utf8(sampler.into_inner()).expect("BUG: invalid UTF-8");
+        let row: Vec<&str> = csv
+            .lines()
+            .last()
+            .expect("BUG: missing row")
+            .split(',')
+            .collect();
+        assert_eq!(row[2], "2");
+        assert_eq!(row[5], "1000");
+        assert_eq!(row[6], "1000");
+    }
+
+    #[tokio::test]
+    async fn test_core_affinity() {
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Arc::new(Core::new(1, &backend_registry, None));
+        let test_affinity = Arc::new(affinity::test::TestAffinity::new(vec![0, 1, 2]));
+        core.set_core_affinity(affinity::Config {
+            work_generation: Some(affinity::Pinning::new(test_affinity.clone(), vec![0])),
+            solution_handling: Some(affinity::Pinning::new(test_affinity.clone(), vec![1, 2])),
+        })
+        .await;
+        let work_pinning = core.work_pinning.lock().await.clone();
+        assert_eq!(work_pinning.expect("BUG: missing pinning").cores(), &[0]);
+
+        // solution router is started on a thread pinned to the configured cores
+        tokio::spawn(core.clone().run());
+        for _ in 0..100 {
+            if !test_affinity.calls().is_empty() {
+                break;
+            }
+            tokio::time::delay_for(time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(test_affinity.calls(), vec![vec![1, 2]]);
+    }
+
+    #[test]
+    fn test_solution_feed() {
+        const CAPACITY: usize = 2;
+        let feed = SolutionFeed::new(CAPACITY);
+        assert!(feed.records().is_empty());
+
+        let blocks = &test_utils::TEST_BLOCKS;
+        assert!(blocks.len() > CAPACITY);
+        let solutions: Vec<work::Solution> = blocks.iter().map(Into::into).collect();
+        for solution in solutions.iter() {
+            feed.push(solution.clone());
+        }
+
+        // only the most recent solutions are retained in the original order
+        let records = feed.records();
+        let expected = &blocks[blocks.len() - CAPACITY..];
+        assert_eq!(records.len(), CAPACITY);
+        for (record, block) in records.iter().zip(expected) {
+            assert_eq!(record.nonce, block.nonce);
+            assert_eq!(record.hash, block.hash);
+            assert!(record.share_difficulty >= record.job_difficulty);
+            assert_eq!(record.outcome, None);
+        }
+
+        // outcome resolved by the client is visible in the feed
+        let last = solutions.last().expect("BUG: no solution");
+        last.resolve(work::SubmitOutcome::Rejected(
+            client::rejection::RejectReason::Duplicate,
+        ));
+        last.resolve(work::SubmitOutcome::Accepted);
+        assert_eq!(
+            feed.records()[CAPACITY - 1].outcome,
+            Some(work::SubmitOutcome::Rejected(
+                client::rejection::RejectReason::Duplicate
+            ))
+        );
+    }
+
+    /// Backend solution referring to a midstate which does not exist in the work
+    #[derive(Debug)]
+    struct BadMidstateSolution(ii_bitcoin::Target);
+
+    impl hal::BackendSolution for BadMidstateSolution {
+        fn nonce(&self) -> u32 {
+            0
+        }
+
+        fn midstate_idx(&self) -> usize {
+            1
+        }
+
+        fn solution_idx(&self) -> usize {
+            0
+        }
+
+        fn target(&self) -> &ii_bitcoin::Target {
+            &self.0
+        }
+    }
+
+    /// Backend solution reporting nTime before time of its job
+    #[derive(Debug)]
+    struct EarlySolution(ii_bitcoin::Target);
+
+    impl hal::BackendSolution for EarlySolution {
+        fn nonce(&self) -> u32 {
+            0
+        }
+
+        fn midstate_idx(&self) -> usize {
+            0
+        }
+
+        fn solution_idx(&self) -> usize {
+            0
+        }
+
+        fn target(&self) -> &ii_bitcoin::Target {
+            &self.0
+        }
+
+        fn ntime(&self) -> Option<u32> {
+            Some(0)
+        }
+    }
+
+    #[test]
+    fn test_check_solution() {
+        let block = &test_utils::TEST_BLOCKS[0];
+        let work_solver = test_utils::create_test_work_solver();
+        let mut work: work::Assignment = block.into();
+        work.path.push(Arc::new(work_solver.clone()));
+        let get_dropped = || {
+            *work_solver
+                .mining_stats()
+                .dropped_solutions()
+                .take_snapshot()
+        };
+
+        let solution =
+            work::Solution::new(work.clone(), test_utils::TestSolution::new(block), None);
+        assert!(SolutionRouter::check(&solution));
+        assert_eq!(get_dropped(), 0);
+
+        let solution =
+            work::Solution::new(work.clone(), BadMidstateSolution(Default::default()), None);
+        assert!(!SolutionRouter::check(&solution));
+        assert_eq!(get_dropped(), 1);
+
+        // job time ahead of the solution is handled without panicking
+        let solution = work::Solution::new(work, EarlySolution(Default::default()), None);
+        assert!(solution.time_offset() < 0);
+        assert!(!SolutionRouter::check(&solution));
+        assert_eq!(get_dropped(), 2);
+    }
+
+    /// Block candidate found on a job with all transactions of the block is also relayed to the
+    /// full node of other solo client while block candidate of a pool job is not
+    #[tokio::test]
+    async fn test_block_candidate_routing() {
+        let node = client::solo::test::FakeNode::start().await;
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(1, &backend_registry, None);
+        core.enable_fastest_block_submission().await;
+
+        let group = core
+            .get_client_manager()
+            .create_group(bosminer_config::GroupDescriptor::new(
+                "solo".to_string(),
+                false,
+                bosminer_config::LoadBalanceStrategy::Quota(1),
+            ))
+            .await
+            .expect("BUG: cannot create group");
+        let solo = group
+            .push_client(client::Handle::new(node.descriptor(), None, None))
+            .await;
+        while !solo.is_running() {
+            tokio::time::delay_for(time::Duration::from_millis(10)).await;
+        }
+
+        let solution_router = core
+            .solution_router
+            .lock()
+            .await
+            .take()
+            .expect("BUG: missing solution router");
+        tokio::spawn(solution_router.run());
+
+        // the job of the block comes from a pool which is not registered in the core and the
+        // block cannot be serialized without its transactions
+        let block = &test_utils::TEST_BLOCKS[0];
+        let solution: work::Solution = block.into();
+        assert!(solution.is_block());
+        assert!(solution.serialize_block().is_none());
+        core.solution_sender
+            .unbounded_send(solution)
+            .expect("BUG: cannot send solution");
+
+        // the job of the block comes from other solo client which is not registered in the core
+        let other_node = client::solo::test::FakeNode::start().await;
+        let (other_solo, _solution_sender) = client::solo::test::start_client(&other_node);
+        let solution = client::solo::test::block_solution(&other_solo).await;
+        let block = hex::encode(
+            solution
+                .serialize_block()
+                .expect("BUG: solo job without block"),
+        );
+        core.solution_sender
+            .unbounded_send(solution)
+            .expect("BUG: cannot send solution");
+
+        let mut submitted = vec![];
+        for _ in 0..100 {
+            submitted = node.calls("submitblock");
+            if !submitted.is_empty() {
+                break;
+            }
+            tokio::time::delay_for(time::Duration::from_millis(10)).await;
+        }
+        // the whole block is submitted and the pool block candidate has not been relayed
+        assert_eq!(submitted, vec![serde_json::json!([block])]);
+        assert!(other_node.calls("submitblock").is_empty());
+        assert!(solo.is_running());
+        node::Client::stop(other_solo.as_ref());
+    }
+
+    /// Test block issued by a particular client
+    #[derive(Debug)]
+    struct ClientBlock {
+        block: test_utils::TestBlock,
+        origin: Weak<dyn node::Client>,
+    }
+
+    impl job::Bitcoin for ClientBlock {
+        fn origin(&self) -> Weak<dyn node::Client> {
+            self.origin.clone()
+        }
+
+        fn version(&self) -> u32 {
+            self.block.version()
+        }
+
+        fn version_mask(&self) -> u32 {
+            self.block.version_mask()
+        }
+
+        fn previous_hash(&self) -> &ii_bitcoin::DHash {
+            self.block.previous_hash()
+        }
+
+        fn merkle_root(&self) -> &ii_bitcoin::DHash {
+            self.block.merkle_root()
+        }
+
+        fn time(&self) -> u32 {
+            self.block.time()
+        }
+
+        fn bits(&self) -> u32 {
+            self.block.bits()
+        }
+
+        fn target(&self) -> ii_bitcoin::Target {
+            self.block.target()
+        }
+
+        fn is_valid(&self) -> bool {
+            true
+        }
+    }
+
+    /// Solutions of donation pool are accounted apart from the statistics of the user's mining
+    #[tokio::test]
+    async fn test_donation_routing() {
+        let backend_registry = Arc::new(backend::Registry::new());
+        let core = Core::new(1, &backend_registry, None);
+        let client_manager = core.get_client_manager();
+
+        let mut clients = vec![];
+        for (name, donation) in [("user", false), ("donation", true)].iter() {
+            let mut group_descriptor = bosminer_config::GroupDescriptor::new(
+                name.to_string(),
+                false,
+                bosminer_config::LoadBalanceStrategy::Quota(1),
+            );
+            group_descriptor.donation = *donation;
+            let group = client_manager
+                .create_group(group_descriptor)
+                .await
+                .expect("BUG: cannot create group");
+            let descriptor = bosminer_config::ClientDescriptor::create(
+                &format!("drain://{}", name),
+                &bosminer_config::ClientUserInfo::new("user", None),
+                true,
+            )
+            .expect("BUG: invalid pool");
+            clients.push(
+                group
+                    .push_client(client::Handle::new(descriptor, None, None))
+                    .await,
+            );
+        }
+        for client in clients.iter() {
+            while !client.is_running() || client.get_last_job().await.is_none() {
+                tokio::time::delay_for(time::Duration::from_millis(10)).await;
+            }
+        }
+
+        let solution_router = core
+            .solution_router
+            .lock()
+            .await
+            .take()
+            .expect("BUG: missing solution router");
+        tokio::spawn(solution_router.run());
+
+        let block = &test_utils::TEST_BLOCKS[0];
+        let work_solver = test_utils::create_test_work_solver();
+        for client in clients.iter() {
+            let origin = client
+                .get_last_job()
+                .await
+                .expect("BUG: missing job")
+                .origin();
+            let midstates = work::Assignment::from(block).midstates;
+            let job = Arc::new(ClientBlock {
+                block: *block,
+                origin,
+            });
+            let mut work = work::Assignment::new(job, midstates, block.time());
+            work.path.push(core.frontend.clone());
+            work.path.push(Arc::new(work_solver.clone()));
+            core.solution_sender
+                .unbounded_send(work::Solution::new(
+                    work,
+                    test_utils::TestSolution::new(block),
+                    None,
+                ))
+                .expect("BUG: cannot send solution");
+        }
+
+        for client in clients.iter() {
+            for _ in 0..100 {
+                if client.stats().accepted().take_snapshot().await.solutions > 0 {
+                    break;
+                }
+                tokio::time::delay_for(time::Duration::from_millis(10)).await;
+            }
+            assert_eq!(client.stats().accepted().take_snapshot().await.solutions, 1);
+        }
+        // the backend accounts all solutions it has found but the frontend only the user's ones
+        let valid_job_diff = work_solver
+            .mining_stats()
+            .valid_job_diff()
+            .take_snapshot()
+            .await;
+        assert_eq!(valid_job_diff.solutions, 2);
+        let valid_job_diff = core
+            .frontend
+            .mining_stats()
+            .valid_job_diff()
+            .take_snapshot()
+            .await;
+        assert_eq!(valid_job_diff.solutions, 1);
+        let donation = client_manager.donation();
+        assert_eq!(donation.valid_job_diff.take_snapshot().await.solutions, 1);
+        assert_eq!(
+            donation.valid_backend_diff.take_snapshot().await.solutions,
+            1
+        );
+    }
 }
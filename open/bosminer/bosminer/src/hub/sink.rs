@@ -0,0 +1,306 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Live stream of valid solutions for external monitoring tools. Every subscriber connected to
+//! the Unix domain socket receives one JSON object per line for each solution passed to clients.
+
+use ii_logging::macros::*;
+
+use crate::work;
+
+use futures::channel::mpsc;
+use futures::stream::StreamExt;
+use ii_async_compat::{futures, tokio};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{UnixListener, UnixStream};
+
+use serde::{Deserialize, Serialize};
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex as StdMutex, MutexGuard as StdMutexGuard};
+
+/// Serializable snapshot of a solution emitted to the subscribers
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SolutionEvent {
+    /// Id of the work solver (e.g. hash chain index) which has found the solution
+    pub solver_id: Option<usize>,
+    pub nonce: u32,
+    pub time: u32,
+    pub version: u32,
+    /// Hash of the solution in the byte order used by block explorers
+    pub hash: String,
+    pub share_difficulty: f64,
+    pub job_difficulty: f64,
+    /// The solution meets the network target
+    pub block: bool,
+}
+
+impl From<&work::Solution> for SolutionEvent {
+    fn from(solution: &work::Solution) -> Self {
+        let hash = *solution.hash();
+        let network_params = solution.network_params();
+        Self {
+            solver_id: solution.solver_id(),
+            nonce: solution.nonce(),
+            time: solution.time(),
+            version: solution.version(),
+            hash: hash.to_string(),
+            share_difficulty: network_params.difficulty(&hash.into()),
+            job_difficulty: network_params.difficulty(solution.job_target()),
+            block: solution.is_block(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "share-simulation"))]
+impl SolutionEvent {
+    /// Event of a share which has not been found by any work solver (see
+    /// `hub::Core::simulate_accepted_share`). The target is used in place of its hash.
+    pub fn simulated(
+        target: &ii_bitcoin::Target,
+        network_params: &ii_bitcoin::NetworkParams,
+    ) -> Self {
+        let difficulty = network_params.difficulty(target);
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs() as u32);
+        Self {
+            solver_id: None,
+            nonce: 0,
+            time,
+            version: 0,
+            hash: target.to_string(),
+            share_difficulty: difficulty,
+            job_difficulty: difficulty,
+            block: false,
+        }
+    }
+}
+
+/// Maximal number of events waiting to be written to one subscriber
+pub const SUBSCRIBER_QUEUE_SIZE: usize = 64;
+
+/// Distributes solution events to all connected subscribers. Each subscriber is served by its
+/// own task with a queue of `SUBSCRIBER_QUEUE_SIZE` events. A subscriber which does not keep up
+/// with the solutions is disconnected as soon as its queue is full so it neither blocks routing
+/// of solutions nor consumes memory without limit.
+#[derive(Debug)]
+pub struct SolutionSink {
+    subscribers: StdMutex<Vec<mpsc::Sender<Arc<String>>>>,
+}
+
+impl SolutionSink {
+    fn new() -> Self {
+        Self {
+            subscribers: StdMutex::new(Vec::new()),
+        }
+    }
+
+    /// Create the socket at `path` and start accepting subscribers. A socket left behind by
+    /// a previous run is replaced but any other file at `path` is kept and reported as an error.
+    pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<Arc<Self>> {
+        let path = path.as_ref();
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("'{}' exists and it is not a socket", path.display()),
+                ))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let listener = UnixListener::bind(path)?;
+        let sink = Arc::new(Self::new());
+        tokio::spawn(sink.clone().accept(listener));
+        Ok(sink)
+    }
+
+    fn lock(&self) -> StdMutexGuard<Vec<mpsc::Sender<Arc<String>>>> {
+        self.subscribers
+            .lock()
+            .expect("BUG: cannot lock solution sink")
+    }
+
+    async fn accept(self: Arc<Self>, mut listener: UnixListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => self.subscribe(stream),
+                Err(e) => warn!("Solution sink: cannot accept subscriber: {}", e),
+            }
+        }
+    }
+
+    fn subscribe(&self, mut stream: UnixStream) {
+        let (sender, mut receiver) = mpsc::channel::<Arc<String>>(SUBSCRIBER_QUEUE_SIZE);
+        self.lock().push(sender);
+        tokio::spawn(async move {
+            while let Some(line) = receiver.next().await {
+                if let Err(e) = stream.write_all(line.as_bytes()).await {
+                    info!("Solution sink: subscriber has disconnected: {}", e);
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Number of currently connected subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Send the solution event to all subscribers and forget those which have disconnected or
+    /// which have a full queue
+    pub fn emit(&self, solution: &work::Solution) {
+        self.send(|| SolutionEvent::from(solution));
+    }
+
+    /// Send the event which does not belong to any solution to all subscribers
+    #[cfg(any(test, feature = "share-simulation"))]
+    pub fn emit_event(&self, event: SolutionEvent) {
+        self.send(|| event);
+    }
+
+    /// The event is built only when there is any subscriber
+    fn send<F: FnOnce() -> SolutionEvent>(&self, build_event: F) {
+        let mut subscribers = self.lock();
+        if subscribers.is_empty() {
+            return;
+        }
+        let mut line =
+            serde_json::to_string(&build_event()).expect("BUG: cannot serialize solution event");
+        line.push('\n');
+        let line = Arc::new(line);
+        subscribers.retain_mut(|subscriber| match subscriber.try_send(line.clone()) {
+            Ok(()) => true,
+            Err(e) => {
+                if e.is_full() {
+                    warn!("Solution sink: disconnecting subscriber which does not keep up");
+                }
+                false
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    use std::time;
+
+    #[tokio::test]
+    async fn test_solution_sink() {
+        let path =
+            std::env::temp_dir().join(format!("bosminer-solutions-{}.sock", std::process::id()));
+        let sink = SolutionSink::bind(&path).expect("BUG: cannot bind solution socket");
+
+        // events are dropped when nobody listens
+        let blocks = &test_utils::TEST_BLOCKS;
+        let solutions: Vec<work::Solution> = blocks.iter().map(Into::into).collect();
+        sink.emit(&solutions[0]);
+
+        let stream = UnixStream::connect(&path)
+            .await
+            .expect("BUG: cannot connect to solution socket");
+        while sink.subscriber_count() == 0 {
+            tokio::time::delay_for(time::Duration::from_millis(10)).await;
+        }
+        for solution in solutions.iter() {
+            sink.emit(solution);
+        }
+
+        let mut reader = BufReader::new(stream);
+        for (solution, block) in solutions.iter().zip(blocks.iter()) {
+            let mut line = String::new();
+            tokio::time::timeout(time::Duration::from_secs(1), reader.read_line(&mut line))
+                .await
+                .expect("BUG: missing solution event")
+                .expect("BUG: cannot read solution event");
+            let event: SolutionEvent =
+                serde_json::from_str(&line).expect("BUG: malformed solution event");
+            assert_eq!(event, SolutionEvent::from(solution));
+            assert_eq!(event.nonce, block.nonce);
+            assert_eq!(event.hash, block.hash.to_string());
+            assert!(event.share_difficulty >= event.job_difficulty);
+        }
+
+        // disconnected subscriber is forgotten on the next event
+        drop(reader);
+        while sink.subscriber_count() > 0 {
+            sink.emit(&solutions[0]);
+            tokio::time::delay_for(time::Duration::from_millis(10)).await;
+        }
+        fs::remove_file(&path).expect("BUG: cannot remove solution socket");
+    }
+
+    #[tokio::test]
+    async fn test_solution_sink_stalled_subscriber() {
+        let path = std::env::temp_dir().join(format!(
+            "bosminer-solutions-stalled-{}.sock",
+            std::process::id()
+        ));
+        let sink = SolutionSink::bind(&path).expect("BUG: cannot bind solution socket");
+        let solution: work::Solution = (&test_utils::TEST_BLOCKS[0]).into();
+
+        let _stream = UnixStream::connect(&path)
+            .await
+            .expect("BUG: cannot connect to solution socket");
+        while sink.subscriber_count() == 0 {
+            tokio::time::delay_for(time::Duration::from_millis(10)).await;
+        }
+
+        // the subscriber task has no chance to run so the queue gets full
+        for _ in 0..SUBSCRIBER_QUEUE_SIZE {
+            sink.emit(&solution);
+        }
+        assert_eq!(sink.subscriber_count(), 1);
+        for _ in 0..SUBSCRIBER_QUEUE_SIZE {
+            sink.emit(&solution);
+        }
+        assert_eq!(sink.subscriber_count(), 0);
+        fs::remove_file(&path).expect("BUG: cannot remove solution socket");
+    }
+
+    #[test]
+    fn test_solution_sink_keeps_regular_file() {
+        let path = std::env::temp_dir().join(format!(
+            "bosminer-solutions-file-{}.sock",
+            std::process::id()
+        ));
+        fs::write(&path, "data").expect("BUG: cannot create file");
+        let error = SolutionSink::bind(&path).expect_err("BUG: regular file has been replaced");
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            fs::read_to_string(&path).expect("BUG: cannot read file"),
+            "data"
+        );
+        fs::remove_file(&path).expect("BUG: cannot remove file");
+    }
+}
@@ -20,6 +20,10 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+pub mod submit_ahead;
+pub mod tip_watch;
+pub mod vardiff;
+
 use ii_logging::macros::*;
 
 use ii_bitcoin::{HashTrait as _, MeetsTarget};
@@ -31,13 +35,18 @@ use crate::work;
 
 use futures::channel::mpsc;
 use futures::stream::StreamExt;
-use ii_async_compat::futures;
+use futures::FutureExt as _;
+use ii_async_compat::{futures, select, tokio};
+use tokio::time::delay_until;
 
+use std::collections::VecDeque;
 use std::convert::TryInto;
 use std::fmt::Debug;
 use std::mem;
-use std::sync::{Arc, Weak};
+use std::sync::{Arc, Mutex as StdMutex, Weak};
+use std::time::{Duration, Instant};
 
+use async_trait::async_trait;
 use downcast_rs::{impl_downcast, Downcast};
 
 /// Represents interface for Bitcoin job with access to block header from which the new work will be
@@ -54,6 +63,15 @@ pub trait Bitcoin: Debug + Downcast + Send + Sync {
     fn previous_hash(&self) -> &ii_bitcoin::DHash;
     /// Double SHA256 hash based on all of the transactions in the block
     fn merkle_root(&self) -> &ii_bitcoin::DHash;
+    /// Hash of the previous block as a big endian hex string, i.e. the way it is shown by block
+    /// explorers (the hash itself is stored in little endian byte order)
+    fn previous_hash_hex(&self) -> String {
+        self.previous_hash().to_string()
+    }
+    /// Merkle root as a big endian hex string (see `previous_hash_hex`)
+    fn merkle_root_hex(&self) -> String {
+        self.merkle_root().to_string()
+    }
     /// Current block timestamp as seconds since 1970-01-01T00:00 UTC
     fn time(&self) -> u32;
     /// Maximal timestamp for current block as seconds since 1970-01-01T00:00 UTC
@@ -65,8 +83,51 @@ pub trait Bitcoin: Debug + Downcast + Send + Sync {
     fn bits(&self) -> u32;
     /// Current pool/protocol target used for solution checking
     fn target(&self) -> ii_bitcoin::Target;
+    /// Target for solutions found by work solver `solver_id` (e.g. hash chain index) which may
+    /// differ from `target` when the pool assigns independent difficulty to each worker
+    fn solver_target(&self, _solver_id: Option<usize>) -> ii_bitcoin::Target {
+        self.target()
+    }
+    /// Target requested by the pool which may be easier than `target` when the client filters
+    /// solutions by its own difficulty floor (see `client::DifficultyFloor`)
+    fn pool_target(&self) -> ii_bitcoin::Target {
+        self.target()
+    }
     /// Checks if job is still valid for mining
     fn is_valid(&self) -> bool;
+    /// Height of the block being mined when it is known (BIP34 height in coinbase)
+    fn block_height(&self) -> Option<u64> {
+        None
+    }
+    /// Bitcoin network of the job which determines allowed range of network target
+    fn network(&self) -> ii_bitcoin::Network {
+        Default::default()
+    }
+    /// Parameters used for validation of network target and conversion of targets to
+    /// difficulty. Jobs of altcoin forks sharing SHA256d proof of work can provide their own.
+    fn network_params(&self) -> ii_bitcoin::NetworkParams {
+        self.network().params()
+    }
+    /// Size (in bytes) of the part of coinbase extranonce which can be rolled by the miner
+    /// Zero means that the coinbase is fixed and only the block header can be rolled.
+    fn extranonce_size(&self) -> usize {
+        0
+    }
+    /// Merkle root of the block with given value of the rolled part of extranonce
+    /// It has to be implemented by all jobs with non-zero `extranonce_size`.
+    fn extranonce_merkle_root(&self, _extranonce: u64) -> ii_bitcoin::DHash {
+        *self.merkle_root()
+    }
+    /// Serialize the whole block with solved `header` and given value of the rolled part of
+    /// extranonce. It is available only when the job knows all transactions of the block (e.g.
+    /// a job built from block template of a full node).
+    fn serialize_block(
+        &self,
+        _header: ii_bitcoin::BlockHeader,
+        _extranonce: u64,
+    ) -> Option<Vec<u8>> {
+        None
+    }
 
     /// Extract least-significant word of merkle root that goes to chunk2 of SHA256
     /// The word is interpreted as a little endian number.
@@ -79,9 +140,35 @@ pub trait Bitcoin: Debug + Downcast + Send + Sync {
                 .expect("slice with incorrect length"),
         )
     }
+
+    /// Least-significant word of merkle root interpreted as a little endian number (the same as
+    /// `merkle_root_tail`). This is the format expected by S9 work FIFO and Icarus work payload.
+    #[inline]
+    fn merkle_root_tail_le(&self) -> u32 {
+        self.merkle_root_tail()
+    }
+
+    /// Least-significant word of merkle root interpreted as a big endian number (the word as it
+    /// is loaded into SHA256 message schedule)
+    #[inline]
+    fn merkle_root_tail_be(&self) -> u32 {
+        self.merkle_root_tail().swap_bytes()
+    }
 }
 impl_downcast!(Bitcoin);
 
+/// Pluggable source of jobs (mining protocol, block template, replay of recorded jobs...) which
+/// can be uniformly consumed by `Sender`
+#[async_trait]
+pub trait Source: Send {
+    /// Called once before the first job is requested
+    async fn start(&mut self) {}
+    /// Wait for the next job. Returning `None` means that the source has been depleted.
+    async fn next_job(&mut self) -> Option<Arc<dyn Bitcoin>>;
+    /// Called once when the source is not used anymore
+    async fn stop(&mut self) {}
+}
+
 /// Compound object for job submission and solution reception intended to be passed to
 /// protocol handler
 pub struct Solver {
@@ -94,9 +181,13 @@ impl Solver {
         engine_sender: Arc<work::EngineSender>,
         solution_receiver: mpsc::UnboundedReceiver<work::Solution>,
     ) -> Self {
+        let job_sender = Sender::new(engine_sender);
+        let mut solution_receiver = SolutionReceiver::new(solution_receiver);
+        // let the receiver know when the jobs are superseded
+        solution_receiver.job_timeline = job_sender.job_timeline.clone();
         Self {
-            job_sender: Sender::new(engine_sender),
-            solution_receiver: SolutionReceiver::new(solution_receiver),
+            job_sender,
+            solution_receiver,
         }
     }
 }
@@ -105,11 +196,32 @@ impl Solver {
 /// Typically the mining protocol handler will inject new jobs through it
 pub struct Sender {
     engine_sender: Arc<work::EngineSender>,
+    /// Previous block hash of the last broadcast job used for detection of block changes
+    last_prev_hash: StdMutex<Option<ii_bitcoin::DHash>>,
+    /// Chain tip for which the work has been already invalidated (see `check_tip`)
+    stale_tip: StdMutex<Option<ii_bitcoin::DHash>>,
+    /// Arrival of broadcast jobs shared with solution receiver
+    job_timeline: Arc<StdMutex<submit_ahead::JobTimeline>>,
 }
 
 impl Sender {
     pub fn new(engine_sender: Arc<work::EngineSender>) -> Self {
-        Self { engine_sender }
+        Self {
+            engine_sender,
+            last_prev_hash: StdMutex::new(None),
+            stale_tip: StdMutex::new(None),
+            job_timeline: Default::default(),
+        }
+    }
+
+    /// Remember previous block hash of the `job` and return whether it has changed
+    fn update_prev_hash(&self, job: &Arc<dyn job::Bitcoin>) -> bool {
+        let mut last_prev_hash = self
+            .last_prev_hash
+            .lock()
+            .expect("BUG: cannot lock previous block hash");
+        let prev_hash = *job.previous_hash();
+        last_prev_hash.replace(prev_hash) != Some(prev_hash)
     }
 
     /// Check if the job has valid attributes
@@ -118,7 +230,7 @@ impl Sender {
         origin: &Option<Arc<dyn node::Client>>,
     ) -> bool {
         let mut valid = true;
-        if let Err(msg) = ii_bitcoin::Target::from_compact(job.bits()) {
+        if let Err(msg) = job.network_params().target_from_compact(job.bits()) {
             error!(
                 "Invalid job's nBits ({}) received from '{}'",
                 msg,
@@ -132,7 +244,15 @@ impl Sender {
         valid
     }
 
+    /// Broadcast regular job update (change of the block is detected automatically)
+    #[inline]
     pub fn send(&self, job: Arc<dyn job::Bitcoin>) {
+        self.send_with_cause(job, work::GenerationCause::JobUpdate);
+    }
+
+    /// Broadcast job which makes the previous work obsolete for given `cause`. The cause is
+    /// overridden when the job belongs to a different block than the previous one.
+    pub fn send_with_cause(&self, job: Arc<dyn job::Bitcoin>, cause: work::GenerationCause) {
         let origin = job.origin().upgrade();
         if !Self::job_sanity_check(&job, &origin) {
             origin.map(|origin| origin.client_stats().invalid_jobs().inc());
@@ -141,8 +261,25 @@ impl Sender {
 
         // send only jobs with correct data
         if let Some(origin) = origin {
+            let cause = if self.update_prev_hash(&job) {
+                work::GenerationCause::PrevHashChange
+            } else {
+                cause
+            };
+            debug!("Work generation of '{}' bumped: {:?}", origin, cause);
+            origin.client_stats().generation_bumps().account(cause);
             origin.client_stats().valid_jobs().inc();
+            self.stale_tip
+                .lock()
+                .expect("BUG: cannot lock stale tip")
+                .take();
             info!("--- broadcasting new job ---");
+            let now = Instant::now();
+            origin.client_stats().last_job().touch(now);
+            self.job_timeline
+                .lock()
+                .expect("BUG: cannot lock job timeline")
+                .job_sent(&job, now);
             self.engine_sender.broadcast_job(job);
         } else {
             // Origin has been removed and no one will receive any solution
@@ -154,6 +291,34 @@ impl Sender {
     pub fn invalidate(&self) {
         self.engine_sender.invalidate();
     }
+
+    /// Invalidate current work when the chain `tip` is not the previous block of the last job,
+    /// i.e. the job has become stale. The work is invalidated once for each tip until a job
+    /// building on it arrives. Returns whether the work has been invalidated.
+    pub fn check_tip(&self, tip: &ii_bitcoin::DHash) -> bool {
+        let last_prev_hash = *self
+            .last_prev_hash
+            .lock()
+            .expect("BUG: cannot lock previous block hash");
+        let mut stale_tip = self.stale_tip.lock().expect("BUG: cannot lock stale tip");
+        match last_prev_hash {
+            Some(prev_hash) if prev_hash != *tip && *stale_tip != Some(*tip) => {
+                stale_tip.replace(*tip);
+                self.invalidate();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Send all jobs from the `source` until it is depleted
+    pub async fn consume<T: Source>(&self, mut source: T) {
+        source.start().await;
+        while let Some(job) = source.next_job().await {
+            self.send(job);
+        }
+        source.stop().await;
+    }
 }
 
 /// Receives `work::Solution` via a channel and filters only solutions that meet the client/pool
@@ -161,17 +326,94 @@ impl Sender {
 #[derive(Debug)]
 pub struct SolutionReceiver {
     solution_channel: mpsc::UnboundedReceiver<work::Solution>,
+    /// Optional local difficulty filter applied on top of the job target
+    vardiff: Option<vardiff::Controller>,
+    /// Solutions of a job which is expected to be superseded within this horizon are submitted
+    /// ahead of the others (`None` disables reordering)
+    submit_ahead: Option<Duration>,
+    /// Accepted solutions waiting for submission
+    pending: VecDeque<work::Solution>,
+    job_timeline: Arc<StdMutex<submit_ahead::JobTimeline>>,
 }
 
 impl SolutionReceiver {
     pub fn new(solution_channel: mpsc::UnboundedReceiver<work::Solution>) -> Self {
-        Self { solution_channel }
+        Self {
+            solution_channel,
+            vardiff: None,
+            submit_ahead: None,
+            pending: VecDeque::new(),
+            job_timeline: Default::default(),
+        }
+    }
+
+    /// Submit solutions of the current job ahead of other buffered solutions when the job is
+    /// expected to be superseded within `horizon`
+    pub fn enable_submit_ahead(&mut self, horizon: Duration) {
+        self.submit_ahead = Some(horizon);
+    }
+
+    /// Enable local vardiff which submits only shares meeting its difficulty (blocks are always
+    /// submitted). It is intended for setups without upstream vardiff (solo mining, proxy).
+    pub fn enable_vardiff(&mut self, config: vardiff::Config, initial_difficulty: usize) {
+        self.vardiff = Some(vardiff::Controller::new(
+            config,
+            initial_difficulty,
+            Instant::now(),
+        ));
+    }
+
+    /// Return current difficulty of local vardiff (if enabled)
+    pub fn vardiff_difficulty(&self) -> Option<usize> {
+        self.vardiff.as_ref().map(|vardiff| vardiff.difficulty())
+    }
+
+    /// Wait for the next solution from the channel. Local vardiff (if enabled) is retargeted in the
+    /// meantime to lower its difficulty even when no shares are coming at all.
+    async fn next_solution(&mut self) -> Option<work::Solution> {
+        loop {
+            let deadline = match self.vardiff.as_ref() {
+                Some(vardiff) => vardiff.retarget_deadline(),
+                None => return self.solution_channel.next().await,
+            };
+            select! {
+                solution = self.solution_channel.next().fuse() => return solution,
+                _ = delay_until(deadline.into()).fuse() => {
+                    // use the clock of the runtime which has fired the delay
+                    let now = tokio::time::Instant::now().into_std();
+                    if let Some(vardiff) = self.vardiff.as_mut() {
+                        if let Some(difficulty) = vardiff.retarget(now) {
+                            info!("Vardiff: changing local difficulty to {}", difficulty);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Filter solution by local vardiff and feed the controller with accepted shares
+    /// Returns `false` when the solution should not be submitted.
+    fn vardiff_filter(&mut self, solution: &work::Solution, is_block: bool, time: Instant) -> bool {
+        let vardiff = match self.vardiff.as_mut() {
+            Some(vardiff) => vardiff,
+            None => return true,
+        };
+        let share_difficulty = vardiff.difficulty();
+        let target = vardiff.target(&solution.network_params());
+        if !is_block && !solution.hash().meets(&target) {
+            vardiff.retarget(time);
+            return false;
+        }
+        if let Some(difficulty) = vardiff.account_share(share_difficulty, time) {
+            info!("Vardiff: changing local difficulty to {}", difficulty);
+        }
+        true
     }
 
     fn trace_share(solution: &work::Solution, target: &ii_bitcoin::Target) {
         info!(
             "----- Found share within current job's difficulty (diff={}) target range -----",
-            target.get_difficulty()
+            solution.network_params().get_difficulty(target)
         );
         info!(
             "nonce={:08x} bytes={}",
@@ -187,45 +429,625 @@ impl SolutionReceiver {
     }
 
     pub async fn receive(&mut self) -> Option<work::Solution> {
-        while let Some(solution) = self.solution_channel.next().await {
-            let path = solution.path();
-            let time = solution.timestamp();
-            let hash = solution.hash();
-            let job_target = solution.job_target();
-
-            // compare block hash for given solution with all targets
-            // TODO: create tests for solution validation with all difficulty variants
-            assert!(&solution.network_target() <= job_target);
-            if hash.meets(&solution.network_target()) {
-                stats::account_valid_solution(&path, &solution, time, DiffTargetType::Network)
-                    .await;
-            } else if hash.meets(&job_target) {
-                stats::account_valid_solution(&path, &solution, time, DiffTargetType::Job).await;
-            } else if hash.meets(solution.backend_target()) {
-                stats::account_valid_solution(&path, &solution, time, DiffTargetType::Backend)
-                    .await;
-                // skip submitting the solution as we've only met backend difficulty
-                continue;
-            } else {
-                stats::account_error_backend_diff(&path, &solution.backend_target(), time).await;
-                // skip submitting the solution as this is a backend error
-                continue;
+        if self.submit_ahead.is_some() {
+            // buffer all solutions which are immediately available to be able to reorder them
+            while let Ok(Some(solution)) = self.solution_channel.try_next() {
+                if let Some(solution) = self.accept(solution).await {
+                    self.pending.push_back(solution);
+                }
             }
-
-            if solution.has_valid_job() {
-                // TODO: Account solution to Discard meter
-                Self::trace_share(&solution, &job_target);
+            while let Some(solution) = self.take_pending(Instant::now()) {
+                // the job could have been invalidated while the solution was buffered
+                if solution.is_relayed() || solution.has_valid_job() {
+                    return Some(solution);
+                }
+                debug!(
+                    "Dropping buffered solution of invalidated job (generation={:?})",
+                    solution.generation()
+                );
+            }
+        }
+        while let Some(solution) = self.next_solution().await {
+            if let Some(solution) = self.accept(solution).await {
                 return Some(solution);
             }
         }
         None
     }
 
+    /// Take the next buffered solution. Solutions of the current job are expedited when the job
+    /// is about to be superseded otherwise the solutions are taken in order of arrival.
+    fn take_pending(&mut self, now: Instant) -> Option<work::Solution> {
+        let horizon = self.submit_ahead?;
+        let job_timeline = self
+            .job_timeline
+            .lock()
+            .expect("BUG: cannot lock job timeline");
+        let idx = if job_timeline.is_near_superseded(now, horizon) {
+            self.pending
+                .iter()
+                .position(|solution| job_timeline.is_current(solution.dyn_job()))
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        self.pending.remove(idx)
+    }
+
+    /// Account solution in statistics and return it when it should be submitted
+    async fn accept(&mut self, solution: work::Solution) -> Option<work::Solution> {
+        if solution.is_relayed() {
+            // block candidate of other client has been already accounted by its origin
+            return Some(solution);
+        }
+        let path = solution.accounting_path();
+        let time = solution.timestamp();
+        let hash = solution.hash();
+        let network_target = solution.network_target();
+        // the network target can be easier than the job target on test networks (regtest or
+        // testnet block with minimal difficulty) so every block is also a share of the job
+        let job_target = (*solution.job_target()).max(network_target);
+
+        // compare block hash for given solution with all targets
+        let is_block = hash.meets(&network_target);
+        if is_block {
+            stats::account_valid_solution(&path, &solution, time, DiffTargetType::Network).await;
+        } else if hash.meets(&job_target) {
+            stats::account_valid_solution(&path, &solution, time, DiffTargetType::Job).await;
+        } else if hash.meets(solution.backend_target()) {
+            stats::account_valid_solution(&path, &solution, time, DiffTargetType::Backend).await;
+            Self::account_floor_dropped(&solution);
+            // skip submitting the solution as we've only met backend difficulty
+            return None;
+        } else {
+            stats::account_error_backend_diff(&path, &solution.backend_target(), time).await;
+            // skip submitting the solution as this is a backend error
+            return None;
+        }
+
+        if solution.is_stale() {
+            // the share is still submitted when the pool has not invalidated its job yet
+            for node in path.iter() {
+                node.mining_stats().stale_solutions().inc();
+            }
+        }
+        if !self.vardiff_filter(&solution, is_block, time) {
+            // the share doesn't meet local difficulty
+            return None;
+        }
+        if solution.has_valid_job() {
+            // TODO: Account solution to Discard meter
+            Self::trace_share(&solution, &job_target);
+            return Some(solution);
+        }
+        if solution.is_stale() {
+            debug!(
+                "Dropping stale solution of superseded work (generation={:?})",
+                solution.generation()
+            );
+        }
+        None
+    }
+
+    /// Count share which meets the pool target but is not submitted because the job target has
+    /// been raised by the difficulty floor of the client
+    fn account_floor_dropped(solution: &work::Solution) {
+        let job = solution.dyn_job();
+        let pool_target = job.pool_target();
+        if pool_target != job.target() && solution.hash().meets(&pool_target) {
+            if let Some(origin) = solution.origin().upgrade() {
+                origin.client_stats().floor_dropped().inc();
+            }
+        }
+    }
+
     /// Empty all buffered solutions without blocking. This is to prevent the client from submitting
     /// already stale solutions
     /// TODO: We should review this regularly as there may be extensions in the mining protocol that
     /// may allow resume a mining session
     pub fn flush(&mut self) {
+        self.pending.clear();
         while let Ok(Some(_)) = self.solution_channel.try_next() {}
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    use ii_async_compat::prelude::*;
+
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Test block reporting to its own client to not share statistics with other tests
+    #[derive(Debug)]
+    struct TestJob {
+        block: test_utils::TestBlock,
+        client: Arc<test_utils::TestClient>,
+        valid: AtomicBool,
+    }
+
+    impl Bitcoin for TestJob {
+        fn origin(&self) -> Weak<dyn node::Client> {
+            Arc::downgrade(&(self.client.clone() as Arc<dyn node::Client>))
+        }
+
+        fn version(&self) -> u32 {
+            self.block.version()
+        }
+
+        fn version_mask(&self) -> u32 {
+            self.block.version_mask()
+        }
+
+        fn previous_hash(&self) -> &ii_bitcoin::DHash {
+            self.block.previous_hash()
+        }
+
+        fn merkle_root(&self) -> &ii_bitcoin::DHash {
+            self.block.merkle_root()
+        }
+
+        fn time(&self) -> u32 {
+            self.block.time()
+        }
+
+        fn bits(&self) -> u32 {
+            self.block.bits()
+        }
+
+        fn target(&self) -> ii_bitcoin::Target {
+            self.block.target()
+        }
+
+        fn is_valid(&self) -> bool {
+            self.valid.load(Ordering::Relaxed)
+        }
+    }
+
+    #[test]
+    fn test_generation_causes() {
+        let client = Arc::new(test_utils::TestClient::new());
+        let sender = Sender::new(Arc::new(work::EngineSender::new(None)));
+        let job = |idx: usize| -> Arc<dyn Bitcoin> {
+            Arc::new(TestJob {
+                block: test_utils::TEST_BLOCKS[idx],
+                client: client.clone(),
+                valid: AtomicBool::new(true),
+            })
+        };
+        assert_ne!(
+            test_utils::TEST_BLOCKS[0].previous_hash,
+            test_utils::TEST_BLOCKS[1].previous_hash
+        );
+
+        // the first job always starts a new block
+        sender.send(job(0));
+        sender.send(job(0));
+        sender.send_with_cause(job(0), work::GenerationCause::CleanJobs);
+        sender.send_with_cause(job(0), work::GenerationCause::ExtranonceChange);
+        // the change of block is more specific than clean jobs
+        sender.send_with_cause(job(1), work::GenerationCause::CleanJobs);
+
+        let bumps = client.client_stats().generation_bumps();
+        assert_eq!(bumps.get(work::GenerationCause::JobUpdate), 1);
+        assert_eq!(bumps.get(work::GenerationCause::CleanJobs), 1);
+        assert_eq!(bumps.get(work::GenerationCause::ExtranonceChange), 1);
+        assert_eq!(bumps.get(work::GenerationCause::PrevHashChange), 2);
+        assert_eq!(bumps.get(work::GenerationCause::PoolFailover), 0);
+        assert_eq!(bumps.get(work::GenerationCause::ManualRestart), 0);
+        assert_eq!(*client.client_stats().valid_jobs().take_snapshot(), 5);
+        assert!(client.client_stats().last_job().time().is_some());
+    }
+
+    /// Job with network and targets independent of the test block
+    #[derive(Debug)]
+    struct TestNetworkJob {
+        job: TestJob,
+        network: ii_bitcoin::Network,
+        bits: u32,
+        target: ii_bitcoin::Target,
+        pool_target: ii_bitcoin::Target,
+    }
+
+    impl Bitcoin for TestNetworkJob {
+        fn origin(&self) -> Weak<dyn node::Client> {
+            self.job.origin()
+        }
+
+        fn version(&self) -> u32 {
+            self.job.version()
+        }
+
+        fn version_mask(&self) -> u32 {
+            self.job.version_mask()
+        }
+
+        fn previous_hash(&self) -> &ii_bitcoin::DHash {
+            self.job.previous_hash()
+        }
+
+        fn merkle_root(&self) -> &ii_bitcoin::DHash {
+            self.job.merkle_root()
+        }
+
+        fn time(&self) -> u32 {
+            self.job.time()
+        }
+
+        fn bits(&self) -> u32 {
+            self.bits
+        }
+
+        fn target(&self) -> ii_bitcoin::Target {
+            self.target
+        }
+
+        fn pool_target(&self) -> ii_bitcoin::Target {
+            self.pool_target
+        }
+
+        fn is_valid(&self) -> bool {
+            true
+        }
+
+        fn network(&self) -> ii_bitcoin::Network {
+            self.network
+        }
+    }
+
+    /// Job of altcoin fork with its own network parameters
+    #[derive(Debug)]
+    struct TestForkJob {
+        job: TestJob,
+        params: ii_bitcoin::NetworkParams,
+    }
+
+    impl Bitcoin for TestForkJob {
+        fn origin(&self) -> Weak<dyn node::Client> {
+            self.job.origin()
+        }
+
+        fn version(&self) -> u32 {
+            self.job.version()
+        }
+
+        fn version_mask(&self) -> u32 {
+            self.job.version_mask()
+        }
+
+        fn previous_hash(&self) -> &ii_bitcoin::DHash {
+            self.job.previous_hash()
+        }
+
+        fn merkle_root(&self) -> &ii_bitcoin::DHash {
+            self.job.merkle_root()
+        }
+
+        fn time(&self) -> u32 {
+            self.job.time()
+        }
+
+        fn bits(&self) -> u32 {
+            self.job.bits()
+        }
+
+        fn target(&self) -> ii_bitcoin::Target {
+            self.job.target()
+        }
+
+        fn is_valid(&self) -> bool {
+            true
+        }
+
+        fn network_params(&self) -> ii_bitcoin::NetworkParams {
+            self.params
+        }
+    }
+
+    fn build_solution(job: &Arc<dyn Bitcoin>, block: &test_utils::TestBlock) -> work::Solution {
+        let midstate = work::Midstate {
+            version: block.version,
+            state: block.midstate,
+        };
+        let work = work::Assignment::new(job.clone(), vec![midstate], block.time);
+        work::Solution::new(work, test_utils::TestSolution::new(block), None)
+    }
+
+    #[tokio::test]
+    async fn test_accept_test_network_block() {
+        let block = test_utils::TEST_BLOCKS[0];
+        let (_solution_sender, solution_channel) = mpsc::unbounded();
+        let mut receiver = SolutionReceiver::new(solution_channel);
+
+        for &(network, bits) in [
+            // regtest blocks are easier than any pool difficulty
+            (ii_bitcoin::Network::Regtest, 0x207fffff),
+            // testnet block with minimal difficulty allowed after 20 minutes without a block
+            (ii_bitcoin::Network::Testnet, 0x1d00ffff),
+        ]
+        .iter()
+        {
+            let client = Arc::new(test_utils::TestClient::new());
+            let target = ii_bitcoin::Target::from_pool_difficulty(1 << 20);
+            let job: Arc<dyn Bitcoin> = Arc::new(TestNetworkJob {
+                job: TestJob {
+                    block,
+                    client: client.clone(),
+                    valid: AtomicBool::new(true),
+                },
+                network,
+                bits,
+                target,
+                pool_target: target,
+            });
+            let solution = build_solution(&job, &block);
+            assert!(solution.network_target() > *solution.job_target());
+
+            // the block is submitted even though the network target is above the job target
+            let solution = receiver
+                .accept(solution)
+                .await
+                .expect("BUG: block has not been accepted");
+            assert!(solution.hash().meets(&solution.network_target()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_floor_dropped() {
+        let block = test_utils::TEST_BLOCKS[0];
+        let (_solution_sender, solution_channel) = mpsc::unbounded();
+        let mut receiver = SolutionReceiver::new(solution_channel);
+        let client = Arc::new(test_utils::TestClient::new());
+        let build_job = |pool_target| -> Arc<dyn Bitcoin> {
+            Arc::new(TestNetworkJob {
+                job: TestJob {
+                    block,
+                    client: client.clone(),
+                    valid: AtomicBool::new(true),
+                },
+                network: ii_bitcoin::Network::Mainnet,
+                // the solution is not a block
+                bits: 0x03000001,
+                // the floor is raised above the solution
+                target: ii_bitcoin::Target::from_pool_difficulty(1 << 60),
+                pool_target,
+            })
+        };
+
+        // the share meets the pool target but it is dropped by the floor
+        let job = build_job(block.target);
+        assert!(receiver
+            .accept(build_solution(&job, &block))
+            .await
+            .is_none());
+        assert_eq!(*client.client_stats().floor_dropped().take_snapshot(), 1);
+
+        // the share meeting neither of the targets is not counted
+        let job = build_job(ii_bitcoin::Target::from_pool_difficulty(1 << 61));
+        assert!(receiver
+            .accept(build_solution(&job, &block))
+            .await
+            .is_none());
+        assert_eq!(*client.client_stats().floor_dropped().take_snapshot(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stale_solutions() {
+        let block = test_utils::TEST_BLOCKS[0];
+        let (_solution_sender, solution_channel) = mpsc::unbounded();
+        let mut receiver = SolutionReceiver::new(solution_channel);
+        let client = Arc::new(test_utils::TestClient::new());
+        let job = Arc::new(TestJob {
+            block,
+            client: client.clone(),
+            valid: AtomicBool::new(true),
+        });
+        let dyn_job: Arc<dyn Bitcoin> = job.clone();
+        let stale_solutions = || *client.client_stats().stale_solutions().take_snapshot();
+
+        // the solution of current work is not counted
+        assert!(receiver
+            .accept(build_solution(&dyn_job, &block))
+            .await
+            .is_some());
+        assert_eq!(stale_solutions(), 0);
+
+        // the stale solution is counted and still submitted while its job is valid
+        let solution = build_solution(&dyn_job, &block).with_stale(true);
+        assert!(receiver.accept(solution).await.is_some());
+        assert_eq!(stale_solutions(), 1);
+
+        // the stale solution of invalidated job is counted and dropped
+        job.valid.store(false, Ordering::Relaxed);
+        let solution = build_solution(&dyn_job, &block).with_stale(true);
+        assert!(receiver.accept(solution).await.is_none());
+        assert_eq!(stale_solutions(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_vardiff_retarget() {
+        let (_solution_sender, solution_channel) = mpsc::unbounded();
+        let mut receiver = SolutionReceiver::new(solution_channel);
+        receiver.enable_vardiff(
+            vardiff::Config {
+                retarget_interval: Duration::from_millis(20),
+                ..Default::default()
+            },
+            64,
+        );
+
+        // the difficulty is lowered periodically while no shares are coming
+        assert!(receiver
+            .receive()
+            .timeout(Duration::from_millis(200))
+            .await
+            .is_err());
+        let difficulty = receiver
+            .vardiff_difficulty()
+            .expect("BUG: vardiff is not enabled");
+        assert!(difficulty < 16);
+    }
+
+    #[tokio::test]
+    async fn test_submit_ahead() {
+        let client = Arc::new(test_utils::TestClient::new());
+        let blocks = &test_utils::TEST_BLOCKS;
+        let job = |idx: usize| -> Arc<dyn Bitcoin> {
+            Arc::new(TestJob {
+                block: blocks[idx],
+                client: client.clone(),
+                valid: AtomicBool::new(true),
+            })
+        };
+        let (job_a, job_b) = (job(0), job(1));
+
+        let (solution_sender, solution_channel) = mpsc::unbounded();
+        let solver = Solver::new(Arc::new(work::EngineSender::new(None)), solution_channel);
+        let mut receiver = solver.solution_receiver;
+        // the receiver observes jobs sent by the sender
+        solver.job_sender.send(job_a.clone());
+        assert!(receiver
+            .job_timeline
+            .lock()
+            .expect("BUG: cannot lock job timeline")
+            .is_current(&job_a));
+
+        // job B arrives 30 seconds after job A so the next job is expected 60 seconds from now
+        let now = Instant::now();
+        {
+            let mut job_timeline = receiver
+                .job_timeline
+                .lock()
+                .expect("BUG: cannot lock job timeline");
+            job_timeline.job_sent(&job_a, now);
+            job_timeline.job_sent(&job_b, now + Duration::from_secs(30));
+        }
+        let send_solutions = || {
+            for (job, block) in [
+                (&job_a, &blocks[0]),
+                (&job_a, &blocks[0]),
+                (&job_b, &blocks[1]),
+            ]
+            .iter()
+            {
+                solution_sender
+                    .unbounded_send(build_solution(job, block))
+                    .expect("BUG: cannot send solution");
+            }
+        };
+        let received_job = |solution: Option<work::Solution>| {
+            *solution
+                .expect("BUG: missing solution")
+                .dyn_job()
+                .previous_hash()
+        };
+
+        // without submit-ahead the solutions are received in order of arrival
+        send_solutions();
+        for block in [&blocks[0], &blocks[0], &blocks[1]].iter() {
+            assert_eq!(received_job(receiver.receive().await), block.previous_hash);
+        }
+
+        // the next job is not expected within the horizon
+        receiver.enable_submit_ahead(Duration::from_secs(5));
+        send_solutions();
+        for block in [&blocks[0], &blocks[0], &blocks[1]].iter() {
+            assert_eq!(received_job(receiver.receive().await), block.previous_hash);
+        }
+
+        // near-stale solution of job B jumps the queue
+        receiver.enable_submit_ahead(Duration::from_secs(60));
+        send_solutions();
+        for block in [&blocks[1], &blocks[0], &blocks[0]].iter() {
+            assert_eq!(received_job(receiver.receive().await), block.previous_hash);
+        }
+
+        // buffered solutions are dropped together with the channel content
+        send_solutions();
+        assert!(receiver.receive().await.is_some());
+        receiver.flush();
+        assert!(receiver.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_submit_ahead_invalidated_job() {
+        let client = Arc::new(test_utils::TestClient::new());
+        let blocks = &test_utils::TEST_BLOCKS;
+        let job_a = Arc::new(TestJob {
+            block: blocks[0],
+            client: client.clone(),
+            valid: AtomicBool::new(true),
+        });
+        let job_b: Arc<dyn Bitcoin> = Arc::new(TestJob {
+            block: blocks[1],
+            client: client.clone(),
+            valid: AtomicBool::new(true),
+        });
+        let dyn_job_a: Arc<dyn Bitcoin> = job_a.clone();
+
+        let (solution_sender, solution_channel) = mpsc::unbounded();
+        let mut receiver = SolutionReceiver::new(solution_channel);
+        receiver.enable_submit_ahead(Duration::from_secs(5));
+        for (job, block) in [
+            (&dyn_job_a, &blocks[0]),
+            (&dyn_job_a, &blocks[0]),
+            (&job_b, &blocks[1]),
+        ]
+        .iter()
+        {
+            solution_sender
+                .unbounded_send(build_solution(job, block))
+                .expect("BUG: cannot send solution");
+        }
+
+        // all solutions are buffered and the first one is received
+        let solution = receiver.receive().await.expect("BUG: missing solution");
+        assert_eq!(*solution.dyn_job().previous_hash(), blocks[0].previous_hash);
+        assert_eq!(receiver.pending.len(), 2);
+
+        // the buffered solution of invalidated job is dropped
+        job_a.valid.store(false, Ordering::Relaxed);
+        let solution = receiver.receive().await.expect("BUG: missing solution");
+        assert_eq!(*solution.dyn_job().previous_hash(), blocks[1].previous_hash);
+        assert!(receiver.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fork_network_params() {
+        let block = test_utils::TEST_BLOCKS[0];
+        let (_solution_sender, solution_channel) = mpsc::unbounded();
+        let mut receiver = SolutionReceiver::new(solution_channel);
+        let client = Arc::new(test_utils::TestClient::new());
+        // fork with 256 times lower difficulty 1 target
+        let params = ii_bitcoin::NetworkParams::new(
+            ii_bitcoin::Target::from_pool_difficulty(256),
+            ii_bitcoin::Target::from_pool_difficulty(256),
+        );
+        let job: Arc<dyn Bitcoin> = Arc::new(TestForkJob {
+            job: TestJob {
+                block,
+                client: client.clone(),
+                valid: AtomicBool::new(true),
+            },
+            params,
+        });
+
+        let solution = receiver
+            .accept(build_solution(&job, &block))
+            .await
+            .expect("BUG: share has not been accepted");
+        assert_eq!(solution.network_params(), params);
+
+        // the last share is accounted with difficulty of the fork
+        let last_share = client
+            .client_stats()
+            .last_share()
+            .take_snapshot()
+            .await
+            .expect("BUG: missing last share");
+        let mainnet_difficulty = ii_bitcoin::NetworkParams::default().difficulty(&block.target);
+        assert_eq!(last_share.difficulty, mainnet_difficulty / 256.0);
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Submit-ahead of near-stale solutions. When a job change is imminent, solutions of the current
+//! job are submitted before solutions buffered earlier so that they reach the pool before they get
+//! stale. It recovers marginal shares on pools which change jobs frequently.
+
+use crate::job;
+
+use std::sync::{Arc, Weak};
+use std::time;
+
+/// Arrival of jobs used for prediction when the current job is going to be superseded
+#[derive(Debug, Default)]
+pub struct JobTimeline {
+    /// The most recent job with its arrival time
+    last_job: Option<(Weak<dyn job::Bitcoin>, time::Instant)>,
+    /// Interval between the two most recent jobs
+    interval: Option<time::Duration>,
+}
+
+impl JobTimeline {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Record that `job` has been sent for solving at `now`
+    pub fn job_sent(&mut self, job: &Arc<dyn job::Bitcoin>, now: time::Instant) {
+        if let Some((_, last_time)) = self.last_job {
+            self.interval = Some(now.saturating_duration_since(last_time));
+        }
+        self.last_job = Some((Arc::downgrade(job), now));
+    }
+
+    /// Expected arrival of the next job which supersedes the current one. The prediction requires
+    /// at least two jobs.
+    pub fn next_job_eta(&self) -> Option<time::Instant> {
+        let (_, last_time) = self.last_job.as_ref()?;
+        Some(*last_time + self.interval?)
+    }
+
+    /// Check if `job` is the most recent one
+    pub fn is_current(&self, job: &Arc<dyn job::Bitcoin>) -> bool {
+        match self
+            .last_job
+            .as_ref()
+            .and_then(|(last_job, _)| last_job.upgrade())
+        {
+            // compare only data pointers because vtables of the same type may differ
+            Some(last_job) => Arc::as_ptr(&last_job) as *const () == Arc::as_ptr(job) as *const (),
+            None => false,
+        }
+    }
+
+    /// Check if the current job is expected to be superseded within `horizon` from `now`
+    pub fn is_near_superseded(&self, now: time::Instant, horizon: time::Duration) -> bool {
+        self.next_job_eta()
+            .map_or(false, |next_job| now + horizon >= next_job)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    #[test]
+    fn test_job_timeline() {
+        let job_a: Arc<dyn job::Bitcoin> = Arc::new(test_utils::TEST_BLOCKS[0]);
+        let job_b: Arc<dyn job::Bitcoin> = Arc::new(test_utils::TEST_BLOCKS[1]);
+        let horizon = time::Duration::from_secs(5);
+        let start = time::Instant::now();
+        let at = |secs| start + time::Duration::from_secs(secs);
+
+        let mut timeline = JobTimeline::new();
+        assert!(!timeline.is_current(&job_a));
+        timeline.job_sent(&job_a, at(0));
+        assert!(timeline.is_current(&job_a));
+        // the interval between jobs is not known yet
+        assert_eq!(timeline.next_job_eta(), None);
+        assert!(!timeline.is_near_superseded(at(100), horizon));
+
+        timeline.job_sent(&job_b, at(30));
+        assert!(timeline.is_current(&job_b));
+        assert!(!timeline.is_current(&job_a));
+        assert_eq!(timeline.next_job_eta(), Some(at(60)));
+        assert!(!timeline.is_near_superseded(at(54), horizon));
+        assert!(timeline.is_near_superseded(at(55), horizon));
+        assert!(timeline.is_near_superseded(at(70), horizon));
+    }
+}
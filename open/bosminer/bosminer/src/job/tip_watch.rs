@@ -0,0 +1,181 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Watch of the chain tip for solo mining. The tip reported by the full node is polled
+//! periodically and the current work is invalidated as soon as a new block arrives so that the
+//! miner does not keep mining on a stale template until the next one is fetched.
+
+use ii_logging::macros::*;
+
+use crate::job;
+
+use async_trait::async_trait;
+use ii_async_compat::{futures, tokio};
+use tokio::time::delay_for;
+
+use std::time;
+
+/// Source of the current chain tip (e.g. previous block hash of `getblocktemplate`)
+#[async_trait]
+pub trait Tip: Send + Sync {
+    /// Hash of the block at the chain tip (`None` when it cannot be determined right now)
+    async fn tip(&self) -> Option<ii_bitcoin::DHash>;
+}
+
+/// Periodic check that the job being mined still builds on the chain tip
+#[derive(Debug, Clone)]
+pub struct TipWatch {
+    /// Period of polling the tip (`None` disables the watch)
+    interval: Option<time::Duration>,
+}
+
+impl TipWatch {
+    pub fn new(interval: time::Duration) -> Self {
+        Self {
+            interval: Some(interval),
+        }
+    }
+
+    /// The tip is never polled
+    pub fn disabled() -> Self {
+        Self { interval: None }
+    }
+
+    #[inline]
+    pub fn interval(&self) -> Option<time::Duration> {
+        self.interval
+    }
+
+    /// Poll the `tip` and invalidate work of `job_sender` as soon as the tip does not match the
+    /// current job. The new tip is returned so that the caller can fetch a job building on it.
+    /// It never returns when the watch is disabled.
+    pub async fn run<T: Tip>(&self, tip: &T, job_sender: &job::Sender) -> ii_bitcoin::DHash {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return futures::future::pending().await,
+        };
+        loop {
+            delay_for(interval).await;
+            match tip.tip().await {
+                Some(tip) => {
+                    if job_sender.check_tip(&tip) {
+                        info!("Chain tip has changed to {}, stale work invalidated", tip);
+                        return tip;
+                    }
+                }
+                None => debug!("Chain tip is not available"),
+            }
+        }
+    }
+}
+
+impl Default for TipWatch {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+    use crate::work;
+
+    use futures::FutureExt as _;
+
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    /// Full node whose tip is controlled by the test
+    struct TestTip(Arc<StdMutex<ii_bitcoin::DHash>>);
+
+    #[async_trait]
+    impl Tip for TestTip {
+        async fn tip(&self) -> Option<ii_bitcoin::DHash> {
+            Some(*self.0.lock().expect("BUG: cannot lock tip"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tip_change() {
+        const INTERVAL: time::Duration = time::Duration::from_secs(10);
+
+        let (engine_sender, mut engine_receiver) = work::engine_channel(work::IgnoreEvents);
+        engine_sender.replace_engine_generator(Box::new(|job| {
+            Arc::new(work::engine::VersionRolling::new(job, 1))
+        }));
+        let job_sender = job::Sender::new(Arc::new(engine_sender));
+
+        let block = test_utils::TEST_BLOCKS[0];
+        job_sender.send(Arc::new(block));
+        let engine = engine_receiver
+            .get_engine()
+            .await
+            .expect("BUG: missing engine");
+
+        tokio::time::pause();
+        let tip = Arc::new(StdMutex::new(block.previous_hash));
+        let test_tip = TestTip(tip.clone());
+        let watch = TipWatch::new(INTERVAL);
+        let mut run = Box::pin(watch.run(&test_tip, &job_sender));
+
+        // the job builds on the tip so the work stays valid
+        assert_eq!((&mut run).now_or_never(), None);
+        for _ in 0..3 {
+            tokio::time::advance(INTERVAL).await;
+            assert_eq!((&mut run).now_or_never(), None);
+        }
+        assert!(!engine.is_exhausted());
+        assert!(engine_receiver.is_current(&engine));
+
+        // a new block arrives and the work is invalidated on the next poll
+        let new_tip = test_utils::TEST_BLOCKS[1].previous_hash;
+        *tip.lock().expect("BUG: cannot lock tip") = new_tip;
+        tokio::time::advance(INTERVAL / 2).await;
+        assert_eq!((&mut run).now_or_never(), None);
+        assert!(engine_receiver.is_current(&engine));
+        tokio::time::advance(INTERVAL / 2).await;
+        assert_eq!((&mut run).now_or_never(), Some(new_tip));
+        assert!(!engine_receiver.is_current(&engine));
+
+        // the stale work is invalidated only once until a new job arrives
+        assert!(!job_sender.check_tip(&new_tip));
+        job_sender.send(Arc::new(test_utils::TEST_BLOCKS[1]));
+        assert!(!job_sender.check_tip(&new_tip));
+        assert!(job_sender.check_tip(&block.previous_hash));
+
+        // disabled watch never returns
+        let disabled = TipWatch::disabled();
+        let mut run = Box::pin(disabled.run(&test_tip, &job_sender));
+        tokio::time::advance(INTERVAL * 10).await;
+        assert_eq!((&mut run).now_or_never(), None);
+    }
+
+    #[test]
+    fn test_disabled() {
+        assert_eq!(TipWatch::disabled().interval(), None);
+        assert_eq!(TipWatch::default().interval(), None);
+        assert_eq!(
+            TipWatch::new(time::Duration::from_secs(5)).interval(),
+            Some(time::Duration::from_secs(5))
+        );
+    }
+}
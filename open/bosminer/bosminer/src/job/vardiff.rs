@@ -0,0 +1,219 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Local variable difficulty controller used when there is no upstream vardiff (solo mining, proxy
+//! with local difficulty). The controller adjusts difficulty of the local share filter to keep
+//! share rate close to the configured number of shares per minute.
+
+use std::time;
+
+/// Maximal factor by which the difficulty can be changed in one step
+const MAX_ADJUSTMENT_FACTOR: f64 = 4.0;
+
+/// Configuration of the vardiff controller
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Desired rate of shares
+    pub shares_per_minute: f64,
+    /// How often the difficulty is recalculated
+    pub retarget_interval: time::Duration,
+    /// Difficulty is never set below this value
+    pub min_difficulty: usize,
+    /// Difficulty is never set above this value
+    pub max_difficulty: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            shares_per_minute: 20.0,
+            retarget_interval: time::Duration::from_secs(90),
+            min_difficulty: 1,
+            max_difficulty: std::usize::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Controller {
+    config: Config,
+    /// Current difficulty of the local share filter
+    difficulty: usize,
+    /// Start of the current measurement period
+    period_start: time::Instant,
+    /// Sum of difficulties of all shares received in current period
+    accepted_difficulty: f64,
+}
+
+impl Controller {
+    pub fn new(config: Config, initial_difficulty: usize, now: time::Instant) -> Self {
+        assert!(
+            config.shares_per_minute > 0.0,
+            "BUG: vardiff requires positive share rate"
+        );
+        assert!(config.min_difficulty > 0 && config.min_difficulty <= config.max_difficulty);
+        let difficulty = initial_difficulty
+            .max(config.min_difficulty)
+            .min(config.max_difficulty);
+        Self {
+            config,
+            difficulty,
+            period_start: now,
+            accepted_difficulty: 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn difficulty(&self) -> usize {
+        self.difficulty
+    }
+
+    /// Target of the local share filter on the network described by `network_params`
+    #[inline]
+    pub fn target(&self, network_params: &ii_bitcoin::NetworkParams) -> ii_bitcoin::Target {
+        network_params.target_from_difficulty(self.difficulty)
+    }
+
+    /// Time when the difficulty is going to be recalculated even when no shares are coming
+    #[inline]
+    pub fn retarget_deadline(&self) -> time::Instant {
+        self.period_start + self.config.retarget_interval
+    }
+
+    /// Account share which passed the local filter with `share_difficulty` (the difficulty of the
+    /// filter at the time the share has been found). New difficulty is returned when it has been
+    /// changed.
+    pub fn account_share(&mut self, share_difficulty: usize, now: time::Instant) -> Option<usize> {
+        self.accepted_difficulty += share_difficulty as f64;
+        self.retarget(now)
+    }
+
+    /// Recalculate difficulty when the retarget interval has elapsed. It should be also called
+    /// periodically to detect low share rate when no shares are coming at all.
+    pub fn retarget(&mut self, now: time::Instant) -> Option<usize> {
+        let elapsed = now.saturating_duration_since(self.period_start);
+        if elapsed < self.config.retarget_interval {
+            return None;
+        }
+        let elapsed_minutes = elapsed.as_secs_f64() / 60.0;
+        // the difficulty which would result in desired share rate with current hashrate
+        let ideal_difficulty =
+            self.accepted_difficulty / elapsed_minutes / self.config.shares_per_minute;
+        let current_difficulty = self.difficulty as f64;
+        let new_difficulty = ideal_difficulty
+            .max(current_difficulty / MAX_ADJUSTMENT_FACTOR)
+            .min(current_difficulty * MAX_ADJUSTMENT_FACTOR)
+            .max(self.config.min_difficulty as f64)
+            .min(self.config.max_difficulty as f64)
+            .round() as usize;
+
+        self.period_start = now;
+        self.accepted_difficulty = 0.0;
+        if new_difficulty == self.difficulty {
+            return None;
+        }
+        self.difficulty = new_difficulty;
+        Some(new_difficulty)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SHARES_PER_MINUTE: f64 = 10.0;
+
+    fn build_controller(initial_difficulty: usize, now: time::Instant) -> Controller {
+        Controller::new(
+            Config {
+                shares_per_minute: SHARES_PER_MINUTE,
+                retarget_interval: time::Duration::from_secs(60),
+                min_difficulty: 16,
+                max_difficulty: 1 << 20,
+            },
+            initial_difficulty,
+            now,
+        )
+    }
+
+    /// Simulate miner with `hashrate` measured in difficulty 1 shares per second for `minutes`
+    fn simulate(controller: &mut Controller, start: time::Instant, hashrate: f64, minutes: u64) {
+        // sum of difficulty 1 shares which have not been found yet
+        let mut work = 0.0;
+        for second in 1..=minutes * 60 {
+            let now = start + time::Duration::from_secs(second);
+            work += hashrate;
+            while work >= controller.difficulty() as f64 {
+                work -= controller.difficulty() as f64;
+                controller.account_share(controller.difficulty(), now);
+            }
+            controller.retarget(now);
+        }
+    }
+
+    #[test]
+    fn test_high_share_rate() {
+        let start = time::Instant::now();
+        let mut controller = build_controller(16, start);
+        // 1000 shares per second at difficulty 1
+        simulate(&mut controller, start, 1000.0, 1);
+        // the difficulty has to be raised but only by limited factor at once
+        assert_eq!(controller.difficulty(), 64);
+
+        let start = start + time::Duration::from_secs(60);
+        simulate(&mut controller, start, 1000.0, 10);
+        // converge to 1000 * 60 / SHARES_PER_MINUTE
+        let difficulty = controller.difficulty() as f64;
+        assert!((difficulty - 6000.0).abs() / 6000.0 < 0.2);
+    }
+
+    #[test]
+    fn test_low_share_rate() {
+        let start = time::Instant::now();
+        let mut controller = build_controller(1 << 16, start);
+        // 100 difficulty 1 shares per second are not enough to get any share at initial difficulty
+        simulate(&mut controller, start, 100.0, 1);
+        assert_eq!(controller.difficulty(), 1 << 14);
+
+        let start = start + time::Duration::from_secs(60);
+        simulate(&mut controller, start, 100.0, 10);
+        let difficulty = controller.difficulty() as f64;
+        assert!((difficulty - 600.0).abs() / 600.0 < 0.2);
+    }
+
+    #[test]
+    fn test_difficulty_limits() {
+        let start = time::Instant::now();
+        let mut controller = build_controller(1, start);
+        assert_eq!(controller.difficulty(), 16);
+        // no shares at all
+        simulate(&mut controller, start, 0.0, 5);
+        assert_eq!(controller.difficulty(), 16);
+
+        let controller = build_controller(1 << 30, start);
+        assert_eq!(controller.difficulty(), 1 << 20);
+        assert_eq!(
+            controller.target(&Default::default()),
+            ii_bitcoin::Target::from_pool_difficulty(1 << 20)
+        );
+    }
+}
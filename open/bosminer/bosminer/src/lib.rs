@@ -25,6 +25,7 @@
 #![recursion_limit = "256"]
 
 mod api;
+pub mod affinity;
 pub mod backend;
 pub mod client;
 pub mod config;
@@ -34,6 +35,7 @@ pub mod hal;
 pub mod hub;
 pub mod job;
 pub mod node;
+pub mod retry;
 pub mod stats;
 pub mod sync;
 pub mod version;
@@ -84,6 +86,10 @@ impl node::WorkSolver for Frontend {
     async fn get_nominal_hashrate(&self) -> Option<ii_bitcoin::HashesUnit> {
         None
     }
+
+    async fn get_estimated_power(&self) -> Option<f64> {
+        None
+    }
 }
 
 impl fmt::Display for Frontend {
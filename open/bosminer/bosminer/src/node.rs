@@ -20,6 +20,7 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+use crate::error;
 use crate::job;
 use crate::stats;
 use crate::sync;
@@ -27,6 +28,7 @@ use crate::sync;
 use std::any::Any;
 use std::fmt::{Debug, Display};
 use std::sync::Arc;
+use std::time;
 
 use async_trait::async_trait;
 
@@ -60,6 +62,24 @@ pub trait Client: Info + ClientStats {
     async fn get_last_job(&self) -> Option<Arc<dyn job::Bitcoin>>;
     /// FIXME: Do not allow dynamic descriptor changes
     fn change_connection_details(&self, _descriptor: &bosminer_config::ClientDescriptor) {}
+    /// Pass registry of error conditions to let the client report its operational problems
+    async fn set_error_registry(&self, _error_registry: Arc<error::ErrorRegistry>) {}
+    /// Pass minimal number of extranonce bytes which the server has to leave for hardware rolling
+    /// its own extranonce ranges
+    fn set_min_extranonce_size(&self, _size: usize) {}
+    /// Pass practical minimum of pool difficulty derived from the hardware (see
+    /// `client::DifficultyFloor::hardware_minimum`) which may be suggested to the server. Shares
+    /// meeting lower pool difficulty must not be dropped because of it.
+    fn set_hardware_min_difficulty(&self, _difficulty: u64) {}
+    /// Smoothed time between submission of a solution and its acknowledgement by remote server
+    fn submission_latency(&self) -> Option<time::Duration> {
+        None
+    }
+    /// Client is able to submit block candidates found on jobs of other clients (e.g. it is
+    /// connected directly to a full node)
+    fn accepts_foreign_blocks(&self) -> bool {
+        false
+    }
 }
 
 pub trait ClientStats: Stats {
@@ -101,6 +121,8 @@ pub trait WorkSolver: Info + WorkSolverStats {
     }
     /// Return nominal/expected hashrate in hashes per second
     async fn get_nominal_hashrate(&self) -> Option<ii_bitcoin::HashesUnit>;
+    /// Return estimated power consumption in watts when the hardware is able to provide it
+    async fn get_estimated_power(&self) -> Option<f64>;
 }
 
 pub trait WorkSolverStats: Stats {
@@ -138,6 +160,10 @@ impl<T: ?Sized + WorkSolver> WorkSolver for Arc<T> {
     async fn get_nominal_hashrate(&self) -> Option<ii_bitcoin::HashesUnit> {
         self.as_ref().get_nominal_hashrate().await
     }
+
+    async fn get_estimated_power(&self) -> Option<f64> {
+        self.as_ref().get_estimated_power().await
+    }
 }
 
 impl<T: ?Sized + WorkSolverStats> WorkSolverStats for Arc<T> {
@@ -0,0 +1,256 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Retry policy with exponential backoff and jitter shared by all subsystems which have to cope
+//! with transient failures (hardware buses, network connections, ...)
+
+use ii_logging::macros::*;
+
+use ii_async_compat::tokio;
+use tokio::time::delay_for;
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, SystemTime};
+
+/// Describes how many times and how often a failed operation is retried
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Total number of attempts including the first one (at least one)
+    pub max_attempts: usize,
+    /// Delay before the first retry
+    pub base_delay: Duration,
+    /// Factor applied to the delay after each failed retry
+    pub multiplier: f64,
+    /// Upper bound of the delay (before jitter is applied)
+    pub max_delay: Duration,
+    /// Relative randomization of each delay in range 0 to 1 (e.g. 0.1 means +-10%)
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Create policy with constant delay between attempts
+    pub fn new(max_attempts: usize, base_delay: Duration) -> Self {
+        assert!(
+            max_attempts > 0,
+            "BUG: retry policy needs at least one attempt"
+        );
+        Self {
+            max_attempts,
+            base_delay,
+            multiplier: 1.0,
+            max_delay: base_delay,
+            jitter: 0.0,
+        }
+    }
+
+    /// Multiply the delay by `multiplier` after each failure up to `max_delay`
+    pub fn with_backoff(mut self, multiplier: f64, max_delay: Duration) -> Self {
+        assert!(multiplier >= 1.0, "BUG: backoff multiplier below 1");
+        self.multiplier = multiplier;
+        self.max_delay = max_delay.max(self.base_delay);
+        self
+    }
+
+    /// Randomize each delay by `jitter` fraction of its nominal value
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&jitter),
+            "BUG: jitter {} out of range",
+            jitter
+        );
+        self.jitter = jitter;
+        self
+    }
+
+    /// Check if another attempt is allowed after `attempt` (counted from 0) has failed
+    #[inline]
+    pub fn has_next_attempt(&self, attempt: usize) -> bool {
+        attempt + 1 < self.max_attempts
+    }
+
+    /// Delay (without jitter) before the retry which follows failed `attempt`
+    pub fn nominal_delay(&self, attempt: usize) -> Duration {
+        let delay = self.base_delay.as_secs_f64() * self.multiplier.powi(attempt as i32);
+        // comparison in floating point prevents overflow of `Duration`
+        if delay >= self.max_delay.as_secs_f64() {
+            self.max_delay
+        } else {
+            Duration::from_secs_f64(delay)
+        }
+    }
+
+    /// Apply jitter to nominal delay of given `attempt`. The `random` value in range 0 to 1
+    /// selects position within the jitter interval.
+    pub fn jittered_delay(&self, attempt: usize, random: f64) -> Duration {
+        let delay = self.nominal_delay(attempt).as_secs_f64();
+        let factor = 1.0 + self.jitter * (2.0 * random.max(0.0).min(1.0) - 1.0);
+        Duration::from_secs_f64(delay * factor)
+    }
+
+    /// Delay before the retry which follows failed `attempt` with random jitter
+    pub fn delay(&self, attempt: usize) -> Duration {
+        if self.jitter <= 0.0 {
+            self.nominal_delay(attempt)
+        } else {
+            self.jittered_delay(attempt, random_unit())
+        }
+    }
+
+    /// Nominal delays between all attempts
+    pub fn delays(&self) -> impl Iterator<Item = Duration> + '_ {
+        (0..self.max_attempts - 1).map(move |attempt| self.nominal_delay(attempt))
+    }
+
+    /// Sleep before the next attempt and return `false` when there are no attempts left
+    pub async fn backoff(&self, attempt: usize) -> bool {
+        if !self.has_next_attempt(attempt) {
+            return false;
+        }
+        delay_for(self.delay(attempt)).await;
+        true
+    }
+
+    /// Run `op` until it succeeds or all attempts are exhausted. The last error is returned and
+    /// the previous ones are logged as failures of `what`.
+    pub async fn retry<F, Fut, T, E>(&self, what: &str, mut op: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: fmt::Display,
+    {
+        let mut attempt = 0;
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    if !self.has_next_attempt(attempt) {
+                        return Err(e);
+                    }
+                    warn!(
+                        "{} failed (attempt {}/{}): {}, retrying...",
+                        what,
+                        attempt + 1,
+                        self.max_attempts,
+                        e
+                    );
+                    self.backoff(attempt).await;
+                }
+            }
+            attempt += 1;
+        }
+    }
+}
+
+/// Return pseudo-random number in range 0 to 1 which is sufficient for spreading retries
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(elapsed) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u32(elapsed.subsec_nanos());
+    }
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_delay_sequence() {
+        let policy = RetryPolicy::new(4, Duration::from_millis(100));
+        assert_eq!(
+            policy.delays().collect::<Vec<_>>(),
+            vec![Duration::from_millis(100); 3]
+        );
+
+        let policy = RetryPolicy::new(6, Duration::from_millis(100))
+            .with_backoff(2.0, Duration::from_millis(1000));
+        assert_eq!(
+            policy.delays().collect::<Vec<_>>(),
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(400),
+                Duration::from_millis(800),
+                Duration::from_millis(1000),
+            ]
+        );
+        // huge number of attempts does not overflow
+        assert_eq!(policy.nominal_delay(10_000), Duration::from_millis(1000));
+        assert!(policy.has_next_attempt(4));
+        assert!(!policy.has_next_attempt(5));
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(100))
+            .with_backoff(2.0, Duration::from_secs(1))
+            .with_jitter(0.25);
+
+        let assert_delay = |delay: Duration, expected_ms: f64| {
+            assert!((delay.as_secs_f64() * 1000.0 - expected_ms).abs() < 1e-3);
+        };
+        assert_delay(policy.jittered_delay(1, 0.0), 150.0);
+        assert_delay(policy.jittered_delay(1, 0.5), 200.0);
+        assert_delay(policy.jittered_delay(1, 1.0), 250.0);
+        // random value out of range is clamped
+        assert_delay(policy.jittered_delay(1, 2.0), 250.0);
+        for _ in 0..1000 {
+            let delay = policy.delay(0);
+            assert!((Duration::from_millis(75)..=Duration::from_millis(125)).contains(&delay));
+        }
+        let unit = random_unit();
+        assert!((0.0..1.0).contains(&unit));
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let calls = AtomicUsize::new(0);
+
+        // transient failures are recovered within the attempts
+        let result: Result<usize, &str> = policy
+            .retry("test operation", || async {
+                match calls.fetch_add(1, Ordering::Relaxed) {
+                    call @ 2 => Ok(call),
+                    _ => Err("transient error"),
+                }
+            })
+            .await;
+        assert_eq!(result, Ok(2));
+
+        // the last error is returned once all attempts are exhausted
+        calls.store(0, Ordering::Relaxed);
+        let result: Result<(), &str> = policy
+            .retry("test operation", || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err("persistent error")
+            })
+            .await;
+        assert_eq!(result, Err("persistent error"));
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+    }
+}
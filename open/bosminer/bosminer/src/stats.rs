@@ -20,8 +20,13 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+pub mod anomaly;
+pub mod csv;
+pub mod summary;
+
 use ii_logging::macros::*;
 
+use crate::client;
 use crate::node;
 use crate::stats;
 use crate::work;
@@ -34,8 +39,10 @@ use futures::lock::Mutex;
 use ii_async_compat::{futures, tokio};
 use tokio::time::delay_for;
 
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex as StdMutex;
 use std::time;
 
 use once_cell::sync::Lazy;
@@ -50,6 +57,14 @@ pub static TIME_MEAN_INTERVAL_15M: Lazy<time::Duration> =
 pub static TIME_MEAN_INTERVAL_24H: Lazy<time::Duration> =
     Lazy::new(|| time::Duration::from_secs(24 * 60 * 60));
 
+/// Minimal difficulty of a new best share which is reported in the log (disabled by default)
+static BEST_SHARE_LOG_THRESHOLD: AtomicU64 = AtomicU64::new(std::u64::MAX);
+
+/// Log every new best share of a work solver which reaches `threshold` difficulty
+pub fn set_best_share_log_threshold(threshold: Option<u64>) {
+    BEST_SHARE_LOG_THRESHOLD.store(threshold.unwrap_or(std::u64::MAX), Ordering::Relaxed);
+}
+
 static DEFAULT_TIME_MEAN_INTERVALS: Lazy<Vec<time::Duration>> = Lazy::new(|| {
     vec![
         *TIME_MEAN_INTERVAL_5S,
@@ -92,9 +107,16 @@ pub struct MeterSnapshot {
     pub shares: ii_bitcoin::Shares,
     /// Approximate arithmetic mean of hashes within given time intervals (in kH/time)
     time_means: Vec<WindowedTimeMean>,
+    /// End of the last warm-up period (solutions found before it are not included in time means)
+    pub warmup_end: Option<time::Instant>,
 }
 
 impl MeterSnapshot {
+    #[inline]
+    pub fn is_warming_up(&self, now: time::Instant) -> bool {
+        self.warmup_end.map_or(false, |warmup_end| now < warmup_end)
+    }
+
     fn get_time_mean(&self, interval: time::Duration) -> &WindowedTimeMean {
         self.time_means
             .iter()
@@ -146,11 +168,141 @@ impl MeterSnapshot {
     ) -> ii_bitcoin::HashesUnit {
         self.to_kilo_hashes(interval, now).into_pretty_hashes()
     }
+
+    /// Format hash rate in the unit which suits its magnitude (see `ii_bitcoin::format_hashrate`)
+    #[inline]
+    pub fn to_hashrate_string(&self, interval: time::Duration, now: time::Instant) -> String {
+        ii_bitcoin::format_hashrate_auto(self.to_kilo_hashes(interval, now).into_f64() * 1e+3)
+    }
+
+    /// Ratio of shares actually found to shares expected from `hashrate` within `interval`
+    /// Values near 1.0 are expected, lower values mean bad luck (or underperforming hardware
+    /// when it persists) and higher values good luck. The luck is undefined when no share is
+    /// expected.
+    pub fn luck(&self, hashrate: ii_bitcoin::HashesUnit, interval: time::Duration) -> Option<f64> {
+        let expected_hashes = hashrate.into_hashes().into_f64() * interval.as_secs_f64();
+        let expected_shares = ii_bitcoin::Shares::from(ii_bitcoin::HashesUnit::Hashes(
+            expected_hashes as u128,
+        ))
+        .as_f64();
+        if expected_shares == 0.0 {
+            None
+        } else {
+            Some(self.shares.as_f64() / expected_shares)
+        }
+    }
+
+    /// Energy efficiency in J/GH of hardware consuming `power` (in watts) while producing
+    /// hashrate measured by this snapshot within `interval` (see `efficiency`)
+    pub fn efficiency(
+        &self,
+        power: f64,
+        interval: time::Duration,
+        now: time::Instant,
+    ) -> Option<f64> {
+        efficiency(power, self.to_giga_hashes(interval, now))
+    }
+}
+
+/// Energy needed for computing one giga hash (in J/GH) by hardware consuming `power` (in watts)
+/// at `hashrate`. Lower values are better. The efficiency is undefined without any hashrate.
+pub fn efficiency(power: f64, hashrate: ii_bitcoin::HashesUnit) -> Option<f64> {
+    let giga_hashes = hashrate.into_giga_hashes().into_f64();
+    if giga_hashes > 0.0 && power.is_finite() && power >= 0.0 {
+        Some(power / giga_hashes)
+    } else {
+        None
+    }
+}
+
+/// Mean time between two solutions meeting `target` found by hardware computing at `hashrate`.
+/// The interval is undefined without any hashrate.
+pub fn expected_share_interval(
+    hashrate: ii_bitcoin::HashesUnit,
+    target: &ii_bitcoin::Target,
+) -> Option<time::Duration> {
+    let hashes = hashrate.into_hashes().into_f64();
+    if hashes > 0.0 {
+        let share_hashes = ii_bitcoin::Shares::new(target).into_hashes().into_f64();
+        Some(time::Duration::from_secs_f64(share_hashes / hashes))
+    } else {
+        None
+    }
+}
+
+/// Number of shares meeting `target` expected from hardware computing at `hashrate` within
+/// `window`
+pub fn expected_shares(
+    hashrate: ii_bitcoin::HashesUnit,
+    target: &ii_bitcoin::Target,
+    window: time::Duration,
+) -> f64 {
+    let share_hashes = ii_bitcoin::Shares::new(target).into_hashes().into_f64();
+    hashrate.into_hashes().into_f64() * window.as_secs_f64() / share_hashes
+}
+
+/// Shares accepted within a single reporting period together with the count expected for it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeriodShares {
+    pub accepted: u64,
+    pub expected: f64,
+}
+
+impl PeriodShares {
+    /// Ratio of found to expected shares (see `MeterSnapshot::luck`) which is undefined when
+    /// nothing is expected
+    pub fn ratio(&self) -> Option<f64> {
+        if self.expected > 0.0 {
+            Some(self.accepted as f64 / self.expected)
+        } else {
+            None
+        }
+    }
+}
+
+/// Splits a monotonic count of accepted shares into consecutive reporting periods so that they
+/// can be reconciled with share counts the pool reports for the same periods
+#[derive(Debug, Clone)]
+pub struct SharePeriod {
+    start: time::Instant,
+    /// Total number of accepted shares at the start of the period
+    accepted: u64,
+}
+
+impl SharePeriod {
+    pub fn new(start: time::Instant, accepted: u64) -> Self {
+        Self { start, accepted }
+    }
+
+    #[inline]
+    pub fn start(&self) -> time::Instant {
+        self.start
+    }
+
+    /// Finish the current period at `now` with `accepted` total shares and start the next one.
+    /// Shares are expected at `target` from the average `hashrate` within the period.
+    pub fn close(
+        &mut self,
+        now: time::Instant,
+        accepted: u64,
+        hashrate: ii_bitcoin::HashesUnit,
+        target: &ii_bitcoin::Target,
+    ) -> PeriodShares {
+        let window = now.saturating_duration_since(self.start);
+        let period_shares = PeriodShares {
+            accepted: accepted.saturating_sub(self.accepted),
+            expected: expected_shares(hashrate, target, window),
+        };
+        *self = Self::new(now, accepted);
+        period_shares
+    }
 }
 
 #[derive(Debug)]
 pub struct Meter {
     inner: Mutex<MeterSnapshot>,
+    /// Warm-up boundary is kept separately to allow setting it from synchronous context
+    warmup_end: StdMutex<Option<time::Instant>>,
 }
 
 impl Meter {
@@ -163,15 +315,32 @@ impl Meter {
                     .iter()
                     .map(|&interval| WindowedTimeMean::new(interval))
                     .collect(),
+                warmup_end: None,
             }),
+            warmup_end: StdMutex::new(None),
         }
     }
 
+    fn lock_warmup_end(&self) -> std::sync::MutexGuard<Option<time::Instant>> {
+        self.warmup_end.lock().expect("cannot lock warm-up end")
+    }
+
+    /// Exclude solutions found until `warmup_end` from time means. The solutions are still
+    /// accounted in the total number of shares.
+    pub fn set_warmup_end(&self, warmup_end: time::Instant) {
+        *self.lock_warmup_end() = Some(warmup_end);
+    }
+
     pub async fn take_snapshot(&self) -> Snapshot<MeterSnapshot> {
-        Snapshot::new(self.inner.lock().await.clone())
+        let mut snapshot = self.inner.lock().await.clone();
+        snapshot.warmup_end = *self.lock_warmup_end();
+        Snapshot::new(snapshot)
     }
 
     pub(crate) async fn account_solution(&self, target: &ii_bitcoin::Target, time: time::Instant) {
+        let warming_up = self
+            .lock_warmup_end()
+            .map_or(false, |warmup_end| time < warmup_end);
         let mut meter = self.inner.lock().await;
         let kilo_hashes = ii_bitcoin::Shares::new(target)
             .into_kilo_hashes()
@@ -180,6 +349,10 @@ impl Meter {
         // TODO: what to do when number overflows
         meter.solutions += 1;
         meter.shares.account_solution(target);
+        if warming_up {
+            // solution rates are erratic during warm-up and would skew the displayed hashrate
+            return;
+        }
         for time_mean in &mut meter.time_means {
             time_mean.insert(kilo_hashes, time);
         }
@@ -197,7 +370,7 @@ pub struct LastShareSnapshot {
     /// Time when the last share has been submitted
     pub time: time::SystemTime,
     /// Difficulty of the last share
-    pub difficulty: usize,
+    pub difficulty: f64,
 }
 
 #[derive(Debug)]
@@ -217,11 +390,12 @@ impl LastShare {
     pub(crate) async fn account_solution(
         &self,
         target: &ii_bitcoin::Target,
+        network_params: &ii_bitcoin::NetworkParams,
         time: time::SystemTime,
     ) {
         self.inner.lock().await.replace(LastShareSnapshot {
             time,
-            difficulty: target.get_difficulty(),
+            difficulty: network_params.difficulty(target),
         });
     }
 }
@@ -234,14 +408,16 @@ impl Default for LastShare {
     }
 }
 
+/// Highest difficulty of a share found so far. It is kept in 64 bits because shares above
+/// difficulty of 2^32 are found regularly even on platforms with 32-bit `usize`.
 #[derive(Debug)]
 pub struct BestShare {
-    inner: AtomicUsize,
+    inner: AtomicU64,
 }
 
 impl BestShare {
-    const INVALID_DIFFICULTY: usize = 0;
-    pub fn take_snapshot(&self) -> Option<Snapshot<usize>> {
+    const INVALID_DIFFICULTY: u64 = 0;
+    pub fn take_snapshot(&self) -> Option<Snapshot<u64>> {
         let difficulty = self.inner.load(Ordering::Relaxed);
         if difficulty == Self::INVALID_DIFFICULTY {
             None
@@ -250,27 +426,90 @@ impl BestShare {
         }
     }
 
-    pub(crate) fn account_solution(&self, target: &ii_bitcoin::Target) {
-        let new_diff = target.get_difficulty();
-        let mut old_diff = self.inner.load(Ordering::Relaxed);
-
-        while old_diff < new_diff {
-            let prev_diff = self
-                .inner
-                .compare_and_swap(old_diff, new_diff, Ordering::Relaxed);
-            if old_diff == prev_diff {
-                break;
-            } else {
-                old_diff = prev_diff;
-            }
-        }
+    /// Returns `true` when the solution has exceeded all previous ones
+    pub(crate) fn account_solution(
+        &self,
+        target: &ii_bitcoin::Target,
+        network_params: &ii_bitcoin::NetworkParams,
+    ) -> bool {
+        let new_diff = network_params.get_difficulty_u64(target);
+        self.inner.fetch_max(new_diff, Ordering::Relaxed) < new_diff
     }
 }
 
 impl Default for BestShare {
     fn default() -> Self {
         Self {
-            inner: AtomicUsize::new(Self::INVALID_DIFFICULTY),
+            inner: AtomicU64::new(Self::INVALID_DIFFICULTY),
+        }
+    }
+}
+
+/// Snapshot of hardware solutions verified by CPU
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrossCheckSnapshot {
+    /// Number of solutions which are valid as reported by the hardware
+    pub valid: u64,
+    /// Number of solutions which have not been found even in the neighbourhood
+    pub invalid: u64,
+    /// Number of solutions found with particular offset of nonce/nTime
+    pub offsets: Vec<(work::SolutionOffset, u64)>,
+}
+
+impl CrossCheckSnapshot {
+    #[inline]
+    pub fn total(&self) -> u64 {
+        self.valid + self.invalid + self.offsets.iter().map(|(_, count)| count).sum::<u64>()
+    }
+
+    /// Return offset which is present in the majority of all checked solutions
+    /// It usually signals a bug in the firmware or the mining chip
+    pub fn systematic_offset(&self) -> Option<work::SolutionOffset> {
+        let total = self.total();
+        self.offsets
+            .iter()
+            .find(|(_, count)| *count * 2 > total)
+            .map(|(offset, _)| *offset)
+    }
+}
+
+/// Statistics of the CPU cross-check of hardware solutions
+#[derive(Debug, Default)]
+pub struct CrossCheck {
+    valid: CounterU64,
+    invalid: CounterU64,
+    offsets: StdMutex<HashMap<work::SolutionOffset, u64>>,
+}
+
+impl CrossCheck {
+    pub fn take_snapshot(&self) -> CrossCheckSnapshot {
+        let mut offsets: Vec<_> = self
+            .offsets
+            .lock()
+            .expect("cannot lock cross-check offsets")
+            .iter()
+            .map(|(offset, count)| (*offset, *count))
+            .collect();
+        offsets.sort();
+        CrossCheckSnapshot {
+            valid: *self.valid.take_snapshot(),
+            invalid: *self.invalid.take_snapshot(),
+            offsets,
+        }
+    }
+
+    pub(crate) fn account(&self, result: work::CrossCheck) {
+        match result {
+            work::CrossCheck::Valid => self.valid.inc(),
+            work::CrossCheck::Invalid => self.invalid.inc(),
+            work::CrossCheck::Offset(offset) => {
+                *self
+                    .offsets
+                    .lock()
+                    .expect("cannot lock cross-check offsets")
+                    .entry(offset)
+                    .or_insert(0) += 1;
+            }
         }
     }
 }
@@ -363,6 +602,260 @@ atomic_counter_impl!(AtomicUsize, usize);
 pub type CounterU64 = Counter<AtomicU64>;
 pub type CounterUsize = Counter<AtomicUsize>;
 
+/// Number of work generation bumps split by their cause. Comparing these counters with stale
+/// shares reveals what makes the work obsolete.
+#[derive(Debug, Default)]
+pub struct GenerationBumps {
+    counters: [CounterU64; work::GenerationCause::COUNT],
+}
+
+impl GenerationBumps {
+    #[inline]
+    pub fn account(&self, cause: work::GenerationCause) {
+        self.counters[cause as usize].inc();
+    }
+
+    pub fn get(&self, cause: work::GenerationCause) -> u64 {
+        *self.counters[cause as usize].take_snapshot()
+    }
+}
+
+/// Number of shares rejected by remote server split by the reason of rejection. It tells apart
+/// shares rejected due to late job invalidation from really invalid ones.
+#[derive(Debug, Default)]
+pub struct RejectReasons {
+    pub stale: CounterU64,
+    pub job_not_found: CounterU64,
+    pub duplicate: CounterU64,
+    pub low_difficulty: CounterU64,
+    pub other: CounterU64,
+}
+
+impl RejectReasons {
+    pub fn account(&self, reason: &client::rejection::RejectReason) {
+        use client::rejection::RejectReason;
+
+        match reason {
+            RejectReason::Stale => self.stale.inc(),
+            RejectReason::JobNotFound => self.job_not_found.inc(),
+            RejectReason::Duplicate => self.duplicate.inc(),
+            RejectReason::LowDifficulty => self.low_difficulty.inc(),
+            RejectReason::Other(_) => self.other.inc(),
+        }
+    }
+
+    /// Number of shares rejected although their job was still valid
+    pub fn invalid(&self) -> u64 {
+        *self.duplicate.take_snapshot()
+            + *self.low_difficulty.take_snapshot()
+            + *self.other.take_snapshot()
+    }
+
+    /// Number of shares rejected because their hash does not meet the pool target. Unlike the
+    /// other invalid shares (duplicates, authorization failures, ...) it is caused by chips
+    /// computing wrong hashes.
+    pub fn invalid_hash(&self) -> u64 {
+        *self.low_difficulty.take_snapshot()
+    }
+}
+
+/// Number of shares accepted by remote server split by whether the solved block header has
+/// rolled version (BIP320) or the base version of the job. It confirms that AsicBoost is actually
+/// active.
+#[derive(Debug, Default)]
+pub struct VersionRollingShares {
+    pub rolled: CounterU64,
+    pub base: CounterU64,
+}
+
+impl VersionRollingShares {
+    #[inline]
+    pub fn account(&self, version_rolled: bool) {
+        if version_rolled {
+            self.rolled.inc();
+        } else {
+            self.base.inc();
+        }
+    }
+}
+
+/// Number of retired work items and total number of distinct midstates which produced at least
+/// one solution for them. Low average indicates that multi-midstate work (AsicBoost) does not
+/// pay off as expected.
+#[derive(Debug, Default)]
+pub struct MidstateYield {
+    pub work: CounterU64,
+    pub solved_midstates: CounterU64,
+}
+
+impl MidstateYield {
+    #[inline]
+    pub fn account_work(&self, solved_midstates: usize) {
+        self.work.inc();
+        self.solved_midstates.add(solved_midstates as u64);
+    }
+
+    /// Average number of solved midstates per work item or `None` when no work has been retired
+    pub fn average(&self) -> Option<f64> {
+        let work = *self.work.take_snapshot();
+        if work == 0 {
+            return None;
+        }
+        Some(*self.solved_midstates.take_snapshot() as f64 / work as f64)
+    }
+}
+
+/// Number of solutions submitted to the remote server which have not been acknowledged yet and
+/// number of solutions held back until the count of outstanding submissions drops below its limit
+/// (the held back solutions which did not fit into the queue are counted as dropped)
+#[derive(Debug, Default)]
+pub struct PendingSubmissions {
+    outstanding: AtomicUsize,
+    queued: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+impl PendingSubmissions {
+    #[inline]
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(crate) fn set(&self, outstanding: usize, queued: usize, dropped: u64) {
+        self.outstanding.store(outstanding, Ordering::Relaxed);
+        self.queued.store(queued, Ordering::Relaxed);
+        self.dropped.store(dropped, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+struct DonationWindow {
+    /// Beginning of the current donation period
+    start: Option<time::Instant>,
+    /// Total time of all finished donation periods
+    elapsed: time::Duration,
+}
+
+/// Mining on behalf of developers (dev fee) accounted apart from the statistics of the user's
+/// pools so that it is visible how much hashing went to the fee
+#[derive(Debug)]
+pub struct Donation {
+    /// Valid shares at job difficulty found on jobs of donation pools
+    pub valid_job_diff: Meter,
+    /// Valid shares at backend difficulty found on jobs of donation pools
+    pub valid_backend_diff: Meter,
+    window: StdMutex<DonationWindow>,
+}
+
+impl Donation {
+    pub fn new(intervals: &Vec<time::Duration>) -> Self {
+        Self {
+            valid_job_diff: Meter::new(intervals),
+            valid_backend_diff: Meter::new(intervals),
+            window: Default::default(),
+        }
+    }
+
+    fn lock_window(&self) -> std::sync::MutexGuard<DonationWindow> {
+        self.window
+            .lock()
+            .expect("BUG: cannot lock donation window")
+    }
+
+    /// Begin donation period at `now` unless it is already running
+    pub fn start(&self, now: time::Instant) {
+        let mut window = self.lock_window();
+        if window.start.is_none() {
+            window.start = Some(now);
+        }
+    }
+
+    /// Finish current donation period at `now`
+    pub fn stop(&self, now: time::Instant) {
+        let mut window = self.lock_window();
+        if let Some(start) = window.start.take() {
+            window.elapsed += now.saturating_duration_since(start);
+        }
+    }
+
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.lock_window().start.is_some()
+    }
+
+    /// Total time spent mining for donation including the current period until `now`
+    pub fn time(&self, now: time::Instant) -> time::Duration {
+        let window = self.lock_window();
+        window.elapsed
+            + window.start.map_or(Default::default(), |start| {
+                now.saturating_duration_since(start)
+            })
+    }
+
+    /// Account `solution` found on a job of a donation pool
+    pub(crate) async fn account_solution(&self, solution: &work::Solution) {
+        let time = solution.timestamp();
+        let hash = solution.hash();
+        let backend_target = solution.backend_target();
+        if !hash.meets(backend_target) {
+            // hardware errors are accounted by the work solvers
+            return;
+        }
+        self.valid_backend_diff
+            .account_solution(backend_target, time)
+            .await;
+        let job_target = solution.job_target();
+        if hash.meets(job_target) {
+            self.valid_job_diff.account_solution(job_target, time).await;
+        }
+    }
+}
+
+impl Default for Donation {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIME_MEAN_INTERVALS.as_ref())
+    }
+}
+
+/// Arrival time of a job which can be updated from synchronous context
+#[derive(Debug, Default)]
+pub struct LastJob {
+    time: StdMutex<Option<time::Instant>>,
+}
+
+impl LastJob {
+    fn lock_time(&self) -> std::sync::MutexGuard<Option<time::Instant>> {
+        self.time.lock().expect("BUG: cannot lock last job time")
+    }
+
+    #[inline]
+    pub fn time(&self) -> Option<time::Instant> {
+        *self.lock_time()
+    }
+
+    /// Time elapsed since arrival of the job at `now` (`None` when no job has been received)
+    #[inline]
+    pub fn age(&self, now: time::Instant) -> Option<time::Duration> {
+        self.time().map(|time| now.saturating_duration_since(time))
+    }
+
+    #[inline]
+    pub(crate) fn touch(&self, time: time::Instant) {
+        self.lock_time().replace(time);
+    }
+}
+
 #[derive(Debug)]
 pub struct Timestamp {
     inner: Mutex<Option<time::SystemTime>>,
@@ -419,6 +912,35 @@ pub trait Mining: Send + Sync {
     fn valid_backend_diff(&self) -> &Meter;
     /// Statistics for all invalid work on backend difficulty (backend/HW error)
     fn error_backend_diff(&self) -> &Meter;
+    /// Results of CPU verification of hardware solutions (when enabled)
+    fn cross_check(&self) -> &CrossCheck;
+    /// Number of malformed solutions (e.g. with nonexistent midstate) dropped without processing
+    fn dropped_solutions(&self) -> &CounterU64;
+    /// Number of valid solutions of work which had been already replaced by work of a newer job
+    /// when they were found
+    fn stale_solutions(&self) -> &CounterU64;
+    /// Number of switches to new work split by their cause
+    fn generation_bumps(&self) -> &GenerationBumps;
+    /// Number of distinct solved midstates per retired work
+    fn midstate_yield(&self) -> &MidstateYield;
+    /// Depth of submissions waiting for acknowledgement from the remote server
+    fn pending_submissions(&self) -> &PendingSubmissions;
+
+    /// Start warm-up period (e.g. after startup or change of frequency) of given `duration`
+    /// during which the solutions are not reflected in the rolling hashrate
+    fn start_warmup(&self, duration: time::Duration) {
+        let warmup_end = time::Instant::now() + duration;
+        for meter in [
+            self.valid_network_diff(),
+            self.valid_job_diff(),
+            self.valid_backend_diff(),
+            self.error_backend_diff(),
+        ]
+        .iter()
+        {
+            meter.set_warmup_end(warmup_end);
+        }
+    }
 }
 
 pub trait Client: Mining {
@@ -426,6 +948,8 @@ pub trait Client: Mining {
     fn valid_jobs(&self) -> &CounterUsize;
     /// Number of invalid jobs received from remote server
     fn invalid_jobs(&self) -> &CounterUsize;
+    /// Arrival of the most recent valid job
+    fn last_job(&self) -> &LastJob;
     /// Number of work generated from jobs by rolling or with extra nonce
     fn generated_work(&self) -> &CounterU64;
     /// Shares accepted by remote server
@@ -434,6 +958,13 @@ pub trait Client: Mining {
     fn rejected(&self) -> &Meter;
     /// Valid shares rejected by remote server or discarded due to some error
     fn stale(&self) -> &Meter;
+    /// Number of rejected shares split by the reason stated by remote server
+    fn reject_reasons(&self) -> &RejectReasons;
+    /// Number of shares meeting pool difficulty which have not been submitted because of the
+    /// internal difficulty floor (see `client::DifficultyFloor`)
+    fn floor_dropped(&self) -> &CounterU64;
+    /// Number of shares accepted by remote server with rolled and base version
+    fn version_rolling(&self) -> &VersionRollingShares;
 }
 
 pub trait WorkSolver: Mining {
@@ -441,6 +972,9 @@ pub trait WorkSolver: Mining {
     fn last_work_time(&self) -> &Timestamp;
     /// Number of work generated from jobs by rolling or with extra nonce
     fn generated_work(&self) -> &CounterU64;
+    /// Number of work assignments rejected before dispatch because they would waste hashing
+    /// capacity (e.g. duplicate midstates)
+    fn invalid_work(&self) -> &CounterU64;
 }
 
 #[derive(Debug, MiningStats)]
@@ -459,6 +993,18 @@ pub struct BasicMining {
     pub valid_backend_diff: Meter,
     #[member_error_backend_diff]
     pub error_backend_diff: Meter,
+    #[member_cross_check]
+    pub cross_check: CrossCheck,
+    #[member_dropped_solutions]
+    pub dropped_solutions: CounterU64,
+    #[member_stale_solutions]
+    pub stale_solutions: CounterU64,
+    #[member_generation_bumps]
+    pub generation_bumps: GenerationBumps,
+    #[member_midstate_yield]
+    pub midstate_yield: MidstateYield,
+    #[member_pending_submissions]
+    pub pending_submissions: PendingSubmissions,
 }
 
 impl BasicMining {
@@ -471,6 +1017,12 @@ impl BasicMining {
             valid_job_diff: Meter::new(&intervals),
             valid_backend_diff: Meter::new(&intervals),
             error_backend_diff: Meter::new(&intervals),
+            cross_check: Default::default(),
+            dropped_solutions: Default::default(),
+            stale_solutions: Default::default(),
+            generation_bumps: Default::default(),
+            midstate_yield: Default::default(),
+            pending_submissions: Default::default(),
         }
     }
 }
@@ -489,6 +1041,8 @@ pub struct BasicClient {
     pub valid_jobs: stats::CounterUsize,
     #[member_invalid_jobs]
     pub invalid_jobs: stats::CounterUsize,
+    #[member_last_job]
+    pub last_job: LastJob,
     #[member_generated_work]
     pub generated_work: CounterU64,
     #[member_last_share]
@@ -501,6 +1055,12 @@ pub struct BasicClient {
     pub rejected: stats::Meter,
     #[member_stale]
     pub stale: stats::Meter,
+    #[member_reject_reasons]
+    pub reject_reasons: RejectReasons,
+    #[member_floor_dropped]
+    pub floor_dropped: CounterU64,
+    #[member_version_rolling]
+    pub version_rolling: VersionRollingShares,
     #[member_valid_network_diff]
     pub valid_network_diff: Meter,
     #[member_valid_job_diff]
@@ -509,6 +1069,18 @@ pub struct BasicClient {
     pub valid_backend_diff: Meter,
     #[member_error_backend_diff]
     pub error_backend_diff: Meter,
+    #[member_cross_check]
+    pub cross_check: CrossCheck,
+    #[member_dropped_solutions]
+    pub dropped_solutions: CounterU64,
+    #[member_stale_solutions]
+    pub stale_solutions: CounterU64,
+    #[member_generation_bumps]
+    pub generation_bumps: GenerationBumps,
+    #[member_midstate_yield]
+    pub midstate_yield: MidstateYield,
+    #[member_pending_submissions]
+    pub pending_submissions: PendingSubmissions,
 }
 
 impl BasicClient {
@@ -517,18 +1089,36 @@ impl BasicClient {
             start_time,
             valid_jobs: Default::default(),
             invalid_jobs: Default::default(),
+            last_job: Default::default(),
             generated_work: Default::default(),
             last_share: Default::default(),
             best_share: Default::default(),
             accepted: Meter::new(&intervals),
             rejected: Meter::new(&intervals),
             stale: Default::default(),
+            reject_reasons: Default::default(),
+            floor_dropped: Default::default(),
+            version_rolling: Default::default(),
             valid_network_diff: Meter::new(&intervals),
             valid_job_diff: Meter::new(&intervals),
             valid_backend_diff: Meter::new(&intervals),
             error_backend_diff: Meter::new(&intervals),
+            cross_check: Default::default(),
+            dropped_solutions: Default::default(),
+            stale_solutions: Default::default(),
+            generation_bumps: Default::default(),
+            midstate_yield: Default::default(),
+            pending_submissions: Default::default(),
         }
     }
+
+    /// Account solution which has been accepted by remote server
+    pub async fn account_accepted(&self, solution: &work::Solution, time: time::Instant) {
+        self.accepted
+            .account_solution(&solution.job_target(), time)
+            .await;
+        self.version_rolling.account(solution.version_rolled());
+    }
 }
 
 impl Default for BasicClient {
@@ -545,6 +1135,8 @@ pub struct BasicWorkSolver {
     pub last_work_time: Timestamp,
     #[member_generated_work]
     pub generated_work: CounterU64,
+    #[member_invalid_work]
+    pub invalid_work: CounterU64,
     #[member_last_share]
     pub last_share: LastShare,
     #[member_best_share]
@@ -557,6 +1149,18 @@ pub struct BasicWorkSolver {
     pub valid_backend_diff: Meter,
     #[member_error_backend_diff]
     pub error_backend_diff: Meter,
+    #[member_cross_check]
+    pub cross_check: CrossCheck,
+    #[member_dropped_solutions]
+    pub dropped_solutions: CounterU64,
+    #[member_stale_solutions]
+    pub stale_solutions: CounterU64,
+    #[member_generation_bumps]
+    pub generation_bumps: GenerationBumps,
+    #[member_midstate_yield]
+    pub midstate_yield: MidstateYield,
+    #[member_pending_submissions]
+    pub pending_submissions: PendingSubmissions,
 }
 
 impl BasicWorkSolver {
@@ -567,10 +1171,17 @@ impl BasicWorkSolver {
             best_share: Default::default(),
             last_work_time: Default::default(),
             generated_work: Default::default(),
+            invalid_work: Default::default(),
             valid_network_diff: Meter::new(&intervals),
             valid_job_diff: Meter::new(&intervals),
             valid_backend_diff: Meter::new(&intervals),
             error_backend_diff: Meter::new(&intervals),
+            cross_check: Default::default(),
+            dropped_solutions: Default::default(),
+            stale_solutions: Default::default(),
+            generation_bumps: Default::default(),
+            midstate_yield: Default::default(),
+            pending_submissions: Default::default(),
         }
     }
 }
@@ -638,18 +1249,60 @@ pub async fn account_valid_solution(
             );
         }
         // use only job difficulty for accounting the last share even if a hash of the solution
-        // meets higher difficulties
-        for node in path {
-            let mining_stats = node.mining_stats();
-            mining_stats
-                .last_share()
-                .account_solution(target, time::SystemTime::now())
-                .await;
-            mining_stats.best_share().account_solution(target);
+        // meets higher difficulties while the best share is the real difficulty of the hash
+        let share_target = ii_bitcoin::Target::from(*solution.hash());
+        account_share(path, target, &share_target, &solution.network_params()).await;
+    }
+}
+
+/// Accounts a share of `target` difficulty as the last share and a share of `share_target`
+/// difficulty as a candidate for the best share in all nodes in the path
+async fn account_share(
+    path: &node::Path,
+    target: &ii_bitcoin::Target,
+    share_target: &ii_bitcoin::Target,
+    network_params: &ii_bitcoin::NetworkParams,
+) {
+    for (i, node) in path.iter().enumerate() {
+        let mining_stats = node.mining_stats();
+        mining_stats
+            .last_share()
+            .account_solution(target, network_params, time::SystemTime::now())
+            .await;
+        let new_best = mining_stats
+            .best_share()
+            .account_solution(share_target, network_params);
+        // the path ends with the work solver which has found the solution
+        if new_best && i == path.len() - 1 {
+            log_best_share(node, network_params.get_difficulty_u64(share_target));
         }
     }
 }
 
+/// Accounts a share of `target` difficulty which has not been found by any work solver (see
+/// `hub::Core::simulate_accepted_share`) in the same statistics as a valid solution meeting the
+/// job target
+#[cfg(any(test, feature = "share-simulation"))]
+pub(crate) async fn account_simulated_share(
+    path: &node::Path,
+    target: &ii_bitcoin::Target,
+    network_params: &ii_bitcoin::NetworkParams,
+    time: time::Instant,
+) {
+    account_valid_backend_diff(path, target, time).await;
+    account_valid_job_diff(path, target, time).await;
+    account_share(path, target, target, network_params).await;
+}
+
+fn log_best_share(node: &node::DynInfo, difficulty: u64) {
+    if difficulty >= BEST_SHARE_LOG_THRESHOLD.load(Ordering::Relaxed) {
+        info!(
+            "New best share of '{}' with difficulty {}",
+            node, difficulty
+        );
+    }
+}
+
 pub async fn mining_task(node: node::DynInfo, interval: time::Duration) {
     loop {
         delay_for(time::Duration::from_secs(1)).await;
@@ -661,11 +1314,388 @@ pub async fn mining_task(node: node::DynInfo, interval: time::Duration) {
             .await;
 
         info!(
-            "Hash rate ({} s avg.) for '{}' @ backend diff.: {}/s, job diff.: {}/s",
+            "Hash rate ({} s avg.) for '{}' @ backend diff.: {}, job diff.: {}",
             interval.as_secs(),
             node,
-            valid_backend_diff.to_pretty_hashes(interval, time::Instant::now()),
-            valid_job_diff.to_pretty_hashes(interval, time::Instant::now()),
+            valid_backend_diff.to_hashrate_string(interval, time::Instant::now()),
+            valid_job_diff.to_hashrate_string(interval, time::Instant::now()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use ii_async_compat::tokio;
+
+    #[tokio::test]
+    async fn test_luck() {
+        let meter = Meter::default();
+        let target = ii_bitcoin::Target::from_pool_difficulty(1000);
+        let now = time::Instant::now();
+
+        // feed 10 shares at difficulty 1000
+        for _ in 0..10 {
+            meter.account_solution(&target, now).await;
+        }
+        let snapshot = meter.take_snapshot().await;
+
+        // hashrate which should produce 5000 shares within 100 seconds
+        let interval = time::Duration::from_secs(100);
+        let hashrate = ii_bitcoin::HashesUnit::Hashes((5000u128 << 32) / 100);
+        let luck = snapshot.luck(hashrate, interval).expect("BUG: undefined luck");
+        assert!((luck - 2.0).abs() < 1e-6, "unexpected luck {}", luck);
+
+        // nothing is expected without hashrate
+        assert!(snapshot
+            .luck(ii_bitcoin::HashesUnit::Hashes(0), interval)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_donation() {
+        let donation = Donation::default();
+        let start = time::Instant::now();
+        assert!(!donation.is_active());
+        assert_eq!(donation.time(start), time::Duration::from_secs(0));
+
+        // simulate two donation windows lasting 30 and 10 seconds
+        donation.start(start);
+        assert!(donation.is_active());
+        donation.start(start + time::Duration::from_secs(10));
+        assert_eq!(
+            donation.time(start + time::Duration::from_secs(20)),
+            time::Duration::from_secs(20)
+        );
+        donation.stop(start + time::Duration::from_secs(30));
+        assert!(!donation.is_active());
+        donation.start(start + time::Duration::from_secs(100));
+        donation.stop(start + time::Duration::from_secs(110));
+        assert_eq!(
+            donation.time(start + time::Duration::from_secs(200)),
+            time::Duration::from_secs(40)
+        );
+
+        // only valid solutions are accounted (routing is tested in `hub::test`)
+        for block in crate::test_utils::TEST_BLOCKS.iter() {
+            let solution: work::Solution = block.into();
+            donation.account_solution(&solution).await;
+        }
+        let blocks = crate::test_utils::TEST_BLOCKS.len() as u64;
+        assert_eq!(
+            donation.valid_job_diff.take_snapshot().await.solutions,
+            blocks
+        );
+        assert_eq!(
+            donation.valid_backend_diff.take_snapshot().await.solutions,
+            blocks
+        );
+    }
+
+    #[test]
+    fn test_version_rolling_shares() {
+        let shares = VersionRollingShares::default();
+        shares.account(true);
+        shares.account(false);
+        shares.account(true);
+        assert_eq!(*shares.rolled.take_snapshot(), 2);
+        assert_eq!(*shares.base.take_snapshot(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_accepted_version_rolling() {
+        use crate::test_utils;
+
+        use std::sync::Arc;
+
+        let block = test_utils::TEST_BLOCKS[0];
+        let build_solution = |version| {
+            let midstate = work::Midstate {
+                version,
+                state: block.midstate,
+            };
+            let work = work::Assignment::new(Arc::new(block), vec![midstate], block.time);
+            work::Solution::new(work, test_utils::TestSolution::new(&block), None)
+        };
+        let base_solution = build_solution(block.version);
+        let rolled_solution =
+            build_solution(block.version ^ (1 << ii_bitcoin::BIP320_VERSION_SHIFT));
+        assert!(!base_solution.version_rolled());
+        assert!(rolled_solution.version_rolled());
+
+        // only the shares accepted by the pool are counted
+        let client_stats = BasicClient::default();
+        let now = time::Instant::now();
+        client_stats.account_accepted(&rolled_solution, now).await;
+        client_stats.account_accepted(&base_solution, now).await;
+        client_stats.account_accepted(&rolled_solution, now).await;
+        assert_eq!(*client_stats.version_rolling.rolled.take_snapshot(), 2);
+        assert_eq!(*client_stats.version_rolling.base.take_snapshot(), 1);
+        assert_eq!(client_stats.accepted.take_snapshot().await.solutions, 3);
+    }
+
+    #[test]
+    fn test_reject_reasons() {
+        use crate::client::rejection::RejectReason;
+
+        let reject_reasons = RejectReasons::default();
+        for reason in &[
+            RejectReason::Stale,
+            RejectReason::JobNotFound,
+            RejectReason::LowDifficulty,
+            RejectReason::LowDifficulty,
+            RejectReason::Duplicate,
+            RejectReason::Other("unknown".to_string()),
+        ] {
+            reject_reasons.account(reason);
+        }
+        assert_eq!(*reject_reasons.stale.take_snapshot(), 1);
+        assert_eq!(*reject_reasons.job_not_found.take_snapshot(), 1);
+        assert_eq!(*reject_reasons.low_difficulty.take_snapshot(), 2);
+        // rejections caused by staleness are not invalid
+        assert_eq!(reject_reasons.invalid(), 4);
+        assert_eq!(reject_reasons.invalid_hash(), 2);
+    }
+
+    #[test]
+    fn test_midstate_yield() {
+        let midstate_yield = MidstateYield::default();
+        assert!(midstate_yield.average().is_none());
+        for solved_midstates in &[4, 2, 0, 2] {
+            midstate_yield.account_work(*solved_midstates);
+        }
+        assert_eq!(*midstate_yield.work.take_snapshot(), 4);
+        assert_eq!(midstate_yield.average(), Some(2.0));
+    }
+
+    #[test]
+    fn test_expected_share_interval() {
+        // 2^32 hashes per second finds one share at difficulty 1 every second
+        let hashrate = ii_bitcoin::HashesUnit::Hashes(1 << 32);
+        assert_eq!(
+            super::expected_share_interval(hashrate, &Default::default()),
+            Some(time::Duration::from_secs(1))
+        );
+        let target = ii_bitcoin::Target::from_pool_difficulty(64);
+        assert_eq!(
+            super::expected_share_interval(hashrate, &target),
+            Some(time::Duration::from_secs(64))
+        );
+        assert!(
+            super::expected_share_interval(ii_bitcoin::HashesUnit::Hashes(0), &target).is_none()
+        );
+    }
+
+    #[test]
+    fn test_expected_shares() {
+        let hashrate = ii_bitcoin::HashesUnit::Hashes(1 << 32);
+        let window = time::Duration::from_secs(600);
+        let expected = super::expected_shares(hashrate, &Default::default(), window);
+        assert!((expected - 600.0).abs() < 1e-9, "unexpected {}", expected);
+
+        // 14 TH/s at difficulty 8192 within an hour
+        let hashrate = ii_bitcoin::HashesUnit::TeraHashes(14.0);
+        let target = ii_bitcoin::Target::from_pool_difficulty(8192);
+        let window = time::Duration::from_secs(3600);
+        let expected = super::expected_shares(hashrate, &target, window);
+        assert!(
+            (expected - 1432.454).abs() < 1e-3,
+            "unexpected {}",
+            expected
         );
+
+        assert_eq!(
+            super::expected_shares(ii_bitcoin::HashesUnit::Hashes(0), &target, window),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_share_period() {
+        let target = ii_bitcoin::Target::from_pool_difficulty(64);
+        // one share at difficulty 64 is expected every second
+        let hashrate = ii_bitcoin::HashesUnit::Hashes(64 << 32);
+        let start = time::Instant::now();
+        let mut period = SharePeriod::new(start, 100);
+
+        let now = start + time::Duration::from_secs(100);
+        let shares = period.close(now, 180, hashrate, &target);
+        assert_eq!(shares.accepted, 80);
+        assert!((shares.expected - 100.0).abs() < 1e-9);
+        assert!((shares.ratio().expect("BUG: undefined ratio") - 0.8).abs() < 1e-9);
+
+        // the next period starts where the previous one ended
+        assert_eq!(period.start(), now);
+        let shares = period.close(now + time::Duration::from_secs(50), 230, hashrate, &target);
+        assert_eq!(shares.accepted, 50);
+        assert!((shares.ratio().expect("BUG: undefined ratio") - 1.0).abs() < 1e-9);
+
+        // empty period has undefined ratio
+        let shares = period.close(period.start(), 230, hashrate, &target);
+        assert_eq!(shares.accepted, 0);
+        assert_eq!(shares.ratio(), None);
+    }
+
+    #[tokio::test]
+    async fn test_efficiency() {
+        // 1400 W at 14 TH/s corresponds to 0.1 J/GH
+        let efficiency = super::efficiency(1400.0, ii_bitcoin::HashesUnit::TeraHashes(14.0))
+            .expect("BUG: undefined efficiency");
+        assert!(
+            (efficiency - 0.1).abs() < 1e-9,
+            "unexpected efficiency {}",
+            efficiency
+        );
+
+        // the unit of hashrate does not matter
+        let efficiency = super::efficiency(90.0, ii_bitcoin::HashesUnit::Hashes(900_000_000_000))
+            .expect("BUG: undefined efficiency");
+        assert!(
+            (efficiency - 0.1).abs() < 1e-9,
+            "unexpected efficiency {}",
+            efficiency
+        );
+
+        // efficiency is undefined without hashrate or with invalid power
+        assert!(super::efficiency(100.0, ii_bitcoin::HashesUnit::Hashes(0)).is_none());
+        assert!(super::efficiency(f64::NAN, ii_bitcoin::HashesUnit::GigaHashes(1.0)).is_none());
+        assert!(super::efficiency(-1.0, ii_bitcoin::HashesUnit::GigaHashes(1.0)).is_none());
+
+        // efficiency of hashrate measured by meter
+        let interval = time::Duration::from_secs(5);
+        let meter = Meter::new(&vec![interval]);
+        let target = ii_bitcoin::Target::from_pool_difficulty(1000);
+        let now = time::Instant::now();
+        meter.account_solution(&target, now).await;
+        let snapshot = meter.take_snapshot().await;
+        let giga_hashes = ii_bitcoin::Shares::new(&target)
+            .into_giga_hashes()
+            .into_f64()
+            / interval.as_secs_f64();
+        let efficiency = snapshot
+            .efficiency(100.0, interval, now)
+            .expect("BUG: undefined efficiency");
+        assert!(
+            (efficiency - 100.0 / giga_hashes).abs() < 1e-6,
+            "unexpected efficiency {}",
+            efficiency
+        );
+    }
+
+    #[test]
+    fn test_cross_check() {
+        let cross_check = CrossCheck::default();
+        let offset = work::SolutionOffset { nonce: 1, ntime: 0 };
+
+        cross_check.account(work::CrossCheck::Valid);
+        cross_check.account(work::CrossCheck::Invalid);
+        cross_check.account(work::CrossCheck::Offset(offset));
+        let snapshot = cross_check.take_snapshot();
+        assert_eq!(snapshot.total(), 3);
+        // the offset is not present in majority of solutions
+        assert_eq!(snapshot.systematic_offset(), None);
+
+        // simulate hardware which is off-by-one in the nonce
+        for _ in 0..2 {
+            cross_check.account(work::CrossCheck::Offset(offset));
+        }
+        let snapshot = cross_check.take_snapshot();
+        assert_eq!(snapshot.offsets, vec![(offset, 3)]);
+        assert_eq!(snapshot.systematic_offset(), Some(offset));
+    }
+
+    #[tokio::test]
+    async fn test_warmup() {
+        let interval = time::Duration::from_secs(5);
+        let meter = Meter::new(&vec![interval]);
+        let target = ii_bitcoin::Target::from_pool_difficulty(1000);
+        let kilo_hashes = ii_bitcoin::Shares::new(&target)
+            .into_kilo_hashes()
+            .into_f64();
+        let now = time::Instant::now();
+        let warmup_end = now + time::Duration::from_secs(60);
+        meter.set_warmup_end(warmup_end);
+
+        // shares found during warm-up are not included in the hashrate
+        for _ in 0..10 {
+            meter.account_solution(&target, now).await;
+        }
+        let snapshot = meter.take_snapshot().await;
+        assert!(snapshot.is_warming_up(now));
+        assert_eq!(snapshot.warmup_end, Some(warmup_end));
+        assert_eq!(snapshot.solutions, 10);
+        assert_eq!(snapshot.to_kilo_hashes(interval, now).into_f64(), 0.0);
+
+        // but shares found after warm-up are
+        meter.account_solution(&target, warmup_end).await;
+        let snapshot = meter.take_snapshot().await;
+        assert!(!snapshot.is_warming_up(warmup_end));
+        assert_eq!(snapshot.solutions, 11);
+        assert_eq!(
+            snapshot.shares.as_f64(),
+            ii_bitcoin::Shares::new(&target).as_f64() * 11.0
+        );
+        let hashrate = snapshot.to_kilo_hashes(interval, warmup_end).into_f64();
+        assert!(
+            (hashrate - kilo_hashes / interval.as_secs_f64()).abs() < 1e-6,
+            "unexpected hashrate {}",
+            hashrate
+        );
+    }
+
+    #[test]
+    fn test_best_share() {
+        let best_share = BestShare::default();
+        assert!(best_share.take_snapshot().is_none());
+
+        // only a new maximum replaces the best share
+        for (difficulty, best, new_best) in [
+            (10, 10, true),
+            (100, 100, true),
+            (50, 100, false),
+            (100, 100, false),
+            (1000, 1000, true),
+            (1, 1000, false),
+        ]
+        .iter()
+        {
+            let target = ii_bitcoin::Target::from_pool_difficulty(*difficulty);
+            assert_eq!(
+                best_share.account_solution(&target, &Default::default()),
+                *new_best
+            );
+            assert_eq!(best_share.take_snapshot().map(|share| *share), Some(*best));
+        }
+
+        // share above difficulty 2^32 does not wrap
+        let target = ii_bitcoin::Target::from_pool_difficulty_f64(2f64.powi(40));
+        assert!(best_share.account_solution(&target, &Default::default()));
+        assert!(best_share.take_snapshot().map(|share| *share) > Some(1 << 32));
+    }
+
+    #[tokio::test]
+    async fn test_fractional_difficulty() {
+        let meter = Meter::new(&vec![time::Duration::from_secs(5)]);
+        let last_share = LastShare::default();
+        let now = time::Instant::now();
+        for difficulty in [0.5, 3.0, 16384.5].iter() {
+            let target = ii_bitcoin::Target::from_pool_difficulty_f64(*difficulty);
+            meter.account_solution(&target, now).await;
+            last_share
+                .account_solution(&target, &Default::default(), time::SystemTime::now())
+                .await;
+            let snapshot = last_share
+                .take_snapshot()
+                .await
+                .expect("BUG: no last share");
+            assert!((snapshot.difficulty - difficulty).abs() < 1e-6);
+        }
+
+        // shares of difficulty below 1 are not lost
+        let snapshot = meter.take_snapshot().await;
+        assert_eq!(snapshot.solutions, 3);
+        assert!((snapshot.shares.as_f64() - 16388.0).abs() < 1e-3);
+        assert_eq!(snapshot.shares.value(), 16388);
     }
 }
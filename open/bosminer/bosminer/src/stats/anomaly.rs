@@ -0,0 +1,255 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Detection of gradual decline of solution rate which usually signals degrading chips long
+//! before the hash chain stops producing solutions at all
+
+use std::time;
+
+/// Parameters of one-sided (lower) CUSUM detector
+#[derive(Debug, Clone)]
+pub struct RateDeclineConfig {
+    /// Number of initial samples used for estimation of the baseline rate
+    pub warmup_samples: usize,
+    /// Smoothing factor used for following slow changes of the baseline while the rate is stable
+    pub baseline_alpha: f64,
+    /// Relative decrease of the rate (against the baseline) which is tolerated as noise
+    pub slack: f64,
+    /// Accumulated relative deficit of the rate which raises the anomaly
+    pub threshold: f64,
+}
+
+impl Default for RateDeclineConfig {
+    fn default() -> Self {
+        Self {
+            warmup_samples: 10,
+            baseline_alpha: 0.01,
+            slack: 0.05,
+            threshold: 1.0,
+        }
+    }
+}
+
+/// Sustained decline of the rate reported by `RateDecline` detector
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateDeclineAnomaly {
+    /// Rate which is considered to be normal
+    pub baseline: f64,
+    /// Last sampled rate
+    pub rate: f64,
+}
+
+impl RateDeclineAnomaly {
+    /// Return current rate as percentage of the baseline rate
+    pub fn ratio(&self) -> f64 {
+        self.rate / self.baseline * 100.0
+    }
+}
+
+/// CUSUM detector over periodically sampled rate (e.g. solutions per second of one chain)
+///
+/// The baseline is estimated as a mean of the first samples and then it is slowly adjusted only
+/// while there is no accumulated deficit so that degrading hardware cannot drag the baseline down
+/// with itself. Every sample below the tolerated slack accumulates its relative deficit and the
+/// anomaly is raised when the sum exceeds the threshold. Samples above the slack pay the
+/// accumulated deficit back.
+#[derive(Debug, Clone)]
+pub struct RateDecline {
+    config: RateDeclineConfig,
+    /// Sum of samples and their count during warm-up
+    warmup: (f64, usize),
+    baseline: Option<f64>,
+    cusum: f64,
+    /// Anomaly is reported only once until the rate recovers
+    flagged: bool,
+}
+
+impl RateDecline {
+    pub fn new(config: RateDeclineConfig) -> Self {
+        assert!(config.warmup_samples > 0, "BUG: missing warm-up samples");
+        Self {
+            config,
+            warmup: (0.0, 0),
+            baseline: None,
+            cusum: 0.0,
+            flagged: false,
+        }
+    }
+
+    /// Forget learned baseline (e.g. after intentional change of frequency)
+    pub fn reset(&mut self) {
+        self.warmup = (0.0, 0);
+        self.baseline = None;
+        self.cusum = 0.0;
+        self.flagged = false;
+    }
+
+    #[inline]
+    pub fn baseline(&self) -> Option<f64> {
+        self.baseline
+    }
+
+    #[inline]
+    pub fn is_flagged(&self) -> bool {
+        self.flagged
+    }
+
+    /// Convenience method for computing the rate from the number of events counted during
+    /// sampling `interval`
+    pub fn account_count(
+        &mut self,
+        count: usize,
+        interval: time::Duration,
+    ) -> Option<RateDeclineAnomaly> {
+        self.account(count as f64 / interval.as_secs_f64())
+    }
+
+    /// Account a new sample of the rate and return the anomaly when the decline has just been
+    /// detected
+    pub fn account(&mut self, rate: f64) -> Option<RateDeclineAnomaly> {
+        let baseline = match self.baseline {
+            Some(baseline) => baseline,
+            None => {
+                self.warmup.0 += rate;
+                self.warmup.1 += 1;
+                if self.warmup.1 >= self.config.warmup_samples {
+                    self.baseline = Some(self.warmup.0 / self.warmup.1 as f64);
+                }
+                return None;
+            }
+        };
+        if baseline <= 0.0 {
+            // nothing to compare with, the hardware has not produced anything yet
+            self.baseline = Some(rate);
+            return None;
+        }
+
+        let deficit = (baseline * (1.0 - self.config.slack) - rate) / baseline;
+        self.cusum = (self.cusum + deficit).max(0.0);
+
+        if self.cusum == 0.0 {
+            // the rate is stable (or it has recovered)
+            self.baseline = Some(baseline + self.config.baseline_alpha * (rate - baseline));
+            self.flagged = false;
+        } else if !self.flagged && self.cusum > self.config.threshold {
+            self.flagged = true;
+            return Some(RateDeclineAnomaly { baseline, rate });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BASELINE: f64 = 100.0;
+
+    fn warm_up(detector: &mut RateDecline) {
+        for _ in 0..RateDeclineConfig::default().warmup_samples {
+            assert_eq!(detector.account(BASELINE), None);
+        }
+        assert_eq!(detector.baseline(), Some(BASELINE));
+    }
+
+    #[test]
+    fn test_gradual_decline() {
+        let mut detector = RateDecline::new(Default::default());
+        warm_up(&mut detector);
+
+        // the rate declines by 1% of the original baseline in every sample
+        // deficit is accumulated after crossing ~95% of the baseline (samples 6, 7, ...) and
+        // the sum ~0.01 + ~0.02 + ... exceeds the threshold 1.0 in the sample 19
+        let mut flagged_at = None;
+        for i in 1..50 {
+            let rate = BASELINE - i as f64;
+            if let Some(anomaly) = detector.account(rate) {
+                assert!(flagged_at.is_none(), "anomaly reported more than once");
+                // the baseline follows the rate just slightly before the decline is suspected
+                assert!(anomaly.baseline < BASELINE && anomaly.baseline > BASELINE - 1.0);
+                assert_eq!(anomaly.rate, rate);
+                flagged_at = Some(i);
+            }
+        }
+        assert_eq!(flagged_at, Some(19));
+        assert!(detector.is_flagged());
+
+        // recovery pays the accumulated deficit back and clears the flag
+        for _ in 0..300 {
+            assert_eq!(detector.account(BASELINE), None);
+        }
+        assert!(!detector.is_flagged());
+    }
+
+    #[test]
+    fn test_noisy_stable_rate() {
+        let mut detector = RateDecline::new(Default::default());
+        warm_up(&mut detector);
+
+        // fluctuations around the baseline must not raise the anomaly
+        for i in 0..1000 {
+            let rate = BASELINE + if i % 2 == 0 { 8.0 } else { -8.0 };
+            assert_eq!(detector.account(rate), None);
+        }
+        assert!(!detector.is_flagged());
+    }
+
+    #[test]
+    fn test_sudden_drop() {
+        let mut detector = RateDecline::new(Default::default());
+        warm_up(&mut detector);
+
+        // rate going to zero is flagged quickly
+        assert_eq!(detector.account(0.0), None);
+        assert!(detector.account(0.0).is_some());
+
+        detector.reset();
+        assert_eq!(detector.baseline(), None);
+        assert!(!detector.is_flagged());
+    }
+
+    #[test]
+    fn test_reset_after_retune() {
+        let mut detector = RateDecline::new(Default::default());
+        warm_up(&mut detector);
+
+        // permanently lower rate after retuning stays flagged without reset
+        let retuned_rate = BASELINE / 2.0;
+        assert_eq!(detector.account(retuned_rate), None);
+        assert_eq!(detector.account(retuned_rate), None);
+        assert!(detector.account(retuned_rate).is_some());
+        for _ in 0..100 {
+            assert_eq!(detector.account(retuned_rate), None);
+        }
+        assert!(detector.is_flagged());
+
+        // the detector is re-armed with the new baseline
+        detector.reset();
+        for _ in 0..RateDeclineConfig::default().warmup_samples {
+            assert_eq!(detector.account(retuned_rate), None);
+        }
+        assert_eq!(detector.baseline(), Some(retuned_rate));
+        assert_eq!(detector.account(retuned_rate), None);
+        assert_eq!(detector.account(0.0), None);
+        assert!(detector.account(0.0).is_some());
+    }
+}
@@ -0,0 +1,363 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Periodic sampling of aggregated mining statistics into a CSV time series suitable for offline
+//! analysis
+
+use ii_logging::macros::*;
+
+use crate::node;
+use crate::stats::UnixTime as _;
+
+use futures::future::BoxFuture;
+use ii_async_compat::{futures, tokio};
+use tokio::time::delay_for;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time;
+
+/// Closure providing current temperature of the miner (in degree Celsius) when it is known
+pub type TemperatureSource = Box<dyn Fn() -> Option<f32> + Send + Sync>;
+
+/// Closure providing current generation of work engines of all work solvers
+pub type GenerationSource = Box<dyn Fn() -> Vec<u64> + Send + Sync>;
+
+/// Closure estimating current efficiency of the miner (in J/GH) when its power is known
+pub type EfficiencySource = Box<dyn Fn() -> BoxFuture<'static, Option<f64>> + Send + Sync>;
+
+/// Describes where and how often the statistics should be sampled
+#[derive(Debug, Clone)]
+pub struct CsvConfig {
+    /// Path to a file where rows are appended
+    pub path: PathBuf,
+    /// Period between two consecutive rows
+    pub interval: time::Duration,
+}
+
+pub struct CsvSampler<W> {
+    /// Node whose statistics are sampled
+    node: node::DynInfo,
+    writer: W,
+    /// Interval used for calculation of hash rate (it has to be one of the time mean intervals)
+    hashrate_interval: time::Duration,
+    temperature_source: Option<TemperatureSource>,
+    generation_source: Option<GenerationSource>,
+    efficiency_source: Option<EfficiencySource>,
+}
+
+impl<W: Write> CsvSampler<W> {
+    /// Names of all columns in the exact order they are written into each row
+    pub const COLUMNS: &'static [&'static str] = &[
+        "timestamp",
+        "valid_network_solutions",
+        "valid_job_solutions",
+        "valid_backend_solutions",
+        "error_backend_solutions",
+        "valid_job_shares",
+        "best_share",
+        "hashrate_backend_ghs",
+        "hashrate_job_ghs",
+        "temperature",
+        "generations",
+        "efficiency_jgh",
+    ];
+
+    /// Create a sampler writing into `writer` and immediately write the header row
+    pub fn new(
+        node: node::DynInfo,
+        writer: W,
+        hashrate_interval: time::Duration,
+        temperature_source: Option<TemperatureSource>,
+    ) -> io::Result<Self> {
+        let mut sampler = Self::create(node, writer, hashrate_interval, temperature_source);
+        sampler.write_header()?;
+        Ok(sampler)
+    }
+
+    fn create(
+        node: node::DynInfo,
+        writer: W,
+        hashrate_interval: time::Duration,
+        temperature_source: Option<TemperatureSource>,
+    ) -> Self {
+        Self {
+            node,
+            writer,
+            hashrate_interval,
+            temperature_source,
+            generation_source: None,
+            efficiency_source: None,
+        }
+    }
+
+    /// Export generations of work solvers (separated by semicolon) in each row
+    pub fn with_generation_source(mut self, generation_source: GenerationSource) -> Self {
+        self.generation_source = Some(generation_source);
+        self
+    }
+
+    /// Export estimated efficiency of the miner in each row
+    pub fn with_efficiency_source(mut self, efficiency_source: EfficiencySource) -> Self {
+        self.efficiency_source = Some(efficiency_source);
+        self
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "{}", Self::COLUMNS.join(","))?;
+        self.writer.flush()
+    }
+
+    /// Take snapshot of all statistics and append it as a new row
+    pub async fn sample(&mut self) -> io::Result<()> {
+        let mining_stats = self.node.mining_stats();
+        let valid_network_diff = mining_stats.valid_network_diff().take_snapshot().await;
+        let valid_job_diff = mining_stats.valid_job_diff().take_snapshot().await;
+        let valid_backend_diff = mining_stats.valid_backend_diff().take_snapshot().await;
+        let error_backend_diff = mining_stats.error_backend_diff().take_snapshot().await;
+        let best_share = mining_stats
+            .best_share()
+            .take_snapshot()
+            .map(|difficulty| *difficulty)
+            .unwrap_or_default();
+
+        let now = time::Instant::now();
+        let timestamp = time::SystemTime::now()
+            .get_unix_time()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let temperature = self
+            .temperature_source
+            .as_ref()
+            .and_then(|source| source())
+            .map(|temperature| format!("{:.1}", temperature))
+            .unwrap_or_default();
+        let generations = self
+            .generation_source
+            .as_ref()
+            .map(|source| {
+                source()
+                    .iter()
+                    .map(|generation| generation.to_string())
+                    .collect::<Vec<_>>()
+                    .join(";")
+            })
+            .unwrap_or_default();
+        let efficiency = match self.efficiency_source.as_ref() {
+            Some(source) => source().await,
+            None => None,
+        }
+        .map(|efficiency| format!("{:.4}", efficiency))
+        .unwrap_or_default();
+
+        writeln!(
+            self.writer,
+            "{},{},{},{},{},{},{},{:.3},{:.3},{},{},{}",
+            timestamp,
+            valid_network_diff.solutions,
+            valid_job_diff.solutions,
+            valid_backend_diff.solutions,
+            error_backend_diff.solutions,
+            valid_job_diff.shares.value(),
+            best_share,
+            valid_backend_diff
+                .to_giga_hashes(self.hashrate_interval, now)
+                .into_f64(),
+            valid_job_diff
+                .to_giga_hashes(self.hashrate_interval, now)
+                .into_f64(),
+            temperature,
+            generations,
+            efficiency,
+        )?;
+        self.writer.flush()
+    }
+
+    /// Append a new row every `interval`
+    pub async fn run(mut self, interval: time::Duration) {
+        assert!(
+            interval > time::Duration::from_secs(0),
+            "BUG: zero CSV interval"
+        );
+        loop {
+            delay_for(interval).await;
+            if let Err(e) = self.sample().await {
+                error!("Cannot write statistics into CSV: {}", e);
+            }
+        }
+    }
+
+    /// Return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl CsvSampler<fs::File> {
+    /// Open file for appending and write the header only when the file is empty
+    pub fn open(
+        node: node::DynInfo,
+        path: &PathBuf,
+        hashrate_interval: time::Duration,
+        temperature_source: Option<TemperatureSource>,
+    ) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+
+        let mut sampler = Self::create(node, file, hashrate_interval, temperature_source);
+        if is_empty {
+            sampler.write_header()?;
+        }
+        Ok(sampler)
+    }
+}
+
+/// Sample statistics of `node` into the file described by `config`
+pub async fn csv_task(
+    node: node::DynInfo,
+    config: CsvConfig,
+    hashrate_interval: time::Duration,
+    temperature_source: Option<TemperatureSource>,
+    generation_source: Option<GenerationSource>,
+    efficiency_source: Option<EfficiencySource>,
+) {
+    match CsvSampler::open(node, &config.path, hashrate_interval, temperature_source) {
+        Ok(mut sampler) => {
+            if let Some(generation_source) = generation_source {
+                sampler = sampler.with_generation_source(generation_source);
+            }
+            if let Some(efficiency_source) = efficiency_source {
+                sampler = sampler.with_efficiency_source(efficiency_source);
+            }
+            sampler.run(config.interval).await
+        }
+        Err(e) => error!(
+            "Cannot open CSV file '{}' for statistics: {}",
+            config.path.display(),
+            e
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stats;
+    use crate::test_utils;
+    use crate::work;
+
+    use futures::FutureExt as _;
+
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    /// Writer whose content can be inspected while the sampler owns it
+    #[derive(Clone, Default)]
+    struct SharedWriter(Arc<StdMutex<Vec<u8>>>);
+
+    impl SharedWriter {
+        fn rows(&self) -> usize {
+            let content = self.0.lock().expect("BUG: cannot lock writer");
+            String::from_utf8_lossy(&content).lines().count()
+        }
+    }
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().expect("BUG: cannot lock writer").write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_csv_sampler() {
+        let node: node::DynInfo = Arc::new(test_utils::TestNode::new());
+        let mut sampler = CsvSampler::new(
+            node.clone(),
+            Vec::new(),
+            *stats::TIME_MEAN_INTERVAL_1M,
+            Some(Box::new(|| Some(75.0))),
+        )
+        .expect("BUG: cannot create CSV sampler")
+        .with_generation_source(Box::new(|| vec![3, 1]))
+        .with_efficiency_source(Box::new(|| Box::pin(async { Some(0.0985) })));
+
+        const SAMPLES: usize = 3;
+        for _ in 0..SAMPLES {
+            let solution: work::Solution = (&test_utils::TEST_BLOCKS[0]).into();
+            stats::account_valid_solution(
+                &vec![node.clone()],
+                &solution,
+                time::Instant::now(),
+                stats::DiffTargetType::Job,
+            )
+            .await;
+            sampler.sample().await.expect("BUG: cannot sample stats");
+        }
+
+        let csv = String::from_utf8(sampler.into_inner()).expect("BUG: invalid UTF-8");
+        let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+
+        // header and one row per sample
+        assert_eq!(rows.len(), SAMPLES + 1);
+        assert_eq!(rows[0], CsvSampler::<Vec<u8>>::COLUMNS);
+        for (i, row) in rows[1..].iter().enumerate() {
+            assert_eq!(row.len(), CsvSampler::<Vec<u8>>::COLUMNS.len());
+            // valid job solutions are accumulated
+            assert_eq!(row[2], (i + 1).to_string());
+            assert_eq!(row[9], "75.0");
+            assert_eq!(row[10], "3;1");
+            assert_eq!(row[11], "0.0985");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_csv_sampler_run() {
+        const INTERVAL: time::Duration = time::Duration::from_secs(10);
+
+        tokio::time::pause();
+        let writer = SharedWriter::default();
+        let node: node::DynInfo = Arc::new(test_utils::TestNode::new());
+        let sampler = CsvSampler::new(node, writer.clone(), *stats::TIME_MEAN_INTERVAL_1M, None)
+            .expect("BUG: cannot create CSV sampler");
+        let mut run = Box::pin(sampler.run(INTERVAL));
+
+        // only the header is written until the first interval elapses
+        assert_eq!((&mut run).now_or_never(), None);
+        assert_eq!(writer.rows(), 1);
+        tokio::time::advance(INTERVAL / 2).await;
+        assert_eq!((&mut run).now_or_never(), None);
+        assert_eq!(writer.rows(), 1);
+
+        // one row is appended per interval
+        for row in 2..4 {
+            tokio::time::advance(INTERVAL).await;
+            assert_eq!((&mut run).now_or_never(), None);
+            assert_eq!(writer.rows(), row);
+        }
+    }
+}
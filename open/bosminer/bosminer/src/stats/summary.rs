@@ -0,0 +1,531 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Periodic status line with mining statistics of each hash chain and their total similar to
+//! the one printed by cgminer
+
+use ii_logging::macros::*;
+
+use crate::node;
+use crate::stats;
+
+use ii_async_compat::tokio;
+use tokio::time::delay_for;
+
+use std::fmt;
+use std::sync::Arc;
+use std::time;
+
+/// Cumulative counters of a node taken at a particular instant
+#[derive(Debug, Clone)]
+pub struct SummarySnapshot {
+    pub time: time::Instant,
+    pub valid_solutions: u64,
+    pub valid_shares: u64,
+    pub error_solutions: u64,
+    /// Estimated power consumption (in watts) at the instant when it is known
+    pub power: Option<f64>,
+}
+
+impl SummarySnapshot {
+    pub async fn take<T: node::Stats + ?Sized>(node: &T) -> Self {
+        let mining_stats = node.mining_stats();
+        let valid_backend_diff = mining_stats.valid_backend_diff().take_snapshot().await;
+        let error_backend_diff = mining_stats.error_backend_diff().take_snapshot().await;
+        Self {
+            time: valid_backend_diff.snapshot_time,
+            valid_solutions: valid_backend_diff.solutions,
+            valid_shares: valid_backend_diff.shares.value(),
+            error_solutions: error_backend_diff.solutions,
+            power: None,
+        }
+    }
+
+    pub fn with_power(self, power: Option<f64>) -> Self {
+        Self { power, ..self }
+    }
+
+    /// Compute increments of all counters since the `previous` snapshot
+    pub fn diff(&self, previous: &Self) -> SummaryDelta {
+        SummaryDelta {
+            interval: self.time.saturating_duration_since(previous.time),
+            valid_solutions: self
+                .valid_solutions
+                .saturating_sub(previous.valid_solutions),
+            valid_shares: self.valid_shares.saturating_sub(previous.valid_shares),
+            error_solutions: self
+                .error_solutions
+                .saturating_sub(previous.error_solutions),
+            power: match (previous.power, self.power) {
+                (Some(previous_power), Some(power)) => Some((previous_power + power) / 2.0),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Increments of node counters within one logging interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryDelta {
+    pub interval: time::Duration,
+    pub valid_solutions: u64,
+    pub valid_shares: u64,
+    pub error_solutions: u64,
+    /// Mean power consumption (in watts) within the interval when it is known
+    pub power: Option<f64>,
+}
+
+impl SummaryDelta {
+    /// Hash rate derived from valid shares found within the interval
+    pub fn hashrate(&self) -> ii_bitcoin::HashesUnit {
+        ii_bitcoin::Shares::from(self.valid_shares).into_hashrate(self.interval)
+    }
+
+    pub fn with_power(self, power: f64) -> Self {
+        Self {
+            power: Some(power),
+            ..self
+        }
+    }
+
+    /// Precise hash rate (in H/s) used for comparison of intervals with different lengths
+    fn hashes_per_second(&self) -> Option<f64> {
+        let secs = self.interval.as_secs_f64();
+        if secs > 0.0 {
+            Some(
+                ii_bitcoin::Shares::from(self.valid_shares)
+                    .into_hashes()
+                    .into_f64()
+                    / secs,
+            )
+        } else {
+            None
+        }
+    }
+
+    /// Fraction of erroneous solutions from all solutions found within the interval
+    fn error_rate(&self) -> Option<f64> {
+        let total = self.valid_solutions + self.error_solutions;
+        if total > 0 {
+            Some(self.error_solutions as f64 / total as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Energy (in J/GH) spent within the interval
+    fn efficiency(&self) -> Option<f64> {
+        let hashes_per_second = self.hashes_per_second()?;
+        stats::efficiency(
+            self.power?,
+            ii_bitcoin::HashesUnit::Hashes(hashes_per_second as u128),
+        )
+    }
+
+    /// Compare this interval with `baseline` interval (e.g. measured before a tuning change) and
+    /// report in which direction each metric moved from the `baseline` to this interval
+    pub fn report_delta(&self, baseline: &Self) -> DeltaReport {
+        DeltaReport {
+            hashrate: MetricDelta::compare(
+                "hash rate",
+                baseline.hashes_per_second(),
+                self.hashes_per_second(),
+                true,
+            ),
+            error_rate: MetricDelta::compare(
+                "error rate",
+                baseline.error_rate(),
+                self.error_rate(),
+                false,
+            ),
+            efficiency: MetricDelta::compare(
+                "efficiency",
+                baseline.efficiency(),
+                self.efficiency(),
+                false,
+            ),
+        }
+    }
+}
+
+impl fmt::Display for SummaryDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({}s) {}, valid: {} ({} shares), errors: {}",
+            self.interval.as_secs(),
+            ii_bitcoin::format_hashrate_auto(self.hashrate().into_hashes().into_f64()),
+            self.valid_solutions,
+            self.valid_shares,
+            self.error_solutions
+        )
+    }
+}
+
+/// Direction in which a metric moved compared to the baseline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    Improved,
+    Regressed,
+    Unchanged,
+    /// The metric cannot be computed for one of the intervals
+    Unknown,
+}
+
+impl fmt::Display for Trend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Improved => "improved",
+            Self::Regressed => "regressed",
+            Self::Unchanged => "unchanged",
+            Self::Unknown => "unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Change of a single metric between baseline and current interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricDelta {
+    pub name: &'static str,
+    pub trend: Trend,
+    /// Relative change of the metric value (`0.1` means the value is higher by 10 %)
+    pub change: Option<f64>,
+}
+
+impl MetricDelta {
+    /// Relative changes smaller than this are treated as measurement noise
+    pub const TOLERANCE: f64 = 0.01;
+
+    fn compare(
+        name: &'static str,
+        baseline: Option<f64>,
+        current: Option<f64>,
+        higher_is_better: bool,
+    ) -> Self {
+        let (trend, change) = match (baseline, current) {
+            (Some(baseline), Some(current)) => {
+                let difference = current - baseline;
+                let change = if baseline != 0.0 {
+                    Some(difference / baseline)
+                } else {
+                    None
+                };
+                let trend = if difference.abs() <= baseline.abs() * Self::TOLERANCE {
+                    Trend::Unchanged
+                } else if (difference > 0.0) == higher_is_better {
+                    Trend::Improved
+                } else {
+                    Trend::Regressed
+                };
+                (trend, change)
+            }
+            _ => (Trend::Unknown, None),
+        };
+        Self {
+            name,
+            trend,
+            change,
+        }
+    }
+}
+
+impl fmt::Display for MetricDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.trend)?;
+        match self.change {
+            Some(change) if self.trend != Trend::Unchanged => {
+                write!(f, " ({:+.1} %)", change * 100.0)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Result of A/B comparison of two intervals used for deciding whether a tuning change helped
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeltaReport {
+    pub hashrate: MetricDelta,
+    pub error_rate: MetricDelta,
+    pub efficiency: MetricDelta,
+}
+
+impl DeltaReport {
+    /// At least one metric got worse
+    pub fn has_regression(&self) -> bool {
+        self.metrics()
+            .any(|metric| metric.trend == Trend::Regressed)
+    }
+
+    fn metrics(&self) -> impl Iterator<Item = &MetricDelta> {
+        vec![&self.hashrate, &self.error_rate, &self.efficiency].into_iter()
+    }
+}
+
+impl fmt::Display for DeltaReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metrics: Vec<_> = self.metrics().map(|metric| metric.to_string()).collect();
+        write!(f, "{}", metrics.join(", "))
+    }
+}
+
+pub struct SummaryLogger {
+    /// Work solvers of individual hash chains
+    chains: Vec<Arc<dyn node::WorkSolver>>,
+    /// Node with merged statistics of all hash chains
+    total: node::DynInfo,
+    /// Snapshots from previous round in the order of chains followed by total
+    last_snapshots: Vec<SummarySnapshot>,
+}
+
+impl SummaryLogger {
+    /// Create logger and take the initial snapshots which the first interval is counted from
+    pub async fn new(chains: Vec<Arc<dyn node::WorkSolver>>, total: node::DynInfo) -> Self {
+        let mut logger = Self {
+            chains,
+            total,
+            last_snapshots: vec![],
+        };
+        logger.last_snapshots = logger.take_snapshots().await;
+        logger
+    }
+
+    fn node_names(&self) -> Vec<String> {
+        self.chains
+            .iter()
+            .map(|chain| chain.to_string())
+            .chain(Some(self.total.to_string()))
+            .collect()
+    }
+
+    /// Take snapshots of all chains followed by the total. Power of the total is known only when
+    /// all chains provide their estimate.
+    async fn take_snapshots(&self) -> Vec<SummarySnapshot> {
+        let mut snapshots = Vec::with_capacity(self.chains.len() + 1);
+        let mut total_power = if self.chains.is_empty() {
+            None
+        } else {
+            Some(0.0)
+        };
+        for chain in self.chains.iter() {
+            let power = chain.get_estimated_power().await;
+            total_power = total_power.and_then(|total_power| Some(total_power + power?));
+            snapshots.push(SummarySnapshot::take(&**chain).await.with_power(power));
+        }
+        snapshots.push(
+            SummarySnapshot::take(&*self.total)
+                .await
+                .with_power(total_power),
+        );
+        snapshots
+    }
+
+    /// Return increments of all chains followed by the total since the previous call
+    pub async fn sample(&mut self) -> Vec<SummaryDelta> {
+        let snapshots = self.take_snapshots().await;
+        let deltas = snapshots
+            .iter()
+            .zip(self.last_snapshots.iter())
+            .map(|(snapshot, previous)| snapshot.diff(previous))
+            .collect();
+        self.last_snapshots = snapshots;
+        deltas
+    }
+
+    /// Sample statistics every `interval` and pass formatted lines to `emit`
+    async fn run_with<F: FnMut(String)>(mut self, interval: time::Duration, mut emit: F) {
+        loop {
+            delay_for(interval).await;
+            let deltas = self.sample().await;
+            for (node, delta) in self.node_names().iter().zip(deltas.iter()) {
+                emit(format!("{}: {}", node, delta));
+            }
+        }
+    }
+
+    /// Log status line of each chain and the total every `interval`
+    pub async fn run(self, interval: time::Duration) {
+        self.run_with(interval, |line| info!("{}", line)).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stats;
+    use crate::test_utils;
+    use crate::work;
+
+    async fn account_solutions(path: &node::Path, count: usize) {
+        for _ in 0..count {
+            let solution: work::Solution = (&test_utils::TEST_BLOCKS[0]).into();
+            stats::account_valid_solution(
+                path,
+                &solution,
+                time::Instant::now(),
+                stats::DiffTargetType::Backend,
+            )
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_deltas() {
+        let total: node::DynInfo = Arc::new(test_utils::TestNode::new());
+        let work_solvers: Vec<Arc<dyn node::WorkSolver>> = (0..2)
+            .map(|_| Arc::new(test_utils::TestWorkSolver::new()) as Arc<dyn node::WorkSolver>)
+            .collect();
+        let chains: Vec<node::DynInfo> = work_solvers
+            .iter()
+            .map(|work_solver| Arc::new(work_solver.clone()) as node::DynInfo)
+            .collect();
+        // solutions found before the logger is started are not reported
+        account_solutions(&vec![total.clone(), chains[0].clone()], 5).await;
+        let mut logger = SummaryLogger::new(work_solvers, total.clone()).await;
+
+        account_solutions(&vec![total.clone(), chains[0].clone()], 2).await;
+        account_solutions(&vec![total.clone(), chains[1].clone()], 1).await;
+        let valid_solutions: Vec<_> = logger
+            .sample()
+            .await
+            .iter()
+            .map(|delta| delta.valid_solutions)
+            .collect();
+        assert_eq!(valid_solutions, vec![2, 1, 3]);
+
+        // next interval contains only new solutions
+        account_solutions(&vec![total.clone(), chains[1].clone()], 4).await;
+        let deltas = logger.sample().await;
+        let valid_solutions: Vec<_> = deltas.iter().map(|delta| delta.valid_solutions).collect();
+        assert_eq!(valid_solutions, vec![0, 4, 4]);
+        assert_eq!(deltas[2].error_solutions, 0);
+        assert_eq!(deltas[1].valid_shares, deltas[2].valid_shares);
+    }
+
+    #[tokio::test]
+    async fn test_summary_power() {
+        let total: node::DynInfo = Arc::new(test_utils::TestNode::new());
+        let work_solvers: Vec<Arc<dyn node::WorkSolver>> = vec![
+            Arc::new(test_utils::TestWorkSolver::with_power(100.0)),
+            Arc::new(test_utils::TestWorkSolver::with_power(200.0)),
+        ];
+        let mut logger = SummaryLogger::new(work_solvers.clone(), total.clone()).await;
+        let power: Vec<_> = logger
+            .sample()
+            .await
+            .iter()
+            .map(|delta| delta.power)
+            .collect();
+        assert_eq!(power, vec![Some(100.0), Some(200.0), Some(300.0)]);
+
+        // total power is unknown when some chain does not provide its estimate
+        let work_solvers = vec![
+            work_solvers[0].clone(),
+            Arc::new(test_utils::TestWorkSolver::new()) as Arc<dyn node::WorkSolver>,
+        ];
+        let mut logger = SummaryLogger::new(work_solvers, total).await;
+        let power: Vec<_> = logger
+            .sample()
+            .await
+            .iter()
+            .map(|delta| delta.power)
+            .collect();
+        assert_eq!(power, vec![Some(100.0), None, None]);
+    }
+
+    fn build_delta(valid_shares: u64, valid: u64, errors: u64, power: f64) -> SummaryDelta {
+        SummaryDelta {
+            interval: time::Duration::from_secs(60),
+            valid_solutions: valid,
+            valid_shares,
+            error_solutions: errors,
+            power: None,
+        }
+        .with_power(power)
+    }
+
+    #[test]
+    fn test_report_delta() {
+        let baseline = build_delta(1000, 1000, 10, 1200.0);
+
+        // higher hashrate with the same power and less errors
+        let report = build_delta(1100, 1100, 5, 1200.0).report_delta(&baseline);
+        assert_eq!(report.hashrate.trend, Trend::Improved);
+        assert!((report.hashrate.change.expect("BUG: missing change") - 0.1).abs() < 1e-9);
+        assert_eq!(report.error_rate.trend, Trend::Improved);
+        assert_eq!(report.efficiency.trend, Trend::Improved);
+        assert!(!report.has_regression());
+
+        // overclocking raises hashrate at the expense of errors and power
+        let report = build_delta(1050, 1050, 40, 1400.0).report_delta(&baseline);
+        assert_eq!(report.hashrate.trend, Trend::Improved);
+        assert_eq!(report.error_rate.trend, Trend::Regressed);
+        assert_eq!(report.efficiency.trend, Trend::Regressed);
+        assert!(report.has_regression());
+        assert!(report
+            .to_string()
+            .starts_with("hash rate improved (+5.0 %)"));
+
+        // the metrics move from the baseline to the receiver
+        let report = baseline.report_delta(&build_delta(1050, 1050, 40, 1400.0));
+        assert_eq!(report.hashrate.trend, Trend::Regressed);
+        assert_eq!(report.error_rate.trend, Trend::Improved);
+        assert_eq!(report.efficiency.trend, Trend::Improved);
+
+        // small differences are within tolerance
+        let report = build_delta(1005, 1000, 10, 1200.0).report_delta(&baseline);
+        assert_eq!(report.hashrate.trend, Trend::Unchanged);
+        assert_eq!(report.error_rate.trend, Trend::Unchanged);
+
+        // efficiency cannot be compared without power
+        let mut current = build_delta(900, 900, 10, 1200.0);
+        current.power = None;
+        let report = current.report_delta(&baseline);
+        assert_eq!(report.hashrate.trend, Trend::Regressed);
+        assert_eq!(report.efficiency.trend, Trend::Unknown);
+    }
+
+    #[tokio::test]
+    async fn test_summary_interval() {
+        let interval = time::Duration::from_millis(100);
+        let total: node::DynInfo = Arc::new(test_utils::TestNode::new());
+        let chain: node::DynInfo = Arc::new(test_utils::TestWorkSolver::new());
+        let logger = SummaryLogger::new(vec![chain], total).await;
+
+        let start = time::Instant::now();
+        let mut emitted = vec![];
+        // wait for three rounds with some reserve
+        let _ = tokio::time::timeout(
+            interval * 3 + interval / 2,
+            logger.run_with(interval, |line| emitted.push((time::Instant::now(), line))),
+        )
+        .await;
+
+        // one line for the chain and one for the total in each round
+        assert_eq!(emitted.len(), 3 * 2);
+        for (round, lines) in emitted.chunks(2).enumerate() {
+            assert!(lines[0].0.duration_since(start) >= interval * (round as u32 + 1));
+            assert!(lines[0].1.starts_with("Test work solver: "));
+            assert!(lines[1].1.starts_with("Test generic node: "));
+        }
+    }
+}
@@ -29,6 +29,7 @@ use crate::stats;
 use crate::sync;
 use crate::work;
 
+use ii_bitcoin::HashTrait as _;
 pub use ii_bitcoin::{TestBlock, TEST_BLOCKS};
 
 use bosminer_macros::{ClientNode, MiningNode, WorkSolverNode};
@@ -103,12 +104,22 @@ impl fmt::Display for TestClient {
 pub struct TestWorkSolver {
     #[member_work_solver_stats]
     work_solver_stats: stats::BasicWorkSolver,
+    power: Option<f64>,
 }
 
 impl TestWorkSolver {
     pub fn new() -> Self {
         Self {
             work_solver_stats: Default::default(),
+            power: None,
+        }
+    }
+
+    /// Work solver reporting constant estimated power (in watts)
+    pub fn with_power(power: f64) -> Self {
+        Self {
+            work_solver_stats: Default::default(),
+            power: Some(power),
         }
     }
 }
@@ -118,6 +129,10 @@ impl node::WorkSolver for TestWorkSolver {
     async fn get_nominal_hashrate(&self) -> Option<ii_bitcoin::HashesUnit> {
         None
     }
+
+    async fn get_estimated_power(&self) -> Option<f64> {
+        self.power
+    }
 }
 
 impl fmt::Display for TestWorkSolver {
@@ -136,7 +151,7 @@ impl job::Bitcoin for TestBlock {
     }
 
     fn version_mask(&self) -> u32 {
-        0
+        ii_bitcoin::BIP320_VERSION_MASK
     }
 
     fn previous_hash(&self) -> &ii_bitcoin::DHash {
@@ -178,8 +193,69 @@ impl TestBlockBuilder for TestBlock {
     }
 }
 
+/// Job with test block which allows rolling of the extranonce. Only `EXTRANONCE` yields merkle
+/// root of the original block so the block remains a valid solution for this extranonce.
+#[derive(Debug, Clone, Copy)]
+pub struct TestExtranonceJob(pub TestBlock);
+
+impl TestExtranonceJob {
+    pub const EXTRANONCE: u64 = 5;
+    pub const EXTRANONCE_SIZE: usize = 1;
+}
+
+impl job::Bitcoin for TestExtranonceJob {
+    fn origin(&self) -> Weak<dyn node::Client> {
+        self.0.origin()
+    }
+
+    fn version(&self) -> u32 {
+        self.0.version()
+    }
+
+    fn version_mask(&self) -> u32 {
+        self.0.version_mask()
+    }
+
+    fn previous_hash(&self) -> &ii_bitcoin::DHash {
+        self.0.previous_hash()
+    }
+
+    fn merkle_root(&self) -> &ii_bitcoin::DHash {
+        self.0.merkle_root()
+    }
+
+    fn time(&self) -> u32 {
+        self.0.time()
+    }
+
+    fn bits(&self) -> u32 {
+        self.0.bits()
+    }
+
+    fn target(&self) -> ii_bitcoin::Target {
+        self.0.target()
+    }
+
+    fn is_valid(&self) -> bool {
+        self.0.is_valid()
+    }
+
+    fn extranonce_size(&self) -> usize {
+        Self::EXTRANONCE_SIZE
+    }
+
+    fn extranonce_merkle_root(&self, extranonce: u64) -> ii_bitcoin::DHash {
+        if extranonce == Self::EXTRANONCE {
+            return *self.merkle_root();
+        }
+        let mut data = self.merkle_root().into_inner().to_vec();
+        data.extend_from_slice(&extranonce.to_le_bytes());
+        ii_bitcoin::DHash::hash(&data)
+    }
+}
+
 #[derive(Debug)]
-struct TestSolution {
+pub struct TestSolution {
     test_block: TestBlock,
     target: ii_bitcoin::Target,
 }
@@ -24,6 +24,82 @@ use git_version::git_version;
 
 use once_cell::sync::Lazy;
 
+use std::env;
+use std::fmt;
+
 // TODO: create special proc-macro which returns constant string
 pub static STRING: Lazy<String> =
     Lazy::new(|| format!(concat!(env!("CARGO_PKG_VERSION"), "-{}"), git_version!()));
+
+/// Version of the crate and information about the build of the running binary
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Git revision as described by `git describe` (with `-modified` suffix for dirty tree)
+    pub git_hash: &'static str,
+    /// Cargo profile which has been used for the build
+    pub profile: &'static str,
+    /// Architecture and operating system the binary has been built for
+    pub target: String,
+    /// Names of enabled compile-time features
+    pub features: Vec<&'static str>,
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, {} build for {}",
+            self.version, self.git_hash, self.profile, self.target
+        )?;
+        if !self.features.is_empty() {
+            write!(f, " with {}", self.features.join(", "))?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Optional compile-time features which affect behaviour of the binary
+const FEATURES: &[(&str, bool)] = &[("debug-assertions", cfg!(debug_assertions))];
+
+/// Return information about the running binary populated at build time
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: git_version!(),
+        profile: if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+        target: format!("{}-{}", env::consts::ARCH, env::consts::OS),
+        features: FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_info() {
+        let info = build_info();
+        assert!(!info.version.is_empty());
+        assert!(!info.git_hash.is_empty());
+        assert!(!info.profile.is_empty());
+        assert!(!info.target.is_empty());
+        // the information has to be consistent with version string
+        assert_eq!(*STRING, format!("{}-{}", info.version, info.git_hash));
+
+        assert_eq!(
+            info.features.contains(&"debug-assertions"),
+            cfg!(debug_assertions)
+        );
+        assert!(info.features.iter().all(|feature| !feature.is_empty()));
+        assert!(info.to_string().starts_with(info.version));
+    }
+}
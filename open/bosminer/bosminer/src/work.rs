@@ -24,27 +24,52 @@
 //! to the actual work solving (mining) backends
 
 pub mod engine;
+pub mod replay;
 mod solver;
+mod verification;
 
+use crate::client;
 use crate::hal;
 use crate::job;
 use crate::node;
 
-use ii_bitcoin::HashTrait as _;
+use ii_bitcoin::{HashTrait as _, MeetsTarget};
 
-pub use solver::{Generator, SolutionSender, SolverBuilder};
+pub use solver::{
+    Distribution, GenerationCause, Generator, HashrateCap, InflightWork, JobBarrier, LiveJob,
+    MidstateCount, SolutionSender, SolverBuilder, SolverSwitch, WorkGeneration,
+};
+pub use verification::VerificationCache;
 
 use ii_async_compat::prelude::*;
 use tokio::sync::watch;
 
+use failure::Fail;
 use once_cell::sync::OnceCell;
 
+use std::convert::TryInto;
 use std::fmt::{self, Debug};
 use std::iter;
 use std::mem;
 use std::sync::{Arc, Mutex as StdMutex, MutexGuard as StdMutexGuard, Weak};
 use std::time;
 
+/// Maximal distance (in seconds) from nTime of work searched by `Solution::recover_time`. Each
+/// candidate costs a double hash on the solution path so the search has to stay short.
+pub const NTIME_RECOVERY_WINDOW: u32 = 16;
+
+/// Number of recently verified solutions whose hashes are remembered by each work solver
+const VERIFICATION_CACHE_SIZE: usize = 256;
+
+/// Return the latest nTime acceptable for work with `work_ntime` generated from `job` when nTime
+/// is rolled by at most `max_ntime_roll` seconds
+#[inline]
+fn max_rolled_time(job: &dyn job::Bitcoin, work_ntime: u32, max_ntime_roll: u32) -> u32 {
+    job.max_time()
+        .max(work_ntime)
+        .min(job.time().saturating_add(max_ntime_roll))
+}
+
 pub enum LoopState<T> {
     /// Mining work is exhausted
     Exhausted,
@@ -83,6 +108,50 @@ pub struct Midstate {
     pub state: ii_bitcoin::Midstate,
 }
 
+/// Number of nonces the hardware tries for a single midstate
+pub const NONCE_SPACE_SIZE: u64 = 1 << 32;
+
+/// Time it takes to exhaust the whole nonce space of work with `midstate_count` midstates at
+/// `hashrate` (in hashes per second). When it is shorter than the time between block changes,
+/// the work has to be varied more (by nTime rolling or by more midstates) to keep the hardware
+/// busy. Returns `None` when there is no hashrate.
+pub fn nonce_space_exhaustion_time(hashrate: f64, midstate_count: usize) -> Option<time::Duration> {
+    if hashrate <= 0.0 || hashrate.is_nan() {
+        return None;
+    }
+    Some(time::Duration::from_secs_f64(
+        (NONCE_SPACE_SIZE * midstate_count as u64) as f64 / hashrate,
+    ))
+}
+
+/// Range of extranonce values handed off to hardware which rolls extranonce on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtranonceRange {
+    /// The first extranonce of the range (midstates of the work are computed for this value)
+    pub start: u64,
+    /// Number of extranonce values in the range
+    pub count: u32,
+}
+
+impl ExtranonceRange {
+    /// Return the first extranonce which does not belong to the range
+    #[inline]
+    pub fn end(&self) -> u64 {
+        self.start + self.count as u64
+    }
+
+    #[inline]
+    pub fn contains(&self, extranonce: u64) -> bool {
+        extranonce >= self.start && extranonce < self.end()
+    }
+}
+
+impl fmt::Display for ExtranonceRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{:#x}, {:#x})", self.start, self.end())
+    }
+}
+
 /// Describes actual mining work for assignment to a hashing hardware.
 /// Starting with merkle_root_tail the data goes to chunk2 of SHA256.
 #[derive(Clone, Debug)]
@@ -95,6 +164,17 @@ pub struct Assignment {
     pub midstates: Vec<Midstate>,
     /// nTime value for current work
     pub ntime: u32,
+    /// Range of extranonce values rolled by the hardware (`None` when the coinbase is fixed)
+    pub extranonce: Option<ExtranonceRange>,
+    /// Id of the work solver the work has been generated for (see `node::WorkSolver::get_id`)
+    pub solver_id: Option<usize>,
+    /// Generation of work engines of the work solver at the time the work has been generated.
+    /// It is comparable only with `WorkGeneration` of the same work solver.
+    pub generation: Option<u64>,
+    /// Maximal number of seconds the nTime can be rolled forward from the job time. The cap of
+    /// the engine which has generated the work applies also to its solutions (see
+    /// `engine::VersionRolling::with_ntime_roll`).
+    pub max_ntime_roll: u32,
 }
 
 impl Assignment {
@@ -104,6 +184,10 @@ impl Assignment {
             job,
             midstates,
             ntime,
+            extranonce: None,
+            solver_id: None,
+            generation: None,
+            max_ntime_roll: std::u32::MAX,
         }
     }
 
@@ -114,9 +198,34 @@ impl Assignment {
     }
 
     /// Return merkle root tail
-    #[inline]
+    /// The work with extranonce range uses merkle root of the first extranonce in the range.
     pub fn merkle_root_tail(&self) -> u32 {
-        self.job.merkle_root_tail()
+        match self.extranonce {
+            None => self.job.merkle_root_tail(),
+            Some(range) => Self::tail(&self.job.extranonce_merkle_root(range.start)),
+        }
+    }
+
+    /// Extract least-significant word of merkle root as a little endian number
+    fn tail(merkle_root: &ii_bitcoin::DHash) -> u32 {
+        let merkle_root = merkle_root.into_inner();
+        u32::from_le_bytes(
+            merkle_root[merkle_root.len() - mem::size_of::<u32>()..]
+                .try_into()
+                .expect("BUG: slice with incorrect length"),
+        )
+    }
+
+    /// Return merkle root tail as a little endian word (see `job::Bitcoin::merkle_root_tail_le`)
+    #[inline]
+    pub fn merkle_root_tail_le(&self) -> u32 {
+        self.merkle_root_tail()
+    }
+
+    /// Return merkle root tail as a big endian word (see `job::Bitcoin::merkle_root_tail_be`)
+    #[inline]
+    pub fn merkle_root_tail_be(&self) -> u32 {
+        self.merkle_root_tail().swap_bytes()
     }
 
     /// Return current target (nBits)
@@ -126,12 +235,57 @@ impl Assignment {
     }
 
     /// Return number of generated work associated within this work assignment
+    /// Each extranonce rolled by the hardware multiplies the work covered by the midstates.
     #[inline]
     pub fn generated_work_amount(&self) -> usize {
-        self.midstates.len()
+        let extranonce_count = self.extranonce.map_or(1, |range| range.count as usize);
+        self.midstates.len() * extranonce_count
+    }
+
+    /// Time it takes to exhaust nonce space of this work at `hashrate` (see
+    /// `nonce_space_exhaustion_time`)
+    #[inline]
+    pub fn exhaustion_time(&self, hashrate: f64) -> Option<time::Duration> {
+        nonce_space_exhaustion_time(hashrate, self.generated_work_amount())
+    }
+
+    /// Check that the work does not waste hashing capacity before it is dispatched to hardware.
+    /// All midstates have to be distinct and their versions may differ from the job version
+    /// only in the bits allowed to be rolled by the job version mask.
+    pub fn validate(&self) -> Result<(), WorkError> {
+        let job_version = self.job.version();
+        let version_mask = self.job.version_mask();
+        for (i, midstate) in self.midstates.iter().enumerate() {
+            if (midstate.version ^ job_version) & !version_mask != 0 {
+                Err(WorkError::VersionOutOfMask(midstate.version))?
+            }
+            if self.midstates[..i]
+                .iter()
+                .any(|other| other.state == midstate.state)
+            {
+                Err(WorkError::DuplicateMidstate(midstate.version))?
+            }
+        }
+        Ok(())
     }
 }
 
+/// Defect of generated work found by `Assignment::validate`
+#[derive(Clone, Eq, PartialEq, Debug, Fail)]
+pub enum WorkError {
+    #[fail(display = "duplicate midstate for version {:#010x}", _0)]
+    DuplicateMidstate(u32),
+    #[fail(display = "version {:#010x} is outside of the rolling mask", _0)]
+    VersionOutOfMask(u32),
+}
+
+/// Final response of the remote server to the submitted solution
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubmitOutcome {
+    Accepted,
+    Rejected(client::rejection::RejectReason),
+}
+
 /// Container with mining work and a corresponding solution received at a particular time
 /// This data structure is used when posting work+solution pairs for further submission upstream.
 #[derive(Clone)]
@@ -142,12 +296,28 @@ pub struct Solution {
     work: Assignment,
     /// Solution of the PoW puzzle
     solution: Arc<dyn hal::BackendSolution>,
+    /// Lazy evaluated nTime which has been actually used by the hardware for this solution
+    ntime: OnceCell<u32>,
     /// Lazy evaluated double hash of this solution
     hash: OnceCell<ii_bitcoin::DHash>,
     /// Lazy evaluated job target to ensure that the value is stable for this solution
     job_target: OnceCell<ii_bitcoin::Target>,
     /// Lazy evaluated backend target to ensure that the value is stable for this solution
     backend_target: OnceCell<ii_bitcoin::Target>,
+    /// Flag that nTime of this solution has been already adjusted for resubmission
+    time_adjusted: bool,
+    /// Flag that this is a copy of block candidate relayed to other client than its origin
+    relayed: bool,
+    /// Flag that the solution has been found on a job of a donation pool
+    donation: bool,
+    /// Submission outcome shared by all copies of this solution (including resubmitted ones)
+    outcome: Arc<OnceCell<SubmitOutcome>>,
+    /// Hashes of solutions recently verified for the work solver which has found this solution
+    verification_cache: Option<Arc<StdMutex<VerificationCache>>>,
+    /// Flag that the work solver had already switched to newer work when it sent this solution
+    stale: bool,
+    /// Flag that the solution is verified starting from the solving midstate
+    midstate_verification: bool,
 }
 
 impl Solution {
@@ -160,12 +330,54 @@ impl Solution {
             timestamp: timestamp.unwrap_or_else(|| time::Instant::now()),
             work,
             solution: Arc::new(solution),
+            ntime: OnceCell::new(),
             hash: OnceCell::new(),
             backend_target: OnceCell::new(),
             job_target: OnceCell::new(),
+            time_adjusted: false,
+            relayed: false,
+            donation: false,
+            outcome: Arc::new(OnceCell::new()),
+            verification_cache: None,
+            stale: false,
+            midstate_verification: false,
         }
     }
 
+    /// Attach cache of recently verified solutions of the work solver which has found this
+    /// solution (see `SolutionSender::send`)
+    pub(crate) fn with_verification_cache(
+        self,
+        verification_cache: Arc<StdMutex<VerificationCache>>,
+    ) -> Self {
+        Self {
+            verification_cache: Some(verification_cache),
+            ..self
+        }
+    }
+
+    /// Classify the solution as stale (see `SolutionSender::send`)
+    pub(crate) fn with_stale(self, stale: bool) -> Self {
+        Self { stale, ..self }
+    }
+
+    /// Verify the solution by computing only the second chunk of block header on top of the
+    /// solving midstate instead of the whole double hash. It relies on midstates being computed
+    /// correctly by the work engine which is true for all work generated by the miner itself.
+    pub(crate) fn with_midstate_verification(self, midstate_verification: bool) -> Self {
+        Self {
+            midstate_verification,
+            ..self
+        }
+    }
+
+    /// Check if the solution has been found on work superseded by a newer job of the same work
+    /// solver
+    #[inline]
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
     /// Return origin from which the work has been generated
     #[inline]
     pub fn origin(&self) -> Weak<dyn node::Client> {
@@ -177,6 +389,18 @@ impl Solution {
         self.timestamp
     }
 
+    /// Return id of the work solver (e.g. hash chain index) which has found this solution
+    #[inline]
+    pub fn solver_id(&self) -> Option<usize> {
+        self.work.solver_id
+    }
+
+    /// Return generation of the work solver's engines from which the solved work originates
+    #[inline]
+    pub fn generation(&self) -> Option<u64> {
+        self.work.generation
+    }
+
     pub fn job<T: job::Bitcoin>(&self) -> &T {
         self.work
             .job
@@ -184,32 +408,105 @@ impl Solution {
             .expect("cannot downcast to original job")
     }
 
+    /// Return the original job without downcasting
+    #[inline]
+    pub fn dyn_job(&self) -> &Arc<dyn job::Bitcoin> {
+        &self.work.job
+    }
+
     #[inline]
     pub fn nonce(&self) -> u32 {
         self.solution.nonce()
     }
 
+    /// Return nTime used for this solution
+    /// The value reported by the backend takes precedence. Otherwise the nTime is recovered from
+    /// the allowed range and when it fails then the nTime of original work is used.
     #[inline]
     pub fn time(&self) -> u32 {
-        self.work.ntime
+        *self.ntime.get_or_init(|| {
+            self.solution
+                .ntime()
+                .or_else(|| self.recover_time())
+                .unwrap_or(self.work.ntime)
+        })
     }
 
+    /// Return signed offset of the solution nTime from the job time. The offset is negative when
+    /// the job time is ahead of the solution (e.g. due to clock skew of the pool) and such
+    /// solution is reported by `validate` as `SolutionFault::Time`.
+    #[inline]
+    pub fn time_offset(&self) -> i64 {
+        self.time() as i64 - self.work.job.time() as i64
+    }
+
+    /// Try to find nTime for which the block header meets the backend target
+    /// The nTime of original work is checked first and then its neighbours up to
+    /// `NTIME_RECOVERY_WINDOW` seconds away (the nearest first) within the range allowed by the
+    /// job. It is useful for hardware which rolls nTime implicitly without reporting it.
+    pub fn recover_time(&self) -> Option<u32> {
+        let job = &self.work.job;
+        let work_ntime = self.work.ntime;
+        let target = self.backend_target();
+
+        let min_time = job.time();
+        let max_time = max_rolled_time(job.as_ref(), work_ntime, self.work.max_ntime_roll);
+
+        iter::once(work_ntime)
+            .chain((1..=NTIME_RECOVERY_WINDOW).flat_map(|distance| {
+                iter::once(work_ntime.checked_add(distance))
+                    .chain(iter::once(work_ntime.checked_sub(distance)))
+                    .flatten()
+            }))
+            .filter(|&ntime| ntime >= min_time && ntime <= max_time)
+            .find(|&ntime| self.hash_with_time(ntime).meets(target))
+    }
+
+    /// Return version of the solved block header
+    /// The solution has to be validated first (see `Solution::validate`).
     #[inline]
     pub fn version(&self) -> u32 {
-        let i = self.midstate_idx();
-        self.work.midstates[i].version
+        self.solving_midstate().version
+    }
+
+    /// Check if the solution has been found for a rolled version which differs from the base
+    /// version of the job
+    #[inline]
+    pub fn version_rolled(&self) -> bool {
+        self.version() != self.work.job.version()
+    }
+
+    /// Return midstate (with its version) which has been solved by the hardware
+    #[inline]
+    pub fn solving_midstate(&self) -> &Midstate {
+        self.try_solving_midstate()
+            .expect("BUG: solution with invalid midstate index has not been dropped")
+    }
+
+    #[inline]
+    pub fn try_solving_midstate(&self) -> Option<&Midstate> {
+        self.work.midstates.get(self.midstate_idx())
     }
 
     #[inline]
     pub fn network_target(&self) -> ii_bitcoin::Target {
         // NOTE: it is expected that job has been checked in client and is correct
-        ii_bitcoin::Target::from_compact(self.work.job.bits())
+        self.network_params()
+            .target_from_compact(self.work.job.bits())
             .expect("BUG: job has incorrect nbits")
     }
 
+    /// Return parameters of the network of the job used for conversion of targets to difficulty
+    #[inline]
+    pub fn network_params(&self) -> ii_bitcoin::NetworkParams {
+        self.work.job.network_params()
+    }
+
+    /// Return target required by the job for the work solver which has found this solution
     #[inline]
     pub fn job_target(&self) -> &ii_bitcoin::Target {
-        self.job_target.get_or_init(|| self.work.job.target())
+        self.job_target
+            .get_or_init(|| self.work.job.solver_target(self.work.solver_id))
     }
 
     #[inline]
@@ -222,26 +519,261 @@ impl Solution {
         self.solution.midstate_idx()
     }
 
+    /// Return extranonce used for this solution
+    /// When the backend does not report it then the first extranonce of the work range is used.
+    #[inline]
+    pub fn extranonce(&self) -> Option<u64> {
+        self.solution
+            .extranonce()
+            .or_else(|| self.work.extranonce.map(|range| range.start))
+    }
+
+    /// Check if the solution has been found for other extranonce than the one used for
+    /// computation of the work midstates
+    #[inline]
+    fn extranonce_rolled(&self) -> bool {
+        self.extranonce() != self.work.extranonce.map(|range| range.start)
+    }
+
+    /// Check that the solution reported by the backend is consistent with its work so that it can
+    /// be processed without panicking. Malformed solutions should be dropped.
+    pub fn validate(&self) -> Result<(), SolutionFault> {
+        self.validate_with_ntime_roll(self.work.max_ntime_roll)
+    }
+
+    /// Validate the solution with explicit cap of nTime rolling instead of the one of its work
+    fn validate_with_ntime_roll(&self, max_ntime_roll: u32) -> Result<(), SolutionFault> {
+        let midstate_idx = self.midstate_idx();
+        let midstate_count = self.work.midstates.len();
+        if midstate_idx >= midstate_count {
+            return Err(SolutionFault::MidstateIdx {
+                midstate_idx,
+                midstate_count,
+            });
+        }
+        if let Some(ntime) = self.solution.ntime() {
+            let min_time = self.work.job.time();
+            let max_time = max_rolled_time(self.work.job.as_ref(), self.work.ntime, max_ntime_roll);
+            if ntime < min_time || ntime > max_time {
+                return Err(SolutionFault::Time {
+                    ntime,
+                    min_time,
+                    max_time,
+                });
+            }
+        }
+        if let Some(extranonce) = self.solution.extranonce() {
+            match self.work.extranonce {
+                Some(range) if range.contains(extranonce) => {}
+                range => return Err(SolutionFault::Extranonce { extranonce, range }),
+            }
+        }
+        Ok(())
+    }
+
     /// Return double hash of this solution
+    /// Other copies of the same solution (e.g. duplicates reported by the hardware) are not
+    /// verified again as long as they are found in the cache of recently verified solutions of
+    /// the work solver which has found them.
     #[inline]
     pub fn hash(&self) -> &ii_bitcoin::DHash {
-        self.hash.get_or_init(|| self.get_block_header().hash())
+        self.hash.get_or_init(|| {
+            let header = self.get_block_header();
+            let midstate = self.verification_midstate();
+            let compute = || self.compute_header_hash(&header, midstate.is_some());
+            match &self.verification_cache {
+                Some(verification_cache) => verification_cache
+                    .lock()
+                    .expect("BUG: cannot lock verification cache")
+                    .get_or_compute(&header, midstate, compute),
+                None => compute(),
+            }
+        })
+    }
+
+    /// Check if the solution meets network target (i.e. it is a block candidate)
+    #[inline]
+    pub fn is_block(&self) -> bool {
+        self.hash().meets(&self.network_target())
+    }
+
+    /// Try to find neighbouring nTime for which the solution still meets the job target
+    /// It is used as a fallback for marginal shares rejected as stale. The nTime cannot exceed
+    /// the range allowed by the job and the adjustment is done only once for each solution.
+    pub fn adjust_time(&self) -> Option<Self> {
+        if self.time_adjusted {
+            return None;
+        }
+        let job = &self.work.job;
+        let time = self.time();
+        let target = self.job_target();
+        let max_time = max_rolled_time(job.as_ref(), job.max_time(), self.work.max_ntime_roll);
+
+        // prefer later nTime because the rejection is expected to be caused by high latency
+        [time.wrapping_add(1), time.wrapping_sub(1)]
+            .iter()
+            .cloned()
+            .filter(|&ntime| ntime >= job.time() && ntime <= max_time)
+            .find(|&ntime| self.hash_with_time(ntime).meets(target))
+            .map(|ntime| Self {
+                ntime: ntime.into(),
+                hash: OnceCell::new(),
+                time_adjusted: true,
+                ..self.clone()
+            })
+    }
+
+    #[inline]
+    pub fn is_time_adjusted(&self) -> bool {
+        self.time_adjusted
+    }
+
+    /// Create copy of block candidate which is sent to other client than its origin (see
+    /// `client::JobExecutor::get_block_solution_senders`). The relayed copy is not accounted in
+    /// statistics again because its origin already accounts it.
+    pub fn relayed(&self) -> Self {
+        Self {
+            relayed: true,
+            ..self.clone()
+        }
+    }
+
+    #[inline]
+    pub fn is_relayed(&self) -> bool {
+        self.relayed
+    }
+
+    /// Mark solution found on a job of a donation pool (see `stats::Donation`). Such solution is
+    /// not accounted in the statistics of the user's mining (see `Solution::accounting_path`).
+    pub fn donated(&self) -> Self {
+        Self {
+            donation: true,
+            ..self.clone()
+        }
+    }
+
+    #[inline]
+    pub fn is_donation(&self) -> bool {
+        self.donation
+    }
+
+    /// Record the response of the remote server to this solution
+    /// Only the first outcome is kept because the solution is resolved just once.
+    pub fn resolve(&self, outcome: SubmitOutcome) {
+        let _ = self.outcome.set(outcome);
+    }
+
+    /// Return the outcome of submission or `None` while the solution hasn't been resolved yet
+    #[inline]
+    pub fn outcome(&self) -> Option<&SubmitOutcome> {
+        self.outcome.get()
+    }
+
+    /// Independently verify on CPU that the reported nonce solves the work at backend target
+    /// When it does not then the neighbourhood of the reported nonce and nTime (up to `radius`
+    /// in both directions) is scanned to find out whether the hardware is off by some offset.
+    /// The reported nTime is the one provided by the backend or that of the original work.
+    /// Solution referring to a midstate which does not exist in the work is always invalid.
+    pub fn cross_check(&self, radius: u32) -> CrossCheck {
+        if self.try_solving_midstate().is_none() {
+            return CrossCheck::Invalid;
+        }
+        let target = self.backend_target();
+        let nonce = self.nonce();
+        let time = self.solution.ntime().unwrap_or(self.work.ntime);
+        let meets = |offset: SolutionOffset| {
+            let mut header =
+                self.get_block_header_with_time(time.wrapping_add(offset.ntime as u32));
+            header.nonce = nonce.wrapping_add(offset.nonce as u32);
+            self.header_hash(&header).meets(target)
+        };
+
+        if meets(SolutionOffset::default()) {
+            return CrossCheck::Valid;
+        }
+        let radius = radius as i32;
+        let mut offsets: Vec<_> = (-radius..=radius)
+            .flat_map(|ntime| (-radius..=radius).map(move |nonce| SolutionOffset { nonce, ntime }))
+            .filter(|offset| *offset != SolutionOffset::default())
+            .collect();
+        // prefer the closest offsets
+        offsets.sort_by_key(|offset| offset.nonce.abs() + offset.ntime.abs());
+        offsets
+            .into_iter()
+            .find(|&offset| meets(offset))
+            .map(CrossCheck::Offset)
+            .unwrap_or(CrossCheck::Invalid)
+    }
+
+    /// Compute double hash of block header derived from this solution either completely or from
+    /// the solving midstate when it is enabled
+    #[inline]
+    fn header_hash(&self, header: &ii_bitcoin::BlockHeader) -> ii_bitcoin::DHash {
+        self.compute_header_hash(header, self.midstate_verification)
+    }
+
+    /// Return the solving midstate when the solution is going to be verified from it
+    fn verification_midstate(&self) -> Option<&ii_bitcoin::Midstate> {
+        if self.midstate_verification && !self.extranonce_rolled() {
+            Some(&self.solving_midstate().state)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn hash_with_time(&self, time: u32) -> ii_bitcoin::DHash {
+        self.header_hash(&self.get_block_header_with_time(time))
+    }
+
+    fn compute_header_hash(
+        &self,
+        header: &ii_bitcoin::BlockHeader,
+        from_midstate: bool,
+    ) -> ii_bitcoin::DHash {
+        // midstates are not available for extranonce rolled by the hardware
+        if from_midstate && !self.extranonce_rolled() {
+            header.hash_with_midstate(&self.solving_midstate().state)
+        } else {
+            header.hash()
+        }
     }
 
     /// Converts mining work solution to Bitcoin block header structure which is packable
     pub fn get_block_header(&self) -> ii_bitcoin::BlockHeader {
+        self.get_block_header_with_time(self.time())
+    }
+
+    fn get_block_header_with_time(&self, time: u32) -> ii_bitcoin::BlockHeader {
         let job = &self.work.job;
 
         ii_bitcoin::BlockHeader {
             version: self.version(),
             previous_hash: job.previous_hash().into_inner(),
-            merkle_root: job.merkle_root().into_inner(),
-            time: self.time(),
+            merkle_root: self.merkle_root().into_inner(),
+            time,
             bits: job.bits(),
             nonce: self.nonce(),
         }
     }
 
+    /// Serialize the whole block of this solution when its job knows all transactions of the
+    /// block (see `job::Bitcoin::serialize_block`)
+    pub fn serialize_block(&self) -> Option<Vec<u8>> {
+        self.work.job.serialize_block(
+            self.get_block_header(),
+            self.extranonce().unwrap_or_default(),
+        )
+    }
+
+    /// Return merkle root of the block with the extranonce used for this solution
+    fn merkle_root(&self) -> ii_bitcoin::DHash {
+        match self.extranonce() {
+            Some(extranonce) => self.work.job.extranonce_merkle_root(extranonce),
+            None => *self.work.job.merkle_root(),
+        }
+    }
+
     #[inline]
     pub fn has_valid_job(&self) -> bool {
         self.work.job.is_valid()
@@ -264,10 +796,33 @@ impl Solution {
             self.work.path.clone()
         }
     }
+
+    /// Return nodes in which the solution should be accounted. Donation solutions are accounted
+    /// in the donation client and in the backend nodes which have found them (the hardware has
+    /// to be fully accounted) but not in the frontend which sums up the user's mining.
+    pub fn accounting_path(&self) -> node::Path {
+        let path = self.path();
+        if !self.donation {
+            return path;
+        }
+        path.into_iter()
+            .filter(|node| !node.clone().get_unique_ptr().is::<crate::Frontend>())
+            .collect()
+    }
 }
 
 impl Debug for Solution {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Err(fault) = self.validate() {
+            // the hash of malformed solution cannot be computed
+            return write!(
+                f,
+                "malformed (nonce {:08x}, midstate {}): {}",
+                self.nonce(),
+                self.midstate_idx(),
+                fault
+            );
+        }
         write!(
             f,
             "{:?} (nonce {:08x}, midstate {})",
@@ -278,6 +833,87 @@ impl Debug for Solution {
     }
 }
 
+/// Inconsistency between the solution reported by the backend and its work
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolutionFault {
+    /// The solution refers to a midstate which does not exist in the work
+    MidstateIdx {
+        midstate_idx: usize,
+        midstate_count: usize,
+    },
+    /// The nTime reported by the backend is out of the range allowed by the job
+    Time {
+        ntime: u32,
+        min_time: u32,
+        max_time: u32,
+    },
+    /// The extranonce reported by the backend does not belong to the range assigned to the work
+    Extranonce {
+        extranonce: u64,
+        range: Option<ExtranonceRange>,
+    },
+}
+
+impl fmt::Display for SolutionFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MidstateIdx {
+                midstate_idx,
+                midstate_count,
+            } => write!(
+                f,
+                "midstate index {} out of range (work has {} midstates)",
+                midstate_idx, midstate_count
+            ),
+            Self::Time {
+                ntime,
+                min_time,
+                max_time,
+            } => write!(
+                f,
+                "nTime {:#010x} out of range <{:#010x}, {:#010x}>",
+                ntime, min_time, max_time
+            ),
+            Self::Extranonce {
+                extranonce,
+                range: Some(range),
+            } => write!(f, "extranonce {:#x} out of range {}", extranonce, range),
+            Self::Extranonce {
+                extranonce,
+                range: None,
+            } => write!(
+                f,
+                "extranonce {:#x} reported for work without extranonce range",
+                extranonce
+            ),
+        }
+    }
+}
+
+/// Offset of the actual solution from the nonce and nTime reported by the hardware
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SolutionOffset {
+    pub nonce: i32,
+    pub ntime: i32,
+}
+
+impl fmt::Display for SolutionOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "nonce {:+}, ntime {:+}", self.nonce, self.ntime)
+    }
+}
+
+/// Result of CPU verification of a solution found by the hardware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossCheck {
+    /// The reported nonce solves the work
+    Valid,
+    /// The work is solved only by a nonce/nTime in the neighbourhood of the reported one
+    Offset(SolutionOffset),
+    /// No solution has been found in the scanned neighbourhood
+    Invalid,
+}
+
 pub trait Engine: Debug + Send + Sync {
     fn terminate(&self);
 
@@ -469,6 +1105,12 @@ impl EngineReceiver {
         }
     }
 
+    /// Check if the `engine` is the most recent one
+    #[inline]
+    pub fn is_current(&self, engine: &DynEngine) -> bool {
+        Arc::ptr_eq(&*self.watch_receiver.borrow(), engine)
+    }
+
     /// This function should be called just when last entry has been taken out of engine
     #[inline]
     pub fn handle_exhausted(&self, engine: DynEngine) {
@@ -480,6 +1122,82 @@ impl EngineReceiver {
 pub mod test {
     use super::*;
 
+    #[test]
+    fn test_nonce_space_exhaustion_time() {
+        let hashrate = NONCE_SPACE_SIZE as f64;
+        assert_eq!(
+            nonce_space_exhaustion_time(hashrate, 1),
+            Some(time::Duration::from_secs(1))
+        );
+        assert_eq!(
+            nonce_space_exhaustion_time(hashrate / 4.0, 4),
+            Some(time::Duration::from_secs(16))
+        );
+        // S9 hash chain with 4 midstates exhausts the work within a few milliseconds
+        let exhaustion_time =
+            nonce_space_exhaustion_time(14e12 / 3.0, 4).expect("BUG: missing exhaustion time");
+        assert_eq!(exhaustion_time.as_micros(), 3681);
+        assert_eq!(nonce_space_exhaustion_time(0.0, 4), None);
+
+        // extranonce rolled by the hardware extends the nonce space
+        let block = &crate::test_utils::TEST_BLOCKS[0];
+        let mut work = Assignment::new(
+            Arc::new(*block),
+            vec![Midstate {
+                version: block.version,
+                state: block.midstate,
+            }],
+            block.time,
+        );
+        assert_eq!(
+            work.exhaustion_time(hashrate),
+            Some(time::Duration::from_secs(1))
+        );
+        work.extranonce = Some(ExtranonceRange { start: 0, count: 8 });
+        assert_eq!(
+            work.exhaustion_time(hashrate),
+            Some(time::Duration::from_secs(8))
+        );
+    }
+
+    #[test]
+    fn test_validate_work() {
+        let block = &crate::test_utils::TEST_BLOCKS[0];
+        let job = Arc::new(*block);
+        let midstate = |version| Midstate {
+            version,
+            state: hal::compute_midstate(block, version),
+        };
+        let rolled_version = block.version ^ (1 << ii_bitcoin::BIP320_VERSION_SHIFT);
+
+        let work = Assignment::new(
+            job.clone(),
+            vec![midstate(block.version), midstate(rolled_version)],
+            block.time,
+        );
+        assert_eq!(work.validate(), Ok(()));
+
+        // version has not been actually rolled
+        let work = Assignment::new(
+            job.clone(),
+            vec![midstate(block.version), midstate(block.version)],
+            block.time,
+        );
+        assert_eq!(
+            work.validate(),
+            Err(WorkError::DuplicateMidstate(block.version))
+        );
+
+        // only bits of the job version mask may be rolled
+        let version = block.version ^ 1;
+        let work = Assignment::new(
+            job,
+            vec![midstate(block.version), midstate(version)],
+            block.time,
+        );
+        assert_eq!(work.validate(), Err(WorkError::VersionOutOfMask(version)));
+    }
+
     #[test]
     fn test_block_double_hash() {
         for block in crate::test_utils::TEST_BLOCKS.iter() {
@@ -494,4 +1212,493 @@ pub mod test {
             assert_eq!(&block.hash, hash);
         }
     }
+
+    #[test]
+    fn test_verification_cache_hit() {
+        let verification_cache = Arc::new(StdMutex::new(VerificationCache::new(
+            VERIFICATION_CACHE_SIZE,
+        )));
+        let other_cache = Arc::new(StdMutex::new(VerificationCache::new(
+            VERIFICATION_CACHE_SIZE,
+        )));
+        let hits = |cache: &StdMutex<VerificationCache>| {
+            cache
+                .lock()
+                .expect("BUG: cannot lock verification cache")
+                .hits()
+        };
+        for (i, block) in crate::test_utils::TEST_BLOCKS.iter().enumerate() {
+            let solution =
+                Solution::from(block).with_verification_cache(verification_cache.clone());
+            assert_eq!(&block.hash, solution.hash());
+
+            // duplicate of the solution is not verified again
+            let duplicate =
+                Solution::from(block).with_verification_cache(verification_cache.clone());
+            assert_eq!(&block.hash, duplicate.hash());
+            assert_eq!(hits(&verification_cache), i as u64 + 1);
+
+            // the cache is not shared with other work solvers
+            let other = Solution::from(block).with_verification_cache(other_cache.clone());
+            assert_eq!(&block.hash, other.hash());
+            assert_eq!(hits(&other_cache), 0);
+        }
+    }
+
+    #[test]
+    fn test_midstate_verification() {
+        for block in crate::test_utils::TEST_BLOCKS.iter() {
+            let solution: Solution = block.into();
+            let header = solution.get_block_header();
+            assert_eq!(block.hash, solution.compute_header_hash(&header, true));
+            assert_eq!(block.hash, solution.compute_header_hash(&header, false));
+
+            // both methods have to agree also for headers which are not solutions
+            let header = solution.get_block_header_with_time(block.time + 1);
+            assert_eq!(
+                solution.compute_header_hash(&header, false),
+                solution.compute_header_hash(&header, true)
+            );
+        }
+    }
+
+    #[test]
+    fn test_merkle_root_tail_byte_order() {
+        use std::convert::TryInto;
+
+        for block in crate::test_utils::TEST_BLOCKS.iter() {
+            let work: Assignment = block.into();
+            let merkle_root = block.merkle_root.into_inner();
+            let tail: [u8; 4] = merkle_root[merkle_root.len() - 4..]
+                .try_into()
+                .expect("BUG: slice with incorrect length");
+
+            assert_eq!(work.merkle_root_tail_le(), u32::from_le_bytes(tail));
+            assert_eq!(work.merkle_root_tail_be(), u32::from_be_bytes(tail));
+            assert_eq!(work.merkle_root_tail_le(), work.merkle_root_tail());
+            assert_eq!(
+                work.merkle_root_tail_le().swap_bytes(),
+                work.merkle_root_tail_be()
+            );
+        }
+
+        // merkle root of the first test block ends with bytes [0x2f, 0xa7, 0x22, 0xce]
+        let block = &crate::test_utils::TEST_BLOCKS[0];
+        let work: Assignment = block.into();
+        assert_eq!(work.merkle_root_tail_le(), 0xce22a72f);
+        assert_eq!(work.merkle_root_tail_be(), 0x2fa722ce);
+    }
+
+    #[test]
+    fn test_recover_time() {
+        for block in crate::test_utils::TEST_BLOCKS.iter() {
+            let solution: Solution = block.into();
+            // pretend that the hardware has been given work with shifted nTime
+            let mut work = solution.work.clone();
+            work.ntime = block.time + 2;
+            let solution = Solution {
+                work,
+                ..solution
+            };
+
+            assert_eq!(solution.recover_time(), Some(block.time));
+            assert_eq!(solution.time(), block.time);
+            assert_eq!(&block.hash, solution.hash());
+
+            // nTime too far from the work is not searched
+            let mut work = solution.work.clone();
+            work.ntime = block.time + NTIME_RECOVERY_WINDOW + 1;
+            let solution = Solution {
+                work,
+                ntime: OnceCell::new(),
+                hash: OnceCell::new(),
+                ..solution
+            };
+            assert_eq!(solution.recover_time(), None);
+        }
+    }
+
+    #[test]
+    fn test_adjust_time() {
+        for block in crate::test_utils::TEST_BLOCKS.iter() {
+            let solution: Solution = block.into();
+            // simulate solution submitted with borderline nTime
+            let solution = Solution {
+                ntime: (block.time + 1).into(),
+                ..solution
+            };
+            assert_ne!(&block.hash, solution.hash());
+
+            let adjusted_solution = solution
+                .adjust_time()
+                .expect("BUG: cannot adjust solution nTime");
+            assert!(adjusted_solution.is_time_adjusted());
+            assert_eq!(adjusted_solution.time(), block.time);
+            assert_eq!(&block.hash, adjusted_solution.hash());
+
+            // the solution can be resubmitted only once
+            assert!(adjusted_solution.adjust_time().is_none());
+        }
+    }
+
+    /// Backend solution with nonce and nTime shifted against the real solution
+    #[derive(Debug)]
+    struct ShiftedSolution {
+        nonce: u32,
+        ntime: u32,
+        target: ii_bitcoin::Target,
+    }
+
+    impl hal::BackendSolution for ShiftedSolution {
+        fn nonce(&self) -> u32 {
+            self.nonce
+        }
+
+        fn midstate_idx(&self) -> usize {
+            0
+        }
+
+        fn solution_idx(&self) -> usize {
+            0
+        }
+
+        fn target(&self) -> &ii_bitcoin::Target {
+            &self.target
+        }
+
+        fn ntime(&self) -> Option<u32> {
+            Some(self.ntime)
+        }
+    }
+
+    /// Backend solution found for a particular midstate of multi-midstate work
+    #[derive(Debug)]
+    struct MidstateSolution {
+        midstate_idx: usize,
+        target: ii_bitcoin::Target,
+    }
+
+    impl hal::BackendSolution for MidstateSolution {
+        fn nonce(&self) -> u32 {
+            0
+        }
+
+        fn midstate_idx(&self) -> usize {
+            self.midstate_idx
+        }
+
+        fn solution_idx(&self) -> usize {
+            0
+        }
+
+        fn target(&self) -> &ii_bitcoin::Target {
+            &self.target
+        }
+    }
+
+    /// Backend solution found by hardware rolling extranonce
+    #[derive(Debug)]
+    struct ExtranonceSolution {
+        nonce: u32,
+        extranonce: u64,
+        target: ii_bitcoin::Target,
+    }
+
+    impl hal::BackendSolution for ExtranonceSolution {
+        fn nonce(&self) -> u32 {
+            self.nonce
+        }
+
+        fn midstate_idx(&self) -> usize {
+            0
+        }
+
+        fn solution_idx(&self) -> usize {
+            0
+        }
+
+        fn target(&self) -> &ii_bitcoin::Target {
+            &self.target
+        }
+
+        fn extranonce(&self) -> Option<u64> {
+            Some(self.extranonce)
+        }
+    }
+
+    #[test]
+    fn test_extranonce_solution() {
+        use crate::test_utils::TestExtranonceJob;
+
+        for block in crate::test_utils::TEST_BLOCKS.iter() {
+            let job = Arc::new(TestExtranonceJob(*block));
+            // the range starts below the extranonce which solves the block
+            let engine = engine::ExtranonceRolling::new(job, 1, 8);
+            let work = engine.next_work().unwrap();
+            let range = work.extranonce.expect("BUG: missing extranonce range");
+            assert!(range.contains(TestExtranonceJob::EXTRANONCE));
+            assert_ne!(range.start, TestExtranonceJob::EXTRANONCE);
+
+            let solution_for = |extranonce| {
+                Solution::new(
+                    work.clone(),
+                    ExtranonceSolution {
+                        nonce: block.nonce,
+                        extranonce,
+                        target: Default::default(),
+                    },
+                    None,
+                )
+            };
+
+            let solution = solution_for(TestExtranonceJob::EXTRANONCE);
+            assert_eq!(solution.validate(), Ok(()));
+            assert_eq!(solution.extranonce(), Some(TestExtranonceJob::EXTRANONCE));
+            assert_eq!(&block.hash, solution.hash());
+            assert_eq!(
+                block.merkle_root.into_inner(),
+                solution.get_block_header().merkle_root
+            );
+            // the midstate of the work cannot be used for rolled extranonce
+            let header = solution.get_block_header();
+            assert_eq!(block.hash, solution.compute_header_hash(&header, true));
+
+            // other extranonce from the range is valid but does not solve the block
+            let solution = solution_for(range.start + 1);
+            assert_eq!(solution.validate(), Ok(()));
+            assert_ne!(&block.hash, solution.hash());
+
+            let extranonce = range.end();
+            assert_eq!(
+                solution_for(extranonce).validate(),
+                Err(SolutionFault::Extranonce {
+                    extranonce,
+                    range: Some(range),
+                })
+            );
+
+            // the extranonce cannot be reported for work without the range
+            let solution = Solution::new(
+                block.into(),
+                ExtranonceSolution {
+                    nonce: block.nonce,
+                    extranonce: TestExtranonceJob::EXTRANONCE,
+                    target: Default::default(),
+                },
+                None,
+            );
+            assert_eq!(
+                solution.validate(),
+                Err(SolutionFault::Extranonce {
+                    extranonce: TestExtranonceJob::EXTRANONCE,
+                    range: None,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_try_solving_midstate() {
+        let block = &crate::test_utils::TEST_BLOCKS[0];
+        let solution: Solution = block.into();
+        let midstate = solution
+            .try_solving_midstate()
+            .expect("BUG: missing solving midstate");
+        assert_eq!(midstate.version, block.version);
+
+        // solution referring to nonexistent midstate is not solved by any midstate
+        let solution = Solution::new(
+            block.into(),
+            MidstateSolution {
+                midstate_idx: 1,
+                target: Default::default(),
+            },
+            None,
+        );
+        assert!(solution.try_solving_midstate().is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        for block in crate::test_utils::TEST_BLOCKS.iter() {
+            let solution: Solution = block.into();
+            assert_eq!(solution.validate(), Ok(()));
+
+            let solution = Solution::new(
+                block.into(),
+                MidstateSolution {
+                    midstate_idx: 1,
+                    target: Default::default(),
+                },
+                None,
+            );
+            assert_eq!(
+                solution.validate(),
+                Err(SolutionFault::MidstateIdx {
+                    midstate_idx: 1,
+                    midstate_count: 1,
+                })
+            );
+            // malformed solution can be still printed
+            assert!(format!("{:?}", solution).starts_with("malformed"));
+
+            for &ntime in [std::u32::MAX, block.time + 1, block.time - 1].iter() {
+                let solution = Solution::new(
+                    block.into(),
+                    ShiftedSolution {
+                        nonce: block.nonce,
+                        ntime,
+                        target: Default::default(),
+                    },
+                    None,
+                );
+                assert_eq!(
+                    solution.validate(),
+                    Err(SolutionFault::Time {
+                        ntime,
+                        min_time: block.time,
+                        max_time: block.time,
+                    })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_time_offset() {
+        let block = &crate::test_utils::TEST_BLOCKS[0];
+        let solution_with_time = |ntime| {
+            Solution::new(
+                block.into(),
+                ShiftedSolution {
+                    nonce: block.nonce,
+                    ntime,
+                    target: Default::default(),
+                },
+                None,
+            )
+        };
+
+        assert_eq!(solution_with_time(block.time).time_offset(), 0);
+        assert_eq!(solution_with_time(block.time + 5).time_offset(), 5);
+        // job time in the future does not underflow
+        let solution = solution_with_time(block.time - 5);
+        assert_eq!(solution.time_offset(), -5);
+        assert!(solution.validate().is_err());
+        assert_eq!(solution_with_time(0).time_offset(), -(block.time as i64));
+    }
+
+    #[test]
+    fn test_validate_max_ntime_roll() {
+        const MAX_NTIME_ROLL: u32 = 4;
+
+        for block in crate::test_utils::TEST_BLOCKS.iter() {
+            // work rolled by the engine further than the cap allows
+            let mut work: Assignment = block.into();
+            work.ntime = block.time + 2 * MAX_NTIME_ROLL;
+
+            let solution_with_time = |ntime| {
+                Solution::new(
+                    work.clone(),
+                    ShiftedSolution {
+                        nonce: block.nonce,
+                        ntime,
+                        target: Default::default(),
+                    },
+                    None,
+                )
+            };
+
+            let solution = solution_with_time(block.time + MAX_NTIME_ROLL);
+            assert_eq!(solution.validate_with_ntime_roll(MAX_NTIME_ROLL), Ok(()));
+
+            // solution is still within the range of the work but beyond the cap
+            let ntime = block.time + MAX_NTIME_ROLL + 1;
+            let solution = solution_with_time(ntime);
+            assert_eq!(solution.validate_with_ntime_roll(std::u32::MAX), Ok(()));
+            assert_eq!(
+                solution.validate_with_ntime_roll(MAX_NTIME_ROLL),
+                Err(SolutionFault::Time {
+                    ntime,
+                    min_time: block.time,
+                    max_time: block.time + MAX_NTIME_ROLL,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_solving_midstate() {
+        const MIDSTATE_COUNT: usize = 4;
+
+        for block in crate::test_utils::TEST_BLOCKS.iter() {
+            let engine = engine::VersionRolling::new(Arc::new(*block), MIDSTATE_COUNT);
+            let work = match engine.next_work() {
+                LoopState::Continue(work) => work,
+                _ => panic!("BUG: version rolling engine is exhausted"),
+            };
+            assert_eq!(work.midstates.len(), MIDSTATE_COUNT);
+
+            for midstate_idx in 0..MIDSTATE_COUNT {
+                let solution = Solution::new(
+                    work.clone(),
+                    MidstateSolution {
+                        midstate_idx,
+                        target: Default::default(),
+                    },
+                    None,
+                );
+                let expected_midstate = &work.midstates[midstate_idx];
+                let midstate = solution.solving_midstate();
+
+                assert_eq!(midstate.version, expected_midstate.version);
+                assert_eq!(midstate.state, expected_midstate.state);
+                assert_eq!(solution.version(), expected_midstate.version);
+                // rolled versions must produce distinct midstates
+                for (i, other) in work.midstates.iter().enumerate() {
+                    if i != midstate_idx {
+                        assert_ne!(midstate.version, other.version);
+                        assert_ne!(midstate.state, other.state);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_cross_check() {
+        for block in crate::test_utils::TEST_BLOCKS.iter() {
+            let solution: Solution = block.into();
+            assert_eq!(solution.cross_check(1), CrossCheck::Valid);
+
+            for &(nonce, ntime) in [(1, 0), (-1, 0), (0, 1), (1, -1)].iter() {
+                let solution = Solution::new(
+                    block.into(),
+                    ShiftedSolution {
+                        nonce: block.nonce.wrapping_sub(nonce as u32),
+                        ntime: block.time.wrapping_sub(ntime as u32),
+                        target: Default::default(),
+                    },
+                    None,
+                );
+                assert_eq!(
+                    solution.cross_check(1),
+                    CrossCheck::Offset(SolutionOffset { nonce, ntime })
+                );
+                // the offset is out of the scanned neighbourhood
+                assert_eq!(solution.cross_check(0), CrossCheck::Invalid);
+            }
+
+            // solution with garbage midstate index from hardware
+            let solution = Solution::new(
+                block.into(),
+                MidstateSolution {
+                    midstate_idx: 1,
+                    target: Default::default(),
+                },
+                None,
+            );
+            assert_eq!(solution.cross_check(1), CrossCheck::Invalid);
+        }
+    }
 }
@@ -22,11 +22,35 @@
 
 //! Provides work engines that are capable for converting Jobs to actual work suitable for mining
 //! backend processing
+use ii_logging::macros::*;
+
 use super::*;
 use crate::job;
 
+use std::mem;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::OnceCell;
+
+/// Number of midstates at the beginning of version space which are kept for all nTime rounds
+pub const DEFAULT_PREFETCH_MIDSTATE_COUNT: usize = 64;
+
+/// Derive seed of the extranonce sequence from current time. The nanoseconds are mixed so that
+/// coarse clock resolution does not leave the lower bits constant.
+fn time_seed() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (now.as_nanos() as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 16
+}
+
+/// Derive seed of an engine source with `index` from a seed shared by all sources. Each index
+/// starts at a different extranonce range (zero index keeps the shared seed).
+fn indexed_seed(seed: u64, index: u64) -> u64 {
+    seed ^ index.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
 
 #[derive(Debug)]
 pub struct ExhaustedWork;
@@ -128,10 +152,206 @@ impl AtomicRange {
     }
 }
 
+/// Midstates of the first versions of rolled version space. Midstates depend only on the first
+/// chunk of the block header (version, previous hash and most of the merkle root) and not on
+/// nTime so the same midstates are needed again in each nTime round of the job and by any other
+/// job that shares this chunk (e.g. a job that differs only in nTime or target). They are
+/// computed on the first use and reused afterwards instead of computing them again.
+#[derive(Debug)]
+pub struct MidstatePrefetch {
+    /// Base Bitcoin block header version with BIP320 bits cleared
+    base_version: u32,
+    previous_hash: ii_bitcoin::DHash,
+    merkle_root: ii_bitcoin::DHash,
+    midstates: Vec<OnceCell<Midstate>>,
+}
+
+impl MidstatePrefetch {
+    pub fn new(job: &dyn job::Bitcoin, count: usize) -> Self {
+        let count = count.min(BIP320_UPPER_BOUND_EXCLUSIVE_INDEX as usize);
+        Self {
+            base_version: job.version() & !ii_bitcoin::BIP320_VERSION_MASK,
+            previous_hash: *job.previous_hash(),
+            merkle_root: *job.merkle_root(),
+            midstates: (0..count).map(|_| OnceCell::new()).collect(),
+        }
+    }
+
+    /// Check if the prefetched midstates are valid for given job
+    pub fn matches(&self, job: &dyn job::Bitcoin) -> bool {
+        self.base_version == job.version() & !ii_bitcoin::BIP320_VERSION_MASK
+            && self.previous_hash == *job.previous_hash()
+            && self.merkle_root == *job.merkle_root()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.midstates.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.midstates.is_empty()
+    }
+
+    /// Return midstate for index of rolled version space and compute it when it is not
+    /// available yet. Midstates out of the prefetched range are not kept.
+    #[inline]
+    fn get_or_compute<F>(&self, index: u32, compute: F) -> Midstate
+    where
+        F: FnOnce() -> Midstate,
+    {
+        match self.midstates.get(index as usize) {
+            Some(midstate) => midstate.get_or_init(compute).clone(),
+            None => compute(),
+        }
+    }
+}
+
+/// Creates work engines for new jobs. Version rolling engines keep midstates of the first
+/// `prefetch_count` versions for the following nTime rounds of the job. The prefetcher also
+/// speculates that the next job keeps the header prefix of the previous one, which is the case
+/// of jobs reissued only to update nTime or target and of jobs resent after reconnection. When
+/// the speculation is right the new engine starts with midstates already computed for the
+/// previous job.
+#[derive(Debug)]
+pub struct Prefetcher {
+    midstate_count: usize,
+    prefetch_count: usize,
+    /// Number of extranonce values the hardware is able to roll
+    extranonce_rolling: u32,
+    /// Configured seed of the extranonce sequence
+    work_seed: Option<u64>,
+    /// Index mixed into the seed to keep sequences of distinct prefetchers apart
+    seed_index: u64,
+    /// Configured cap of nTime rolling of version rolling engines
+    max_ntime_roll: Option<u32>,
+    /// Midstates of the previous job which are expected to be reused by the next one
+    speculation: StdMutex<Option<Arc<MidstatePrefetch>>>,
+}
+
+/// Each clone keeps its own speculation because jobs of different clients never match
+impl Clone for Prefetcher {
+    fn clone(&self) -> Self {
+        Self {
+            midstate_count: self.midstate_count,
+            prefetch_count: self.prefetch_count,
+            extranonce_rolling: self.extranonce_rolling,
+            work_seed: self.work_seed,
+            seed_index: self.seed_index,
+            max_ntime_roll: self.max_ntime_roll,
+            speculation: StdMutex::new(None),
+        }
+    }
+}
+
+impl Prefetcher {
+    pub fn new(midstate_count: usize, prefetch_count: usize) -> Self {
+        Self {
+            midstate_count,
+            prefetch_count,
+            extranonce_rolling: 0,
+            work_seed: None,
+            seed_index: 0,
+            max_ntime_roll: None,
+            speculation: StdMutex::new(None),
+        }
+    }
+
+    /// Hand off ranges of `range_size` extranonce values to hardware which rolls extranonce on
+    /// its own (see `hal::BackendConfig::extranonce_rolling`). Jobs which allow extranonce
+    /// rolling are then mined by `ExtranonceRolling` engine.
+    pub fn with_extranonce_rolling(mut self, range_size: u32) -> Self {
+        self.extranonce_rolling = range_size;
+        self
+    }
+
+    /// Number of extranonce bytes which have to be left by the pool so that the hardware is
+    /// able to roll whole ranges of extranonce values (zero when extranonce rolling is disabled)
+    pub fn min_extranonce_size(&self) -> usize {
+        ExtranonceRolling::extranonce_size_for(self.extranonce_rolling)
+    }
+
+    /// Make the sequence of extranonce ranges reproducible for debugging and replay of test runs.
+    /// Without the seed each job starts at a position derived from current time so restarted
+    /// miner does not repeat the sequence of its previous run.
+    pub fn with_work_seed(mut self, seed: Option<u64>) -> Self {
+        self.work_seed = seed;
+        self
+    }
+
+    /// Mix `index` into the seed (either configured or derived from time) so that prefetchers
+    /// with distinct indexes do not generate overlapping sequences for the same job
+    pub fn with_seed_index(mut self, index: usize) -> Self {
+        self.seed_index = index as u64;
+        self
+    }
+
+    /// Limit rolling of nTime to `max_ntime_roll` seconds after the job time (`None` leaves only
+    /// the limit given by the job). The cap applies both to generated work and its solutions.
+    pub fn with_max_ntime_roll(mut self, max_ntime_roll: Option<u32>) -> Self {
+        self.max_ntime_roll = max_ntime_roll;
+        self
+    }
+
+    /// Create engine for a new job. Extranonce rolling is preferred when both the hardware and
+    /// the job support it, otherwise version rolling engine is created (see `engine`).
+    /// A job with zero-width extranonce (e.g. from a pool which doesn't allow extranonce2
+    /// rolling) has a fixed merkle root so its search space is extended only by rolling of
+    /// the version and nTime.
+    pub fn build_engine(&self, job: Arc<dyn job::Bitcoin>) -> DynEngine {
+        match ExtranonceRolling::negotiate(job.as_ref(), self.extranonce_rolling) {
+            Some(range_size) => Arc::new(ExtranonceRolling::with_seed(
+                job,
+                self.midstate_count,
+                range_size,
+                indexed_seed(self.work_seed.unwrap_or_else(time_seed), self.seed_index),
+            )),
+            None => {
+                if self.extranonce_rolling > 0 && job.extranonce_size() == 0 {
+                    debug!("Job doesn't allow extranonce rolling, using version and nTime rolling");
+                }
+                Arc::new(self.engine(job))
+            }
+        }
+    }
+
+    /// Create version rolling engine for a new job. The speculation is used when it matches the
+    /// job otherwise it is replaced by (still empty) midstates of the new job. Midstates are
+    /// computed lazily by the engines so no hashing is done under the lock.
+    pub fn engine(&self, job: Arc<dyn job::Bitcoin>) -> VersionRolling {
+        let prefetch = {
+            let mut speculation = self
+                .speculation
+                .lock()
+                .expect("BUG: cannot lock prefetch speculation");
+            match speculation.as_ref() {
+                Some(prefetch) if prefetch.matches(job.as_ref()) => {
+                    trace!("Reusing midstates of previous job with the same header prefix");
+                    prefetch.clone()
+                }
+                _ => {
+                    let prefetch =
+                        Arc::new(MidstatePrefetch::new(job.as_ref(), self.prefetch_count));
+                    speculation.replace(prefetch.clone());
+                    prefetch
+                }
+            }
+        };
+        VersionRolling::with_ntime_roll(
+            job,
+            self.midstate_count,
+            prefetch,
+            self.max_ntime_roll.unwrap_or(std::u32::MAX),
+        )
+    }
+}
+
 /// Version rolling implements WorkEngine trait and represents a shared source of work for mining
 /// backends. Each instance takes care of atomically allocating version field ranges until the
 /// range is full exhausted. After version has been rolled over, ntime is incremented and version
-/// resetted to 0. The limit of `ntime` range is determined by `ROLL_NTIME_SECONDS`.
+/// resetted to 0. The limit of `ntime` range is determined by `ROLL_NTIME_SECONDS` unless it is
+/// lowered by configured cap of nTime rolling (see `Prefetcher::with_max_ntime_roll`).
 ///
 /// TODO: Rolling ntime together with version IS A HACK. This needs to be fixed properly by raising
 /// `ntime` in sync with real-time clock.
@@ -147,10 +367,41 @@ pub struct VersionRolling {
     curr_range: AtomicRange,
     /// Base Bitcoin block header version with BIP320 bits cleared
     base_version: u32,
+    /// Midstates for the beginning of version space shared by all nTime rounds
+    prefetch: Arc<MidstatePrefetch>,
+    /// Maximal number of seconds the nTime of generated work and its solutions can be rolled
+    max_ntime_roll: u32,
 }
 
 impl VersionRolling {
     pub fn new(job: Arc<dyn job::Bitcoin>, midstate_count: usize) -> Self {
+        let prefetch = Arc::new(MidstatePrefetch::new(job.as_ref(), 0));
+        Self::with_prefetch(job, midstate_count, prefetch)
+    }
+
+    /// Create engine which reuses midstates kept in `prefetch` instead of computing them again.
+    /// Prefetched midstates which do not match the job are discarded.
+    pub fn with_prefetch(
+        job: Arc<dyn job::Bitcoin>,
+        midstate_count: usize,
+        prefetch: Arc<MidstatePrefetch>,
+    ) -> Self {
+        Self::with_ntime_roll(job, midstate_count, prefetch, std::u32::MAX)
+    }
+
+    /// Create engine which rolls nTime by at most `max_ntime_roll` seconds after the job time
+    pub fn with_ntime_roll(
+        job: Arc<dyn job::Bitcoin>,
+        midstate_count: usize,
+        prefetch: Arc<MidstatePrefetch>,
+        max_ntime_roll: u32,
+    ) -> Self {
+        let prefetch = if prefetch.matches(job.as_ref()) {
+            prefetch
+        } else {
+            Arc::new(MidstatePrefetch::new(job.as_ref(), prefetch.len()))
+        };
+        let roll_ntime_seconds = ROLL_NTIME_SECONDS.min(max_ntime_roll.saturating_add(1));
         let base_version = job.version() & !ii_bitcoin::BIP320_VERSION_MASK;
         // we have to be sure we have no "leftover" midstates when we roll
         assert_eq!(
@@ -162,10 +413,12 @@ impl VersionRolling {
             midstate_count,
             curr_range: AtomicRange::new(
                 0,
-                BIP320_UPPER_BOUND_EXCLUSIVE_INDEX * ROLL_NTIME_SECONDS,
+                BIP320_UPPER_BOUND_EXCLUSIVE_INDEX * roll_ntime_seconds,
                 midstate_count as u32,
             ),
             base_version,
+            prefetch,
+            max_ntime_roll,
         }
     }
 
@@ -217,13 +470,17 @@ impl Engine for VersionRolling {
 
         // generate all midstates from given range of indexes
         for index in current..next {
-            // use index for generation compatible header version
-            let version = self.get_block_version(index);
-            block_chunk1.version = version;
-            midstates.push(Midstate {
-                version,
-                state: block_chunk1.midstate(),
-            })
+            // midstates of the same version are shared by all nTime rounds
+            let version_index = index % BIP320_UPPER_BOUND_EXCLUSIVE_INDEX;
+            midstates.push(self.prefetch.get_or_compute(version_index, || {
+                // use index for generation compatible header version
+                let version = self.get_block_version(index);
+                block_chunk1.version = version;
+                Midstate {
+                    version,
+                    state: block_chunk1.midstate(),
+                }
+            }));
         }
 
         // Once we exhaust version-rolling-space, we start rolling ntime.
@@ -233,7 +490,10 @@ impl Engine for VersionRolling {
         let ntime_offset = self.get_ntime_offset(current);
         assert_eq!(ntime_offset, self.get_ntime_offset(next - 1));
 
-        let work = Assignment::new(self.job.clone(), midstates, self.job.time() + ntime_offset);
+        let work = Assignment {
+            max_ntime_roll: self.max_ntime_roll,
+            ..Assignment::new(self.job.clone(), midstates, self.job.time() + ntime_offset)
+        };
         if self.curr_range.is_exhausted(next) {
             // when the whole version space has been exhausted then mark the generated work as
             // a last one (the next call of this method will return 'Exhausted')
@@ -244,11 +504,141 @@ impl Engine for VersionRolling {
     }
 }
 
+/// Engine for hardware which rolls part of coinbase extranonce on its own. Each work is handed
+/// off with a range of extranonce values and midstates for the first one of them. The midstates
+/// cover the first BIP320 versions and the nTime is not rolled because the hardware range
+/// multiplies the space of each work.
+#[derive(Debug, Clone)]
+pub struct ExtranonceRolling {
+    job: Arc<dyn job::Bitcoin>,
+    /// Number of midstates that each generated work covers
+    midstate_count: usize,
+    /// Number of extranonce values in each range
+    range_size: u32,
+    /// Current index of the extranonce range (relative to `first_range`)
+    curr_range: AtomicRange,
+    /// Index of the first extranonce range (the sequence wraps around to the ranges below it)
+    first_range: u32,
+    /// Number of all extranonce ranges in the job
+    range_count: u32,
+    /// Base Bitcoin block header version with BIP320 bits cleared
+    base_version: u32,
+}
+
+impl ExtranonceRolling {
+    /// Determine size of extranonce range for each work which fits both the hardware capability
+    /// and the extranonce space allowed by the job. Returns `None` when extranonce cannot be
+    /// rolled.
+    pub fn negotiate(job: &dyn job::Bitcoin, hardware_range_size: u32) -> Option<u32> {
+        if hardware_range_size == 0 {
+            return None;
+        }
+        let space = Self::extranonce_space(job)?;
+        Some((hardware_range_size as u64).min(space) as u32)
+    }
+
+    /// Number of extranonce bytes which are able to hold `range_size` distinct values
+    pub fn extranonce_size_for(range_size: u32) -> usize {
+        let max_value = range_size.saturating_sub(1);
+        (32 - max_value.leading_zeros() as usize + 7) / 8
+    }
+
+    /// Number of extranonce values allowed by the job (limited to 64-bit space)
+    fn extranonce_space(job: &dyn job::Bitcoin) -> Option<u64> {
+        match job.extranonce_size() {
+            0 => None,
+            size if size >= mem::size_of::<u64>() => Some(std::u64::MAX),
+            size => Some(1 << (size * 8)),
+        }
+    }
+
+    pub fn new(job: Arc<dyn job::Bitcoin>, midstate_count: usize, range_size: u32) -> Self {
+        Self::with_seed(job, midstate_count, range_size, 0)
+    }
+
+    /// Create engine whose sequence of extranonce ranges starts at a position given by `seed`.
+    /// The same seed yields the same sequence, which still covers all ranges of the job.
+    pub fn with_seed(
+        job: Arc<dyn job::Bitcoin>,
+        midstate_count: usize,
+        range_size: u32,
+        seed: u64,
+    ) -> Self {
+        assert!(range_size > 0);
+        assert!(midstate_count as u32 <= BIP320_UPPER_BOUND_EXCLUSIVE_INDEX);
+        let space = Self::extranonce_space(job.as_ref())
+            .expect("BUG: job does not allow extranonce rolling");
+        let range_count = (space / range_size as u64).min(std::u32::MAX as u64) as u32;
+        let base_version = job.version() & !ii_bitcoin::BIP320_VERSION_MASK;
+        Self {
+            job,
+            midstate_count,
+            range_size,
+            curr_range: AtomicRange::new(0, range_count, 1),
+            first_range: (seed % range_count as u64) as u32,
+            range_count,
+            base_version,
+        }
+    }
+}
+
+impl Engine for ExtranonceRolling {
+    fn terminate(&self) {
+        self.curr_range.terminate();
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.curr_range.is_exhausted(None)
+    }
+
+    fn next_work(&self) -> LoopState<Assignment> {
+        let (index, next) = match self.curr_range.next() {
+            None => return LoopState::Exhausted,
+            Some(range) => range,
+        };
+        let index = (self.first_range as u64 + index as u64) % self.range_count as u64;
+        let extranonce = ExtranonceRange {
+            start: index * self.range_size as u64,
+            count: self.range_size,
+        };
+
+        let mut block_chunk1 = ii_bitcoin::BlockHeader {
+            previous_hash: self.job.previous_hash().into_inner(),
+            merkle_root: self
+                .job
+                .extranonce_merkle_root(extranonce.start)
+                .into_inner(),
+            ..Default::default()
+        };
+        let midstates = (0..self.midstate_count as u32)
+            .map(|index| {
+                let version = self.base_version | (index << ii_bitcoin::BIP320_VERSION_SHIFT);
+                block_chunk1.version = version;
+                Midstate {
+                    version,
+                    state: block_chunk1.midstate(),
+                }
+            })
+            .collect();
+
+        let work = Assignment {
+            extranonce: Some(extranonce),
+            ..Assignment::new(self.job.clone(), midstates, self.job.time())
+        };
+        if self.curr_range.is_exhausted(next) {
+            LoopState::Break(work)
+        } else {
+            LoopState::Continue(work)
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use super::*;
     use crate::job::Bitcoin;
     use crate::test_utils;
+    use crate::test_utils::TestBlockBuilder;
 
     fn compare_range(start: u32, stop: u32, step: u32) {
         let range = AtomicRange::new(start, stop, step);
@@ -295,6 +685,124 @@ pub mod test {
         compare_range(5, 9, 4);
     }
 
+    #[test]
+    fn test_prefetched_midstates() {
+        let block = &test_utils::TEST_BLOCKS[0];
+        let job = Arc::new(*block);
+        let prefetch = Arc::new(MidstatePrefetch::new(job.as_ref(), 2));
+        let engine = VersionRolling::with_prefetch(job.clone(), 1, prefetch);
+        assert_eq!(engine.prefetch.len(), 2);
+        assert!(engine.prefetch.midstates[0].get().is_none());
+
+        // Midstates are kept when they are computed in the first nTime round
+        let work = engine.next_work().unwrap();
+        assert_eq!(block.midstate, work.midstates[0].state);
+        let kept = engine.prefetch.midstates[0].get().unwrap();
+        assert_eq!(work.midstates[0].version, kept.version);
+        assert_eq!(work.midstates[0].state, kept.state);
+        // Midstates out of prefetched range are computed and not kept
+        engine.next_work().unwrap();
+        let work = engine.next_work().unwrap();
+        assert_eq!(get_block_version(&job, 2), work.midstates[0].version);
+        assert_eq!(engine.prefetch.len(), 2);
+    }
+
+    #[test]
+    fn test_prefetch_hit() {
+        let block = &test_utils::TEST_BLOCKS[0];
+        let job = Arc::new(*block);
+        let engine = Prefetcher::new(1, 1).engine(job.clone());
+        assert_eq!(engine.prefetch.len(), 1);
+
+        // Mark the kept midstate to be able to recognize it in generated work
+        let marked = Midstate {
+            version: get_block_version(&job, 0),
+            state: Default::default(),
+        };
+        let prefetch = MidstatePrefetch::new(job.as_ref(), 1);
+        prefetch.midstates[0]
+            .set(marked.clone())
+            .expect("BUG: midstate already set");
+        let engine = VersionRolling::with_prefetch(job.clone(), 1, Arc::new(prefetch));
+
+        // The first version of each nTime round takes the kept midstate
+        for ntime_index in 0..2 {
+            engine
+                .curr_range
+                .curr_index
+                .store(make_compound_index(ntime_index, 0), Ordering::Relaxed);
+            let work = engine.next_work().unwrap();
+            assert_eq!(marked.version, work.midstates[0].version);
+            assert_eq!(marked.state, work.midstates[0].state);
+            assert_eq!(get_ntime(&job, ntime_index), work.ntime);
+            // Other versions are computed
+            let work = engine.next_work().unwrap();
+            assert_eq!(get_block_version(&job, 1), work.midstates[0].version);
+            assert_ne!(marked.state, work.midstates[0].state);
+        }
+    }
+
+    #[test]
+    fn test_prefetch_mismatch() {
+        let job = Arc::new(test_utils::TEST_BLOCKS[0]);
+        let prefetch = Arc::new(MidstatePrefetch::new(job.as_ref(), 1));
+        assert!(prefetch.matches(job.as_ref()));
+        // Only the header prefix is relevant for midstates
+        let mut other_time_job = job.change_target(Default::default());
+        other_time_job.time += 1;
+        assert!(prefetch.matches(&other_time_job));
+
+        // Prefetched midstates are discarded when the job differs
+        let other_block = &test_utils::TEST_BLOCKS[1];
+        let other_job = Arc::new(*other_block);
+        assert!(!prefetch.matches(other_job.as_ref()));
+        let engine = VersionRolling::with_prefetch(other_job, 1, prefetch.clone());
+        assert!(!Arc::ptr_eq(&prefetch, &engine.prefetch));
+        assert_eq!(engine.prefetch.len(), 1);
+        let work = engine.next_work().unwrap();
+        assert_eq!(other_block.midstate, work.midstates[0].state);
+        assert!(prefetch.midstates[0].get().is_none());
+    }
+
+    #[test]
+    fn test_prefetcher_speculation() {
+        let prefetcher = Prefetcher::new(1, 4);
+        let block = &test_utils::TEST_BLOCKS[0];
+        let job = Arc::new(*block);
+
+        let engine = prefetcher.engine(job.clone());
+        let first_prefetch = engine.prefetch.clone();
+        assert_eq!(
+            block.midstate,
+            engine.next_work().unwrap().midstates[0].state
+        );
+
+        // Job reissued with updated nTime and target has the same header prefix so the
+        // speculation hits and the midstate computed for the previous job is reused
+        let mut reissued_job = job.change_target(Default::default());
+        reissued_job.time += 1;
+        let engine = prefetcher.engine(Arc::new(reissued_job));
+        assert!(Arc::ptr_eq(&first_prefetch, &engine.prefetch));
+        let kept = engine.prefetch.midstates[0].get().unwrap();
+        assert_eq!(block.midstate, kept.state);
+        let work = engine.next_work().unwrap();
+        assert_eq!(block.midstate, work.midstates[0].state);
+        assert_eq!(reissued_job.time, work.ntime);
+
+        // Different job replaces the speculation
+        let other_block = &test_utils::TEST_BLOCKS[1];
+        let engine = prefetcher.engine(Arc::new(*other_block));
+        assert!(!Arc::ptr_eq(&first_prefetch, &engine.prefetch));
+        assert_eq!(
+            other_block.midstate,
+            engine.next_work().unwrap().midstates[0].state
+        );
+
+        // Clones do not share the speculation
+        let engine = prefetcher.clone().engine(Arc::new(*other_block));
+        assert!(engine.prefetch.midstates[0].get().is_none());
+    }
+
     #[test]
     fn test_block_midstate() {
         for block in test_utils::TEST_BLOCKS.iter() {
@@ -306,6 +814,10 @@ pub mod test {
         }
     }
 
+    fn build_prefetch(job: &Arc<test_utils::TestBlock>) -> Arc<MidstatePrefetch> {
+        Arc::new(MidstatePrefetch::new(job.as_ref(), 0))
+    }
+
     fn get_block_version(job: &Arc<test_utils::TestBlock>, version_index: u32) -> u32 {
         job.version() | (version_index << ii_bitcoin::BIP320_VERSION_SHIFT)
     }
@@ -403,4 +915,241 @@ pub mod test {
         }
         assert!(engine.is_exhausted());
     }
+
+    #[test]
+    fn test_max_ntime_roll() {
+        const MAX_NTIME_ROLL: u32 = 2;
+        let job = Arc::new(test_utils::TEST_BLOCKS[0]);
+        let engine =
+            VersionRolling::with_ntime_roll(job.clone(), 1, build_prefetch(&job), MAX_NTIME_ROLL);
+
+        // the last version of the capped nTime is the last work generated by the engine
+        engine.curr_range.curr_index.store(
+            make_compound_index(MAX_NTIME_ROLL, ii_bitcoin::BIP320_VERSION_MAX),
+            Ordering::Relaxed,
+        );
+        match engine.next_work() {
+            LoopState::Break(work) => {
+                assert_eq!(get_ntime(&job, MAX_NTIME_ROLL), work.ntime);
+                // the cap is passed to the work so that its solutions are validated against it
+                assert_eq!(MAX_NTIME_ROLL, work.max_ntime_roll);
+            }
+            _ => panic!("expected 'LoopState::Break'"),
+        }
+        assert!(engine.is_exhausted());
+
+        // zero cap disables nTime rolling completely
+        let engine = VersionRolling::with_ntime_roll(job.clone(), 1, build_prefetch(&job), 0);
+        engine.curr_range.curr_index.store(
+            make_compound_index(0, ii_bitcoin::BIP320_VERSION_MAX),
+            Ordering::Relaxed,
+        );
+        match engine.next_work() {
+            LoopState::Break(work) => assert_eq!(job.time(), work.ntime),
+            _ => panic!("expected 'LoopState::Break'"),
+        }
+        assert!(engine.is_exhausted());
+    }
+
+    #[test]
+    fn test_prefetcher_max_ntime_roll() {
+        const MAX_NTIME_ROLL: u32 = 3;
+        let job = Arc::new(test_utils::TEST_BLOCKS[0]);
+
+        // without the cap the work is limited only by the job
+        let engine = Prefetcher::new(1, 0).engine(job.clone());
+        match engine.next_work() {
+            LoopState::Continue(work) => assert_eq!(std::u32::MAX, work.max_ntime_roll),
+            _ => panic!("expected 'LoopState::Continue'"),
+        }
+
+        let engine = Prefetcher::new(1, 0)
+            .with_max_ntime_roll(Some(MAX_NTIME_ROLL))
+            .engine(job.clone());
+        match engine.next_work() {
+            LoopState::Continue(work) => assert_eq!(MAX_NTIME_ROLL, work.max_ntime_roll),
+            _ => panic!("expected 'LoopState::Continue'"),
+        }
+        engine.curr_range.curr_index.store(
+            make_compound_index(MAX_NTIME_ROLL, ii_bitcoin::BIP320_VERSION_MAX),
+            Ordering::Relaxed,
+        );
+        match engine.next_work() {
+            LoopState::Break(work) => assert_eq!(get_ntime(&job, MAX_NTIME_ROLL), work.ntime),
+            _ => panic!("expected 'LoopState::Break'"),
+        }
+        assert!(engine.is_exhausted());
+    }
+
+    #[test]
+    fn test_extranonce_rolling() {
+        const RANGE_SIZE: u32 = 4;
+        const MIDSTATE_COUNT: usize = 2;
+
+        let block = test_utils::TEST_BLOCKS[0];
+        let job = Arc::new(test_utils::TestExtranonceJob(block));
+
+        // both the hardware and the job have to support extranonce rolling
+        assert_eq!(ExtranonceRolling::negotiate(&block, RANGE_SIZE), None);
+        assert_eq!(ExtranonceRolling::negotiate(job.as_ref(), 0), None);
+        assert_eq!(
+            ExtranonceRolling::negotiate(job.as_ref(), RANGE_SIZE),
+            Some(RANGE_SIZE)
+        );
+        // the range cannot exceed extranonce space of the job
+        assert_eq!(ExtranonceRolling::negotiate(job.as_ref(), 1000), Some(256));
+
+        // the pool has to leave enough extranonce bytes for the hardware range
+        assert_eq!(ExtranonceRolling::extranonce_size_for(0), 0);
+        assert_eq!(ExtranonceRolling::extranonce_size_for(1), 0);
+        assert_eq!(ExtranonceRolling::extranonce_size_for(RANGE_SIZE), 1);
+        assert_eq!(ExtranonceRolling::extranonce_size_for(256), 1);
+        assert_eq!(ExtranonceRolling::extranonce_size_for(257), 2);
+        assert_eq!(ExtranonceRolling::extranonce_size_for(std::u32::MAX), 4);
+        assert_eq!(
+            Prefetcher::new(MIDSTATE_COUNT, 0)
+                .with_extranonce_rolling(1000)
+                .min_extranonce_size(),
+            2
+        );
+
+        let engine = ExtranonceRolling::new(job.clone(), MIDSTATE_COUNT, RANGE_SIZE);
+        let range_count = 256 / RANGE_SIZE;
+        for index in 0..range_count {
+            let work = match engine.next_work() {
+                LoopState::Continue(work) if index + 1 < range_count => work,
+                LoopState::Break(work) if index + 1 == range_count => work,
+                _ => panic!("BUG: unexpected extranonce rolling state"),
+            };
+            let range = work.extranonce.expect("BUG: missing extranonce range");
+            assert_eq!(range.start, (index * RANGE_SIZE) as u64);
+            assert_eq!(range.count, RANGE_SIZE);
+            assert_eq!(work.ntime, block.time);
+            assert_eq!(
+                work.generated_work_amount(),
+                MIDSTATE_COUNT * RANGE_SIZE as usize
+            );
+
+            // midstates are computed for the first extranonce of the range
+            assert_eq!(work.midstates.len(), MIDSTATE_COUNT);
+            let midstate = &work.midstates[0];
+            assert_eq!(midstate.version, block.version);
+            let expected_state = ii_bitcoin::BlockHeader {
+                version: block.version,
+                previous_hash: block.previous_hash.into_inner(),
+                merkle_root: job.extranonce_merkle_root(range.start).into_inner(),
+                ..Default::default()
+            }
+            .midstate();
+            assert_eq!(midstate.state, expected_state);
+        }
+        assert!(engine.is_exhausted());
+
+        // the prefetcher chooses engine negotiated with the job
+        let prefetcher = Prefetcher::new(1, 0)
+            .with_extranonce_rolling(RANGE_SIZE)
+            .with_work_seed(Some(0));
+        let work = prefetcher.build_engine(job).next_work().unwrap();
+        assert_eq!(
+            work.extranonce,
+            Some(ExtranonceRange {
+                start: 0,
+                count: RANGE_SIZE
+            })
+        );
+        let work = prefetcher
+            .build_engine(Arc::new(block))
+            .next_work()
+            .unwrap();
+        assert_eq!(work.extranonce, None);
+    }
+
+    #[test]
+    fn test_extranonce_seed() {
+        const RANGE_SIZE: u32 = 4;
+        let range_count = 256 / RANGE_SIZE;
+
+        let job = Arc::new(test_utils::TestExtranonceJob(test_utils::TEST_BLOCKS[0]));
+        let sequence = |seed| {
+            let engine = ExtranonceRolling::with_seed(job.clone(), 1, RANGE_SIZE, seed);
+            let mut starts = Vec::new();
+            while !engine.is_exhausted() {
+                let work = engine.next_work().unwrap();
+                let range = work.extranonce.expect("BUG: missing extranonce range");
+                starts.push(range.start);
+            }
+            starts
+        };
+
+        // the same seed always yields the same sequence
+        let seeded = sequence(1234);
+        assert_eq!(seeded, sequence(1234));
+        assert_eq!(seeded[0], (1234 % range_count as u64) * RANGE_SIZE as u64);
+        assert_ne!(seeded, sequence(4321));
+
+        // the sequence wraps around and it covers each range exactly once
+        let mut sorted = seeded.clone();
+        sorted.sort();
+        let expected: Vec<_> = (0..range_count as u64)
+            .map(|index| index * RANGE_SIZE as u64)
+            .collect();
+        assert_eq!(sorted, expected);
+
+        // zero seed starts from the first range
+        assert_eq!(sequence(0), expected);
+
+        // the prefetcher passes the configured seed to engines of all jobs
+        let prefetcher = Prefetcher::new(1, 0)
+            .with_extranonce_rolling(RANGE_SIZE)
+            .with_work_seed(Some(1234));
+        for _ in 0..2 {
+            let work = prefetcher.build_engine(job.clone()).next_work().unwrap();
+            assert_eq!(work.extranonce.map(|range| range.start), Some(seeded[0]));
+        }
+
+        // prefetchers with distinct indexes start at different ranges of the same seed
+        let starts: Vec<_> = (0..4)
+            .map(|index| {
+                let prefetcher = Prefetcher::new(1, 0)
+                    .with_extranonce_rolling(RANGE_SIZE)
+                    .with_work_seed(Some(1234))
+                    .with_seed_index(index);
+                let work = prefetcher.build_engine(job.clone()).next_work().unwrap();
+                work.extranonce
+                    .expect("BUG: missing extranonce range")
+                    .start
+            })
+            .collect();
+        assert_eq!(starts[0], seeded[0]);
+        let mut distinct = starts.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), starts.len());
+    }
+
+    #[test]
+    fn test_zero_width_extranonce() {
+        const MIDSTATE_COUNT: usize = 4096;
+        const WORK_COUNT: usize = 40;
+
+        // the hardware is able to roll extranonce but the job has fixed coinbase
+        let block = test_utils::TEST_BLOCKS[0];
+        assert_eq!(block.extranonce_size(), 0);
+        let prefetcher = Prefetcher::new(MIDSTATE_COUNT, 0).with_extranonce_rolling(16);
+        let engine = prefetcher.build_engine(Arc::new(block));
+
+        // the work is still distinct thanks to rolling of the version and nTime
+        let works_per_second = BIP320_UPPER_BOUND_EXCLUSIVE_INDEX as usize / MIDSTATE_COUNT;
+        let mut rolled = std::collections::HashSet::new();
+        for i in 0..WORK_COUNT {
+            let work = engine.next_work().unwrap();
+            assert_eq!(work.extranonce, None);
+            assert_eq!(work.merkle_root_tail(), block.merkle_root_tail());
+            assert_eq!(work.ntime, block.time + (i / works_per_second) as u32);
+            for midstate in work.midstates.iter() {
+                assert!(rolled.insert((work.ntime, midstate.version)));
+            }
+        }
+        assert_eq!(rolled.len(), WORK_COUNT * MIDSTATE_COUNT);
+    }
 }
@@ -0,0 +1,461 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Binary snapshots of mining work which can be captured from a running miner and later reloaded
+//! for replaying exactly the same work against hardware drivers.
+
+use crate::error;
+use crate::job;
+use crate::node;
+use crate::work;
+
+use ii_bitcoin::HashTrait as _;
+
+use std::convert::TryInto;
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Weak};
+
+/// Version of the binary format emitted by `WorkSnapshot::to_bytes`
+const FORMAT_VERSION: u8 = 3;
+
+/// Frozen copy of all job fields that are needed for recreating the mining work
+#[derive(Clone, Debug)]
+pub struct JobSnapshot {
+    origin: Weak<dyn node::Client>,
+    pub version: u32,
+    pub version_mask: u32,
+    pub previous_hash: ii_bitcoin::DHash,
+    pub merkle_root: ii_bitcoin::DHash,
+    pub time: u32,
+    pub max_time: u32,
+    pub bits: u32,
+    pub target: ii_bitcoin::Target,
+    pub block_height: Option<u64>,
+}
+
+impl JobSnapshot {
+    pub fn new(job: &dyn job::Bitcoin) -> Self {
+        Self {
+            origin: job.origin(),
+            version: job.version(),
+            version_mask: job.version_mask(),
+            previous_hash: *job.previous_hash(),
+            merkle_root: *job.merkle_root(),
+            time: job.time(),
+            max_time: job.max_time(),
+            bits: job.bits(),
+            target: job.target(),
+            block_height: job.block_height(),
+        }
+    }
+}
+
+impl job::Bitcoin for JobSnapshot {
+    fn origin(&self) -> Weak<dyn node::Client> {
+        self.origin.clone()
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn version_mask(&self) -> u32 {
+        self.version_mask
+    }
+
+    fn previous_hash(&self) -> &ii_bitcoin::DHash {
+        &self.previous_hash
+    }
+
+    fn merkle_root(&self) -> &ii_bitcoin::DHash {
+        &self.merkle_root
+    }
+
+    fn time(&self) -> u32 {
+        self.time
+    }
+
+    fn max_time(&self) -> u32 {
+        self.max_time
+    }
+
+    fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    fn target(&self) -> ii_bitcoin::Target {
+        self.target
+    }
+
+    /// Recorded job is replayed regardless of the current state of the network
+    fn is_valid(&self) -> bool {
+        true
+    }
+
+    fn block_height(&self) -> Option<u64> {
+        self.block_height
+    }
+}
+
+/// Captured mining work consisting of the job snapshot, midstates and nTime
+#[derive(Clone, Debug)]
+pub struct WorkSnapshot {
+    pub job: JobSnapshot,
+    pub midstates: Vec<work::Midstate>,
+    pub ntime: u32,
+    /// Generation of work engines of the chain which solved the work (zero when unknown)
+    pub generation: u64,
+}
+
+impl WorkSnapshot {
+    pub fn new(work: &work::Assignment) -> Self {
+        Self {
+            job: JobSnapshot::new(work.job.as_ref()),
+            midstates: work.midstates.clone(),
+            ntime: work.ntime,
+            generation: 0,
+        }
+    }
+
+    /// Record generation of the chain the work has been captured from (see
+    /// `hub::Core::current_generation`)
+    pub fn with_generation(mut self, generation: u64) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    /// Serialize the snapshot into a binary form with all numbers stored as little endian.
+    /// Hashes and midstates are stored as raw bytes exactly as used by the miner.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![FORMAT_VERSION];
+
+        let job = &self.job;
+        bytes.extend_from_slice(&job.version.to_le_bytes());
+        bytes.extend_from_slice(&job.version_mask.to_le_bytes());
+        bytes.extend_from_slice(&job.previous_hash.into_inner());
+        bytes.extend_from_slice(&job.merkle_root.into_inner());
+        bytes.extend_from_slice(&job.time.to_le_bytes());
+        bytes.extend_from_slice(&job.max_time.to_le_bytes());
+        bytes.extend_from_slice(&job.bits.to_le_bytes());
+        let target: [u8; ii_bitcoin::SHA256_DIGEST_SIZE] = job.target.into();
+        bytes.extend_from_slice(&target);
+        // zero is stored for unknown height
+        bytes.extend_from_slice(&job.block_height.unwrap_or(0).to_le_bytes());
+
+        bytes.extend_from_slice(&self.ntime.to_le_bytes());
+        bytes.extend_from_slice(&self.generation.to_le_bytes());
+        bytes.extend_from_slice(&(self.midstates.len() as u32).to_le_bytes());
+        for midstate in &self.midstates {
+            bytes.extend_from_slice(&midstate.version.to_le_bytes());
+            bytes.extend_from_slice(midstate.state.as_ref());
+        }
+
+        bytes
+    }
+
+    /// Reload snapshot produced by `to_bytes`. The origin of the original job cannot be
+    /// serialized so the caller has to provide the client the replayed work belongs to.
+    pub fn from_bytes(bytes: &[u8], origin: Weak<dyn node::Client>) -> error::Result<Self> {
+        let mut reader = Reader::new(bytes);
+
+        let format_version = reader.read_u8()?;
+        if format_version != FORMAT_VERSION {
+            Err(error::ErrorKind::General(format!(
+                "unsupported work snapshot format version {}",
+                format_version
+            )))?;
+        }
+
+        let job = JobSnapshot {
+            origin,
+            version: reader.read_u32()?,
+            version_mask: reader.read_u32()?,
+            previous_hash: ii_bitcoin::DHash::from_inner(reader.read_hash()?),
+            merkle_root: ii_bitcoin::DHash::from_inner(reader.read_hash()?),
+            time: reader.read_u32()?,
+            max_time: reader.read_u32()?,
+            bits: reader.read_u32()?,
+            target: reader.read_hash()?.into(),
+            block_height: Some(reader.read_u64()?).filter(|height| *height != 0),
+        };
+
+        let ntime = reader.read_u32()?;
+        let generation = reader.read_u64()?;
+        let midstate_count = reader.read_u32()? as usize;
+        let midstates = (0..midstate_count)
+            .map(|_| {
+                Ok(work::Midstate {
+                    version: reader.read_u32()?,
+                    state: reader.read_hash()?.into(),
+                })
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+
+        if !reader.is_empty() {
+            Err(error::ErrorKind::General(
+                "trailing data after work snapshot".to_string(),
+            ))?;
+        }
+
+        Ok(Self {
+            job,
+            midstates,
+            ntime,
+            generation,
+        })
+    }
+
+    /// Recreate mining work which can be sent to the hardware again
+    pub fn into_assignment(self) -> work::Assignment {
+        work::Assignment::new(Arc::new(self.job), self.midstates, self.ntime)
+    }
+}
+
+/// Serialize multiple snapshots into single buffer where each snapshot is prefixed with its
+/// length (see `decode_snapshots`)
+pub fn encode_snapshots(snapshots: &[WorkSnapshot]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for snapshot in snapshots {
+        let snapshot = snapshot.to_bytes();
+        bytes.extend_from_slice(&(snapshot.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&snapshot);
+    }
+    bytes
+}
+
+/// Reload all snapshots produced by `encode_snapshots`
+pub fn decode_snapshots(
+    bytes: &[u8],
+    origin: Weak<dyn node::Client>,
+) -> error::Result<Vec<WorkSnapshot>> {
+    let mut reader = Reader::new(bytes);
+    let mut snapshots = vec![];
+    while !reader.is_empty() {
+        let size = reader.read_u32()? as usize;
+        snapshots.push(WorkSnapshot::from_bytes(
+            reader.take(size)?,
+            origin.clone(),
+        )?);
+    }
+    Ok(snapshots)
+}
+
+/// Human readable summary of the snapshot with hashes in the byte order used by block explorers
+impl fmt::Display for WorkSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use job::Bitcoin as _;
+
+        write!(
+            f,
+            "previous_hash={} merkle_root={} ntime={} midstates={}",
+            self.job.previous_hash_hex(),
+            self.job.merkle_root_hex(),
+            self.ntime,
+            self.midstates.len()
+        )
+    }
+}
+
+/// Helper for sequential reading of the binary snapshot
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, size: usize) -> error::Result<&'a [u8]> {
+        if self.bytes.len() < size {
+            Err(error::ErrorKind::General(format!(
+                "work snapshot truncated: expected {} more bytes, got {}",
+                size,
+                self.bytes.len()
+            )))?;
+        }
+        let (head, tail) = self.bytes.split_at(size);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn read_u8(&mut self) -> error::Result<u8> {
+        Ok(self.take(mem::size_of::<u8>())?[0])
+    }
+
+    fn read_u32(&mut self) -> error::Result<u32> {
+        Ok(u32::from_le_bytes(
+            self.take(mem::size_of::<u32>())?
+                .try_into()
+                .expect("BUG: slice with incorrect length"),
+        ))
+    }
+
+    fn read_u64(&mut self) -> error::Result<u64> {
+        Ok(u64::from_le_bytes(
+            self.take(mem::size_of::<u64>())?
+                .try_into()
+                .expect("BUG: slice with incorrect length"),
+        ))
+    }
+
+    fn read_hash(&mut self) -> error::Result<[u8; ii_bitcoin::SHA256_DIGEST_SIZE]> {
+        Ok(self
+            .take(ii_bitcoin::SHA256_DIGEST_SIZE)?
+            .try_into()
+            .expect("BUG: slice with incorrect length"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::job::Bitcoin as _;
+    use crate::test_utils;
+
+    fn build_work(block: &test_utils::TestBlock) -> work::Assignment {
+        let midstates = (0..4)
+            .map(|i| work::Midstate {
+                version: block.version + i,
+                state: block.midstate,
+            })
+            .collect();
+        work::Assignment::new(Arc::new(*block), midstates, block.time + 1)
+    }
+
+    #[test]
+    fn test_work_snapshot_round_trip() {
+        for block in test_utils::TEST_BLOCKS.iter() {
+            let work = build_work(block);
+            let bytes = WorkSnapshot::new(&work).to_bytes();
+            let replayed = WorkSnapshot::from_bytes(&bytes, work.origin())
+                .expect("BUG: cannot reload work snapshot")
+                .into_assignment();
+
+            assert_eq!(replayed.ntime, work.ntime);
+            assert_eq!(replayed.merkle_root_tail(), work.merkle_root_tail());
+            assert_eq!(replayed.merkle_root_tail(), block.merkle_root_tail());
+            assert_eq!(replayed.bits(), work.bits());
+            assert_eq!(replayed.midstates.len(), work.midstates.len());
+            for (replayed, original) in replayed.midstates.iter().zip(work.midstates.iter()) {
+                assert_eq!(replayed.version, original.version);
+                assert_eq!(replayed.state.as_ref(), original.state.as_ref());
+            }
+            // serialization of reloaded work has to be stable
+            assert_eq!(WorkSnapshot::new(&replayed).to_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_work_snapshot_hex() {
+        // Block 171874 as shown by block explorers
+        const PREVIOUS_HASH: &str =
+            "0000000000000488d0b6c4c05f24afe4817a122a1e1a5f009dd391fb0cc1aeb3";
+        const MERKLE_ROOT: &str =
+            "ce22a72fa0e9f309830fdb3f75d6c95f051f23ef288a137693ab5c03f2bb6e7e";
+
+        let block = &test_utils::TEST_BLOCKS[0];
+        let snapshot = WorkSnapshot::new(&build_work(block));
+        assert_eq!(snapshot.job.previous_hash_hex(), PREVIOUS_HASH);
+        assert_eq!(snapshot.job.merkle_root_hex(), MERKLE_ROOT);
+        // the internal representation starts with the least significant byte
+        assert_eq!(snapshot.job.previous_hash.into_inner()[0], 0xb3);
+        assert_eq!(snapshot.job.merkle_root.into_inner()[0], 0x7e);
+
+        let summary = snapshot.to_string();
+        assert!(summary.contains(PREVIOUS_HASH));
+        assert!(summary.contains(MERKLE_ROOT));
+    }
+
+    #[test]
+    fn test_work_snapshot_generation() {
+        let work = build_work(&test_utils::TEST_BLOCKS[0]);
+        let snapshot = WorkSnapshot::new(&work);
+        assert_eq!(snapshot.generation, 0);
+
+        let bytes = snapshot.with_generation(42).to_bytes();
+        let snapshot = WorkSnapshot::from_bytes(&bytes, work.origin())
+            .expect("BUG: cannot reload work snapshot");
+        assert_eq!(snapshot.generation, 42);
+    }
+
+    #[test]
+    fn test_work_snapshot_block_height() {
+        let work = build_work(&test_utils::TEST_BLOCKS[0]);
+        let mut snapshot = WorkSnapshot::new(&work);
+        assert_eq!(snapshot.job.block_height, None);
+
+        let bytes = snapshot.to_bytes();
+        let reloaded = WorkSnapshot::from_bytes(&bytes, work.origin())
+            .expect("BUG: cannot reload work snapshot");
+        assert_eq!(reloaded.job.block_height(), None);
+
+        snapshot.job.block_height = Some(3181);
+        let bytes = snapshot.to_bytes();
+        let reloaded = WorkSnapshot::from_bytes(&bytes, work.origin())
+            .expect("BUG: cannot reload work snapshot");
+        assert_eq!(reloaded.job.block_height(), Some(3181));
+    }
+
+    #[test]
+    fn test_work_snapshot_malformed() {
+        let block = &test_utils::TEST_BLOCKS[0];
+        let work = build_work(block);
+        let mut bytes = WorkSnapshot::new(&work).to_bytes();
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert!(WorkSnapshot::from_bytes(truncated, work.origin()).is_err());
+
+        bytes.push(0);
+        assert!(WorkSnapshot::from_bytes(&bytes, work.origin()).is_err());
+
+        bytes[0] = FORMAT_VERSION + 1;
+        assert!(WorkSnapshot::from_bytes(&bytes, work.origin()).is_err());
+    }
+
+    #[test]
+    fn test_encode_snapshots() {
+        let works: Vec<_> = test_utils::TEST_BLOCKS.iter().map(build_work).collect();
+        let snapshots: Vec<_> = works
+            .iter()
+            .enumerate()
+            .map(|(i, work)| WorkSnapshot::new(work).with_generation(i as u64))
+            .collect();
+        let bytes = encode_snapshots(&snapshots);
+        let reloaded =
+            decode_snapshots(&bytes, works[0].origin()).expect("BUG: cannot reload work snapshots");
+
+        assert_eq!(reloaded.len(), snapshots.len());
+        for (reloaded, snapshot) in reloaded.iter().zip(snapshots.iter()) {
+            assert_eq!(reloaded.to_bytes(), snapshot.to_bytes());
+        }
+        assert!(decode_snapshots(&[], works[0].origin())
+            .expect("BUG: cannot reload empty snapshots")
+            .is_empty());
+        assert!(decode_snapshots(&bytes[..bytes.len() - 1], works[0].origin()).is_err());
+    }
+}
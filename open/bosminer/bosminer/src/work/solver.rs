@@ -20,15 +20,20 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+use ii_logging::macros::*;
+
 use super::*;
+use crate::affinity;
 use crate::backend;
 use crate::node;
 
 use futures::channel::mpsc;
 use futures::lock::Mutex;
-use ii_async_compat::futures;
+use ii_async_compat::{futures, tokio};
 
-use std::sync::{Arc, Weak};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicIsize, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, MutexGuard as StdMutexGuard, Weak};
 use std::time;
 
 type WorkSolverPath = Vec<Arc<dyn node::WorkSolver>>;
@@ -53,6 +58,8 @@ pub struct SolverBuilder<T> {
     solution_sender: SolutionSender,
     /// Custom hierarchy builder object driven by `SolverBuilder`
     hierarchy_builder: Arc<dyn backend::HierarchyBuilder>,
+    /// Cores dedicated to work generation of all created work solvers
+    work_pinning: Option<affinity::Pinning>,
 }
 
 impl<T> SolverBuilder<T>
@@ -69,11 +76,23 @@ where
             node: NodeType::Base(base_work_solver),
             path: vec![],
             engine_receiver,
-            solution_sender: SolutionSender(solution_sender),
+            solution_sender: SolutionSender::new(solution_sender, Arc::new(WorkGeneration::new())),
             hierarchy_builder,
+            work_pinning: None,
         }
     }
 
+    /// Pin work generation of all work solvers created by this builder (and all its work hubs)
+    pub fn set_work_pinning(&mut self, work_pinning: Option<affinity::Pinning>) {
+        self.work_pinning = work_pinning;
+    }
+
+    /// Verify solutions of all work solvers created by this builder (and all its work hubs) from
+    /// the solving midstate (see `Solution::with_midstate_verification`)
+    pub fn set_midstate_verification(&mut self, midstate_verification: bool) {
+        self.solution_sender.midstate_verification = midstate_verification;
+    }
+
     #[inline]
     pub fn to_node(&self) -> &Arc<T> {
         match &self.node {
@@ -125,6 +144,7 @@ where
             engine_receiver: self.engine_receiver.clone(),
             solution_sender: self.solution_sender.clone(),
             hierarchy_builder: self.hierarchy_builder.clone(),
+            work_pinning: self.work_pinning.clone(),
         }
     }
 
@@ -140,16 +160,29 @@ where
         let inner_work_solver = Arc::new(Mutex::new(None));
 
         let path = self.get_path();
-        let work_generator = Generator::new(
+        let mut work_generator = Generator::new(
             self.engine_receiver.clone(),
             path,
             inner_work_solver.clone(),
         );
-        let solution_sender = self.solution_sender.clone();
+        work_generator.set_pinning(self.work_pinning.clone());
+        let midstate_count = work_generator.midstate_count().clone();
+        let switch = work_generator.switch().clone();
+        let hashrate_cap = work_generator.hashrate_cap().clone();
+        let generation = work_generator.generation().clone();
+        let solution_sender = self.solution_sender.for_work_solver(generation.clone());
+        let inflight = work_generator.inflight().clone();
 
         let work_solver = Arc::new(create(work_generator, solution_sender));
         self.call_hierarchy_builder(node::WorkSolverType::WorkSolver(work_solver.clone()))
             .await;
+        self.hierarchy_builder
+            .add_midstate_count(midstate_count)
+            .await;
+        self.hierarchy_builder.add_solver_switch(switch).await;
+        self.hierarchy_builder.add_hashrate_cap(hashrate_cap).await;
+        self.hierarchy_builder.add_work_generation(generation).await;
+        self.hierarchy_builder.add_inflight_work(inflight).await;
 
         // create weak reference to newly created work solver to prevent circular dependency
         *inner_work_solver.lock().await = Some(Arc::downgrade(
@@ -160,6 +193,484 @@ where
     }
 }
 
+/// Midstate count of work requested by a particular work solver. Work engines generate work with
+/// the maximal midstate count supported by the backend and `Generator` splits it to smaller pieces
+/// when lower count is requested. The count can be changed at runtime and every change bumps the
+/// generation so that pieces split with the previous count are thrown away.
+#[derive(Debug, Default)]
+pub struct MidstateCount {
+    /// Requested midstate count (`None` means the count provided by the work engine) together with
+    /// generation of this setting
+    inner: StdMutex<(Option<usize>, usize)>,
+}
+
+impl MidstateCount {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Request new midstate count for all subsequently generated work
+    /// It is expected that the count has been validated against the hardware capabilities.
+    pub fn set(&self, count: usize) {
+        assert!(count > 0, "BUG: requesting work without midstates");
+        let mut inner = self.inner.lock().expect("BUG: cannot lock midstate count");
+        *inner = (Some(count), inner.1.wrapping_add(1));
+    }
+
+    /// Return requested midstate count (if any)
+    pub fn get(&self) -> Option<usize> {
+        self.inner
+            .lock()
+            .expect("BUG: cannot lock midstate count")
+            .0
+    }
+
+    /// Return requested midstate count together with its generation
+    fn get_with_generation(&self) -> (Option<usize>, usize) {
+        *self.inner.lock().expect("BUG: cannot lock midstate count")
+    }
+}
+
+/// Strategy of distributing version space of a job among the work handed over to work solvers.
+/// More midstates in each work save SHA256 computation of the hardware (AsicBoost) while more
+/// work items keep deep work FIFOs of the hardware filled with finer grained work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distribution {
+    /// Each work carries the maximal midstate count supported by the backend so the job is
+    /// covered by the least number of work items
+    Midstates,
+    /// Each work carries single midstate and the job is covered by the most work items
+    WorkItems,
+}
+
+impl Distribution {
+    /// Midstate count of work generated with this strategy for backend which supports at most
+    /// `max_midstate_count` midstates
+    pub fn midstate_count(&self, max_midstate_count: usize) -> usize {
+        match self {
+            Self::Midstates => max_midstate_count,
+            Self::WorkItems => 1,
+        }
+    }
+}
+
+impl Default for Distribution {
+    fn default() -> Self {
+        Self::Midstates
+    }
+}
+
+impl std::str::FromStr for Distribution {
+    type Err = crate::error::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "midstates" => Ok(Self::Midstates),
+            "work-items" => Ok(Self::WorkItems),
+            _ => Err(crate::error::ErrorKind::General(format!(
+                "unknown work distribution '{}' (expected 'midstates' or 'work-items')",
+                value
+            ))
+            .into()),
+        }
+    }
+}
+
+impl std::fmt::Display for Distribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Midstates => write!(f, "midstates"),
+            Self::WorkItems => write!(f, "work items"),
+        }
+    }
+}
+
+/// Switch for enabling or disabling work assignment to a particular work solver at runtime.
+/// Disabled work solver is not torn down (it can still be monitored), only its `Generator` stops
+/// providing new work until the solver is enabled again.
+#[derive(Debug)]
+pub struct SolverSwitch {
+    sender: watch::Sender<bool>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl SolverSwitch {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(true);
+        Self { sender, receiver }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        if *self.receiver.borrow() != enabled {
+            self.sender
+                .broadcast(enabled)
+                .expect("BUG: solver switch without receiver");
+        }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    fn subscribe(&self) -> watch::Receiver<bool> {
+        self.receiver.clone()
+    }
+}
+
+/// Cap of hash rate of a particular work solver expressed as a fraction of its nominal hash rate.
+/// It is intended for demand-response (power) events when the miner has to temporarily reduce its
+/// consumption. The backend is responsible for converging to the capped hash rate smoothly (e.g.
+/// by ramping chip frequency) and for restoring the nominal hash rate when the cap is released.
+#[derive(Debug)]
+pub struct HashrateCap {
+    sender: watch::Sender<f64>,
+    receiver: watch::Receiver<f64>,
+}
+
+impl HashrateCap {
+    /// Fraction of nominal hash rate which represents released cap
+    pub const RELEASED: f64 = 1.0;
+
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(Self::RELEASED);
+        Self { sender, receiver }
+    }
+
+    /// Check if `fraction` can be used as a hash rate cap
+    #[inline]
+    pub fn is_valid(fraction: f64) -> bool {
+        fraction > 0.0 && fraction <= Self::RELEASED
+    }
+
+    /// Request work solver to run at `fraction` of its nominal hash rate
+    /// It is expected that the fraction has been validated with `is_valid`.
+    pub fn set(&self, fraction: f64) {
+        assert!(
+            Self::is_valid(fraction),
+            "BUG: invalid hash rate cap {}",
+            fraction
+        );
+        if *self.receiver.borrow() != fraction {
+            self.sender
+                .broadcast(fraction)
+                .expect("BUG: hash rate cap without receiver");
+        }
+    }
+
+    /// Restore nominal hash rate of the work solver
+    #[inline]
+    pub fn release(&self) {
+        self.set(Self::RELEASED);
+    }
+
+    /// Return currently requested fraction of nominal hash rate
+    #[inline]
+    pub fn get(&self) -> f64 {
+        *self.receiver.borrow()
+    }
+
+    #[inline]
+    pub fn is_capped(&self) -> bool {
+        self.get() < Self::RELEASED
+    }
+
+    /// Subscribe to changes of the cap so that the backend can react to them
+    pub fn subscribe(&self) -> watch::Receiver<f64> {
+        self.receiver.clone()
+    }
+}
+
+/// Reason why work solvers have to switch to a new work engine (i.e. why their generation is
+/// bumped)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerationCause {
+    /// Regular job update with the same previous block hash
+    JobUpdate,
+    /// Pool requested to abandon all previous jobs without change of the previous block hash
+    CleanJobs,
+    /// Pool changed extranonce which makes all previous jobs obsolete
+    ExtranonceChange,
+    /// Another pool became active
+    PoolFailover,
+    /// Another pool has been selected by an external profit-switching controller
+    ProfitSwitch,
+    /// Pool has been restarted on request
+    ManualRestart,
+    /// New block has been found on the network
+    PrevHashChange,
+}
+
+impl GenerationCause {
+    pub const COUNT: usize = 7;
+
+    pub const ALL: [GenerationCause; Self::COUNT] = [
+        GenerationCause::JobUpdate,
+        GenerationCause::CleanJobs,
+        GenerationCause::ExtranonceChange,
+        GenerationCause::PoolFailover,
+        GenerationCause::ProfitSwitch,
+        GenerationCause::ManualRestart,
+        GenerationCause::PrevHashChange,
+    ];
+}
+
+/// Number of distinct work engines (i.e. job updates) from which a particular work solver has
+/// started generating work. Comparing generations of all work solvers shows whether some of them
+/// lag behind the job updates.
+#[derive(Debug, Default)]
+pub struct WorkGeneration {
+    counter: AtomicU64,
+    /// The engine observed most recently (weak reference to not prolong the life of old jobs)
+    last_engine: StdMutex<Option<Weak<dyn Engine>>>,
+    /// Barrier acknowledged with every new engine together with index of the work solver
+    barrier: StdMutex<Option<(Arc<JobBarrier>, usize)>>,
+    /// The most recent generation whose work has been delivered to the hardware
+    delivered: AtomicU64,
+}
+
+impl WorkGeneration {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[inline]
+    pub fn get(&self) -> u64 {
+        self.counter.load(Ordering::Relaxed)
+    }
+
+    /// Check if the work stamped with `generation` has been superseded by a newer engine of this
+    /// work solver. Work without the stamp is never considered stale.
+    pub fn is_stale(&self, generation: Option<u64>) -> bool {
+        generation.map_or(false, |generation| generation < self.get())
+    }
+
+    /// Acknowledge every new engine to `barrier` as work solver `solver_idx` once its first work
+    /// has been delivered (see `confirm_delivery`)
+    pub fn join_barrier(&self, barrier: Arc<JobBarrier>, solver_idx: usize) {
+        barrier.set_participating(solver_idx, true);
+        self.barrier
+            .lock()
+            .expect("BUG: cannot lock work generation barrier")
+            .replace((barrier, solver_idx));
+    }
+
+    /// Bump the generation when `engine` differs from the previously observed one
+    fn observe(&self, engine: &DynEngine) {
+        let mut last_engine = self
+            .last_engine
+            .lock()
+            .expect("BUG: cannot lock work generation");
+        let is_current = last_engine
+            .as_ref()
+            .and_then(|last_engine| last_engine.upgrade())
+            .map_or(false, |last_engine| Arc::ptr_eq(&last_engine, engine));
+        if !is_current {
+            last_engine.replace(Arc::downgrade(engine));
+            self.counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Acknowledge the engine of the current generation to the barrier when `work` is the first
+    /// work generated from it which has been delivered to the hardware. Delivery of work from
+    /// previous engines is ignored.
+    pub fn confirm_delivery(&self, work: &Assignment) {
+        let generation = match work.generation {
+            Some(generation) => generation,
+            None => return,
+        };
+        // the generation cannot be bumped while the last engine is locked
+        let last_engine = self
+            .last_engine
+            .lock()
+            .expect("BUG: cannot lock work generation");
+        if generation != self.get()
+            || self.delivered.swap(generation, Ordering::Relaxed) == generation
+        {
+            return;
+        }
+        let engine = match last_engine.as_ref().and_then(|engine| engine.upgrade()) {
+            Some(engine) => engine,
+            None => return,
+        };
+        if let Some((barrier, solver_idx)) = self
+            .barrier
+            .lock()
+            .expect("BUG: cannot lock work generation barrier")
+            .as_ref()
+        {
+            barrier.ack(*solver_idx, &engine, time::Instant::now());
+        }
+    }
+}
+
+/// Job which has been started by all participating work solvers
+#[derive(Debug, Clone)]
+pub struct LiveJob {
+    engine: Weak<dyn Engine>,
+    /// Time between the first and the last work solver starting to generate work for the job
+    pub spread: time::Duration,
+}
+
+#[derive(Debug, Default)]
+struct JobBarrierInner {
+    /// Work solvers which are expected to start every job (the enabled ones)
+    participants: HashSet<usize>,
+    /// Engine of the job which is not live yet
+    pending: Option<Weak<dyn Engine>>,
+    /// Work solvers which have already started the pending job
+    acked: HashSet<usize>,
+    /// Time of the first acknowledgement of the pending job
+    first_ack: Option<time::Instant>,
+    live: Option<LiveJob>,
+    live_count: u64,
+}
+
+impl JobBarrierInner {
+    fn is_live(&self, engine: &DynEngine) -> bool {
+        self.live
+            .as_ref()
+            .and_then(|live| live.engine.upgrade())
+            .map_or(false, |live_engine| Arc::ptr_eq(&live_engine, engine))
+    }
+
+    fn try_pass(&mut self, now: time::Instant) -> bool {
+        let engine = match self.pending.as_ref() {
+            Some(engine) if self.participants.is_subset(&self.acked) => engine.clone(),
+            _ => return false,
+        };
+        let first_ack = self.first_ack.unwrap_or(now);
+        self.live = Some(LiveJob {
+            engine,
+            spread: now.saturating_duration_since(first_ack),
+        });
+        self.live_count += 1;
+        self.pending = None;
+        self.acked.clear();
+        self.first_ack = None;
+        true
+    }
+}
+
+/// Barrier which marks a job as live once all participating work solvers have acknowledged that
+/// they have started generating work for it. The job may be superseded before it becomes live
+/// when some work solver lags behind the job updates.
+#[derive(Debug, Default)]
+pub struct JobBarrier {
+    inner: StdMutex<JobBarrierInner>,
+}
+
+impl JobBarrier {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn lock(&self) -> StdMutexGuard<JobBarrierInner> {
+        self.inner.lock().expect("BUG: cannot lock job barrier")
+    }
+
+    /// Include or exclude work solver `solver_idx` (e.g. disabled chain) from the barrier
+    pub fn set_participating(&self, solver_idx: usize, participating: bool) {
+        let mut inner = self.lock();
+        if participating {
+            inner.participants.insert(solver_idx);
+        } else {
+            inner.participants.remove(&solver_idx);
+            // the pending job may now wait only for solvers which have already started it
+            inner.try_pass(time::Instant::now());
+        }
+    }
+
+    /// Acknowledge that work solver `solver_idx` has started generating work from `engine` at
+    /// `now`. Returns `true` when the job has become live by this acknowledgement.
+    pub fn ack(&self, solver_idx: usize, engine: &DynEngine, now: time::Instant) -> bool {
+        let mut inner = self.lock();
+        let is_pending = inner
+            .pending
+            .as_ref()
+            .and_then(|pending| pending.upgrade())
+            .map_or(false, |pending| Arc::ptr_eq(&pending, engine));
+        if !is_pending {
+            if inner.is_live(engine) {
+                // late acknowledgement from a solver which has not participated in the job
+                return false;
+            }
+            // new job supersedes the pending one
+            inner.pending = Some(Arc::downgrade(engine));
+            inner.acked.clear();
+            inner.first_ack = Some(now);
+        }
+        inner.acked.insert(solver_idx);
+        inner.try_pass(now)
+    }
+
+    /// Check if the job of `engine` has been started by all participating work solvers
+    pub fn is_live(&self, engine: &DynEngine) -> bool {
+        self.lock().is_live(engine)
+    }
+
+    /// The most recent live job
+    pub fn live_job(&self) -> Option<LiveJob> {
+        self.lock().live.clone()
+    }
+
+    /// Number of jobs which have become live
+    pub fn live_count(&self) -> u64 {
+        self.lock().live_count
+    }
+}
+
+/// Number of the most recent assignments remembered by `InflightWork`
+const INFLIGHT_WORK_CAPACITY: usize = 16;
+
+/// The most recent work handed over to a particular work solver. The backend usually keeps only a
+/// few assignments in the hardware so this is a good approximation of the work in flight and it
+/// can be dumped for later replay when the miner crashes.
+#[derive(Debug, Default)]
+pub struct InflightWork {
+    works: StdMutex<VecDeque<Assignment>>,
+}
+
+impl InflightWork {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    fn record(&self, work: &Assignment) {
+        let mut works = self.works.lock().expect("BUG: cannot lock in-flight work");
+        if works.len() == INFLIGHT_WORK_CAPACITY {
+            works.pop_front();
+        }
+        works.push_back(work.clone());
+    }
+
+    /// Capture snapshots of the remembered work from the oldest one. It does not block on the
+    /// executor so it is safe to call it from a panic hook.
+    pub fn snapshot(&self) -> Vec<replay::WorkSnapshot> {
+        // the lock may be poisoned by the panic which is being handled
+        let works = match self.works.lock() {
+            Ok(works) => works,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        works
+            .iter()
+            .map(|work| {
+                replay::WorkSnapshot::new(work).with_generation(work.generation.unwrap_or_default())
+            })
+            .collect()
+    }
+}
+
+/// Work split from engine assignment which has not been given to the work solver yet
+#[derive(Debug, Clone)]
+struct PendingWork {
+    /// Engine which generated the original work
+    engine: DynEngine,
+    /// Generation of midstate count used for splitting of the work
+    generation: usize,
+    works: VecDeque<Assignment>,
+}
+
 /// Generator is responsible for accepting a `WorkEngine` and draining as much
 /// `MiningWork` as possible from it.
 #[derive(Debug, Clone)]
@@ -172,6 +683,21 @@ pub struct Generator {
     work_solver: Arc<Mutex<Option<Weak<dyn node::WorkSolver>>>>,
     /// Source of trait objects that implement `WorkEngine` interface
     engine_receiver: EngineReceiver,
+    /// Midstate count requested by the work solver
+    midstate_count: Arc<MidstateCount>,
+    /// Switch for (dis)allowing work generation and its subscription
+    switch: Arc<SolverSwitch>,
+    switch_receiver: watch::Receiver<bool>,
+    /// Cap of hash rate requested from the work solver
+    hashrate_cap: Arc<HashrateCap>,
+    /// Generation of work engines used by this generator
+    generation: Arc<WorkGeneration>,
+    /// The most recent work given to the work solver
+    inflight: Arc<InflightWork>,
+    /// Remaining pieces of the last split work
+    pending_work: Option<PendingWork>,
+    /// Cores dedicated to work generation
+    pinning: Option<affinity::Pinning>,
 }
 
 impl Generator {
@@ -180,33 +706,213 @@ impl Generator {
         path: WorkSolverPath,
         work_solver: Arc<Mutex<Option<Weak<dyn node::WorkSolver>>>>,
     ) -> Self {
+        let switch = Arc::new(SolverSwitch::new());
         Self {
             path,
             work_solver,
             engine_receiver,
+            midstate_count: Arc::new(MidstateCount::new()),
+            switch_receiver: switch.subscribe(),
+            switch,
+            hashrate_cap: Arc::new(HashrateCap::new()),
+            generation: Arc::new(WorkGeneration::new()),
+            inflight: Arc::new(InflightWork::new()),
+            pending_work: None,
+            pinning: None,
         }
     }
 
-    /// Loops until new work is available or no more `WorkEngines` are supplied (signals
-    /// Generator shutdown)
-    pub async fn generate(&mut self) -> Option<Assignment> {
-        let work_solver = self
-            .work_solver
+    /// Generate work only on cores of `pinning`. The blocking tasks of `generate_parallel` are
+    /// pinned by the generator itself while the backend is responsible for running its task
+    /// calling `generate` on a pinned thread (see `pinning`).
+    pub fn set_pinning(&mut self, pinning: Option<affinity::Pinning>) {
+        self.pinning = pinning;
+    }
+
+    /// Return cores dedicated to work generation
+    #[inline]
+    pub fn pinning(&self) -> Option<&affinity::Pinning> {
+        self.pinning.as_ref()
+    }
+
+    /// Return handle for changing midstate count of generated work at runtime
+    #[inline]
+    pub fn midstate_count(&self) -> &Arc<MidstateCount> {
+        &self.midstate_count
+    }
+
+    /// Return handle for enabling/disabling work generation at runtime
+    #[inline]
+    pub fn switch(&self) -> &Arc<SolverSwitch> {
+        &self.switch
+    }
+
+    /// Return handle for capping hash rate of the work solver at runtime
+    #[inline]
+    pub fn hashrate_cap(&self) -> &Arc<HashrateCap> {
+        &self.hashrate_cap
+    }
+
+    /// Return handle for reading generation of work engines used by this generator
+    #[inline]
+    pub fn generation(&self) -> &Arc<WorkGeneration> {
+        &self.generation
+    }
+
+    /// Return handle for capturing the most recent work generated for the work solver
+    #[inline]
+    pub fn inflight(&self) -> &Arc<InflightWork> {
+        &self.inflight
+    }
+
+    /// The backend has to confirm that generated `work` has been actually sent to the hardware
+    /// (see `WorkGeneration::confirm_delivery`)
+    #[inline]
+    pub fn confirm_delivery(&self, work: &Assignment) {
+        self.generation.confirm_delivery(work);
+    }
+
+    /// Block work generation for as long as the work solver is disabled
+    async fn wait_for_enabled(&mut self) {
+        while !*self.switch_receiver.borrow() {
+            self.switch_receiver
+                .recv()
+                .await
+                .expect("BUG: solver switch has been dropped");
+        }
+    }
+
+    /// Split `work` to pieces with at most `midstate_count` midstates
+    fn split_work(mut work: Assignment, midstate_count: Option<usize>) -> Vec<Assignment> {
+        let midstate_count = match midstate_count {
+            Some(count) if count < work.midstates.len() => count,
+            _ => return vec![work],
+        };
+        let mut midstates = mem::replace(&mut work.midstates, vec![]);
+        let mut works = Vec::with_capacity((midstates.len() + midstate_count - 1) / midstate_count);
+        while !midstates.is_empty() {
+            let rest = midstates.split_off(midstate_count.min(midstates.len()));
+            works.push(Assignment {
+                midstates,
+                ..work.clone()
+            });
+            midstates = rest;
+        }
+        works
+    }
+
+    /// Return next piece of previously split work as long as it was split with the current
+    /// midstate count and originates from the current engine
+    fn take_pending_work(&mut self) -> Option<Assignment> {
+        let pending_work = self.pending_work.as_mut()?;
+        let (_, generation) = self.midstate_count.get_with_generation();
+        if pending_work.generation == generation
+            && self.engine_receiver.is_current(&pending_work.engine)
+        {
+            if let Some(work) = pending_work.works.pop_front() {
+                return Some(work);
+            }
+        }
+        self.pending_work = None;
+        None
+    }
+
+    /// Split `work` from the `engine` by the requested midstate count, return the first piece and
+    /// keep the rest for subsequent calls
+    fn split_engine_work(&mut self, engine: &DynEngine, work: Assignment) -> Assignment {
+        let (midstate_count, generation) = self.midstate_count.get_with_generation();
+        let mut works: VecDeque<_> = Self::split_work(work, midstate_count).into();
+        let work = works.pop_front().expect("BUG: work split to nothing");
+        self.pending_work = if works.is_empty() {
+            None
+        } else {
+            Some(PendingWork {
+                engine: engine.clone(),
+                generation,
+                works,
+            })
+        };
+        work
+    }
+
+    async fn get_work_solver(&self) -> Arc<dyn node::WorkSolver> {
+        self.work_solver
             .lock()
             .await
             .as_ref()
             .expect("BUG: calling work generator before full registration")
             .upgrade()
-            .expect("BUG: calling work generator after node destruction");
+            .expect("BUG: calling work generator after node destruction")
+    }
+
+    /// Reject work which would waste hashing capacity due to a bug in the work engine and count it
+    /// in the work solver statistics
+    fn validate_work(work: &Assignment, work_solver: &Arc<dyn node::WorkSolver>) -> bool {
+        match work.validate() {
+            Ok(()) => true,
+            Err(e) => {
+                error!("BUG: work engine generated invalid work: {}", e);
+                work_solver.work_solver_stats().invalid_work().inc();
+                false
+            }
+        }
+    }
+
+    /// Account generated work on the client side and in all work solvers in the path
+    /// Returns `false` when the origin of the work has been removed and the work should be
+    /// thrown away because no one will receive any solution
+    async fn account_work(
+        &self,
+        work: &mut Assignment,
+        work_solver: &Arc<dyn node::WorkSolver>,
+    ) -> bool {
+        // determine how much work has been generated for current work assignment
+        let work_amount = work.generated_work_amount() as u64;
+        // account generated work on the client side
+        match work.origin().upgrade() {
+            Some(origin) => origin.client_stats().generated_work().add(work_amount),
+            None => return false,
+        }
 
+        work.solver_id = work_solver.get_id();
+        work.generation = Some(self.generation.get());
+        // account generated work in all work solvers in the path
+        let now = time::SystemTime::now();
+        for node in self.path.iter().chain(iter::once(work_solver)) {
+            let work_solver_stats = node.work_solver_stats();
+            // Arc does not support dynamic casting to trait bounds so there must be used
+            // another Arc indirection with implemented `node::Info` trait.
+            // This blanket implementation can be found in the module `crate::node`:
+            // impl<T: ?Sized + Info> Info for Arc<T> {}
+            work.path.push(Arc::new(node.clone()));
+            work_solver_stats.generated_work().add(work_amount);
+            work_solver_stats.last_work_time().touch(now).await;
+        }
+        self.inflight.record(work);
+        true
+    }
+
+    /// Loops until new work is available or no more `WorkEngines` are supplied (signals
+    /// Generator shutdown)
+    pub async fn generate(&mut self) -> Option<Assignment> {
+        let work_solver = self.get_work_solver().await;
+
+        self.wait_for_enabled().await;
+        while let Some(mut work) = self.take_pending_work() {
+            if self.account_work(&mut work, &work_solver).await {
+                return Some(work);
+            }
+        }
         loop {
+            self.wait_for_enabled().await;
             let engine = match self.engine_receiver.get_engine().await {
                 // end of stream
                 None => return None,
                 Some(value) => value,
             };
+            self.generation.observe(&engine);
             // try to generate new work from engine
-            let mut work = match engine.next_work() {
+            let work = match engine.next_work() {
                 // one or more competing work engines are exhausted
                 // try to gen new work engine
                 // NOTE: this can happen simultaneously for multiple parallel generators because
@@ -221,30 +927,120 @@ impl Generator {
                     value
                 }
             };
-            // determine how much work has been generated for current work assignment
-            let work_amount = work.generated_work_amount() as u64;
-            // account generated work on the client side
-            if let Some(origin) = work.origin().upgrade() {
-                origin.client_stats().generated_work().add(work_amount);
-            } else {
+            if !Self::validate_work(&work, &work_solver) {
+                continue;
+            }
+            let mut work = self.split_engine_work(&engine, work);
+            if !self.account_work(&mut work, &work_solver).await {
                 // Origin has been removed and no one will receive any solution
                 engine.terminate();
+                self.pending_work = None;
                 continue;
             }
+            return Some(work);
+        }
+    }
 
-            // account generated work in all work solvers in the path
-            let now = time::SystemTime::now();
-            for node in self.path.iter().chain(iter::once(&work_solver)) {
-                let work_solver_stats = node.work_solver_stats();
-                // Arc does not support dynamic casting to trait bounds so there must be used
-                // another Arc indirection with implemented `node::Info` trait.
-                // This blanket implementation can be found in the module `crate::node`:
-                // impl<T: ?Sized + Info> Info for Arc<T> {}
-                work.path.push(Arc::new(node.clone()));
-                work_solver_stats.generated_work().add(work_amount);
-                work_solver_stats.last_work_time().touch(now).await;
+    /// Generates up to `count` work assignments from the current engine at once. Computation of
+    /// midstates is dispatched to at most `threads` blocking tasks so that backends with many
+    /// chains can fill their queues without stalling the executor. The returned work is sorted
+    /// in the same order as the engine would produce it sequentially (by nTime and version).
+    /// When lower midstate count is requested, each generated assignment is split and all its
+    /// pieces are returned. Empty vector is returned only when no more `WorkEngines` are supplied.
+    pub async fn generate_parallel(&mut self, count: usize, threads: usize) -> Vec<Assignment> {
+        assert!(count > 0, "BUG: requesting empty batch of work");
+        assert!(threads > 0, "BUG: work generation requires at least one thread");
+        let work_solver = self.get_work_solver().await;
+
+        // finish work previously split by `generate` first
+        self.wait_for_enabled().await;
+        let mut pending = Vec::new();
+        while let Some(mut work) = self.take_pending_work() {
+            if self.account_work(&mut work, &work_solver).await {
+                pending.push(work);
             }
-            return Some(work);
+        }
+        if !pending.is_empty() {
+            return pending;
+        }
+        loop {
+            self.wait_for_enabled().await;
+            let engine = match self.engine_receiver.get_engine().await {
+                // end of stream
+                None => return vec![],
+                Some(value) => value,
+            };
+            self.generation.observe(&engine);
+            // shared budget of work that is decremented by all generating tasks
+            let remaining = Arc::new(AtomicIsize::new(count as isize));
+            let tasks: Vec<_> = (0..threads.min(count))
+                .map(|_| {
+                    let engine = engine.clone();
+                    let remaining = remaining.clone();
+                    let pinning = self.pinning.clone();
+                    tokio::task::spawn_blocking(move || {
+                        // the blocking thread is shared so its affinity is restored afterwards
+                        let _pinned = pinning.as_ref().and_then(|pinning| pinning.pin());
+                        let mut works = Vec::new();
+                        let mut exhausted = false;
+                        while remaining.fetch_sub(1, Ordering::Relaxed) > 0 {
+                            match engine.next_work() {
+                                LoopState::Exhausted => break,
+                                LoopState::Continue(work) => works.push(work),
+                                LoopState::Break(work) => {
+                                    works.push(work);
+                                    exhausted = true;
+                                    break;
+                                }
+                            }
+                        }
+                        (works, exhausted)
+                    })
+                })
+                .collect();
+
+            let mut works = Vec::with_capacity(count);
+            for task in tasks {
+                let (task_works, exhausted) =
+                    task.await.expect("BUG: work generation task panicked");
+                if exhausted {
+                    // only one task can get the last work from the engine
+                    self.engine_receiver.handle_exhausted(engine.clone());
+                }
+                works.extend(task_works);
+            }
+            works.retain(|work| Self::validate_work(work, &work_solver));
+            if works.is_empty() {
+                // the engine has been exhausted by other generators in the meantime (or it has
+                // generated only invalid work)
+                continue;
+            }
+            // restore the order of the work as it was generated by the engine
+            works.sort_by_key(|work| {
+                (
+                    work.ntime,
+                    work.midstates.first().map(|midstate| midstate.version),
+                )
+            });
+
+            let midstate_count = self.midstate_count.get();
+            let works: Vec<_> = works
+                .into_iter()
+                .flat_map(|work| Self::split_work(work, midstate_count))
+                .collect();
+
+            let mut assigned = Vec::with_capacity(works.len());
+            for mut work in works {
+                if self.account_work(&mut work, &work_solver).await {
+                    assigned.push(work);
+                }
+            }
+            if assigned.is_empty() {
+                // Origin has been removed and no one will receive any solution
+                engine.terminate();
+                continue;
+            }
+            return assigned;
         }
     }
 }
@@ -252,12 +1048,555 @@ impl Generator {
 /// This struct is to be passed to the underlying mining backend. It allows submission of
 /// `work::Solution`
 #[derive(Debug, Clone)]
-pub struct SolutionSender(mpsc::UnboundedSender<Solution>);
+pub struct SolutionSender {
+    sender: mpsc::UnboundedSender<Solution>,
+    /// Hashes of solutions recently verified for the work solver which owns this sender
+    verification_cache: Arc<StdMutex<VerificationCache>>,
+    /// Generation of the work solver which owns this sender
+    generation: Arc<WorkGeneration>,
+    /// Solutions are verified from the solving midstate
+    midstate_verification: bool,
+}
 
 impl SolutionSender {
+    fn new(sender: mpsc::UnboundedSender<Solution>, generation: Arc<WorkGeneration>) -> Self {
+        Self {
+            sender,
+            verification_cache: Arc::new(StdMutex::new(VerificationCache::new(
+                VERIFICATION_CACHE_SIZE,
+            ))),
+            generation,
+            midstate_verification: false,
+        }
+    }
+
+    /// Create sender to the same solution queue for a new work solver with its own cache of
+    /// recently verified solutions
+    fn for_work_solver(&self, generation: Arc<WorkGeneration>) -> Self {
+        Self {
+            midstate_verification: self.midstate_verification,
+            ..Self::new(self.sender.clone(), generation)
+        }
+    }
+
+    /// Send the solution to the queue. The solution is classified as stale when the work solver
+    /// has already switched to a newer engine than the one of the solved work.
     pub fn send(&self, solution: Solution) {
-        self.0
-            .unbounded_send(solution)
+        let stale = self.generation.is_stale(solution.generation());
+        self.sender
+            .unbounded_send(
+                solution
+                    .with_verification_cache(self.verification_cache.clone())
+                    .with_stale(stale)
+                    .with_midstate_verification(self.midstate_verification),
+            )
             .expect("solution queue send failed");
     }
+
+    /// Number of solution verifications which have been served from the cache of recently
+    /// verified solutions of the work solver
+    pub fn verification_cache_hits(&self) -> u64 {
+        self.verification_cache
+            .lock()
+            .expect("BUG: cannot lock verification cache")
+            .hits()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    use std::collections::HashSet;
+
+    const MIDSTATE_COUNT: usize = 4;
+
+    fn create_generator(
+        job: Arc<dyn job::Bitcoin>,
+    ) -> (EngineSender, Arc<dyn node::WorkSolver>, Generator) {
+        let work_solver: Arc<dyn node::WorkSolver> = test_utils::create_test_work_solver();
+        let (engine_sender, engine_receiver) = engine_channel(IgnoreEvents);
+        engine_sender.broadcast_engine(Arc::new(engine::VersionRolling::new(
+            job,
+            MIDSTATE_COUNT,
+        )));
+        let generator = Generator::new(
+            engine_receiver,
+            vec![],
+            Arc::new(Mutex::new(Some(Arc::downgrade(&work_solver)))),
+        );
+        (engine_sender, work_solver, generator)
+    }
+
+    /// Work engine with a bug which does not roll the version of the first generated work
+    #[derive(Debug)]
+    struct DuplicateMidstateEngine {
+        inner: engine::VersionRolling,
+        broken: std::sync::atomic::AtomicBool,
+    }
+
+    impl Engine for DuplicateMidstateEngine {
+        fn terminate(&self) {
+            self.inner.terminate();
+        }
+
+        fn is_exhausted(&self) -> bool {
+            self.inner.is_exhausted()
+        }
+
+        fn next_work(&self) -> LoopState<Assignment> {
+            self.inner.next_work().map(|mut work| {
+                if self.broken.swap(false, Ordering::Relaxed) {
+                    work.midstates[1] = work.midstates[0].clone();
+                }
+                work
+            })
+        }
+    }
+
+    fn get_generated_work(work_solver: &Arc<dyn node::WorkSolver>) -> u64 {
+        *work_solver
+            .work_solver_stats()
+            .generated_work()
+            .take_snapshot()
+    }
+
+    #[tokio::test]
+    async fn test_generate_parallel() {
+        const BATCH_SIZE: usize = 256;
+
+        for block in test_utils::TEST_BLOCKS.iter() {
+            let job: Arc<dyn job::Bitcoin> = Arc::new(*block);
+            let (_engine_sender, work_solver, mut generator) = create_generator(job.clone());
+
+            let works = generator.generate_parallel(BATCH_SIZE, 8).await;
+            assert_eq!(works.len(), BATCH_SIZE);
+            assert_eq!(
+                get_generated_work(&work_solver),
+                (BATCH_SIZE * MIDSTATE_COUNT) as u64
+            );
+
+            let mut versions = HashSet::new();
+            let mut last_key = None;
+            for work in works.iter() {
+                // the work has to be attributed to the generating work solver
+                assert_eq!(work.path.len(), 1);
+                assert_eq!(work.midstates.len(), MIDSTATE_COUNT);
+                // the work has to be returned in the order of the engine
+                let key = (work.ntime, work.midstates[0].version);
+                assert!(last_key < Some(key));
+                last_key = Some(key);
+
+                for midstate in work.midstates.iter() {
+                    // every version has to be generated exactly once
+                    assert!(versions.insert((work.ntime, midstate.version)));
+                    // and the midstate has to correspond to the version
+                    let header = ii_bitcoin::BlockHeader {
+                        version: midstate.version,
+                        previous_hash: job.previous_hash().into_inner(),
+                        merkle_root: job.merkle_root().into_inner(),
+                        ..Default::default()
+                    };
+                    assert_eq!(header.midstate(), midstate.state);
+                }
+            }
+
+            // sequential generation has to continue right after the parallel batch
+            let work = generator.generate().await.expect("BUG: missing work");
+            assert!(last_key < Some((work.ntime, work.midstates[0].version)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_parallel_pinned() {
+        const BATCH_SIZE: usize = 16;
+        const THREADS: usize = 4;
+
+        let job: Arc<dyn job::Bitcoin> = Arc::new(test_utils::TEST_BLOCKS[0]);
+        let (_engine_sender, _work_solver, mut generator) = create_generator(job);
+        let affinity = Arc::new(affinity::test::TestAffinity::new(vec![0, 1, 2, 3]));
+        generator.set_pinning(Some(affinity::Pinning::new(affinity.clone(), vec![2, 3])));
+
+        let works = generator.generate_parallel(BATCH_SIZE, THREADS).await;
+        assert_eq!(works.len(), BATCH_SIZE);
+        // every generating task is pinned to the configured cores and then restored
+        let calls = affinity.calls();
+        assert_eq!(calls.len(), 2 * THREADS);
+        assert_eq!(
+            calls.iter().filter(|cores| **cores == vec![2, 3]).count(),
+            THREADS
+        );
+    }
+
+    /// Rough benchmark of parallel work generation (run with `--ignored --nocapture`)
+    #[tokio::test(threaded_scheduler)]
+    #[ignore]
+    async fn bench_generate_parallel() {
+        const BATCH_SIZE: usize = 4096;
+
+        let job: Arc<dyn job::Bitcoin> = Arc::new(test_utils::TEST_BLOCKS[0]);
+        for threads in [1, 2, 4, 8].iter() {
+            let (_engine_sender, _work_solver, mut generator) = create_generator(job.clone());
+
+            let start = time::Instant::now();
+            let works = generator.generate_parallel(BATCH_SIZE, *threads).await;
+            let elapsed = start.elapsed();
+            assert_eq!(works.len(), BATCH_SIZE);
+            println!(
+                "{} thread(s): {} work in {:?} ({:.0} work/s)",
+                threads,
+                BATCH_SIZE,
+                elapsed,
+                BATCH_SIZE as f64 / elapsed.as_secs_f64()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_midstate_count() {
+        let job: Arc<dyn job::Bitcoin> = Arc::new(test_utils::TEST_BLOCKS[0]);
+        let (_engine_sender, work_solver, mut generator) = create_generator(job);
+        let midstate_count = generator.midstate_count().clone();
+
+        // the work is generated with the count of the engine by default
+        let work = generator.generate().await.unwrap();
+        assert_eq!(work.midstates.len(), MIDSTATE_COUNT);
+        let mut last_version = work.midstates[MIDSTATE_COUNT - 1].version;
+
+        // the work from the engine is split into pieces with the requested midstate count
+        midstate_count.set(2);
+        for _ in 0..2 {
+            let work = generator.generate().await.unwrap();
+            assert_eq!(work.midstates.len(), 2);
+            assert_eq!(work.path.len(), 1);
+            for midstate in work.midstates.iter() {
+                assert!(midstate.version > last_version);
+                last_version = midstate.version;
+            }
+        }
+        assert_eq!(get_generated_work(&work_solver), 2 * MIDSTATE_COUNT as u64);
+
+        // change of the count throws away the rest of already split work
+        let work = generator.generate().await.unwrap();
+        assert_eq!(work.midstates.len(), 2);
+        let skipped_version = work.midstates[1].version;
+        midstate_count.set(1);
+        let mut next_version = skipped_version + (3 << ii_bitcoin::BIP320_VERSION_SHIFT);
+        for _ in 0..MIDSTATE_COUNT {
+            let work = generator.generate().await.unwrap();
+            assert_eq!(work.midstates.len(), 1);
+            assert_eq!(work.midstates[0].version, next_version);
+            next_version += 1 << ii_bitcoin::BIP320_VERSION_SHIFT;
+        }
+
+        // parallel generation returns all pieces of split work
+        let works = generator.generate_parallel(2, 2).await;
+        assert_eq!(works.len(), 2 * MIDSTATE_COUNT);
+        assert!(works.iter().all(|work| work.midstates.len() == 1));
+
+        // pieces of work split by sequential generation are returned first
+        midstate_count.set(2);
+        let work = generator.generate().await.unwrap();
+        let works = generator.generate_parallel(2, 2).await;
+        assert_eq!(works.len(), 1);
+        assert_eq!(works[0].midstates.len(), 2);
+        assert!(works[0].midstates[0].version > work.midstates[1].version);
+    }
+
+    #[tokio::test]
+    async fn test_distribution() {
+        const VERSION_COUNT: usize = 16;
+
+        assert_eq!(Distribution::default(), Distribution::Midstates);
+        assert_eq!(
+            "midstates".parse::<Distribution>().ok(),
+            Some(Distribution::Midstates)
+        );
+        assert_eq!(
+            "work-items".parse::<Distribution>().ok(),
+            Some(Distribution::WorkItems)
+        );
+        assert!("items".parse::<Distribution>().is_err());
+        for &(distribution, midstate_count) in [
+            (Distribution::Midstates, MIDSTATE_COUNT),
+            (Distribution::WorkItems, 1),
+        ]
+        .iter()
+        {
+            let job: Arc<dyn job::Bitcoin> = Arc::new(test_utils::TEST_BLOCKS[0]);
+            let (_engine_sender, work_solver, mut generator) = create_generator(job);
+            assert_eq!(distribution.midstate_count(MIDSTATE_COUNT), midstate_count);
+            generator
+                .midstate_count()
+                .set(distribution.midstate_count(MIDSTATE_COUNT));
+
+            // the same part of version space is covered by different number of work items
+            let mut versions = HashSet::new();
+            let work_count = VERSION_COUNT / midstate_count;
+            for _ in 0..work_count {
+                let work = generator.generate().await.unwrap();
+                assert_eq!(work.midstates.len(), midstate_count);
+                for midstate in work.midstates.iter() {
+                    assert!(versions.insert(midstate.version));
+                }
+            }
+            assert_eq!(versions.len(), VERSION_COUNT);
+            assert_eq!(get_generated_work(&work_solver), VERSION_COUNT as u64);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_solver_switch() {
+        const TIMEOUT: time::Duration = time::Duration::from_millis(100);
+
+        let job: Arc<dyn job::Bitcoin> = Arc::new(test_utils::TEST_BLOCKS[0]);
+        let (engine_sender, enabled_solver, mut enabled_generator) = create_generator(job);
+        // second chain shares the same engines
+        let disabled_solver: Arc<dyn node::WorkSolver> = test_utils::create_test_work_solver();
+        let mut disabled_generator = Generator::new(
+            enabled_generator.engine_receiver.clone(),
+            vec![],
+            Arc::new(Mutex::new(Some(Arc::downgrade(&disabled_solver)))),
+        );
+        let switch = disabled_generator.switch().clone();
+        assert!(switch.is_enabled());
+
+        switch.set_enabled(false);
+        assert!(!switch.is_enabled());
+        assert!(enabled_generator.generate().await.is_some());
+        assert!(disabled_generator
+            .generate()
+            .timeout(TIMEOUT)
+            .await
+            .is_err());
+        assert!(disabled_generator
+            .generate_parallel(2, 2)
+            .timeout(TIMEOUT)
+            .await
+            .is_err());
+        assert_eq!(get_generated_work(&enabled_solver), MIDSTATE_COUNT as u64);
+        assert_eq!(get_generated_work(&disabled_solver), 0);
+
+        // re-enabled work solver resumes waiting generator
+        let resumed = tokio::spawn(async move { disabled_generator.generate().await.is_some() });
+        switch.set_enabled(true);
+        assert!(resumed.await.expect("BUG: generator task failed"));
+        assert_eq!(get_generated_work(&disabled_solver), MIDSTATE_COUNT as u64);
+        drop(engine_sender);
+    }
+
+    #[tokio::test]
+    async fn test_work_generation() {
+        let job: Arc<dyn job::Bitcoin> = Arc::new(test_utils::TEST_BLOCKS[0]);
+        let (engine_sender, _work_solver, mut generator) = create_generator(job);
+        let generation = generator.generation().clone();
+        assert_eq!(generation.get(), 0);
+
+        // more work from the same engine does not change the generation
+        for _ in 0..3 {
+            assert!(generator.generate().await.is_some());
+        }
+        assert_eq!(generation.get(), 1);
+
+        // every job update bumps the generation
+        for (i, block) in test_utils::TEST_BLOCKS[1..].iter().enumerate() {
+            engine_sender.broadcast_engine(Arc::new(engine::VersionRolling::new(
+                Arc::new(*block),
+                MIDSTATE_COUNT,
+            )));
+            assert!(!generator.generate_parallel(2, 2).await.is_empty());
+            assert_eq!(generation.get(), i as u64 + 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_per_chain_staleness() {
+        // two chains mining jobs from independent sources
+        let (engine_sender_a, _work_solver_a, mut generator_a) =
+            create_generator(Arc::new(test_utils::TEST_BLOCKS[0]));
+        let (_engine_sender_b, _work_solver_b, mut generator_b) =
+            create_generator(Arc::new(test_utils::TEST_BLOCKS[1]));
+        let generation_a = generator_a.generation().clone();
+        let generation_b = generator_b.generation().clone();
+
+        let work_a = generator_a.generate().await.expect("BUG: missing work");
+        let work_b = generator_b.generate().await.expect("BUG: missing work");
+        assert_eq!(work_a.generation, Some(1));
+        assert_eq!(work_b.generation, Some(1));
+        assert!(!generation_a.is_stale(work_a.generation));
+        assert!(!generation_b.is_stale(work_b.generation));
+
+        // job update of the first chain makes only its own work stale
+        engine_sender_a.broadcast_engine(Arc::new(engine::VersionRolling::new(
+            Arc::new(test_utils::TEST_BLOCKS[2]),
+            MIDSTATE_COUNT,
+        )));
+        let new_work_a = generator_a.generate().await.expect("BUG: missing work");
+        assert!(generation_a.is_stale(work_a.generation));
+        assert!(!generation_a.is_stale(new_work_a.generation));
+        assert!(!generation_b.is_stale(work_b.generation));
+        assert_eq!(generation_b.get(), 1);
+
+        // work which has not been generated by any work solver is never stale
+        assert!(!generation_a.is_stale(None));
+
+        // solutions are classified by generation of the work solver which has found them
+        let (sender, mut receiver) = mpsc::unbounded();
+        let solution_sender = SolutionSender::new(sender, generation_a.clone());
+        for (work, stale) in vec![(work_a, true), (new_work_a, false)] {
+            solution_sender.send(Solution::new(
+                work,
+                test_utils::TestSolution::new(&test_utils::TEST_BLOCKS[0]),
+                None,
+            ));
+            let solution = receiver.try_next().expect("BUG: missing solution");
+            assert_eq!(solution.expect("BUG: closed queue").is_stale(), stale);
+        }
+    }
+
+    #[test]
+    fn test_midstate_verification() {
+        let (sender, mut receiver) = mpsc::unbounded();
+        let mut solution_sender = SolutionSender::new(sender, Arc::new(WorkGeneration::new()));
+        let block = &test_utils::TEST_BLOCKS[0];
+
+        for &midstate_verification in [false, true].iter() {
+            solution_sender.midstate_verification = midstate_verification;
+            // the setting is inherited by senders of all work solvers
+            let work_solver_sender =
+                solution_sender.for_work_solver(Arc::new(WorkGeneration::new()));
+            work_solver_sender.send(Solution::new(
+                block.into(),
+                test_utils::TestSolution::new(block),
+                None,
+            ));
+            let solution = receiver
+                .try_next()
+                .expect("BUG: missing solution")
+                .expect("BUG: closed queue");
+            assert_eq!(
+                solution.verification_midstate().is_some(),
+                midstate_verification
+            );
+            assert_eq!(solution.hash(), &block.hash);
+        }
+    }
+
+    #[test]
+    fn test_job_barrier() {
+        let create_engine = |block: &test_utils::TestBlock| -> DynEngine {
+            Arc::new(engine::VersionRolling::new(
+                Arc::new(*block),
+                MIDSTATE_COUNT,
+            ))
+        };
+        let barrier = Arc::new(JobBarrier::new());
+        // generations of three mock chains
+        let generations: Vec<_> = (0..3)
+            .map(|idx| {
+                let generation = WorkGeneration::new();
+                generation.join_barrier(barrier.clone(), idx);
+                generation
+            })
+            .collect();
+        // work stamped with the current generation of the chain
+        let build_work = |generation: &WorkGeneration| {
+            let mut work: Assignment = (&test_utils::TEST_BLOCKS[0]).into();
+            work.generation = Some(generation.get());
+            work
+        };
+        // the chain starts the job by delivering its first work to the hardware
+        let start = |generation: &WorkGeneration, engine: &DynEngine| {
+            generation.observe(engine);
+            generation.confirm_delivery(&build_work(generation));
+        };
+
+        // job is live only after all chains have started it
+        let engine = create_engine(&test_utils::TEST_BLOCKS[0]);
+        for generation in generations.iter() {
+            assert!(!barrier.is_live(&engine));
+            start(generation, &engine);
+        }
+        assert!(barrier.is_live(&engine));
+        assert_eq!(barrier.live_count(), 1);
+
+        // new job supersedes the one which has not become live yet
+        let superseded_engine = create_engine(&test_utils::TEST_BLOCKS[1]);
+        start(&generations[0], &superseded_engine);
+        let engine = create_engine(&test_utils::TEST_BLOCKS[2]);
+        for generation in generations.iter() {
+            start(generation, &engine);
+        }
+        assert!(!barrier.is_live(&superseded_engine));
+        assert!(barrier.is_live(&engine));
+        assert_eq!(barrier.live_count(), 2);
+
+        // work generated from the job does not count until it is delivered
+        let engine = create_engine(&test_utils::TEST_BLOCKS[1]);
+        let stale_work = build_work(&generations[0]);
+        for generation in generations.iter() {
+            generation.observe(&engine);
+        }
+        // delivery of work of the previous job does not acknowledge the new one
+        generations[0].confirm_delivery(&stale_work);
+        assert!(!barrier.is_live(&engine));
+        for generation in generations.iter() {
+            generation.confirm_delivery(&build_work(generation));
+        }
+        assert!(barrier.is_live(&engine));
+        assert_eq!(barrier.live_count(), 3);
+        // subsequent deliveries of the same job are not acknowledged again
+        generations[0].confirm_delivery(&build_work(&generations[0]));
+        assert_eq!(barrier.live_count(), 3);
+
+        // disabled chain does not hold the job back
+        barrier.set_participating(2, false);
+        let engine = create_engine(&test_utils::TEST_BLOCKS[0]);
+        start(&generations[0], &engine);
+        start(&generations[1], &engine);
+        assert!(barrier.is_live(&engine));
+        assert_eq!(barrier.live_count(), 4);
+
+        // disabling the lagging chain releases the pending job
+        let engine = create_engine(&test_utils::TEST_BLOCKS[1]);
+        start(&generations[0], &engine);
+        assert!(!barrier.is_live(&engine));
+        barrier.set_participating(1, false);
+        assert!(barrier.is_live(&engine));
+        assert_eq!(barrier.live_count(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_reject_invalid_work() {
+        let job: Arc<dyn job::Bitcoin> = Arc::new(test_utils::TEST_BLOCKS[0]);
+        let work_solver: Arc<dyn node::WorkSolver> = test_utils::create_test_work_solver();
+        let (engine_sender, engine_receiver) = engine_channel(IgnoreEvents);
+        engine_sender.broadcast_engine(Arc::new(DuplicateMidstateEngine {
+            inner: engine::VersionRolling::new(job, MIDSTATE_COUNT),
+            broken: std::sync::atomic::AtomicBool::new(true),
+        }));
+        let mut generator = Generator::new(
+            engine_receiver,
+            vec![],
+            Arc::new(Mutex::new(Some(Arc::downgrade(&work_solver)))),
+        );
+        let get_invalid_work = || {
+            *work_solver
+                .work_solver_stats()
+                .invalid_work()
+                .take_snapshot()
+        };
+
+        // work with duplicate midstates is not dispatched
+        let work = generator.generate().await.expect("BUG: no work generated");
+        assert_eq!(work.validate(), Ok(()));
+        assert_eq!(get_invalid_work(), 1);
+        // only the dispatched work is accounted as generated
+        assert_eq!(get_generated_work(&work_solver), MIDSTATE_COUNT as u64);
+
+        assert!(generator.generate().await.is_some());
+        assert_eq!(get_invalid_work(), 1);
+        drop(engine_sender);
+    }
 }
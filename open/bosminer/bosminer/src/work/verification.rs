@@ -0,0 +1,181 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Cache of recently verified solutions. The same solution may be verified several times (e.g.
+//! duplicates reported by the hardware, resubmission or routing of block candidates) and the
+//! cache saves the repeated double SHA256 computation.
+
+use std::collections::{HashMap, VecDeque};
+
+/// Identification of block header by all its fields together with the midstate the hash has been
+/// computed from (the result depends on the midstate when it doesn't match the header)
+type HeaderKey = (u32, [u8; 32], [u8; 32], u32, u32, u32, Option<[u8; 32]>);
+
+fn header_key(
+    header: &ii_bitcoin::BlockHeader,
+    midstate: Option<&ii_bitcoin::Midstate>,
+) -> HeaderKey {
+    (
+        header.version,
+        header.previous_hash,
+        header.merkle_root,
+        header.time,
+        header.bits,
+        header.nonce,
+        midstate.map(|midstate| *midstate.as_ref()),
+    )
+}
+
+/// Bounded map of block headers to their double hashes. The oldest entry is evicted when
+/// the capacity is reached.
+#[derive(Debug)]
+pub struct VerificationCache {
+    capacity: usize,
+    hashes: HashMap<HeaderKey, ii_bitcoin::DHash>,
+    /// Insertion order of cached headers
+    order: VecDeque<HeaderKey>,
+    /// Number of lookups which have been served from the cache
+    hits: u64,
+}
+
+impl VerificationCache {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "BUG: empty verification cache");
+        Self {
+            capacity,
+            hashes: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            hits: 0,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    #[inline]
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Return double hash of `header` from the cache or compute it with `compute` and remember it.
+    /// The `midstate` has to be passed when the hash is computed on top of it instead of the first
+    /// chunk of the header.
+    pub fn get_or_compute<F>(
+        &mut self,
+        header: &ii_bitcoin::BlockHeader,
+        midstate: Option<&ii_bitcoin::Midstate>,
+        compute: F,
+    ) -> ii_bitcoin::DHash
+    where
+        F: FnOnce() -> ii_bitcoin::DHash,
+    {
+        let key = header_key(header, midstate);
+        if let Some(hash) = self.hashes.get(&key) {
+            self.hits += 1;
+            return *hash;
+        }
+        let hash = compute();
+        if self.order.len() == self.capacity {
+            let oldest = self.order.pop_front().expect("BUG: empty cache order");
+            self.hashes.remove(&oldest);
+        }
+        self.order.push_back(key);
+        self.hashes.insert(key, hash);
+        hash
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils;
+
+    use ii_bitcoin::HashTrait as _;
+
+    use std::cell::Cell;
+
+    fn block_header(block: &test_utils::TestBlock) -> ii_bitcoin::BlockHeader {
+        ii_bitcoin::BlockHeader {
+            version: block.version,
+            previous_hash: block.previous_hash.into_inner(),
+            merkle_root: block.merkle_root.into_inner(),
+            time: block.time,
+            bits: block.bits,
+            nonce: block.nonce,
+        }
+    }
+
+    #[test]
+    fn test_verification_cache() {
+        let mut cache = VerificationCache::new(2);
+        let computed = Cell::new(0);
+        let verify = |cache: &mut VerificationCache, header: &ii_bitcoin::BlockHeader| {
+            cache.get_or_compute(header, None, || {
+                computed.set(computed.get() + 1);
+                header.hash()
+            })
+        };
+        let headers: Vec<_> = test_utils::TEST_BLOCKS.iter().map(block_header).collect();
+
+        let hash = verify(&mut cache, &headers[0]);
+        assert_eq!(hash, test_utils::TEST_BLOCKS[0].hash);
+        // the second verification hits the cache
+        assert_eq!(verify(&mut cache, &headers[0]), hash);
+        assert_eq!(computed.get(), 1);
+        assert_eq!(cache.hits(), 1);
+
+        // different nonce is a different header
+        let mut other_nonce = headers[0];
+        other_nonce.nonce ^= 1;
+        assert_ne!(verify(&mut cache, &other_nonce), hash);
+        assert_eq!(computed.get(), 2);
+
+        // the result computed from a midstate is not mixed with the one for the whole header
+        let midstate = test_utils::TEST_BLOCKS[0].midstate;
+        let mut corrupted_midstate: [u8; 32] = midstate.into();
+        corrupted_midstate[0] ^= 1;
+        let corrupted_midstate: ii_bitcoin::Midstate = corrupted_midstate.into();
+        let corrupted_hash = cache.get_or_compute(&headers[0], Some(&corrupted_midstate), || {
+            headers[0].hash_with_midstate(&corrupted_midstate)
+        });
+        assert_ne!(corrupted_hash, hash);
+        assert_eq!(
+            cache.get_or_compute(&headers[0], Some(&corrupted_midstate), || unreachable!()),
+            corrupted_hash
+        );
+        assert_eq!(cache.hits(), 2);
+
+        // the oldest headers are evicted
+        verify(&mut cache, &headers[1]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(verify(&mut cache, &headers[0]), hash);
+        assert_eq!(computed.get(), 4);
+        assert_eq!(cache.hits(), 2);
+    }
+}
@@ -0,0 +1,475 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Construction and verification of coinbase transactions for solo mining where the miner builds
+//! the coinbase itself and has to make sure that the reward goes to the configured payout script
+
+use std::convert::TryInto;
+use std::mem::size_of;
+
+/// Value of the block reward or fees in satoshis
+pub type Amount = u64;
+
+/// Lowest block version which requires block height in the coinbase (BIP34)
+pub const BIP34_BLOCK_VERSION: u32 = 2;
+
+/// Transaction output
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxOut {
+    pub value: Amount,
+    pub script_pubkey: Vec<u8>,
+}
+
+/// Builds serialized coinbase transaction (without witness) with BIP34 block height in the
+/// script signature
+#[derive(Clone, Debug)]
+pub struct CoinbaseBuilder {
+    height: u32,
+    script_sig_data: Vec<u8>,
+    outputs: Vec<TxOut>,
+}
+
+impl CoinbaseBuilder {
+    const VERSION: u32 = 1;
+    const SEQUENCE: u32 = 0xffff_ffff;
+    const LOCK_TIME: u32 = 0;
+
+    pub fn new(height: u32) -> Self {
+        Self {
+            height,
+            script_sig_data: vec![],
+            outputs: vec![],
+        }
+    }
+
+    /// Append arbitrary data (e.g. extra nonce) to the script signature after the block height
+    pub fn script_sig_data(mut self, data: &[u8]) -> Self {
+        self.script_sig_data.extend_from_slice(data);
+        self
+    }
+
+    pub fn output(mut self, value: Amount, script_pubkey: &[u8]) -> Self {
+        self.outputs.push(TxOut {
+            value,
+            script_pubkey: script_pubkey.to_vec(),
+        });
+        self
+    }
+
+    /// BIP34 requires the height to be pushed as a minimally encoded script number, so heights
+    /// up to 16 are pushed as opcodes OP_0 - OP_16
+    fn height_push(&self) -> Vec<u8> {
+        if let 1..=16 = self.height {
+            // OP_1 - OP_16
+            return vec![0x50 + self.height as u8];
+        }
+        let mut number: Vec<u8> = self
+            .height
+            .to_le_bytes()
+            .iter()
+            .cloned()
+            .rev()
+            .skip_while(|&byte| byte == 0)
+            .collect();
+        number.reverse();
+        if number.last().map_or(false, |&byte| byte & 0x80 != 0) {
+            // the most significant bit would be interpreted as a sign
+            number.push(0);
+        }
+        let mut push = vec![number.len() as u8];
+        push.extend(number);
+        push
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut tx = Vec::new();
+        tx.extend_from_slice(&Self::VERSION.to_le_bytes());
+
+        // the only input does not spend any previous output
+        write_var_int(&mut tx, 1);
+        tx.extend_from_slice(&[0; 32]);
+        tx.extend_from_slice(&u32::max_value().to_le_bytes());
+        let mut script_sig = self.height_push();
+        script_sig.extend_from_slice(&self.script_sig_data);
+        write_var_int(&mut tx, script_sig.len() as u64);
+        tx.extend_from_slice(&script_sig);
+        tx.extend_from_slice(&Self::SEQUENCE.to_le_bytes());
+
+        write_var_int(&mut tx, self.outputs.len() as u64);
+        for output in &self.outputs {
+            tx.extend_from_slice(&output.value.to_le_bytes());
+            write_var_int(&mut tx, output.script_pubkey.len() as u64);
+            tx.extend_from_slice(&output.script_pubkey);
+        }
+
+        tx.extend_from_slice(&Self::LOCK_TIME.to_le_bytes());
+        tx
+    }
+
+    /// Serialize the coinbase with witness (BIP141) as required in blocks with witness
+    /// commitment. The only witness item is the 32-byte reserved value of zeros.
+    pub fn build_with_witness(&self) -> Vec<u8> {
+        let tx = self.build();
+        let (head, lock_time) = tx.split_at(tx.len() - size_of::<u32>());
+        let (version, body) = head.split_at(size_of::<u32>());
+
+        let mut witness_tx = version.to_vec();
+        // segwit marker and flag
+        witness_tx.extend_from_slice(&[0x00, 0x01]);
+        witness_tx.extend_from_slice(body);
+        write_var_int(&mut witness_tx, 1);
+        write_var_int(&mut witness_tx, WITNESS_RESERVED_VALUE.len() as u64);
+        witness_tx.extend_from_slice(&WITNESS_RESERVED_VALUE);
+        witness_tx.extend_from_slice(lock_time);
+        witness_tx
+    }
+}
+
+/// Witness reserved value of coinbase which is committed in the witness commitment
+const WITNESS_RESERVED_VALUE: [u8; 32] = [0; 32];
+
+/// Append variable length integer (CompactSize) used in serialization of transactions and blocks
+pub fn write_var_int(bytes: &mut Vec<u8>, value: u64) {
+    if value < 0xfd {
+        bytes.push(value as u8);
+    } else if value <= u16::max_value() as u64 {
+        bytes.push(0xfd);
+        bytes.extend_from_slice(&(value as u16).to_le_bytes());
+    } else if value <= u32::max_value() as u64 {
+        bytes.push(0xfe);
+        bytes.extend_from_slice(&(value as u32).to_le_bytes());
+    } else {
+        bytes.push(0xff);
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Helper for sequential reading of serialized transaction
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        if self.bytes.len() < len {
+            return Err("truncated transaction");
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, &'static str> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_u64(&mut self) -> Result<u64, &'static str> {
+        Ok(u64::from_le_bytes(
+            self.take(size_of::<u64>())?
+                .try_into()
+                .expect("BUG: slice with incorrect length"),
+        ))
+    }
+
+    fn read_var_int(&mut self) -> Result<u64, &'static str> {
+        let len = match self.read_u8()? {
+            0xfd => size_of::<u16>(),
+            0xfe => size_of::<u32>(),
+            0xff => size_of::<u64>(),
+            value => return Ok(value as u64),
+        };
+        let mut value = [0u8; size_of::<u64>()];
+        value[..len].copy_from_slice(self.take(len)?);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    fn read_var_bytes(&mut self) -> Result<&'a [u8], &'static str> {
+        let len = self.read_var_int()?;
+        if len > self.bytes.len() as u64 {
+            return Err("truncated transaction");
+        }
+        self.take(len as usize)
+    }
+}
+
+/// Parse all outputs of serialized (coinbase) transaction
+pub fn parse_outputs(tx: &[u8]) -> Result<Vec<TxOut>, &'static str> {
+    let mut reader = Reader { bytes: tx };
+    // version
+    reader.take(size_of::<u32>())?;
+    // skip segwit marker and flag when the transaction is serialized with witness
+    if reader.bytes.starts_with(&[0x00, 0x01]) {
+        reader.take(2)?;
+    }
+
+    let input_count = reader.read_var_int()?;
+    for _ in 0..input_count {
+        // previous output (hash + index)
+        reader.take(32 + size_of::<u32>())?;
+        reader.read_var_bytes()?;
+        // sequence
+        reader.take(size_of::<u32>())?;
+    }
+
+    let output_count = reader.read_var_int()?;
+    let mut outputs = Vec::new();
+    for _ in 0..output_count {
+        let value = reader.read_u64()?;
+        let script_pubkey = reader.read_var_bytes()?.to_vec();
+        outputs.push(TxOut {
+            value,
+            script_pubkey,
+        });
+    }
+    Ok(outputs)
+}
+
+/// Parse block height pushed at the beginning of script signature of coinbase transaction `tx`.
+/// The transaction can be truncated right after the height (e.g. coinbase prefix of a stratum
+/// job). Blocks before BIP34 may contain anything at that position (see `block_height`).
+pub fn parse_height(tx: &[u8]) -> Result<u64, &'static str> {
+    let mut reader = Reader { bytes: tx };
+    // version
+    reader.take(size_of::<u32>())?;
+    // skip segwit marker and flag when the transaction is serialized with witness
+    if reader.bytes.starts_with(&[0x00, 0x01]) {
+        reader.take(2)?;
+    }
+    if reader.read_var_int()? != 1 {
+        return Err("coinbase has to have exactly one input");
+    }
+    let previous_output = reader.take(32 + size_of::<u32>())?;
+    if previous_output[..32].iter().any(|&byte| byte != 0) {
+        return Err("input of coinbase cannot spend previous output");
+    }
+    if reader.read_var_int()? == 0 {
+        return Err("empty script signature");
+    }
+    match reader.read_u8()? {
+        // OP_0
+        0x00 => Ok(0),
+        // OP_1 - OP_16
+        opcode @ 0x51..=0x60 => Ok((opcode - 0x50) as u64),
+        len @ 1..=8 => {
+            let number = reader.take(len as usize)?;
+            if number.last().map_or(false, |&byte| byte & 0x80 != 0) {
+                return Err("negative block height");
+            }
+            let mut height = [0u8; size_of::<u64>()];
+            height[..number.len()].copy_from_slice(number);
+            Ok(u64::from_le_bytes(height))
+        }
+        _ => Err("script signature does not start with block height"),
+    }
+}
+
+/// Block height of coinbase transaction `tx` from a block with `block_version` (see
+/// `parse_height`). There is no height in blocks before BIP34.
+pub fn block_height(tx: &[u8], block_version: u32) -> Option<u64> {
+    if block_version < BIP34_BLOCK_VERSION {
+        return None;
+    }
+    parse_height(tx).ok()
+}
+
+/// Verify that all the value of coinbase transaction `tx` is paid to `script_pubkey`. Outputs
+/// without any value (e.g. witness commitment) can pay to any script.
+pub fn verify_payout(tx: &[u8], script_pubkey: &[u8]) -> Result<Amount, &'static str> {
+    let mut payout = 0;
+    for output in parse_outputs(tx)? {
+        if output.script_pubkey == script_pubkey {
+            payout = payout
+                .checked_add(output.value)
+                .ok_or("coinbase payout overflows")?;
+        } else if output.value > 0 {
+            return Err("coinbase pays to an unexpected script");
+        }
+    }
+    if payout == 0 {
+        return Err("coinbase does not pay to the payout script");
+    }
+    Ok(payout)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DHash, FromHex, HashTrait};
+
+    /// Coinbase transaction of the genesis block
+    const GENESIS_COINBASE_HEX: &str = concat!(
+        "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04",
+        "ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e20",
+        "6272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01",
+        "000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4c",
+        "ef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000",
+    );
+    const GENESIS_TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+    const BLOCK_REWARD: Amount = 50 * 100_000_000;
+
+    fn decode_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("BUG: invalid hex"))
+            .collect()
+    }
+
+    /// P2PKH script paying to HASH160 consisting of `byte` repeated
+    fn p2pkh_script(byte: u8) -> Vec<u8> {
+        let mut script = vec![0x76, 0xa9, 0x14];
+        script.extend_from_slice(&[byte; 20]);
+        script.extend_from_slice(&[0x88, 0xac]);
+        script
+    }
+
+    #[test]
+    fn test_parse_genesis_coinbase() {
+        let tx = decode_hex(GENESIS_COINBASE_HEX);
+        assert_eq!(
+            DHash::hash(&tx),
+            DHash::from_hex(GENESIS_TXID).expect("BUG: invalid hex")
+        );
+
+        let outputs = parse_outputs(&tx).expect("BUG: cannot parse coinbase");
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].value, BLOCK_REWARD);
+        assert_eq!(outputs[0].script_pubkey.len(), 0x43);
+        assert_eq!(
+            verify_payout(&tx, &outputs[0].script_pubkey),
+            Ok(BLOCK_REWARD)
+        );
+
+        // every truncation of the transaction is detected
+        for len in 0..tx.len() - size_of::<u32>() {
+            assert!(parse_outputs(&tx[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_built_coinbase_payout() {
+        let payout_script = p2pkh_script(0x11);
+        // witness commitment without any value
+        let commitment_script = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+        let tx = CoinbaseBuilder::new(630_000)
+            .script_sig_data(&[0u8; 8])
+            .output(BLOCK_REWARD / 4, &payout_script)
+            .output(0, &commitment_script)
+            .build();
+
+        assert_eq!(verify_payout(&tx, &payout_script), Ok(BLOCK_REWARD / 4));
+        let outputs = parse_outputs(&tx).expect("BUG: cannot parse coinbase");
+        assert_eq!(outputs[0].script_pubkey, payout_script);
+        assert_eq!(outputs[1].script_pubkey, commitment_script);
+
+        // the reward must not go to anyone else
+        assert!(verify_payout(&tx, &p2pkh_script(0x22)).is_err());
+        let tx = CoinbaseBuilder::new(630_000)
+            .output(BLOCK_REWARD / 8, &payout_script)
+            .output(BLOCK_REWARD / 8, &p2pkh_script(0x22))
+            .build();
+        assert!(verify_payout(&tx, &payout_script).is_err());
+
+        // the sum of payouts cannot overflow
+        let tx = CoinbaseBuilder::new(630_000)
+            .output(Amount::max_value(), &payout_script)
+            .output(1, &payout_script)
+            .build();
+        assert!(verify_payout(&tx, &payout_script).is_err());
+    }
+
+    #[test]
+    fn test_coinbase_with_witness() {
+        let payout_script = p2pkh_script(0x11);
+        let builder = CoinbaseBuilder::new(630_000)
+            .script_sig_data(&[0u8; 8])
+            .output(BLOCK_REWARD, &payout_script);
+        let tx = builder.build();
+        let witness_tx = builder.build_with_witness();
+
+        // marker, flag and the witness with one 32-byte item
+        assert_eq!(witness_tx.len(), tx.len() + 2 + 1 + 1 + 32);
+        assert_eq!(&witness_tx[..4], &tx[..4]);
+        assert_eq!(&witness_tx[4..6], &[0x00, 0x01]);
+        assert_eq!(&witness_tx[witness_tx.len() - 4..], &tx[tx.len() - 4..]);
+        assert_eq!(parse_outputs(&witness_tx), parse_outputs(&tx));
+        assert_eq!(parse_height(&witness_tx), Ok(630_000));
+    }
+
+    #[test]
+    fn test_height_push() {
+        let push = |height| CoinbaseBuilder::new(height).height_push();
+        assert_eq!(push(0), vec![0x00]);
+        assert_eq!(push(1), vec![0x51]);
+        assert_eq!(push(16), vec![0x60]);
+        assert_eq!(push(17), vec![0x01, 0x11]);
+        assert_eq!(push(0x7f), vec![0x01, 0x7f]);
+        // positive number with the highest bit set requires extra byte
+        assert_eq!(push(0x80), vec![0x02, 0x80, 0x00]);
+        assert_eq!(push(630_000), vec![0x03, 0xf0, 0x9c, 0x09]);
+    }
+
+    #[test]
+    fn test_parse_height() {
+        for &height in &[0, 1, 16, 17, 0x7f, 0x80, 227_836, 630_000, 0x7fff_ffff] {
+            let tx = CoinbaseBuilder::new(height)
+                .script_sig_data(&[0xff; 4])
+                .output(BLOCK_REWARD, &p2pkh_script(0x11))
+                .build();
+            assert_eq!(parse_height(&tx), Ok(height as u64));
+            assert_eq!(block_height(&tx, 0x2000_0000), Some(height as u64));
+        }
+
+        // the height is available even in coinbase prefix
+        let tx = CoinbaseBuilder::new(630_000).build();
+        let prefix = &tx[..size_of::<u32>() + 1 + 36 + 1 + 4];
+        assert_eq!(parse_height(prefix), Ok(630_000));
+        assert!(parse_height(&prefix[..prefix.len() - 1]).is_err());
+
+        // small heights pushed as opcodes
+        let mut tx = CoinbaseBuilder::new(0).build();
+        tx[size_of::<u32>() + 1 + 36 + 1] = 0x5a;
+        assert_eq!(parse_height(&tx), Ok(10));
+    }
+
+    #[test]
+    fn test_genesis_without_height() {
+        let tx = decode_hex(GENESIS_COINBASE_HEX);
+        // the script signature starts with nBits push which is not a height
+        assert_eq!(parse_height(&tx), Ok(0x1d00ffff));
+        assert_eq!(block_height(&tx, 1), None);
+
+        // the height has to be located in coinbase
+        let mut tx = tx;
+        tx[size_of::<u32>() + 1] = 0x01;
+        assert!(parse_height(&tx).is_err());
+    }
+
+    #[test]
+    fn test_var_int() {
+        for &(value, len) in &[(0xfcu64, 1), (0xfd, 3), (0x1_0000, 5), (0x1_0000_0000, 9)] {
+            let mut bytes = vec![];
+            write_var_int(&mut bytes, value);
+            assert_eq!(bytes.len(), len);
+            assert_eq!(Reader { bytes: &bytes }.read_var_int(), Ok(value));
+        }
+    }
+}
@@ -20,6 +20,7 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
+pub mod coinbase;
 pub mod test_blocks;
 
 // reexport Bitcoin test structures
@@ -36,6 +37,7 @@ use std::convert::TryInto;
 use std::fmt;
 use std::mem::size_of;
 use std::slice::Chunks;
+use std::str::FromStr;
 use std::time;
 
 /// SHA256 digest size used in Bitcoin protocol
@@ -47,6 +49,10 @@ const DIFFICULTY_1_TARGET_BYTES: [u8; SHA256_DIGEST_SIZE] = [
     0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
 
+/// Number of fractional bits kept for difficulties which are not integers (pools may set
+/// difficulty below 1 or with a fractional part)
+const DIFFICULTY_FRACTION_BITS: usize = 16;
+
 /// https://github.com/bitcoin/bips/blob/master/bip-0320.mediawiki
 /// Sixteen bits from the block header nVersion field, starting from 13 and ending at 28 inclusive,
 /// are reserved for general use.
@@ -61,6 +67,62 @@ pub const BLOCK_HEADER_SIZE: usize = 80;
 /// First chunk of Bitcoin block header used for midstate computation
 pub const BLOCK_HEADER_CHUNK1_SIZE: usize = 64;
 
+/// Round constants of SHA256 compression function
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Process one 64 byte chunk with SHA256 compression function and update the `state`
+/// The hash engine from `bitcoin_hashes` cannot be resumed from a midstate so it is implemented
+/// here for computation of the second chunk of block header.
+fn sha256_compress(state: &mut [u32; 8], chunk: &[u8; BLOCK_HEADER_CHUNK1_SIZE]) {
+    let mut w = [0u32; 64];
+    for (word, bytes) in w.iter_mut().zip(chunk.chunks(size_of::<u32>())) {
+        *word = u32::from_be_bytes(bytes.try_into().expect("BUG: invalid chunk word"));
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (k, w) in SHA256_K.iter().zip(w.iter()) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(*k)
+            .wrapping_add(*w);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (word, value) in state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(*value);
+    }
+}
+
 /// Bitcoin block header structure which can be packed to binary representation
 /// which is 80 bytes long
 #[derive(PackedStruct, Debug, Clone, Copy, Default)]
@@ -99,6 +161,37 @@ impl BlockHeader {
         engine.input(&self.into_bytes()[..BLOCK_HEADER_CHUNK1_SIZE]);
         engine.midstate().into()
     }
+
+    /// Compute SHA256 double hash starting from already known `midstate` of the first chunk
+    /// Only the second chunk and the final SHA256 are computed which saves one compression
+    /// compared to `hash`. The midstate has to correspond to the version, previous hash and merkle
+    /// root of this header otherwise the result is meaningless.
+    pub fn hash_with_midstate(&self, midstate: &Midstate) -> DHash {
+        let block_bytes = self.into_bytes();
+
+        let mut state = [0u32; 8];
+        for (word, bytes) in state
+            .iter_mut()
+            .zip(midstate.as_ref().chunks(size_of::<u32>()))
+        {
+            *word = u32::from_be_bytes(bytes.try_into().expect("BUG: invalid midstate word"));
+        }
+
+        // the rest of block header followed by SHA256 padding with message length in bits
+        let tail = &block_bytes[BLOCK_HEADER_CHUNK1_SIZE..];
+        let mut chunk2 = [0u8; BLOCK_HEADER_CHUNK1_SIZE];
+        chunk2[..tail.len()].copy_from_slice(tail);
+        chunk2[tail.len()] = 0x80;
+        chunk2[BLOCK_HEADER_CHUNK1_SIZE - size_of::<u64>()..]
+            .copy_from_slice(&(BLOCK_HEADER_SIZE as u64 * 8).to_be_bytes());
+        sha256_compress(&mut state, &chunk2);
+
+        let mut digest = [0u8; SHA256_DIGEST_SIZE];
+        for (bytes, word) in digest.chunks_mut(size_of::<u32>()).zip(state.iter()) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+        DHash::from_inner(sha256::Hash::hash(&digest).into_inner())
+    }
 }
 
 /// Array containing SHA256 digest
@@ -239,8 +332,12 @@ impl Target {
     /// Create target from difficulty used by pools
     /// This implementation can produce different results than targets for network difficulty.
     pub fn from_pool_difficulty(difficulty: usize) -> Self {
-        // TODO: use floating point division to get the same result expected by pool
-        Self(Self::difficulty_1_target() / difficulty)
+        NetworkParams::default().target_from_difficulty(difficulty)
+    }
+
+    /// Create target from arbitrary (possibly fractional) difficulty used by pools
+    pub fn from_pool_difficulty_f64(difficulty: f64) -> Self {
+        NetworkParams::default().target_from_difficulty_f64(difficulty)
     }
 
     /// Create target from its compact representation used by Bitcoin protocol
@@ -271,7 +368,17 @@ impl Target {
 
     /// Convert target to pool difficulty
     pub fn get_difficulty(&self) -> usize {
-        (Self::difficulty_1_target() / self.0).low_u64() as usize
+        NetworkParams::default().get_difficulty(self)
+    }
+
+    /// Convert target to pool difficulty which does not wrap on platforms with 32-bit `usize`
+    pub fn get_difficulty_u64(&self) -> u64 {
+        NetworkParams::default().get_difficulty_u64(self)
+    }
+
+    /// Convert target to precise pool difficulty (including fractional part)
+    pub fn get_difficulty_f64(&self) -> f64 {
+        NetworkParams::default().difficulty(self)
     }
 
     /// Convert target to its compact representation used by Bitcoin protocol
@@ -372,6 +479,180 @@ target_hex_fmt_impl!(Debug);
 target_hex_fmt_impl!(Display);
 target_hex_fmt_impl!(LowerHex);
 
+/// Parameters of a network with SHA256d proof of work which determine the relation between
+/// targets and difficulty. Besides predefined Bitcoin networks (see `Network::params`) they can
+/// describe altcoin forks with their own limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkParams {
+    /// Target which corresponds to difficulty 1
+    pub difficulty_1_target: Target,
+    /// The highest target allowed for blocks (proof of work limit)
+    pub pow_limit: Target,
+}
+
+impl NetworkParams {
+    pub fn new(difficulty_1_target: Target, pow_limit: Target) -> Self {
+        Self {
+            difficulty_1_target,
+            pow_limit,
+        }
+    }
+
+    /// Create target from (pool) difficulty
+    pub fn target_from_difficulty(&self, difficulty: usize) -> Target {
+        // TODO: use floating point division to get the same result expected by pool
+        Target(self.difficulty_1_target.0 / difficulty)
+    }
+
+    /// Create target from (pool) difficulty which doesn't have to be integer
+    /// The difficulty is rounded to `DIFFICULTY_FRACTION_BITS` fractional bits and difficulties
+    /// below 1 result in targets above the difficulty 1 target.
+    pub fn target_from_difficulty_f64(&self, difficulty: f64) -> Target {
+        assert!(
+            difficulty.is_finite() && difficulty > 0.0,
+            "BUG: invalid difficulty {}",
+            difficulty
+        );
+        let scale = (1u64 << DIFFICULTY_FRACTION_BITS) as f64;
+        // the upper bound keeps the conversion to integer in range
+        let scaled_difficulty = (difficulty * scale)
+            .round()
+            .max(1.0)
+            .min(std::u64::MAX as f64 / 2.0) as u64;
+        Target((self.difficulty_1_target.0 << DIFFICULTY_FRACTION_BITS) / scaled_difficulty)
+    }
+
+    /// Convert target to (pool) difficulty rounded down to integer
+    pub fn get_difficulty(&self, target: &Target) -> usize {
+        (self.difficulty_1_target.0 / target.0).low_u64() as usize
+    }
+
+    /// Convert target to (pool) difficulty rounded down to 64-bit integer (saturated)
+    pub fn get_difficulty_u64(&self, target: &Target) -> u64 {
+        let difficulty = self.difficulty_1_target.0 / target.0;
+        if difficulty.bits() > 64 {
+            std::u64::MAX
+        } else {
+            difficulty.low_u64()
+        }
+    }
+
+    /// Convert target to precise difficulty (it is below 1 for targets above difficulty 1)
+    pub fn difficulty(&self, target: &Target) -> f64 {
+        fn to_f64(value: uint::U256) -> f64 {
+            // the precision of the highest 64 bits is sufficient for difficulty
+            let shift = value.bits().saturating_sub(64);
+            (value >> shift).low_u64() as f64 * 2f64.powi(shift as i32)
+        }
+        to_f64(self.difficulty_1_target.0) / to_f64(target.0)
+    }
+
+    /// The lowest difficulty of blocks allowed by the proof of work limit
+    pub fn min_difficulty(&self) -> f64 {
+        self.difficulty(&self.pow_limit)
+    }
+
+    /// Create target from compact representation and check it is within the proof of work limit
+    pub fn target_from_compact(&self, bits: u32) -> Result<Target, &'static str> {
+        let target = Target::from_compact(bits)?;
+        if target.0.is_zero() {
+            Err("target cannot be zero")
+        } else if target > self.pow_limit {
+            Err("target exceeds the maximal target of the network")
+        } else {
+            Ok(target)
+        }
+    }
+}
+
+impl Default for NetworkParams {
+    /// Parameters of Bitcoin mainnet
+    fn default() -> Self {
+        Network::Mainnet.params()
+    }
+}
+
+/// Bitcoin network which determines consensus rules for targets of blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl Network {
+    /// Compact representation of the highest target (proof of work limit) allowed on mainnet
+    /// and testnet
+    pub const MAINNET_MAX_TARGET_BITS: u32 = 0x1d00ffff;
+    /// Compact representation of the highest target allowed on regtest
+    pub const REGTEST_MAX_TARGET_BITS: u32 = 0x207fffff;
+
+    /// Return compact representation of the highest target allowed by the network
+    pub fn max_target_bits(self) -> u32 {
+        match self {
+            Network::Mainnet | Network::Testnet => Self::MAINNET_MAX_TARGET_BITS,
+            Network::Regtest => Self::REGTEST_MAX_TARGET_BITS,
+        }
+    }
+
+    /// Return parameters of the network used for difficulty calculations
+    /// All Bitcoin networks share the mainnet difficulty 1 target to keep the difficulty
+    /// comparable (so difficulty of regtest blocks is below 1).
+    pub fn params(self) -> NetworkParams {
+        NetworkParams::new(
+            Target(Target::difficulty_1_target()),
+            Target::from_compact(self.max_target_bits()).expect("BUG: invalid maximal target"),
+        )
+    }
+
+    /// Return the highest target (the lowest difficulty) allowed by the network
+    pub fn max_target(self) -> Target {
+        self.params().pow_limit
+    }
+
+    /// Create target from compact representation and check it is allowed by the network
+    /// NOTE: The testnet minimum difficulty rule doesn't have to be applied because the node
+    /// already sets `bits` of the block template to the maximal target when it is allowed.
+    pub fn target_from_compact(self, bits: u32) -> Result<Target, &'static str> {
+        self.params().target_from_compact(bits)
+    }
+
+    /// Convert target to network difficulty
+    pub fn difficulty(self, target: &Target) -> f64 {
+        self.params().difficulty(target)
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network::Mainnet
+    }
+}
+
+impl FromStr for Network {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mainnet" | "main" | "bitcoin" => Ok(Network::Mainnet),
+            "testnet" | "testnet3" | "test" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err("unknown Bitcoin network"),
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 /// Auxiliary trait for adding target comparison with various types compatible with the `Target`
 pub trait MeetsTarget {
     /// Check if the type is less or equal to the target
@@ -394,30 +675,55 @@ impl MeetsTarget for DHash {
 /// Share=1 represents a space of 2^32 calculated hashes for Bitcoin mainnet; exactly
 /// 2^256 / (0xffff << 208), where 0xffff << 208 is defined as target difficulty 1 for Bitcoin
 /// mainnet. Each solution that meets a target at difficulty D is accounted as D shares.
+/// The difficulty D is not necessarily an integer so the shares are stored as a fixed point number
+/// with `DIFFICULTY_FRACTION_BITS` fractional bits. The 128-bit storage is wide enough to hold
+/// many solutions at network difficulty and all arithmetic saturates instead of overflowing.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, PartialOrd, Ord)]
-pub struct Shares(u64);
+pub struct Shares(u128);
 
 impl Shares {
     const DIFFICULTY_1_SHIFT: usize = 32;
 
+    /// Difficulty of the target with fractional part
+    fn scaled_difficulty(target: &Target) -> u128 {
+        let difficulty = (Target::difficulty_1_target() << DIFFICULTY_FRACTION_BITS) / target.0;
+        if difficulty.bits() > 128 {
+            std::u128::MAX
+        } else {
+            (((difficulty >> 64).low_u64() as u128) << 64) | difficulty.low_u64() as u128
+        }
+    }
+
     /// Create shares object with some initial value based on target
     pub fn new(target: &Target) -> Self {
-        Self(target.get_difficulty() as u64)
+        Self(Self::scaled_difficulty(target))
     }
 
     /// Account solution specified by its target difficulty to the shares
     pub fn account_solution(&mut self, target: &Target) {
-        self.0 += target.get_difficulty() as u64
+        self.0 = self.0.saturating_add(Self::scaled_difficulty(target))
     }
 
     #[inline]
     pub fn into_hashes(self) -> HashesUnit {
-        HashesUnit::Hashes((self.0 as u128) << Self::DIFFICULTY_1_SHIFT)
+        const SHIFT: usize = Shares::DIFFICULTY_1_SHIFT - DIFFICULTY_FRACTION_BITS;
+        HashesUnit::Hashes(if self.0 > std::u128::MAX >> SHIFT {
+            std::u128::MAX
+        } else {
+            self.0 << SHIFT
+        })
     }
 
+    /// Number of whole shares (the fractional part is rounded down and the value saturates at
+    /// `u64::MAX`)
     #[inline]
     pub fn value(&self) -> u64 {
-        self.0
+        let value = self.0 >> DIFFICULTY_FRACTION_BITS;
+        if value > std::u64::MAX as u128 {
+            std::u64::MAX
+        } else {
+            value as u64
+        }
     }
 
     pub fn into_hashrate(self, interval: time::Duration) -> HashesUnit {
@@ -455,16 +761,16 @@ impl Shares {
     /// Convert inner representation to floating point number
     #[inline]
     pub fn as_f64(&self) -> f64 {
-        self.0 as f64
+        self.0 as f64 / (1u64 << DIFFICULTY_FRACTION_BITS) as f64
     }
 
     /// Compute number of shares per second
     pub fn to_sharerate(&self, interval: time::Duration) -> f64 {
         let secs = interval.as_secs_f64();
         if secs == 0.0 {
-            self.0 as f64
+            self.as_f64()
         } else {
-            self.0 as f64 / secs
+            self.as_f64() / secs
         }
     }
 }
@@ -472,14 +778,14 @@ impl Shares {
 /// Helper conversion from a u64 share counter
 impl From<u64> for Shares {
     fn from(value: u64) -> Self {
-        Self(value)
+        Self((value as u128) << DIFFICULTY_FRACTION_BITS)
     }
 }
 
 /// It is useful to convert from hashes to shares
 impl From<HashesUnit> for Shares {
     fn from(hashes: HashesUnit) -> Self {
-        ((hashes.into_u128() >> Self::DIFFICULTY_1_SHIFT) as u64).into()
+        Self(hashes.into_u128() >> (Self::DIFFICULTY_1_SHIFT - DIFFICULTY_FRACTION_BITS))
     }
 }
 
@@ -487,7 +793,7 @@ impl std::ops::Add for Shares {
     type Output = Self;
 
     fn add(self, shares: Self) -> Self {
-        Self(self.0 + shares.0)
+        Self(self.0.saturating_add(shares.0))
     }
 }
 
@@ -592,6 +898,101 @@ impl HashesUnit {
     }
 }
 
+/// Unit for presenting hash rate (hashes per second) to operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashrateUnit {
+    Hashes,
+    KiloHashes,
+    MegaHashes,
+    GigaHashes,
+    TeraHashes,
+    PetaHashes,
+}
+
+impl HashrateUnit {
+    /// All units ordered from the smallest one
+    const ALL: [Self; 6] = [
+        Self::Hashes,
+        Self::KiloHashes,
+        Self::MegaHashes,
+        Self::GigaHashes,
+        Self::TeraHashes,
+        Self::PetaHashes,
+    ];
+
+    /// Number of hashes per second in one unit
+    pub fn multiplier(self) -> f64 {
+        match self {
+            Self::Hashes => 1.0,
+            Self::KiloHashes => 1e+3,
+            Self::MegaHashes => 1e+6,
+            Self::GigaHashes => 1e+9,
+            Self::TeraHashes => 1e+12,
+            Self::PetaHashes => 1e+15,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Hashes => "H/s",
+            Self::KiloHashes => "kH/s",
+            Self::MegaHashes => "MH/s",
+            Self::GigaHashes => "GH/s",
+            Self::TeraHashes => "TH/s",
+            Self::PetaHashes => "PH/s",
+        }
+    }
+
+    /// The largest unit in which hash rate `h` (in H/s) is at least one. The value which would
+    /// be formatted as 1000 of the unit is moved to the next one.
+    pub fn auto(h: f64) -> Self {
+        let idx = Self::ALL
+            .iter()
+            .rposition(|unit| h.abs() >= unit.multiplier())
+            .unwrap_or(0);
+        let unit = Self::ALL[idx];
+        match Self::ALL.get(idx + 1) {
+            Some(&next_unit) if round_hashrate(h / unit.multiplier()).abs() >= 1000.0 => next_unit,
+            _ => unit,
+        }
+    }
+}
+
+impl fmt::Display for HashrateUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+/// Number of decimal places which keep at least three significant digits of small values
+fn hashrate_precision(value: f64) -> usize {
+    let precision = |value: f64| match value.abs() {
+        value if value < 1.0 => 3,
+        value if value < 100.0 => 2,
+        _ => 1,
+    };
+    // value just below the limit may be rounded up to the next order of magnitude
+    let unrounded_precision = precision(value);
+    let factor = 10f64.powi(unrounded_precision as i32);
+    precision((value * factor).round() / factor).min(unrounded_precision)
+}
+
+fn round_hashrate(value: f64) -> f64 {
+    let factor = 10f64.powi(hashrate_precision(value) as i32);
+    (value * factor).round() / factor
+}
+
+/// Format hash rate `h` (in H/s) in the given `unit`
+pub fn format_hashrate(h: f64, unit: HashrateUnit) -> String {
+    let value = h / unit.multiplier();
+    format!("{:.*} {}", hashrate_precision(value), value, unit)
+}
+
+/// Format hash rate `h` (in H/s) in the unit which suits its magnitude
+pub fn format_hashrate_auto(h: f64) -> String {
+    format_hashrate(h, HashrateUnit::auto(h))
+}
+
 impl From<u128> for HashesUnit {
     fn from(hashes: u128) -> Self {
         Self::Hashes(hashes)
@@ -666,6 +1067,39 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn test_block_header_hash_with_midstate() {
+        for block in TEST_BLOCKS.iter() {
+            let mut block_header = BlockHeader {
+                version: block.version,
+                previous_hash: block.previous_hash.into_inner(),
+                merkle_root: block.merkle_root.into_inner(),
+                time: block.time,
+                bits: block.bits,
+                nonce: block.nonce,
+            };
+            assert_eq!(block.hash, block_header.hash_with_midstate(&block.midstate));
+
+            // the result has to match full computation also for other nonce which is not solution
+            block_header.nonce = block.nonce.wrapping_add(1);
+            assert_eq!(
+                block_header.hash(),
+                block_header.hash_with_midstate(&block.midstate)
+            );
+
+            // midstate of other header gives a different hash
+            let other_midstate = BlockHeader {
+                version: block.version ^ (1 << BIP320_VERSION_SHIFT),
+                ..block_header
+            }
+            .midstate();
+            assert_ne!(
+                block_header.hash(),
+                block_header.hash_with_midstate(&other_midstate)
+            );
+        }
+    }
+
     #[test]
     fn test_midstate_words() {
         use bytes::{BufMut, BytesMut};
@@ -706,6 +1140,19 @@ pub mod test {
         }
     }
 
+    #[test]
+    fn test_difficulty_u64() {
+        let difficulty_1_target = uint::U256::from_big_endian(&DIFFICULTY_1_TARGET_BYTES);
+
+        // difficulty which does not fit into 32 bits
+        let target: Target = (difficulty_1_target >> 40).into();
+        assert_eq!(target.get_difficulty_u64(), 1 << 40);
+
+        // the conversion saturates instead of wrapping
+        let target: Target = uint::U256::from(1).into();
+        assert_eq!(target.get_difficulty_u64(), std::u64::MAX);
+    }
+
     #[test]
     fn test_target_difficulty_1() {
         const TARGET_1_STR: &str =
@@ -771,6 +1218,102 @@ pub mod test {
         assert!(Target::from_compact(0xfffffff).is_err())
     }
 
+    #[test]
+    fn test_network_target() {
+        // mainnet and testnet share the proof of work limit at difficulty 1
+        for network in [Network::Mainnet, Network::Testnet].iter() {
+            assert_eq!(network.max_target(), Target::default());
+            assert!(network
+                .target_from_compact(Network::REGTEST_MAX_TARGET_BITS)
+                .is_err());
+            assert_eq!(network.difficulty(&network.max_target()), 1.0);
+        }
+
+        // regtest target is far above mainnet difficulty 1
+        let regtest_target = Network::Regtest
+            .target_from_compact(Network::REGTEST_MAX_TARGET_BITS)
+            .unwrap();
+        assert_eq!(regtest_target, Network::Regtest.max_target());
+        assert!(regtest_target > Network::Mainnet.max_target());
+        assert_eq!(
+            regtest_target.to_hex(),
+            "7fffff0000000000000000000000000000000000000000000000000000000000"
+        );
+        let difficulty = Network::Regtest.difficulty(&regtest_target);
+        assert!(difficulty > 4.65e-10 && difficulty < 4.66e-10);
+
+        // mainnet blocks are valid on all networks
+        for block in TEST_BLOCKS.iter() {
+            let target = Target::from_compact(block.bits).unwrap();
+            for network in [Network::Mainnet, Network::Testnet, Network::Regtest].iter() {
+                assert_eq!(network.target_from_compact(block.bits), Ok(target));
+            }
+        }
+        assert!(Network::Regtest.target_from_compact(0).is_err());
+        assert_eq!("regtest".parse(), Ok(Network::Regtest));
+        assert_eq!(Network::Testnet.to_string(), "testnet");
+    }
+
+    #[test]
+    fn test_network_params() {
+        let mainnet = Network::Mainnet.params();
+        let regtest = Network::Regtest.params();
+        assert_eq!(NetworkParams::default(), mainnet);
+        assert_eq!(Network::Testnet.params(), mainnet);
+
+        // difficulty 1 is shared but the proof of work limit differs
+        assert_eq!(mainnet.difficulty_1_target, regtest.difficulty_1_target);
+        assert_eq!(mainnet.pow_limit, Network::Mainnet.max_target());
+        assert_eq!(regtest.pow_limit, Network::Regtest.max_target());
+        assert_eq!(mainnet.min_difficulty(), 1.0);
+        assert!(regtest.min_difficulty() < 1e-9);
+        assert_eq!(regtest.get_difficulty(&regtest.pow_limit), 0);
+
+        // pool difficulty conversions are the same as for mainnet
+        for difficulty in [1, 2, 1024, 65536].iter() {
+            let target = regtest.target_from_difficulty(*difficulty);
+            assert_eq!(target, Target::from_pool_difficulty(*difficulty));
+            assert_eq!(regtest.get_difficulty(&target), *difficulty);
+        }
+
+        // fork with 256 times lower difficulty 1 target
+        let fork = NetworkParams::new(
+            Target::from_pool_difficulty(256),
+            Target::from_pool_difficulty(256),
+        );
+        assert_eq!(
+            fork.target_from_difficulty(1),
+            Target::from_pool_difficulty(256)
+        );
+        assert_eq!(fork.get_difficulty(&mainnet.difficulty_1_target), 0);
+        assert_eq!(fork.difficulty(&mainnet.difficulty_1_target), 1.0 / 256.0);
+        assert_eq!(fork.min_difficulty(), 1.0);
+    }
+
+    #[test]
+    fn test_network_params_compact() {
+        let mainnet = Network::Mainnet.params();
+        let fork = NetworkParams::new(
+            Target::from_pool_difficulty(256),
+            Target::from_pool_difficulty(256),
+        );
+
+        // the proof of work limit of the fork is below the mainnet one
+        assert!(mainnet
+            .target_from_compact(Network::MAINNET_MAX_TARGET_BITS)
+            .is_ok());
+        assert!(fork
+            .target_from_compact(Network::MAINNET_MAX_TARGET_BITS)
+            .is_err());
+        for block in TEST_BLOCKS.iter() {
+            assert_eq!(
+                fork.target_from_compact(block.bits),
+                Target::from_compact(block.bits)
+            );
+        }
+        assert!(fork.target_from_compact(0).is_err());
+    }
+
     #[test]
     fn test_meets_target() {
         for block in TEST_BLOCKS.iter() {
@@ -836,4 +1379,127 @@ pub mod test {
         assert!(Shares::default() < shares);
         assert!(shares > Shares::default());
     }
+
+    #[test]
+    fn test_shares_network_difficulty() {
+        // difficulty above 2^48 does not fit into 64 bits with the fractional part
+        let target = Target(Target::difficulty_1_target() >> 50);
+        let mut shares = Shares::new(&target);
+        assert_eq!(shares.value(), 1 << 50);
+        assert_eq!(shares.into_hashes(), HashesUnit::Hashes(1 << 82));
+
+        // solo mining accounts several solutions at network difficulty
+        shares.account_solution(&target);
+        shares.account_solution(&target);
+        assert_eq!(shares.value(), 3 << 50);
+        assert_eq!(shares.into_hashes(), HashesUnit::Hashes(3 << 82));
+        assert_eq!((shares + shares).value(), 6 << 50);
+
+        // real mainnet block target
+        let target = Target::from_compact(0x17028c61).expect("BUG: invalid compact target");
+        let shares = Shares::new(&target);
+        assert_eq!(shares.value(), target.get_difficulty_u64());
+
+        // extreme difficulty saturates instead of overflowing
+        let mut shares = Shares::new(&Target(uint::U256::from(1)));
+        assert_eq!(shares.value(), std::u64::MAX);
+        shares.account_solution(&Target(uint::U256::from(1)));
+        assert_eq!(shares.into_hashes(), HashesUnit::Hashes(std::u128::MAX));
+    }
+
+    #[test]
+    fn test_fractional_difficulty() {
+        // difficulty below 1 yields target above the difficulty 1 target
+        let target = Target::from_pool_difficulty_f64(0.5);
+        assert_eq!(target.0, Target::difficulty_1_target() << 1);
+        assert_eq!(target.get_difficulty(), 0);
+        assert_eq!(NetworkParams::default().difficulty(&target), 0.5);
+        let shares = Shares::new(&target);
+        assert_eq!(shares.as_f64(), 0.5);
+        assert_eq!(shares.value(), 0);
+        assert_eq!(shares.into_hashes(), HashesUnit::Hashes(0x80000000));
+
+        // integer difficulty is the same as for integer conversion
+        let target = Target::from_pool_difficulty_f64(3.0);
+        assert_eq!(target, Target::from_pool_difficulty(3));
+        assert_eq!(target.get_difficulty(), 3);
+        assert_eq!(Shares::new(&target).value(), 3);
+
+        // fractional part of high difficulty is not lost
+        let target = Target::from_pool_difficulty_f64(16384.5);
+        assert!(target < Target::from_pool_difficulty(16384));
+        assert!(target > Target::from_pool_difficulty(16385));
+        assert_eq!(target.get_difficulty(), 16384);
+        assert!((NetworkParams::default().difficulty(&target) - 16384.5).abs() < 1e-6);
+        let mut shares = Shares::new(&target);
+        assert!((shares.as_f64() - 16384.5).abs() < 1e-4);
+
+        // fractions are accumulated
+        shares.account_solution(&Target::from_pool_difficulty_f64(0.5));
+        assert_eq!(shares.value(), 16385);
+        assert_eq!(Shares::from(16385).value(), 16385);
+    }
+
+    #[test]
+    fn test_fractional_difficulty_filter() {
+        let hash_with_target = |target: uint::U256| {
+            let bytes: Sha256Array = Target(target).into();
+            DHash::from_inner(bytes)
+        };
+        let difficulty_1_target = Target::difficulty_1_target();
+
+        // hash with difficulty 2/3 meets only difficulties below it
+        let hash = hash_with_target(difficulty_1_target * 3 / 2);
+        assert!(hash.meets(&Target::from_pool_difficulty_f64(0.5)));
+        assert!(!hash.meets(&Target::from_pool_difficulty_f64(0.75)));
+        assert!(!hash.meets(&Target::from_pool_difficulty(1)));
+
+        // hash with difficulty slightly above 16384.5 is not filtered out while hash with
+        // difficulty 16384 (accepted by integer conversion) is
+        let target = Target::from_pool_difficulty_f64(16384.5);
+        assert!(hash_with_target(target.0).meets(&target));
+        assert!(hash_with_target(target.0 - 1).meets(&target));
+        assert!(!hash_with_target(target.0 + 1).meets(&target));
+        let hash = hash_with_target(difficulty_1_target / 16384);
+        assert!(hash.meets(&Target::from_pool_difficulty(16384)));
+        assert!(!hash.meets(&target));
+
+        // hash with difficulty 3 meets difficulty 3
+        let target = Target::from_pool_difficulty_f64(3.0);
+        assert!(hash_with_target(target.0).meets(&target));
+        assert!(!hash_with_target(target.0 + 1).meets(&target));
+    }
+
+    #[test]
+    fn test_format_hashrate() {
+        assert_eq!(
+            format_hashrate(999e+9, HashrateUnit::TeraHashes),
+            "0.999 TH/s"
+        );
+        assert_eq!(
+            format_hashrate(13.5e+12, HashrateUnit::GigaHashes),
+            "13500.0 GH/s"
+        );
+        assert_eq!(
+            format_hashrate(13.5e+12, HashrateUnit::TeraHashes),
+            "13.50 TH/s"
+        );
+        assert_eq!(
+            format_hashrate(2.5e+15, HashrateUnit::PetaHashes),
+            "2.50 PH/s"
+        );
+        assert_eq!(format_hashrate(0.0, HashrateUnit::Hashes), "0.000 H/s");
+
+        assert_eq!(format_hashrate_auto(999e+9), "999.0 GH/s");
+        assert_eq!(format_hashrate_auto(1e+12), "1.00 TH/s");
+        assert_eq!(format_hashrate_auto(99.99e+12), "99.99 TH/s");
+        assert_eq!(format_hashrate_auto(100e+12), "100.0 TH/s");
+        assert_eq!(format_hashrate_auto(12_346e+12), "12.35 PH/s");
+        assert_eq!(format_hashrate_auto(2e+18), "2000.0 PH/s");
+        assert_eq!(format_hashrate_auto(0.5), "0.500 H/s");
+
+        // value which would be rounded up to a whole unit moves to the unit
+        assert_eq!(format_hashrate_auto(999.99e+9), "1.00 TH/s");
+        assert_eq!(HashrateUnit::auto(999.94e+9), HashrateUnit::GigaHashes);
+    }
 }
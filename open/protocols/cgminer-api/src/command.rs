@@ -62,6 +62,14 @@ pub const DEVDETAILS: &str = "devdetails";
 pub const TEMPCTRL: &str = "tempctrl";
 pub const TEMPS: &str = "temps";
 pub const FANS: &str = "fans";
+pub const ERRORS: &str = "errors";
+pub const CLEAR_ERRORS: &str = "clearerrors";
+pub const CHIPSHARES: &str = "chipshares";
+pub const BUILDINFO: &str = "buildinfo";
+pub const DONATION: &str = "donation";
+pub const SOLUTIONS: &str = "solutions";
+pub const REPLACE_POOLS: &str = "replacepools";
+pub const SIMULATE_SHARE: &str = "simulateshare";
 
 pub type Result<T> = std::result::Result<T, response::Error>;
 /// Type describing command table
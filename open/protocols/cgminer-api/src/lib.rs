@@ -56,6 +56,9 @@ pub const SIGNATURE_TAG: &str = "{SIGNATURE}";
 /// Default signature of CGMiner API
 pub const PARAMETER_DELIMITER: char = ',';
 
+/// Delimiter of pool details passed to the `replacepools` command
+pub const POOL_DELIMITER: char = ';';
+
 /// Codec for the CGMiner API.
 /// The `Codec` decodes `Command`s and encodes `ResponseSet`s.
 #[derive(Default, Debug)]
@@ -129,6 +129,14 @@ pub enum StatusCode {
     TempCtrl = 200,
     Temps = 201,
     Fans = 202,
+    Errors = 203,
+    ChipShares = 204,
+    BuildInfo = 205,
+    Donation = 206,
+    ClearErrors = 207,
+    Solutions = 208,
+    ReplacePools = 209,
+    SimulateShare = 210,
 
     // info status codes
     PoolAlreadyEnabled = 49,
@@ -146,6 +154,7 @@ pub enum StatusCode {
     InvalidAddPoolDetails = 53,
     MissingCheckCmd = 71,
     InvalidAscId = 107,
+    InvalidSimulatedShare = 211,
 
     // special value which is added to the custom status codes
     CustomBase = 300,
@@ -201,6 +210,7 @@ pub enum ErrorCode {
     InvalidAddPoolDetails(String),
     MissingCheckCmd,
     InvalidAscId(i32, i32),
+    InvalidSimulatedShare(String),
 }
 
 impl From<ErrorCode> for Dispatch {
@@ -303,6 +313,10 @@ impl From<ErrorCode> for Error {
                     idx_requested, idx_last
                 ),
             ),
+            ErrorCode::InvalidSimulatedShare(reason) => (
+                StatusCode::InvalidSimulatedShare,
+                format!("Cannot simulate share: {}", reason),
+            ),
         };
 
         Self {
@@ -675,6 +689,10 @@ pub struct Config {
     pub os: String,
     #[serde(rename = "Hotplug")]
     pub hotplug: String,
+    /// Strategy of distributing jobs among midstates and work items (BOSminer extension)
+    #[serde(rename = "Work Distribution")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_distribution: Option<String>,
 }
 
 impl From<Config> for Dispatch {
@@ -125,3 +125,239 @@ impl From<Fans> for Dispatch {
         )
     }
 }
+
+/// Active or recent error condition of the miner
+#[derive(Serialize, PartialEq, Clone, Debug)]
+pub struct ErrorCondition {
+    #[serde(rename = "ERROR")]
+    pub idx: i32,
+    #[serde(rename = "Kind")]
+    pub kind: String,
+    #[serde(rename = "Severity")]
+    pub severity: String,
+    /// Subsystem or device which reported the condition
+    #[serde(rename = "Source")]
+    pub source: String,
+    #[serde(rename = "Message")]
+    pub message: String,
+    #[serde(rename = "First Seen")]
+    pub first_seen: Time,
+    #[serde(rename = "Last Seen")]
+    pub last_seen: Time,
+    #[serde(rename = "Count")]
+    pub count: u64,
+}
+
+pub struct Errors {
+    pub list: Vec<ErrorCondition>,
+}
+
+impl From<Errors> for Dispatch {
+    fn from(errors: Errors) -> Self {
+        let error_count = errors.list.len();
+        Dispatch::from_success(
+            StatusCode::Errors.into(),
+            format!("{} Error(s)", error_count),
+            Some(Body {
+                name: "ERRORS",
+                list: errors.list,
+            }),
+        )
+    }
+}
+
+/// Error conditions which have been acknowledged and removed by the operator
+pub struct ClearErrors {
+    pub count: usize,
+}
+
+impl From<ClearErrors> for Dispatch {
+    fn from(clear_errors: ClearErrors) -> Self {
+        Dispatch::from_success::<()>(
+            StatusCode::ClearErrors.into(),
+            format!("{} Error(s) cleared", clear_errors.count),
+            None,
+        )
+    }
+}
+
+/// Result of swapping the whole pool set of the default group
+pub struct ReplacePools {
+    /// Number of configured pools after the swap
+    pub count: usize,
+}
+
+impl From<ReplacePools> for Dispatch {
+    fn from(replace_pools: ReplacePools) -> Self {
+        Dispatch::from_success::<()>(
+            StatusCode::ReplacePools.into(),
+            format!("{} Pool(s) configured", replace_pools.count),
+            None,
+        )
+    }
+}
+
+/// Share accounted as accepted without being found by the hardware (for testing of monitoring)
+pub struct SimulateShare {
+    pub difficulty: f64,
+}
+
+impl From<SimulateShare> for Dispatch {
+    fn from(simulate_share: SimulateShare) -> Self {
+        Dispatch::from_success::<()>(
+            StatusCode::SimulateShare.into(),
+            format!(
+                "Share with difficulty {} simulated",
+                simulate_share.difficulty
+            ),
+            None,
+        )
+    }
+}
+
+/// Contribution of a single chip to valid solutions of its hash chain
+#[derive(Serialize, PartialEq, Clone, Debug)]
+pub struct ChipShare {
+    #[serde(rename = "CHIPSHARE")]
+    pub idx: i32,
+    /// ID of hash chain the chip belongs to
+    #[serde(rename = "ID")]
+    pub id: i32,
+    /// Address of the chip within its hash chain
+    #[serde(rename = "Chip")]
+    pub chip: i32,
+    #[serde(rename = "Valid")]
+    pub valid: u64,
+    /// Fraction of valid solutions found by the whole hash chain
+    #[serde(rename = "Share")]
+    pub share: f64,
+    /// Share relative to the expectation when all chips contribute equally
+    #[serde(rename = "Ratio")]
+    pub ratio: f64,
+}
+
+pub struct ChipShares {
+    pub list: Vec<ChipShare>,
+}
+
+impl From<ChipShares> for Dispatch {
+    fn from(chip_shares: ChipShares) -> Self {
+        let chip_count = chip_shares.list.len();
+        Dispatch::from_success(
+            StatusCode::ChipShares.into(),
+            format!("{} Chip Share(s)", chip_count),
+            Some(Body {
+                name: "CHIPSHARES",
+                list: chip_shares.list,
+            }),
+        )
+    }
+}
+
+/// Information about the running binary intended mainly for support
+#[derive(Serialize, PartialEq, Clone, Debug)]
+pub struct BuildInfo {
+    #[serde(rename = "Version")]
+    pub version: String,
+    /// Revision of source code from which the binary has been built
+    #[serde(rename = "Git Hash")]
+    pub git_hash: String,
+    #[serde(rename = "Profile")]
+    pub profile: String,
+    #[serde(rename = "Target")]
+    pub target: String,
+    /// Comma separated list of compile-time features
+    #[serde(rename = "Features")]
+    pub features: String,
+}
+
+impl From<BuildInfo> for Dispatch {
+    fn from(build_info: BuildInfo) -> Self {
+        Dispatch::from_success(
+            StatusCode::BuildInfo.into(),
+            "Build information".to_string(),
+            Some(Body {
+                name: "BUILDINFO",
+                list: vec![build_info],
+            }),
+        )
+    }
+}
+
+/// Mining on behalf of developers which is not included in the other statistics
+#[derive(Serialize, PartialEq, Clone, Debug)]
+pub struct Donation {
+    /// A donation pool is being mined right now
+    #[serde(rename = "Active")]
+    pub active: bool,
+    /// Total time spent mining for donation
+    #[serde(rename = "Elapsed")]
+    pub elapsed: Elapsed,
+    #[serde(rename = "MHS 1m")]
+    pub mhs_1m: MegaHashes,
+    #[serde(rename = "MHS 15m")]
+    pub mhs_15m: MegaHashes,
+    /// Solutions meeting job difficulty
+    #[serde(rename = "Valid")]
+    pub valid: u64,
+    #[serde(rename = "Difficulty Valid")]
+    pub difficulty_valid: Difficulty,
+}
+
+impl From<Donation> for Dispatch {
+    fn from(donation: Donation) -> Self {
+        Dispatch::from_success(
+            StatusCode::Donation.into(),
+            "Donation".to_string(),
+            Some(Body {
+                name: "DONATION",
+                list: vec![donation],
+            }),
+        )
+    }
+}
+
+/// Recent solution submitted to a pool together with the response of the pool
+#[derive(Serialize, PartialEq, Clone, Debug)]
+pub struct Solution {
+    #[serde(rename = "SOLUTION")]
+    pub idx: i32,
+    /// ID of the device (e.g. hash chain) which has found the solution or -1 when unknown
+    #[serde(rename = "ID")]
+    pub id: i32,
+    #[serde(rename = "Nonce")]
+    pub nonce: String,
+    #[serde(rename = "Hash")]
+    pub hash: String,
+    #[serde(rename = "Share Difficulty")]
+    pub share_difficulty: Difficulty,
+    #[serde(rename = "Job Difficulty")]
+    pub job_difficulty: Difficulty,
+    /// Seconds since the solution has been found
+    #[serde(rename = "Age")]
+    pub age: Interval,
+    /// "Pending" until the pool responds, then "Accepted" or "Rejected"
+    #[serde(rename = "Status")]
+    pub status: String,
+    /// Reason of rejection reported by the pool
+    #[serde(rename = "Reason")]
+    pub reason: String,
+}
+
+pub struct Solutions {
+    pub list: Vec<Solution>,
+}
+
+impl From<Solutions> for Dispatch {
+    fn from(solutions: Solutions) -> Self {
+        let solution_count = solutions.list.len();
+        Dispatch::from_success(
+            StatusCode::Solutions.into(),
+            format!("{} Solution(s)", solution_count),
+            Some(Body {
+                name: "SOLUTIONS",
+                list: solutions.list,
+            }),
+        )
+    }
+}
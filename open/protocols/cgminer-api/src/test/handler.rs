@@ -167,6 +167,7 @@ impl command::Handler for BasicTest {
             device_code: String::new(),
             os: "Braiins OS".to_string(),
             hotplug: "None".to_string(),
+            work_distribution: None,
         })
     }
 
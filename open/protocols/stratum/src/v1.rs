@@ -23,6 +23,7 @@
 pub mod error;
 pub mod framing;
 pub mod messages;
+pub mod notify;
 pub mod rpc;
 
 use self::error::ErrorKind;
@@ -122,7 +123,9 @@ pub fn build_message_from_frame(frame: framing::Frame) -> Result<Message<Protoco
                 Method::SetExtranonce => Box::new(messages::SetExtranonce::try_from(request)?)
                     as Box<dyn AnyPayload<Protocol>>,
                 Method::Notify => {
-                    Box::new(messages::Notify::try_from(request)?) as Box<dyn AnyPayload<Protocol>>
+                    // Validate the notification strictly before turning it into the job
+                    let notify = notify::StratumNotify::try_from(&request)?;
+                    Box::new(messages::Notify::from(notify)) as Box<dyn AnyPayload<Protocol>>
                 }
                 Method::SetVersionMask => Box::new(messages::SetVersionMask::try_from(request)?)
                     as Box<dyn AnyPayload<Protocol>>,
@@ -38,4 +38,8 @@ pub enum ErrorKind {
 
     #[fail(display = "Submit error: {}", _0)]
     Submit(String),
+
+    /// Malformed `mining.notify` message
+    #[fail(display = "Malformed notify: {}", _0)]
+    Notify(String),
 }
@@ -30,6 +30,7 @@ use super::error::ErrorKind;
 
 use crate::error::{Result, ResultExt};
 use crate::v1::{
+    notify::StratumNotify,
     rpc::{self, Method},
     ExtraNonce1, HexBytes, HexU32Be, PrevHash, Protocol,
 };
@@ -169,6 +170,32 @@ impl TryInto<(String, serde_json::Value)> for VersionRolling {
     }
 }
 
+/// Minimum difficulty configuration extension that follows the model in BIP310
+/// Miner requests the pool not to send any difficulty lower than `value`
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct MinimumDifficulty {
+    /// Lowest difficulty the miner is willing to work on
+    #[serde(rename = "minimum-difficulty.value")]
+    pub value: u32,
+}
+
+impl MinimumDifficulty {
+    pub fn new(value: u32) -> Self {
+        Self { value }
+    }
+}
+
+impl TryInto<(String, serde_json::Value)> for MinimumDifficulty {
+    type Error = crate::error::Error;
+
+    fn try_into(self) -> Result<(String, serde_json::Value)> {
+        Ok((
+            "minimum-difficulty".to_string(),
+            serde_json::to_value(self).context("JSON error")?,
+        ))
+    }
+}
+
 /// Mining configure
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub struct Configure(pub Vec<String>, pub serde_json::Value);
@@ -209,6 +236,14 @@ pub struct ConfigureResult(pub serde_json::Value);
 
 impl_conversion_response!(ConfigureResult);
 
+impl ConfigureResult {
+    /// Pool acknowledges the minimum difficulty extension with a plain boolean, anything else
+    /// (missing field or an error string) means the extension has not been negotiated
+    pub fn minimum_difficulty_accepted(&self) -> bool {
+        self.0["minimum-difficulty"].as_bool() == Some(true)
+    }
+}
+
 /// Extranonce subscriptionMessage
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub struct ExtranonceSubscribe();
@@ -424,10 +459,32 @@ impl Notify {
     pub fn clean_jobs(&self) -> bool {
         self.8
     }
+
+    /// Height of the mined block parsed from the coinbase prefix (BIP34)
+    pub fn block_height(&self) -> Option<u64> {
+        ii_bitcoin::coinbase::block_height(self.coin_base_1(), self.version())
+    }
 }
 
 impl_conversion_request!(Notify, Method::Notify, visit_notify);
 
+/// Build the job notification from fields that passed `StratumNotify` validation
+impl From<StratumNotify> for Notify {
+    fn from(notify: StratumNotify) -> Self {
+        Self(
+            JobId(notify.job_id),
+            notify.prev_hash,
+            CoinBase1(notify.coin_base_1),
+            CoinBase2(notify.coin_base_2),
+            MerkleBranch(notify.merkle_branch),
+            Version(HexU32Be(notify.version)),
+            Bits(HexU32Be(notify.bits)),
+            Time(HexU32Be(notify.time)),
+            notify.clean_jobs,
+        )
+    }
+}
+
 /// Server may arbitrarily adjust version mask
 /// Note, that we explicitly enforce 1 one element array so that serde doesn't flatten the
 /// 'params' JSON array to a single value, eliminating the array completely.
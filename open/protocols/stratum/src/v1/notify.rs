@@ -0,0 +1,304 @@
+// Copyright (C) 2019  Braiins Systems s.r.o.
+//
+// This file is part of Braiins Open-Source Initiative (BOSI).
+//
+// BOSI is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+//
+// Please, keep in mind that we may also license BOSI or any part thereof
+// under a proprietary license. For more information on the terms and conditions
+// of such proprietary license or if you have any other questions, please
+// contact us at opensource@braiins.com.
+
+//! Strict parser of `mining.notify` parameters
+//!
+//! The serde representation of `messages::Notify` is lenient: a malformed hex string silently
+//! turns into an empty vector and odd-length hex strings are padded with a leading zero. This
+//! parser validates every field first and reports what exactly was wrong with the notification,
+//! so that a broken upstream message never results in a subtly wrong job.
+//!
+//! The input comes from untrusted pools and therefore the parser must never panic. Any malformed
+//! input is reported as an error (see the `notify` fuzz target).
+
+use super::error::ErrorKind;
+use super::rpc::{self, Method, Rpc};
+use super::{HexBytes, HexU32Be, PrevHash};
+use crate::error::Result;
+
+use serde_json::Value;
+use std::convert::TryFrom;
+
+/// Number of parameters of `mining.notify`
+const PARAMS_COUNT: usize = 9;
+/// Size of previous block hash and merkle branch hashes
+const HASH_SIZE: usize = 32;
+/// Size of version, nBits and nTime fields
+const U32_SIZE: usize = 4;
+
+/// Fields of `mining.notify` that passed validation
+#[derive(Clone, Debug, PartialEq)]
+pub struct StratumNotify {
+    pub job_id: String,
+    pub prev_hash: PrevHash,
+    pub coin_base_1: HexBytes,
+    pub coin_base_2: HexBytes,
+    pub merkle_branch: Vec<HexBytes>,
+    pub version: u32,
+    pub bits: u32,
+    pub time: u32,
+    pub clean_jobs: bool,
+}
+
+impl StratumNotify {
+    /// Parse and validate `mining.notify` parameters (JSON array)
+    pub fn parse(params: &Value) -> Result<Self> {
+        let params = params
+            .as_array()
+            .ok_or_else(|| malformed("parameters are not an array".to_string()))?;
+        if params.len() != PARAMS_COUNT {
+            return Err(malformed(format!(
+                "expected {} parameters, got {}",
+                PARAMS_COUNT,
+                params.len()
+            )));
+        }
+
+        let job_id = get_str("job_id", &params[0])?;
+        if job_id.is_empty() {
+            return Err(malformed("job_id is empty".to_string()));
+        }
+        let prev_hash = get_str("prev_hash", &params[1])?;
+        check_hex("prev_hash", prev_hash, Some(HASH_SIZE))?;
+
+        let merkle_branch = params[4]
+            .as_array()
+            .ok_or_else(|| malformed("merkle_branch is not an array".to_string()))?
+            .iter()
+            .enumerate()
+            .map(|(i, hash)| {
+                let field = format!("merkle_branch[{}]", i);
+                let hash = get_str(&field, hash)?;
+                parse_hex_bytes(&field, hash, Some(HASH_SIZE))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            job_id: job_id.to_string(),
+            prev_hash: PrevHash::try_from(prev_hash)?,
+            coin_base_1: parse_hex_bytes("coin_base_1", get_str("coin_base_1", &params[2])?, None)?,
+            coin_base_2: parse_hex_bytes("coin_base_2", get_str("coin_base_2", &params[3])?, None)?,
+            merkle_branch,
+            version: parse_u32("version", &params[5])?,
+            bits: parse_u32("bits", &params[6])?,
+            time: parse_u32("time", &params[7])?,
+            clean_jobs: params[8]
+                .as_bool()
+                .ok_or_else(|| malformed(format!("clean_jobs is not a boolean: {}", params[8])))?,
+        })
+    }
+
+    /// Parse and validate raw JSON-RPC message which is expected to be `mining.notify`
+    pub fn from_slice(frame: &[u8]) -> Result<Self> {
+        match Rpc::try_from(frame)? {
+            Rpc::Request(request) => Self::try_from(&request),
+            Rpc::Response(response) => Err(ErrorKind::Rpc(format!(
+                "expected mining.notify request, got response {:?}",
+                response
+            ))
+            .into()),
+        }
+    }
+}
+
+impl TryFrom<&rpc::Request> for StratumNotify {
+    type Error = crate::error::Error;
+
+    fn try_from(req: &rpc::Request) -> Result<Self> {
+        // Unlike the other messages the request is not trusted to be a notification
+        if req.payload.method != Method::Notify {
+            return Err(ErrorKind::Rpc(format!(
+                "expected mining.notify request, got {:?}",
+                req.payload.method
+            ))
+            .into());
+        }
+        Self::parse(&req.payload.params)
+    }
+}
+
+fn malformed(reason: String) -> crate::error::Error {
+    ErrorKind::Notify(reason).into()
+}
+
+fn get_str<'a>(field: &str, value: &'a Value) -> Result<&'a str> {
+    value
+        .as_str()
+        .ok_or_else(|| malformed(format!("{} is not a string: {}", field, value)))
+}
+
+/// Verify that `value` is an even-length hex string optionally of exact decoded `size`
+fn check_hex(field: &str, value: &str, size: Option<usize>) -> Result<()> {
+    if value.len() % 2 != 0 {
+        return Err(malformed(format!(
+            "{} has odd-length hex string ({} characters)",
+            field,
+            value.len()
+        )));
+    }
+    if let Some((pos, c)) = value.char_indices().find(|(_, c)| !c.is_ascii_hexdigit()) {
+        return Err(malformed(format!(
+            "{} has invalid hex character {:?} at position {}",
+            field, c, pos
+        )));
+    }
+    if let Some(size) = size {
+        if value.len() != size * 2 {
+            return Err(malformed(format!(
+                "{} has wrong length: expected {} bytes, got {}",
+                field,
+                size,
+                value.len() / 2
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn parse_hex_bytes(field: &str, value: &str, size: Option<usize>) -> Result<HexBytes> {
+    check_hex(field, value, size)?;
+    HexBytes::try_from(value)
+}
+
+fn parse_u32(field: &str, value: &Value) -> Result<u32> {
+    let value = get_str(field, value)?;
+    check_hex(field, value, Some(U32_SIZE))?;
+    Ok(HexU32Be::try_from(value)?.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::v1::*;
+    use crate::v1::messages::Notify;
+
+    /// Parameters of the reference notification with parameter `idx` replaced by `value`
+    fn notify_params_with(idx: usize, value: Value) -> Value {
+        let mut notify: Value =
+            serde_json::from_str(MINING_NOTIFY_JSON).expect("BUG: cannot parse notify");
+        notify["params"][idx] = value;
+        notify["params"].clone()
+    }
+
+    fn expect_error(params: Value, expected: &str) {
+        let err = StratumNotify::parse(&params).expect_err("Malformed notify was accepted");
+        assert_eq!(
+            err.kind(),
+            crate::error::ErrorKind::V1(ErrorKind::Notify(expected.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_notify() {
+        let notify: Value =
+            serde_json::from_str(MINING_NOTIFY_JSON).expect("BUG: cannot parse notify");
+        let notify = StratumNotify::parse(&notify["params"]).expect("Valid notify rejected");
+
+        assert_eq!(notify.job_id, MINING_NOTIFY_JOB_ID);
+        assert_eq!(notify.version, 0x20000000);
+        assert_eq!(notify.bits, 0x1d00ffff);
+        assert_eq!(notify.time, 0x5d10bc0a);
+        assert!(!notify.clean_jobs);
+        assert_eq!(Notify::from(notify), build_mining_notify());
+    }
+
+    #[test]
+    fn test_block_height() {
+        let notify: Value =
+            serde_json::from_str(MINING_NOTIFY_JSON).expect("BUG: cannot parse notify");
+        let notify = StratumNotify::parse(&notify["params"]).expect("Valid notify rejected");
+        // coinbase prefix starts with height push 026d0c
+        assert_eq!(Notify::from(notify).block_height(), Some(0x0c6d));
+
+        // blocks before BIP34 do not contain height
+        let notify = StratumNotify::parse(&notify_params_with(5, "00000001".into()))
+            .expect("Valid notify rejected");
+        assert_eq!(Notify::from(notify).block_height(), None);
+    }
+
+    #[test]
+    fn test_parse_malformed_notify() {
+        // prev hash shorter by one byte
+        expect_error(
+            notify_params_with(
+                1,
+                "13f46cc7bf03a16697170dbb9d15680b7e75fcf10846037f171d7f6b000000".into(),
+            ),
+            "prev_hash has wrong length: expected 32 bytes, got 31",
+        );
+        expect_error(
+            notify_params_with(2, "0100000".into()),
+            "coin_base_1 has odd-length hex string (7 characters)",
+        );
+        expect_error(
+            notify_params_with(3, "e91d01zz".into()),
+            "coin_base_2 has invalid hex character 'z' at position 6",
+        );
+        expect_error(
+            notify_params_with(4, serde_json::json!(["00".repeat(32), "00".repeat(31)])),
+            "merkle_branch[1] has wrong length: expected 32 bytes, got 31",
+        );
+        expect_error(
+            notify_params_with(5, "2000000".into()),
+            "version has odd-length hex string (7 characters)",
+        );
+        expect_error(
+            notify_params_with(7, 1561377802.into()),
+            "time is not a string: 1561377802",
+        );
+        expect_error(
+            notify_params_with(8, "false".into()),
+            "clean_jobs is not a boolean: \"false\"",
+        );
+        expect_error(serde_json::json!(["ahoj"]), "expected 9 parameters, got 1");
+    }
+
+    #[test]
+    fn test_notify_from_slice() {
+        let notify = StratumNotify::from_slice(MINING_NOTIFY_JSON.as_bytes())
+            .expect("Valid notify rejected");
+        assert_eq!(Notify::from(notify), build_mining_notify());
+
+        // other messages are not notifications
+        assert!(
+            StratumNotify::from_slice(br#"{"id":1,"method":"mining.subscribe","params":[]}"#)
+                .is_err()
+        );
+        assert!(StratumNotify::from_slice(br#"{"id":1,"result":true,"error":null}"#).is_err());
+    }
+
+    /// Truncated and corrupted notifications are rejected without panicking
+    #[test]
+    fn test_notify_corrupted() {
+        let frame = MINING_NOTIFY_JSON.as_bytes();
+        for len in 0..frame.len() {
+            assert!(StratumNotify::from_slice(&frame[..len]).is_err());
+        }
+        for pos in 0..frame.len() {
+            for &byte in &[b'"', b'0', b'[', b'\\', 0xff] {
+                let mut corrupted = frame.to_vec();
+                corrupted[pos] = byte;
+                let _ = StratumNotify::from_slice(&corrupted);
+            }
+        }
+    }
+}
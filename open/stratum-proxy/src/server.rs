@@ -94,7 +94,7 @@ impl ConnTranslation {
     ) -> Result<()> {
         let v1_msg = v1::build_message_from_frame(frame)?;
         v1_msg.accept(translation).await;
-        Ok(())
+        translation.check_fatal_error()
     }
 
     //    async fn handle_frame(&mut self, frame: v2::framing::Frame) -> Result<()> {
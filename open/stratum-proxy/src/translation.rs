@@ -20,12 +20,14 @@
 // of such proprietary license or if you have any other questions, please
 // contact us at opensource@braiins.com.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::From;
 use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::fmt;
 use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 
 use ii_async_compat::{bytes, futures};
 
@@ -67,19 +69,87 @@ impl SeqId {
     }
 }
 
+/// Height of the block being mined as announced by the latest V1 job. The value is shared with
+/// the owner of the translation because V2 `NewMiningJob` doesn't carry the coinbase.
+#[derive(Debug, Clone, Default)]
+pub struct BlockHeight(Arc<AtomicU64>);
+
+impl BlockHeight {
+    /// Zero is reserved for unknown height (genesis block cannot be mined anyway)
+    const UNKNOWN: u64 = 0;
+
+    pub fn get(&self) -> Option<u64> {
+        match self.0.load(Ordering::Relaxed) {
+            Self::UNKNOWN => None,
+            height => Some(height),
+        }
+    }
+
+    pub fn set(&self, height: Option<u64>) {
+        self.0
+            .store(height.unwrap_or(Self::UNKNOWN), Ordering::Relaxed);
+    }
+}
+
+/// Flag raised when the upstream V1 server changes extranonce via `mining.set_extranonce`. The
+/// flag is shared with the owner of the translation because V2 `NewMiningJob` doesn't tell that
+/// the following jobs are built with another extranonce.
+#[derive(Debug, Clone, Default)]
+pub struct ExtranonceChange(Arc<AtomicBool>);
+
+impl ExtranonceChange {
+    pub fn set(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Return whether extranonce has changed since the previous call
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
 pub struct V2ToV1TranslationOptions {
     /// Try to send `extranonce.subscribe` during handshake
     pub try_enable_xnsub: bool,
+    /// Request a floor difficulty via `minimum-difficulty` extension of `mining.configure`
+    pub minimum_difficulty: Option<u32>,
+    /// Minimal number of extranonce2 bytes the pool has to leave for rolling by downstream
+    pub min_extranonce2_size: usize,
+    /// Prefix of sub-workers used for shares submitted to channels returned by
+    /// `V2ToV1Translation::sub_worker_channel_id`. Share of sub-worker `n` is submitted as
+    /// `<user>.<prefix><n>` which lets the pool break out statistics e.g. per hash chain.
+    pub sub_worker_prefix: Option<String>,
+    /// Session captured from a previous connection (see `V2ToV1Translation::session_state`). Its
+    /// extranonce 1 is passed to `mining.subscribe` so that the pool may resume the session.
+    pub resume_session: Option<SessionState>,
 }
 
 impl Default for V2ToV1TranslationOptions {
     fn default() -> Self {
         Self {
             try_enable_xnsub: false,
+            minimum_difficulty: None,
+            min_extranonce2_size: 0,
+            sub_worker_prefix: None,
+            resume_session: None,
         }
     }
 }
 
+/// Parameters negotiated with the upstream V1 server during `mining.configure` and
+/// `mining.subscribe`. The state is captured from an operational translation and restored on
+/// reconnect in order to continue with the same session.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SessionState {
+    pub extra_nonce1: v1::ExtraNonce1,
+    pub extra_nonce2_size: usize,
+    /// Subscription IDs as reported by the pool
+    pub subscriptions: Vec<v1::messages::Subscription>,
+    /// Version rolling mask acknowledged by the pool
+    pub version_mask: Option<u32>,
+    pub minimum_difficulty: Option<u32>,
+}
+
 /// States of the Translation setup
 #[derive(PartialEq, Debug)]
 enum V2ToV1TranslationState {
@@ -144,8 +214,14 @@ pub struct V2ToV1Translation {
 
     v1_extra_nonce1: Option<v1::ExtraNonce1>,
     v1_extra_nonce2_size: usize,
+    /// Subscription IDs from the latest `mining.subscribe` response
+    v1_subscriptions: Vec<v1::messages::Subscription>,
+    /// Version rolling mask acknowledged during `mining.configure`
+    v1_version_mask: Option<u32>,
     v1_authorized: bool,
     v1_xnsub_enabled: bool,
+    /// Floor difficulty that the pool has acknowledged during `mining.configure`
+    v1_minimum_difficulty: Option<u32>,
 
     /// Whether to force future jobs: might be handy for v1 pools which don't accept solutions with
     /// `ntime` less than specified on jobs they are solving (but greater than ntime on prevhash).
@@ -154,6 +230,14 @@ pub struct V2ToV1Translation {
     /// Latest mining.notify payload that arrived before V1 authorize has completed.
     /// This allows immediate completion of channel open on V2.
     v1_deferred_notify: Option<v1::messages::Notify>,
+    /// Block height parsed from coinbase of the latest mining.notify
+    v1_block_height: BlockHeight,
+    /// Raised by `mining.set_extranonce` which makes all previous jobs obsolete
+    v1_extranonce_change: ExtranonceChange,
+    /// Sub-workers which have been already authorized (see `sub_worker_prefix` option)
+    v1_sub_workers: HashSet<u32>,
+    /// Error raised by a V1 visitor which makes the upstream connection unusable
+    v1_fatal_error: Option<Error>,
 
     /// Channel for sending out V2 responses
     v2_tx: mpsc::Sender<v2::Frame>,
@@ -189,6 +273,16 @@ impl V2ToV1Translation {
     /// TODO: DIFF1 const target is broken, the last U64 word gets actually initialized to 0xffffffff, not sure why
     const DIFF1_TARGET: uint::U256 = uint::U256([0, 0, 0, 0xffff0000u64]);
 
+    /// Converts V1 difficulty to V2 target. The difficulty doesn't have to be integer and it can
+    /// be even below 1 which results in a target above the difficulty 1 target.
+    /// Returns `None` for difficulty that cannot be represented by any target.
+    fn diff_to_target(diff: f64) -> Option<uint::U256> {
+        if !diff.is_finite() || diff <= 0.0 {
+            return None;
+        }
+        Some(ii_bitcoin::Target::from_pool_difficulty_f64(diff).into())
+    }
+
     pub fn new(
         v1_tx: mpsc::Sender<v1::Frame>,
         v2_tx: mpsc::Sender<v2::Frame>,
@@ -204,10 +298,17 @@ impl V2ToV1Translation {
             v1_req_map: V1ReqMap::default(),
             v1_extra_nonce1: None,
             v1_extra_nonce2_size: 0,
+            v1_subscriptions: Vec::new(),
+            v1_version_mask: None,
             v1_authorized: false,
             v1_force_future_jobs: true,
             v1_xnsub_enabled: false,
+            v1_minimum_difficulty: None,
             v1_deferred_notify: None,
+            v1_block_height: BlockHeight::default(),
+            v1_extranonce_change: ExtranonceChange::default(),
+            v1_sub_workers: HashSet::new(),
+            v1_fatal_error: None,
             v2_tx,
             v2_req_id: SeqId::new(),
             v2_job_id: SeqId::new(),
@@ -216,6 +317,76 @@ impl V2ToV1Translation {
         }
     }
 
+    /// Shared handle to the height of the block currently being mined
+    pub fn block_height(&self) -> BlockHeight {
+        self.v1_block_height.clone()
+    }
+
+    /// Shared handle to the notification about extranonce change
+    pub fn extranonce_change(&self) -> ExtranonceChange {
+        self.v1_extranonce_change.clone()
+    }
+
+    /// Report error raised while visiting the last V1 message. The visitors cannot return
+    /// anything so the owner of the translation has to check it after each message and close
+    /// the upstream connection when it fails.
+    pub fn check_fatal_error(&mut self) -> Result<()> {
+        match self.v1_fatal_error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Snapshot of the session negotiated with the upstream V1 server. `None` is returned until
+    /// the subscription has completed.
+    pub fn session_state(&self) -> Option<SessionState> {
+        Some(SessionState {
+            extra_nonce1: self.v1_extra_nonce1.clone()?,
+            extra_nonce2_size: self.v1_extra_nonce2_size,
+            subscriptions: self.v1_subscriptions.clone(),
+            version_mask: self.v1_version_mask,
+            minimum_difficulty: self.v1_minimum_difficulty,
+        })
+    }
+
+    /// Channel ID which has to be used in `SubmitSharesStandard` for submitting shares as
+    /// sub-worker `index`. It is accepted only when the `sub_worker_prefix` option is set.
+    pub fn sub_worker_channel_id(index: u32) -> u32 {
+        Self::CHANNEL_ID + 1 + index
+    }
+
+    /// Resolve V1 worker name for shares submitted to `channel_id` and authorize the sub-worker
+    /// before its first share. `None` is returned for unrecognized channels.
+    fn prepare_submit_worker(&mut self, channel_id: u32, user: String) -> Option<String> {
+        if channel_id == Self::CHANNEL_ID {
+            return Some(user);
+        }
+        let index = channel_id.checked_sub(Self::sub_worker_channel_id(0))?;
+        let worker = format!(
+            "{}.{}{}",
+            user,
+            self.options.sub_worker_prefix.as_ref()?,
+            index
+        );
+
+        if self.v1_sub_workers.insert(index) {
+            // V1 requests are processed in order so the share is submitted after authorization
+            let authorize = v1::messages::Authorize(worker.clone(), "".to_string());
+            let v1_authorize_message = self.v1_method_into_message(
+                authorize,
+                Self::handle_sub_worker_authorize_result,
+                Self::handle_sub_worker_authorize_error,
+            );
+            if let Err(submit_err) = util::submit_message(&mut self.v1_tx, v1_authorize_message) {
+                info!(
+                    "Cannot send V1 mining.authorize for {}: {:?}",
+                    worker, submit_err
+                );
+            }
+        }
+        Some(worker)
+    }
+
     /// Builds a V1 request from V1 method and assigns a unique identifier to it
     fn v1_method_into_message<M, E>(
         &mut self,
@@ -317,6 +488,7 @@ impl V2ToV1Translation {
         self.v1_authorized = false;
         self.v1_extra_nonce1 = None;
         self.v1_extra_nonce2_size = 0;
+        self.v1_subscriptions.clear();
 
         if let Some(v2_channel_details) = self.v2_channel_details.as_ref() {
             let msg = v2::messages::OpenStandardMiningChannelError {
@@ -370,6 +542,8 @@ impl V2ToV1Translation {
             && (proposed_version_mask.0).0 == ii_stratum::BIP320_N_VERSION_MASK
         {
             self.state = V2ToV1TranslationState::ConnectionSetup;
+            self.v1_version_mask = Some((proposed_version_mask.0).0);
+            self.v1_minimum_difficulty = self.negotiated_minimum_difficulty(payload);
 
             let success = v2::messages::SetupConnectionSuccess {
                 used_version: Self::PROTOCOL_VERSION as u16,
@@ -389,6 +563,25 @@ impl V2ToV1Translation {
         }
     }
 
+    /// Extracts the floor difficulty from the pool's response to `mining.configure`. The floor
+    /// is honored only when it has been requested and the pool explicitly acknowledged it
+    fn negotiated_minimum_difficulty(&self, payload: &v1::rpc::StratumResult) -> Option<u32> {
+        let minimum_difficulty = self.options.minimum_difficulty?;
+        match v1::messages::ConfigureResult::try_from(payload) {
+            Ok(result) if result.minimum_difficulty_accepted() => {
+                info!("Pool accepted minimum difficulty {}", minimum_difficulty);
+                Some(minimum_difficulty)
+            }
+            _ => {
+                info!(
+                    "Pool refused minimum difficulty {}, payload: {:?}",
+                    minimum_difficulty, payload
+                );
+                None
+            }
+        }
+    }
+
     fn handle_configure_error(
         &mut self,
         id: &v1::MessageId,
@@ -442,6 +635,77 @@ impl V2ToV1Translation {
         Ok(())
     }
 
+    /// Sends a benign request upstream to prevent the V1 server from dropping an idle
+    /// connection. `mining.extranonce.subscribe` is used because it is harmless to repeat and
+    /// servers that don't support it just respond with an error.
+    /// Nothing is sent until the channel is operational as the handshake generates its own
+    /// traffic.
+    pub fn send_keepalive(&mut self) -> Result<()> {
+        if self.state != V2ToV1TranslationState::Operational {
+            return Ok(());
+        }
+        let v1_keepalive = self.v1_method_into_message(
+            v1::messages::ExtranonceSubscribe(),
+            Self::handle_keepalive_result,
+            Self::handle_keepalive_error,
+        );
+        util::submit_message(&mut self.v1_tx, v1_keepalive)
+    }
+
+    /// Response to keepalive has no effect on the translation
+    fn handle_keepalive_result(
+        &mut self,
+        id: &v1::MessageId,
+        payload: &v1::rpc::StratumResult,
+    ) -> Result<()> {
+        trace!(
+            "handle_keepalive_result() id={:?} payload:{:?}",
+            id,
+            payload
+        );
+        Ok(())
+    }
+
+    fn handle_keepalive_error(
+        &mut self,
+        id: &v1::MessageId,
+        payload: &v1::rpc::StratumError,
+    ) -> Result<()> {
+        trace!("handle_keepalive_error() id={:?} payload:{:?}", id, payload);
+        Ok(())
+    }
+
+    /// Number of extranonce2 bytes required for the channel ID and for the configured needs of
+    /// downstream work generation
+    fn required_extra_nonce2_size(&self) -> usize {
+        let channel_id_size = (32 - Self::CHANNEL_ID.leading_zeros() as usize + 7) / 8;
+        channel_id_size.max(self.options.min_extranonce2_size)
+    }
+
+    /// Check that the extranonce assigned by the pool leaves enough extranonce2 bytes. Otherwise
+    /// the translation would produce incorrect work.
+    fn check_extra_nonce2_size(
+        &self,
+        extra_nonce1: &v1::ExtraNonce1,
+        extra_nonce2_size: usize,
+    ) -> Result<()> {
+        let required_size = self.required_extra_nonce2_size();
+        if extra_nonce2_size < required_size {
+            Err(
+                ii_stratum::error::Error::from(v1::error::ErrorKind::Subscribe(format!(
+                    "pool assigned extranonce1 of {} bytes leaving only {} extranonce2 bytes, \
+                     at least {} bytes are required",
+                    extra_nonce1.0.len(),
+                    extra_nonce2_size,
+                    required_size
+                )))
+                .into(),
+            )
+        } else {
+            Ok(())
+        }
+    }
+
     fn handle_subscribe_result(
         &mut self,
         id: &v1::MessageId,
@@ -459,8 +723,29 @@ impl V2ToV1Translation {
             e
         })?;
 
+        self.check_extra_nonce2_size(
+            subscribe_result.extra_nonce_1(),
+            subscribe_result.extra_nonce_2_size(),
+        )
+        .map_err(|e| {
+            error!("Unworkable upstream extranonce: {}", e);
+            self.abort_open_channel("Upstream extranonce2 too small");
+            e
+        })?;
+
         self.v1_extra_nonce1 = Some(subscribe_result.extra_nonce_1().clone());
         self.v1_extra_nonce2_size = subscribe_result.extra_nonce_2_size().clone();
+        self.v1_subscriptions = subscribe_result.subscriptions().clone();
+
+        if let Some(resumed) = self.options.resume_session.as_ref() {
+            if resumed.extra_nonce1 == *subscribe_result.extra_nonce_1()
+                && resumed.extra_nonce2_size == *subscribe_result.extra_nonce_2_size()
+            {
+                info!("Upstream V1 session resumed");
+            } else {
+                info!("Upstream V1 session not resumed, starting a new one");
+            }
+        }
 
         // In order to finalize the opening procedure we need 3 items: authorization,
         // subscription and difficulty
@@ -545,6 +830,34 @@ impl V2ToV1Translation {
         }
     }
 
+    /// Failed authorization of a sub-worker does not affect the channel, the pool rejects its
+    /// shares anyway
+    fn handle_sub_worker_authorize_result(
+        &mut self,
+        id: &v1::MessageId,
+        payload: &v1::rpc::StratumResult,
+    ) -> Result<()> {
+        v1::messages::BooleanResult::try_from(payload)
+            .map_err(Into::into)
+            .map(|bool_result| {
+                if !bool_result.0 {
+                    warn!("Sub-worker authorization refused (request ID {:?})", id);
+                }
+            })
+    }
+
+    fn handle_sub_worker_authorize_error(
+        &mut self,
+        id: &v1::MessageId,
+        payload: &v1::rpc::StratumError,
+    ) -> Result<()> {
+        warn!(
+            "Sub-worker authorization failed (request ID {:?}): {}",
+            id, payload.1
+        );
+        Ok(())
+    }
+
     fn handle_submit_result(
         &mut self,
         id: &v1::MessageId,
@@ -757,12 +1070,27 @@ impl V2ToV1Translation {
     fn perform_notify(&mut self, payload: &v1::messages::Notify) -> Result<()> {
         let merkle_root = self.calculate_merkle_root(payload)?;
 
+        // Some pools reuse V1 job IDs. Solutions of both jobs would be submitted under the same
+        // ID so the reused job is handled as a clean job that invalidates all previous jobs.
+        let v1_job_id = v1::messages::JobId::from_str(payload.job_id());
+        let job_id_reused = self
+            .v2_to_v1_job_map
+            .values()
+            .any(|template| template.job_id == v1_job_id);
+        if job_id_reused {
+            warn!(
+                "V1 job ID {:x?} has been reused, flushing all previous jobs",
+                payload.job_id()
+            );
+        }
+
         let v2_job = v2::messages::NewMiningJob {
             channel_id: Self::CHANNEL_ID,
             job_id: self.v2_job_id.next(),
             future_job: self.v2_to_v1_job_map.is_empty()
                 || payload.clean_jobs()
-                || self.v1_force_future_jobs,
+                || self.v1_force_future_jobs
+                || job_id_reused,
             merkle_root: Uint256Bytes(merkle_root.into_inner()),
             version: payload.version(),
         };
@@ -790,7 +1118,7 @@ impl V2ToV1Translation {
             .insert(
                 v2_job.job_id,
                 V1SubmitTemplate {
-                    job_id: v1::messages::JobId::from_str(payload.job_id()),
+                    job_id: v1_job_id,
                     time: payload.time(),
                     version: payload.version(),
                 },
@@ -802,6 +1130,13 @@ impl V2ToV1Translation {
             panic!("V2 id already exists");
         }
 
+        // The height has to be updated before the job is passed to the V2 client
+        let block_height = payload.block_height();
+        if block_height != self.v1_block_height.get() {
+            debug!("Block height changed to {:?}", block_height);
+        }
+        self.v1_block_height.set(block_height);
+
         util::submit_message(&mut self.v2_tx, v2_job)?;
 
         if let Some(set_new_prev_hash) = maybe_set_new_prev_hash {
@@ -902,8 +1237,28 @@ impl v1::Handler for V2ToV1Translation {
             self.state,
             payload,
         );
-        let diff = payload.value() as u32;
-        self.v2_target = Some(Self::DIFF1_TARGET / diff);
+        let diff = payload.value() as f64;
+        // Pools may still announce lower difficulty than negotiated, keep the floor so that the
+        // miner doesn't flood us with shares the pool is not interested in
+        let diff = match self.v1_minimum_difficulty {
+            Some(minimum_difficulty) if diff < minimum_difficulty as f64 => {
+                trace!(
+                    "Difficulty {} below negotiated minimum {}",
+                    diff,
+                    minimum_difficulty
+                );
+                minimum_difficulty as f64
+            }
+            _ => diff,
+        };
+        let target = match Self::diff_to_target(diff) {
+            Some(target) => target,
+            None => {
+                info!("Ignoring invalid difficulty {}", diff);
+                return;
+            }
+        };
+        self.v2_target = Some(target);
         if self.v1_authorized && self.v1_extra_nonce1.is_some() {
             // Initial set difficulty finalizes open channel if all preconditions are met
             if self.state == V2ToV1TranslationState::OpenStandardMiningChannelPending {
@@ -934,11 +1289,22 @@ impl v1::Handler for V2ToV1Translation {
             payload,
         );
 
+        if let Err(e) =
+            self.check_extra_nonce2_size(payload.extra_nonce_1(), payload.extra_nonce_2_size())
+        {
+            // The new extranonce is not usable and all jobs built with the old one would be
+            // rejected so the connection has to be closed
+            error!("Unworkable upstream extranonce: {}", e);
+            self.v1_fatal_error = Some(e);
+            return;
+        }
+
         // Update extranonces.
         // Changes are reflected after new mining job as per:
         //   https://en.bitcoin.it/wiki/Stratum_mining_protocol#mining.set_extranonce
         self.v1_extra_nonce1 = Some(payload.extra_nonce_1().clone());
         self.v1_extra_nonce2_size = payload.extra_nonce_2_size().clone();
+        self.v1_extranonce_change.set();
     }
 
     /// Composes a new mining job and sends it downstream
@@ -1024,6 +1390,11 @@ impl v2::Handler for V2ToV1Translation {
                 ii_stratum::BIP320_N_VERSION_MAX_BITS,
             ))
             .expect("addfeature failed"); // FIXME: how to handle errors from configure.add_feature() ?
+        if let Some(minimum_difficulty) = self.options.minimum_difficulty {
+            configure
+                .add_feature(v1::messages::MinimumDifficulty::new(minimum_difficulty))
+                .expect("addfeature failed");
+        }
 
         let v1_configure_message = self.v1_method_into_message(
             configure,
@@ -1090,9 +1461,14 @@ impl v2::Handler for V2ToV1Translation {
                 .expect("BUG: Cannot convert to string from connection details");
 
             let hostname_port = format!("{}:{}", hostname, conn_details.endpoint_port);
+            let resumed_extra_nonce1 = self
+                .options
+                .resume_session
+                .as_ref()
+                .map(|session| session.extra_nonce1.clone());
             let subscribe = v1::messages::Subscribe(
                 Some(conn_details.device.fw_ver.to_string()),
-                None,
+                resumed_extra_nonce1,
                 Some(hostname_port),
                 None,
             );
@@ -1157,21 +1533,25 @@ impl v2::Handler for V2ToV1Translation {
             self.state,
             payload,
         );
-        // Report invalid channel ID
-        if payload.channel_id != Self::CHANNEL_ID {
-            self.reject_shares(
-                payload,
-                format!("Unrecognized channel ID {}", payload.channel_id),
-            );
-            return;
-        }
-
         // Channel details must be filled by now, anything else is a bug, unfortunately, due to
         // the 'expect' we have to clone them. TODO review this code
         let v2_channel_details = &self
             .v2_channel_details
             .clone()
             .expect("Missing channel details");
+        // Report invalid channel ID
+        let worker = match self
+            .prepare_submit_worker(payload.channel_id, v2_channel_details.user.to_string())
+        {
+            Some(worker) => worker,
+            None => {
+                self.reject_shares(
+                    payload,
+                    format!("Unrecognized channel ID {}", payload.channel_id),
+                );
+                return;
+            }
+        };
         // TODO this is only here as we want to prevent locking up 'self' into multiple closures
         // and causing borrow checker complains
         let v1_extra_nonce2_size = self.v1_extra_nonce2_size;
@@ -1191,7 +1571,7 @@ impl v2::Handler for V2ToV1Translation {
         match v1_submit_template {
             Ok(v1_submit_template) => {
                 let submit = v1::messages::Submit::new(
-                    v2_channel_details.user.to_string(),
+                    worker,
                     v1_submit_template.job_id.clone(),
                     Self::channel_to_extra_nonce2_bytes(Self::CHANNEL_ID, v1_extra_nonce2_size)
                         .as_ref(),
@@ -124,6 +124,8 @@ async fn test_setup_connection_translate() {
     // Now we should have a successfully open channel
     v2_verify_generated_response_message(&mut v2_rx).await;
 
+    let block_height = translation.block_height();
+    assert_eq!(block_height.get(), None);
     v1_simulate_incoming_message(
         &mut translation,
         test_utils::v1::build_mining_notify_request_message(),
@@ -133,6 +135,8 @@ async fn test_setup_connection_translate() {
     v2_verify_generated_response_message(&mut v2_rx).await;
     // Expect SetNewPrevHash
     v2_verify_generated_response_message(&mut v2_rx).await;
+    // Height from coinbase of the notify is visible through the shared handle
+    assert_eq!(block_height.get(), Some(3181));
     // Ensure that the V1 job has been registered
     let submit_template = V1SubmitTemplate {
         job_id: v1::messages::JobId::from_str(&test_utils::v1::MINING_NOTIFY_JOB_ID),
@@ -165,6 +169,393 @@ async fn test_setup_connection_translate() {
     // });
 }
 
+/// Captures V2 jobs as pairs of job ID and future job flag together with the number of new
+/// prevhash messages
+#[derive(Default)]
+struct JobCaptureHandler {
+    jobs: Vec<(u32, bool)>,
+    prev_hashes: usize,
+}
+
+#[async_trait]
+impl v2::Handler for JobCaptureHandler {
+    async fn visit_new_mining_job(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::NewMiningJob,
+    ) {
+        self.jobs.push((payload.job_id, payload.future_job));
+    }
+
+    async fn visit_set_new_prev_hash(
+        &mut self,
+        _header: &v2::framing::Header,
+        _payload: &v2::messages::SetNewPrevHash,
+    ) {
+        self.prev_hashes += 1;
+    }
+}
+
+async fn capture_v2_messages(
+    v2_rx: &mut mpsc::Receiver<v2::Frame>,
+    handler: &mut JobCaptureHandler,
+    count: usize,
+) {
+    for _ in 0..count {
+        let frame = v2_rx.next().await.expect("At least 1 message was expected");
+        v2::build_message_from_frame(frame)
+            .expect("Deserialization failed")
+            .accept(handler)
+            .await;
+    }
+}
+
+/// Builds `mining.notify` from the test notification with its job ID replaced
+fn build_mining_notify_with_job_id(job_id: &str) -> v1::rpc::Rpc {
+    let json = test_utils::v1::MINING_NOTIFY_JSON.replacen(
+        test_utils::v1::MINING_NOTIFY_JOB_ID,
+        job_id,
+        1,
+    );
+    json.parse().expect("Cannot parse mining job")
+}
+
+/// Verifies that a V1 job ID reused by the pool flushes all previous jobs because solutions of
+/// both jobs would be submitted under the same ID
+#[tokio::test]
+async fn test_reused_job_id() {
+    let (v1_tx, mut v1_rx) = mpsc::channel(1);
+    let (v2_tx, mut v2_rx) = mpsc::channel(2);
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, Default::default());
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_setup_connection()).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_configure_ok_response_message(),
+    )
+    .await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_open_channel()).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_subscribe_ok_response_message(),
+    )
+    .await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_authorize_ok_response_message(),
+    )
+    .await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_set_difficulty_request_message(),
+    )
+    .await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+
+    let mut handler = JobCaptureHandler::default();
+
+    // The first job is always a future job activated by a new prevhash
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_mining_notify_request_message(),
+    )
+    .await;
+    capture_v2_messages(&mut v2_rx, &mut handler, 2).await;
+    // Another job of the same block can be mined right away
+    v1_simulate_incoming_message(&mut translation, build_mining_notify_with_job_id("cau")).await;
+    capture_v2_messages(&mut v2_rx, &mut handler, 1).await;
+    // Repeated job ID without clean jobs flag has to replace all previous jobs
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_mining_notify_request_message(),
+    )
+    .await;
+    capture_v2_messages(&mut v2_rx, &mut handler, 2).await;
+
+    assert_eq!(handler.jobs, vec![(0, true), (1, false), (2, true)]);
+    assert_eq!(handler.prev_hashes, 2);
+    assert!(v2_rx.try_next().is_err());
+    // Solutions of the flushed jobs cannot be submitted under the reused V1 job ID
+    assert_eq!(translation.v2_to_v1_job_map.len(), 1);
+    assert!(translation.v2_to_v1_job_map.contains_key(&2));
+}
+
+/// Verifies that minimum difficulty is requested in `mining.configure` and that the floor is
+/// applied to the target once the pool acknowledges it
+#[tokio::test]
+async fn test_minimum_difficulty_translate() {
+    const MINIMUM_DIFFICULTY: u32 = 16;
+
+    let (v1_tx, mut v1_rx) = mpsc::channel(1);
+    let (v2_tx, mut v2_rx) = mpsc::channel(1);
+    let options = V2ToV1TranslationOptions {
+        minimum_difficulty: Some(MINIMUM_DIFFICULTY),
+        ..Default::default()
+    };
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, options);
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_setup_connection()).await;
+    let frame = v1_rx.next().await.expect("mining.configure was expected");
+    let configure = match v1::rpc::Rpc::try_from(frame).expect("Deserialization failed") {
+        v1::rpc::Rpc::Request(request) => {
+            v1::messages::Configure::try_from(request).expect("Conversion failed")
+        }
+        _ => panic!("Request expected"),
+    };
+    assert!(configure.0.contains(&"minimum-difficulty".to_string()));
+    assert_eq!(
+        configure.1["minimum-difficulty.value"].as_u64(),
+        Some(MINIMUM_DIFFICULTY as u64)
+    );
+
+    let configure_result = v1::rpc::Rpc::from(v1::rpc::Response {
+        id: 0,
+        payload: v1::rpc::ResponsePayload {
+            result: Some(v1::rpc::StratumResult(serde_json::json!({
+                "version-rolling": true,
+                "version-rolling.mask": "1fffe000",
+                "minimum-difficulty": true,
+            }))),
+            error: None,
+        },
+    });
+    v1_simulate_incoming_message(&mut translation, configure_result).await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+    assert_eq!(translation.v1_minimum_difficulty, Some(MINIMUM_DIFFICULTY));
+
+    // Pool difficulty 4 is below the negotiated floor
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_set_difficulty_request_message(),
+    )
+    .await;
+    assert_eq!(
+        translation.v2_target,
+        Some(V2ToV1Translation::DIFF1_TARGET / MINIMUM_DIFFICULTY)
+    );
+}
+
+/// Pool that doesn't acknowledge the extension leaves the difficulty intact
+#[tokio::test]
+async fn test_minimum_difficulty_refused() {
+    let (v1_tx, mut v1_rx) = mpsc::channel(1);
+    let (v2_tx, mut v2_rx) = mpsc::channel(1);
+    let options = V2ToV1TranslationOptions {
+        minimum_difficulty: Some(16),
+        ..Default::default()
+    };
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, options);
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_setup_connection()).await;
+    v1_rx.next().await.expect("mining.configure was expected");
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_configure_ok_response_message(),
+    )
+    .await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+    assert_eq!(translation.v1_minimum_difficulty, None);
+
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_set_difficulty_request_message(),
+    )
+    .await;
+    assert_eq!(
+        translation.v2_target,
+        Some(V2ToV1Translation::DIFF1_TARGET / 4)
+    );
+}
+
+/// Difficulties that are not integers or powers of two are converted to precise targets and
+/// invalid difficulties don't affect the current target
+#[tokio::test]
+async fn test_fractional_difficulty() {
+    let (v1_tx, _v1_rx) = mpsc::channel(1);
+    let (v2_tx, _v2_rx) = mpsc::channel(1);
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, V2ToV1TranslationOptions::default());
+
+    let expected_targets = [
+        (0.5, V2ToV1Translation::DIFF1_TARGET << 1),
+        (3.0, V2ToV1Translation::DIFF1_TARGET / 3),
+        (
+            16384.5,
+            (V2ToV1Translation::DIFF1_TARGET << 16) / (16384 * 65536 + 32768),
+        ),
+    ];
+    for (diff, expected_target) in expected_targets.iter() {
+        let set_difficulty = v1::rpc::Rpc::from(v1::rpc::Request {
+            id: None,
+            payload: v1::messages::SetDifficulty([*diff])
+                .try_into()
+                .expect("Cannot serialize request"),
+        });
+        v1_simulate_incoming_message(&mut translation, set_difficulty).await;
+        assert_eq!(translation.v2_target, Some(*expected_target));
+    }
+    // 16384.5 is harder than 16384 and easier than 16385
+    let target = translation.v2_target.expect("BUG: missing target");
+    assert!(target < V2ToV1Translation::DIFF1_TARGET / 16384);
+    assert!(target > V2ToV1Translation::DIFF1_TARGET / 16385);
+
+    for diff in [0.0, -1.0].iter() {
+        let set_difficulty = v1::rpc::Rpc::from(v1::rpc::Request {
+            id: None,
+            payload: v1::messages::SetDifficulty([*diff])
+                .try_into()
+                .expect("Cannot serialize request"),
+        });
+        v1_simulate_incoming_message(&mut translation, set_difficulty).await;
+        assert_eq!(translation.v2_target, Some(target));
+    }
+}
+
+/// Captures the error code of rejected channel opening
+struct OpenChannelErrorHandler(Option<String>);
+
+#[async_trait]
+impl v2::Handler for OpenChannelErrorHandler {
+    async fn visit_open_standard_mining_channel_error(
+        &mut self,
+        _header: &v2::framing::Header,
+        payload: &v2::messages::OpenStandardMiningChannelError,
+    ) {
+        self.0 = Some(payload.code.to_string());
+    }
+}
+
+/// Runs the handshake up to the subscribe response with `extra_nonce2_size` and returns the
+/// translation together with its V2 output
+async fn subscribe_with_extra_nonce2_size(
+    min_extranonce2_size: usize,
+    extra_nonce2_size: usize,
+) -> (V2ToV1Translation, mpsc::Receiver<v2::Frame>) {
+    let (v1_tx, mut v1_rx) = mpsc::channel(1);
+    let (v2_tx, mut v2_rx) = mpsc::channel(1);
+    let options = V2ToV1TranslationOptions {
+        min_extranonce2_size,
+        ..Default::default()
+    };
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, options);
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_setup_connection()).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_configure_ok_response_message(),
+    )
+    .await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_open_channel()).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+
+    // The pool assigns extranonce1 and leaves only `extra_nonce2_size` bytes for extranonce2
+    let subscribe_result = v1::rpc::Rpc::from(v1::rpc::Response {
+        id: 1,
+        payload: v1::rpc::ResponsePayload {
+            result: Some(v1::rpc::StratumResult(serde_json::json!([
+                [["mining.set_difficulty", "4"], ["mining.notify", "1"]],
+                "6c6f0100",
+                extra_nonce2_size,
+            ]))),
+            error: None,
+        },
+    });
+    v1_simulate_incoming_message(&mut translation, subscribe_result).await;
+    (translation, v2_rx)
+}
+
+#[tokio::test]
+async fn test_extranonce2_size_sufficient() {
+    let (translation, _v2_rx) = subscribe_with_extra_nonce2_size(4, 4).await;
+    assert_eq!(translation.v1_extra_nonce2_size, 4);
+    assert!(translation.v1_extra_nonce1.is_some());
+    assert_eq!(
+        translation.state,
+        V2ToV1TranslationState::OpenStandardMiningChannelPending
+    );
+}
+
+#[tokio::test]
+async fn test_extranonce2_size_insufficient() {
+    let (translation, mut v2_rx) = subscribe_with_extra_nonce2_size(4, 2).await;
+
+    // The channel is refused instead of producing work with truncated extranonce2
+    assert!(translation.v1_extra_nonce1.is_none());
+    assert_eq!(
+        translation.state,
+        V2ToV1TranslationState::V1SubscribeOrAuthorizeFail
+    );
+    let frame = v2_rx
+        .next()
+        .await
+        .expect("OpenStandardMiningChannelError was expected");
+    let mut handler = OpenChannelErrorHandler(None);
+    v2::build_message_from_frame(frame)
+        .expect("Deserialization failed")
+        .accept(&mut handler)
+        .await;
+    assert_eq!(
+        handler.0,
+        Some("Upstream extranonce2 too small".to_string())
+    );
+}
+
+/// Verifies that the owner of the translation is notified when the pool changes extranonce
+#[tokio::test]
+async fn test_extranonce_change() {
+    let (mut translation, _v2_rx) = subscribe_with_extra_nonce2_size(4, 4).await;
+    let extranonce_change = translation.extranonce_change();
+    assert!(!extranonce_change.take());
+
+    let set_extranonce = v1::rpc::Rpc::from(v1::rpc::Request {
+        id: None,
+        payload: v1::messages::SetExtranonce(
+            v1::ExtraNonce1(v1::HexBytes::try_from("6c6f0200").expect("Cannot parse extranonce")),
+            4,
+        )
+        .try_into()
+        .expect("Cannot serialize request"),
+    });
+    v1_simulate_incoming_message(&mut translation, set_extranonce).await;
+    assert_eq!(translation.v1_extra_nonce2_size, 4);
+    assert!(extranonce_change.take());
+    // the change is reported only once
+    assert!(!extranonce_change.take());
+    assert!(translation.check_fatal_error().is_ok());
+}
+
+/// Verifies that unusable extranonce from `mining.set_extranonce` closes the connection
+#[tokio::test]
+async fn test_extranonce_change_insufficient() {
+    let (mut translation, _v2_rx) = subscribe_with_extra_nonce2_size(4, 4).await;
+    let extranonce_change = translation.extranonce_change();
+
+    let set_extranonce = v1::rpc::Rpc::from(v1::rpc::Request {
+        id: None,
+        payload: v1::messages::SetExtranonce(
+            v1::ExtraNonce1(v1::HexBytes::try_from("6c6f0200").expect("Cannot parse extranonce")),
+            2,
+        )
+        .try_into()
+        .expect("Cannot serialize request"),
+    });
+    v1_simulate_incoming_message(&mut translation, set_extranonce).await;
+    // the previous extranonce is kept and the owner is asked to close the connection instead
+    assert_eq!(translation.v1_extra_nonce2_size, 4);
+    assert!(!extranonce_change.take());
+    assert!(translation.check_fatal_error().is_err());
+    // the error is reported only once
+    assert!(translation.check_fatal_error().is_ok());
+}
+
 #[test]
 fn test_diff_1_bitcoin_target() {
     // Difficulty 1 target in big-endian format
@@ -184,3 +575,231 @@ fn test_diff_1_bitcoin_target() {
         V2ToV1Translation::DIFF1_TARGET
     );
 }
+
+/// Captures user names of V1 requests emitted by the translation
+#[derive(Default)]
+struct WorkerCaptureHandler {
+    authorized: Vec<String>,
+    submitted: Vec<String>,
+}
+
+#[async_trait]
+impl v1::Handler for WorkerCaptureHandler {
+    async fn visit_authorize(&mut self, _id: &v1::MessageId, payload: &v1::messages::Authorize) {
+        self.authorized.push(payload.0.clone());
+    }
+
+    async fn visit_submit(&mut self, _id: &v1::MessageId, payload: &v1::messages::Submit) {
+        self.submitted.push(payload.user_name().clone());
+    }
+}
+
+async fn capture_v1_message(
+    v1_rx: &mut mpsc::Receiver<v1::Frame>,
+    handler: &mut WorkerCaptureHandler,
+) {
+    let frame = v1_rx.next().await.expect("At least 1 message was expected");
+    v1::build_message_from_frame(frame)
+        .expect("Deserialization failed")
+        .accept(handler)
+        .await;
+}
+
+/// Verifies that shares submitted to sub-worker channels are submitted under the sub-worker
+/// name which is authorized only once before its first share
+#[tokio::test]
+async fn test_sub_worker_submit() {
+    let (v1_tx, mut v1_rx) = mpsc::channel(2);
+    let (v2_tx, mut v2_rx) = mpsc::channel(2);
+    let options = V2ToV1TranslationOptions {
+        sub_worker_prefix: Some("board".to_string()),
+        ..Default::default()
+    };
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, options);
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_setup_connection()).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_configure_ok_response_message(),
+    )
+    .await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_open_channel()).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_subscribe_ok_response_message(),
+    )
+    .await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_authorize_ok_response_message(),
+    )
+    .await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_set_difficulty_request_message(),
+    )
+    .await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_mining_notify_request_message(),
+    )
+    .await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+
+    let mut handler = WorkerCaptureHandler::default();
+    let user = test_utils::common::USER_CREDENTIALS.to_string();
+    let sub_worker = format!("{}.board2", user);
+
+    // the main channel is submitted under the channel user
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_submit_shares()).await;
+    capture_v1_message(&mut v1_rx, &mut handler).await;
+
+    // the first share of the sub-worker is preceded by its authorization
+    let mut share = test_utils::v2::build_submit_shares();
+    share.channel_id = V2ToV1Translation::sub_worker_channel_id(2);
+    v2_simulate_incoming_message(&mut translation, share.clone()).await;
+    capture_v1_message(&mut v1_rx, &mut handler).await;
+    capture_v1_message(&mut v1_rx, &mut handler).await;
+    v2_simulate_incoming_message(&mut translation, share).await;
+    capture_v1_message(&mut v1_rx, &mut handler).await;
+    assert_eq!(handler.authorized, vec![sub_worker.clone()]);
+    assert_eq!(
+        handler.submitted,
+        vec![user, sub_worker.clone(), sub_worker]
+    );
+}
+
+/// Sub-worker channels are recognized only when the sub-worker prefix is configured
+#[test]
+fn test_sub_worker_disabled() {
+    let (v1_tx, _v1_rx) = mpsc::channel(1);
+    let (v2_tx, _v2_rx) = mpsc::channel(1);
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, Default::default());
+    let channel_id = V2ToV1Translation::sub_worker_channel_id(0);
+    assert_eq!(
+        translation.prepare_submit_worker(channel_id, "user".to_string()),
+        None
+    );
+    assert_eq!(
+        translation.prepare_submit_worker(V2ToV1Translation::CHANNEL_ID, "user".to_string()),
+        Some("user".to_string())
+    );
+}
+
+/// Captures the session parameter of `mining.subscribe` requests
+#[derive(Default)]
+struct SubscribeCaptureHandler(Vec<Option<v1::ExtraNonce1>>);
+
+#[async_trait]
+impl v1::Handler for SubscribeCaptureHandler {
+    async fn visit_subscribe(&mut self, _id: &v1::MessageId, payload: &v1::messages::Subscribe) {
+        self.0.push(payload.1.clone());
+    }
+}
+
+/// Runs the handshake up to the subscribe response and captures the V1 requests emitted by the
+/// translation when the channel is being open
+async fn subscribe_session(
+    options: V2ToV1TranslationOptions,
+) -> (V2ToV1Translation, SubscribeCaptureHandler) {
+    let (v1_tx, mut v1_rx) = mpsc::channel(1);
+    let (v2_tx, mut v2_rx) = mpsc::channel(1);
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, options);
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_setup_connection()).await;
+    v1_verify_generated_response_message(&mut v1_rx).await;
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_configure_ok_response_message(),
+    )
+    .await;
+    v2_verify_generated_response_message(&mut v2_rx).await;
+    // No session is available before subscription
+    assert_eq!(translation.session_state(), None);
+
+    v2_simulate_incoming_message(&mut translation, test_utils::v2::build_open_channel()).await;
+    let mut handler = SubscribeCaptureHandler::default();
+    for _ in 0..2 {
+        let frame = v1_rx.next().await.expect("At least 1 message was expected");
+        v1::build_message_from_frame(frame)
+            .expect("Deserialization failed")
+            .accept(&mut handler)
+            .await;
+    }
+    v1_simulate_incoming_message(
+        &mut translation,
+        test_utils::v1::build_subscribe_ok_response_message(),
+    )
+    .await;
+    (translation, handler)
+}
+
+/// Verifies that the session captured from one connection is requested again after reconnect
+/// and that the restored session matches the original one
+#[tokio::test]
+async fn test_session_resume() {
+    let (translation, handler) = subscribe_session(Default::default()).await;
+    assert_eq!(handler.0, vec![None]);
+    let session = translation
+        .session_state()
+        .expect("BUG: missing session state");
+    assert_eq!(
+        session.version_mask,
+        Some(ii_stratum::BIP320_N_VERSION_MASK)
+    );
+    assert!(!session.subscriptions.is_empty());
+
+    // Reconnect with the captured session
+    let options = V2ToV1TranslationOptions {
+        resume_session: Some(session.clone()),
+        ..Default::default()
+    };
+    let (translation, handler) = subscribe_session(options).await;
+    assert_eq!(handler.0, vec![Some(session.extra_nonce1.clone())]);
+    assert_eq!(translation.session_state(), Some(session));
+}
+
+/// Keepalive is sent only over an operational channel and doesn't expect any particular response
+#[tokio::test]
+async fn test_send_keepalive() {
+    let (v1_tx, mut v1_rx) = mpsc::channel(1);
+    let (v2_tx, _v2_rx) = mpsc::channel(1);
+    let mut translation = V2ToV1Translation::new(v1_tx, v2_tx, Default::default());
+
+    translation
+        .send_keepalive()
+        .expect("BUG: cannot send keepalive");
+    assert!(v1_rx.try_next().is_err());
+
+    translation.state = V2ToV1TranslationState::Operational;
+    translation
+        .send_keepalive()
+        .expect("BUG: cannot send keepalive");
+    let frame = v1_rx.next().await.expect("Keepalive was expected");
+    let id = match v1::rpc::Rpc::try_from(frame).expect("Deserialization failed") {
+        v1::rpc::Rpc::Request(request) => {
+            assert_eq!(request.payload.method, v1::rpc::Method::ExtranonceSubscribe);
+            request.id.expect("BUG: missing request ID")
+        }
+        _ => panic!("Request expected"),
+    };
+
+    // Refusal of the keepalive request doesn't affect the channel
+    let error_response = v1::rpc::Rpc::from(v1::rpc::Response {
+        id,
+        payload: v1::rpc::ResponsePayload {
+            result: None,
+            error: Some(v1::rpc::StratumError(20, "Unsupported".to_string(), None)),
+        },
+    });
+    v1_simulate_incoming_message(&mut translation, error_response).await;
+    assert_eq!(translation.state, V2ToV1TranslationState::Operational);
+    assert!(!translation.v1_xnsub_enabled);
+}